@@ -0,0 +1,8 @@
+fn main() {
+    // the gRPC service and messages of `src/grpc.rs`, compiling them needs `protoc`
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/dca.proto");
+        tonic_build::compile_protos("proto/dca.proto").expect("compile proto/dca.proto");
+    }
+}
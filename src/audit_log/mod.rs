@@ -0,0 +1,131 @@
+use crate::{
+    keycodec::{parse_sequence_key, sequence_key},
+    new_blake2b,
+    oplog::{encode_ops, OpLog},
+    storage::traits::ReadStore,
+    AccumulatorError, BlockOps,
+};
+use rocksdb::{prelude::Put, Direction, IteratorMode};
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+pub const AUDIT_LOG_KEY: &[u8] = b"AUDIT";
+
+/// The digest of the operation stream before the first sequence.
+pub const GENESIS_DIGEST: [u8; 32] = [0u8; 32];
+
+/// Chains the operations of a sequence into the digest of the previous one,
+/// blake2b(previous digest || sequence as u64 LE || `encode_ops(ops)`), so the digest of a sequence commits to the whole
+/// operation stream up to it, in order.
+pub fn chain_digest(previous: &[u8; 32], sequence: u64, ops: &BlockOps) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(previous);
+    hasher.update(&sequence.to_le_bytes());
+    hasher.update(&encode_ops(ops));
+    hasher.finalize(&mut digest);
+    digest
+}
+
+/// A tamper-evident log of the operations applied to an accumulator: the rolling `chain_digest` of every committed
+/// sequence, stored next to the accumulator in the same RocksDB. Like the oplog, a record is keyed by sequence directly
+/// and written once when the sequence is committed. Two runs of the same workload, on any backends, have the same
+/// digests, and `audit` re-derives them from the oplog.
+pub struct AuditLog<'a, DB, WO> {
+    inner: &'a DB,
+    write_options: PhantomData<WO>,
+}
+
+impl<'a, DB, WO> AuditLog<'a, DB, WO> {
+    pub fn new(db: &'a DB) -> Self {
+        AuditLog {
+            inner: db,
+            write_options: PhantomData,
+        }
+    }
+}
+
+impl<'a, DB, WO> AuditLog<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the digest of the given sequence, or `None` if no record was written.
+    pub fn digest_at(&self, sequence: u64) -> Result<Option<[u8; 32]>, AccumulatorError> {
+        self.inner
+            .get(sequence_key(AUDIT_LOG_KEY, sequence))
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?
+            .map(|v| {
+                v.as_ref().try_into().map_err(|_| {
+                    AccumulatorError::InternalError("invalid audit log record".to_string())
+                })
+            })
+            .transpose()
+    }
+
+    /// Re-derives the digests of the sequences `0..=sequence` from the oplog and compares them with the recorded ones,
+    /// returns the first sequence whose recorded digest differs or is missing, `None` if the log is intact.
+    pub fn audit(&self, sequence: u64) -> Result<Option<u64>, AccumulatorError> {
+        let oplog = OpLog::<_, WO>::new(self.inner);
+        let mut digest = GENESIS_DIGEST;
+        for s in 0..=sequence {
+            let ops = oplog.ops_at(s)?.ok_or_else(|| {
+                AccumulatorError::InternalError(format!("missing oplog record of sequence {}", s))
+            })?;
+            digest = chain_digest(&digest, s, &ops);
+            if self.digest_at(s)? != Some(digest) {
+                return Ok(Some(s));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a, DB, WO> AuditLog<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the last recorded sequence and its digest, or `None` if the log was never recorded.
+    pub fn latest(&self) -> Result<Option<(u64, [u8; 32])>, AccumulatorError> {
+        let last_key = sequence_key(AUDIT_LOG_KEY, u64::MAX);
+        let mode = IteratorMode::From(&last_key, Direction::Reverse);
+        self.inner
+            .iterator(mode)
+            .next()
+            .and_then(|(key, value)| {
+                parse_sequence_key(AUDIT_LOG_KEY, &key).map(|sequence| (sequence, value))
+            })
+            .map(|(sequence, value)| {
+                let digest = value[..].try_into().map_err(|_| {
+                    AccumulatorError::InternalError("invalid audit log record".to_string())
+                })?;
+                Ok((sequence, digest))
+            })
+            .transpose()
+    }
+}
+
+impl<'a, DB, WO> AuditLog<'a, DB, WO>
+where
+    DB: ReadStore + Put<WO>,
+{
+    /// Records the digest of a sequence, chained from the digest of the previous one. The first sequence starts from
+    /// `GENESIS_DIGEST`, the log can't be started on a database which has committed sequences without it.
+    pub fn record(&mut self, sequence: u64, ops: &BlockOps) -> Result<[u8; 32], AccumulatorError> {
+        let previous = match sequence.checked_sub(1) {
+            Some(previous) => self.digest_at(previous)?.ok_or_else(|| {
+                AccumulatorError::InternalError(format!(
+                    "missing audit log record of sequence {}",
+                    previous
+                ))
+            })?,
+            None => GENESIS_DIGEST,
+        };
+        let digest = chain_digest(&previous, sequence, ops);
+        self.inner
+            .put(sequence_key(AUDIT_LOG_KEY, sequence), digest)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        Ok(digest)
+    }
+}
@@ -0,0 +1,61 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use crate::{oplog::OpLog, BlockOps, OutPoint};
+
+use super::{chain_digest, AuditLog, GENESIS_DIGEST};
+
+fn out_point(i: u8) -> OutPoint {
+    OutPoint {
+        tx_hash: [i; 32],
+        index: i as u32,
+    }
+}
+
+#[test]
+fn test_audit_log() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let blocks = vec![
+        BlockOps {
+            added: vec![out_point(0), out_point(1)],
+            deleted: vec![],
+        },
+        BlockOps {
+            added: vec![out_point(2)],
+            deleted: vec![out_point(0)],
+        },
+    ];
+    let mut log = AuditLog::new(&tx);
+    let mut oplog = OpLog::new(&tx);
+    let mut digest = GENESIS_DIGEST;
+    for (sequence, ops) in blocks.iter().enumerate() {
+        digest = log.record(sequence as u64, ops).unwrap();
+        oplog.put(sequence as u64, ops).unwrap();
+    }
+    // the chain builds on the previous sequence
+    assert!(log.record(3, &blocks[0]).is_err());
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let log = AuditLog::<_, ()>::new(&snapshot);
+    assert_eq!(Some((1, digest)), log.latest().unwrap());
+    assert_eq!(None, log.audit(1).unwrap());
+    // the digest commits to the order of the operations
+    let reordered = BlockOps {
+        added: vec![out_point(1), out_point(0)],
+        deleted: vec![],
+    };
+    assert_ne!(
+        chain_digest(&GENESIS_DIGEST, 0, &blocks[0]),
+        chain_digest(&GENESIS_DIGEST, 0, &reordered)
+    );
+
+    // a tampered oplog record no longer matches the log
+    let tx = db.transaction_default();
+    OpLog::new(&tx).put(1, &reordered).unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    assert_eq!(Some(1), AuditLog::<_, ()>::new(&snapshot).audit(1).unwrap());
+}
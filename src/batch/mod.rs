@@ -0,0 +1,206 @@
+use crate::{
+    mmr::accumulator::{MergeH256, H256},
+    new_blake2b, AccumulatorError, AccumulatorWriter, CellStatus, OutPoint,
+};
+use merkle_mountain_range::{
+    leaf_index_to_mmr_size, leaf_index_to_pos, Error, MMRStoreReadOps, MMRStoreWriteOps,
+    MerkleProof, MMR,
+};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+/// An operation applied to the accumulator within a committed range.
+#[derive(Clone)]
+pub enum Operation {
+    Add(OutPoint),
+    Delete(OutPoint),
+    Update(OutPoint, CellStatus),
+}
+
+impl Operation {
+    /// The leaf of the operation in the range MMR: blake2b(tag || tx_hash || index LE [|| status]), the tag is 0 for an
+    /// add, 1 for a delete and 2 for an update, followed by the new status.
+    pub fn hash(&self) -> [u8; 32] {
+        let (tag, out_point, status) = match self {
+            Operation::Add(out_point) => (0u8, out_point, None),
+            Operation::Delete(out_point) => (1u8, out_point, None),
+            Operation::Update(out_point, status) => (2u8, out_point, Some(status)),
+        };
+        let mut hash = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&[tag]);
+        hasher.update(&out_point.to_bytes());
+        if let Some(status) = status {
+            hasher.update(&status.block_numbers);
+        }
+        hasher.finalize(&mut hash);
+        hash
+    }
+}
+
+// the operations of a range are few and short lived, they are kept in memory
+#[derive(Default)]
+struct OpStore(HashMap<u64, H256>);
+
+impl MMRStoreReadOps<H256> for OpStore {
+    fn get(&self, pos: u64) -> Result<Option<H256>, Error> {
+        Ok(self.0.get(&pos).cloned())
+    }
+}
+
+impl MMRStoreWriteOps<H256> for OpStore {
+    fn insert(&mut self, pos: u64, elem: H256) -> Result<(), Error> {
+        self.0.insert(pos, elem);
+        Ok(())
+    }
+}
+
+/// Wraps an accumulator so that one commitment attests to all the operations since the previous one, for designs
+/// where a root per block is too expensive. The operations of the range are hashed into a small MMR, so a proof can
+/// pinpoint the index of an operation within the range.
+pub struct RangeWriter<W> {
+    inner: W,
+    ops: MMR<H256, MergeH256, OpStore>,
+    op_count: u64,
+}
+
+impl<W> RangeWriter<W>
+where
+    W: AccumulatorWriter<Item = OutPoint>,
+{
+    pub fn new(inner: W) -> Self {
+        RangeWriter {
+            inner,
+            ops: MMR::new(0, OpStore::default()),
+            op_count: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn record(&mut self, operation: Operation) -> Result<(), AccumulatorError> {
+        self.ops.push(H256::from(operation.hash()))?;
+        self.op_count += 1;
+        Ok(())
+    }
+}
+
+impl<W> AccumulatorWriter for RangeWriter<W>
+where
+    W: AccumulatorWriter<Item = OutPoint>,
+{
+    type Item = OutPoint;
+    type Commitment = RangeCommitment<W::Commitment>;
+
+    fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        self.inner.add(elements.clone())?;
+        for out_point in elements {
+            self.record(Operation::Add(out_point))?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        self.inner.delete(elements.clone())?;
+        for out_point in elements {
+            self.record(Operation::Delete(out_point))?;
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        self.inner.update(elements.clone())?;
+        for (out_point, status) in elements {
+            self.record(Operation::Update(out_point, status))?;
+        }
+        Ok(())
+    }
+
+    /// Commits the accumulator, the returned commitment covers every operation since the previous commitment.
+    fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let commitment = self.inner.commit()?;
+        let ops_root = if self.op_count == 0 {
+            [0u8; 32]
+        } else {
+            let root = self.ops.get_root()?;
+            root.as_ref().try_into().expect("checked length")
+        };
+        self.ops.commit()?;
+        let ops = std::mem::replace(&mut self.ops, MMR::new(0, OpStore::default()));
+        Ok(RangeCommitment {
+            commitment,
+            ops_root,
+            op_count: std::mem::take(&mut self.op_count),
+            ops,
+        })
+    }
+}
+
+/// The commitment of a range of operations: the accumulator commitment at the end of the range, and the root and
+/// count of the operations of the range.
+pub struct RangeCommitment<C> {
+    pub commitment: C,
+    pub ops_root: [u8; 32],
+    pub op_count: u64,
+    ops: MMR<H256, MergeH256, OpStore>,
+}
+
+impl<C> RangeCommitment<C> {
+    /// Folds the operations of the range into a commitment digest, blake2b(digest || ops root || op count as u64 LE).
+    pub fn fold_into(&self, digest: [u8; 32]) -> [u8; 32] {
+        let mut folded = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&digest);
+        hasher.update(&self.ops_root);
+        hasher.update(&self.op_count.to_le_bytes());
+        hasher.finalize(&mut folded);
+        folded
+    }
+
+    /// Proves that the operation at `index` within the range is the given one.
+    pub fn op_proof(&self, index: u64) -> Result<OpProof, AccumulatorError> {
+        if index >= self.op_count {
+            return Err(AccumulatorError::ElementNotFound(index as usize));
+        }
+        let proof = self.ops.gen_proof(vec![leaf_index_to_pos(index)])?;
+        Ok(OpProof {
+            index,
+            items: proof
+                .proof_items()
+                .iter()
+                .map(|item| item.as_ref().try_into().expect("checked length"))
+                .collect(),
+        })
+    }
+}
+
+/// A proof of the index of an operation within a committed range, see `RangeCommitment::op_proof`.
+pub struct OpProof {
+    pub index: u64,
+    items: Vec<[u8; 32]>,
+}
+
+impl OpProof {
+    pub fn verify(
+        &self,
+        ops_root: [u8; 32],
+        op_count: u64,
+        operation: &Operation,
+    ) -> Result<bool, AccumulatorError> {
+        if self.index >= op_count {
+            return Ok(false);
+        }
+        let mmr_size = leaf_index_to_mmr_size(op_count - 1);
+        let items = self.items.iter().map(|item| H256::from(*item)).collect();
+        MerkleProof::<H256, MergeH256>::new(mmr_size, items)
+            .verify(
+                H256::from(ops_root),
+                vec![(leaf_index_to_pos(self.index), H256::from(operation.hash()))],
+            )
+            .map_err(Into::into)
+    }
+}
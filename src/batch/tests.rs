@@ -0,0 +1,82 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use crate::{mmr::accumulator::MMRAccumulator, AccumulatorWriter, BlockOps, OutPoint};
+
+use super::{Operation, RangeWriter};
+
+fn out_point(i: u8) -> OutPoint {
+    OutPoint {
+        tx_hash: [i; 32],
+        index: i as u32,
+    }
+}
+
+#[test]
+fn test_commit_range() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let mut writer = RangeWriter::new(MMRAccumulator::<_, ()>::new_with_strict(&tx, true).unwrap());
+
+    // two blocks in one range
+    writer
+        .add(vec![out_point(0), out_point(1), out_point(2)])
+        .unwrap();
+    writer.add(vec![out_point(3)]).unwrap();
+    writer.delete(vec![out_point(1)]).unwrap();
+    let range = writer.commit().unwrap();
+    assert_eq!(5, range.op_count);
+    assert_eq!(0, range.commitment.sequence());
+
+    let proof = range.op_proof(4).unwrap();
+    let deleted = Operation::Delete(out_point(1));
+    assert!(proof
+        .verify(range.ops_root, range.op_count, &deleted)
+        .unwrap());
+    assert!(!proof
+        .verify(
+            range.ops_root,
+            range.op_count,
+            &Operation::Add(out_point(1))
+        )
+        .unwrap_or(false));
+    assert!(!range
+        .op_proof(3)
+        .unwrap()
+        .verify(range.ops_root, range.op_count, &deleted)
+        .unwrap_or(false));
+    assert!(range.op_proof(5).is_err());
+
+    // the next range starts empty
+    let empty = writer.commit().unwrap();
+    assert_eq!(0, empty.op_count);
+    assert_eq!(1, empty.commitment.sequence());
+    assert_ne!(
+        range.fold_into(range.commitment.commitment_digest()),
+        empty.fold_into(range.commitment.commitment_digest())
+    );
+
+    // the commitments of `apply_blocks` carry the operations of their block
+    let ranges = writer
+        .apply_blocks(vec![
+            BlockOps {
+                added: vec![out_point(4)],
+                deleted: vec![out_point(0)],
+            },
+            BlockOps {
+                added: vec![out_point(5)],
+                deleted: vec![],
+            },
+        ])
+        .unwrap();
+    assert_eq!(2, ranges[0].op_count);
+    assert_eq!(2, ranges[0].commitment.sequence());
+    assert!(ranges[0]
+        .op_proof(1)
+        .unwrap()
+        .verify(ranges[0].ops_root, 2, &Operation::Delete(out_point(0)))
+        .unwrap());
+    assert_eq!(1, ranges[1].op_count);
+    assert_eq!(3, ranges[1].commitment.sequence());
+}
@@ -0,0 +1,895 @@
+use crate::{
+    metrics::{
+        CoalescedOps, FlushMode, FlushStats, FlushWrites, KeyTracer, LevelHistogram, LevelWrites,
+        StageTimes, StageTiming, TraceKeys,
+    },
+    mmr::{
+        accumulator::{
+            AccumulatorCommitment as MMRCommitment, AccumulatorProof as MMRProof,
+            GenericMMRAccumulator,
+        },
+        position_index::{PositionedCommitment, PositionedMMRAccumulator, PositionedProof},
+    },
+    sequence_width::skipped_sequences,
+    smt::{
+        accumulator::{
+            AbsenceProof as SMTAbsenceProof, AccumulatorCommitment as SMTCommitment,
+            AccumulatorProof as SMTProof, GenericSMTAccumulator,
+        },
+        sharded::ShardedSMTAccumulator,
+        tiered::{TieredCommitment, TieredDBs, TieredProof, TieredSMTAccumulator},
+    },
+    smt_live::{
+        accumulator::{
+            AbsenceProof as SMTLiveAbsenceProof, AccumulatorCommitment as SMTLiveCommitment,
+            AccumulatorProof as SMTLiveProof, GenericSMTAccumulator as GenericSMTLiveAccumulator,
+        },
+        presence::{GenericPresenceAccumulator, PresenceCommitment, PresenceProof},
+    },
+    storage::traits::{ReadStore, WriteStore},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, CommitmentDigest,
+    OutPoint, Proof,
+};
+use rocksdb::prelude::Put;
+use std::{
+    marker::PhantomData,
+    ops::{Deref, Range},
+};
+
+/// The construction options shared by all the backends.
+#[derive(Clone, Debug, Default)]
+pub struct AccumulatorOptions {
+    // Rejects existing elements on `add` and missing or dead elements on `delete`.
+    pub strict: bool,
+    // Opens the accumulator at a committed sequence for historical reads instead of the latest state, it can't be written.
+    pub sequence: Option<u64>,
+    pub key_tracer: KeyTracer,
+}
+
+/// Accumulators which can be built from `AccumulatorOptions` over a database handle.
+pub trait FromOptions: Sized {
+    type Handle;
+
+    fn from_options(
+        db: Self::Handle,
+        options: AccumulatorOptions,
+    ) -> Result<Self, AccumulatorError>;
+}
+
+macro_rules! impl_from_options {
+    ($accumulator:ident) => {
+        impl_from_options!($accumulator, D);
+    };
+    ($accumulator:ident, $handle:ty) => {
+        impl<D, WO> FromOptions for $accumulator<D, WO>
+        where
+            D: Deref,
+            D::Target: WriteStore<WO>,
+        {
+            type Handle = $handle;
+
+            fn from_options(
+                db: $handle,
+                options: AccumulatorOptions,
+            ) -> Result<Self, AccumulatorError> {
+                let mut accumulator = match options.sequence {
+                    Some(_) if options.strict => {
+                        return Err(AccumulatorError::InternalError(
+                            "a historical accumulator can't be strict".to_string(),
+                        ))
+                    }
+                    Some(sequence) => Self::new_with_sequence(db, sequence)?,
+                    None => Self::new_with_strict(db, options.strict)?,
+                };
+                accumulator.trace_keys(options.key_tracer);
+                Ok(accumulator)
+            }
+        }
+    };
+}
+
+impl_from_options!(GenericSMTAccumulator);
+impl_from_options!(GenericSMTLiveAccumulator);
+impl_from_options!(GenericMMRAccumulator);
+impl_from_options!(GenericPresenceAccumulator);
+impl_from_options!(ShardedSMTAccumulator, Vec<D>);
+
+/// Builds the accumulator `A` of a backend, e.g. `AccumulatorBuilder::<SMTAccumulator<_, ()>>::new().strict(true).build(&tx)`,
+/// the write options are the `WO` parameter of `A`.
+pub struct AccumulatorBuilder<A> {
+    options: AccumulatorOptions,
+    accumulator: PhantomData<fn() -> A>,
+}
+
+impl<A> Default for AccumulatorBuilder<A> {
+    fn default() -> Self {
+        AccumulatorBuilder {
+            options: AccumulatorOptions::default(),
+            accumulator: PhantomData,
+        }
+    }
+}
+
+impl<A: FromOptions> AccumulatorBuilder<A> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    pub fn sequence(mut self, sequence: u64) -> Self {
+        self.options.sequence = Some(sequence);
+        self
+    }
+
+    pub fn key_tracer(mut self, key_tracer: KeyTracer) -> Self {
+        self.options.key_tracer = key_tracer;
+        self
+    }
+
+    pub fn build(self, db: A::Handle) -> Result<A, AccumulatorError> {
+        A::from_options(db, self.options)
+    }
+}
+
+/// The operations of an `AnyAccumulator` beyond add, delete and commit, which not every backend supports, see
+/// `Builder::supports`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// Readers opened at any committed sequence.
+    HistoricalReader,
+    /// Proofs of live cells verified against one commitment.
+    LiveProof,
+    /// Proofs of consumed cells verified against one commitment. A consumed cell of `smt_live` is also proved against
+    /// the commitment consuming it, see `AnyProof`.
+    DeadProof,
+    /// Proofs that cells were never added, or aren't live for `smt_live`, see `AnyAccumulator::absence_proof`. A MMR
+    /// can't prove an absence.
+    AbsenceProof,
+    /// The writes of the blocks of a rolled back transaction are dropped with it.
+    Rollback,
+    /// Commits at given sequences, see `AccumulatorWriter::skip_to`.
+    SkipSequences,
+    /// The statuses of consumed cells, told apart from the cells which were never added. `smt_presence` only commits to
+    /// the live cells.
+    ConsumedStatus,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 7] = [
+        Feature::HistoricalReader,
+        Feature::LiveProof,
+        Feature::DeadProof,
+        Feature::AbsenceProof,
+        Feature::Rollback,
+        Feature::SkipSequences,
+        Feature::ConsumedStatus,
+    ];
+}
+
+/// Builds the accumulator of a backend chosen at runtime, e.g. by a command line argument, as an `AnyAccumulator`.
+#[derive(Clone, Debug)]
+pub enum Builder {
+    Smt(AccumulatorOptions),
+    SmtLive(AccumulatorOptions),
+    Mmr(AccumulatorOptions),
+    SmtPresence(AccumulatorOptions),
+    /// A smt sharded over the given number of databases, see `ShardedSMTAccumulator`.
+    SmtSharded(AccumulatorOptions, usize),
+    /// A smt whose hot tree is folded into its cold tree every given number of sequences, see `TieredSMTAccumulator`.
+    SmtTiered(AccumulatorOptions, u64),
+    /// A mmr committing to the leaf positions of its cells, see `PositionedMMRAccumulator`.
+    MmrPositioned(AccumulatorOptions),
+}
+
+impl Builder {
+    /// The builder of a backend by its name, smt | smt_live | mmr | smt_presence.
+    pub fn from_name(backend: &str) -> Option<Self> {
+        let options = AccumulatorOptions::default();
+        match backend {
+            "smt" => Some(Builder::Smt(options)),
+            "smt_live" => Some(Builder::SmtLive(options)),
+            "mmr" => Some(Builder::Mmr(options)),
+            "smt_presence" => Some(Builder::SmtPresence(options)),
+            _ => None,
+        }
+    }
+
+    /// The builder of a smt sharded over `shards` databases, built with `build_over`.
+    pub fn sharded(shards: usize) -> Self {
+        Builder::SmtSharded(AccumulatorOptions::default(), shards)
+    }
+
+    /// The builder of a smt in a hot and a cold database, folded every `fold_interval` sequences, built with
+    /// `build_over`.
+    pub fn tiered(fold_interval: u64) -> Self {
+        Builder::SmtTiered(AccumulatorOptions::default(), fold_interval)
+    }
+
+    /// The builder of a mmr and its position index, built with `build_over` over the database of the mmr then the one of
+    /// the index, e.g. two column families of one transaction.
+    pub fn positioned_mmr() -> Self {
+        Builder::MmrPositioned(AccumulatorOptions::default())
+    }
+
+    /// The number of database handles the accumulator is built over: the shards of a sharded smt, the hot and cold
+    /// databases of a tiered one, the mmr and position index databases of a positioned mmr, a single database otherwise.
+    pub fn databases(&self) -> usize {
+        match self {
+            Builder::SmtSharded(_, shards) => *shards,
+            Builder::SmtTiered(..) | Builder::MmrPositioned(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn options_mut(&mut self) -> &mut AccumulatorOptions {
+        match self {
+            Builder::Smt(options)
+            | Builder::SmtLive(options)
+            | Builder::Mmr(options)
+            | Builder::SmtPresence(options)
+            | Builder::SmtSharded(options, _)
+            | Builder::SmtTiered(options, _)
+            | Builder::MmrPositioned(options) => options,
+        }
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options_mut().strict = strict;
+        self
+    }
+
+    pub fn sequence(mut self, sequence: u64) -> Self {
+        self.options_mut().sequence = Some(sequence);
+        self
+    }
+
+    pub fn key_tracer(mut self, key_tracer: KeyTracer) -> Self {
+        self.options_mut().key_tracer = key_tracer;
+        self
+    }
+
+    /// Whether the `AnyAccumulator` of the backend supports `feature`, the operations of an unsupported feature fail
+    /// with an error or don't verify.
+    pub fn supports(&self, feature: Feature) -> bool {
+        !matches!(
+            (self, feature),
+            (Builder::SmtLive(_), Feature::DeadProof)
+                | (
+                    Builder::Mmr(_) | Builder::MmrPositioned(_),
+                    Feature::AbsenceProof
+                )
+                | (
+                    Builder::SmtPresence(_),
+                    Feature::DeadProof | Feature::ConsumedStatus
+                )
+                | (
+                    Builder::SmtTiered(..),
+                    Feature::AbsenceProof | Feature::SkipSequences
+                )
+        )
+    }
+
+    /// Builds the accumulator over a single database, see `build_over`.
+    pub fn build<D, WO>(self, db: D) -> Result<AnyAccumulator<D, WO>, AccumulatorError>
+    where
+        D: Deref + Clone,
+        D::Target: WriteStore<WO>,
+    {
+        self.build_over(vec![db])
+    }
+
+    /// Builds the accumulator over `databases()` database handles, in the order of the shards of a sharded smt, the hot
+    /// database then the cold one of a tiered smt.
+    pub fn build_over<D, WO>(self, dbs: Vec<D>) -> Result<AnyAccumulator<D, WO>, AccumulatorError>
+    where
+        D: Deref + Clone,
+        D::Target: WriteStore<WO>,
+    {
+        if dbs.len() != self.databases() {
+            return Err(AccumulatorError::InternalError(format!(
+                "the accumulator is built over {} databases, got {}",
+                self.databases(),
+                dbs.len()
+            )));
+        }
+        let mut dbs = dbs.into_iter();
+        let mut next = move || dbs.next().expect("the number of databases is checked");
+        Ok(match self {
+            Builder::Smt(options) => {
+                AnyAccumulator::Smt(FromOptions::from_options(next(), options)?)
+            }
+            Builder::SmtLive(options) => {
+                AnyAccumulator::SmtLive(FromOptions::from_options(next(), options)?)
+            }
+            Builder::Mmr(options) => {
+                AnyAccumulator::Mmr(FromOptions::from_options(next(), options)?)
+            }
+            Builder::SmtPresence(options) => {
+                AnyAccumulator::SmtPresence(FromOptions::from_options(next(), options)?)
+            }
+            Builder::SmtSharded(options, shards) => AnyAccumulator::SmtSharded(
+                FromOptions::from_options((0..shards).map(|_| next()).collect(), options)?,
+            ),
+            Builder::SmtTiered(options, fold_interval) => {
+                let dbs = TieredDBs {
+                    hot: next(),
+                    cold: next(),
+                    fold_interval,
+                };
+                let mut accumulator = match options.sequence {
+                    Some(_) if options.strict => {
+                        return Err(AccumulatorError::InternalError(
+                            "a historical accumulator can't be strict".to_string(),
+                        ))
+                    }
+                    Some(sequence) => TieredSMTAccumulator::new_with_sequence(dbs, sequence)?,
+                    None => TieredSMTAccumulator::new_with_strict(dbs, options.strict)?,
+                };
+                accumulator.trace_keys(options.key_tracer);
+                AnyAccumulator::SmtTiered(accumulator)
+            }
+            Builder::MmrPositioned(options) => {
+                let (db, index_db) = (next(), next());
+                let mut accumulator = match options.sequence {
+                    Some(_) if options.strict => {
+                        return Err(AccumulatorError::InternalError(
+                            "a historical accumulator can't be strict".to_string(),
+                        ))
+                    }
+                    Some(sequence) => {
+                        PositionedMMRAccumulator::new_with_sequence(db, index_db, sequence)?
+                    }
+                    None => {
+                        PositionedMMRAccumulator::new_with_strict(db, index_db, options.strict)?
+                    }
+                };
+                accumulator.trace_keys(options.key_tracer);
+                AnyAccumulator::MmrPositioned(accumulator)
+            }
+        })
+    }
+
+    /// Returns the committed sequences of the database, the ones an accumulator opened at a sequence reproduces the
+    /// commitment of.
+    pub fn committed_sequences<D, WO>(&self, db: D) -> Result<Range<u64>, AccumulatorError>
+    where
+        D: Deref + Clone,
+        D::Target: WriteStore<WO>,
+    {
+        self.committed_sequences_over::<D, WO>(vec![db])
+    }
+
+    /// See `committed_sequences`, over the database handles of `build_over`.
+    pub fn committed_sequences_over<D, WO>(
+        &self,
+        dbs: Vec<D>,
+    ) -> Result<Range<u64>, AccumulatorError>
+    where
+        D: Deref + Clone,
+        D::Target: WriteStore<WO>,
+    {
+        let mut latest = self.clone().strict(false);
+        latest.options_mut().sequence = None;
+        // the latest accumulator sees the sequence which is being written
+        let (committed, _) = latest.build_over::<D, WO>(dbs)?.commitment()?;
+        Ok(0..committed)
+    }
+
+    /// Iterates over the sequences in `range` and their commitment digests, each one read by an accumulator opened at
+    /// the sequence when it's reached, so `rev` reads the latest ones only. The range is clamped to the committed
+    /// sequences, and the sequences skipped by `skip_to` are left out since they were never committed.
+    pub fn commitments<D, WO>(
+        self,
+        db: D,
+        range: Range<u64>,
+    ) -> Result<
+        impl DoubleEndedIterator<Item = Result<(u64, CommitmentDigest), AccumulatorError>>,
+        AccumulatorError,
+    >
+    where
+        D: Deref + Clone,
+        D::Target: WriteStore<WO>,
+    {
+        let end = range
+            .end
+            .min(self.committed_sequences::<D, WO>(db.clone())?.end);
+        let skipped = skipped_sequences(&*db);
+        let committed = move |sequence: &u64| !skipped.iter().any(|gap| gap.contains(sequence));
+        Ok((range.start..end).filter(committed).map(move |sequence| {
+            self.clone()
+                .strict(false)
+                .sequence(sequence)
+                .build::<D, WO>(db.clone())?
+                .commitment()
+        }))
+    }
+}
+
+/// The accumulator of any backend, its commitments are the canonical commitment digests, see `crate::commitment_digest`.
+/// It implements the writer and reader traits by delegating to the backend, so that library users can choose a backend
+/// at runtime, e.g. with `Builder::from_name`.
+pub enum AnyAccumulator<D, WO> {
+    Smt(GenericSMTAccumulator<D, WO>),
+    SmtLive(GenericSMTLiveAccumulator<D, WO>),
+    Mmr(GenericMMRAccumulator<D, WO>),
+    SmtPresence(GenericPresenceAccumulator<D, WO>),
+    SmtSharded(ShardedSMTAccumulator<D, WO>),
+    SmtTiered(TieredSMTAccumulator<D, WO>),
+    MmrPositioned(PositionedMMRAccumulator<D, D, WO>),
+}
+
+impl<D, WO> AccumulatorWriter for AnyAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    type Item = OutPoint;
+    type Commitment = [u8; 32];
+
+    fn add(&mut self, elements: Vec<OutPoint>) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.add(elements),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.add(elements),
+            AnyAccumulator::Mmr(accumulator) => accumulator.add(elements),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.add(elements),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.add(elements),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.add(elements),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.add(elements),
+        }
+    }
+
+    fn delete(&mut self, elements: Vec<OutPoint>) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::Mmr(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.delete(elements),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.delete(elements),
+        }
+    }
+
+    fn update(&mut self, elements: Vec<(OutPoint, CellStatus)>) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.update(elements),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.update(elements),
+            AnyAccumulator::Mmr(accumulator) => accumulator.update(elements),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.update(elements),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.update(elements),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.update(elements),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.update(elements),
+        }
+    }
+
+    fn commit(&mut self) -> Result<[u8; 32], AccumulatorError> {
+        Ok(match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.commit()?.commitment_digest(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.commit()?.commitment_digest(),
+        })
+    }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::Mmr(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.skip_to(sequence),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.skip_to(sequence),
+        }
+    }
+}
+
+impl<D, WO> AnyAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    /// Returns the sequence seen by this accumulator, i.e. the one it was opened with, and the digest of its commitment.
+    pub fn commitment(&self) -> Result<(u64, CommitmentDigest), AccumulatorError> {
+        let commitment = self.backend_commitment()?;
+        Ok((commitment.sequence(), commitment.commitment_digest()))
+    }
+
+    /// Returns the commitment of the state seen by this accumulator in the format of its backend, which its proofs
+    /// are requested and verified with.
+    pub fn backend_commitment(&self) -> Result<AnyCommitment, AccumulatorError> {
+        Ok(match self {
+            AnyAccumulator::Smt(accumulator) => AnyCommitment::Smt(accumulator.commitment()),
+            AnyAccumulator::SmtLive(accumulator) => {
+                AnyCommitment::SmtLive(accumulator.commitment())
+            }
+            AnyAccumulator::Mmr(accumulator) => AnyCommitment::Mmr(accumulator.commitment()?),
+            AnyAccumulator::SmtPresence(accumulator) => {
+                AnyCommitment::SmtPresence(accumulator.commitment())
+            }
+            // the sharded smt has the tree and the commitments of the smt
+            AnyAccumulator::SmtSharded(accumulator) => AnyCommitment::Smt(accumulator.commitment()),
+            AnyAccumulator::SmtTiered(accumulator) => {
+                AnyCommitment::SmtTiered(accumulator.commitment())
+            }
+            AnyAccumulator::MmrPositioned(accumulator) => {
+                AnyCommitment::MmrPositioned(accumulator.commitment()?)
+            }
+        })
+    }
+
+    /// Returns the commitment the backend would commit after applying `block`, without persisting anything, see
+    /// `SMTAccumulator::preview_root`.
+    pub fn preview_root(&self, block: BlockOps) -> Result<AnyCommitment, AccumulatorError> {
+        Ok(match self {
+            AnyAccumulator::Smt(accumulator) => {
+                AnyCommitment::Smt(accumulator.preview_root(block)?)
+            }
+            AnyAccumulator::SmtLive(accumulator) => {
+                AnyCommitment::SmtLive(accumulator.preview_root(block)?)
+            }
+            AnyAccumulator::Mmr(accumulator) => {
+                AnyCommitment::Mmr(accumulator.preview_root(block)?)
+            }
+            AnyAccumulator::SmtPresence(accumulator) => {
+                AnyCommitment::SmtPresence(accumulator.preview_root(block)?)
+            }
+            AnyAccumulator::SmtSharded(_) | AnyAccumulator::SmtTiered(_) => {
+                return Err(AccumulatorError::InternalError(
+                    "the sharded and tiered smt can't preview a root".to_string(),
+                ))
+            }
+            AnyAccumulator::MmrPositioned(_) => {
+                return Err(AccumulatorError::InternalError(
+                    "the positioned mmr can't preview a root".to_string(),
+                ))
+            }
+        })
+    }
+
+    /// Returns the status of an element as seen at the sequence of this accumulator, `None` if the element doesn't
+    /// exist yet.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::Mmr(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.cell_status(out_point),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.cell_status(out_point),
+        }
+    }
+
+    /// Generates a proof that none of the given out_points is in the state seen by this accumulator, see
+    /// `SMTAccumulator::absence_proof`, the mmr and the tiered smt can't prove an absence.
+    pub fn absence_proof(
+        &self,
+        out_points: Vec<OutPoint>,
+    ) -> Result<AnyAbsenceProof, AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => {
+                Ok(AnyAbsenceProof::Smt(accumulator.prove_absence(out_points)?))
+            }
+            AnyAccumulator::SmtLive(accumulator) => Ok(AnyAbsenceProof::SmtLive(
+                accumulator.prove_absence(out_points)?,
+            )),
+            AnyAccumulator::Mmr(_) | AnyAccumulator::MmrPositioned(_) => Err(
+                AccumulatorError::InternalError("the mmr can't prove an absence".to_string()),
+            ),
+            AnyAccumulator::SmtPresence(accumulator) => Ok(AnyAbsenceProof::SmtPresence(
+                accumulator.prove_absence(out_points)?,
+            )),
+            AnyAccumulator::SmtSharded(accumulator) => {
+                Ok(AnyAbsenceProof::Smt(accumulator.prove_absence(out_points)?))
+            }
+            AnyAccumulator::SmtTiered(_) => Err(AccumulatorError::InternalError(
+                "the tiered smt can't prove an absence".to_string(),
+            )),
+        }
+    }
+}
+
+impl<D, WO> AccumulatorReader for AnyAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    type Item = OutPoint;
+    type Commitment = AnyCommitment;
+    type Proof = AnyProof;
+
+    fn proof(
+        &self,
+        commitment: AnyCommitment,
+        elements: Vec<OutPoint>,
+    ) -> Result<AnyProof, AccumulatorError> {
+        Ok(match (self, commitment) {
+            (AnyAccumulator::Smt(accumulator), AnyCommitment::Smt(commitment)) => {
+                AnyProof::Smt(accumulator.proof(commitment, elements)?)
+            }
+            (AnyAccumulator::SmtLive(accumulator), AnyCommitment::SmtLive(commitment)) => {
+                AnyProof::SmtLive(accumulator.proof(commitment, elements)?)
+            }
+            (AnyAccumulator::Mmr(accumulator), AnyCommitment::Mmr(commitment)) => {
+                AnyProof::Mmr(accumulator.proof(commitment, elements)?)
+            }
+            (AnyAccumulator::SmtPresence(accumulator), AnyCommitment::SmtPresence(commitment)) => {
+                AnyProof::SmtPresence(accumulator.proof(commitment, elements)?)
+            }
+            (AnyAccumulator::SmtSharded(accumulator), AnyCommitment::Smt(commitment)) => {
+                AnyProof::Smt(accumulator.proof(commitment, elements)?)
+            }
+            (AnyAccumulator::SmtTiered(accumulator), AnyCommitment::SmtTiered(commitment)) => {
+                AnyProof::SmtTiered(accumulator.proof(commitment, elements)?)
+            }
+            (
+                AnyAccumulator::MmrPositioned(accumulator),
+                AnyCommitment::MmrPositioned(commitment),
+            ) => AnyProof::MmrPositioned(accumulator.proof(commitment, elements)?),
+            _ => return Err(AccumulatorError::InvalidCommitment),
+        })
+    }
+}
+
+/// The commitment of any backend, see `AnyAccumulator::backend_commitment`.
+#[derive(Clone, Debug)]
+pub enum AnyCommitment {
+    Smt(SMTCommitment),
+    SmtLive(SMTLiveCommitment),
+    Mmr(MMRCommitment),
+    SmtPresence(PresenceCommitment),
+    SmtTiered(TieredCommitment),
+    MmrPositioned(PositionedCommitment),
+}
+
+impl AnyCommitment {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            AnyCommitment::Smt(commitment) => commitment.sequence(),
+            AnyCommitment::SmtLive(commitment) => commitment.sequence(),
+            AnyCommitment::Mmr(commitment) => commitment.sequence(),
+            AnyCommitment::SmtPresence(commitment) => commitment.sequence(),
+            AnyCommitment::SmtTiered(commitment) => commitment.sequence(),
+            AnyCommitment::MmrPositioned(commitment) => commitment.sequence(),
+        }
+    }
+
+    /// See `crate::commitment_digest`, tagged with the tag of the backend.
+    pub fn commitment_digest(&self) -> CommitmentDigest {
+        match self {
+            AnyCommitment::Smt(commitment) => commitment.commitment_digest(),
+            AnyCommitment::SmtLive(commitment) => commitment.commitment_digest(),
+            AnyCommitment::Mmr(commitment) => commitment.commitment_digest(),
+            AnyCommitment::SmtPresence(commitment) => commitment.commitment_digest(),
+            AnyCommitment::SmtTiered(commitment) => commitment.commitment_digest(),
+            AnyCommitment::MmrPositioned(commitment) => commitment.commitment_digest(),
+        }
+    }
+
+    /// The `AccumulatorCommitment::to_bytes` of the backend.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            AnyCommitment::Smt(commitment) => commitment.to_bytes(),
+            AnyCommitment::SmtLive(commitment) => commitment.to_bytes(),
+            AnyCommitment::Mmr(commitment) => commitment.to_bytes(),
+            AnyCommitment::SmtPresence(commitment) => commitment.to_bytes(),
+            AnyCommitment::SmtTiered(commitment) => commitment.to_bytes(),
+            AnyCommitment::MmrPositioned(commitment) => commitment.to_bytes(),
+        }
+    }
+}
+
+/// The proof of any backend, verified against the `AnyCommitment` of the same backend. A consumed cell of `smt_live`
+/// is also proved against the commitment consuming it, which a single commitment doesn't provide, so only its live
+/// cells are verified through this type.
+pub enum AnyProof {
+    Smt(SMTProof),
+    SmtLive(SMTLiveProof),
+    Mmr(MMRProof),
+    SmtPresence(PresenceProof),
+    SmtTiered(TieredProof),
+    MmrPositioned(PositionedProof),
+}
+
+impl Proof for AnyProof {
+    type Item = (OutPoint, CellStatus);
+    type Commitment = AnyCommitment;
+
+    fn verify(
+        self,
+        commitment: AnyCommitment,
+        elements: Vec<(OutPoint, CellStatus)>,
+    ) -> Result<bool, AccumulatorError> {
+        match (self, commitment) {
+            (AnyProof::Smt(proof), AnyCommitment::Smt(commitment)) => {
+                proof.verify(commitment, elements)
+            }
+            (AnyProof::SmtLive(proof), AnyCommitment::SmtLive(commitment)) => {
+                let commitments = elements
+                    .iter()
+                    .map(|_| (commitment.clone(), None))
+                    .collect();
+                proof.verify(commitments, elements)
+            }
+            (AnyProof::Mmr(proof), AnyCommitment::Mmr(commitment)) => {
+                proof.verify(commitment, elements)
+            }
+            (AnyProof::SmtPresence(proof), AnyCommitment::SmtPresence(commitment)) => {
+                Proof::verify(proof, commitment, elements)
+            }
+            (AnyProof::SmtTiered(proof), AnyCommitment::SmtTiered(commitment)) => {
+                proof.verify(commitment, elements)
+            }
+            (AnyProof::MmrPositioned(proof), AnyCommitment::MmrPositioned(commitment)) => {
+                proof.verify(commitment, elements)
+            }
+            _ => Err(AccumulatorError::InvalidCommitment),
+        }
+    }
+
+    /// Hands the proofs of a `smt` commitment to `SMTProof::verify_batch`, the proofs of the other backends are verified
+    /// in turn.
+    fn verify_batch(
+        commitment: AnyCommitment,
+        proofs: Vec<(Vec<(OutPoint, CellStatus)>, AnyProof)>,
+    ) -> Result<bool, AccumulatorError> {
+        match commitment {
+            AnyCommitment::Smt(commitment) => {
+                let proofs = proofs
+                    .into_iter()
+                    .map(|(elements, proof)| match proof {
+                        AnyProof::Smt(proof) => Ok((elements, proof)),
+                        _ => Err(AccumulatorError::InvalidCommitment),
+                    })
+                    .collect::<Result<_, _>>()?;
+                SMTProof::verify_batch(commitment, proofs)
+            }
+            commitment => {
+                for (elements, proof) in proofs {
+                    if !proof.verify(commitment.clone(), elements)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// The absence proof of a smt backend, see `AnyAccumulator::absence_proof`.
+pub enum AnyAbsenceProof {
+    Smt(SMTAbsenceProof),
+    SmtLive(SMTLiveAbsenceProof),
+    SmtPresence(PresenceProof),
+}
+
+impl AnyAbsenceProof {
+    pub fn verify(&self, commitment: &AnyCommitment) -> Result<bool, AccumulatorError> {
+        match (self, commitment) {
+            (AnyAbsenceProof::Smt(proof), AnyCommitment::Smt(commitment)) => {
+                proof.verify(commitment)
+            }
+            (AnyAbsenceProof::SmtLive(proof), AnyCommitment::SmtLive(commitment)) => {
+                proof.verify(commitment)
+            }
+            // a proof of live cells doesn't verify as an absence proof
+            (AnyAbsenceProof::SmtPresence(proof), AnyCommitment::SmtPresence(commitment)) => {
+                Ok(!proof.is_present() && proof.verify(commitment)?)
+            }
+            _ => Err(AccumulatorError::InvalidCommitment),
+        }
+    }
+}
+
+impl<D, WO> LevelWrites for AnyAccumulator<D, WO> {
+    fn level_writes(&self) -> Option<&LevelHistogram> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.level_writes(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.level_writes(),
+        }
+    }
+}
+
+impl<D, WO> FlushWrites for AnyAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: Put<WO>,
+{
+    fn set_flush_mode(&mut self, mode: FlushMode) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::Mmr(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.set_flush_mode(mode),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.set_flush_mode(mode),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), AccumulatorError> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.flush(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.flush(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.flush(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.flush(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.flush(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.flush(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.flush(),
+        }
+    }
+
+    fn flush_stats(&self) -> Option<&FlushStats> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.flush_stats(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.flush_stats(),
+        }
+    }
+}
+
+impl<D, WO> CoalescedOps for AnyAccumulator<D, WO> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.coalesced_ops(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.coalesced_ops(),
+        }
+    }
+}
+
+impl<D, WO> StageTiming for AnyAccumulator<D, WO> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::Mmr(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.stage_times(),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.stage_times(),
+        }
+    }
+}
+
+impl<D, WO> TraceKeys for AnyAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        match self {
+            AnyAccumulator::Smt(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::SmtLive(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::Mmr(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::SmtPresence(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::SmtSharded(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::SmtTiered(accumulator) => accumulator.trace_keys(tracer),
+            AnyAccumulator::MmrPositioned(accumulator) => accumulator.trace_keys(tracer),
+        }
+    }
+}
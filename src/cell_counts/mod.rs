@@ -0,0 +1,211 @@
+use crate::{
+    keycodec::{parse_sequence_key, sequence_key},
+    new_blake2b,
+    storage::traits::ReadStore,
+    AccumulatorError, BlockOps, CellStatus, OutPoint,
+};
+use rocksdb::{prelude::Put, Direction, IteratorMode};
+use std::{collections::HashSet, fmt, marker::PhantomData};
+
+#[cfg(test)]
+mod tests;
+
+pub const CELL_COUNTS_KEY: &[u8] = b"COUNTS";
+
+/// The running counters of the cells of an accumulator as of a sequence, the live cells are the created ones not spent yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CellCounts {
+    pub created: u64,
+    pub spent: u64,
+}
+
+impl CellCounts {
+    pub fn live(&self) -> u64 {
+        self.created - self.spent
+    }
+
+    /// The counters after the operations of a block, which must all change the set of live cells, see
+    /// `performed_ops`.
+    pub fn apply(&self, ops: &BlockOps) -> Self {
+        self.apply_transitions(ops.added.len() as u64, ops.deleted.len() as u64)
+    }
+
+    /// The counters after `created` cells were created and `spent` live cells were spent.
+    pub fn apply_transitions(&self, created: u64, spent: u64) -> Self {
+        CellCounts {
+            created: self.created + created,
+            spent: self.spent + spent,
+        }
+    }
+
+    /// Folds the live count into a commitment digest, blake2b(digest || live count as u64 LE), so the number of live
+    /// cells is provable alongside the root.
+    pub fn fold_into(&self, digest: [u8; 32]) -> [u8; 32] {
+        let mut folded = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&digest);
+        hasher.update(&self.live().to_le_bytes());
+        hasher.finalize(&mut folded);
+        folded
+    }
+
+    /// Serialize as `created (8 LE) | spent (8 LE)`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.created.to_le_bytes(), self.spent.to_le_bytes()].concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 16 {
+            return Err(AccumulatorError::InternalError(
+                "invalid cell counts record".to_string(),
+            ));
+        }
+        let counts = CellCounts {
+            created: u64::from_le_bytes(slice[0..8].try_into().expect("checked length")),
+            spent: u64::from_le_bytes(slice[8..16].try_into().expect("checked length")),
+        };
+        if counts.spent > counts.created {
+            return Err(AccumulatorError::InternalError(
+                "invalid cell counts record".to_string(),
+            ));
+        }
+        Ok(counts)
+    }
+}
+
+impl fmt::Display for CellCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cells: {} created, {} spent, {} live",
+            self.created,
+            self.spent,
+            self.live()
+        )
+    }
+}
+
+/// The operations of a block which change the set of live cells, given `status`, the status of a cell before the
+/// block: the adds of cells which aren't live and the deletes of live cells or of cells added by the block, each cell
+/// once. A non-strict accumulator accepts the other operations, e.g. the delete of a consumed cell, as no-ops which the
+/// counters must not count. A strict accumulator rejects them, so its blocks are performed as they are.
+pub fn performed_ops<F>(ops: &BlockOps, mut status: F) -> Result<BlockOps, AccumulatorError>
+where
+    F: FnMut(&OutPoint) -> Result<Option<CellStatus>, AccumulatorError>,
+{
+    let mut is_live = |out_point: &OutPoint| -> Result<bool, AccumulatorError> {
+        Ok(status(out_point)?.map_or(false, |status| status.is_live()))
+    };
+    let mut created = HashSet::new();
+    let mut added = Vec::new();
+    for out_point in &ops.added {
+        if !created.contains(out_point) && !is_live(out_point)? {
+            created.insert(out_point.clone());
+            added.push(out_point.clone());
+        }
+    }
+    let mut spent = HashSet::new();
+    let mut deleted = Vec::new();
+    for out_point in &ops.deleted {
+        if !spent.contains(out_point) && (created.contains(out_point) || is_live(out_point)?) {
+            spent.insert(out_point.clone());
+            deleted.push(out_point.clone());
+        }
+    }
+    Ok(BlockOps { added, deleted })
+}
+
+/// The `CellCounts` of every committed sequence, stored next to the accumulator in the same RocksDB. Like the oplog, a
+/// record is keyed by sequence directly and written once when the sequence is committed.
+pub struct CellCounter<'a, DB, WO> {
+    inner: &'a DB,
+    write_options: PhantomData<WO>,
+}
+
+impl<'a, DB, WO> CellCounter<'a, DB, WO> {
+    pub fn new(db: &'a DB) -> Self {
+        CellCounter {
+            inner: db,
+            write_options: PhantomData,
+        }
+    }
+}
+
+impl<'a, DB, WO> CellCounter<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the counters as of the given sequence, or `None` if no record was written.
+    pub fn counts_at(&self, sequence: u64) -> Result<Option<CellCounts>, AccumulatorError> {
+        self.inner
+            .get(sequence_key(CELL_COUNTS_KEY, sequence))
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?
+            .map(|v| CellCounts::from_bytes(v.as_ref()))
+            .transpose()
+    }
+}
+
+impl<'a, DB, WO> CellCounter<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the last recorded sequence and its counters, or `None` if the counters were never recorded.
+    pub fn latest(&self) -> Result<Option<(u64, CellCounts)>, AccumulatorError> {
+        let last_key = sequence_key(CELL_COUNTS_KEY, u64::MAX);
+        let mode = IteratorMode::From(&last_key, Direction::Reverse);
+        self.inner
+            .iterator(mode)
+            .next()
+            .and_then(|(key, value)| {
+                parse_sequence_key(CELL_COUNTS_KEY, &key).map(|sequence| (sequence, value))
+            })
+            .map(|(sequence, value)| Ok((sequence, CellCounts::from_bytes(&value)?)))
+            .transpose()
+    }
+}
+
+impl<'a, DB, WO> CellCounter<'a, DB, WO>
+where
+    DB: ReadStore + Put<WO>,
+{
+    /// Records the counters of a sequence from the counters of the previous one and the operations of the sequence,
+    /// which must all change the set of live cells, see `performed_ops`. The first sequence starts from zero, the
+    /// counters can't be started on a database which has committed sequences without them.
+    pub fn record(
+        &mut self,
+        sequence: u64,
+        ops: &BlockOps,
+    ) -> Result<CellCounts, AccumulatorError> {
+        self.record_transitions(sequence, ops.added.len() as u64, ops.deleted.len() as u64)
+    }
+
+    /// Records the counters of a sequence from the counters of the previous one and the cells created and spent by the
+    /// sequence, e.g. including the cells of an expiry sweep.
+    pub fn record_transitions(
+        &mut self,
+        sequence: u64,
+        created: u64,
+        spent: u64,
+    ) -> Result<CellCounts, AccumulatorError> {
+        let previous = match sequence.checked_sub(1) {
+            Some(previous) => self.counts_at(previous)?.ok_or_else(|| {
+                AccumulatorError::InternalError(format!(
+                    "missing cell counts of sequence {}",
+                    previous
+                ))
+            })?,
+            None => CellCounts::default(),
+        };
+        let counts = previous.apply_transitions(created, spent);
+        self.put(sequence, &counts)?;
+        Ok(counts)
+    }
+
+    /// Writes the counters of a sequence as they are, e.g. to replay a sequence recorded before.
+    pub fn put(&mut self, sequence: u64, counts: &CellCounts) -> Result<(), AccumulatorError> {
+        self.inner
+            .put(sequence_key(CELL_COUNTS_KEY, sequence), counts.to_bytes())
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+}
@@ -0,0 +1,94 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use crate::{BlockOps, CellStatus, OutPoint};
+
+use super::{performed_ops, CellCounter, CellCounts};
+
+fn out_point(i: u8) -> OutPoint {
+    OutPoint {
+        tx_hash: [i; 32],
+        index: i as u32,
+    }
+}
+
+#[test]
+fn test_record_cell_counts() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let mut counter = CellCounter::new(&tx);
+
+    let ops = BlockOps {
+        added: vec![out_point(0), out_point(1), out_point(2)],
+        deleted: vec![],
+    };
+    assert_eq!(3, counter.record(0, &ops).unwrap().live());
+    let ops = BlockOps {
+        added: vec![out_point(3)],
+        deleted: vec![out_point(0), out_point(2)],
+    };
+    let counts = counter.record(1, &ops).unwrap();
+    assert_eq!(
+        CellCounts {
+            created: 4,
+            spent: 2
+        },
+        counts
+    );
+    // the counters of a sequence build on the previous one
+    assert!(counter.record(3, &ops).is_err());
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let counter = CellCounter::<_, ()>::new(&snapshot);
+    assert_eq!(Some(counts), counter.counts_at(1).unwrap());
+    assert_eq!(Some((1, counts)), counter.latest().unwrap());
+    assert_eq!(counts, CellCounts::from_bytes(&counts.to_bytes()).unwrap());
+
+    // the folded digest commits to the live count
+    let digest = [7u8; 32];
+    assert_ne!(digest, counts.fold_into(digest));
+    assert_ne!(
+        counts.fold_into(digest),
+        CellCounts {
+            created: 5,
+            spent: 2
+        }
+        .fold_into(digest)
+    );
+}
+
+#[test]
+fn test_performed_ops() {
+    // 0 is live, 1 is consumed and 2 doesn't exist
+    let status = |out_point: &OutPoint| {
+        Ok(match out_point.index {
+            0 => Some(CellStatus::new_live(0)),
+            1 => Some(CellStatus::new_dead(0, 1)),
+            _ => None,
+        })
+    };
+    let ops = BlockOps {
+        added: vec![out_point(0), out_point(3), out_point(3), out_point(4)],
+        deleted: vec![
+            out_point(0),
+            out_point(0),
+            out_point(1),
+            out_point(2),
+            out_point(4),
+        ],
+    };
+    // the live cell 0 isn't created again, the consumed and unknown cells aren't spent, a cell counts once
+    let performed = performed_ops(&ops, status).unwrap();
+    assert_eq!(vec![out_point(3), out_point(4)], performed.added);
+    assert_eq!(vec![out_point(0), out_point(4)], performed.deleted);
+    let counts = CellCounts::default().apply(&performed);
+    assert_eq!(
+        CellCounts {
+            created: 2,
+            spent: 2
+        },
+        counts
+    );
+}
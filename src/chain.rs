@@ -0,0 +1,60 @@
+use crate::{AccumulatorError, BlockOps};
+
+/// A block of the simulated chain: its cells and the commitment digest of the state it's built on, i.e. of its parent.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    pub number: u64,
+    pub parent_commitment: [u8; 32],
+    pub ops: BlockOps,
+}
+
+/// The header of an applied block, embedding the commitment digest of the accumulator state after the block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
+    pub number: u64,
+    pub parent_commitment: [u8; 32],
+    pub commitment: [u8; 32],
+}
+
+/// A simulated header chain, it hands out the next block on top of its tip and links the headers of the applied blocks
+/// by their commitments. Only the tip is kept.
+#[derive(Clone, Debug, Default)]
+pub struct HeaderChain {
+    tip: Option<Header>,
+    next_number: u64,
+}
+
+impl HeaderChain {
+    /// A chain whose first block is `start_block_number`, its parent commitment is zero.
+    pub fn new(start_block_number: u64) -> Self {
+        HeaderChain {
+            tip: None,
+            next_number: start_block_number,
+        }
+    }
+
+    pub fn tip(&self) -> Option<&Header> {
+        self.tip.as_ref()
+    }
+
+    /// The next block of the chain with the cells of `ops`.
+    pub fn next_block(&self, ops: BlockOps) -> Block {
+        Block {
+            number: self.next_number,
+            parent_commitment: self.tip.map(|tip| tip.commitment).unwrap_or_default(),
+            ops,
+        }
+    }
+
+    /// Appends the header of a block applied on top of the tip.
+    pub fn append(&mut self, header: Header) -> Result<&Header, AccumulatorError> {
+        let parent_commitment = self.tip.map(|tip| tip.commitment).unwrap_or_default();
+        if header.number != self.next_number || header.parent_commitment != parent_commitment {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        self.next_number += 1;
+        Ok(self.tip.insert(header))
+    }
+}
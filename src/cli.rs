@@ -0,0 +1,690 @@
+// the command line of the benchmark binary, every subcommand has its own typed arguments, a command line starting with a
+// backend instead of a subcommand runs `bench` so the scripts written for the positional invocation keep working
+
+#[cfg(feature = "serde")]
+use clap::{parser::ValueSource, ArgMatches};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use dca_bench::{
+    metrics::{FlushMode, ReportInterval},
+    retry::RetryPolicy,
+    sequence_width::SequenceWidth,
+    sink::SinkTarget,
+    smt,
+    sweep::ExpirySweep,
+    workload::{model::ChainStats, Spikes, Timelocks},
+    SequenceSource,
+};
+#[cfg(feature = "grpc")]
+use std::net::SocketAddr;
+use std::{ffi::OsString, path::PathBuf, time::Duration};
+
+pub const BACKENDS: [&str; 3] = ["smt", "mmr", "smt_live"];
+// the backends which can update their tree in chunks and prove an absence
+const SMT_BACKENDS: [&str; 2] = ["smt", "smt_live"];
+// the backends which can be benchmarked, `smt_presence` is only a baseline for the cost of the cell statuses
+const BENCH_BACKENDS: [&str; 4] = ["smt", "mmr", "smt_live", "smt_presence"];
+
+/// Benchmarks the dynamic cryptographic accumulators on RocksDB
+#[derive(Parser)]
+#[command(name = "dca-bench", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Parses the arguments of the process, `dca-bench <backend> ...` is an alias of `dca-bench bench <backend> ...`.
+    /// The arguments are taken as OS strings, the paths don't have to be valid UTF-8.
+    pub fn parse_compat() -> Self {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        if args
+            .get(1)
+            .and_then(|arg| arg.to_str())
+            .map_or(false, |arg| BENCH_BACKENDS.contains(&arg))
+        {
+            args.insert(1, "bench".into());
+        }
+        let matches = Cli::command().get_matches_from(&args);
+        #[cfg_attr(not(feature = "serde"), allow(unused_mut))]
+        let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        #[cfg(feature = "serde")]
+        if let (Command::Bench(bench), Some(("bench", matches))) =
+            (&mut cli.command, matches.subcommand())
+        {
+            bench.config = config_args(matches);
+        }
+        cli
+    }
+}
+
+// the configuration of a run is its backend, block range and flags, without the database path and the paths of its output
+// files, so the runs of a configuration are compared in the results ledger whatever they are written to. Every argument
+// given on the command line is a `<name>=<values>` entry of the parsed arguments, and `config_hash` sorts them, so the
+// spelling and the order of the flags don't change the configuration.
+#[cfg(feature = "serde")]
+fn config_args(matches: &ArgMatches) -> Vec<String> {
+    const PATHS: [&str; 6] = [
+        "db_path",
+        "metrics",
+        "prometheus",
+        "results",
+        "profile",
+        "publish",
+    ];
+    let command = Cli::command();
+    let bench = command
+        .find_subcommand("bench")
+        .expect("the bench subcommand");
+    bench
+        .get_arguments()
+        .map(|arg| arg.get_id().as_str())
+        .filter(|id| !PATHS.contains(id))
+        .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        .map(|id| {
+            let values: Vec<String> = matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect();
+            format!("{}={}", id, values.join(","))
+        })
+        .collect()
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs the benchmark blocks on a database, also the default when the first argument is a backend
+    Bench(BenchArgs),
+    /// Checks the consistency of a benchmark database at its last committed sequence
+    Fsck(FsckArgs),
+    /// Repeatedly kills a benchmark run and checks the database it leaves behind
+    CrashTest(CrashTestArgs),
+    /// Measures the read latency of every key family under different prefix extractor and bloom filter settings
+    TunePrefix(TunePrefixArgs),
+    /// Reports the key families, cell counters, latest commitments and hottest keys of a benchmark database
+    Stats(StatsArgs),
+    /// Lists every stored version of a key
+    History(HistoryArgs),
+    /// Opens an interactive prompt to inspect the roots, cells, proofs and keys of a benchmark database
+    Inspect(InspectArgs),
+    /// Builds a tree in a new database from a file of pre-hashed leaves
+    BuildTree(BuildTreeArgs),
+    /// Measures the trade-off of splitting the tree updates of large blocks into chunks
+    UpdateChunks(UpdateChunksArgs),
+    /// Measures the spend throughput of the smt backend with and without the live cache, and the memory of the cache
+    LiveCache(LiveCacheArgs),
+    /// Measures the database size of the inline and the shared smt branch storage over the same blocks
+    BranchStorage(BranchStorageArgs),
+    /// Measures the commit time and database size of one mmr commitment per range of blocks against one per block
+    RangeCommit(RangeCommitArgs),
+    /// Re-derives the audit log of a benchmark database from its oplog
+    Audit(AuditArgs),
+    /// Checks which backends are history independent
+    HistoryAudit(HistoryAuditArgs),
+    /// Prints the canonical dump of the keys and values of a database
+    DumpLayout(DumpLayoutArgs),
+    /// Measures the throughput of serving proofs, generated on request and from a proof cache
+    ProofCache(ProofCacheArgs),
+    /// Measures the latency and size of absence proofs
+    AbsenceProofs(AbsenceProofsArgs),
+    /// Measures the throughput of verifying proofs one by one and in batches against one commitment
+    VerifyBatch(VerifyBatchArgs),
+    /// Measures the proof latency of readers opened at sequences behind the latest one
+    ProofLag(ProofLagArgs),
+    /// Exports the tree of a smt database to flat files and measures the proofs served from them
+    ExportFlat(ExportFlatArgs),
+    /// Measures a fast sync of a new node to a sequence of a benchmark database
+    Sync(SyncArgs),
+    /// Copies a benchmark database with its versioned keys re-encoded in another sequence width
+    MigrateWidth(MigrateWidthArgs),
+    /// Prints a markdown table comparing metrics files
+    Report(ReportArgs),
+    /// Reports the regressions of the latest runs of a results ledger
+    #[cfg(feature = "serde")]
+    Trend(TrendArgs),
+    /// Runs every combination of a benchmark matrix
+    #[cfg(feature = "serde")]
+    RunMatrix(RunMatrixArgs),
+    /// Serves status, proof and commitment queries over gRPC
+    #[cfg(feature = "grpc")]
+    Serve(ServeArgs),
+    /// Prints the completion script of a shell
+    Completions(CompletionsArgs),
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    #[arg(value_parser = BENCH_BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub start_block_number: u64,
+    pub total_blocks: u64,
+    /// The RocksDB flavor to write to
+    #[arg(long, default_value = "optimistic", value_parser = ["optimistic", "pessimistic", "plain"])]
+    pub db_kind: String,
+    /// Rejects adding an existing cell and consuming a dead one
+    #[arg(long)]
+    pub strict: bool,
+    /// Records the added and deleted cells of every block in the operation log
+    #[arg(long = "oplog")]
+    pub record_oplog: bool,
+    /// Chains the operations of every block into the digests of the audit log, needs --oplog
+    #[arg(long)]
+    pub audit_log: bool,
+    /// Records the cell counters of every sequence and folds the live count into the header commitments
+    #[arg(long)]
+    pub cell_counts: bool,
+    /// Runs the soak checks every given number of blocks
+    #[arg(long, value_name = "n", value_parser = clap::value_parser!(u64).range(1..))]
+    pub soak: Option<u64>,
+    /// Measures the witness of every block
+    #[arg(long)]
+    pub block_witness: bool,
+    /// Measures the witness of every block and alerts above the given size, implies --block-witness
+    #[arg(long, value_name = "bytes")]
+    pub witness_alert: Option<u64>,
+    /// Commits the transaction of every commit interval on a worker thread while the next one is written, and generates
+    /// the blocks on a background thread
+    #[arg(long)]
+    pub overlap_commit: bool,
+    /// Number of outputs of the workload transactions
+    #[arg(long, value_name = "n", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub outputs_per_tx: u64,
+    /// Makes every given block a spike block creating and consuming the given numbers of extra cells
+    #[arg(long, value_name = "interval>,<created>,<consumed", value_parser = parse_spikes)]
+    pub spikes: Option<Spikes>,
+    /// Timelocks the given percent of the cells and sweeps the ones older than the given number of sequences every given blocks
+    #[arg(long, value_name = "interval>,<age>,<percent", value_parser = parse_expiry_sweep)]
+    pub expiry_sweep: Option<ExpirySweep>,
+    /// Generates the workload from a model fitted to the given chain statistics
+    #[arg(
+        long,
+        value_name = "txs=<mean>,outputs=<mean>,inputs=<mean>,age-median=<blocks>,age-mean=<blocks>"
+    )]
+    pub workload_model: Option<ChainStats>,
+    /// Reports the branch writes per tree level
+    #[arg(long)]
+    pub level_writes: bool,
+    /// Builds every block as a block template in a pending overlay before promoting it
+    #[arg(long)]
+    pub speculative: bool,
+    /// Traces the keys and reports the given number of the hottest ones
+    #[arg(long, value_name = "n")]
+    pub hot_keys: Option<usize>,
+    /// Leaf value encoding of a new smt database
+    #[arg(long, value_parser = parse_leaf_encoding)]
+    pub leaf_encoding: Option<smt::LeafEncoding>,
+    /// Leaf key derivation of a new smt database
+    #[arg(long, value_parser = parse_key_derivation)]
+    pub key_derivation: Option<smt::KeyDerivation>,
+    /// Branch node storage of a new smt database
+    #[arg(long, value_parser = parse_branch_storage)]
+    pub branch_storage: Option<smt::BranchStorage>,
+    /// Sequence width of the versioned keys of a new database
+    #[arg(long, value_parser = parse_sequence_width)]
+    pub sequence_width: Option<SequenceWidth>,
+    /// Writes checked element index records in a new mmr database
+    #[arg(long)]
+    pub element_integrity: bool,
+    /// When the mmr writes the batches of its commits to the database
+    #[arg(long = "mmr-flush", value_parser = parse_flush_mode)]
+    pub flush_mode: Option<FlushMode>,
+    /// Prints the sizes of the flushed mmr batches every 100 blocks
+    #[arg(long)]
+    pub flush_metrics: bool,
+    /// Garbage collects the mmr element index every 100 blocks, keeping the given number of sequences of history
+    #[arg(long, value_name = "n")]
+    pub gc_retention: Option<u64>,
+    /// Expires the superseded versions of the versioned keys of the mmr, smt or smt_live backend during the compactions,
+    /// keeping the given number of sequences of history
+    #[arg(long, value_name = "n")]
+    pub expire_retention: Option<u64>,
+    /// Maintains a secondary index over the given number of synthetic lock scripts
+    #[arg(long, value_name = "scripts")]
+    pub script_index: Option<u32>,
+    /// Paces the blocks to one every given milliseconds
+    #[arg(long, value_name = "ms")]
+    pub target_block_interval: Option<u64>,
+    /// Prints the report lines every given number of blocks, or every given duration, e.g. 30s
+    #[arg(long, value_name = "duration|blocks", default_value = "100")]
+    pub report_every: ReportInterval,
+    /// Splits the run into equal segments committing the transaction every given number of blocks, one per interval
+    #[arg(long, value_name = "blocks", value_delimiter = ',', value_parser = clap::value_parser!(u64).range(1..))]
+    pub commit_sweep: Vec<u64>,
+    /// Retries a transaction commit failing on a conflict the given number of times, replaying the uncommitted blocks
+    /// kept in memory after a backoff starting at the given milliseconds
+    #[arg(long, value_name = "retries>,<backoff-ms", default_value = "3,10", value_parser = parse_commit_retry)]
+    pub commit_retry: RetryPolicy,
+    /// Writes the metrics of the run to the given file
+    #[arg(long, value_name = "path")]
+    pub metrics: Option<PathBuf>,
+    /// Exports the block and stage time histograms to the given textfile collector file every 100 blocks
+    #[arg(long, value_name = "path")]
+    pub prometheus: Option<PathBuf>,
+    /// Appends the summary of the run to the given results ledger
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "path")]
+    pub results: Option<PathBuf>,
+    /// Publishes the commitment of every block to the given file or http:// url, can be repeated
+    #[arg(long, value_name = "path|url")]
+    pub publish: Vec<SinkTarget>,
+    /// Writes the flamegraphs of the add, delete and commit phases to the given directory
+    #[cfg(feature = "profiling")]
+    #[arg(long, value_name = "dir")]
+    pub profile: Option<PathBuf>,
+    /// Keeps the statuses of the live smt cells in memory for the delete path
+    #[arg(long)]
+    pub live_cache: bool,
+    /// Shards the smt over the benchmark database and the given databases
+    #[arg(long, value_name = "path", value_delimiter = ',')]
+    pub shards: Vec<PathBuf>,
+    /// Writes the blocks to a hot smt in the given database, folded into the benchmark database every given blocks
+    #[arg(long, value_name = "path>,<fold-interval", value_parser = parse_hot_tree)]
+    pub hot_tree: Option<(PathBuf, u64)>,
+    /// Where the sequences of the commits come from, one per block by default
+    #[arg(long, value_name = "block|operation|block-number", default_value = "block", value_parser = parse_sequence_source)]
+    pub sequence_source: SequenceSource,
+    /// Length of the prefix extractor
+    #[arg(long, value_name = "n")]
+    pub prefix_len: Option<usize>,
+    /// Bits per key of the bloom filters
+    #[arg(long = "bloom-bits", value_name = "n")]
+    pub bloom_bits_per_key: Option<i32>,
+    /// Stores the values of at least the given size in blob files
+    #[arg(long, value_name = "bytes")]
+    pub min_blob_size: Option<u64>,
+    /// Size of the LRU block cache
+    #[arg(long = "block-cache", value_name = "bytes")]
+    pub block_cache_size: Option<usize>,
+    /// Size of a memtable
+    #[arg(long = "write-buffer", value_name = "bytes")]
+    pub write_buffer_size: Option<usize>,
+    /// Maximum number of memtables
+    #[arg(long = "max-write-buffers", value_name = "n", value_parser = clap::value_parser!(i32).range(2..))]
+    pub max_write_buffer_number: Option<i32>,
+    /// The backend, block range and flags of the run, see `config_args`
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    pub config: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct FsckArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+}
+
+#[derive(Args)]
+pub struct CrashTestArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub rounds: u64,
+    /// Options of the benchmark runs
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub bench_options: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct TunePrefixArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 10000)]
+    pub samples_per_key_family: usize,
+}
+
+#[derive(Args)]
+pub struct StatsArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 20)]
+    pub hottest_keys: usize,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    /// An out_point as `<tx hash hex>:<index>`, or a raw key as hex without its version
+    pub key: String,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+}
+
+#[derive(Args)]
+pub struct BuildTreeArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub leaves_file: PathBuf,
+    /// Writes the given number of random leaves to the leaves file first
+    pub generate_leaves: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct UpdateChunksArgs {
+    #[arg(value_parser = SMT_BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 10000)]
+    pub outputs_per_block: usize,
+    #[arg(default_value_t = 20)]
+    pub blocks: usize,
+}
+
+#[derive(Args)]
+pub struct BranchStorageArgs {
+    pub db_path: PathBuf,
+    /// The number of blocks of the standard workload written with each storage
+    #[arg(default_value_t = 10000)]
+    pub blocks: u64,
+}
+
+#[derive(Args)]
+pub struct RangeCommitArgs {
+    pub db_path: PathBuf,
+    /// The number of blocks of the standard workload written with each commit interval
+    #[arg(default_value_t = 10000)]
+    pub blocks: u64,
+    /// The number of blocks covered by a range commitment
+    #[arg(long, value_name = "blocks", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub range: u64,
+}
+
+#[derive(Args)]
+pub struct LiveCacheArgs {
+    pub db_path: PathBuf,
+    /// The number of cells spent by each run, spread over the live cells
+    #[arg(default_value_t = 100000)]
+    pub spends: usize,
+    /// The numbers of live cells the database is filled with before the spends
+    #[arg(
+        long,
+        value_name = "cells",
+        value_delimiter = ',',
+        default_value = "1000000,10000000"
+    )]
+    pub cells: Vec<u64>,
+}
+
+#[derive(Args)]
+pub struct AuditArgs {
+    pub db_path: PathBuf,
+}
+
+#[derive(Args)]
+pub struct HistoryAuditArgs {
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 200)]
+    pub blocks: u64,
+}
+
+#[derive(Args)]
+pub struct DumpLayoutArgs {
+    pub db_path: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ProofCacheArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub sequence: u64,
+    #[arg(default_value_t = 10000)]
+    pub cells: usize,
+}
+
+#[derive(Args)]
+pub struct AbsenceProofsArgs {
+    #[arg(value_parser = SMT_BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 10000)]
+    pub proofs: usize,
+}
+
+#[derive(Args)]
+pub struct VerifyBatchArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    #[arg(default_value_t = 10000)]
+    pub proofs: usize,
+    /// The number of cells proved together by one proof
+    #[arg(long, default_value_t = 100)]
+    pub batch_size: usize,
+    /// Also verifies one witness of all the cells incrementally, in chunks of the given number of cells
+    #[arg(long, value_name = "cells")]
+    pub chunk_size: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct ProofLagArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    /// The number of proofs per lag
+    #[arg(default_value_t = 1000)]
+    pub proofs: usize,
+    /// The numbers of sequences behind the latest committed one the readers are opened at
+    #[arg(
+        long,
+        value_name = "sequences",
+        value_delimiter = ',',
+        default_value = "10,1000,100000"
+    )]
+    pub lags: Vec<u64>,
+}
+
+#[derive(Args)]
+pub struct ExportFlatArgs {
+    /// A smt benchmark database
+    pub db_path: PathBuf,
+    /// The directory of the flat files
+    pub out_dir: PathBuf,
+    /// The sequence to export, the latest committed one by default
+    #[arg(long)]
+    pub sequence: Option<u64>,
+    /// The number of proofs measured from the flat files and from RocksDB
+    #[arg(long, default_value_t = 1000)]
+    pub proofs: usize,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub client_db_path: PathBuf,
+    pub sequence: u64,
+    #[arg(default_value_t = 1000)]
+    pub chunk_size: usize,
+}
+
+#[derive(Args)]
+pub struct MigrateWidthArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    pub target_db_path: PathBuf,
+    #[arg(value_parser = parse_sequence_width)]
+    pub width: SequenceWidth,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    #[arg(required = true)]
+    pub metrics_files: Vec<PathBuf>,
+    /// The transaction rates to project the annual storage growth of the cost model at
+    #[arg(
+        long,
+        value_name = "tx/s",
+        value_delimiter = ',',
+        default_value = "10,100,1000"
+    )]
+    pub tx_rates: Vec<f64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+pub struct TrendArgs {
+    pub results_ledger: PathBuf,
+    #[arg(default_value_t = 5.0)]
+    pub threshold_percent: f64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Args)]
+pub struct RunMatrixArgs {
+    pub matrix: PathBuf,
+    pub output_dir: PathBuf,
+    /// The transaction rates to project the annual storage growth of the cost model at
+    #[arg(
+        long,
+        value_name = "tx/s",
+        value_delimiter = ',',
+        default_value = "10,100,1000"
+    )]
+    pub tx_rates: Vec<f64>,
+}
+
+#[cfg(feature = "grpc")]
+#[derive(Args)]
+pub struct ServeArgs {
+    #[arg(value_parser = BACKENDS)]
+    pub backend: String,
+    pub db_path: PathBuf,
+    /// The address the server listens on
+    #[arg(value_name = "ip:port")]
+    pub listen_addr: SocketAddr,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    pub shell: clap_complete::Shell,
+}
+
+fn parse_leaf_encoding(value: &str) -> Result<smt::LeafEncoding, String> {
+    match value {
+        "full" => Ok(smt::LeafEncoding::Full),
+        "delta" => Ok(smt::LeafEncoding::Delta),
+        _ => Err("must be full | delta".to_string()),
+    }
+}
+
+fn parse_key_derivation(value: &str) -> Result<smt::KeyDerivation, String> {
+    match value {
+        "hashed" => Ok(smt::KeyDerivation::Hashed),
+        "prefixed" => Ok(smt::KeyDerivation::Prefixed),
+        _ => Err("must be hashed | prefixed".to_string()),
+    }
+}
+
+fn parse_branch_storage(value: &str) -> Result<smt::BranchStorage, String> {
+    match value {
+        "inline" => Ok(smt::BranchStorage::Inline),
+        "shared" => Ok(smt::BranchStorage::Shared),
+        _ => Err("must be inline | shared".to_string()),
+    }
+}
+
+fn parse_sequence_width(value: &str) -> Result<SequenceWidth, String> {
+    match value {
+        "u64" => Ok(SequenceWidth::U64),
+        "u32" => Ok(SequenceWidth::U32),
+        _ => Err("must be u64 | u32".to_string()),
+    }
+}
+
+fn parse_flush_mode(value: &str) -> Result<FlushMode, String> {
+    match value {
+        "block" => Ok(FlushMode::Block),
+        "transaction" => Ok(FlushMode::Transaction),
+        _ => Err("must be block | transaction".to_string()),
+    }
+}
+
+fn parse_sequence_source(value: &str) -> Result<SequenceSource, String> {
+    match value {
+        "block" => Ok(SequenceSource::Block),
+        "operation" => Ok(SequenceSource::Operation),
+        "block-number" => Ok(SequenceSource::BlockNumber),
+        _ => Err("must be block | operation | block-number".to_string()),
+    }
+}
+
+fn parse_hot_tree(value: &str) -> Result<(PathBuf, u64), String> {
+    let (path, fold_interval) = value
+        .rsplit_once(',')
+        .ok_or("must be <path>,<fold-interval>")?;
+    match fold_interval.parse() {
+        Ok(fold_interval) if fold_interval > 0 => Ok((PathBuf::from(path), fold_interval)),
+        _ => Err("the fold interval must be a positive number".to_string()),
+    }
+}
+
+fn parse_commit_retry(value: &str) -> Result<RetryPolicy, String> {
+    let (retries, backoff) = value
+        .split_once(',')
+        .ok_or("must be <retries>,<backoff-ms>")?;
+    match (retries.parse(), backoff.parse()) {
+        (Ok(max_retries), Ok(backoff)) => Ok(RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(backoff),
+            ..RetryPolicy::default()
+        }),
+        _ => Err("must be <retries>,<backoff-ms>".to_string()),
+    }
+}
+
+fn parse_spikes(value: &str) -> Result<Spikes, String> {
+    let numbers: Vec<u64> = value
+        .split(',')
+        .map(|number| number.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "must be <interval>,<created>,<consumed>")?;
+    match numbers[..] {
+        [interval, created, consumed] if interval > 0 => Ok(Spikes {
+            interval,
+            created,
+            consumed,
+        }),
+        [_, _, _] => Err("the spike interval must be a positive number".to_string()),
+        _ => Err("must be <interval>,<created>,<consumed>".to_string()),
+    }
+}
+
+fn parse_expiry_sweep(value: &str) -> Result<ExpirySweep, String> {
+    let numbers: Vec<u64> = value
+        .split(',')
+        .map(|number| number.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "must be <interval>,<age>,<percent>")?;
+    let max_percent = u64::from(Timelocks::MAX_PERCENT);
+    match numbers[..] {
+        [interval, age, percent] if interval > 0 && (1..max_percent).contains(&percent) => {
+            Ok(ExpirySweep {
+                interval,
+                age,
+                timelocks: Timelocks {
+                    percent: percent as u8,
+                },
+            })
+        }
+        [_, _, _] => Err(format!(
+            "the sweep interval must be a positive number and the percent between 1 and {}",
+            max_percent - 1
+        )),
+        _ => Err("must be <interval>,<age>,<percent>".to_string()),
+    }
+}
@@ -0,0 +1,67 @@
+use crate::expiry::VersionExpiry;
+use rocksdb::{compaction_filter::Decision, BlockBasedOptions, Cache, Options, SliceTransform};
+
+/// RocksDB tuning of a benchmark database, applied to the default column family.
+#[derive(Clone, Debug, Default)]
+pub struct DbOptions {
+    // Length of the fixed prefix extractor, `None` disables it. The versioned reads seek backward from `key || sequence`,
+    // so it must not be longer than the shortest key (without its version) read by the backend, or the seek may miss versions.
+    pub prefix_len: Option<usize>,
+    // Bits per key of the bloom filter, built on the prefixes when a prefix extractor is set, `None` disables it.
+    pub bloom_bits_per_key: Option<i32>,
+    // Values of at least this many bytes are separated into blob files (BlobDB, WiscKey-style), the SST files only keep a
+    // pointer to them so compactions don't rewrite the values, `None` keeps every value inline.
+    pub min_blob_size: Option<u64>,
+    // Capacity in bytes of the LRU block cache of the tables, `None` keeps the RocksDB default of 8 MiB. The SMT update
+    // path reads the siblings of every updated leaf, so it depends on the cache more than the writes do.
+    pub block_cache_size: Option<usize>,
+    // Size in bytes of a memtable before it's flushed, `None` keeps the RocksDB default of 64 MiB.
+    pub write_buffer_size: Option<usize>,
+    // Maximum number of memtables in memory, the one being written included, `None` keeps the RocksDB default of 2.
+    pub max_write_buffer_number: Option<i32>,
+    // Drops the superseded versions of the versioned keys of the backend during the compactions, `None` keeps every
+    // version.
+    pub version_expiry: Option<VersionExpiry>,
+}
+
+impl DbOptions {
+    pub fn to_rocksdb_options(&self) -> Options {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        if let Some(prefix_len) = self.prefix_len {
+            options.set_prefix_extractor(SliceTransform::create_fixed_prefix(prefix_len));
+        }
+        if self.bloom_bits_per_key.is_some() || self.block_cache_size.is_some() {
+            let mut block_options = BlockBasedOptions::default();
+            if let Some(bits_per_key) = self.bloom_bits_per_key {
+                block_options.set_bloom_filter(bits_per_key, false);
+            }
+            if let Some(capacity) = self.block_cache_size {
+                block_options.set_block_cache(&Cache::new_lru_cache(capacity));
+            }
+            options.set_block_based_table_factory(&block_options);
+        }
+        if let Some(size) = self.write_buffer_size {
+            options.set_write_buffer_size(size);
+        }
+        if let Some(number) = self.max_write_buffer_number {
+            options.set_max_write_buffer_number(number);
+        }
+        if let Some(min_blob_size) = self.min_blob_size {
+            options.set_enable_blob_files(true);
+            options.set_min_blob_size(min_blob_size);
+            // relocates the live values of old blob files during compactions, or the space of dead values is never reclaimed
+            options.set_enable_blob_gc(true);
+        }
+        if let Some(expiry) = self.version_expiry.clone() {
+            options.set_compaction_filter("version_expiry", move |_level, key, value| {
+                if expiry.expires(key, value) {
+                    Decision::Remove
+                } else {
+                    Decision::Keep
+                }
+            });
+        }
+        options
+    }
+}
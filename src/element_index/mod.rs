@@ -0,0 +1,318 @@
+use crate::{
+    keycodec::{element_key, hashed_element_key},
+    storage::traits::{RawIterator, ReadStore},
+    AccumulatorError, CellStatus, OutPoint,
+};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// How the keys of an element index are derived from the out_points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElementKeying {
+    /// `prefix || tx_hash || index LE`, see `keycodec::element_key`, the outputs of a transaction are adjacent.
+    Plain,
+    /// `prefix || out_point hash || index LE`, see `keycodec::hashed_element_key`.
+    Hashed,
+}
+
+/// How the records of an element index are encoded. Both decode into an `ElementRecord`, the encodings are those of the
+/// existing databases of the backends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordEncoding {
+    /// The created-by sequence (8 bytes LE), followed by the consumed-by sequence (8 bytes LE) once the element is
+    /// consumed, the records of `smt_live`.
+    Sequences,
+    /// The position of the leaf (8 bytes LE) followed by the 16 bytes cell status, the records of `mmr`. A longer record
+    /// carries a trailer, e.g. the checksum of a checked record, which is left to the backend.
+    Positioned,
+}
+
+/// The record of an element in an element index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElementRecord {
+    /// The position of the leaf of the element, `None` in an index without positions.
+    pub pos: Option<u64>,
+    pub status: CellStatus,
+}
+
+impl ElementRecord {
+    pub fn created_by(&self) -> u64 {
+        self.status.created_by()
+    }
+
+    /// The consumed-by sequence, `None` if the element is live.
+    pub fn consumed_by(&self) -> Option<u64> {
+        self.status.consumed_by()
+    }
+
+    /// The status of the element as seen at `sequence`, for the records which aren't versioned or were written after
+    /// the sequence: `None` if it was created after the sequence, live if it was consumed after it.
+    pub fn status_at(&self, sequence: u64) -> Option<CellStatus> {
+        if self.created_by() > sequence {
+            return None;
+        }
+        match self.consumed_by() {
+            Some(consumed_by) if consumed_by <= sequence => {
+                Some(CellStatus::new_dead(self.created_by(), consumed_by))
+            }
+            _ => Some(CellStatus::new_live(self.created_by())),
+        }
+    }
+}
+
+impl RecordEncoding {
+    pub fn encode(self, record: &ElementRecord) -> Vec<u8> {
+        match self {
+            RecordEncoding::Sequences => match record.consumed_by() {
+                Some(_) => record.status.block_numbers.to_vec(),
+                None => record.status.block_numbers[0..8].to_vec(),
+            },
+            RecordEncoding::Positioned => {
+                let pos = record.pos.expect("a positioned record has a position");
+                [
+                    pos.to_le_bytes().as_ref(),
+                    record.status.block_numbers.as_ref(),
+                ]
+                .concat()
+            }
+        }
+    }
+
+    /// Decodes a record, `None` if its length doesn't match the encoding.
+    pub fn decode(self, slice: &[u8]) -> Option<ElementRecord> {
+        match self {
+            RecordEncoding::Sequences => {
+                let created_by = u64::from_le_bytes(slice.get(0..8)?.try_into().ok()?);
+                let status = match slice.len() {
+                    8 => CellStatus::new_live(created_by),
+                    16 => CellStatus::new_dead(
+                        created_by,
+                        u64::from_le_bytes(slice[8..16].try_into().ok()?),
+                    ),
+                    _ => return None,
+                };
+                Some(ElementRecord { pos: None, status })
+            }
+            RecordEncoding::Positioned => {
+                let pos = u64::from_le_bytes(slice.get(0..8)?.try_into().ok()?);
+                let block_numbers = slice.get(8..24)?.try_into().ok()?;
+                Some(ElementRecord {
+                    pos: Some(pos),
+                    status: CellStatus { block_numbers },
+                })
+            }
+        }
+    }
+}
+
+/// Reads the records of an element index from the store of a backend. A versioned store reads the latest version of a
+/// record up to its sequence, an unversioned one keeps a single record per key.
+pub trait RecordStore {
+    fn get_record(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Reads the records of several keys, in order. A store may read them in a single pass, see `latest_versions`.
+    fn get_records(&self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        keys.iter().map(|key| self.get_record(key)).collect()
+    }
+}
+
+/// Reads the latest versions of several versioned keys, in order, `versioned` returns the versioned key of a key at the
+/// sequence read. A point lookup such as RocksDB's `multi_get` can't find the latest version of a key, so the keys are
+/// sought in sorted order on a single iterator, which is created once instead of once per key.
+pub(crate) fn latest_versions<DB, F>(db: &DB, keys: &[&[u8]], versioned: F) -> Vec<Option<Vec<u8>>>
+where
+    DB: ReadStore + ?Sized,
+    F: Fn(&[u8]) -> Vec<u8>,
+{
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by_key(|i| keys[*i]);
+    let mut records = vec![None; keys.len()];
+    let mut iter = db.raw_iterator();
+    for i in order {
+        iter.seek_for_prev(&versioned(keys[i]));
+        if iter.key().map_or(false, |key| key.starts_with(keys[i])) {
+            records[i] = iter.value().map(|value| value.to_vec());
+        }
+    }
+    records
+}
+
+/// Writes the records of an element index to the store of a backend, at the sequence of a versioned store.
+pub trait RecordStoreMut: RecordStore {
+    fn put_record(&mut self, key: &[u8], record: &[u8]) -> Result<(), AccumulatorError>;
+
+    /// Removes the record, a versioned store writes an empty version so that the previous ones stay readable.
+    fn delete_record(&mut self, key: &[u8]) -> Result<(), AccumulatorError>;
+}
+
+/// The number of reads and writes of an element index since it was created.
+#[derive(Debug, Default)]
+pub struct ElementIndexStats {
+    reads: AtomicU64,
+    misses: AtomicU64,
+    writes: AtomicU64,
+    written_bytes: AtomicU64,
+}
+
+impl ElementIndexStats {
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// The reads of elements which have no record.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    pub fn written_bytes(&self) -> u64 {
+        self.written_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_read(&self, found: bool) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if !found {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_write(&self, bytes: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.written_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for ElementIndexStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "element index {} reads, {} misses, {} writes, {} bytes written",
+            self.reads(),
+            self.misses(),
+            self.writes(),
+            self.written_bytes()
+        )
+    }
+}
+
+/// The index from the out_points to the records of their elements, shared by the backends which keep one next to their
+/// tree: the key derivation and the record encoding of a backend, and the counters of its reads and writes.
+#[derive(Debug)]
+pub struct ElementIndex {
+    prefix: &'static [u8],
+    keying: ElementKeying,
+    encoding: RecordEncoding,
+    stats: ElementIndexStats,
+}
+
+impl ElementIndex {
+    pub fn new(prefix: &'static [u8], keying: ElementKeying, encoding: RecordEncoding) -> Self {
+        ElementIndex {
+            prefix,
+            keying,
+            encoding,
+            stats: ElementIndexStats::default(),
+        }
+    }
+
+    pub fn prefix(&self) -> &'static [u8] {
+        self.prefix
+    }
+
+    pub fn encoding(&self) -> RecordEncoding {
+        self.encoding
+    }
+
+    pub fn stats(&self) -> &ElementIndexStats {
+        &self.stats
+    }
+
+    /// The key of the record of an out_point.
+    pub fn key(&self, out_point: &OutPoint) -> Vec<u8> {
+        match self.keying {
+            ElementKeying::Plain => element_key(self.prefix, out_point),
+            ElementKeying::Hashed => hashed_element_key(self.prefix, out_point),
+        }
+    }
+
+    /// Decodes a record read by the backend, `None` if there is none or it's empty, i.e. deleted.
+    pub fn decode(
+        &self,
+        key: &[u8],
+        record: Option<&[u8]>,
+    ) -> Result<Option<ElementRecord>, AccumulatorError> {
+        let record = record.filter(|record| !record.is_empty());
+        self.stats.record_read(record.is_some());
+        record
+            .map(|record| {
+                self.encoding.decode(record).ok_or_else(|| {
+                    AccumulatorError::InternalError(format!(
+                        "invalid element record of {:?}: {:?}",
+                        key, record
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Encodes a record to be written by the backend.
+    pub fn encode(&self, record: &ElementRecord) -> Vec<u8> {
+        let encoded = self.encoding.encode(record);
+        self.stats.record_write(encoded.len());
+        encoded
+    }
+
+    pub fn get<S: RecordStore + ?Sized>(
+        &self,
+        store: &S,
+        key: &[u8],
+    ) -> Result<Option<ElementRecord>, AccumulatorError> {
+        self.decode(key, store.get_record(key).as_deref())
+    }
+
+    /// Reads the records of several keys, in order, with a single `RecordStore::get_records`.
+    pub fn multi_get<S: RecordStore + ?Sized>(
+        &self,
+        store: &S,
+        keys: &[Vec<u8>],
+    ) -> Result<Vec<Option<ElementRecord>>, AccumulatorError> {
+        keys.iter()
+            .zip(store.get_records(keys))
+            .map(|(key, record)| self.decode(key, record.as_deref()))
+            .collect()
+    }
+
+    pub fn put<S: RecordStoreMut + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &[u8],
+        record: &ElementRecord,
+    ) -> Result<(), AccumulatorError> {
+        store.put_record(key, &self.encode(record))
+    }
+
+    pub fn delete<S: RecordStoreMut + ?Sized>(
+        &self,
+        store: &mut S,
+        key: &[u8],
+    ) -> Result<(), AccumulatorError> {
+        self.stats.record_write(0);
+        store.delete_record(key)
+    }
+}
+
+/// Accumulators which keep an element index, see `ElementIndex`.
+pub trait IndexedElements {
+    fn element_index_stats(&self) -> Option<&ElementIndexStats> {
+        None
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use crate::{AccumulatorError, CellStatus, OutPoint};
+
+use super::{
+    ElementIndex, ElementKeying, ElementRecord, RecordEncoding, RecordStore, RecordStoreMut,
+};
+
+// an unversioned store, a deleted record is removed
+#[derive(Default)]
+struct MemoryStore(HashMap<Vec<u8>, Vec<u8>>);
+
+impl RecordStore for MemoryStore {
+    fn get_record(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+}
+
+impl RecordStoreMut for MemoryStore {
+    fn put_record(&mut self, key: &[u8], record: &[u8]) -> Result<(), AccumulatorError> {
+        self.0.insert(key.to_vec(), record.to_vec());
+        Ok(())
+    }
+
+    fn delete_record(&mut self, key: &[u8]) -> Result<(), AccumulatorError> {
+        self.0.remove(key);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_record_encoding() {
+    let live = ElementRecord {
+        pos: None,
+        status: CellStatus::new_live(3),
+    };
+    let dead = ElementRecord {
+        pos: None,
+        status: CellStatus::new_dead(3, 7),
+    };
+    let encoded = RecordEncoding::Sequences.encode(&live);
+    assert_eq!(3u64.to_le_bytes().to_vec(), encoded);
+    assert_eq!(Some(live), RecordEncoding::Sequences.decode(&encoded));
+    let encoded = RecordEncoding::Sequences.encode(&dead);
+    assert_eq!(16, encoded.len());
+    assert_eq!(Some(dead), RecordEncoding::Sequences.decode(&encoded));
+    assert_eq!(None, RecordEncoding::Sequences.decode(&[0u8; 12]));
+    assert_eq!(None, RecordEncoding::Sequences.decode(&[]));
+
+    let positioned = ElementRecord {
+        pos: Some(42),
+        status: CellStatus::new_dead(3, 7),
+    };
+    let encoded = RecordEncoding::Positioned.encode(&positioned);
+    assert_eq!(24, encoded.len());
+    assert_eq!(
+        Some(positioned.clone()),
+        RecordEncoding::Positioned.decode(&encoded)
+    );
+    // a trailer is left to the backend
+    let with_trailer = [encoded.as_slice(), &[0xffu8; 8]].concat();
+    assert_eq!(
+        Some(positioned),
+        RecordEncoding::Positioned.decode(&with_trailer)
+    );
+    assert_eq!(None, RecordEncoding::Positioned.decode(&encoded[0..16]));
+}
+
+#[test]
+fn test_status_at() {
+    let record = ElementRecord {
+        pos: None,
+        status: CellStatus::new_dead(3, 7),
+    };
+    assert_eq!(3, record.created_by());
+    assert_eq!(Some(7), record.consumed_by());
+    assert_eq!(None, record.status_at(2));
+    assert_eq!(Some(CellStatus::new_live(3)), record.status_at(3));
+    assert_eq!(Some(CellStatus::new_live(3)), record.status_at(6));
+    assert_eq!(Some(CellStatus::new_dead(3, 7)), record.status_at(7));
+
+    let record = ElementRecord {
+        pos: None,
+        status: CellStatus::new_live(3),
+    };
+    assert_eq!(None, record.consumed_by());
+    assert_eq!(Some(CellStatus::new_live(3)), record.status_at(100));
+}
+
+#[test]
+fn test_element_index() {
+    let index = ElementIndex::new(b"e", ElementKeying::Plain, RecordEncoding::Sequences);
+    let mut store = MemoryStore::default();
+    let out_point_1 = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    };
+    let out_point_2 = OutPoint {
+        tx_hash: [2u8; 32],
+        index: 1,
+    };
+    let key_1 = index.key(&out_point_1);
+    let key_2 = index.key(&out_point_2);
+    assert!(key_1.starts_with(b"e"));
+    assert_ne!(
+        key_1,
+        ElementIndex::new(b"e", ElementKeying::Hashed, RecordEncoding::Sequences).key(&out_point_1)
+    );
+
+    let record = ElementRecord {
+        pos: None,
+        status: CellStatus::new_live(5),
+    };
+    index.put(&mut store, &key_1, &record).unwrap();
+    assert_eq!(Some(record.clone()), index.get(&store, &key_1).unwrap());
+    assert_eq!(
+        vec![Some(record), None],
+        index
+            .multi_get(&store, &[key_1.clone(), key_2.clone()])
+            .unwrap()
+    );
+    index.delete(&mut store, &key_1).unwrap();
+    assert_eq!(None, index.get(&store, &key_1).unwrap());
+
+    // an empty record is a deleted one, an invalid one is an error
+    store.put_record(&key_2, &[]).unwrap();
+    assert_eq!(None, index.get(&store, &key_2).unwrap());
+    store.put_record(&key_2, &[0u8; 5]).unwrap();
+    assert!(matches!(
+        index.get(&store, &key_2),
+        Err(AccumulatorError::InternalError(_))
+    ));
+
+    let stats = index.stats();
+    assert_eq!(6, stats.reads());
+    assert_eq!(3, stats.misses());
+    assert_eq!(2, stats.writes());
+    assert_eq!(8, stats.written_bytes());
+    assert_eq!(
+        "element index 6 reads, 3 misses, 2 writes, 8 bytes written",
+        stats.to_string()
+    );
+}
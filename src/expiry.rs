@@ -0,0 +1,173 @@
+// Expires the superseded versions of the versioned keys of a backend during the RocksDB compactions, see
+// `DbOptions::version_expiry`.
+
+use crate::{mmr, sequence_width::SequenceWidth, smt, smt_live};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The most keys a `VersionExpiry` remembers between two compactions, about 64 MiB of keys. The keys found when it's
+/// full are not remembered, their versions are left to a later compaction.
+pub const MAX_PENDING_KEYS: usize = 1 << 20;
+
+/// The backend whose versioned keys a `VersionExpiry` expires, told apart from the other keys by the
+/// `is_versioned_key` of the backend, whatever the sequence width of the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionedKeys {
+    Smt,
+    SmtLive,
+    Mmr,
+}
+
+impl VersionedKeys {
+    pub fn from_backend(name: &str) -> Option<Self> {
+        match name {
+            "smt" => Some(VersionedKeys::Smt),
+            "smt_live" => Some(VersionedKeys::SmtLive),
+            "mmr" => Some(VersionedKeys::Mmr),
+            _ => None,
+        }
+    }
+
+    // the key and the version of a versioned key of the backend, `None` for the other keys
+    fn split(self, key: &[u8]) -> Option<(&[u8], u64)> {
+        let is_versioned = match self {
+            VersionedKeys::Smt => smt::is_versioned_key,
+            VersionedKeys::SmtLive => smt_live::is_versioned_key,
+            VersionedKeys::Mmr => mmr::is_versioned_key,
+        };
+        [SequenceWidth::U64, SequenceWidth::U32]
+            .into_iter()
+            .find(|width| is_versioned(key, *width))
+            .map(|width| width.split(key))
+    }
+}
+
+/// Expires the superseded versions of the versioned keys of a backend during the RocksDB compactions, instead of the
+/// explicit passes of `mmr::gc::gc_element_index`, keeping the history of the last `retention` sequences. A version is
+/// superseded once a newer version of its key is at or before the horizon, the readers at the horizon or later never
+/// see it.
+///
+/// A compaction visits the versions of a key oldest first, so a version can't be dropped by the compaction which sees
+/// the version superseding it. The filter remembers the keys with a version at or before the horizon which follows an
+/// older version, and a later compaction drops the older versions. It remembers at most `MAX_PENDING_KEYS` keys, and
+/// forgets them when the process exits, their versions are then kept until a compaction sees them again. Unlike
+/// `gc_element_index`, the last version of a key is always kept, so the consumed cells stay known.
+#[derive(Clone)]
+pub struct VersionExpiry {
+    keys: VersionedKeys,
+    retention: u64,
+    // `u64::MAX` until the first `advance`, nothing expires before it.
+    horizon: Arc<AtomicU64>,
+    state: Arc<Mutex<ExpiryState>>,
+    expired: Arc<AtomicU64>,
+    expired_bytes: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct ExpiryState {
+    // The keys, without their version, with a version at or before the horizon superseding an older one, and that
+    // version.
+    superseded: HashMap<Vec<u8>, u64>,
+    // The key, without its version, of the last version kept. The compactions may run concurrently, a wrong match only
+    // delays an expiry, since a version is dropped only if the version superseding it was seen.
+    previous: Vec<u8>,
+}
+
+impl VersionExpiry {
+    pub fn new(keys: VersionedKeys, retention: u64) -> Self {
+        VersionExpiry {
+            keys,
+            retention,
+            horizon: Arc::new(AtomicU64::new(u64::MAX)),
+            state: Arc::default(),
+            expired: Arc::default(),
+            expired_bytes: Arc::default(),
+        }
+    }
+
+    /// Moves the horizon to `sequence - retention` after `sequence` is committed, it never moves back.
+    pub fn advance(&self, sequence: u64) {
+        if let Some(horizon) = sequence.checked_sub(self.retention) {
+            let previous = self.horizon.load(Ordering::Relaxed);
+            if previous == u64::MAX || previous < horizon {
+                self.horizon.store(horizon, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Whether the compaction drops a stored key, only the superseded versions of the versioned keys expire.
+    pub fn expires(&self, key: &[u8], value: &[u8]) -> bool {
+        let (unversioned, version) = match self.keys.split(key) {
+            Some(split) => split,
+            None => return false,
+        };
+        let horizon = self.horizon.load(Ordering::Relaxed);
+        let mut state = self.state.lock().expect("poisoned lock");
+        if let Some(superseding) = state.superseded.get(unversioned).copied() {
+            if version < superseding {
+                self.expired.fetch_add(1, Ordering::Relaxed);
+                self.expired_bytes
+                    .fetch_add((key.len() + value.len()) as u64, Ordering::Relaxed);
+                return true;
+            }
+            // the versions before the superseding one are all dropped
+            state.superseded.remove(unversioned);
+        }
+        if horizon != u64::MAX
+            && version <= horizon
+            && state.previous == unversioned
+            && state.superseded.len() < MAX_PENDING_KEYS
+        {
+            state.superseded.insert(unversioned.to_vec(), version);
+        }
+        state.previous.clear();
+        state.previous.extend_from_slice(unversioned);
+        false
+    }
+
+    /// The number of versions expired so far.
+    pub fn expired(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    /// The key and value bytes of the versions expired so far, which the database would still hold without the expiry.
+    pub fn expired_bytes(&self) -> u64 {
+        self.expired_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The number of keys whose older versions are waiting for a compaction.
+    pub fn pending(&self) -> usize {
+        self.state.lock().expect("poisoned lock").superseded.len()
+    }
+}
+
+impl fmt::Debug for VersionExpiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionExpiry")
+            .field("keys", &self.keys)
+            .field("retention", &self.retention)
+            .finish()
+    }
+}
+
+impl fmt::Display for VersionExpiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.horizon.load(Ordering::Relaxed) {
+            u64::MAX => write!(f, "version expiry not started"),
+            horizon => write!(
+                f,
+                "version expiry horizon {}, {} expired versions of {} bytes, {} pending keys",
+                horizon,
+                self.expired(),
+                self.expired_bytes(),
+                self.pending()
+            ),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// C ABI of the proof verifiers, over the serialized commitment (`AccumulatorCommitment::to_bytes`, or
+// `PositionedCommitment::to_bytes` for the positioned MMR) and wallet proof (`WalletProof::to_bytes`, or
+// `PositionedWalletProof::to_bytes`) formats of each backend. Every function returns 1 if the proof is valid, 0 if it's not,
+// and -1 if the commitment or the proof can't be decoded.
+
+use crate::{mmr, smt, smt_live, AccumulatorError};
+use std::slice;
+
+/// # Safety
+///
+/// `commitment` and `proof` must be valid for reads of `commitment_len` and `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dca_verify_smt_proof(
+    commitment: *const u8,
+    commitment_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> i32 {
+    verify(
+        commitment,
+        commitment_len,
+        proof,
+        proof_len,
+        |commitment, proof| {
+            let commitment = smt::accumulator::AccumulatorCommitment::from_bytes(commitment)?;
+            smt::accumulator::WalletProof::from_bytes(proof)?.verify(&commitment)
+        },
+    )
+}
+
+/// # Safety
+///
+/// `commitment` and `proof` must be valid for reads of `commitment_len` and `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dca_verify_smt_live_proof(
+    commitment: *const u8,
+    commitment_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> i32 {
+    verify(
+        commitment,
+        commitment_len,
+        proof,
+        proof_len,
+        |commitment, proof| {
+            let commitment = smt_live::accumulator::AccumulatorCommitment::from_bytes(commitment)?;
+            smt_live::accumulator::WalletProof::from_bytes(proof)?.verify(&commitment)
+        },
+    )
+}
+
+/// # Safety
+///
+/// `commitment` and `proof` must be valid for reads of `commitment_len` and `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dca_verify_mmr_proof(
+    commitment: *const u8,
+    commitment_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> i32 {
+    verify(
+        commitment,
+        commitment_len,
+        proof,
+        proof_len,
+        |commitment, proof| {
+            let commitment = mmr::accumulator::AccumulatorCommitment::from_bytes(commitment)?;
+            mmr::accumulator::WalletProof::from_bytes(proof)?.verify(&commitment)
+        },
+    )
+}
+
+/// Verifies a `PositionedWalletProof` against a `PositionedCommitment`, the positions claimed by the MMR proof are
+/// checked against the position root of the commitment.
+///
+/// # Safety
+///
+/// `commitment` and `proof` must be valid for reads of `commitment_len` and `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dca_verify_positioned_mmr_proof(
+    commitment: *const u8,
+    commitment_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> i32 {
+    verify(
+        commitment,
+        commitment_len,
+        proof,
+        proof_len,
+        |commitment, proof| {
+            let commitment = mmr::position_index::PositionedCommitment::from_bytes(commitment)?;
+            mmr::position_index::PositionedWalletProof::from_bytes(proof)?.verify(&commitment)
+        },
+    )
+}
+
+unsafe fn verify<F>(
+    commitment: *const u8,
+    commitment_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+    verify: F,
+) -> i32
+where
+    F: FnOnce(&[u8], &[u8]) -> Result<bool, AccumulatorError>,
+{
+    if commitment.is_null() || proof.is_null() {
+        return -1;
+    }
+    let commitment = slice::from_raw_parts(commitment, commitment_len);
+    let proof = slice::from_raw_parts(proof, proof_len);
+    match verify(commitment, proof) {
+        Ok(true) => 1,
+        Ok(false) => 0,
+        // a proof which decodes but fails to verify, e.g. a corrupted merkle path, is not valid rather than malformed
+        Err(AccumulatorError::InvalidCommitment) | Err(AccumulatorError::InvalidProof) => -1,
+        Err(_) => 0,
+    }
+}
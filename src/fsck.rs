@@ -0,0 +1,78 @@
+use crate::{sequence_width::SequenceWidth, storage::traits::ReadStore};
+use rocksdb::IteratorMode;
+use std::fmt;
+
+/// The result of a consistency check of a benchmark database, at its last committed sequence.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    // The last committed sequence, `None` if nothing was committed yet.
+    pub sequence: Option<u64>,
+    // Number of live leaves (SMT) or leaf positions (MMR) checked.
+    pub leaves: u64,
+    // Number of element index records checked.
+    pub elements: u64,
+    // Number of key versions written after the last committed sequence, e.g. by a crashed run.
+    pub orphaned_versions: u64,
+    pub errors: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sequence {
+            Some(sequence) => writeln!(f, "sequence: {}", sequence)?,
+            None => writeln!(f, "sequence: none")?,
+        }
+        writeln!(f, "leaves: {}", self.leaves)?;
+        writeln!(f, "elements: {}", self.elements)?;
+        writeln!(f, "orphaned versions: {}", self.orphaned_versions)?;
+        writeln!(f, "errors: {}", self.errors.len())?;
+        for error in &self.errors {
+            writeln!(f, "  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scans the whole database and collects the latest version at or before `sequence` of every versioned key accepted by `collect`,
+/// the returned keys are stripped of their version suffix of `width`. Versions of any key accepted by `is_versioned` written after `sequence` are counted as orphaned.
+pub(crate) fn latest_versions<DB, F, C>(
+    db: &DB,
+    sequence: u64,
+    width: SequenceWidth,
+    is_versioned: F,
+    collect: C,
+    report: &mut FsckReport,
+) -> Vec<(Vec<u8>, Box<[u8]>)>
+where
+    DB: ReadStore + ?Sized,
+    F: Fn(&[u8]) -> bool,
+    C: Fn(&[u8]) -> bool,
+{
+    let mut ret: Vec<(Vec<u8>, Box<[u8]>)> = Vec::new();
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        if !is_versioned(&key) {
+            continue;
+        }
+        let (prefix, version) = width.split(&key);
+        if version > sequence {
+            report.orphaned_versions += 1;
+            continue;
+        }
+        if !collect(&key) {
+            continue;
+        }
+        let key = prefix;
+        // versions are big endian encoded, so the latest one comes last
+        match ret.last_mut() {
+            Some((last_key, last_value)) if last_key.as_slice() == key => *last_value = value,
+            _ => ret.push((key.to_vec(), value)),
+        }
+    }
+    ret
+}
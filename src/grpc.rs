@@ -0,0 +1,237 @@
+// gRPC service answering status, proof and commitment queries over a benchmark database, see `proto/dca.proto`. Every
+// query is answered by a historical accumulator opened at a committed sequence, so it sees a consistent state while
+// the database is written. The RocksDB reads of a query block, they run on the blocking thread pool of the runtime.
+
+use crate::{
+    builder::{AnyAccumulator, Builder},
+    witness::{wallet_proof, ChunkedProver},
+    AccumulatorError, OutPoint,
+};
+use rocksdb::{WriteOptions, DB};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio::sync::mpsc;
+use tonic::{
+    codegen::tokio_stream::{wrappers::ReceiverStream, Stream},
+    transport::Server,
+    Request, Response, Status,
+};
+
+pub mod proto {
+    tonic::include_proto!("dca");
+}
+
+use proto::{
+    accumulator_server::{Accumulator, AccumulatorServer},
+    ChunkedProofRequest, CommitmentReply, CommitmentRequest, ProofChunkReply, ProofReply,
+    ProofRequest, StatusReply, StatusRequest,
+};
+
+#[derive(Clone)]
+pub struct AccumulatorService {
+    backend: Builder,
+    db: Arc<DB>,
+}
+
+impl AccumulatorService {
+    pub fn new(backend: Builder, db: Arc<DB>) -> Self {
+        AccumulatorService { backend, db }
+    }
+
+    pub fn into_server(self) -> AccumulatorServer<Self> {
+        AccumulatorServer::new(self)
+    }
+
+    /// The requested sequence if it's committed, or the latest committed one.
+    fn sequence(&self, requested: Option<u64>) -> Result<u64, Status> {
+        let committed = self
+            .backend
+            .committed_sequences::<_, WriteOptions>(Arc::clone(&self.db))
+            .map_err(status)?;
+        match requested {
+            Some(sequence) if committed.contains(&sequence) => Ok(sequence),
+            Some(sequence) => Err(Status::out_of_range(format!(
+                "sequence {} is not committed",
+                sequence
+            ))),
+            None => committed
+                .end
+                .checked_sub(1)
+                .ok_or_else(|| Status::failed_precondition("no committed sequence")),
+        }
+    }
+
+    fn accumulator(&self, sequence: u64) -> Result<AnyAccumulator<Arc<DB>, WriteOptions>, Status> {
+        self.backend
+            .clone()
+            .strict(false)
+            .sequence(sequence)
+            .build(Arc::clone(&self.db))
+            .map_err(status)
+    }
+}
+
+#[tonic::async_trait]
+impl Accumulator for AccumulatorService {
+    async fn get_status(
+        &self,
+        request: Request<StatusRequest>,
+    ) -> Result<Response<StatusReply>, Status> {
+        let request = request.into_inner();
+        let out_point = out_point(
+            request
+                .out_point
+                .ok_or_else(|| Status::invalid_argument("missing out_point"))?,
+        )?;
+        let service = self.clone();
+        blocking(move || {
+            let sequence = service.sequence(request.sequence)?;
+            let status = service
+                .accumulator(sequence)?
+                .cell_status(&out_point)
+                .map_err(status)?;
+            Ok(Response::new(StatusReply {
+                sequence,
+                live: status.as_ref().map_or(false, |status| status.is_live()),
+                status: status.map_or(Vec::new(), |status| status.block_numbers.to_vec()),
+            }))
+        })
+        .await
+    }
+
+    async fn get_proof(
+        &self,
+        request: Request<ProofRequest>,
+    ) -> Result<Response<ProofReply>, Status> {
+        let request = request.into_inner();
+        let out_points = request
+            .out_points
+            .into_iter()
+            .map(out_point)
+            .collect::<Result<Vec<_>, _>>()?;
+        if out_points.is_empty() {
+            return Err(Status::invalid_argument("no out_points"));
+        }
+        let service = self.clone();
+        blocking(move || {
+            let sequence = service.sequence(request.sequence)?;
+            let proof =
+                wallet_proof::<_, WriteOptions>(&service.backend, service.db, out_points, sequence)
+                    .map_err(status)?;
+            Ok(Response::new(ProofReply { sequence, proof }))
+        })
+        .await
+    }
+
+    type GetProofChunksStream =
+        Pin<Box<dyn Stream<Item = Result<ProofChunkReply, Status>> + Send + 'static>>;
+
+    async fn get_proof_chunks(
+        &self,
+        request: Request<ChunkedProofRequest>,
+    ) -> Result<Response<Self::GetProofChunksStream>, Status> {
+        let request = request.into_inner();
+        let out_points = request
+            .out_points
+            .into_iter()
+            .map(out_point)
+            .collect::<Result<Vec<_>, _>>()?;
+        if out_points.is_empty() || request.chunk_size == 0 {
+            return Err(Status::invalid_argument(
+                "no out_points or a chunk size of 0",
+            ));
+        }
+        let service = self.clone();
+        let sequence = blocking(move || service.sequence(request.sequence)).await?;
+        let prover = ChunkedProver::<_, WriteOptions>::new(
+            self.backend.clone(),
+            Arc::clone(&self.db),
+            out_points,
+            sequence,
+            request.chunk_size as usize,
+        );
+        let header = prover.header();
+        // the chunks are proved off the async runtime as the client reads the stream, the bounded channel keeps the
+        // prover at most one chunk ahead of the client
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::task::spawn_blocking(move || {
+            for chunk in prover {
+                let reply = chunk.map_err(status).map(|chunk| ProofChunkReply {
+                    sequence: header.sequence,
+                    chunks: header.chunks,
+                    cells: header.cells,
+                    index: chunk.index,
+                    proof: chunk.proof,
+                });
+                let failed = reply.is_err();
+                // the client dropped the stream
+                if sender.blocking_send(reply).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn get_commitment(
+        &self,
+        request: Request<CommitmentRequest>,
+    ) -> Result<Response<CommitmentReply>, Status> {
+        let requested = request.into_inner().sequence;
+        let service = self.clone();
+        blocking(move || {
+            let sequence = service.sequence(requested)?;
+            let commitment = service
+                .accumulator(sequence)?
+                .backend_commitment()
+                .map_err(status)?;
+            Ok(Response::new(CommitmentReply {
+                sequence,
+                digest: commitment.commitment_digest().to_vec(),
+                commitment: commitment.to_bytes(),
+            }))
+        })
+        .await
+    }
+}
+
+/// Serves the service on `addr` until the server fails.
+pub async fn serve(
+    service: AccumulatorService,
+    addr: SocketAddr,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(service.into_server())
+        .serve(addr)
+        .await
+}
+
+// runs a query on the blocking thread pool, so that its RocksDB reads don't stall the async runtime
+async fn blocking<T, F>(query: F) -> Result<T, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(query)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+}
+
+fn out_point(out_point: proto::OutPoint) -> Result<OutPoint, Status> {
+    Ok(OutPoint {
+        tx_hash: out_point
+            .tx_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| Status::invalid_argument("tx_hash must be 32 bytes"))?,
+        index: out_point.index,
+    })
+}
+
+fn status(e: AccumulatorError) -> Status {
+    match e {
+        AccumulatorError::ElementNotFound(i) => {
+            Status::not_found(format!("out_point {} is not live", i))
+        }
+        e => Status::internal(format!("{:?}", e)),
+    }
+}
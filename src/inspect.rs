@@ -0,0 +1,82 @@
+// The commands of the `inspect` prompt over a benchmark database.
+
+use crate::OutPoint;
+
+pub const INSPECT_HELP: &str = "commands:
+  sequences                          the committed sequences
+  root [sequence]                    the commitment at a sequence, the latest by default
+  status <tx-hash:index> [sequence]  the status of a cell at a sequence
+  proof <tx-hash:index> [sequence]   the wallet proof of a cell at a sequence, verified against its commitment
+  key <raw-key-hex>                  every stored version of a raw key without its version, e.g. a branch key
+  help
+  quit";
+
+/// A command of the prompt, the sequences are `None` for the latest committed sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Empty,
+    Quit,
+    Help,
+    Sequences,
+    Root(Option<u64>),
+    Status(OutPoint, Option<u64>),
+    Proof(OutPoint, Option<u64>),
+    Key(Vec<u8>),
+}
+
+/// Parses a line of the prompt, the error is printed to the user.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let sequence = |i: usize| -> Result<Option<u64>, String> {
+        words
+            .get(i)
+            .map(|word| {
+                word.parse()
+                    .map_err(|_| "sequence must be a number".to_string())
+            })
+            .transpose()
+    };
+    let out_point = || -> Result<OutPoint, String> {
+        words
+            .get(1)
+            .and_then(|word| parse_out_point(word))
+            .ok_or_else(|| "out_point must be <tx-hash-hex>:<index>".to_string())
+    };
+    Ok(match words.first().copied() {
+        None => Command::Empty,
+        Some("quit") | Some("exit") => Command::Quit,
+        Some("help") => Command::Help,
+        Some("sequences") => Command::Sequences,
+        Some("root") => Command::Root(sequence(1)?),
+        Some("status") => Command::Status(out_point()?, sequence(2)?),
+        Some("proof") => Command::Proof(out_point()?, sequence(2)?),
+        Some("key") | Some("branch") => Command::Key(
+            words
+                .get(1)
+                .and_then(|word| parse_hex(word))
+                .ok_or_else(|| "key must be hex".to_string())?,
+        ),
+        Some(command) => return Err(format!("unknown command {}, see help", command)),
+    })
+}
+
+/// An out_point given as `<tx hash hex>:<index>`.
+pub fn parse_out_point(s: &str) -> Option<OutPoint> {
+    let (tx_hash, index) = s.split_once(':')?;
+    Some(OutPoint {
+        tx_hash: parse_hex(tx_hash)?.try_into().ok()?,
+        index: index.parse().ok()?,
+    })
+}
+
+/// Hex bytes, with or without a `0x` prefix.
+pub fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,67 @@
+use crate::OutPoint;
+
+#[cfg(test)]
+mod tests;
+
+/// The key of an element indexed by its out_point, `prefix || tx_hash || index LE`.
+pub fn element_key(prefix: &[u8], out_point: &OutPoint) -> Vec<u8> {
+    [prefix, out_point.to_bytes().as_ref()].concat()
+}
+
+/// Decodes the out_point of an `element_key`, `None` if the prefix or the length doesn't match.
+pub fn parse_element_key(prefix: &[u8], key: &[u8]) -> Option<OutPoint> {
+    key.strip_prefix(prefix).and_then(OutPoint::from_bytes)
+}
+
+/// The key of an element indexed by its out_point hash, `prefix || out_point hash || index LE`. The index is kept after
+/// the hash so the key still tells the output apart within its tx, but the tx hash can't be recovered from it.
+pub fn hashed_element_key(prefix: &[u8], out_point: &OutPoint) -> Vec<u8> {
+    hashed_element_key_from_parts(prefix, &out_point.hash(), out_point.index)
+}
+
+/// A `hashed_element_key` from the out_point hash and index, e.g. as decoded by `parse_hashed_element_key`.
+pub fn hashed_element_key_from_parts(prefix: &[u8], hash: &[u8; 32], index: u32) -> Vec<u8> {
+    [prefix, hash.as_ref(), index.to_le_bytes().as_ref()].concat()
+}
+
+/// Decodes the out_point hash and index of a `hashed_element_key`, `None` if the prefix or the length doesn't match.
+pub fn parse_hashed_element_key(prefix: &[u8], key: &[u8]) -> Option<([u8; 32], u32)> {
+    let key = key.strip_prefix(prefix)?;
+    if key.len() != 36 {
+        return None;
+    }
+    let index = u32::from_le_bytes(key[32..].try_into().ok()?);
+    Some((key[..32].try_into().ok()?, index))
+}
+
+/// The key of a record addressed by a 32 bytes hash, `prefix || hash`, e.g. a shared branch node or a hot leaf.
+pub fn hash_key(prefix: &[u8], hash: &[u8]) -> Vec<u8> {
+    [prefix, hash].concat()
+}
+
+/// Decodes the hash of a `hash_key`, `None` if the prefix or the length doesn't match.
+pub fn parse_hash_key(prefix: &[u8], key: &[u8]) -> Option<[u8; 32]> {
+    key.strip_prefix(prefix)?.try_into().ok()
+}
+
+/// The key of a node by its position, `prefix || position LE`.
+pub fn position_key(prefix: &[u8], pos: u64) -> Vec<u8> {
+    [prefix, pos.to_le_bytes().as_ref()].concat()
+}
+
+/// Decodes the position of a `position_key`, `None` if the prefix or the length doesn't match.
+pub fn parse_position_key(prefix: &[u8], key: &[u8]) -> Option<u64> {
+    let pos = key.strip_prefix(prefix)?.try_into().ok()?;
+    Some(u64::from_le_bytes(pos))
+}
+
+/// The key of a record written once per sequence, `prefix || sequence BE`, so the records are sorted by sequence.
+pub fn sequence_key(prefix: &[u8], sequence: u64) -> Vec<u8> {
+    [prefix, sequence.to_be_bytes().as_ref()].concat()
+}
+
+/// Decodes the sequence of a `sequence_key`, `None` if the prefix or the length doesn't match.
+pub fn parse_sequence_key(prefix: &[u8], key: &[u8]) -> Option<u64> {
+    let sequence = key.strip_prefix(prefix)?.try_into().ok()?;
+    Some(u64::from_be_bytes(sequence))
+}
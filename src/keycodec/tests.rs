@@ -0,0 +1,97 @@
+use super::{
+    element_key, hash_key, hashed_element_key, hashed_element_key_from_parts, parse_element_key,
+    parse_hash_key, parse_hashed_element_key, parse_position_key, parse_sequence_key, position_key,
+    sequence_key,
+};
+use crate::OutPoint;
+
+fn out_points() -> Vec<OutPoint> {
+    vec![
+        OutPoint {
+            tx_hash: [0u8; 32],
+            index: 0,
+        },
+        OutPoint {
+            tx_hash: [0xffu8; 32],
+            index: u32::MAX,
+        },
+        OutPoint {
+            tx_hash: [7u8; 32],
+            index: 0x0102_0304,
+        },
+    ]
+}
+
+#[test]
+fn test_out_point_roundtrip() {
+    for out_point in out_points() {
+        let bytes = out_point.to_bytes();
+        assert_eq!(Some(out_point), OutPoint::from_bytes(&bytes));
+        assert!(OutPoint::from_bytes(&bytes[1..]).is_none());
+    }
+    assert_eq!([4, 3, 2, 1], out_points()[2].to_bytes()[32..OutPoint::LEN]);
+    // ordered by tx hash, then by index
+    let mut sorted = out_points();
+    sorted.sort();
+    assert_eq!(
+        vec![0, 7, 0xff],
+        sorted.iter().map(|o| o.tx_hash[0]).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_element_key_roundtrip() {
+    for prefix in [&[][..], &[0u8][..], &[2u8, 9][..]] {
+        for out_point in out_points() {
+            let key = element_key(prefix, &out_point);
+            assert_eq!(prefix.len() + OutPoint::LEN, key.len());
+            let parsed = parse_element_key(prefix, &key).expect("element key");
+            assert_eq!(out_point, parsed);
+            assert!(parse_element_key(&[3u8], &key[prefix.len()..]).is_none());
+            assert!(parse_element_key(prefix, &key[..key.len() - 1]).is_none());
+
+            let key = hashed_element_key(prefix, &out_point);
+            assert_eq!(prefix, &key[..prefix.len()]);
+            assert_eq!(out_point.hash(), key[prefix.len()..prefix.len() + 32]);
+            assert_eq!(out_point.index.to_le_bytes(), key[prefix.len() + 32..]);
+            let (hash, index) = parse_hashed_element_key(prefix, &key).expect("hashed element key");
+            assert_eq!((out_point.hash(), out_point.index), (hash, index));
+            assert_eq!(key, hashed_element_key_from_parts(prefix, &hash, index));
+            assert!(parse_hashed_element_key(&[3u8], &key[prefix.len()..]).is_none());
+            assert!(parse_hashed_element_key(prefix, &key[..key.len() - 1]).is_none());
+        }
+    }
+}
+
+#[test]
+fn test_position_and_sequence_key_roundtrip() {
+    for value in [0, 1, 255, 256, u32::MAX as u64, u64::MAX] {
+        let key = position_key(&[0u8], value);
+        assert_eq!(Some(value), parse_position_key(&[0u8], &key));
+        assert_eq!(None, parse_position_key(&[1u8], &key));
+        assert_eq!(None, parse_position_key(&[0u8], &key[..8]));
+
+        let key = sequence_key(&[1u8], value);
+        assert_eq!(Some(value), parse_sequence_key(&[1u8], &key));
+        assert_eq!(None, parse_sequence_key(&[0u8], &key));
+        assert_eq!(None, parse_sequence_key(&[1u8], &key[..8]));
+    }
+    // sequence keys sort by sequence, position keys don't need to
+    assert!(sequence_key(&[1u8], 255) < sequence_key(&[1u8], 256));
+}
+
+#[test]
+fn test_hash_key_roundtrip() {
+    for hash in [[0u8; 32], [0xffu8; 32], out_points()[2].hash()] {
+        let key = hash_key(b"HOT", &hash);
+        assert_eq!(3 + 32, key.len());
+        assert_eq!(Some(hash), parse_hash_key(b"HOT", &key));
+        assert_eq!(None, parse_hash_key(b"NODE", &key));
+        assert_eq!(None, parse_hash_key(b"HOT", &key[..key.len() - 1]));
+        // a versioned key of the same prefix is longer
+        assert_eq!(
+            None,
+            parse_hash_key(b"HOT", &[key.as_slice(), &[0u8; 8]].concat())
+        );
+    }
+}
@@ -0,0 +1,24 @@
+// Canonical dump of the stored keys and values of a database, to check that a refactored store keeps the on-disk layout
+// which existing databases were written with.
+
+use crate::{new_blake2b, storage::traits::ReadStore};
+use rocksdb::IteratorMode;
+use std::fmt::Write;
+
+/// Dumps every key of the database in key order, one line per key: `<key hex> <value length> <value blake2b hex>`. The
+/// values are hashed so that the dump of a small database stays small enough to be checked in.
+pub fn dump_layout<DB: ReadStore>(db: &DB) -> String {
+    let mut dump = String::new();
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        let mut hash = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&value);
+        hasher.finalize(&mut hash);
+        writeln!(dump, "{} {} {}", hex(&key), value.len(), hex(&hash)).expect("write to a string");
+    }
+    dump
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
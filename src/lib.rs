@@ -1,8 +1,52 @@
 use blake2b_rs::{Blake2b, Blake2bBuilder};
+use std::{collections::HashSet, fmt};
+use subtle::ConstantTimeEq;
 
+pub mod audit_log;
+pub mod batch;
+pub mod builder;
+pub mod cell_counts;
+pub mod chain;
+pub mod db_options;
+pub mod element_index;
+pub mod expiry;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod fsck;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod inspect;
+pub mod keycodec;
+pub mod layout;
+pub mod live_cache;
+#[cfg(feature = "serde")]
+pub mod matrix;
+pub mod metrics;
 pub mod mmr;
+pub mod oplog;
+pub mod overlay;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod prometheus;
+pub mod proof_cache;
+pub mod report;
+#[cfg(feature = "serde")]
+pub mod results;
+pub mod retry;
+pub mod sequence_width;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod sink;
 pub mod smt;
 pub mod smt_live;
+pub mod storage;
+pub mod sweep;
+pub mod sync;
+pub mod testing;
+#[cfg(test)]
+mod tests;
+pub mod witness;
+pub mod workload;
 
 pub trait AccumulatorWriter {
     type Item;
@@ -10,7 +54,86 @@ pub trait AccumulatorWriter {
 
     fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError>;
     fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError>;
+    /// Replaces the status of existing elements in place, e.g. for account-model workloads where a value changes
+    /// without being consumed.
+    fn update(&mut self, _elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        Err(AccumulatorError::InternalError(
+            "the backend can't update elements in place".to_string(),
+        ))
+    }
     fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError>;
+
+    /// Applies several blocks in one call, committing after each block, returns the commitment of every block.
+    /// Backends may override it to batch the writes of a block.
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError>
+    where
+        Self: AccumulatorWriter<Item = OutPoint>,
+    {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            self.add(block.added)?;
+            if !block.deleted.is_empty() {
+                self.delete(block.deleted)?;
+            }
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
+    /// Moves the next commit forward to `sequence`, must be called before the writes of the block. The sequences
+    /// skipped are never committed and read as the state of the previous commit.
+    fn skip_to(&mut self, _sequence: u64) -> Result<(), AccumulatorError> {
+        Err(AccumulatorError::InternalError(
+            "the backend can't skip sequences".to_string(),
+        ))
+    }
+
+    /// Applies blocks numbered by their block numbers, committing them at the sequences given by `source`, returns
+    /// the commitment of every commit.
+    fn apply_numbered_blocks(
+        &mut self,
+        blocks: Vec<(u64, BlockOps)>,
+        source: SequenceSource,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError>
+    where
+        Self: AccumulatorWriter<Item = OutPoint>,
+    {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for (block_number, block) in blocks {
+            match source {
+                SequenceSource::Block => commitments.extend(self.apply_blocks(vec![block])?),
+                SequenceSource::Operation => {
+                    self.add(block.added)?;
+                    commitments.push(self.commit()?);
+                    if !block.deleted.is_empty() {
+                        self.delete(block.deleted)?;
+                        commitments.push(self.commit()?);
+                    }
+                }
+                SequenceSource::BlockNumber => {
+                    self.skip_to(block_number)?;
+                    commitments.extend(self.apply_blocks(vec![block])?);
+                }
+            }
+        }
+        Ok(commitments)
+    }
+}
+
+/// Where the sequences of the commits come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SequenceSource {
+    /// One sequence per block, the block numbers are ignored.
+    #[default]
+    Block,
+    /// One sequence per operation, the adds and the deletes of a block are committed separately.
+    Operation,
+    /// The block number is the sequence, e.g. for an imported chain whose block numbers have gaps, the block numbers
+    /// must be increasing.
+    BlockNumber,
 }
 
 pub trait AccumulatorReader {
@@ -34,34 +157,114 @@ pub trait Proof {
         commitment: Self::Commitment,
         elements: Vec<Self::Item>,
     ) -> Result<bool, AccumulatorError>;
+
+    /// Verifies many proofs against one commitment, e.g. the proofs of the inputs of a block checked by a stateless
+    /// validator, `false` if any of them fails. The proofs of the underlying trees can't be merged once generated, so
+    /// this verifies them in turn and stops at the first invalid one. The smt backends override it to read the root of
+    /// the commitment once, but the siblings shared by the proofs are still hashed by every proof: they are only hashed
+    /// once for many elements by a single proof of all of them.
+    fn verify_batch(
+        commitment: Self::Commitment,
+        proofs: Vec<(Vec<Self::Item>, Self)>,
+    ) -> Result<bool, AccumulatorError>
+    where
+        Self: Sized,
+        Self::Commitment: Clone,
+    {
+        for (elements, proof) in proofs {
+            if !proof.verify(commitment.clone(), elements)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 }
 
 #[derive(Debug)]
 pub enum AccumulatorError {
+    AlreadyExists(usize),
     ElementNotFound(usize),
+    // The element at the index has been consumed already, with its created-by and consumed-by status.
+    AlreadyConsumed(usize, CellStatus),
     InternalError(String),
     InvalidCommitment,
     InvalidProof,
 }
 
-#[derive(Clone)]
+/// The out_points added and deleted in one block, i.e. one accumulator sequence.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockOps {
+    pub added: Vec<OutPoint>,
+    pub deleted: Vec<OutPoint>,
+}
+
+impl BlockOps {
+    /// Removes the cells added in the block from its consumed cells and returns them. They are never live at a
+    /// commitment, so a writer can write their final, dead status at once instead of an add followed by a delete.
+    pub fn take_transient(&mut self) -> HashSet<OutPoint> {
+        let mut transient = HashSet::new();
+        if self.deleted.is_empty() {
+            return transient;
+        }
+        let added: HashSet<&OutPoint> = self.added.iter().collect();
+        self.deleted.retain(|out_point| {
+            if added.contains(out_point) {
+                transient.insert(out_point.clone());
+                false
+            } else {
+                true
+            }
+        });
+        transient
+    }
+}
+
+/// A cell, by the hash of the transaction which creates it and its index in the outputs. Out_points are ordered by tx
+/// hash, then by index.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutPoint {
     pub tx_hash: [u8; 32],
     pub index: u32,
 }
 
 impl OutPoint {
+    /// The length of the canonical encoding.
+    pub const LEN: usize = 32 + 4;
+
+    /// The canonical encoding, `tx_hash || index LE`, used by the stored keys and records, the serialized proofs and
+    /// the hashes of out_points.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..32].copy_from_slice(&self.tx_hash);
+        bytes[32..].copy_from_slice(&self.index.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes the canonical encoding, `None` if the length doesn't match.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN {
+            return None;
+        }
+        Some(OutPoint {
+            tx_hash: bytes[..32].try_into().expect("checked length"),
+            index: u32::from_le_bytes(bytes[32..].try_into().expect("checked length")),
+        })
+    }
+
     pub fn hash(&self) -> [u8; 32] {
         let mut buf = [0u8; 32];
         let mut hasher = new_blake2b();
-        hasher.update(self.tx_hash.as_ref());
-        hasher.update(&self.index.to_le_bytes());
+        hasher.update(&self.to_bytes());
         hasher.finalize(&mut buf);
         buf
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The block numbers a cell was created and consumed by, its `Debug` and `Display` show them as integers.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CellStatus {
     pub block_numbers: [u8; 16],
 }
@@ -91,11 +294,109 @@ impl CellStatus {
         let (_, consumed_by) = self.block_numbers.split_at_mut(8);
         consumed_by.copy_from_slice(&consumed_by_block_number.to_le_bytes());
     }
+
+    pub fn created_by(&self) -> u64 {
+        u64::from_le_bytes(self.block_numbers[0..8].try_into().expect("checked length"))
+    }
+
+    /// The block number the cell was consumed by, `None` if it's live.
+    pub fn consumed_by(&self) -> Option<u64> {
+        if self.is_live() {
+            return None;
+        }
+        Some(u64::from_le_bytes(
+            self.block_numbers[8..16]
+                .try_into()
+                .expect("checked length"),
+        ))
+    }
+}
+
+impl fmt::Debug for CellStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CellStatus")
+            .field("created_by", &self.created_by())
+            .field("consumed_by", &self.consumed_by())
+            .finish()
+    }
+}
+
+impl fmt::Display for CellStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.consumed_by() {
+            Some(consumed_by) => write!(
+                f,
+                "created by {}, consumed by {}",
+                self.created_by(),
+                consumed_by
+            ),
+            None => write!(f, "created by {}, live", self.created_by()),
+        }
+    }
+}
+
+/// Formats the first 8 bytes of a hash in hex, followed by `..` if it's longer, to tell hashes apart in logs and test
+/// failures. Two values are equal if all their bytes are, so asserting on them compares the whole hashes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ShortHex<'a>(pub &'a [u8]);
+
+impl fmt::Display for ShortHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter().take(8) {
+            write!(f, "{:02x}", byte)?;
+        }
+        if self.0.len() > 8 {
+            write!(f, "..")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ShortHex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// the `Debug` of the commitment of a backend, with its root and digest as short hex
+pub(crate) fn debug_commitment(
+    f: &mut fmt::Formatter<'_>,
+    sequence: u64,
+    root: &[u8],
+    digest: &[u8],
+) -> fmt::Result {
+    f.debug_struct("AccumulatorCommitment")
+        .field("sequence", &sequence)
+        .field("root", &ShortHex(root))
+        .field("digest", &ShortHex(digest))
+        .finish()
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockNumber([u8; 8]);
 
 pub fn new_blake2b() -> Blake2b {
     Blake2bBuilder::new(32).build()
 }
+
+/// Compares two roots or digests in constant time for inputs of equal length, so the time a verifier takes to reject a
+/// proof doesn't depend on the position of the first differing byte. Every `Proof::verify` compares its root with it.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// The canonical digest of a commitment, see `commitment_digest`.
+pub type CommitmentDigest = [u8; 32];
+
+/// The canonical 32 bytes digest of a commitment: blake2b(root || sequence as u64 LE || backend tag),
+/// it has the size of a CKB header hash field, and the tag keeps equal roots of different backends apart.
+pub fn commitment_digest(tag: &[u8], root: &[u8], sequence: u64) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(root);
+    hasher.update(&sequence.to_le_bytes());
+    hasher.update(tag);
+    hasher.finalize(&mut digest);
+    digest
+}
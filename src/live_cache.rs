@@ -0,0 +1,87 @@
+use std::{collections::HashMap, fmt, mem::size_of};
+
+use sparse_merkle_tree::H256;
+
+use crate::CellStatus;
+
+/// The statuses of the live cells written by the accumulator, kept in memory so that the delete path of a cell doesn't
+/// read its leaf from the tree. It only knows the cells written since it was created, the other cells are read from the
+/// tree on a miss, so it never has to be loaded from the database. It must be dropped with the writes of a rolled back
+/// transaction.
+#[derive(Default)]
+pub struct LiveCache {
+    cells: HashMap<H256, CellStatus>,
+    hits: u64,
+    misses: u64,
+}
+
+impl LiveCache {
+    /// Returns the status of a live cell, `None` if it isn't cached.
+    pub fn get(&mut self, key: &H256) -> Option<CellStatus> {
+        let status = self.cells.get(key).cloned();
+        if status.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        status
+    }
+
+    /// Records the status written to a leaf, a live cell is cached and a dead one is evicted.
+    pub fn write(&mut self, key: &H256, status: &CellStatus) {
+        if status.is_live() {
+            self.cells.insert(*key, status.clone());
+        } else {
+            self.cells.remove(key);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// An estimate of the heap memory of the cache, the allocated buckets with their control byte.
+    pub fn memory_usage(&self) -> usize {
+        self.cells.capacity() * (size_of::<(H256, CellStatus)>() + 1)
+    }
+}
+
+impl fmt::Display for LiveCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "live cache {} cells, {} bytes, {} hits, {} misses",
+            self.len(),
+            self.memory_usage(),
+            self.hits,
+            self.misses
+        )
+    }
+}
+
+/// Accumulators whose delete path can read the statuses of live cells from a `LiveCache`.
+pub trait CacheLiveCells {
+    /// Enables the cache, a cache taken from the previous writer of the same database keeps its cells.
+    fn set_live_cache(&mut self, _cache: LiveCache) {}
+
+    fn live_cache(&self) -> Option<&LiveCache> {
+        None
+    }
+
+    /// Takes the cache out of the accumulator, to hand it over to the next writer.
+    fn take_live_cache(&mut self) -> Option<LiveCache> {
+        None
+    }
+}
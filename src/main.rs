@@ -1,93 +1,3278 @@
+mod cli;
+
+use clap::CommandFactory;
+#[cfg(feature = "grpc")]
+use cli::ServeArgs;
+use cli::{
+    AbsenceProofsArgs, AuditArgs, BenchArgs, BranchStorageArgs, BuildTreeArgs, Cli,
+    Command as CliCommand, CompletionsArgs, CrashTestArgs, DumpLayoutArgs, ExportFlatArgs,
+    FsckArgs, HistoryArgs, HistoryAuditArgs, InspectArgs, LiveCacheArgs, MigrateWidthArgs,
+    ProofCacheArgs, ProofLagArgs, RangeCommitArgs, ReportArgs, StatsArgs, SyncArgs, TunePrefixArgs,
+    UpdateChunksArgs, VerifyBatchArgs,
+};
+#[cfg(feature = "serde")]
+use cli::{RunMatrixArgs, TrendArgs};
+#[cfg(feature = "grpc")]
+use dca_bench::grpc::{self, AccumulatorService};
+#[cfg(feature = "profiling")]
+use dca_bench::profiling::{Phase, PhaseProfiler};
+use dca_bench::{
+    audit_log::{AuditLog, AUDIT_LOG_KEY},
+    batch::{Operation, RangeWriter},
+    builder::{AnyCommitment, AnyProof, Builder},
+    cell_counts::{performed_ops, CellCounter, CellCounts, CELL_COUNTS_KEY},
+    chain::{Header, HeaderChain},
+    db_options::DbOptions,
+    element_index::IndexedElements,
+    expiry::{VersionExpiry, VersionedKeys},
+    fsck::FsckReport,
+    inspect::{parse_command, parse_hex, Command, INSPECT_HELP},
+    layout,
+    live_cache::{CacheLiveCells, LiveCache},
+    metrics::{
+        BlockLatencies, BlockPacing, CellCosts, CoalescedOps, CommitSweep, FlushMode, FlushWrites,
+        KeyStats, KeyTracer, LevelWrites, MemoryUsage, ReportClock, ReportInterval, Stage,
+        StageTimes, StageTiming, TraceKeys, WitnessSizes, WriteCounts, WriteStalls,
+    },
+    mmr,
+    mmr::{
+        accumulator::{MMRAccumulator, MMRAccumulatorOwned},
+        sync::MMRSyncClient,
+    },
+    new_blake2b,
+    oplog::{OpLog, OPLOG_KEY},
+    overlay::PendingOverlay,
+    prometheus::BlockMetrics,
+    proof_cache::ProofCache,
+    report::{cost_table, dir_size, markdown_table, percentile, stage_table, RunMetrics},
+    retry::{is_conflict, replay_blocks, CommitConflicts, RetryPolicy, UncommittedBlock},
+    sequence_width::{
+        key_versions, migrate_sequence_width, set_sequence_width, stored_sequence_width,
+        SequenceWidth,
+    },
+    sink::{CommitmentSink, PublishedCommitment, SinkTarget},
+    smt,
+    smt::{
+        accumulator::{SMTAccumulator, SMTAccumulatorOwned},
+        flat::{self, FlatReader},
+        script_index::ScriptIndex,
+        sharded::ShardedSMTAccumulator,
+        sync::SMTSyncClient,
+        tiered::{TieredDBs, TieredSMTAccumulator},
+    },
+    smt_live,
+    smt_live::{
+        accumulator::{
+            SMTAccumulator as SMTLiveAccumulator, SMTAccumulatorOwned as SMTLiveAccumulatorOwned,
+        },
+        presence::{PresenceAccumulator, PresenceAccumulatorOwned},
+        sync::SMTLiveSyncClient,
+    },
+    storage::{overlap::OverlapTransaction, read_only::ReadOnly, traits, write_batch::BatchedDB},
+    sweep::{ExpirySweep, SweepCells, SweepStats},
+    sync::SyncClient,
+    witness::{ChunkedProver, IncrementalVerifier, ProofChunk},
+    workload::{model::ChainStats, reorder, sample_live_cells, Reordering, Spikes, Workload},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
+    SequenceSource,
+};
+#[cfg(feature = "serde")]
 use dca_bench::{
-    mmr::accumulator::MMRAccumulator, smt::accumulator::SMTAccumulator, AccumulatorWriter, OutPoint,
-    smt_live::accumulator::SMTAccumulator as SMTLiveAccumulator,
+    matrix::Matrix,
+    results::{config_hash, trends, Ledger, RunRecord},
 };
 use rand_chacha::{
     rand_core::{RngCore, SeedableRng},
     ChaChaRng,
 };
-use rocksdb::{prelude::Open, OptimisticTransaction, OptimisticTransactionDB};
-use std::time::Instant;
+use rocksdb::{
+    prelude::{Get, Iterate, Open, Put},
+    Direction, IteratorMode, OptimisticTransaction, OptimisticTransactionDB, Options, ReadOnlyDB,
+    Transaction, TransactionDB, WriteOptions, DB,
+};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::sync_channel,
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// set by SIGINT / SIGTERM, the benchmark loops stop after the current block
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+// a first signal lets the run finish its block, commit and write its summary, a second one exits right away
+fn install_shutdown_handler() {
+    ctrlc::set_handler(|| {
+        if SHUTDOWN.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        println!("shutting down after the current block, signal again to exit now");
+    })
+    .unwrap();
+}
+
+struct BenchOptions {
+    // the accumulator type, smt | mmr | smt_live | smt_presence
+    backend: String,
+    db_path: PathBuf,
+    start_block_number: u64,
+    total_blocks: u64,
+    db_kind: String,
+    strict: bool,
+    record_oplog: bool,
+    // chains the operations of every sequence into the digests of the audit log
+    audit_log: bool,
+    // runs the soak checks every given number of blocks
+    soak: Option<u64>,
+    // measures the witness of every block, i.e. the proof of the cells it consumes, and alerts above the given size
+    block_witness: bool,
+    witness_alert: Option<u64>,
+    // records the cell counters of every sequence and folds the live count into the header commitments
+    cell_counts: bool,
+    overlap_commit: bool,
+    // number of outputs of the workload transactions, their out_points share the tx_hash
+    outputs_per_tx: u64,
+    // the spike blocks injected into the workload, their processing time is reported apart from the regular blocks
+    spikes: Option<Spikes>,
+    // timelocks a share of the workload cells and sweeps the expired ones every given number of blocks
+    expiry_sweep: Option<ExpirySweep>,
+    // the chain statistics the workload model is fitted to, the model replaces the default blocks
+    workload_model: Option<ChainStats>,
+    level_writes: bool,
+    speculative: bool,
+    // number of the hottest keys to report at the end of the run, the keys are traced only if set
+    hot_keys: Option<usize>,
+    // leaf value encoding of a new smt database
+    leaf_encoding: Option<smt::LeafEncoding>,
+    // leaf key derivation of a new smt database
+    key_derivation: Option<smt::KeyDerivation>,
+    // branch node storage of a new smt database
+    branch_storage: Option<smt::BranchStorage>,
+    // sequence width of the versioned keys of a new database
+    sequence_width: Option<SequenceWidth>,
+    // checked element records of a new mmr database
+    element_integrity: bool,
+    // when the mmr writes the batches of its commits to the database, per block by default
+    flush_mode: Option<FlushMode>,
+    // prints the sizes of the flushed mmr batches every 100 blocks
+    flush_metrics: bool,
+    // number of sequences of history the mmr element index keeps when it's garbage collected every 100 blocks
+    gc_retention: Option<u64>,
+    // number of synthetic lock scripts of the secondary script index, the index is maintained only if set
+    script_index: Option<u32>,
+    // paces the blocks to one every given milliseconds instead of running them as fast as possible
+    target_block_interval: Option<u64>,
+    // how often the report lines are printed, every 100 blocks by default
+    report_every: ReportInterval,
+    // the transaction commit intervals of the equal segments of the run, every 100 blocks if empty
+    commit_sweep: Vec<u64>,
+    // how a transaction commit failing on a conflict is retried, the uncommitted blocks are kept in memory to be replayed
+    commit_retry: RetryPolicy,
+    // path of the metrics file written at the end of the run, for the `report` subcommand
+    metrics: Option<PathBuf>,
+    // exports the histograms of the block and stage times every 100 blocks to the given textfile collector file
+    prometheus: Option<PathBuf>,
+    // appends the summary of the run to the results ledger at the given path, keyed by the hash of the configuration,
+    // only with the serde feature
+    results: Option<PathBuf>,
+    #[cfg(feature = "serde")]
+    config_hash: String,
+    // the sinks the commitment of every block is published to, as soon as it's committed
+    publish: Vec<SinkTarget>,
+    // writes the flamegraphs of the add, delete and commit phases to the given directory
+    #[cfg(feature = "profiling")]
+    profile: Option<PathBuf>,
+    // keeps the statuses of the live cells written by the smt accumulator in memory, so deletes don't read the tree
+    live_cache: bool,
+    // paths of the additional databases of a smt accumulator sharded over them and the benchmark database
+    shards: Vec<PathBuf>,
+    // path of the hot tree database and the fold interval of a two-tier smt accumulator, the cold tree is in the
+    // benchmark database
+    hot_tree: Option<(PathBuf, u64)>,
+    // where the sequences of the commits come from, see `AccumulatorWriter::apply_numbered_blocks`
+    sequence_source: SequenceSource,
+    db_options: DbOptions,
+}
+
+// evaluates `$body` as a phase of a block, under the phase profiler of `--profile` if it's enabled
+#[cfg(feature = "profiling")]
+macro_rules! profile_phase {
+    ($profiler: ident, $phase: ident, $body: expr) => {
+        match $profiler.as_mut() {
+            Some(profiler) => profiler.profile(Phase::$phase, || $body).unwrap(),
+            None => $body,
+        }
+    };
+}
+
+#[cfg(not(feature = "profiling"))]
+macro_rules! profile_phase {
+    ($profiler: ident, $phase: ident, $body: expr) => {
+        $body
+    };
+}
+
+// runs the benchmark blocks, `$db` is the opened database, `$writer` is the transaction or database the accumulator writes to,
+// or the handles it's opened with, `$records` is the transaction or database the records of the blocks are written to,
+// `$commit` is evaluated every 100 blocks to commit the pending writes, and `$renew` starts the next transaction after it
+macro_rules! bench_loop {
+    ($options: ident, $accumulator: ty, $wo: ty, $db: ident, $writer: expr, $records: expr, $commit: block, $renew: block) => {
+        let (key_tracer, key_stats, write_counts) = key_tracer(&$options);
+        let mut accumulator = <$accumulator>::new_with_strict($writer, $options.strict).unwrap();
+        accumulator.trace_keys(key_tracer.clone());
+        if let Some(mode) = $options.flush_mode {
+            accumulator.set_flush_mode(mode).unwrap();
+        }
+        if $options.live_cache {
+            accumulator.set_live_cache(LiveCache::default());
+        }
+
+        let mut workload = new_workload(&$options);
+        let block_numbers =
+            $options.start_block_number..$options.start_block_number + $options.total_blocks;
+        let mut blocks: Box<dyn Iterator<Item = BlockOps>> = if $options.overlap_commit {
+            // the next blocks are generated in the background too, the transactions are double-buffered by `bench!`
+            let (sender, receiver) = sync_channel(100);
+            thread::spawn(move || {
+                for i in block_numbers {
+                    if sender.send(workload.next_block(i)).is_err() {
+                        break;
+                    }
+                }
+            });
+            Box::new(receiver.into_iter())
+        } else {
+            Box::new(block_numbers.map(move |i| workload.next_block(i)))
+        };
+
+        // the script index is stored next to the database, its writes are not atomic with the accumulator's
+        let script_index_db = $options
+            .script_index
+            .map(|_| DB::open_default(with_suffix(&$options.db_path, ".script-index")).unwrap());
+        let mut script_index = script_index_db
+            .as_ref()
+            .map(|db| ScriptIndex::<_, WriteOptions>::new(db).unwrap());
+        let mut script_index_elapsed = Duration::ZERO;
+        let mut commit_latencies = Vec::with_capacity($options.total_blocks as usize);
+        // a cell of the last block and its sequence, proved at the end of the run for the metrics
+        let mut last_cell = None;
+        let mut cell_counts = None;
+        let mut witness_sizes = $options
+            .block_witness
+            .then(|| WitnessSizes::new($options.witness_alert));
+        // the sequence of the parent of the next block, unknown for the first block of a resumed run
+        let mut parent_sequence = None;
+        #[cfg(feature = "profiling")]
+        let mut profiler = $options.profile.as_ref().map(|_| PhaseProfiler::new());
+
+        let now = Instant::now();
+        let mut pacing = $options
+            .target_block_interval
+            .map(|millis| BlockPacing::new(Duration::from_millis(millis)));
+        // the commitment of every block is embedded in the header of the block, which the next block builds on
+        let mut headers = HeaderChain::new($options.start_block_number);
+        let mut finished_blocks = 0;
+        let mut report_clock = ReportClock::new($options.report_every);
+        let mut write_stalls = WriteStalls::default();
+        // the stages timed by the accumulators are merged in when they are recreated and at the end
+        let mut stage_times = StageTimes::default();
+        let mut block_metrics = $options
+            .prometheus
+            .as_ref()
+            .map(|_| BlockMetrics::new(&$options.backend));
+        let mut block_latencies = BlockLatencies::default();
+        let mut sweep_stats = SweepStats::default();
+        let mut cell_costs = CellCosts::default();
+        // the coalesced operations of the accumulators, merged in like the stages
+        let mut coalesced_ops = 0;
+        let mut commit_conflicts = CommitConflicts::default();
+        // the blocks since the last transaction commit, replayed if the next commit conflicts, the writes of a plain
+        // database are not transactional, and an overlapped commit fails after its blocks were left behind. The
+        // replay commits a sequence per block, so a conflict of another sequence source isn't retried.
+        let keep_uncommitted = $options.commit_retry.max_retries > 0
+            && $options.db_kind != "plain"
+            && !$options.overlap_commit
+            && $options.sequence_source == SequenceSource::Block;
+        let mut uncommitted: Vec<UncommittedBlock> = Vec::new();
+        // the cell counts recorded by the uncommitted blocks, written again with them
+        let mut uncommitted_counts: Vec<(u64, CellCounts)> = Vec::new();
+        let mut sinks = open_sinks(&$options);
+        let mut commit_sweep = (!$options.commit_sweep.is_empty()).then(|| {
+            CommitSweep::new(
+                &$options.commit_sweep,
+                $options.start_block_number,
+                $options.total_blocks,
+            )
+        });
+        loop {
+            // with --overlap-commit the blocks are generated in the background, only the wait for them is counted
+            let workload_start = Instant::now();
+            let ops = match blocks.next() {
+                Some(ops) => ops,
+                None => break,
+            };
+            let workload_elapsed = workload_start.elapsed();
+            stage_times.record(Stage::Workload, workload_elapsed);
+            let block = headers.next_block(ops);
+            let i = block.number;
+            let spike = $options.spikes.map_or(false, |spikes| spikes.is_spike(i));
+            let (added, deleted) = (block.ops.added.len(), block.ops.deleted.len());
+            let block_start = Instant::now();
+            let block_stages = accumulator.stage_times().copied().unwrap_or_default();
+            if let (Some(index), Some(scripts)) = (script_index.as_mut(), $options.script_index) {
+                let start = Instant::now();
+                index
+                    .apply_block(&block.ops, |out_point| lock_script_hash(out_point, scripts))
+                    .unwrap();
+                script_index_elapsed += start.elapsed();
+            }
+            let logged_ops = if $options.record_oplog
+                || $options.cell_counts
+                || $options.audit_log
+                || keep_uncommitted
+            {
+                Some(block.ops.clone())
+            } else {
+                None
+            };
+            let first_added = block.ops.added.first().cloned();
+            let soak_cells = match $options.soak {
+                Some(interval) if i % interval == interval - 1 => {
+                    Some((block.ops.added.clone(), block.ops.deleted.clone()))
+                }
+                _ => None,
+            };
+            if let (Some(witness), Some(parent)) = (witness_sizes.as_mut(), parent_sequence) {
+                // the witness of a block proves the cells it consumes live at its parent, with their statuses
+                let bytes = if block.ops.deleted.is_empty() {
+                    0
+                } else {
+                    // the proof reads the database, the writes buffered by `--mmr-flush transaction` are flushed first
+                    accumulator.flush().unwrap();
+                    <$accumulator>::wallet_proof($writer, block.ops.deleted.clone(), parent)
+                        .unwrap()
+                        .to_bytes()
+                        .len() as u64
+                };
+                if witness.record(bytes) {
+                    println!("witness alert at block {}: {} bytes", i, bytes);
+                }
+            }
+            // the outputs of a transaction are consecutive
+            let transactions = block.ops.added.len().min(1)
+                + block
+                    .ops
+                    .added
+                    .windows(2)
+                    .filter(|pair| pair[0].tx_hash != pair[1].tx_hash)
+                    .count();
+            // the block is committed at the sequence after its parent, the sweep is part of its processing time, it runs
+            // before the writes of the block, which never adds or consumes a locked cell
+            let mut swept_before = None;
+            let mut swept_cells = 0;
+            if let (Some(sweep), Some(parent)) = ($options.expiry_sweep, parent_sequence) {
+                if sweep.is_due(i) {
+                    let created_before = (parent + 1).saturating_sub(sweep.age);
+                    let swept = profile_phase!(
+                        profiler,
+                        Sweep,
+                        accumulator.sweep(created_before, |hash, _status| sweep.timelocks.is_locked(hash))
+                    )
+                    .unwrap();
+                    swept_before = Some(created_before);
+                    swept_cells = swept.cells;
+                    sweep_stats.record(&swept);
+                    println!(
+                        "expiry sweep at block {}: {} cells, scan {} micros, update {} micros",
+                        i,
+                        swept.cells,
+                        swept.scan.as_micros(),
+                        swept.update.as_micros()
+                    );
+                }
+            }
+            // the cell counts count the transitions the block performs, a non-strict accumulator accepts e.g. the delete
+            // of a consumed cell as a no-op, so the statuses are looked up after the sweep, before the block
+            let performed = match logged_ops.as_ref() {
+                Some(ops) if $options.cell_counts && !$options.strict => Some(
+                    performed_ops(ops, |out_point| accumulator.cell_status(out_point)).unwrap(),
+                ),
+                _ => None,
+            };
+            // the block is written in a single update and committed by `apply_blocks`, which coalesces a cell added and
+            // consumed in the block, see `CoalescedOps`, or by the commits of the sequence source, the block is
+            // identified by the last one
+            let commit_start = Instant::now();
+            let bytes_before_block = write_counts.bytes();
+            let commitment = profile_phase!(
+                profiler,
+                Apply,
+                accumulator.apply_numbered_blocks(vec![(i, block.ops)], $options.sequence_source)
+            )
+            .unwrap()
+            .pop()
+            .expect("a commitment per block");
+            commit_latencies.push(commit_start.elapsed().as_micros() as u64);
+            // the writes of an expiry sweep are not attributed, the writes of the block are shared out by its leaves
+            cell_costs.record(
+                added as u64,
+                deleted as u64,
+                transactions as u64,
+                0,
+                0,
+                write_counts.bytes() - bytes_before_block,
+            );
+            let mut digest = commitment.commitment_digest();
+            if let (true, Some(ops)) = ($options.cell_counts, performed.as_ref().or(logged_ops.as_ref())) {
+                let counts = CellCounter::<_, $wo>::new($records)
+                    .record_transitions(
+                        commitment.sequence(),
+                        ops.added.len() as u64,
+                        ops.deleted.len() as u64 + swept_cells,
+                    )
+                    .unwrap();
+                digest = counts.fold_into(digest);
+                cell_counts = Some(counts);
+                if keep_uncommitted {
+                    uncommitted_counts.push((commitment.sequence(), counts));
+                }
+            }
+            headers
+                .append(Header {
+                    number: block.number,
+                    parent_commitment: block.parent_commitment,
+                    commitment: digest,
+                })
+                .unwrap();
+            publish(&mut sinks, i, commitment.sequence(), digest);
+            last_cell = first_added.map(|out_point| (out_point, commitment.sequence()));
+            parent_sequence = Some(commitment.sequence());
+            if let Some((added, deleted)) = soak_cells {
+                // the proofs of sampled cells of the block recompute the root of their subtrees from the stored nodes,
+                // and the element index (the leaves of `smt`) must hold the cells live, the cells it consumed must be
+                // dead and not provable, the buffered writes are flushed first
+                accumulator.flush().unwrap();
+                let mut rng = ChaChaRng::seed_from_u64(i);
+                let sequence = commitment.sequence();
+                for _ in 0..added.len().min(SOAK_SAMPLES) {
+                    let cell = added[rng.next_u32() as usize % added.len()].clone();
+                    match <$accumulator>::wallet_proof($writer, vec![cell.clone()], sequence)
+                        .and_then(|proof| proof.verify(&commitment))
+                    {
+                        Ok(true) => {}
+                        result => soak_failure(i, sequence, "live cell proof", &cell, &result),
+                    }
+                    match accumulator.cell_status(&cell) {
+                        Ok(Some(status)) if status.is_live() => {}
+                        result => soak_failure(i, sequence, "live cell status", &cell, &result),
+                    }
+                }
+                for cell in deleted.into_iter().take(SOAK_SAMPLES) {
+                    if let Ok(proof) =
+                        <$accumulator>::wallet_proof($writer, vec![cell.clone()], sequence)
+                    {
+                        soak_failure(i, sequence, "consumed cell proof", &cell, &proof.verify(&commitment));
+                    }
+                    match accumulator.cell_status(&cell) {
+                        Ok(status) if !status.as_ref().map_or(false, CellStatus::is_live) => {}
+                        result => soak_failure(i, sequence, "consumed cell status", &cell, &result),
+                    }
+                }
+            }
+            if let (true, Some(ops)) = ($options.audit_log, logged_ops.as_ref()) {
+                AuditLog::<_, $wo>::new($records)
+                    .record(commitment.sequence(), ops)
+                    .unwrap();
+            }
+            if let (true, Some(ops)) = ($options.record_oplog, logged_ops.as_ref()) {
+                OpLog::<_, $wo>::new($records)
+                    .put(commitment.sequence(), ops)
+                    .unwrap();
+            }
+            if let (true, Some(ops)) = (keep_uncommitted, logged_ops) {
+                uncommitted.push(UncommittedBlock {
+                    sequence: commitment.sequence(),
+                    ops,
+                    sweep: swept_before,
+                });
+            }
+
+            let block_elapsed = block_start.elapsed();
+            block_latencies.record(spike, block_elapsed);
+            if spike {
+                println!(
+                    "spike block {}: {} added, {} deleted, {} micros",
+                    i,
+                    added,
+                    deleted,
+                    block_elapsed.as_micros()
+                );
+            }
+            if let Some(metrics) = block_metrics.as_mut() {
+                // the stages of the block, the transaction commit of every 100 blocks is observed separately below
+                let mut stages = accumulator
+                    .stage_times()
+                    .map(|times| times.since(&block_stages))
+                    .unwrap_or_default();
+                stages.record(Stage::Workload, workload_elapsed);
+                metrics.observe_block(i, block_elapsed, &stages);
+            }
+
+            finished_blocks += 1;
+            let interrupted = SHUTDOWN.load(Ordering::SeqCst);
+            let report = report_clock.due(i) || interrupted;
+            // commit rocksdb transaction every 100 blocks, or at the interval of the segment of a commit sweep, and at
+            // the block an interrupted run stops at
+            let commit_due = match commit_sweep.as_ref() {
+                Some(sweep) => sweep.commit_due(i),
+                None => i % 100 == 99,
+            };
+            let commit = commit_due || interrupted;
+            let flush_elapsed = if commit {
+                let flush_start = Instant::now();
+                accumulator.flush().unwrap();
+                let flush_elapsed = flush_start.elapsed();
+                stage_times.record(Stage::TxCommit, flush_elapsed);
+                flush_elapsed
+            } else {
+                Duration::ZERO
+            };
+            // the lines of the run are printed at the report interval whatever the commit interval
+            if report {
+                println!(
+                    "elapsed {} millis, finished block: {}, {}",
+                    now.elapsed().as_millis(),
+                    i,
+                    MemoryUsage::sample(&$db)
+                );
+                // a throughput dip with a stall in the same interval is attributable to the compactions
+                if let Some(stalled) = write_stalls.sample(&$db, i).filter(|s| !s.is_zero()) {
+                    println!("write stall {} millis up to block {}", stalled.as_millis(), i);
+                }
+                if $options.level_writes {
+                    if let Some(histogram) = accumulator.level_writes() {
+                        println!("{}", histogram);
+                    }
+                }
+                if $options.flush_metrics {
+                    if let Some(stats) = accumulator.flush_stats() {
+                        println!("{}", stats);
+                    }
+                }
+                if let Some(cache) = accumulator.live_cache() {
+                    println!("{}", cache);
+                }
+                if let Some(stats) = accumulator.element_index_stats() {
+                    println!("{}", stats);
+                }
+                if let Some(pacing) = pacing.as_ref() {
+                    println!("{}", pacing);
+                }
+                if let Some(counts) = cell_counts.as_ref() {
+                    println!("{}", counts);
+                }
+                if let Some(witness) = witness_sizes.as_ref() {
+                    println!("{}", witness);
+                }
+                if let Some(index) = script_index.as_ref() {
+                    println!(
+                        "script index {} millis, root: {:?}",
+                        script_index_elapsed.as_millis(),
+                        index.root()
+                    );
+                }
+            }
+            if commit {
+                let tx_commit_start = Instant::now();
+                // the accumulator is done with the transaction, its stages are merged and the next accumulator takes
+                // its cached cells over
+                if let Some(times) = accumulator.stage_times() {
+                    stage_times.merge(times);
+                }
+                coalesced_ops += accumulator.coalesced_ops().unwrap_or_default();
+                let live_cache = accumulator.take_live_cache();
+                let mut result: Result<(), rocksdb::Error> = $commit;
+                let mut attempt = 0;
+                // a commit conflicting with a concurrent writer loses the transaction, the blocks since the last commit
+                // are replayed from memory on a new one
+                while let Err(e) = result {
+                    if !is_conflict(&e) || attempt == $options.commit_retry.max_retries || !keep_uncommitted {
+                        if is_conflict(&e) {
+                            commit_conflicts.record_failure();
+                        }
+                        println!("transaction commit failed at block {}: {}, {}", i, e, commit_conflicts);
+                        std::process::exit(1);
+                    }
+                    $renew
+                    let backoff = $options.commit_retry.backoff(attempt);
+                    println!(
+                        "commit conflict at block {}: {}, retry {} in {} millis replaying {} blocks",
+                        i,
+                        e,
+                        attempt + 1,
+                        backoff.as_millis(),
+                        uncommitted.len()
+                    );
+                    std::thread::sleep(backoff);
+                    attempt += 1;
+                    commit_conflicts.record_retry(uncommitted.len() as u64);
+                    // the replayed writes were counted when the blocks were first written, they are not traced again
+                    let mut replay = <$accumulator>::new_with_strict($writer, $options.strict).unwrap();
+                    if let Some(mode) = $options.flush_mode {
+                        replay.set_flush_mode(mode).unwrap();
+                    }
+                    let timelocks = $options.expiry_sweep.map(|sweep| sweep.timelocks);
+                    let replayed = replay_blocks(&mut replay, &uncommitted, |hash, _status| {
+                        timelocks.map_or(false, |timelocks| timelocks.is_locked(hash))
+                    })
+                    .unwrap();
+                    replay.flush().unwrap();
+                    drop(replay);
+                    for (sequence, counts) in &uncommitted_counts {
+                        CellCounter::<_, $wo>::new($records).put(*sequence, counts).unwrap();
+                    }
+                    for block in &uncommitted {
+                        if $options.audit_log {
+                            AuditLog::<_, $wo>::new($records).record(block.sequence, &block.ops).unwrap();
+                        }
+                        if $options.record_oplog {
+                            OpLog::<_, $wo>::new($records).put(block.sequence, &block.ops).unwrap();
+                        }
+                    }
+                    // the commitments of the blocks were published already, a concurrent writer which changed the state
+                    // they were built on makes the replay diverge
+                    if replayed.map(|replayed| replayed.commitment_digest()) != Some(commitment.commitment_digest()) {
+                        println!("the blocks replayed after a commit conflict at block {} give another commitment", i);
+                        std::process::exit(1);
+                    }
+                    result = $commit;
+                }
+                uncommitted.clear();
+                uncommitted_counts.clear();
+                $renew
+                let tx_commit_elapsed = tx_commit_start.elapsed();
+                stage_times.record(Stage::TxCommit, tx_commit_elapsed);
+                if let Some(metrics) = block_metrics.as_mut() {
+                    metrics.observe_stage(Stage::TxCommit, flush_elapsed + tx_commit_elapsed);
+                }
+                // the element index is collected every 100 blocks whatever the report interval
+                if let (true, Some(retention)) = (i % 100 == 99 || interrupted, $options.gc_retention) {
+                    let gc_report = mmr::gc::gc_element_index::<_, $wo>(
+                        $records,
+                        commitment.sequence(),
+                        retention,
+                    )
+                    .unwrap();
+                    println!("{}", gc_report);
+                }
+                if let Some(expiry) = $options.db_options.version_expiry.as_ref() {
+                    expiry.advance(commitment.sequence());
+                    if report {
+                        println!("{}", expiry);
+                    }
+                }
+                accumulator = <$accumulator>::new_with_strict($writer, $options.strict).unwrap();
+                accumulator.trace_keys(key_tracer.clone());
+                if let Some(mode) = $options.flush_mode {
+                    accumulator.set_flush_mode(mode).unwrap();
+                }
+                if let Some(cache) = live_cache {
+                    accumulator.set_live_cache(cache);
+                }
+            }
+            if let (true, Some(metrics), Some(path)) =
+                (report, block_metrics.as_ref(), $options.prometheus.as_ref())
+            {
+                metrics.write_textfile(path).unwrap();
+            }
+            if let Some(pacing) = pacing.as_mut() {
+                pacing.finish_block(block_start.elapsed());
+            }
+            if let Some(sweep) = commit_sweep.as_mut() {
+                if let Some(segment) = sweep.finish_block(i) {
+                    println!("{}", segment);
+                }
+            }
+            if interrupted {
+                println!("interrupted after block {}, resume from block {}", i, i + 1);
+                break;
+            }
+        }
+        // the proof of the last cell below reads the writes of the last blocks
+        accumulator.flush().unwrap();
+        if let Some(times) = accumulator.stage_times() {
+            stage_times.merge(times);
+        }
+        coalesced_ops += accumulator.coalesced_ops().unwrap_or_default();
+        if let Some(pacing) = pacing.as_ref() {
+            println!("{}", pacing);
+        }
+        if let Some(witness) = witness_sizes.as_ref() {
+            println!("{}", witness);
+        }
+        let elapsed_millis = now.elapsed().as_millis() as u64;
+        println!("total elapsed {} millis", elapsed_millis);
+        finish_sinks(&mut sinks);
+        println!("{}", write_counts.rates(finished_blocks, Duration::from_millis(elapsed_millis)));
+        println!("{}", cell_costs);
+        println!("coalesced operations: {}", coalesced_ops);
+        if commit_conflicts.conflicts > 0 {
+            println!("{}", commit_conflicts);
+        }
+        if let (Some(metrics), Some(path)) = (block_metrics.as_ref(), $options.prometheus.as_ref()) {
+            metrics.write_textfile(path).unwrap();
+        }
+        println!("{}", stage_times);
+        println!("{}", write_stalls);
+        if $options.spikes.is_some() {
+            println!("{}", block_latencies);
+        }
+        if $options.expiry_sweep.is_some() {
+            println!("{}", sweep_stats);
+        }
+        if let Some(sweep) = commit_sweep.as_ref() {
+            println!("{}", sweep);
+        }
+        if let Some(cache) = accumulator.live_cache() {
+            println!("{}", cache);
+        }
+        if let Some(expiry) = $options.db_options.version_expiry.as_ref() {
+            let db_size = dir_size(&$options.db_path).unwrap();
+            println!(
+                "database size {} bytes with version expiry, {} bytes without ({} bytes of expired versions)",
+                db_size,
+                db_size + expiry.expired_bytes(),
+                expiry.expired_bytes()
+            );
+        }
+        report_hot_keys(&$options, &key_stats);
+        #[cfg(feature = "profiling")]
+        if let (Some(profiler), Some(dir)) = (profiler.as_ref(), &$options.profile) {
+            for path in profiler.write_flamegraphs(dir, &$options.backend).unwrap() {
+                println!("flamegraph written to {}", path.display());
+            }
+        }
+        if $options.metrics.is_some() || $options.results.is_some() {
+            let proof_size = match last_cell {
+                Some((out_point, sequence)) => {
+                    <$accumulator>::wallet_proof($writer, vec![out_point], sequence)
+                        .unwrap()
+                        .to_bytes()
+                        .len() as u64
+                }
+                None => 0,
+            };
+            let metrics = RunMetrics {
+                backend: $options.backend.clone(),
+                blocks: finished_blocks,
+                elapsed_millis,
+                db_size: dir_size(&$options.db_path).unwrap(),
+                proof_size,
+                p99_commit_micros: percentile(&mut commit_latencies, 99),
+                stages: stage_times,
+                keys_written: write_counts.keys(),
+                bytes_written: write_counts.bytes(),
+                costs: cell_costs,
+                commit_conflicts: commit_conflicts.conflicts,
+                commit_retries: commit_conflicts.retries,
+                replayed_blocks: commit_conflicts.replayed_blocks,
+                coalesced_ops,
+                stall_millis: write_stalls.total().as_millis() as u64,
+                stall_windows: write_stalls.windows().len() as u64,
+                block_cache_size: $options.db_options.block_cache_size,
+                write_buffer_size: $options.db_options.write_buffer_size,
+                max_write_buffer_number: $options.db_options.max_write_buffer_number,
+            };
+            if let Some(path) = &$options.metrics {
+                std::fs::write(path, metrics.to_string()).unwrap();
+            }
+            #[cfg(feature = "serde")]
+            if let Some(path) = &$options.results {
+                let record = RunRecord {
+                    commit: current_commit(),
+                    config_hash: $options.config_hash.clone(),
+                    metrics,
+                };
+                Ledger::new(path).append(&record).unwrap();
+            }
+        }
+    };
+}
+
+// runs the benchmark blocks as block templates: each block is built in a `PendingOverlay`, its speculative commitment
+// is computed and rolled back, then it's promoted into the database, `$accumulator` is the owned accumulator type
+macro_rules! speculative_loop {
+    ($options: ident, $accumulator: ty) => {
+        let db = OptimisticTransactionDB::open(
+            &$options.db_options.to_rocksdb_options(),
+            &$options.db_path,
+        )
+        .unwrap();
+        let strict = $options.strict;
+        let (key_tracer, key_stats, write_counts) = key_tracer(&$options);
+        let open = |tx: Arc<OptimisticTransaction>| -> Result<_, AccumulatorError> {
+            let mut accumulator = <$accumulator>::new_with_strict(tx, strict)?;
+            accumulator.trace_keys(key_tracer.clone());
+            Ok(accumulator)
+        };
+        let mut workload = new_workload(&$options);
+
+        let block_numbers =
+            $options.start_block_number..$options.start_block_number + $options.total_blocks;
+
+        let now = Instant::now();
+        let mut report_clock = ReportClock::new($options.report_every);
+        let mut finished_blocks = 0;
+        let mut sinks = open_sinks(&$options);
+        let mut commit_conflicts = CommitConflicts::default();
+        for i in block_numbers {
+            let ops = workload.next_block(i);
+            let mut overlay = PendingOverlay::new(&db, open);
+            overlay.add(ops.added);
+            overlay.delete(ops.deleted);
+            let speculative = overlay.speculative_commitment().unwrap();
+            // a block conflicting with a concurrent writer is applied again on a new transaction
+            let commitment = match overlay.promote_with_retry(&$options.commit_retry, &mut commit_conflicts) {
+                Ok(commitment) => commitment,
+                Err(e) => {
+                    println!("transaction commit failed at block {}: {}, {}", i, e, commit_conflicts);
+                    std::process::exit(1);
+                }
+            };
+            // a concurrent writer which changed the state between the speculative commitment and the promotion
+            // makes them diverge
+            if speculative.to_bytes() != commitment.to_bytes() {
+                println!("the block promoted after a commit conflict at block {} gives another commitment", i);
+                std::process::exit(1);
+            }
+            publish(
+                &mut sinks,
+                i,
+                commitment.sequence(),
+                commitment.commitment_digest(),
+            );
+            finished_blocks += 1;
+
+            // every block is promoted in its own transaction, so an interrupted run stops right after it
+            if SHUTDOWN.load(Ordering::SeqCst) {
+                println!("interrupted after block {}, resume from block {}", i, i + 1);
+                break;
+            }
+            if report_clock.due(i) {
+                println!(
+                    "elapsed {} millis, finished block: {}, {}",
+                    now.elapsed().as_millis(),
+                    i,
+                    MemoryUsage::sample(&db)
+                );
+            }
+        }
+        println!("total elapsed {} millis", now.elapsed().as_millis());
+        println!("{}", write_counts.rates(finished_blocks, now.elapsed()));
+        finish_sinks(&mut sinks);
+        if commit_conflicts.conflicts > 0 {
+            println!("{}", commit_conflicts);
+        }
+        report_hot_keys(&$options, &key_stats);
+    };
+}
+
+// the number of cells sampled by each soak check
+const SOAK_SAMPLES: usize = 4;
+
+// aborts a soak run with the diagnostics of a failed check, the result is the verification of a proof or the status of
+// the cell, a consumed cell fails when it's still provable or live
+fn soak_failure(
+    block_number: u64,
+    sequence: u64,
+    check: &str,
+    cell: &OutPoint,
+    result: &dyn std::fmt::Debug,
+) -> ! {
+    let tx_hash: String = cell.tx_hash.iter().map(|b| format!("{:02x}", b)).collect();
+    println!(
+        "soak check failed at block {}, sequence {}: {} {}:{}, result: {:?}",
+        block_number, sequence, check, tx_hash, cell.index, result
+    );
+    std::process::exit(1);
+}
+
+// the synthetic lock script of a workload cell, the workload has no scripts so the cells are spread uniformly over
+// `scripts` lock scripts by their out_point hash
+fn lock_script_hash(out_point: &OutPoint, scripts: u32) -> [u8; 32] {
+    let hash = out_point.hash();
+    let script = u32::from_le_bytes(hash[0..4].try_into().expect("checked length")) % scripts;
+    let mut buf = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(&script.to_le_bytes());
+    hasher.finalize(&mut buf);
+    buf
+}
+
+// the workload of a benchmark run
+fn new_workload(options: &BenchOptions) -> Workload {
+    let workload = Workload::new(options.start_block_number, options.strict)
+        .with_outputs_per_tx(options.outputs_per_tx);
+    let workload = match options.spikes {
+        Some(spikes) => workload.with_spikes(spikes),
+        None => workload,
+    };
+    let workload = match options.expiry_sweep {
+        Some(sweep) => workload.with_timelocks(sweep.timelocks),
+        None => workload,
+    };
+    match options.workload_model.as_ref() {
+        Some(stats) => workload.with_model(stats),
+        None => workload,
+    }
+}
+
+// returns the key tracer of a benchmark run, the stats it collects and the writes it counts, the stats are disabled
+// unless the hottest keys are reported
+fn key_tracer(options: &BenchOptions) -> (KeyTracer, Arc<Mutex<KeyStats>>, Arc<WriteCounts>) {
+    let key_stats = Arc::new(Mutex::new(KeyStats::default()));
+    let write_counts = Arc::new(WriteCounts::default());
+    let key_tracer = match options.hot_keys {
+        Some(_) => KeyTracer::new(Arc::clone(&key_stats)),
+        None => KeyTracer::default(),
+    };
+    (
+        key_tracer.with_write_counts(Arc::clone(&write_counts)),
+        key_stats,
+        write_counts,
+    )
+}
+
+// opens the `--publish` sinks of a benchmark run
+fn open_sinks(options: &BenchOptions) -> Vec<Box<dyn CommitmentSink>> {
+    options
+        .publish
+        .iter()
+        .map(|target| target.open().unwrap())
+        .collect()
+}
+
+// publishes the commitment of a block to every sink, a sink which fails stops the run
+fn publish(
+    sinks: &mut [Box<dyn CommitmentSink>],
+    block_number: u64,
+    sequence: u64,
+    digest: [u8; 32],
+) {
+    let commitment = PublishedCommitment {
+        block_number,
+        sequence,
+        digest,
+    };
+    for sink in sinks {
+        sink.publish(&commitment).unwrap();
+    }
+}
+
+// waits until the sinks delivered every commitment of the run, after the run is timed
+fn finish_sinks(sinks: &mut [Box<dyn CommitmentSink>]) {
+    for sink in sinks {
+        sink.finish().unwrap();
+    }
+}
+
+fn report_hot_keys(options: &BenchOptions, key_stats: &Mutex<KeyStats>) {
+    if let Some(n) = options.hot_keys {
+        println!("{}", key_stats.lock().unwrap().hottest(n));
+    }
+}
 
 macro_rules! bench {
-    ($accumulator: ty) => {
-        let args: Vec<String> = std::env::args().collect();
-
-        let db_path = &args[2];
-        let start_block_number = args[3].parse::<u64>().unwrap();
-        let total_blocks = args[4].parse::<u64>().unwrap();
-
-        let db = OptimisticTransactionDB::open_default(db_path).unwrap();
-        let mut tx = db.transaction_default();
-        let mut accumulator = <$accumulator>::new(&tx).unwrap();
-
-        let seed = [0u8; 32];
-        // rng1 is used to generate tx_hash for new cells
-        let mut rng1 = ChaChaRng::from_seed(seed);
-        // to fill a tx_hash [u8; 32], we need 8 words of random data, in each block we produce 10 new cells, so we need to skip 80 words
-        rng1.set_word_pos((start_block_number * 80) as u128);
-        // rng2 is used to generate tx_hash for old cells
-        let mut rng2 = ChaChaRng::from_seed(seed);
-        // rng3 is used to generate index for old cells, to make sure the index is exsiting in the accumulator
-        let mut rng3 = ChaChaRng::from_seed([1u8; 32]);
-        rng3.set_word_pos(start_block_number as u128);
+    ($options: ident, $accumulator: ident, $owned: ident) => {
+        match $options.db_kind.as_str() {
+            "optimistic" if $options.speculative => {
+                speculative_loop!($options, $owned::<OptimisticTransaction, ()>);
+            }
+            // the transaction of every commit interval is committed on a worker while the next one is written
+            "optimistic" if $options.overlap_commit => {
+                let db = OptimisticTransactionDB::open(
+                    &$options.db_options.to_rocksdb_options(),
+                    &$options.db_path,
+                )
+                .unwrap();
+                thread::scope(|scope| {
+                    let mut tx = OverlapTransaction::new(db.transaction_default());
+                    bench_loop!(
+                        $options,
+                        $accumulator::<OverlapTransaction<OptimisticTransaction>, ()>,
+                        (),
+                        db,
+                        &tx,
+                        &tx,
+                        {
+                            let (next, result) = tx.commit_overlapped(
+                                scope,
+                                db.transaction_default(),
+                                |tx| tx.commit(),
+                            );
+                            tx = next;
+                            result
+                        },
+                        {}
+                    );
+                    tx.finish().unwrap();
+                });
+            }
+            "pessimistic" if $options.overlap_commit => {
+                let db = TransactionDB::open(
+                    &$options.db_options.to_rocksdb_options(),
+                    &$options.db_path,
+                )
+                .unwrap();
+                thread::scope(|scope| {
+                    let mut tx = OverlapTransaction::new(db.transaction_default());
+                    bench_loop!(
+                        $options,
+                        $accumulator::<OverlapTransaction<Transaction>, ()>,
+                        (),
+                        db,
+                        &tx,
+                        &tx,
+                        {
+                            let (next, result) = tx.commit_overlapped(
+                                scope,
+                                db.transaction_default(),
+                                |tx| tx.commit(),
+                            );
+                            tx = next;
+                            result
+                        },
+                        {}
+                    );
+                    tx.finish().unwrap();
+                });
+            }
+            "optimistic" => {
+                let db = OptimisticTransactionDB::open(
+                    &$options.db_options.to_rocksdb_options(),
+                    &$options.db_path,
+                )
+                .unwrap();
+                let mut tx = db.transaction_default();
+                bench_loop!(
+                    $options,
+                    $accumulator::<OptimisticTransaction, ()>,
+                    (),
+                    db,
+                    &tx,
+                    &tx,
+                    { tx.commit() },
+                    {
+                        tx = db.transaction_default();
+                    }
+                );
+            }
+            "pessimistic" => {
+                let db = TransactionDB::open(
+                    &$options.db_options.to_rocksdb_options(),
+                    &$options.db_path,
+                )
+                .unwrap();
+                let mut tx = db.transaction_default();
+                bench_loop!(
+                    $options,
+                    $accumulator::<Transaction, ()>,
+                    (),
+                    db,
+                    &tx,
+                    &tx,
+                    { tx.commit() },
+                    {
+                        tx = db.transaction_default();
+                    }
+                );
+            }
+            "plain" => {
+                // the writes of every commit interval are kept in memory, where the reads see them, and written in a
+                // single `WriteBatch` by the commit, see `BatchedDB`
+                let db = DB::open(
+                    &$options.db_options.to_rocksdb_options(),
+                    &$options.db_path,
+                )
+                .unwrap();
+                let batched = BatchedDB::new(&db);
+                bench_loop!(
+                    $options,
+                    $accumulator::<BatchedDB, WriteOptions>,
+                    WriteOptions,
+                    db,
+                    &batched,
+                    &batched,
+                    { batched.commit() },
+                    {}
+                );
+            }
+            _ => {
+                println!("--db-kind must be optimistic | pessimistic | plain");
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+// runs the benchmark blocks on a smt accumulator sharded over the benchmark database and the `--shards` databases, e.g. on
+// separate devices, every write goes to the shard databases directly like with the plain db kind, the records of the
+// blocks are written to the benchmark database
+fn bench_sharded(options: BenchOptions) {
+    let db_options = options.db_options.to_rocksdb_options();
+    let db = DB::open(&db_options, &options.db_path).unwrap();
+    let shards: Vec<DB> = options
+        .shards
+        .iter()
+        .map(|path| DB::open(&db_options, path).unwrap())
+        .collect();
+    bench_loop!(
+        options,
+        ShardedSMTAccumulator::<&DB, WriteOptions>,
+        WriteOptions,
+        db,
+        std::iter::once(&db).chain(&shards).collect(),
+        &db,
+        { Ok(()) },
+        {}
+    );
+}
+
+// runs the benchmark blocks on a two-tier smt accumulator, with the cold tree in the benchmark database and the hot tree
+// in the `--hot-tree` database, both are written directly like with the plain db kind, the hot tree first
+fn bench_tiered(options: BenchOptions) {
+    let (hot_path, fold_interval) = options.hot_tree.clone().expect("--hot-tree");
+    let db_options = options.db_options.to_rocksdb_options();
+    let db = DB::open(&db_options, &options.db_path).unwrap();
+    let hot_db = DB::open(&db_options, hot_path).unwrap();
+    let dbs = TieredDBs {
+        hot: &hot_db,
+        cold: &db,
+        fold_interval,
+    };
+    bench_loop!(
+        options,
+        TieredSMTAccumulator::<&DB, WriteOptions>,
+        WriteOptions,
+        db,
+        dbs,
+        &db,
+        { Ok(()) },
+        {}
+    );
+}
 
+// a path next to `path` named after it, e.g. `/data/smt.script-index` for `/data/smt`, the suffix is appended to the file
+// name as is so it works with any path separator
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// checks the internal consistency of an existing benchmark database at its last committed sequence
+fn check_db(accumulator_type: &str, db_path: &Path) -> FsckReport {
+    let db = DB::open_default(db_path).unwrap();
+    let snapshot = db.snapshot();
+    match accumulator_type {
+        "smt" => smt::fsck::fsck(&snapshot),
+        "mmr" => mmr::fsck::fsck(&snapshot),
+        "smt_live" => smt_live::fsck::fsck(&snapshot),
+        _ => {
+            println!("accumulator type must be smt | mmr | smt_live");
+            std::process::exit(1);
+        }
+    }
+    .unwrap()
+}
+
+// exits with an error if any check fails
+fn fsck(args: &FsckArgs) {
+    let report = check_db(&args.backend, &args.db_path);
+    print!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+}
+
+// repeatedly runs the benchmark in a child process and kills it, either at a random time or right after it reports a finished
+// block, when it starts committing the rocksdb transaction. After each kill the database is reopened and checked, it must be
+// consistent without orphaned versions, and the next round resumes from the block after the last committed sequence.
+fn crash_test(args: &CrashTestArgs) {
+    let accumulator_type = &args.backend;
+    let db_path = &args.db_path;
+    let rounds = args.rounds;
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    println!("crash test seed: {}", seed);
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let exe = std::env::current_exe().unwrap();
+
+    for round in 0..=rounds {
+        let report = check_db(accumulator_type, db_path);
+        if !report.is_ok() || report.orphaned_versions > 0 {
+            print!("round {} failed:\n{}", round, report);
+            std::process::exit(1);
+        }
+        if round == rounds {
+            break;
+        }
+
+        // blocks are numbered by sequence, so the next round resumes right after the last committed one
+        let start_block_number = report.sequence.map(|s| s + 1).unwrap_or(0);
+        let mut child = Command::new(&exe)
+            .args(["bench", accumulator_type.as_str()])
+            .arg(db_path)
+            .arg(start_block_number.to_string())
+            // more blocks than a round can ever run, the child is always killed
+            .arg("1000000000")
+            .args(&args.bench_options)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take().unwrap();
+        if rng.next_u32() % 2 == 0 {
+            let delay = 100 + rng.next_u64() % 2000;
+            println!(
+                "round {}: resumed from block {}, killing after {} millis",
+                round, start_block_number, delay
+            );
+            // keep draining the output so the child never blocks on a full pipe
+            thread::spawn(move || for _line in BufReader::new(stdout).lines() {});
+            thread::sleep(Duration::from_millis(delay));
+        } else {
+            let reports = 1 + rng.next_u32() % 5;
+            println!(
+                "round {}: resumed from block {}, killing at commit after {} reports",
+                round, start_block_number, reports
+            );
+            let mut lines = BufReader::new(stdout).lines();
+            let mut seen = 0;
+            while seen < reports {
+                match lines.next() {
+                    Some(Ok(line)) if line.starts_with("elapsed") => seen += 1,
+                    Some(Ok(_)) => {}
+                    _ => break,
+                }
+            }
+        }
+        // the child may have already died on its own, the next check tells whether it left the database consistent
+        let _ = child.kill();
+        child.wait().unwrap();
+    }
+    println!("crash test passed after {} rounds", rounds);
+}
+
+// the key families of each backend: name, length of the key without its version and whether it's versioned,
+// every family has a distinct total key length within a backend
+fn key_families(accumulator_type: &str) -> Vec<(&'static str, usize, bool)> {
+    match accumulator_type {
+        "smt" => vec![
+            ("leaf", 32, true),
+            ("branch", 33, true),
+            ("shared branch node", 10 + 32, false),
+        ],
+        "smt_live" => vec![
+            ("leaf", 32, true),
+            ("branch", 1 + 33, true),
+            ("element", 1 + 32 + 4, true),
+        ],
+        "mmr" => vec![
+            ("node", 1 + 8, true),
+            ("element", 1 + 32 + 4, true),
+            ("mmr size", 1, true),
+        ],
+        _ => {
+            println!("accumulator type must be smt | mmr | smt_live");
+            std::process::exit(1);
+        }
+    }
+}
+
+// measures the latency of the reads of every key family of a benchmark database under different prefix extractor lengths and
+// bloom filter settings, each setting is measured on a copy of the database written with it, so sample databases should be small
+fn tune_prefix(args: &TunePrefixArgs) {
+    let families = key_families(&args.backend);
+    let db_path = &args.db_path;
+    let samples = args.samples_per_key_family;
+
+    let source = DB::open_default(db_path).unwrap();
+    let width = stored_sequence_width(&source).unwrap();
+    let mut sampled_keys = vec![Vec::new(); families.len()];
+    for (key, _value) in source.iterator(IteratorMode::Start) {
+        for (i, (_name, key_len, versioned)) in families.iter().enumerate() {
+            let total_len = if *versioned {
+                key_len + width.encoded_len()
+            } else {
+                *key_len
+            };
+            let keys: &mut Vec<Vec<u8>> = &mut sampled_keys[i];
+            if key.len() == total_len
+                && keys.len() < samples
+                && keys.last().map_or(true, |last| last[..] != key[..*key_len])
+            {
+                keys.push(key[..*key_len].to_vec());
+            }
+        }
+    }
+
+    let mut prefix_lens: Vec<Option<usize>> = vec![None];
+    prefix_lens.extend(families.iter().map(|(_, key_len, _)| Some(*key_len)));
+    prefix_lens.sort();
+    prefix_lens.dedup();
+    let copy_path = with_suffix(db_path, ".tune-prefix");
+    for prefix_len in prefix_lens {
+        for bloom_bits_per_key in [None, Some(10)] {
+            let db_options = DbOptions {
+                prefix_len,
+                bloom_bits_per_key,
+                ..DbOptions::default()
+            };
+            {
+                let db = DB::open(&db_options.to_rocksdb_options(), &copy_path).unwrap();
+                for (key, value) in source.iterator(IteratorMode::Start) {
+                    db.put(key, value).unwrap();
+                }
+
+                for ((name, key_len, versioned), keys) in families.iter().zip(&sampled_keys) {
+                    if keys.is_empty() || prefix_len.map_or(false, |len| len > *key_len) {
+                        println!("{:?}, {}: n/a", db_options, name);
+                        continue;
+                    }
+                    let now = Instant::now();
+                    for key in keys {
+                        if *versioned {
+                            // the latest version, as read by the stores
+                            let start = width.versioned_key(key, width.max_sequence());
+                            let mode = IteratorMode::From(&start, Direction::Reverse);
+                            db.iterator(mode)
+                                .take_while(|(k, _v)| k.starts_with(key))
+                                .next()
+                                .expect("sampled key exists");
+                        } else {
+                            db.get(key).unwrap().expect("sampled key exists");
+                        }
+                    }
+                    println!(
+                        "{:?}, {}: {} nanos per read",
+                        db_options,
+                        name,
+                        now.elapsed().as_nanos() / keys.len() as u128
+                    );
+                }
+            }
+            std::fs::remove_dir_all(&copy_path).unwrap();
+        }
+    }
+}
+
+// runs `$blocks` large synthetic blocks of `$outputs` new cells, each one consuming half of the cells of the previous
+// block, on a new database written by `$accumulator` with the tree updates split in chunks of `$chunk_size` leaves
+macro_rules! update_chunks_run {
+    ($accumulator: ty, $db_path: expr, $chunk_size: expr, $outputs: expr, $blocks: expr) => {{
+        let db = DB::open_default($db_path).unwrap();
+        let mut accumulator = <$accumulator>::new(&db).unwrap();
+        accumulator.set_update_chunk_size($chunk_size);
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut previous: Vec<OutPoint> = Vec::new();
+        let mut latencies = Vec::with_capacity($blocks);
         let now = Instant::now();
-        for i in start_block_number..start_block_number + total_blocks {
-            // each block we produce 10 new cells and consume 6 old cells
-            let out_points = (0..10)
+        for _ in 0..$blocks {
+            let added: Vec<OutPoint> = (0..$outputs)
                 .map(|_| {
                     let mut tx_hash = [0u8; 32];
-                    rng1.fill_bytes(&mut tx_hash);
+                    rng.fill_bytes(&mut tx_hash);
                     OutPoint { tx_hash, index: 0 }
                 })
-                .collect::<Vec<_>>();
-            accumulator.add(out_points).unwrap();
-
-            if i > 100 {
-                let out_points = (0..6)
-                    .map(|_| {
-                        let i = rng3.next_u64() % (i * 10);
-                        rng2.set_word_pos((i * 8) as u128);
-                        let mut tx_hash = [0u8; 32];
-                        rng2.fill_bytes(&mut tx_hash);
-                        OutPoint { tx_hash, index: 0 }
-                    })
-                    .collect::<Vec<_>>();
-                accumulator.delete(out_points).unwrap();
+                .collect();
+            let deleted = previous.split_off(previous.len() / 2);
+            previous = added.clone();
+            let block_start = Instant::now();
+            accumulator.add(added).unwrap();
+            if !deleted.is_empty() {
+                accumulator.delete(deleted).unwrap();
             }
-            // commit accumulator every block
             accumulator.commit().unwrap();
+            latencies.push(block_start.elapsed().as_micros() as u64);
+        }
+        let elapsed = now.elapsed();
+        let max = latencies.iter().copied().max().unwrap_or(0);
+        println!(
+            "chunk size {}: {:.1} blocks/s, p99 block {} micros, max block {} micros, {}",
+            $chunk_size.map_or("none".to_string(), |size: usize| size.to_string()),
+            $blocks as f64 / elapsed.as_secs_f64(),
+            percentile(&mut latencies, 99),
+            max,
+            MemoryUsage::sample(&db)
+        );
+    }};
+}
 
-            // commit rocksdb transaction every 100 blocks
-            if i % 100 == 99 {
-                println!("elapsed {} millis, finished block: {}", now.elapsed().as_millis(), i);
-                tx.commit().unwrap();
-                tx = db.transaction_default();
-                accumulator = <$accumulator>::new(&tx).unwrap();
+// measures the latency and throughput trade-off of splitting the tree updates of large blocks into chunks, every chunk
+// size runs the same blocks on a new database, which is removed afterwards
+fn update_chunks(args: &UpdateChunksArgs) {
+    let db_path = &args.db_path;
+    let outputs = args.outputs_per_block;
+    let blocks = args.blocks;
+    for chunk_size in [None, Some(256), Some(1024), Some(4096)] {
+        match args.backend.as_str() {
+            "smt" => update_chunks_run!(
+                SMTAccumulator<DB, WriteOptions>,
+                db_path,
+                chunk_size,
+                outputs,
+                blocks
+            ),
+            "smt_live" => update_chunks_run!(
+                SMTLiveAccumulator<DB, WriteOptions>,
+                db_path,
+                chunk_size,
+                outputs,
+                blocks
+            ),
+            _ => {
+                println!("accumulator type must be smt | smt_live");
+                std::process::exit(1);
             }
         }
+        std::fs::remove_dir_all(db_path).unwrap();
     }
 }
-fn main() {
-    let mut args = std::env::args();
-    if args.len() < 5 {
+
+// the cells added or spent per block by `live_cache`
+const LIVE_CACHE_BLOCK: usize = 10000;
+
+// fills a new database with `cells` live cells and spends `spends` of them, spread over the fill, the live cache is
+// enabled from the first block when `cached`, returns the spends per second and the cache, the database is removed
+fn live_cache_run(
+    db_path: &Path,
+    cells: u64,
+    spends: usize,
+    cached: bool,
+) -> (f64, Option<LiveCache>) {
+    let db = DB::open_default(db_path).unwrap();
+    let mut accumulator = SMTAccumulator::<DB, WriteOptions>::new(&db).unwrap();
+    if cached {
+        accumulator.set_live_cache(LiveCache::default());
+    }
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let stride = (cells / spends.max(1) as u64).max(1);
+    let mut spent = Vec::with_capacity(spends);
+    let mut filled = 0u64;
+    while filled < cells {
+        let size = (cells - filled).min(LIVE_CACHE_BLOCK as u64);
+        let block: Vec<OutPoint> = (0..size)
+            .map(|_| {
+                let mut tx_hash = [0u8; 32];
+                rng.fill_bytes(&mut tx_hash);
+                OutPoint { tx_hash, index: 0 }
+            })
+            .collect();
+        for (i, out_point) in block.iter().enumerate() {
+            if (filled + i as u64) % stride == 0 && spent.len() < spends {
+                spent.push(out_point.clone());
+            }
+        }
+        filled += size;
+        accumulator.add(block).unwrap();
+        accumulator.commit().unwrap();
+    }
+    let now = Instant::now();
+    for block in spent.chunks(LIVE_CACHE_BLOCK) {
+        accumulator.delete(block.to_vec()).unwrap();
+        accumulator.commit().unwrap();
+    }
+    let spends_per_sec = spent.len() as f64 / now.elapsed().as_secs_f64().max(0.001);
+    let cache = accumulator.take_live_cache();
+    drop(accumulator);
+    drop(db);
+    std::fs::remove_dir_all(db_path).unwrap();
+    (spends_per_sec, cache)
+}
+
+// measures the throughput gain of the live cache on the spends of the smt backend against its memory cost, every number
+// of live cells runs without and with the cache on a new database
+fn live_cache(args: &LiveCacheArgs) {
+    for &cells in &args.cells {
+        let (uncached, _) = live_cache_run(&args.db_path, cells, args.spends, false);
+        let (cached, cache) = live_cache_run(&args.db_path, cells, args.spends, true);
+        println!(
+            "{} live cells: {:.0} spends/s without the cache, {:.0} spends/s with it ({:.2}x), {}",
+            cells,
+            uncached,
+            cached,
+            cached / uncached.max(f64::MIN_POSITIVE),
+            cache.expect("the cache was enabled")
+        );
+    }
+}
+
+// the blocks of a branch storage run written in one transaction, like the benchmark
+const BRANCH_STORAGE_COMMIT: u64 = 100;
+
+// writes the blocks of the standard workload to a new database with the given branch storage, returns the size of the
+// database and its commitment digest at the last block
+fn branch_storage_run(db_path: &Path, blocks: u64, storage: smt::BranchStorage) -> (u64, [u8; 32]) {
+    {
+        let db = DB::open_default(db_path).unwrap();
+        smt::set_branch_storage::<_, WriteOptions>(&db, storage).unwrap();
+    }
+    let db = OptimisticTransactionDB::open_default(db_path).unwrap();
+    let mut workload = Workload::new(0, true);
+    let mut digest = [0u8; 32];
+    for start in (0..blocks).step_by(BRANCH_STORAGE_COMMIT as usize) {
+        let end = (start + BRANCH_STORAGE_COMMIT).min(blocks);
+        let tx = db.transaction_default();
+        let mut accumulator = SMTAccumulator::<_, ()>::new(&tx).unwrap();
+        let commitments = accumulator
+            .apply_blocks((start..end).map(|i| workload.next_block(i)).collect())
+            .unwrap();
+        digest = commitments
+            .last()
+            .expect("a commitment per block")
+            .commitment_digest();
+        drop(accumulator);
+        tx.commit().unwrap();
+    }
+    drop(db);
+    let size = dir_size(db_path).unwrap();
+    std::fs::remove_dir_all(db_path).unwrap();
+    (size, digest)
+}
+
+// measures the size reduction of the shared branch storage against the inline one, both written with the same blocks on
+// a new database, which must give the same commitments
+fn branch_storage(args: &BranchStorageArgs) {
+    let (inline, inline_digest) =
+        branch_storage_run(&args.db_path, args.blocks, smt::BranchStorage::Inline);
+    let (shared, shared_digest) =
+        branch_storage_run(&args.db_path, args.blocks, smt::BranchStorage::Shared);
+    if inline_digest != shared_digest {
+        println!("the shared branch storage gives another commitment than the inline one");
+        std::process::exit(1);
+    }
+    println!(
+        "{} blocks: database size {} bytes with the inline branch storage, {} bytes with the shared one ({:+.1}%)",
+        args.blocks,
+        inline,
+        shared,
+        (shared as f64 - inline as f64) * 100.0 / (inline as f64).max(1.0)
+    );
+}
+
+// the blocks of the transaction of a `range-commit` run, rounded down to whole ranges, at least one
+const RANGE_COMMIT_TX: u64 = 100;
+
+// writes the blocks of the standard workload to a new database with the mmr backend, committing every block, or every
+// `range` blocks through a `RangeWriter` whose first operation of every range is proved against the range commitment,
+// returns the elapsed time, the database size and the number of commits
+fn range_commit_run(db_path: &Path, blocks: u64, range: Option<u64>) -> (Duration, u64, u64) {
+    let db = OptimisticTransactionDB::open_default(db_path).unwrap();
+    let mut workload = Workload::new(0, true);
+    let tx_blocks = range.map_or(RANGE_COMMIT_TX, |range| {
+        RANGE_COMMIT_TX.max(range) / range * range
+    });
+    let mut commits = 0;
+    let now = Instant::now();
+    for start in (0..blocks).step_by(tx_blocks as usize) {
+        let end = (start + tx_blocks).min(blocks);
+        let tx = db.transaction_default();
+        let mut accumulator = MMRAccumulator::<_, ()>::new_with_strict(&tx, true).unwrap();
+        match range {
+            None => {
+                let blocks = (start..end).map(|i| workload.next_block(i)).collect();
+                commits += accumulator.apply_blocks(blocks).unwrap().len() as u64;
+            }
+            Some(range) => {
+                let mut writer = RangeWriter::new(accumulator);
+                let mut first = None;
+                for i in start..end {
+                    let block = workload.next_block(i);
+                    if first.is_none() {
+                        first = block
+                            .added
+                            .first()
+                            .map(|out_point| Operation::Add(out_point.clone()))
+                            .or_else(|| {
+                                block
+                                    .deleted
+                                    .first()
+                                    .map(|out_point| Operation::Delete(out_point.clone()))
+                            });
+                    }
+                    writer.add(block.added).unwrap();
+                    if !block.deleted.is_empty() {
+                        writer.delete(block.deleted).unwrap();
+                    }
+                    if (i + 1 - start) % range != 0 && i + 1 != end {
+                        continue;
+                    }
+                    let committed = writer.commit().unwrap();
+                    commits += 1;
+                    if let Some(operation) = first.take() {
+                        let proved = committed
+                            .op_proof(0)
+                            .and_then(|proof| {
+                                proof.verify(committed.ops_root, committed.op_count, &operation)
+                            })
+                            .unwrap();
+                        if !proved {
+                            println!(
+                                "the first operation of the range committed at sequence {} isn't proved",
+                                committed.commitment.sequence()
+                            );
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                accumulator = writer.into_inner();
+            }
+        }
+        drop(accumulator);
+        tx.commit().unwrap();
+    }
+    let elapsed = now.elapsed();
+    drop(db);
+    let size = dir_size(db_path).unwrap();
+    std::fs::remove_dir_all(db_path).unwrap();
+    (elapsed, size, commits)
+}
+
+// measures the time and size saved by committing a range of blocks at once with a `RangeWriter`, against a commitment
+// per block, both written with the same blocks on a new database
+fn range_commit(args: &RangeCommitArgs) {
+    let (block_elapsed, block_size, block_commits) =
+        range_commit_run(&args.db_path, args.blocks, None);
+    let (range_elapsed, range_size, range_commits) =
+        range_commit_run(&args.db_path, args.blocks, Some(args.range));
+    println!(
+        "{} blocks, a commitment per block: {} commits in {:?}, database size {} bytes",
+        args.blocks, block_commits, block_elapsed, block_size
+    );
+    println!(
+        "{} blocks, a commitment per {} blocks: {} commits in {:?} ({:+.1}%), database size {} bytes ({:+.1}%)",
+        args.blocks,
+        args.range,
+        range_commits,
+        range_elapsed,
+        (range_elapsed.as_secs_f64() - block_elapsed.as_secs_f64()) * 100.0
+            / block_elapsed.as_secs_f64().max(f64::EPSILON),
+        range_size,
+        (range_size as f64 - block_size as f64) * 100.0 / (block_size as f64).max(1.0)
+    );
+}
+
+// applies `$blocks` to a new database at `$db_path` written by `$accumulator`, returns the commitment digest of every block
+// and the number of coalesced operations
+macro_rules! history_audit_run {
+    ($accumulator: ty, $db_path: expr, $blocks: expr) => {{
+        let db = DB::open_default($db_path).unwrap();
+        let mut accumulator = <$accumulator>::new_with_strict(&db, true).unwrap();
+        let digests: Vec<[u8; 32]> = accumulator
+            .apply_blocks($blocks)
+            .unwrap()
+            .iter()
+            .map(|commitment| commitment.commitment_digest())
+            .collect();
+        let coalesced = accumulator.coalesced_ops().unwrap_or_default();
+        drop(accumulator);
+        drop(db);
+        std::fs::remove_dir_all($db_path).unwrap();
+        (digests, coalesced)
+    }};
+}
+
+// applies the same blocks to every backend in different operation orders and compares the roots with the canonical order,
+// the smt backends are expected to be history independent, except `smt` which also commits to the consumed cells, `mmr`
+// commits to the insertion order and is only reported
+fn history_audit(args: &HistoryAuditArgs) {
+    let db_path = &args.db_path;
+    let total_blocks = args.blocks;
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..total_blocks).map(|i| workload.next_block(i)).collect();
+    let mut failed = false;
+    for backend in ["smt", "smt_live", "mmr"] {
+        let run = |blocks: Vec<BlockOps>| match backend {
+            "smt" => history_audit_run!(SMTAccumulator<DB, WriteOptions>, db_path, blocks),
+            "smt_live" => {
+                history_audit_run!(SMTLiveAccumulator<DB, WriteOptions>, db_path, blocks)
+            }
+            _ => history_audit_run!(MMRAccumulator<DB, WriteOptions>, db_path, blocks),
+        };
+        let (canonical, _) = run(blocks.clone());
+        for reordering in [
+            Reordering::Shuffled,
+            Reordering::Transient,
+            Reordering::AcrossBlocks,
+        ] {
+            let (digests, coalesced) = run(reorder(&blocks, reordering, 0));
+            // the blocks in between hold other live cells once the consumptions move, only the last one is compared
+            let skipped = match reordering {
+                Reordering::AcrossBlocks => canonical.len().saturating_sub(1),
+                _ => 0,
+            };
+            let differing = canonical
+                .iter()
+                .zip(digests.iter())
+                .skip(skipped)
+                .filter(|(a, b)| a != b)
+                .count();
+            // `smt` commits to the sequence a cell was consumed at
+            let expected_independent = match backend {
+                "smt" => reordering == Reordering::Shuffled,
+                "smt_live" => true,
+                _ => false,
+            };
+            let verdict = match (differing, expected_independent) {
+                (0, _) => "identical roots",
+                (_, true) => {
+                    failed = true;
+                    "FAILED, roots differ"
+                }
+                (_, false) => "roots differ, not history independent",
+            };
+            println!(
+                "{} {}: {} ({} of {} blocks differ, {} operations coalesced)",
+                backend,
+                reordering,
+                verdict,
+                differing,
+                canonical.len() - skipped,
+                coalesced
+            );
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// checks the audit log of a benchmark database against its oplog and prints its last digest, which is the same for two
+// runs of the same workload on any backends, exits with an error if a recorded digest doesn't match
+fn audit(args: &AuditArgs) {
+    let db = DB::open_default(&args.db_path).unwrap();
+    let log = AuditLog::<_, WriteOptions>::new(&db);
+    let (sequence, digest) = match log.latest().unwrap() {
+        Some(latest) => latest,
+        None => {
+            println!("no audit log, run the benchmark with --oplog --audit-log");
+            std::process::exit(1);
+        }
+    };
+    if let Some(mismatch) = log.audit(sequence).unwrap() {
+        println!("audit log doesn't match the oplog at sequence {}", mismatch);
+        std::process::exit(1);
+    }
+    let digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    println!("audit log intact up to sequence {}: {}", sequence, digest);
+}
+
+// prints the canonical dump of the keys and values of a database, see `dca_bench::layout`
+fn dump_layout(args: &DumpLayoutArgs) {
+    let db = DB::open_default(&args.db_path).unwrap();
+    print!("{}", layout::dump_layout(&db));
+}
+
+// the number of latest commitments printed by `stats`
+const ROOT_HISTORY: usize = 10;
+
+// the stored keys of a key family, the byte totals count every version with its key
+#[derive(Clone, Copy, Default)]
+struct FamilyTotals {
+    keys: u64,
+    versions: u64,
+    key_bytes: u64,
+    value_bytes: u64,
+}
+
+impl FamilyTotals {
+    fn record(&mut self, key: &[u8], value: &[u8]) {
+        self.key_bytes += key.len() as u64;
+        self.value_bytes += value.len() as u64;
+    }
+}
+
+impl std::fmt::Display for FamilyTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} keys, {} versions, {} key bytes, {} value bytes",
+            self.keys, self.versions, self.key_bytes, self.value_bytes
+        )
+    }
+}
+
+// reports the number of keys, stored versions and key and value bytes of every key family of a benchmark database, and
+// the keys with the most versions, i.e. the most written ones, its last cell counters and latest commitments, reads are
+// not persisted and only traced during a run with `--hot-keys`
+fn stats(args: &StatsArgs) {
+    let families = key_families(&args.backend);
+    let db = DB::open_default(&args.db_path).unwrap();
+    let width = stored_sequence_width(&db).unwrap();
+    let n = args.hottest_keys;
+
+    // the versions of a key are adjacent so the current key is counted until the next one starts
+    let mut totals = vec![FamilyTotals::default(); families.len()];
+    // the keys of no family of the backend, by the prefix of the crate data they belong to
+    let mut other_totals = [
+        ("oplog", FamilyTotals::default()),
+        ("cell counts", FamilyTotals::default()),
+        ("audit log", FamilyTotals::default()),
+        ("meta", FamilyTotals::default()),
+    ];
+    let mut hottest = BinaryHeap::new();
+    let mut current: Option<(usize, Vec<u8>, u64)> = None;
+    let mut flush = |totals: &mut Vec<FamilyTotals>, current: Option<(usize, Vec<u8>, u64)>| {
+        if let Some((family, key, versions)) = current {
+            totals[family].keys += 1;
+            totals[family].versions += versions;
+            hottest.push(Reverse((versions, key, family)));
+            if hottest.len() > n {
+                hottest.pop();
+            }
+        }
+    };
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        let family = families.iter().position(|(_name, key_len, versioned)| {
+            key.len()
+                == if *versioned {
+                    key_len + width.encoded_len()
+                } else {
+                    *key_len
+                }
+        });
+        let family = match family {
+            Some(family) => family,
+            None => {
+                let other = [OPLOG_KEY, CELL_COUNTS_KEY, AUDIT_LOG_KEY]
+                    .iter()
+                    .position(|prefix| key.starts_with(prefix))
+                    .unwrap_or(other_totals.len() - 1);
+                other_totals[other].1.record(&key, &value);
+                other_totals[other].1.keys += 1;
+                other_totals[other].1.versions += 1;
+                continue;
+            }
+        };
+        totals[family].record(&key, &value);
+        let base_key = &key[..families[family].1];
+        match &mut current {
+            Some((_, current_key, versions)) if current_key.as_slice() == base_key => {
+                *versions += 1
+            }
+            _ => flush(&mut totals, current.replace((family, base_key.to_vec(), 1))),
+        }
+    }
+    flush(&mut totals, current);
+
+    let named_totals = families
+        .iter()
+        .map(|(name, _key_len, _versioned)| *name)
+        .zip(totals)
+        .chain(other_totals);
+    let mut total = FamilyTotals::default();
+    for (name, totals) in named_totals {
+        println!("{}: {}", name, totals);
+        total.keys += totals.keys;
+        total.versions += totals.versions;
+        total.key_bytes += totals.key_bytes;
+        total.value_bytes += totals.value_bytes;
+    }
+    println!("total: {}", total);
+    if let Some((sequence, counts)) = CellCounter::<_, WriteOptions>::new(&db).latest().unwrap() {
+        println!("{} at sequence {}", counts, sequence);
+    }
+    let commitments = Builder::from_name(&args.backend)
+        .unwrap()
+        .commitments::<_, WriteOptions>(&db, 0..u64::MAX)
+        .unwrap();
+    for commitment in commitments.rev().take(ROOT_HISTORY) {
+        let (sequence, digest) = commitment.unwrap();
+        let digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("commitment at sequence {}: {}", sequence, digest);
+    }
+    for Reverse((versions, key, family)) in hottest.into_sorted_vec() {
+        let key: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        println!(
+            "hot key {} ({}): {} versions",
+            key, families[family].0, versions
+        );
+    }
+}
+
+// the length of a record of a `build-tree` leaves file, key (32) || value hash (32)
+const LEAF_RECORD_LEN: usize = 64;
+
+// builds the tree of a backend in a new database from a file of pre-hashed leaves, optionally generated first with
+// random leaves, and reports the construction throughput, the reading of the file isn't measured
+fn build_tree(args: &BuildTreeArgs) {
+    let leaves_path = &args.leaves_file;
+    if let Some(count) = args.generate_leaves {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut bytes = vec![0u8; count * LEAF_RECORD_LEN];
+        rng.fill_bytes(&mut bytes);
+        std::fs::write(leaves_path, bytes).unwrap();
+    }
+    let bytes = std::fs::read(leaves_path).unwrap();
+    if bytes.len() % LEAF_RECORD_LEN != 0 {
+        println!(
+            "the leaves file must hold {} bytes records, key (32) || value hash (32)",
+            LEAF_RECORD_LEN
+        );
+        std::process::exit(1);
+    }
+    let leaves: Vec<([u8; 32], [u8; 32])> = bytes
+        .chunks_exact(LEAF_RECORD_LEN)
+        .map(|chunk| {
+            (
+                chunk[..32].try_into().expect("checked length"),
+                chunk[32..].try_into().expect("checked length"),
+            )
+        })
+        .collect();
+    let count = leaves.len();
+    if count == 0 {
+        println!("the leaves file holds no leaves");
+        std::process::exit(1);
+    }
+
+    let db = DB::open_default(&args.db_path).unwrap();
+    let now = Instant::now();
+    let root = match args.backend.as_str() {
+        "smt" => smt::bulk::build_tree::<_, WriteOptions>(&db, leaves),
+        "mmr" => mmr::bulk::build_tree::<_, WriteOptions>(&db, leaves),
+        "smt_live" => smt_live::bulk::build_tree::<_, WriteOptions>(&db, leaves),
+        _ => {
+            println!("accumulator type must be smt | mmr | smt_live");
+            std::process::exit(1);
+        }
+    }
+    .unwrap();
+    let elapsed = now.elapsed();
+    let root: String = root.iter().map(|b| format!("{:02x}", b)).collect();
+    println!(
+        "built {} leaves in {} millis, {:.0} leaves/s, root: {}, {}",
+        count,
+        elapsed.as_millis(),
+        count as f64 / elapsed.as_secs_f64(),
+        root,
+        MemoryUsage::sample(&db)
+    );
+}
+
+// lists every stored version of the versioned key of an out_point, given as `<tx hash hex>:<index>`, or of a raw key
+// given as hex without its version, e.g. a branch key
+fn history(args: &HistoryArgs) {
+    let db = DB::open_default(&args.db_path).unwrap();
+    let width = stored_sequence_width(&db).unwrap();
+    let key = match args.key.split_once(':') {
+        Some((tx_hash, index)) => {
+            let out_point = OutPoint {
+                tx_hash: parse_hex(tx_hash)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .expect("tx hash must be 32 bytes hex"),
+                index: index.parse().expect("index must be a number"),
+            };
+            match args.backend.as_str() {
+                "smt" => smt::leaf_key(&db, &out_point).unwrap().as_slice().to_vec(),
+                "mmr" => mmr::element_key(&out_point),
+                "smt_live" => out_point.hash().to_vec(),
+                _ => {
+                    println!("accumulator type must be smt | mmr | smt_live");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => parse_hex(&args.key).expect("raw key must be hex"),
+    };
+
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    println!("key {}", hex(&key));
+    let versions = key_versions(&db, &key, width);
+    if versions.is_empty() {
+        println!("no stored versions");
+    }
+    // the stores read the latest version at or below the sequence they are opened at
+    for (sequence, value) in versions {
+        if value.is_empty() {
+            println!("sequence {}: removed", sequence);
+        } else {
+            println!(
+                "sequence {}: {} bytes {}",
+                sequence,
+                value.len(),
+                hex(&value)
+            );
+        }
+    }
+}
+
+// an interactive prompt over a benchmark database, opened read-only so it can run next to a benchmark writing it, every
+// query is answered by an accumulator opened at a committed sequence or by raw reads
+fn inspect(args: &InspectArgs) {
+    let db = ReadOnly(ReadOnlyDB::open(&Options::default(), &args.db_path).unwrap());
+    let width = stored_sequence_width(&db).unwrap();
+    let backend = Builder::from_name(&args.backend).expect("checked backend");
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    println!("{}", INSPECT_HELP);
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let committed = backend.committed_sequences::<_, WriteOptions>(&db).unwrap();
+        // the latest committed sequence by default
+        let sequence = |sequence: Option<u64>| -> Result<u64, String> {
+            let sequence = match sequence {
+                Some(sequence) => sequence,
+                None => committed
+                    .end
+                    .checked_sub(1)
+                    .ok_or("no committed sequence")?,
+            };
+            if committed.contains(&sequence) {
+                Ok(sequence)
+            } else {
+                Err(format!("sequence {} is not committed", sequence))
+            }
+        };
+        let commitment = |sequence: u64| -> Result<AnyCommitment, String> {
+            backend
+                .clone()
+                .sequence(sequence)
+                .build::<_, WriteOptions>(&db)
+                .and_then(|accumulator| accumulator.backend_commitment())
+                .map_err(|e| format!("{:?}", e))
+        };
+        let command = match parse_command(&line) {
+            Ok(Command::Quit) => break,
+            command => command,
+        };
+        let result = command.and_then(|command| match command {
+            Command::Empty | Command::Quit => Ok(()),
+            Command::Help => {
+                println!("{}", INSPECT_HELP);
+                Ok(())
+            }
+            Command::Sequences => {
+                println!("committed sequences: {:?}", committed);
+                Ok(())
+            }
+            Command::Root(at) => sequence(at).and_then(commitment).map(|commitment| {
+                println!("sequence {}", commitment.sequence());
+                println!("digest {}", hex(&commitment.commitment_digest()));
+                println!("commitment {}", hex(&commitment.to_bytes()));
+            }),
+            Command::Status(out_point, at) => {
+                let sequence = sequence(at)?;
+                let status = backend
+                    .clone()
+                    .sequence(sequence)
+                    .build::<_, WriteOptions>(&db)
+                    .and_then(|accumulator| accumulator.cell_status(&out_point))
+                    .map_err(|e| format!("{:?}", e))?;
+                match status {
+                    Some(status) => println!(
+                        "{} at sequence {}: {}",
+                        if status.is_live() { "live" } else { "dead" },
+                        sequence,
+                        hex(&status.block_numbers)
+                    ),
+                    None => println!("unknown at sequence {}", sequence),
+                }
+                Ok(())
+            }
+            Command::Proof(out_point, at) => {
+                let commitment = sequence(at).and_then(commitment)?;
+                let (proof, valid) =
+                    inspect_proof(&db, commitment, out_point).map_err(|e| format!("{:?}", e))?;
+                println!(
+                    "{} bytes, {}: {}",
+                    proof.len(),
+                    if valid { "valid" } else { "INVALID" },
+                    hex(&proof)
+                );
+                Ok(())
+            }
+            Command::Key(key) => {
+                let versions = key_versions(&db, &key, width);
+                if versions.is_empty() {
+                    println!("no stored versions");
+                }
+                for (sequence, value) in versions {
+                    println!("sequence {}: {}", sequence, hex(&value));
+                }
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            println!("error: {}", e);
+        }
+    }
+}
+
+// the serialized wallet proof of a cell at the sequence of a commitment, and whether it verifies against it
+fn inspect_proof<D: traits::ReadStore>(
+    db: &D,
+    commitment: AnyCommitment,
+    out_point: OutPoint,
+) -> Result<(Vec<u8>, bool), AccumulatorError> {
+    let sequence = commitment.sequence();
+    let out_points = vec![out_point];
+    Ok(match commitment {
+        AnyCommitment::Smt(commitment) => {
+            let proof = SMTAccumulator::<_, ()>::wallet_proof(db, out_points, sequence)?;
+            (proof.to_bytes(), proof.verify(&commitment)?)
+        }
+        AnyCommitment::SmtLive(commitment) => {
+            let proof = SMTLiveAccumulator::<_, ()>::wallet_proof(db, out_points, sequence)?;
+            (proof.to_bytes(), proof.verify(&commitment)?)
+        }
+        AnyCommitment::Mmr(commitment) => {
+            let proof = MMRAccumulator::<_, ()>::wallet_proof(db, out_points, sequence)?;
+            (proof.to_bytes(), proof.verify(&commitment)?)
+        }
+        AnyCommitment::SmtPresence(commitment) => {
+            let proof = PresenceAccumulator::<_, ()>::wallet_proof(db, out_points, sequence)?;
+            (proof.to_bytes(), proof.verify(&commitment)?)
+        }
+        // the proofs of the tiered smt read its hot database too
+        AnyCommitment::SmtTiered(_) => {
+            return Err(AccumulatorError::InternalError(
+                "the tiered smt is proved over its hot and cold databases".to_string(),
+            ))
+        }
+        AnyCommitment::MmrPositioned(_) => {
+            return Err(AccumulatorError::InternalError(
+                "the positioned mmr is proved over its mmr and position index databases"
+                    .to_string(),
+            ))
+        }
+    })
+}
+
+// measures the throughput of serving single cell proofs at a sequence, generated on every request and then read from a proof
+// cache precomputed for the same cells, the cells are regenerated from the workload so it must be a benchmark database
+fn proof_cache(args: &ProofCacheArgs) {
+    let accumulator_type = args.backend.as_str();
+    let db = DB::open_default(&args.db_path).unwrap();
+    let sequence = args.sequence;
+    let cells = args.cells;
+
+    // the cells created up to the sequence, sampled evenly, the consumed ones are not served
+    let mut workload = Workload::new(0, false);
+    let created: Vec<OutPoint> = (0..=sequence)
+        .flat_map(|i| workload.next_block(i).added)
+        .collect();
+    let step = (created.len() / cells).max(1);
+    let out_points: Vec<OutPoint> = created.into_iter().step_by(step).take(cells).collect();
+
+    let snapshot = db.snapshot();
+    let prove =
+        |out_point: &OutPoint| -> Result<Vec<u8>, AccumulatorError> {
+            let out_points = vec![out_point.clone()];
+            Ok(match accumulator_type {
+                "smt" => SMTAccumulator::<_, ()>::wallet_proof(&snapshot, out_points, sequence)?
+                    .to_bytes(),
+                "mmr" => MMRAccumulator::<_, ()>::wallet_proof(&snapshot, out_points, sequence)?
+                    .to_bytes(),
+                "smt_live" => {
+                    SMTLiveAccumulator::<_, ()>::wallet_proof(&snapshot, out_points, sequence)?
+                        .to_bytes()
+                }
+                _ => {
+                    println!("accumulator type must be smt | mmr | smt_live");
+                    std::process::exit(1);
+                }
+            })
+        };
+    let report = |phase: &str, served: u64, now: Instant| {
+        let millis = now.elapsed().as_millis();
+        println!(
+            "{} {} proofs in {} millis, {} proofs/s",
+            phase,
+            served,
+            millis,
+            served as u128 * 1000 / millis.max(1)
+        );
+    };
+
+    let now = Instant::now();
+    let mut served = 0;
+    for out_point in &out_points {
+        match prove(out_point) {
+            Ok(_proof) => served += 1,
+            Err(AccumulatorError::ElementNotFound(_)) => {}
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+    report("generated", served, now);
+
+    // the store keys read by every proof, the keys read to open the accumulator are not counted
+    let key_stats = Arc::new(Mutex::new(KeyStats::default()));
+    let accumulator = Builder::from_name(accumulator_type)
+        .expect("checked backend")
+        .sequence(sequence)
+        .key_tracer(KeyTracer::new(Arc::clone(&key_stats)))
+        .build::<_, WriteOptions>(&db)
+        .unwrap();
+    let commitment = accumulator.backend_commitment().unwrap();
+    let (mut proved, mut keys_read, mut max_keys_read, mut reads) = (0, 0, 0, 0);
+    for out_point in &out_points {
+        *key_stats.lock().unwrap() = KeyStats::default();
+        match accumulator.proof(commitment.clone(), vec![out_point.clone()]) {
+            Ok(_proof) => {
+                let stats = key_stats.lock().unwrap();
+                proved += 1;
+                keys_read += stats.keys_read();
+                max_keys_read = max_keys_read.max(stats.keys_read());
+                reads += stats.reads();
+            }
+            Err(AccumulatorError::ElementNotFound(_)) => {}
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+    println!(
+        "keys read per proof: {} distinct on average, {} at most, {} reads on average",
+        keys_read / proved.max(1),
+        max_keys_read,
+        reads / proved.max(1) as u64
+    );
+
+    // the cache is written to its own database, so that the benchmark database keeps its measured size
+    let cache_db = DB::open_default(with_suffix(&args.db_path, ".proof-cache")).unwrap();
+    let now = Instant::now();
+    let mut cache = ProofCache::<_, WriteOptions>::new(&cache_db);
+    let stored = cache
+        .precompute_proofs(sequence, out_points.clone(), &prove)
+        .unwrap();
+    report("precomputed", stored, now);
+
+    let now = Instant::now();
+    let mut served = 0;
+    for out_point in &out_points {
+        if cache.get(sequence, out_point).unwrap().is_some() {
+            served += 1;
+        }
+    }
+    report("served cached", served, now);
+}
+
+// serves status, proof and commitment queries over gRPC from a benchmark database, see `dca_bench::grpc`
+#[cfg(feature = "grpc")]
+fn serve(args: &ServeArgs) {
+    let backend = Builder::from_name(&args.backend).unwrap_or_else(|| {
+        println!("accumulator type must be smt | mmr | smt_live");
+        std::process::exit(1);
+    });
+    let addr = args.listen_addr;
+    let db = Arc::new(DB::open_default(&args.db_path).unwrap());
+    println!("serving {} on {}", args.backend, addr);
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(grpc::serve(AccumulatorService::new(backend, db), addr))
+        .unwrap();
+}
+
+// measures the latency and size of the proofs that random out_points, never added, are absent at the latest committed
+// sequence, the query of a wallet starting cold and checking whether its cells were spent
+fn absence_proofs(args: &AbsenceProofsArgs) {
+    let accumulator_type = args.backend.as_str();
+    let builder = match Builder::from_name(accumulator_type) {
+        Some(Builder::Mmr(_)) | None => {
+            println!("accumulator type must be smt | smt_live, a mmr can't prove an absence");
+            std::process::exit(1);
+        }
+        Some(builder) => builder,
+    };
+    let db = DB::open_default(&args.db_path).unwrap();
+    let proofs = args.proofs;
+    let sequence = match builder
+        .committed_sequences::<_, WriteOptions>(&db)
+        .unwrap()
+        .end
+        .checked_sub(1)
+    {
+        Some(sequence) => sequence,
+        None => {
+            println!("the database has no committed sequence");
+            std::process::exit(1);
+        }
+    };
+
+    // random tx hashes, a collision with a benchmark cell is negligible
+    let mut rng = ChaChaRng::seed_from_u64(u64::MAX);
+    let out_points: Vec<OutPoint> = (0..proofs)
+        .map(|_| {
+            let mut tx_hash = [0u8; 32];
+            rng.fill_bytes(&mut tx_hash);
+            OutPoint { tx_hash, index: 0 }
+        })
+        .collect();
+
+    let snapshot = db.snapshot();
+    let mut prove_micros = Vec::with_capacity(proofs);
+    let mut verify_micros = Vec::with_capacity(proofs);
+    let mut sizes = Vec::with_capacity(proofs);
+    macro_rules! measure {
+        ($accumulator: ty) => {{
+            let commitment = <$accumulator>::new_with_sequence(&snapshot, sequence)
+                .unwrap()
+                .commitment();
+            for out_point in &out_points {
+                let now = Instant::now();
+                let proof =
+                    <$accumulator>::absence_proof(&snapshot, vec![out_point.clone()], sequence)
+                        .unwrap();
+                prove_micros.push(now.elapsed().as_micros() as u64);
+                sizes.push(proof.to_bytes().len() as u64);
+                let now = Instant::now();
+                assert!(proof.verify(&commitment).unwrap());
+                verify_micros.push(now.elapsed().as_micros() as u64);
+            }
+        }};
+    }
+    match builder {
+        Builder::SmtLive(_) => measure!(SMTLiveAccumulator<_, ()>),
+        Builder::SmtPresence(_) => measure!(PresenceAccumulator<_, ()>),
+        _ => measure!(SMTAccumulator<_, ()>),
+    }
+
+    println!(
+        "{} absence proofs at sequence {}: prove p50 {} micros, p99 {} micros, verify p50 {} micros, p99 {} micros",
+        proofs,
+        sequence,
+        percentile(&mut prove_micros, 50),
+        percentile(&mut prove_micros, 99),
+        percentile(&mut verify_micros, 50),
+        percentile(&mut verify_micros, 99)
+    );
+    println!(
+        "proof size: mean {} bytes, max {} bytes",
+        sizes.iter().sum::<u64>() / (proofs as u64).max(1),
+        sizes.iter().max().copied().unwrap_or(0)
+    );
+}
+
+// measures the throughput of verifying single cell proofs of the cells live at the latest committed sequence, one by one
+// and in batches against one commitment, the work of a stateless validator checking the inputs of a block, the cells are
+// regenerated from the workload so it must be a benchmark database
+fn verify_batch(args: &VerifyBatchArgs) {
+    let builder = Builder::from_name(&args.backend).unwrap_or_else(|| {
+        println!("accumulator type must be smt | mmr | smt_live");
+        std::process::exit(1);
+    });
+    if args.batch_size == 0 || args.chunk_size == Some(0) {
+        println!("batch size and chunk size must be positive");
+        std::process::exit(1);
+    }
+    let db = DB::open_default(&args.db_path).unwrap();
+    let sequence = match builder
+        .committed_sequences::<_, WriteOptions>(&db)
+        .unwrap()
+        .end
+        .checked_sub(1)
+    {
+        Some(sequence) => sequence,
+        None => {
+            println!("the database has no committed sequence");
+            std::process::exit(1);
+        }
+    };
+    let accumulator = builder
+        .clone()
+        .sequence(sequence)
+        .build::<_, WriteOptions>(&db)
+        .unwrap();
+    let commitment = accumulator.backend_commitment().unwrap();
+
+    // the latest created cells which are still live
+    let mut workload = Workload::new(0, false);
+    let created: Vec<OutPoint> = (0..=sequence)
+        .flat_map(|i| workload.next_block(i).added)
+        .collect();
+    let mut cells: Vec<(OutPoint, CellStatus)> = Vec::with_capacity(args.proofs);
+    for out_point in created.into_iter().rev() {
+        if cells.len() == args.proofs {
+            break;
+        }
+        match accumulator.cell_status(&out_point).unwrap() {
+            Some(status) if status.is_live() => cells.push((out_point, status)),
+            _ => {}
+        }
+    }
+    // a proof is consumed by its verification, so every measure verifies its own copies
+    let prove = || -> Vec<(Vec<(OutPoint, CellStatus)>, AnyProof)> {
+        cells
+            .iter()
+            .map(|cell| {
+                let proof = accumulator
+                    .proof(commitment.clone(), vec![cell.0.clone()])
+                    .unwrap();
+                (vec![cell.clone()], proof)
+            })
+            .collect()
+    };
+    let report = |phase: String, now: Instant| {
+        let millis = now.elapsed().as_millis();
         println!(
-            "Usage: {} <smt|mmr|smt_live> <path-to-rocksdb> <start-block-number> <total-blocks>",
-            args.next().unwrap()
+            "verified {} cells {} in {} millis, {} cells/s",
+            cells.len(),
+            phase,
+            millis,
+            cells.len() as u128 * 1000 / millis.max(1)
         );
+    };
+
+    let proofs = prove();
+    let now = Instant::now();
+    for (elements, proof) in proofs {
+        assert!(proof.verify(commitment.clone(), elements).unwrap());
+    }
+    report("one by one".to_string(), now);
+
+    // the same single cell proofs handed to `Proof::verify_batch` in batches, which the smt backends override
+    let mut proofs = prove();
+    let now = Instant::now();
+    while !proofs.is_empty() {
+        let batch = proofs.drain(..args.batch_size.min(proofs.len())).collect();
+        assert!(AnyProof::verify_batch(commitment.clone(), batch).unwrap());
+    }
+    report(
+        format!("with verify_batch of {} proofs", args.batch_size),
+        now,
+    );
+
+    // a single proof of a batch of cells hashes their shared siblings and the root once
+    let batches: Vec<(Vec<(OutPoint, CellStatus)>, AnyProof)> = cells
+        .chunks(args.batch_size)
+        .map(|batch| {
+            let out_points = batch
+                .iter()
+                .map(|(out_point, _)| out_point.clone())
+                .collect();
+            let proof = accumulator.proof(commitment.clone(), out_points).unwrap();
+            (batch.to_vec(), proof)
+        })
+        .collect();
+    let now = Instant::now();
+    for (elements, proof) in batches {
+        assert!(proof.verify(commitment.clone(), elements).unwrap());
+    }
+    report(format!("in proofs of {} cells", args.batch_size), now);
+
+    // the witness of a block consuming all the cells, as a single proof and in chunks verified as they arrive
+    if let Some(chunk_size) = args.chunk_size {
+        let out_points: Vec<OutPoint> = cells
+            .iter()
+            .map(|(out_point, _)| out_point.clone())
+            .collect();
+        for chunk_size in [out_points.len().max(1), chunk_size] {
+            let prover = ChunkedProver::<_, WriteOptions>::new(
+                builder.clone(),
+                &db,
+                out_points.clone(),
+                sequence,
+                chunk_size,
+            );
+            let header = prover.header();
+            let chunks: Vec<ProofChunk> = prover.map(|chunk| chunk.unwrap()).collect();
+            let bytes: usize = chunks.iter().map(|chunk| chunk.proof.len()).sum();
+            let now = Instant::now();
+            let mut verifier = IncrementalVerifier::new(commitment.clone(), header).unwrap();
+            for chunk in chunks.iter() {
+                verifier.feed(chunk).unwrap();
+            }
+            let peak_bytes = verifier.peak_bytes();
+            verifier.finalize().unwrap();
+            println!(
+                "witness of {} cells in {} chunks of {} cells: {} bytes, verified in {} millis, peak verifier memory {} bytes",
+                header.cells,
+                header.chunks,
+                chunk_size,
+                bytes,
+                now.elapsed().as_millis(),
+                peak_bytes
+            );
+        }
+    }
+}
+
+// measures the latency of single cell proofs generated by readers opened `lag` sequences behind the latest committed
+// one, for every lag: a versioned get seeks back from the latest version of a key, so it may slow down as more versions
+// are newer than the read sequence. The cells are regenerated from the workload so it must be a benchmark database
+fn proof_lag(args: &ProofLagArgs) {
+    let builder = Builder::from_name(&args.backend).unwrap_or_else(|| {
+        println!("accumulator type must be smt | mmr | smt_live");
         std::process::exit(1);
+    });
+    let db = DB::open_default(&args.db_path).unwrap();
+    let head = match builder
+        .committed_sequences::<_, WriteOptions>(&db)
+        .unwrap()
+        .end
+        .checked_sub(1)
+    {
+        Some(sequence) => sequence,
+        None => {
+            println!("the database has no committed sequence");
+            std::process::exit(1);
+        }
     };
 
-    let accumulator_type = args.nth(1).unwrap();
+    let mut workload = Workload::new(0, false);
+    let blocks: Vec<BlockOps> = (0..=head).map(|i| workload.next_block(i)).collect();
+    let mut lags = args.lags.clone();
+    lags.sort_unstable();
+    lags.dedup();
+    let (lags, skipped): (Vec<u64>, Vec<u64>) = lags.into_iter().partition(|lag| *lag <= head);
+    for lag in skipped {
+        println!("lag {}: skipped, the head is sequence {}", lag, head);
+    }
+    let oldest = match lags.last() {
+        Some(lag) => head - lag,
+        None => return,
+    };
+    // the same cells at every lag, live from the oldest sequence to the head, drawn from the workload so that no lookup
+    // warms the block cache before the timed proofs
+    let cells = sample_live_cells(&blocks, oldest as usize, args.proofs, u64::MAX);
+    let mut baseline = None;
+    for lag in lags {
+        let sequence = head - lag;
+        let accumulator = builder
+            .clone()
+            .sequence(sequence)
+            .build::<_, WriteOptions>(&db)
+            .unwrap();
+        let commitment = accumulator.backend_commitment().unwrap();
+        let mut micros = Vec::with_capacity(cells.len());
+        let proofs: Vec<AnyProof> = cells
+            .iter()
+            .map(|out_point| {
+                let now = Instant::now();
+                let proof = accumulator
+                    .proof(commitment.clone(), vec![out_point.clone()])
+                    .unwrap();
+                micros.push(now.elapsed().as_micros() as u64);
+                proof
+            })
+            .collect();
+        // verified once every proof of the lag is timed
+        for (out_point, proof) in cells.iter().zip(proofs) {
+            let status = accumulator.cell_status(out_point).unwrap();
+            let status = status.filter(|status| status.is_live()).unwrap_or_else(|| {
+                panic!(
+                    "sampled cell {:?} is not live at sequence {}",
+                    out_point, sequence
+                )
+            });
+            assert!(proof
+                .verify(commitment.clone(), vec![(out_point.clone(), status)])
+                .unwrap());
+        }
+
+        let p50 = percentile(&mut micros, 50);
+        let (baseline_lag, baseline_p50) = *baseline.get_or_insert((lag, p50));
+        println!(
+            "lag {} (sequence {}): {} proofs, p50 {} micros, p99 {} micros, mean {} micros, p50 x{:.2} of lag {}",
+            lag,
+            sequence,
+            micros.len(),
+            p50,
+            percentile(&mut micros, 99),
+            micros.iter().sum::<u64>() / (micros.len() as u64).max(1),
+            p50 as f64 / baseline_p50.max(1) as f64,
+            baseline_lag
+        );
+    }
+}
+
+// exports the tree of a smt database at a sequence, the latest committed one by default, to flat files and compares the
+// latency of single cell proofs served from the memory mapped files with the ones read from RocksDB, the floor of proof
+// serving. The cells are regenerated from the workload so it must be a benchmark database
+fn export_flat(args: &ExportFlatArgs) {
+    let db = DB::open_default(&args.db_path).unwrap();
+    let latest = match Builder::from_name("smt")
+        .unwrap()
+        .committed_sequences::<_, WriteOptions>(&db)
+        .unwrap()
+        .end
+        .checked_sub(1)
+    {
+        Some(sequence) => sequence,
+        None => {
+            println!("the database has no committed sequence");
+            std::process::exit(1);
+        }
+    };
+    let sequence = args.sequence.unwrap_or(latest);
+    if sequence > latest {
+        println!(
+            "sequence {} isn't committed, the latest one is {}",
+            sequence, latest
+        );
+        std::process::exit(1);
+    }
+
+    let now = Instant::now();
+    let export = flat::export_flat(&db, sequence, &args.out_dir).unwrap();
+    println!(
+        "exported {} leaves and {} branch nodes at sequence {} in {} millis, {} bytes, the database is {} bytes",
+        export.leaves,
+        export.nodes,
+        sequence,
+        now.elapsed().as_millis(),
+        export.bytes,
+        dir_size(&args.db_path).unwrap()
+    );
+
+    let reader = FlatReader::open(&args.out_dir).unwrap();
+    let commitment = reader.commitment();
+    // random cells live at the sequence, a bounded number of draws since most cells may be consumed
+    let mut workload = Workload::new(0, false);
+    let created: Vec<OutPoint> = (0..=sequence)
+        .flat_map(|i| workload.next_block(i).added)
+        .collect();
+    let mut rng = ChaChaRng::seed_from_u64(u64::MAX);
+    let mut cells = Vec::with_capacity(args.proofs);
+    for _ in 0..args.proofs * 10 {
+        if cells.len() == args.proofs {
+            break;
+        }
+        let out_point = &created[(rng.next_u64() % created.len() as u64) as usize];
+        if reader
+            .cell_status(out_point)
+            .map_or(false, |status| status.is_live())
+        {
+            cells.push(out_point.clone());
+        }
+    }
+
+    let snapshot = db.snapshot();
+    let mut flat_micros = Vec::with_capacity(cells.len());
+    let mut rocksdb_micros = Vec::with_capacity(cells.len());
+    for out_point in &cells {
+        let now = Instant::now();
+        let proof = reader.wallet_proof(vec![out_point.clone()]).unwrap();
+        flat_micros.push(now.elapsed().as_micros() as u64);
+        assert!(proof.verify(&commitment).unwrap());
+
+        let now = Instant::now();
+        SMTAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_point.clone()], sequence)
+            .unwrap();
+        rocksdb_micros.push(now.elapsed().as_micros() as u64);
+    }
+    println!(
+        "{} proofs: flat files p50 {} micros, p99 {} micros, rocksdb p50 {} micros, p99 {} micros",
+        cells.len(),
+        percentile(&mut flat_micros, 50),
+        percentile(&mut flat_micros, 99),
+        percentile(&mut rocksdb_micros, 50),
+        percentile(&mut rocksdb_micros, 99)
+    );
+}
+
+// measures a fast sync of a new node to a sequence of a benchmark database: the server exports the state in chunks, which
+// the client verifies and loads into an empty database, the time spent on each side is reported separately
+fn sync(args: &SyncArgs) {
+    let server = DB::open_default(&args.db_path).unwrap();
+    let client = DB::open_default(&args.client_db_path).unwrap();
+    let sequence = args.sequence;
+    let chunk_size = args.chunk_size;
+
+    let snapshot = server.snapshot();
+    let mut chunks = 0;
+    let mut bytes = 0;
+    let mut client_time = Duration::ZERO;
+    let now = Instant::now();
+    // the commitment is taken from the server here, a real client gets it from a trusted header
+    macro_rules! run_sync {
+        ($client:expr, $export:path) => {{
+            let mut sync_client = $client.unwrap();
+            let exported = $export(&snapshot, sequence, chunk_size, |chunk| {
+                chunks += 1;
+                bytes += chunk.size();
+                let now = Instant::now();
+                let result = sync_client.ingest(chunk);
+                client_time += now.elapsed();
+                result
+            })
+            .unwrap();
+            let now = Instant::now();
+            sync_client.finish().unwrap();
+            client_time += now.elapsed();
+            exported
+        }};
+    }
+    let exported = match args.backend.as_str() {
+        "smt" => run_sync!(
+            SMTSyncClient::<_, WriteOptions>::new(
+                &client,
+                SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence)
+                    .unwrap()
+                    .commitment()
+            ),
+            smt::sync::export_chunks
+        ),
+        "mmr" => run_sync!(
+            MMRSyncClient::<_, WriteOptions>::new(
+                &client,
+                MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence)
+                    .unwrap()
+                    .commitment()
+                    .unwrap()
+            ),
+            mmr::sync::export_chunks
+        ),
+        "smt_live" => run_sync!(
+            SMTLiveSyncClient::<_, WriteOptions>::new(
+                &client,
+                SMTLiveAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence)
+                    .unwrap()
+                    .commitment()
+            ),
+            smt_live::sync::export_chunks
+        ),
+        _ => {
+            println!("accumulator type must be smt | mmr | smt_live");
+            std::process::exit(1);
+        }
+    };
+    let total = now.elapsed();
+    println!(
+        "synced {} leaves at sequence {} in {} chunks, {} bytes",
+        exported, sequence, chunks, bytes
+    );
+    println!(
+        "server: {} millis, client: {} millis",
+        (total - client_time).as_millis(),
+        client_time.as_millis()
+    );
+}
+
+// copies a benchmark database into an empty one with its versioned keys re-encoded in another sequence width, and reports
+// the bytes of keys and values of both, the values are copied as is so the difference is the saving of the key encoding
+fn migrate_width(args: &MigrateWidthArgs) {
+    let is_versioned: fn(&[u8], SequenceWidth) -> bool = match args.backend.as_str() {
+        "smt" => smt::is_versioned_key,
+        "mmr" => mmr::is_versioned_key,
+        "smt_live" => smt_live::is_versioned_key,
+        _ => {
+            println!("accumulator type must be smt | mmr | smt_live");
+            std::process::exit(1);
+        }
+    };
+    let source = DB::open_default(&args.db_path).unwrap();
+    let target = DB::open_default(&args.target_db_path).unwrap();
+    let width = args.width;
+
+    let now = Instant::now();
+    let migrated =
+        migrate_sequence_width::<_, _, WriteOptions, _>(&source, &target, width, is_versioned)
+            .unwrap();
+    println!(
+        "re-encoded {} versioned keys in {} millis",
+        migrated,
+        now.elapsed().as_millis()
+    );
+
+    let mut totals = Vec::new();
+    for (name, db) in [("source", &source), ("target", &target)] {
+        let (keys, values) = db
+            .iterator(IteratorMode::Start)
+            .fold((0, 0), |(keys, values), (key, value)| {
+                (keys + key.len(), values + value.len())
+            });
+        println!(
+            "{}: {} bytes of keys, {} bytes of values",
+            name, keys, values
+        );
+        totals.push(keys + values);
+    }
+    println!(
+        "saved {} bytes, {:.1}%",
+        totals[0] as i64 - totals[1] as i64,
+        (totals[0] as f64 - totals[1] as f64) * 100.0 / totals[0].max(1) as f64
+    );
+}
+
+// prints a markdown table comparing the metrics files written by `--metrics`, one row per file named by its file stem
+fn report(args: &ReportArgs) {
+    let runs: Vec<(String, RunMetrics)> = args
+        .metrics_files
+        .iter()
+        .map(|path| {
+            let content = std::fs::read_to_string(path).unwrap();
+            let label = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string());
+            (label, RunMetrics::parse(&content).unwrap())
+        })
+        .collect();
+    print!("{}", markdown_table(&runs));
+    println!();
+    print!("{}", stage_table(&runs));
+    println!();
+    print!("{}", cost_table(&runs, &args.tx_rates));
+}
+
+// the last lines of the output of a failed matrix run which are printed, the whole output is kept in its log
+#[cfg(feature = "serde")]
+const MATRIX_LOG_TAIL: usize = 20;
+
+// runs every combination of a matrix in a child process, with its database, metrics file and log of its output named by
+// the label of the combination in the output directory, then reports the metrics of the runs which succeeded
+#[cfg(feature = "serde")]
+fn run_matrix(args: &RunMatrixArgs) {
+    let matrix = Matrix::parse(&std::fs::read_to_string(&args.matrix).unwrap()).unwrap();
+    let output_dir = args.output_dir.as_path();
+    let combinations = matrix.combinations();
+    // every run starts from an empty database
+    for combination in &combinations {
+        if output_dir.join(combination.label()).exists() {
+            println!(
+                "{} already exists",
+                output_dir.join(combination.label()).display()
+            );
+            std::process::exit(1);
+        }
+    }
+    std::fs::create_dir_all(output_dir).unwrap();
+
+    let exe = std::env::current_exe().unwrap();
+    let mut runs = Vec::new();
+    let mut failed = Vec::new();
+    for (i, combination) in combinations.iter().enumerate() {
+        let label = combination.label();
+        let metrics_path = output_dir.join(format!("{}.metrics", label));
+        let log_path = output_dir.join(format!("{}.log", label));
+        let log = std::fs::File::create(&log_path).unwrap();
+        println!("[{}/{}] {}", i + 1, combinations.len(), label);
+        let status = Command::new(&exe)
+            .arg("bench")
+            .arg(&combination.backend)
+            .arg(output_dir.join(&label))
+            .args([matrix.start_block.to_string(), matrix.blocks.to_string()])
+            .args(&combination.flags)
+            .arg("--metrics")
+            .arg(&metrics_path)
+            .stdout(log.try_clone().unwrap())
+            .stderr(log)
+            .status()
+            .unwrap();
+        if !status.success() {
+            let output = std::fs::read_to_string(&log_path).unwrap_or_default();
+            let lines: Vec<&str> = output.lines().collect();
+            println!("{} failed: {}, see {}", label, status, log_path.display());
+            for line in &lines[lines.len().saturating_sub(MATRIX_LOG_TAIL)..] {
+                println!("  {}", line);
+            }
+            failed.push(label);
+            continue;
+        }
+        let content = std::fs::read_to_string(&metrics_path).unwrap();
+        runs.push((label, RunMetrics::parse(&content).unwrap()));
+    }
+
+    let table = format!(
+        "{}\n{}\n{}",
+        markdown_table(&runs),
+        stage_table(&runs),
+        cost_table(&runs, &args.tx_rates)
+    );
+    std::fs::write(output_dir.join("report.md"), &table).unwrap();
+    print!("{}", table);
+    if !failed.is_empty() {
+        println!("failed runs: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+}
+
+// prints the latest run of every backend and configuration of the results ledger against the previous one, and exits
+// with status 1 if a metric regressed by more than the threshold, 5% by default
+#[cfg(feature = "serde")]
+fn trend(args: &TrendArgs) {
+    let trends = trends(
+        &Ledger::new(&args.results_ledger).records().unwrap(),
+        args.threshold_percent,
+    );
+    for trend in &trends {
+        println!("{}", trend);
+    }
+    if trends.iter().any(|trend| !trend.regressions.is_empty()) {
+        std::process::exit(1);
+    }
+}
+
+// the short hash of the commit of the checkout the benchmark was built from, `unknown` outside of a git checkout, git
+// runs in the checkout whatever the working directory of the run
+#[cfg(feature = "serde")]
+fn current_commit() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn main() {
+    match Cli::parse_compat().command {
+        CliCommand::Bench(args) => bench(args),
+        CliCommand::Fsck(args) => fsck(&args),
+        CliCommand::CrashTest(args) => crash_test(&args),
+        CliCommand::TunePrefix(args) => tune_prefix(&args),
+        CliCommand::Stats(args) => stats(&args),
+        CliCommand::History(args) => history(&args),
+        CliCommand::Inspect(args) => inspect(&args),
+        CliCommand::BuildTree(args) => build_tree(&args),
+        CliCommand::UpdateChunks(args) => update_chunks(&args),
+        CliCommand::LiveCache(args) => live_cache(&args),
+        CliCommand::BranchStorage(args) => branch_storage(&args),
+        CliCommand::RangeCommit(args) => range_commit(&args),
+        CliCommand::Audit(args) => audit(&args),
+        CliCommand::HistoryAudit(args) => history_audit(&args),
+        CliCommand::DumpLayout(args) => dump_layout(&args),
+        CliCommand::ProofCache(args) => proof_cache(&args),
+        CliCommand::AbsenceProofs(args) => absence_proofs(&args),
+        CliCommand::VerifyBatch(args) => verify_batch(&args),
+        CliCommand::ProofLag(args) => proof_lag(&args),
+        CliCommand::ExportFlat(args) => export_flat(&args),
+        CliCommand::Sync(args) => sync(&args),
+        CliCommand::MigrateWidth(args) => migrate_width(&args),
+        CliCommand::Report(args) => report(&args),
+        #[cfg(feature = "serde")]
+        CliCommand::Trend(args) => trend(&args),
+        #[cfg(feature = "serde")]
+        CliCommand::RunMatrix(args) => run_matrix(&args),
+        #[cfg(feature = "grpc")]
+        CliCommand::Serve(args) => serve(&args),
+        CliCommand::Completions(args) => completions(&args),
+    }
+}
+
+// prints the completion script of a shell, e.g. `dca-bench completions bash > /etc/bash_completion.d/dca-bench`
+fn completions(args: &CompletionsArgs) {
+    clap_complete::generate(
+        args.shell,
+        &mut Cli::command(),
+        "dca-bench",
+        &mut std::io::stdout(),
+    );
+}
+
+// runs the benchmark blocks, the flags which don't apply to the backend or can't be combined are rejected first
+fn bench(args: BenchArgs) {
+    let versioned_keys = VersionedKeys::from_backend(&args.backend);
+    let options = BenchOptions {
+        #[cfg(feature = "serde")]
+        config_hash: config_hash(&args.config),
+        backend: args.backend,
+        db_path: args.db_path,
+        start_block_number: args.start_block_number,
+        total_blocks: args.total_blocks,
+        db_kind: args.db_kind,
+        strict: args.strict,
+        record_oplog: args.record_oplog,
+        audit_log: args.audit_log,
+        cell_counts: args.cell_counts,
+        soak: args.soak,
+        block_witness: args.block_witness || args.witness_alert.is_some(),
+        witness_alert: args.witness_alert,
+        overlap_commit: args.overlap_commit,
+        outputs_per_tx: args.outputs_per_tx,
+        spikes: args.spikes,
+        expiry_sweep: args.expiry_sweep,
+        workload_model: args.workload_model,
+        level_writes: args.level_writes,
+        speculative: args.speculative,
+        hot_keys: args.hot_keys,
+        leaf_encoding: args.leaf_encoding,
+        key_derivation: args.key_derivation,
+        branch_storage: args.branch_storage,
+        sequence_width: args.sequence_width,
+        element_integrity: args.element_integrity,
+        flush_mode: args.flush_mode,
+        flush_metrics: args.flush_metrics,
+        gc_retention: args.gc_retention,
+        script_index: args.script_index,
+        target_block_interval: args.target_block_interval,
+        report_every: args.report_every,
+        commit_sweep: args.commit_sweep,
+        commit_retry: args.commit_retry,
+        metrics: args.metrics,
+        prometheus: args.prometheus,
+        #[cfg(feature = "serde")]
+        results: args.results,
+        #[cfg(not(feature = "serde"))]
+        results: None,
+        publish: args.publish,
+        #[cfg(feature = "profiling")]
+        profile: args.profile,
+        live_cache: args.live_cache,
+        shards: args.shards,
+        hot_tree: args.hot_tree,
+        sequence_source: args.sequence_source,
+        db_options: DbOptions {
+            prefix_len: args.prefix_len,
+            bloom_bits_per_key: args.bloom_bits_per_key,
+            min_blob_size: args.min_blob_size,
+            block_cache_size: args.block_cache_size,
+            write_buffer_size: args.write_buffer_size,
+            max_write_buffer_number: args.max_write_buffer_number,
+            version_expiry: args
+                .expire_retention
+                .zip(versioned_keys)
+                .map(|(retention, keys)| VersionExpiry::new(keys, retention)),
+        },
+    };
+
+    if options.speculative
+        && (options.db_kind != "optimistic"
+            || options.record_oplog
+            || options.cell_counts
+            || options.soak.is_some()
+            || options.block_witness
+            || options.overlap_commit
+            || options.script_index.is_some()
+            || options.metrics.is_some()
+            || options.prometheus.is_some()
+            || options.results.is_some()
+            || options.target_block_interval.is_some())
+    {
+        println!("--speculative only supports the optimistic db kind, without --oplog, --cell-counts, --soak, --block-witness, --overlap-commit, --script-index, --metrics, --prometheus, --results and --target-block-interval");
+        std::process::exit(1);
+    }
+
+    // the audit is re-derived from the oplog
+    if options.audit_log && !options.record_oplog {
+        println!("--audit-log only supports --oplog");
+        std::process::exit(1);
+    }
+
+    // the index XORs the out_points in, a non-strict workload may consume a dead cell and toggle it back in
+    if options.script_index.is_some() && !options.strict {
+        println!("--script-index only supports --strict");
+        std::process::exit(1);
+    }
+
+    // the writes of a speculative block are rolled back, the cache would keep them
+    if options.live_cache
+        && (options.backend != "smt" || options.speculative || options.hot_tree.is_some())
+    {
+        println!(
+            "--live-cache only supports the smt accumulator, without --speculative and --hot-tree"
+        );
+        std::process::exit(1);
+    }
+
+    // the swept cells aren't in the operations of the blocks, and the speculative loop doesn't sweep
+    if options.expiry_sweep.is_some()
+        && (options.backend == "mmr"
+            || options.backend == "smt_presence"
+            || options.key_derivation == Some(smt::KeyDerivation::Prefixed)
+            || options.speculative
+            || options.spikes.is_some()
+            || options.record_oplog
+            || options.cell_counts)
+    {
+        println!("--expiry-sweep only supports the smt and smt_live accumulators with the hashed key derivation, without --speculative, --spikes, --oplog and --cell-counts");
+        std::process::exit(1);
+    }
+
+    // the model draws its own transactions and cells
+    if options.workload_model.is_some()
+        && (options.outputs_per_tx != 1
+            || options.spikes.is_some()
+            || options.expiry_sweep.is_some())
+    {
+        println!(
+            "--workload-model can't be combined with --outputs-per-tx, --spikes and --expiry-sweep"
+        );
+        std::process::exit(1);
+    }
+
+    // the plain db kind has no transaction, the other loops commit on their own schedule
+    if !options.commit_sweep.is_empty()
+        && (options.db_kind == "plain"
+            || options.speculative
+            || !options.shards.is_empty()
+            || options.hot_tree.is_some()
+            || options.total_blocks < options.commit_sweep.len() as u64)
+    {
+        println!("--commit-sweep needs a block per segment and doesn't support the plain db kind, --speculative, --shards and --hot-tree");
+        std::process::exit(1);
+    }
+
+    // a non-strict workload may consume a dead cell, which has no witness
+    if options.block_witness && !options.strict {
+        println!("--block-witness only supports --strict");
+        std::process::exit(1);
+    }
+
+    // the blocks of the speculative loop aren't profiled
+    #[cfg(feature = "profiling")]
+    if options.profile.is_some() && options.speculative {
+        println!("--profile doesn't support --speculative");
+        std::process::exit(1);
+    }
+
+    let accumulator_type = options.backend.as_str();
+    // after a garbage collection a cell consumed twice by a non-strict workload may be unknown to the element index
+    if options.gc_retention.is_some()
+        && (accumulator_type != "mmr" || !options.strict || options.speculative)
+    {
+        println!(
+            "--gc-retention only supports the mmr accumulator with --strict, without --speculative"
+        );
+        std::process::exit(1);
+    }
+    // the horizon only moves with the commits of the plain benchmark loop, and a delta leaf is rebuilt from its two
+    // versions, the older one can't expire
+    if args.expire_retention.is_some()
+        && (options.db_options.version_expiry.is_none()
+            || options.speculative
+            || options.gc_retention.is_some()
+            || options.leaf_encoding == Some(smt::LeafEncoding::Delta))
+    {
+        println!("--expire-retention only supports the mmr, smt and smt_live accumulators, without --speculative, --gc-retention and --leaf-encoding delta");
+        std::process::exit(1);
+    }
+    if (options.flush_mode.is_some() || options.flush_metrics)
+        && (accumulator_type != "mmr" || options.speculative)
+    {
+        println!("--mmr-flush and --flush-metrics only support the mmr accumulator, without --speculative");
+        std::process::exit(1);
+    }
+    // the writes are buffered until a transaction is committed
+    if options.flush_mode == Some(FlushMode::Transaction) && options.db_kind == "plain" {
+        println!("--mmr-flush transaction doesn't support the plain db kind");
+        std::process::exit(1);
+    }
+    // the width must be set first, on a database which is still empty
+    if let Some(width) = options.sequence_width {
+        let db = DB::open(&options.db_options.to_rocksdb_options(), &options.db_path).unwrap();
+        set_sequence_width::<_, WriteOptions>(&db, width).unwrap();
+    }
+    if options.element_integrity {
+        if accumulator_type != "mmr" {
+            println!("--element-integrity only supports the mmr accumulator");
+            std::process::exit(1);
+        }
+        let db = DB::open(&options.db_options.to_rocksdb_options(), &options.db_path).unwrap();
+        mmr::set_element_integrity::<_, WriteOptions>(&db, true).unwrap();
+    }
+    if let Some(encoding) = options.leaf_encoding {
+        if accumulator_type != "smt" {
+            println!("--leaf-encoding only supports the smt accumulator");
+            std::process::exit(1);
+        }
+        let db = DB::open(&options.db_options.to_rocksdb_options(), &options.db_path).unwrap();
+        smt::set_leaf_encoding::<_, WriteOptions>(&db, encoding).unwrap();
+    }
+    if let Some(derivation) = options.key_derivation {
+        if accumulator_type != "smt" {
+            println!("--key-derivation only supports the smt accumulator");
+            std::process::exit(1);
+        }
+        let db = DB::open(&options.db_options.to_rocksdb_options(), &options.db_path).unwrap();
+        smt::set_key_derivation::<_, WriteOptions>(&db, derivation).unwrap();
+    }
+    if let Some(storage) = options.branch_storage {
+        if accumulator_type != "smt" {
+            println!("--branch-storage only supports the smt accumulator");
+            std::process::exit(1);
+        }
+        // the refcount of a shared node is read and written back, in the transaction of the versions referring to it
+        if storage == smt::BranchStorage::Shared && options.db_kind == "plain" {
+            println!("--branch-storage shared doesn't support the plain db kind");
+            std::process::exit(1);
+        }
+        let db = DB::open(&options.db_options.to_rocksdb_options(), &options.db_path).unwrap();
+        smt::set_branch_storage::<_, WriteOptions>(&db, storage).unwrap();
+    }
+
+    // the records of the blocks of a sharded run go to the benchmark database, the settings of a database and the
+    // database size of the metrics would only see the first shard
+    if !options.shards.is_empty()
+        && (accumulator_type != "smt"
+            || options.db_kind != "plain"
+            || options.speculative
+            || options.metrics.is_some()
+            || options.prometheus.is_some()
+            || options.results.is_some()
+            || options.leaf_encoding.is_some()
+            || options.key_derivation.is_some()
+            || options.branch_storage.is_some()
+            || options.sequence_width.is_some())
+    {
+        println!("--shards only supports the smt accumulator with the plain db kind, without --speculative, --metrics, --prometheus, --results, --leaf-encoding, --key-derivation, --branch-storage and --sequence-width");
+        std::process::exit(1);
+    }
+    // the speculative loop and the tiered smt commit a sequence per block
+    if options.sequence_source != SequenceSource::Block
+        && (options.speculative || options.hot_tree.is_some())
+    {
+        println!("--sequence-source only supports block with --speculative and --hot-tree");
+        std::process::exit(1);
+    }
+    // the records of the blocks of a tiered run go to the benchmark database, which holds the cold tree, like the
+    // database size of the metrics
+    if options.hot_tree.is_some() {
+        if accumulator_type != "smt"
+            || options.db_kind != "plain"
+            || options.speculative
+            || options.metrics.is_some()
+            || options.prometheus.is_some()
+            || options.results.is_some()
+            || options.leaf_encoding.is_some()
+            || options.key_derivation.is_some()
+            || options.branch_storage.is_some()
+            || options.sequence_width.is_some()
+        {
+            println!("--hot-tree only supports the smt accumulator with the plain db kind, without --speculative, --metrics, --prometheus, --results, --leaf-encoding, --key-derivation, --branch-storage and --sequence-width");
+            std::process::exit(1);
+        }
+        if !options.shards.is_empty() {
+            println!("--shards can't be combined with --hot-tree");
+            std::process::exit(1);
+        }
+        install_shutdown_handler();
+        return bench_tiered(options);
+    }
+    if !options.shards.is_empty() {
+        install_shutdown_handler();
+        return bench_sharded(options);
+    }
+
+    install_shutdown_handler();
+
     if accumulator_type == "smt" {
-        bench!(SMTAccumulator::<OptimisticTransaction, ()>);
+        bench!(options, SMTAccumulator, SMTAccumulatorOwned);
     } else if accumulator_type == "mmr" {
-        bench!(MMRAccumulator::<OptimisticTransaction, ()>);
+        bench!(options, MMRAccumulator, MMRAccumulatorOwned);
     } else if accumulator_type == "smt_live" {
-        bench!(SMTLiveAccumulator::<OptimisticTransaction, ()>);
+        bench!(options, SMTLiveAccumulator, SMTLiveAccumulatorOwned);
+    } else if accumulator_type == "smt_presence" {
+        bench!(options, PresenceAccumulator, PresenceAccumulatorOwned);
     } else {
-        println!("first argument must be smt | mmr | smt_live");
+        println!("first argument must be smt | mmr | smt_live | smt_presence");
         std::process::exit(1);
     }
 }
@@ -0,0 +1,115 @@
+use crate::AccumulatorError;
+use serde::Deserialize;
+use toml::Table;
+
+#[cfg(test)]
+mod tests;
+
+/// A benchmark campaign read by the `run-matrix` subcommand: every backend is run with every db profile and every
+/// workload, a profile or a workload is a named list of benchmark flags. The matrix is a TOML file, `start_block` is 0
+/// by default and a missing table runs the default profile or workload:
+///
+/// ```toml
+/// start_block = 0
+/// blocks = 100000
+/// backends = ["smt", "mmr"]
+///
+/// [db_profiles]
+/// default = []
+/// blob = ["--min-blob-size", "4096"]
+///
+/// [workloads]
+/// plain = ["--strict"]
+/// soak = ["--strict", "--soak", "3"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Matrix {
+    pub start_block: u64,
+    pub blocks: u64,
+    pub backends: Vec<String>,
+    pub db_profiles: Vec<(String, Vec<String>)>,
+    pub workloads: Vec<(String, Vec<String>)>,
+}
+
+/// One run of a matrix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Combination {
+    pub backend: String,
+    pub db_profile: String,
+    pub workload: String,
+    // the flags of the db profile followed by the flags of the workload
+    pub flags: Vec<String>,
+}
+
+impl Combination {
+    /// Names the run, its database directory and its metrics file: `<backend>-<db profile>-<workload>`.
+    pub fn label(&self) -> String {
+        format!("{}-{}-{}", self.backend, self.db_profile, self.workload)
+    }
+}
+
+// the matrix file, the tables of profiles and workloads keep the order of their entries
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatrixFile {
+    #[serde(default)]
+    start_block: u64,
+    blocks: u64,
+    backends: Vec<String>,
+    #[serde(default)]
+    db_profiles: Table,
+    #[serde(default)]
+    workloads: Table,
+}
+
+impl Matrix {
+    pub fn parse(content: &str) -> Result<Self, AccumulatorError> {
+        let invalid =
+            |e: toml::de::Error| AccumulatorError::InternalError(format!("invalid matrix: {}", e));
+        let named_flags = |table: Table| {
+            table
+                .into_iter()
+                .map(|(name, flags)| Ok((name, flags.try_into().map_err(invalid)?)))
+                .collect::<Result<Vec<(String, Vec<String>)>, AccumulatorError>>()
+        };
+        let file: MatrixFile = toml::from_str(content).map_err(invalid)?;
+        let mut matrix = Matrix {
+            start_block: file.start_block,
+            blocks: file.blocks,
+            backends: file.backends,
+            db_profiles: named_flags(file.db_profiles)?,
+            workloads: named_flags(file.workloads)?,
+        };
+        if matrix.blocks == 0 || matrix.backends.is_empty() {
+            return Err(AccumulatorError::InternalError(
+                "the matrix must set blocks and backends".to_string(),
+            ));
+        }
+        // a matrix without profiles or workloads runs the backends with the default ones
+        if matrix.db_profiles.is_empty() {
+            matrix.db_profiles.push(("default".to_string(), Vec::new()));
+        }
+        if matrix.workloads.is_empty() {
+            matrix.workloads.push(("default".to_string(), Vec::new()));
+        }
+        Ok(matrix)
+    }
+
+    /// Returns the runs of the matrix, by backend, then db profile, then workload.
+    pub fn combinations(&self) -> Vec<Combination> {
+        let mut combinations = Vec::new();
+        for backend in &self.backends {
+            for (db_profile, profile_flags) in &self.db_profiles {
+                for (workload, workload_flags) in &self.workloads {
+                    combinations.push(Combination {
+                        backend: backend.clone(),
+                        db_profile: db_profile.clone(),
+                        workload: workload.clone(),
+                        flags: [profile_flags.as_slice(), workload_flags.as_slice()].concat(),
+                    });
+                }
+            }
+        }
+        combinations
+    }
+}
@@ -0,0 +1,39 @@
+use super::Matrix;
+
+#[test]
+fn test_parse_matrix() {
+    let matrix = Matrix::parse(
+        r#"
+# a small campaign
+start_block = 0
+blocks = 10_000
+backends = ["smt", "mmr"]
+
+[db_profiles]
+default = []
+"blob#1" = ["--min-blob-size", "4096"] # large values in blob files
+
+[workloads]
+strict = ["--strict",]
+scripts = ["--script-index", "a,b"]
+"#,
+    )
+    .unwrap();
+    assert_eq!(10_000, matrix.blocks);
+    let combinations = matrix.combinations();
+    assert_eq!(8, combinations.len());
+    assert_eq!("smt-default-strict", combinations[0].label());
+    assert_eq!("mmr-blob#1-scripts", combinations[7].label());
+    assert_eq!(
+        vec!["--min-blob-size", "4096", "--script-index", "a,b"],
+        combinations[7].flags
+    );
+
+    assert!(Matrix::parse("backends = [\"smt\"]").is_err());
+    assert!(Matrix::parse("blocks = 1\nbackends = \"smt\"").is_err());
+    assert!(Matrix::parse("blocks = 1\nbackends = [\"smt\"]\n[other]").is_err());
+    assert!(Matrix::parse("blocks = 1\nbackends = [\"smt\"]\n[workloads]\nsoak = 3").is_err());
+    // the default profile and workload
+    let matrix = Matrix::parse("blocks = 1\nbackends = [\"smt\"]").unwrap();
+    assert_eq!("smt-default-default", matrix.combinations()[0].label());
+}
@@ -0,0 +1,995 @@
+use crate::{report::percentile, AccumulatorError};
+use rocksdb::ops::GetProperty;
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const MEM_TABLES_PROPERTY: &str = "rocksdb.cur-size-all-mem-tables";
+const BLOCK_CACHE_PROPERTY: &str = "rocksdb.block-cache-usage";
+const STATS_PROPERTY: &str = "rocksdb.stats";
+
+/// Memory footprint of a benchmark run, sampled at every reporting interval.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUsage {
+    // Resident set size of the current process in bytes, only available on linux.
+    pub rss: Option<u64>,
+    // Approximate size of active and unflushed immutable memtables in bytes.
+    pub mem_tables: Option<u64>,
+    // Memory size of the entries residing in the block cache in bytes.
+    pub block_cache: Option<u64>,
+}
+
+impl MemoryUsage {
+    pub fn sample<DB: GetProperty>(db: &DB) -> Self {
+        MemoryUsage {
+            rss: process_rss(),
+            mem_tables: db.property_int_value(MEM_TABLES_PROPERTY).ok().flatten(),
+            block_cache: db.property_int_value(BLOCK_CACHE_PROPERTY).ok().flatten(),
+        }
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rss {} bytes, mem tables {} bytes, block cache {} bytes",
+            OptionalValue(self.rss),
+            OptionalValue(self.mem_tables),
+            OptionalValue(self.block_cache)
+        )
+    }
+}
+
+struct OptionalValue(Option<u64>);
+
+impl fmt::Display for OptionalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(v) => write!(f, "{}", v),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+
+/// Returns the resident set size of the current process, parsed from the `VmRSS` line of `/proc/self/status`.
+pub fn process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmRSS:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+/// Number of tree node writes per level, indexed by the node height, see `LevelWrites`.
+#[derive(Clone, Debug)]
+pub struct LevelHistogram(pub [u64; 256]);
+
+impl Default for LevelHistogram {
+    fn default() -> Self {
+        LevelHistogram([0; 256])
+    }
+}
+
+impl LevelHistogram {
+    pub fn record(&mut self, height: u8) {
+        self.0[height as usize] += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.0.iter().sum()
+    }
+}
+
+impl fmt::Display for LevelHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "level writes {}:", self.total())?;
+        for (height, count) in self.0.iter().enumerate().filter(|(_, count)| **count > 0) {
+            write!(f, " {}={}", height, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Paces the blocks of a run to a target interval, like a chain producing a block every interval, and tracks whether the
+/// accumulator keeps up. Block `n` is due `(n + 1) * interval` after the pacing starts, a late block doesn't shift the
+/// schedule, so the lag of a backend slower than the chain keeps growing.
+#[derive(Clone, Debug)]
+pub struct BlockPacing {
+    interval: Duration,
+    start: Instant,
+    // Time spent processing the blocks, excluding the waits.
+    busy: Duration,
+    blocks: u32,
+    late_blocks: u32,
+    // How far the last block finished after its deadline, zero if it was on time.
+    lag: Duration,
+}
+
+impl BlockPacing {
+    pub fn new(interval: Duration) -> Self {
+        BlockPacing {
+            interval,
+            start: Instant::now(),
+            busy: Duration::ZERO,
+            blocks: 0,
+            late_blocks: 0,
+            lag: Duration::ZERO,
+        }
+    }
+
+    /// Records a block processed in `busy` and waits until the next one is due.
+    pub fn finish_block(&mut self, busy: Duration) {
+        self.busy += busy;
+        self.blocks += 1;
+        let deadline = self.start + self.interval * self.blocks;
+        let now = Instant::now();
+        if now < deadline {
+            self.lag = Duration::ZERO;
+            std::thread::sleep(deadline - now);
+        } else {
+            self.late_blocks += 1;
+            self.lag = now - deadline;
+        }
+    }
+
+    /// Whether the run is on schedule, i.e. less than one interval behind it.
+    pub fn keeps_up(&self) -> bool {
+        self.lag < self.interval
+    }
+
+    /// The share of the block interval left idle, in percent, negative when the blocks take longer than the interval.
+    pub fn headroom(&self) -> f64 {
+        let budget = self.interval.as_secs_f64() * self.blocks as f64;
+        (1.0 - self.busy.as_secs_f64() / budget.max(f64::MIN_POSITIVE)) * 100.0
+    }
+}
+
+impl fmt::Display for BlockPacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pacing {} ms: {}, headroom {:.1}%, {} of {} blocks late, lag {} ms",
+            self.interval.as_millis(),
+            if self.keeps_up() {
+                "keeps up"
+            } else {
+                "falls behind"
+            },
+            self.headroom(),
+            self.late_blocks,
+            self.blocks,
+            self.lag.as_millis()
+        )
+    }
+}
+
+/// A run split into equal segments, the last one takes the remaining blocks, which commit the transaction at a
+/// different interval each, counted from the first block of the segment. A segment always commits at its last block.
+#[derive(Clone, Debug)]
+pub struct CommitSweep {
+    segments: Vec<CommitSegment>,
+    // the index of the running segment and the instant it started at
+    current: usize,
+    started: Instant,
+}
+
+/// The blocks of a segment of a `CommitSweep` and their throughput.
+#[derive(Clone, Debug)]
+pub struct CommitSegment {
+    pub commit_interval: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub elapsed: Option<Duration>,
+}
+
+impl CommitSegment {
+    pub fn blocks_per_sec(&self) -> Option<f64> {
+        let blocks = self.last_block - self.first_block + 1;
+        self.elapsed
+            .map(|elapsed| blocks as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE))
+    }
+}
+
+impl CommitSweep {
+    pub fn new(commit_intervals: &[u64], first_block: u64, total_blocks: u64) -> Self {
+        assert!(
+            total_blocks >= commit_intervals.len() as u64,
+            "a segment has at least one block"
+        );
+        let segment_blocks = total_blocks / commit_intervals.len() as u64;
+        let segments = (0..)
+            .zip(commit_intervals)
+            .map(|(i, commit_interval)| {
+                let first = first_block + i * segment_blocks;
+                let last = if i + 1 == commit_intervals.len() as u64 {
+                    first_block + total_blocks - 1
+                } else {
+                    first + segment_blocks - 1
+                };
+                CommitSegment {
+                    commit_interval: *commit_interval,
+                    first_block: first,
+                    last_block: last,
+                    elapsed: None,
+                }
+            })
+            .collect();
+        CommitSweep {
+            segments,
+            current: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether the transaction is committed after the block.
+    pub fn commit_due(&self, block_number: u64) -> bool {
+        self.segments
+            .iter()
+            .find(|segment| block_number <= segment.last_block)
+            .map_or(true, |segment| {
+                block_number == segment.last_block
+                    || (block_number + 1 - segment.first_block) % segment.commit_interval == 0
+            })
+    }
+
+    /// Records the end of a block, including its commit, returns the segment it finished if it was the last one.
+    pub fn finish_block(&mut self, block_number: u64) -> Option<&CommitSegment> {
+        let segment = self.segments.get_mut(self.current)?;
+        if block_number != segment.last_block {
+            return None;
+        }
+        let now = Instant::now();
+        segment.elapsed = Some(now - self.started);
+        self.started = now;
+        self.current += 1;
+        self.segments.get(self.current - 1)
+    }
+
+    pub fn segments(&self) -> &[CommitSegment] {
+        &self.segments
+    }
+}
+
+impl fmt::Display for CommitSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "commit every {} blocks, blocks {}..={}: ",
+            self.commit_interval, self.first_block, self.last_block
+        )?;
+        match self.blocks_per_sec() {
+            Some(throughput) => write!(f, "{:.1} blocks/s", throughput),
+            None => write!(f, "not finished"),
+        }
+    }
+}
+
+impl fmt::Display for CommitSweep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "commit sweep:")?;
+        for segment in &self.segments {
+            write!(f, "\n  {}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+/// How often a run prints its report lines: every given number of blocks, or at the first block finished once the given
+/// time has passed since the previous report, so that slow configurations still report regularly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportInterval {
+    Blocks(u64),
+    Elapsed(Duration),
+}
+
+impl Default for ReportInterval {
+    fn default() -> Self {
+        ReportInterval::Blocks(100)
+    }
+}
+
+/// Parses a number of blocks, e.g. `100`, or a duration with a `ms`, `s`, `m` or `h` unit, e.g. `30s`.
+impl FromStr for ReportInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = match number.parse() {
+            Ok(number) if number > 0 => number,
+            _ => return Err("must be a positive number of blocks or a duration".to_string()),
+        };
+        match unit {
+            "" => Ok(ReportInterval::Blocks(number)),
+            "ms" => Ok(ReportInterval::Elapsed(Duration::from_millis(number))),
+            "s" => Ok(ReportInterval::Elapsed(Duration::from_secs(number))),
+            "m" => Ok(ReportInterval::Elapsed(Duration::from_secs(number * 60))),
+            "h" => Ok(ReportInterval::Elapsed(Duration::from_secs(number * 3600))),
+            _ => Err("the duration unit must be ms | s | m | h".to_string()),
+        }
+    }
+}
+
+/// Tells the blocks which end a reporting interval of a run.
+#[derive(Clone, Debug)]
+pub struct ReportClock {
+    interval: ReportInterval,
+    // the instant of the previous report, or of the start of the run
+    last: Instant,
+}
+
+impl ReportClock {
+    pub fn new(interval: ReportInterval) -> Self {
+        ReportClock {
+            interval,
+            last: Instant::now(),
+        }
+    }
+
+    /// Whether the run reports after `block_number`, the elapsed time of an interval restarts at every report.
+    pub fn due(&mut self, block_number: u64) -> bool {
+        let due = match self.interval {
+            ReportInterval::Blocks(blocks) => block_number % blocks == blocks - 1,
+            ReportInterval::Elapsed(elapsed) => self.last.elapsed() >= elapsed,
+        };
+        if due {
+            self.last = Instant::now();
+        }
+        due
+    }
+}
+
+/// The processing time of every block, with the spike blocks of the workload apart from the regular ones, since the
+/// worst case block time is what bounds the block parameters of a chain.
+#[derive(Clone, Debug, Default)]
+pub struct BlockLatencies {
+    regular: Vec<u64>,
+    spikes: Vec<u64>,
+}
+
+impl BlockLatencies {
+    pub fn record(&mut self, spike: bool, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        if spike {
+            self.spikes.push(micros);
+        } else {
+            self.regular.push(micros);
+        }
+    }
+
+    pub fn regular(&self) -> &[u64] {
+        &self.regular
+    }
+
+    pub fn spikes(&self) -> &[u64] {
+        &self.spikes
+    }
+}
+
+// the number of samples and their p50, p99 and maximum
+fn summarize(samples: &[u64]) -> String {
+    let mut sorted = samples.to_vec();
+    format!(
+        "{} blocks, p50 {} micros, p99 {} micros, max {} micros",
+        samples.len(),
+        percentile(&mut sorted, 50),
+        percentile(&mut sorted, 99),
+        samples.iter().copied().max().unwrap_or(0)
+    )
+}
+
+impl fmt::Display for BlockLatencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "regular {}; spike {}",
+            summarize(&self.regular),
+            summarize(&self.spikes)
+        )
+    }
+}
+
+/// The serialized witness of every block for stateless validation, i.e. the proof that the cells it consumes are live
+/// at its parent, with their statuses. A block above the alert threshold is counted as an alert.
+#[derive(Clone, Debug, Default)]
+pub struct WitnessSizes {
+    sizes: Vec<u64>,
+    alert_threshold: Option<u64>,
+    alerts: u64,
+}
+
+impl WitnessSizes {
+    pub fn new(alert_threshold: Option<u64>) -> Self {
+        WitnessSizes {
+            alert_threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Records the witness size of a block, returns whether it's above the alert threshold.
+    pub fn record(&mut self, bytes: u64) -> bool {
+        self.sizes.push(bytes);
+        let alert = self
+            .alert_threshold
+            .map_or(false, |threshold| bytes > threshold);
+        if alert {
+            self.alerts += 1;
+        }
+        alert
+    }
+
+    pub fn mean(&self) -> u64 {
+        self.sizes.iter().sum::<u64>() / (self.sizes.len() as u64).max(1)
+    }
+
+    pub fn p99(&self) -> u64 {
+        percentile(&mut self.sizes.clone(), 99)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.sizes.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for WitnessSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "witness of {} blocks: mean {} bytes, p99 {} bytes, max {} bytes",
+            self.sizes.len(),
+            self.mean(),
+            self.p99(),
+            self.max()
+        )?;
+        if let Some(threshold) = self.alert_threshold {
+            write!(f, ", {} blocks over {} bytes", self.alerts, threshold)?;
+        }
+        Ok(())
+    }
+}
+
+/// The write stalls of a run, i.e. the time RocksDB delayed or stopped the writes while the compactions or memtable
+/// flushes caught up, sampled from the cumulative stall time of the `rocksdb.stats` property at every reporting interval.
+/// Consecutive intervals which stalled are merged into one window, so the throughput dips of a long run can be told
+/// apart from the slowdowns of the accumulator itself.
+#[derive(Clone, Debug, Default)]
+pub struct WriteStalls {
+    // Cumulative stall time of the database at the last sample, it starts at zero when the database is opened.
+    total: Duration,
+    // The last block of the last sampled interval.
+    last_block: Option<u64>,
+    // First block, last block and stall time of every window.
+    windows: Vec<(u64, u64, Duration)>,
+}
+
+impl WriteStalls {
+    /// Samples the stall time at the end of the interval ending at `block`, returns the stall time of the interval, or
+    /// `None` if the property isn't available.
+    pub fn sample<DB: GetProperty>(&mut self, db: &DB, block: u64) -> Option<Duration> {
+        let stats = db.property_value(STATS_PROPERTY).ok().flatten()?;
+        Some(self.record(cumulative_stall(&stats)?, block))
+    }
+
+    /// Records the cumulative stall time `total` sampled at the end of the interval ending at `block`, returns the
+    /// stall time of the interval.
+    pub(crate) fn record(&mut self, total: Duration, block: u64) -> Duration {
+        let stalled = total.saturating_sub(self.total);
+        let first_block = self.last_block.map_or(block, |last_block| last_block + 1);
+        if !stalled.is_zero() {
+            match self.windows.last_mut() {
+                Some((_, last, window)) if *last + 1 == first_block => {
+                    *last = block;
+                    *window += stalled;
+                }
+                _ => self.windows.push((first_block, block, stalled)),
+            }
+        }
+        self.total = total;
+        self.last_block = Some(block);
+        stalled
+    }
+
+    /// The cumulative stall time at the last sample.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The first block, last block and stall time of every window.
+    pub fn windows(&self) -> &[(u64, u64, Duration)] {
+        &self.windows
+    }
+}
+
+impl fmt::Display for WriteStalls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "write stalls: {} millis in {} windows",
+            self.total.as_millis(),
+            self.windows.len()
+        )?;
+        for (first, last, stalled) in &self.windows {
+            write!(
+                f,
+                ", blocks {}-{} {} millis",
+                first,
+                last,
+                stalled.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the cumulative stall time of the DB stats section of the `rocksdb.stats` property, a line like
+/// `Cumulative stall: 00:00:1.250 H:M:S, 0.4 percent`.
+pub(crate) fn cumulative_stall(stats: &str) -> Option<Duration> {
+    let line = stats
+        .lines()
+        .find_map(|line| line.strip_prefix("Cumulative stall:"))?;
+    let mut hms = line.split_whitespace().next()?.split(':');
+    let hours: u64 = hms.next()?.parse().ok()?;
+    let minutes: u64 = hms.next()?.parse().ok()?;
+    let seconds: f64 = hms.next()?.parse().ok()?;
+    Some(Duration::from_secs(hours * 3600 + minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Accumulators which count the writes of their tree nodes per level since they were created,
+/// to see how deep the typical update paths go.
+pub trait LevelWrites {
+    fn level_writes(&self) -> Option<&LevelHistogram> {
+        None
+    }
+}
+
+/// A stage of the block pipeline, see `StageTimes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    // Generating the operations of the block, or waiting for them when they are generated in the background.
+    Workload,
+    // Hashing the out_points into leaf keys and leaf hashes.
+    Hashing,
+    // Writing the leaves to the tree.
+    TreeUpdate,
+    // Reading and writing the element records, `mmr` and `smt_live` only.
+    ElementIndex,
+    // Committing the tree, i.e. computing its root and flushing its pending writes to the store.
+    TreeCommit,
+    // Committing the RocksDB transaction, every 100 blocks.
+    TxCommit,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Workload,
+        Stage::Hashing,
+        Stage::TreeUpdate,
+        Stage::ElementIndex,
+        Stage::TreeCommit,
+        Stage::TxCommit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Workload => "workload",
+            Stage::Hashing => "hashing",
+            Stage::TreeUpdate => "tree_update",
+            Stage::ElementIndex => "element_index",
+            Stage::TreeCommit => "tree_commit",
+            Stage::TxCommit => "tx_commit",
+        }
+    }
+}
+
+/// Time spent in every stage of the block pipeline in nanoseconds, indexed by `Stage`, see `StageTiming`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StageTimes(pub [u64; 6]);
+
+impl StageTimes {
+    pub fn record(&mut self, stage: Stage, elapsed: Duration) {
+        self.0[stage as usize] += elapsed.as_nanos() as u64;
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        Duration::from_nanos(self.0[stage as usize])
+    }
+
+    pub fn merge(&mut self, other: &StageTimes) {
+        for (total, nanos) in self.0.iter_mut().zip(other.0) {
+            *total += nanos;
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_nanos(self.0.iter().sum())
+    }
+
+    /// The time spent in every stage since the `earlier` times of the same accumulator.
+    pub fn since(&self, earlier: &StageTimes) -> StageTimes {
+        let mut times = *self;
+        for (total, nanos) in times.0.iter_mut().zip(earlier.0) {
+            *total = total.saturating_sub(nanos);
+        }
+        times
+    }
+}
+
+impl fmt::Display for StageTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        write!(f, "stages {} millis:", total.as_millis())?;
+        for stage in Stage::ALL {
+            let elapsed = self.get(stage);
+            write!(
+                f,
+                " {}={} ({:.1}%)",
+                stage.name(),
+                elapsed.as_millis(),
+                elapsed.as_secs_f64() * 100.0 / total.as_secs_f64().max(f64::MIN_POSITIVE)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulators which time the stages of their writes since they were created, the workload and transaction stages
+/// are timed by the caller.
+pub trait StageTiming {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        None
+    }
+}
+
+/// Accumulators which coalesce the add and the delete of a cell in the same block of `apply_blocks` into a single write
+/// of its final status, see `BlockOps::take_transient`.
+pub trait CoalescedOps {
+    /// The number of coalesced add and delete pairs since the accumulator was created.
+    fn coalesced_ops(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// When an accumulator writes the batches of its commits to the database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Every commit writes the batch of its block.
+    #[default]
+    Block,
+    /// The batches are buffered in memory, with the sequences they were committed at, until `FlushWrites::flush`,
+    /// which runs before the RocksDB transaction is committed.
+    Transaction,
+}
+
+/// Sizes of the batches flushed by the commits of an accumulator since it was created, and of the buffered writes
+/// flushed to the database in the `Transaction` flush mode, see `FlushWrites`.
+#[derive(Clone, Debug, Default)]
+pub struct FlushStats {
+    pub batches: u64,
+    pub entries: u64,
+    pub bytes: u64,
+    pub max_entries: u64,
+    pub max_bytes: u64,
+    pub flushes: u64,
+    pub flushed_entries: u64,
+    pub flushed_bytes: u64,
+}
+
+impl FlushStats {
+    pub fn record_batch(&mut self, entries: u64, bytes: u64) {
+        self.batches += 1;
+        self.entries += entries;
+        self.bytes += bytes;
+        self.max_entries = self.max_entries.max(entries);
+        self.max_bytes = self.max_bytes.max(bytes);
+    }
+
+    pub fn record_flush(&mut self, entries: u64, bytes: u64) {
+        self.flushes += 1;
+        self.flushed_entries += entries;
+        self.flushed_bytes += bytes;
+    }
+}
+
+impl fmt::Display for FlushStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let batches = self.batches.max(1);
+        write!(
+            f,
+            "flushed batches {}: {} entries, {} bytes, per block avg {} entries, {} bytes, max {} entries, {} bytes",
+            self.batches,
+            self.entries,
+            self.bytes,
+            self.entries / batches,
+            self.bytes / batches,
+            self.max_entries,
+            self.max_bytes
+        )?;
+        if self.flushes > 0 {
+            write!(
+                f,
+                ", transaction flushes {}: {} entries, {} bytes",
+                self.flushes, self.flushed_entries, self.flushed_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulators which batch the writes of a block in memory and flush them to the store at commit, only the MMR
+/// backend does, the SMT backends write through to the store.
+pub trait FlushWrites {
+    fn set_flush_mode(&mut self, _mode: FlushMode) -> Result<(), AccumulatorError> {
+        Ok(())
+    }
+
+    /// Writes the batches buffered in the `Transaction` flush mode to the database.
+    fn flush(&mut self) -> Result<(), AccumulatorError> {
+        Ok(())
+    }
+
+    fn flush_stats(&self) -> Option<&FlushStats> {
+        None
+    }
+}
+
+/// Number of reads and writes of a key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyCounts {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Reads and writes per store key (without its version) over a run, collected by `KeyTracer`.
+#[derive(Debug, Default)]
+pub struct KeyStats {
+    counts: HashMap<Vec<u8>, KeyCounts>,
+}
+
+impl KeyStats {
+    pub fn record_read(&mut self, key: &[u8]) {
+        self.entry(key).reads += 1;
+    }
+
+    pub fn record_write(&mut self, key: &[u8]) {
+        self.entry(key).writes += 1;
+    }
+
+    fn entry(&mut self, key: &[u8]) -> &mut KeyCounts {
+        // avoid allocating the key again for the hot keys, which are the point of the stats
+        if !self.counts.contains_key(key) {
+            self.counts.insert(key.to_vec(), KeyCounts::default());
+        }
+        self.counts.get_mut(key).expect("inserted above")
+    }
+
+    /// The number of distinct keys read, i.e. the read amplification of the traced operations.
+    pub fn keys_read(&self) -> usize {
+        self.counts
+            .values()
+            .filter(|counts| counts.reads > 0)
+            .count()
+    }
+
+    /// The number of reads of all the keys.
+    pub fn reads(&self) -> u64 {
+        self.counts.values().map(|counts| counts.reads).sum()
+    }
+
+    /// Returns the `n` keys with the most reads and writes, the hottest first.
+    pub fn hottest(&self, n: usize) -> HotKeys {
+        let mut keys: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(key, counts)| (key.clone(), *counts))
+            .collect();
+        keys.sort_by(|(key1, counts1), (key2, counts2)| {
+            (counts2.reads + counts2.writes)
+                .cmp(&(counts1.reads + counts1.writes))
+                .then_with(|| key1.cmp(key2))
+        });
+        keys.truncate(n);
+        HotKeys(keys)
+    }
+}
+
+/// The hottest keys of a `KeyStats`, displayed one per line with the key in hex.
+pub struct HotKeys(pub Vec<(Vec<u8>, KeyCounts)>);
+
+impl fmt::Display for HotKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, counts)) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "hot key ")?;
+            for b in key {
+                write!(f, "{:02x}", b)?;
+            }
+            write!(f, ": reads {}, writes {}", counts.reads, counts.writes)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of keys written by the stores of a run and their bytes, the versioned key and the value, counted by
+/// `KeyTracer`. Unlike `KeyStats` it doesn't keep the keys, so it's cheap enough to count every run.
+#[derive(Debug, Default)]
+pub struct WriteCounts {
+    keys: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl WriteCounts {
+    pub fn record(&self, bytes: usize) {
+        self.keys.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn keys(&self) -> u64 {
+        self.keys.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// The write rates of a run of `blocks` blocks which took `elapsed`.
+    pub fn rates(&self, blocks: u64, elapsed: Duration) -> WriteRates {
+        let secs = elapsed.as_secs_f64().max(0.001);
+        WriteRates {
+            blocks_per_sec: blocks as f64 / secs,
+            keys_per_sec: self.keys() as f64 / secs,
+            bytes_per_sec: self.bytes() as f64 / secs,
+        }
+    }
+}
+
+/// The blocks, logical keys and bytes written per second by a run, see `WriteCounts::rates`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteRates {
+    pub blocks_per_sec: f64,
+    pub keys_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+impl fmt::Display for WriteRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "throughput {:.1} blocks/s, written {:.0} keys/s, {:.0} bytes/s",
+            self.blocks_per_sec, self.keys_per_sec, self.bytes_per_sec
+        )
+    }
+}
+
+/// The bytes written by a run attributed to the cells it creates and consumes, from the `WriteCounts` of every phase
+/// of a block. The writes of `add` and `delete` are their own, the writes of the commit update the tree and are shared
+/// out by leaves, every created or consumed cell updates one. A transaction is the outputs of a block sharing a tx_hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CellCosts {
+    pub cells_created: u64,
+    pub cells_spent: u64,
+    pub transactions: u64,
+    pub created_bytes: u64,
+    pub spent_bytes: u64,
+}
+
+/// Seconds in a year of 365 days, to project the growth of a transaction rate.
+const YEAR_SECS: f64 = 365.0 * 24.0 * 3600.0;
+
+impl CellCosts {
+    /// Records the cells of a block and the bytes written by its add, delete and commit phases. The commit bytes of
+    /// a block without cells are not attributed.
+    pub fn record(
+        &mut self,
+        created: u64,
+        spent: u64,
+        transactions: u64,
+        add_bytes: u64,
+        delete_bytes: u64,
+        commit_bytes: u64,
+    ) {
+        let leaves = created + spent;
+        let created_share = if leaves == 0 {
+            0
+        } else {
+            (commit_bytes as u128 * created as u128 / leaves as u128) as u64
+        };
+        let spent_share = if leaves == 0 {
+            0
+        } else {
+            commit_bytes - created_share
+        };
+        self.cells_created += created;
+        self.cells_spent += spent;
+        self.transactions += transactions;
+        self.created_bytes += add_bytes + created_share;
+        self.spent_bytes += delete_bytes + spent_share;
+    }
+
+    pub fn per_created_cell(&self) -> f64 {
+        self.created_bytes as f64 / self.cells_created.max(1) as f64
+    }
+
+    pub fn per_spent_cell(&self) -> f64 {
+        self.spent_bytes as f64 / self.cells_spent.max(1) as f64
+    }
+
+    /// The bytes of the cells created and consumed per transaction.
+    pub fn per_transaction(&self) -> f64 {
+        (self.created_bytes + self.spent_bytes) as f64 / self.transactions.max(1) as f64
+    }
+
+    /// The bytes written in a year at `tx_per_sec` transactions per second with the cells of the run per transaction.
+    /// The stores are versioned, so every write is kept without a garbage collection, and it's before compression.
+    pub fn annual_growth(&self, tx_per_sec: f64) -> f64 {
+        self.per_transaction() * tx_per_sec * YEAR_SECS
+    }
+}
+
+impl fmt::Display for CellCosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cost model: {:.0} bytes per created cell, {:.0} bytes per spent cell, {:.0} bytes per transaction",
+            self.per_created_cell(),
+            self.per_spent_cell(),
+            self.per_transaction()
+        )
+    }
+}
+
+/// Traces the keys read and written by a store into shared `KeyStats`, and counts the writes into shared `WriteCounts`,
+/// so they outlive the accumulators of a run. Both are disabled by default and cost nothing then.
+#[derive(Clone, Debug, Default)]
+pub struct KeyTracer {
+    stats: Option<Arc<Mutex<KeyStats>>>,
+    writes: Option<Arc<WriteCounts>>,
+}
+
+impl KeyTracer {
+    pub fn new(stats: Arc<Mutex<KeyStats>>) -> Self {
+        KeyTracer {
+            stats: Some(stats),
+            writes: None,
+        }
+    }
+
+    pub fn with_write_counts(mut self, writes: Arc<WriteCounts>) -> Self {
+        self.writes = Some(writes);
+        self
+    }
+
+    pub fn read(&self, key: &[u8]) {
+        if let Some(stats) = &self.stats {
+            stats.lock().expect("key stats lock").record_read(key);
+        }
+    }
+
+    /// Traces a write of `key`, which stores `bytes` bytes, its versioned key and its value.
+    pub fn write(&self, key: &[u8], bytes: usize) {
+        if let Some(stats) = &self.stats {
+            stats.lock().expect("key stats lock").record_write(key);
+        }
+        if let Some(writes) = &self.writes {
+            writes.record(bytes);
+        }
+    }
+}
+
+/// Accumulators whose store keys can be traced.
+pub trait TraceKeys {
+    fn trace_keys(&mut self, tracer: KeyTracer);
+}
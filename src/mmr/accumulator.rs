@@ -1,13 +1,28 @@
-use super::store::{DefaultStore, ELEMENT_KEY, MMR_SIZE_KEY};
+use super::{
+    gc::gc_horizon,
+    store::{DefaultStore, ELEMENT_KEY, MMR_SIZE_KEY},
+};
 use crate::{
-    new_blake2b, AccumulatorError, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint,
-    Proof,
+    commitment_digest, ct_eq, debug_commitment,
+    element_index::{ElementIndexStats, IndexedElements},
+    keycodec::hashed_element_key,
+    live_cache::CacheLiveCells,
+    metrics::{
+        CoalescedOps, FlushMode, FlushStats, FlushWrites, KeyTracer, LevelWrites, Stage,
+        StageTimes, StageTiming, TraceKeys,
+    },
+    new_blake2b,
+    overlay::MMROverlayStore,
+    storage::traits::{ReadStore, WriteStore},
+    sweep::SweepCells,
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
 };
 use merkle_mountain_range::{Error, Merge, MerkleProof, MMR};
-use rocksdb::{
-    prelude::{Get, Iterate, Put},
-    ReadOptions,
-};
+use rocksdb::prelude::Put;
+use std::{collections::HashSet, fmt, ops::Deref, sync::Arc, time::Instant};
+
+/// Tags the commitment digests of this backend.
+pub const COMMITMENT_TAG: &[u8] = b"mmr";
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct H256([u8; 32]);
@@ -18,6 +33,12 @@ impl From<Box<[u8]>> for H256 {
     }
 }
 
+impl From<[u8; 32]> for H256 {
+    fn from(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+}
+
 impl AsRef<[u8]> for H256 {
     fn as_ref(&self) -> &[u8] {
         self.0.as_ref()
@@ -28,15 +49,14 @@ impl<'a> From<(&'a OutPoint, &'a CellStatus)> for H256 {
     fn from((out_point, status): (&'a OutPoint, &'a CellStatus)) -> Self {
         let mut hasher = new_blake2b();
         let mut hash = [0u8; 32];
-        hasher.update(out_point.tx_hash.as_ref());
-        hasher.update(&out_point.index.to_le_bytes());
+        hasher.update(&out_point.to_bytes());
         hasher.update(status.block_numbers.as_ref());
         hasher.finalize(&mut hash);
         H256(hash)
     }
 }
 
-struct MergeH256;
+pub(crate) struct MergeH256;
 
 impl Merge for MergeH256 {
     type Item = H256;
@@ -51,121 +71,460 @@ impl Merge for MergeH256 {
     }
 }
 
-pub struct MMRAccumulator<'a, DB, WO> {
-    mmr: MMR<H256, MergeH256, DefaultStore<'a, DB, WO>>,
+/// The accumulator over a handle which dereferences to the RocksDB database, see `MMRAccumulator` and `MMRAccumulatorOwned`.
+pub struct GenericMMRAccumulator<D, WO> {
+    mmr: MMR<H256, MergeH256, DefaultStore<D, WO>>,
+    // When enabled, `add` rejects existing elements and `delete` rejects missing or dead elements.
+    strict: bool,
+    flush_stats: FlushStats,
+    stage_times: StageTimes,
+    // See `CoalescedOps`.
+    coalesced_ops: u64,
+    // Whether the MMR has leaves pushed or updated since the last commit, they are only in its in-memory batch.
+    uncommitted: bool,
 }
 
-impl<'a, DB, WO> MMRAccumulator<'a, DB, WO>
+/// The accumulator borrowing a RocksDB database, transaction or snapshot.
+pub type MMRAccumulator<'a, DB, WO> = GenericMMRAccumulator<&'a DB, WO>;
+
+/// The accumulator sharing ownership of the RocksDB database, so that it can be held by long-lived services or moved across threads.
+pub type MMRAccumulatorOwned<DB, WO> = GenericMMRAccumulator<Arc<DB>, WO>;
+
+impl<D, WO> GenericMMRAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
-    pub fn new(db: &'a DB) -> Result<Self, Error> {
+    pub fn new(db: D) -> Result<Self, Error> {
+        Self::new_with_strict(db, false)
+    }
+
+    pub fn new_with_strict(db: D, strict: bool) -> Result<Self, Error> {
         let store = DefaultStore::new(db);
         let mmr_size = store
             .get(MMR_SIZE_KEY)
             .map(|slice| u64::from_le_bytes(slice.as_ref().try_into().expect("checked length")))
             .unwrap_or_default();
         let mmr = MMR::new(mmr_size, store);
-        Ok(MMRAccumulator { mmr })
+        Ok(GenericMMRAccumulator {
+            mmr,
+            strict,
+            flush_stats: FlushStats::default(),
+            stage_times: StageTimes::default(),
+            coalesced_ops: 0,
+            uncommitted: false,
+        })
     }
 }
 
-impl<'a, DB, WO> AccumulatorWriter for MMRAccumulator<'a, DB, WO>
+impl<D, WO> AccumulatorWriter for GenericMMRAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
 
     fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element exists already, caller should make sure the element is unique
-        let sequence = self.mmr.store().sequence();
-
-        for out_point in elements {
-            let cell_status = CellStatus::new_live(sequence);
-            let pos = self.mmr.push((&out_point, &cell_status).into())?;
-            // since mmr only store the hash of the element, we need to store the element <=> pos mapping by ourselves
-            let key = [
-                ELEMENT_KEY,
-                out_point.hash().as_ref(),
-                out_point.index.to_le_bytes().as_ref(),
-            ]
-            .concat();
-            let value = [
-                pos.to_le_bytes().as_ref(),
-                cell_status.block_numbers.as_ref(),
-            ]
-            .concat();
-            self.mmr.store_mut().put(&key, &value)?;
-        }
-        Ok(())
+        self.push_elements(elements, &HashSet::new())
     }
 
     fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element has been deleted already, caller should make sure the element is deleted only once
+        // in non-strict mode we don't check if the element has been deleted already, caller should make sure the element is deleted only once
+        self.uncommitted = true;
+        let start = Instant::now();
         let sequence = self.mmr.store().sequence();
+        let mut out_points = HashSet::new();
         let mut pos_and_cells: Vec<_> = Vec::with_capacity(elements.len());
-        for (i, out_point) in elements.iter().enumerate() {
-            let key = [
-                ELEMENT_KEY,
-                out_point.hash().as_ref(),
-                out_point.index.to_le_bytes().as_ref(),
-            ]
-            .concat();
-            if let Some(slice) = self.mmr.store().get(&key) {
-                let pos = u64::from_le_bytes(slice[0..8].try_into().expect("checked length"));
-                let block_numbers: [u8; 16] = slice[8..].try_into().expect("checked length");
-                let mut cell_status = CellStatus { block_numbers };
+        let keys: Vec<_> = elements
+            .iter()
+            .map(|out_point| hashed_element_key(ELEMENT_KEY, out_point))
+            .collect();
+        let stored = self.mmr.store().get_elements(&keys)?;
+        for (i, ((out_point, key), element)) in elements.iter().zip(keys).zip(stored).enumerate() {
+            if let Some((pos, mut cell_status)) = element {
+                if self.strict && (!cell_status.is_live() || !out_points.insert(out_point)) {
+                    return Err(AccumulatorError::ElementNotFound(i));
+                }
                 cell_status.mark_as_dead(sequence);
-                pos_and_cells.push((pos, key, (out_point, &cell_status).into(), cell_status));
+                pos_and_cells.push((pos, key, cell_status));
             } else {
                 return Err(AccumulatorError::ElementNotFound(i));
             }
         }
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+
+        let start = Instant::now();
+        let hashes: Vec<H256> = elements
+            .iter()
+            .zip(&pos_and_cells)
+            .map(|(out_point, (_pos, _key, cell_status))| (out_point, cell_status).into())
+            .collect();
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+
+        let start = Instant::now();
+        for ((pos, _key, _cell_status), hash) in pos_and_cells.iter().zip(&hashes) {
+            self.mmr.update(*pos, hash.clone())?;
+        }
+        self.stage_times.record(Stage::TreeUpdate, start.elapsed());
+
+        let start = Instant::now();
+        for ((pos, key, cell_status), hash) in pos_and_cells.iter().zip(&hashes) {
+            self.mmr
+                .store_mut()
+                .put_element(key, *pos, cell_status, hash)?;
+        }
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+        Ok(())
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        self.uncommitted = true;
+        let mut pos_and_cells = Vec::with_capacity(elements.len());
+        for (i, (out_point, cell_status)) in elements.iter().enumerate() {
+            let key = hashed_element_key(ELEMENT_KEY, out_point);
+            match self.mmr.store().get_element(&key)? {
+                Some((pos, _)) => {
+                    pos_and_cells.push((pos, key, (out_point, cell_status).into(), cell_status))
+                }
+                None => return Err(AccumulatorError::ElementNotFound(i)),
+            }
+        }
 
         for (pos, key, hash, cell_status) in pos_and_cells {
-            self.mmr.update(pos, hash)?;
-            let value = [
-                pos.to_le_bytes().as_ref(),
-                cell_status.block_numbers.as_ref(),
-            ]
-            .concat();
-            self.mmr.store_mut().put(&key, &value)?;
+            self.mmr.update(pos, hash.clone())?;
+            self.mmr
+                .store_mut()
+                .put_element(&key, pos, cell_status, &hash)?;
         }
         Ok(())
     }
 
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError> {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // a cell consumed in the block it's added in is pushed once with its dead status, at the position of its add
+            let transient = block.take_transient();
+            self.coalesced_ops += transient.len() as u64;
+            self.push_elements(block.added, &transient)?;
+            if !block.deleted.is_empty() {
+                self.delete(block.deleted)?;
+            }
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
     fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let start = Instant::now();
         let root = self.mmr.get_root()?;
         let sequence = self.mmr.store().sequence();
         let mmr_size = self.mmr.mmr_size();
         self.mmr.commit()?;
+        let (entries, bytes) = self.mmr.store_mut().take_batch();
+        self.flush_stats.record_batch(entries, bytes);
         self.mmr
             .store_mut()
             .put(MMR_SIZE_KEY, mmr_size.to_le_bytes())?;
         self.mmr.store_mut().commit()?;
+        self.uncommitted = false;
+        self.stage_times.record(Stage::TreeCommit, start.elapsed());
         Ok(AccumulatorCommitment { root, sequence })
     }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        Ok(self.mmr.store_mut().skip_to(sequence)?)
+    }
 }
 
-impl<'a, DB, WO> MMRAccumulator<'a, DB, WO>
+impl<D, WO> GenericMMRAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Result<Self, Error> {
+    // pushes the leaves of the added elements and records their positions, the `transient` elements are consumed in the
+    // same sequence and are pushed with their dead status
+    fn push_elements(
+        &mut self,
+        elements: Vec<OutPoint>,
+        transient: &HashSet<OutPoint>,
+    ) -> Result<(), AccumulatorError> {
+        // in non-strict mode we don't check if the element exists already, caller should make sure the element is unique
+        self.uncommitted = true;
+        let start = Instant::now();
+        if self.strict {
+            let mut out_points = HashSet::with_capacity(elements.len());
+            for (i, out_point) in elements.iter().enumerate() {
+                let key = hashed_element_key(ELEMENT_KEY, out_point);
+                if self.mmr.store().get(&key).is_some() || !out_points.insert(out_point) {
+                    return Err(AccumulatorError::AlreadyExists(i));
+                }
+            }
+        }
+
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+
+        let start = Instant::now();
+        let sequence = self.mmr.store().sequence();
+        let leaves: Vec<(Vec<u8>, CellStatus, H256)> = elements
+            .iter()
+            .map(|out_point| {
+                let cell_status = if transient.contains(out_point) {
+                    CellStatus::new_dead(sequence, sequence)
+                } else {
+                    CellStatus::new_live(sequence)
+                };
+                let leaf = (out_point, &cell_status).into();
+                (
+                    hashed_element_key(ELEMENT_KEY, out_point),
+                    cell_status,
+                    leaf,
+                )
+            })
+            .collect();
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+
+        let start = Instant::now();
+        let mut positions = Vec::with_capacity(leaves.len());
+        for (_key, _cell_status, leaf) in &leaves {
+            positions.push(self.mmr.push(leaf.clone())?);
+        }
+        self.stage_times.record(Stage::TreeUpdate, start.elapsed());
+
+        // since mmr only store the hash of the element, we need to store the element <=> pos mapping by ourselves
+        let start = Instant::now();
+        for ((key, cell_status, leaf), pos) in leaves.iter().zip(positions) {
+            self.mmr
+                .store_mut()
+                .put_element(key, pos, cell_status, leaf)?;
+        }
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+        Ok(())
+    }
+}
+
+impl<D, WO> GenericMMRAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    /// Opens the state at `sequence`, which must not be older than the horizon of the last garbage collection of the
+    /// element index, see `gc_element_index`.
+    pub fn new_with_sequence(db: D, sequence: u64) -> Result<Self, Error> {
+        let horizon = gc_horizon(&*db).map_err(|e| Error::StoreError(e.to_string()))?;
+        if let Some(horizon) = horizon.filter(|horizon| sequence < *horizon) {
+            return Err(Error::StoreError(format!(
+                "sequence {} is older than the gc horizon {}",
+                sequence, horizon
+            )));
+        }
         let store = DefaultStore::new_with_sequence(db, sequence);
         let mmr_size = store
             .get(MMR_SIZE_KEY)
             .map(|slice| u64::from_le_bytes(slice.as_ref().try_into().expect("checked length")))
             .unwrap_or_default();
         let mmr = MMR::new(mmr_size, store);
-        Ok(MMRAccumulator { mmr })
+        Ok(GenericMMRAccumulator {
+            mmr,
+            strict: false,
+            flush_stats: FlushStats::default(),
+            stage_times: StageTimes::default(),
+            coalesced_ops: 0,
+            uncommitted: false,
+        })
+    }
+
+    /// Returns the commitment of the state seen by this accumulator, i.e. of the sequence it was opened with.
+    pub fn commitment(&self) -> Result<AccumulatorCommitment, AccumulatorError> {
+        Ok(AccumulatorCommitment {
+            root: self.mmr.get_root()?,
+            sequence: self.mmr.store().sequence(),
+        })
+    }
+
+    /// Returns the commitment that `commit` would return after applying `block`, without persisting anything: the
+    /// leaves are pushed and updated in a MMR over a `MMROverlayStore` which is dropped. The MMR keeps its pending
+    /// leaves in memory, so the writes of this accumulator must be committed first. Strict mode errors of the block are
+    /// reported here.
+    pub fn preview_root(
+        &self,
+        mut block: BlockOps,
+    ) -> Result<AccumulatorCommitment, AccumulatorError> {
+        if self.uncommitted {
+            return Err(AccumulatorError::InternalError(
+                "the writes of the mmr must be committed before a preview".to_string(),
+            ));
+        }
+        let transient = block.take_transient();
+        let store = self.mmr.store();
+        let sequence = store.sequence();
+        let mut mmr =
+            MMR::<H256, MergeH256, _>::new(self.mmr.mmr_size(), MMROverlayStore::new(store));
+        let mut out_points = HashSet::with_capacity(block.added.len());
+        for (i, out_point) in block.added.iter().enumerate() {
+            let key = hashed_element_key(ELEMENT_KEY, out_point);
+            if self.strict && (store.get(&key).is_some() || !out_points.insert(out_point)) {
+                return Err(AccumulatorError::AlreadyExists(i));
+            }
+            let cell_status = if transient.contains(out_point) {
+                CellStatus::new_dead(sequence, sequence)
+            } else {
+                CellStatus::new_live(sequence)
+            };
+            mmr.push((out_point, &cell_status).into())?;
+        }
+        let mut consumed = HashSet::with_capacity(block.deleted.len());
+        for (i, out_point) in block.deleted.iter().enumerate() {
+            let key = hashed_element_key(ELEMENT_KEY, out_point);
+            match store.get_element(&key)? {
+                Some((pos, mut cell_status)) => {
+                    if self.strict && (!cell_status.is_live() || !consumed.insert(out_point)) {
+                        return Err(AccumulatorError::ElementNotFound(i));
+                    }
+                    cell_status.mark_as_dead(sequence);
+                    mmr.update(pos, (out_point, &cell_status).into())?;
+                }
+                None => return Err(AccumulatorError::ElementNotFound(i)),
+            }
+        }
+        Ok(AccumulatorCommitment {
+            root: mmr.get_root()?,
+            sequence,
+        })
+    }
+
+    /// Returns the status of an element from its element record, as seen at the sequence of this accumulator,
+    /// `None` if the element doesn't exist yet.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        let key = hashed_element_key(ELEMENT_KEY, out_point);
+        Ok(self
+            .mmr
+            .store()
+            .get_element(&key)?
+            .map(|(_pos, status)| status))
+    }
+
+    /// Returns the leaf position of an element, `None` if the element doesn't exist yet, see `PositionIndex`.
+    pub fn position(&self, out_point: &OutPoint) -> Result<Option<u64>, AccumulatorError> {
+        let key = hashed_element_key(ELEMENT_KEY, out_point);
+        Ok(self
+            .mmr
+            .store()
+            .get_element(&key)?
+            .map(|(pos, _status)| pos))
+    }
+
+    /// Generates a single batched proof that all the given out_points are live at the given sequence,
+    /// which is the typical query of a wallet proving its whole set of cells to a light client.
+    pub fn wallet_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<WalletProof, AccumulatorError> {
+        let accumulator = Self::new_with_sequence(db, sequence)?;
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key = hashed_element_key(ELEMENT_KEY, &out_point);
+            match accumulator.mmr.store().get_element(&key)? {
+                Some((pos, status)) => {
+                    if !status.is_live() {
+                        return Err(AccumulatorError::ElementNotFound(i));
+                    }
+                    leaves.push((pos, out_point, status));
+                }
+                None => return Err(AccumulatorError::ElementNotFound(i)),
+            }
+        }
+
+        // sibling hashes are shared between positions when they are sorted
+        leaves.sort_unstable_by_key(|(pos, _, _)| *pos);
+        leaves.dedup_by_key(|(pos, _, _)| *pos);
+        let pos_list: Vec<u64> = leaves.iter().map(|(pos, _, _)| *pos).collect();
+        let proof = accumulator.mmr.gen_proof(pos_list.clone())?;
+        Ok(WalletProof {
+            sequence,
+            elements: leaves
+                .into_iter()
+                .map(|(_, out_point, status)| (out_point, status))
+                .collect(),
+            pos_list,
+            proof,
+        })
+    }
+}
+
+// only the SMT backends count their node writes per level
+impl<D, WO> LevelWrites for GenericMMRAccumulator<D, WO> {}
+
+impl<D, WO> FlushWrites for GenericMMRAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: Put<WO>,
+{
+    fn set_flush_mode(&mut self, mode: FlushMode) -> Result<(), AccumulatorError> {
+        Ok(self.mmr.store_mut().set_flush_mode(mode)?)
+    }
+
+    fn flush(&mut self) -> Result<(), AccumulatorError> {
+        let (entries, bytes) = self.mmr.store_mut().flush()?;
+        if entries > 0 {
+            self.flush_stats.record_flush(entries, bytes);
+        }
+        Ok(())
+    }
+
+    fn flush_stats(&self) -> Option<&FlushStats> {
+        Some(&self.flush_stats)
     }
 }
 
-impl<'a, DB, WO> AccumulatorReader for MMRAccumulator<'a, DB, WO>
+impl<D, WO> CoalescedOps for GenericMMRAccumulator<D, WO> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        Some(self.coalesced_ops)
+    }
+}
+
+impl<D, WO> CacheLiveCells for GenericMMRAccumulator<D, WO> {}
+
+impl<D, WO> IndexedElements for GenericMMRAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn element_index_stats(&self) -> Option<&ElementIndexStats> {
+        Some(self.mmr.store().element_index().stats())
+    }
+}
+
+// a leaf hashes the out_point, which the hashed keys of the element index don't keep, so the mmr can't sweep its cells
+impl<D, WO> SweepCells for GenericMMRAccumulator<D, WO> {}
+
+impl<D, WO> StageTiming for GenericMMRAccumulator<D, WO> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        Some(&self.stage_times)
+    }
+}
+
+impl<D, WO> TraceKeys for GenericMMRAccumulator<D, WO> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.mmr.store_mut().set_key_tracer(tracer);
+    }
+}
+
+impl<D, WO> AccumulatorReader for GenericMMRAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
 {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
@@ -183,14 +542,8 @@ where
 
         let mut pos_list: Vec<_> = Vec::with_capacity(elements.len());
         for (i, out_point) in elements.iter().enumerate() {
-            let key = [
-                ELEMENT_KEY,
-                out_point.hash().as_ref(),
-                out_point.index.to_le_bytes().as_ref(),
-            ]
-            .concat();
-            if let Some(slice) = self.mmr.store().get(&key) {
-                let pos = u64::from_le_bytes(slice[0..8].try_into().expect("checked length"));
+            let key = hashed_element_key(ELEMENT_KEY, out_point);
+            if let Some((pos, _status)) = self.mmr.store().get_element(&key)? {
                 pos_list.push(pos);
             } else {
                 return Err(AccumulatorError::ElementNotFound(i));
@@ -211,6 +564,17 @@ pub struct AccumulatorCommitment {
     sequence: u64,
 }
 
+impl fmt::Debug for AccumulatorCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        debug_commitment(
+            f,
+            self.sequence,
+            self.root.as_ref(),
+            &self.commitment_digest(),
+        )
+    }
+}
+
 impl AccumulatorCommitment {
     pub fn root(&self) -> &H256 {
         &self.root
@@ -219,6 +583,28 @@ impl AccumulatorCommitment {
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    /// See `crate::commitment_digest`, tagged with `COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        commitment_digest(COMMITMENT_TAG, self.root.as_ref(), self.sequence)
+    }
+
+    /// Serializes the commitment as root || sequence as u64 LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.root.as_ref(), self.sequence.to_le_bytes().as_ref()].concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 40 {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let root: [u8; 32] = slice[0..32].try_into().expect("checked length");
+        let sequence = u64::from_le_bytes(slice[32..40].try_into().expect("checked length"));
+        Ok(AccumulatorCommitment {
+            root: H256(root),
+            sequence,
+        })
+    }
 }
 
 pub struct AccumulatorProof {
@@ -226,6 +612,14 @@ pub struct AccumulatorProof {
     pos_list: Vec<u64>,
 }
 
+impl AccumulatorProof {
+    /// The leaf positions of the proved elements, which the verifier takes on trust unless they are checked against a
+    /// `PositionProof`.
+    pub fn pos_list(&self) -> &[u64] {
+        &self.pos_list
+    }
+}
+
 impl Proof for AccumulatorProof {
     type Item = (OutPoint, CellStatus);
 
@@ -248,9 +642,102 @@ impl Proof for AccumulatorProof {
             })
             .collect();
 
-        self.inner
-            .verify(commitment.root, leaves)
-            .map_err(Into::into)
+        let root = self.inner.calculate_root(leaves)?;
+        Ok(ct_eq(root.as_ref(), commitment.root.as_ref()))
+    }
+}
+
+/// A batched proof of many live cells at one commitment, see `MMRAccumulator::wallet_proof`.
+pub struct WalletProof {
+    sequence: u64,
+    elements: Vec<(OutPoint, CellStatus)>,
+    pos_list: Vec<u64>,
+    proof: MerkleProof<H256, MergeH256>,
+}
+
+impl WalletProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        &self.elements
+    }
+
+    /// The leaf positions of the elements, see `AccumulatorProof::pos_list`.
+    pub fn pos_list(&self) -> &[u64] {
+        &self.pos_list
+    }
+
+    pub fn verify(&self, commitment: &AccumulatorCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence
+            || !self.elements.iter().all(|(_, status)| status.is_live())
+        {
+            return Ok(false);
+        }
+        let leaves = self
+            .pos_list
+            .iter()
+            .zip(self.elements.iter())
+            .map(|(pos, (out_point, status))| (*pos, (out_point, status).into()))
+            .collect();
+        let root = self.proof.calculate_root(leaves)?;
+        Ok(ct_eq(root.as_ref(), commitment.root.as_ref()))
+    }
+
+    /// Serializes the proof as `sequence (8) | count (4) | count * (pos (8) | tx_hash (32) | index (4) | status (16)) | mmr_size (8) | proof items (32 each)`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret =
+            Vec::with_capacity(20 + self.elements.len() * 60 + self.proof.proof_items().len() * 32);
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.elements.len() as u32).to_le_bytes());
+        for (pos, (out_point, status)) in self.pos_list.iter().zip(self.elements.iter()) {
+            ret.extend_from_slice(&pos.to_le_bytes());
+            ret.extend_from_slice(&out_point.to_bytes());
+            ret.extend_from_slice(&status.block_numbers);
+        }
+        ret.extend_from_slice(&self.proof.mmr_size().to_le_bytes());
+        for item in self.proof.proof_items() {
+            ret.extend_from_slice(item.as_ref());
+        }
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() < 12 {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        let sequence = u64::from_le_bytes(slice[0..8].try_into().expect("checked length"));
+        let count = u32::from_le_bytes(slice[8..12].try_into().expect("checked length")) as usize;
+        let items_offset = count
+            .checked_mul(60)
+            .and_then(|len| len.checked_add(12 + 8))
+            .filter(|offset| *offset <= slice.len() && (slice.len() - offset) % 32 == 0)
+            .ok_or(AccumulatorError::InvalidProof)?;
+        let (pos_list, elements) = slice[12..items_offset - 8]
+            .chunks_exact(60)
+            .map(|chunk| {
+                let pos = u64::from_le_bytes(chunk[0..8].try_into().expect("checked length"));
+                let out_point = OutPoint::from_bytes(&chunk[8..44]).expect("checked length");
+                let block_numbers = chunk[44..60].try_into().expect("checked length");
+                (pos, (out_point, CellStatus { block_numbers }))
+            })
+            .unzip();
+        let mmr_size = u64::from_le_bytes(
+            slice[items_offset - 8..items_offset]
+                .try_into()
+                .expect("checked length"),
+        );
+        let proof_items = slice[items_offset..]
+            .chunks_exact(32)
+            .map(|chunk| H256(chunk.try_into().expect("checked length")))
+            .collect();
+        Ok(WalletProof {
+            sequence,
+            elements,
+            pos_list,
+            proof: MerkleProof::new(mmr_size, proof_items),
+        })
     }
 }
 
@@ -0,0 +1,40 @@
+use super::{
+    accumulator::{MergeH256, H256},
+    store::{DefaultStore, MMR_SIZE_KEY},
+};
+use crate::{storage::traits::ReadStore, AccumulatorError};
+use merkle_mountain_range::MMR;
+use rocksdb::prelude::Put;
+
+/// Builds the mmr of an empty database from pre-hashed `(key, value hash)` leaves and commits it as sequence 0,
+/// returns the root. The leaves are pushed in order, the mmr has no faster bulk path since its nodes only depend on the
+/// positions, and the keys are ignored. No element records are written, so the leaves can't be proved by out_point.
+pub fn build_tree<DB, WO>(
+    db: &DB,
+    leaves: Vec<([u8; 32], [u8; 32])>,
+) -> Result<[u8; 32], AccumulatorError>
+where
+    DB: ReadStore + Put<WO>,
+{
+    if leaves.is_empty() {
+        return Err(AccumulatorError::InternalError(
+            "no leaves to build the tree from".to_string(),
+        ));
+    }
+    let store = DefaultStore::<_, WO>::new(db);
+    if store.sequence() != 0 {
+        return Err(AccumulatorError::InternalError(
+            "the database is not empty".to_string(),
+        ));
+    }
+    let mut mmr = MMR::<H256, MergeH256, _>::new(0, store);
+    for (_key, hash) in leaves {
+        mmr.push(H256::from(hash))?;
+    }
+    let root = mmr.get_root()?;
+    let mmr_size = mmr.mmr_size();
+    mmr.commit()?;
+    mmr.store_mut().put(MMR_SIZE_KEY, mmr_size.to_le_bytes())?;
+    mmr.store_mut().commit()?;
+    Ok(root.as_ref().try_into().expect("checked length"))
+}
@@ -0,0 +1,173 @@
+use super::{
+    accumulator::{MergeH256, H256},
+    gc::GC_HORIZON_KEY,
+    is_versioned_key,
+    store::{
+        checked_record_sequence, parse_element_record, stored_element_integrity,
+        verify_element_record, DefaultStore, CHECKED_ELEMENT_RECORD_LEN, ELEMENT_RECORD_LEN,
+        MMR_SIZE_KEY, POS_KEY,
+    },
+};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    keycodec::parse_position_key,
+    sequence_width::stored_sequence_width,
+    storage::traits::ReadStore,
+    AccumulatorError,
+};
+use merkle_mountain_range::Merge;
+use std::collections::{HashMap, HashSet};
+
+/// Checks the internal consistency of a MMR accumulator database at its last committed sequence:
+/// every position below the mmr size must be stored and every parent must rehash from its children,
+/// and the element index must map each element to a distinct leaf position, one element per leaf. With the element
+/// integrity mode every record must also carry a valid checksum over the leaf at its position.
+pub fn fsck<DB>(db: &DB) -> Result<FsckReport, AccumulatorError>
+where
+    DB: ReadStore,
+{
+    let mut report = FsckReport::default();
+    let sequence = match DefaultStore::<_, ()>::new(db).sequence().checked_sub(1) {
+        Some(sequence) => sequence,
+        None => return Ok(report),
+    };
+    report.sequence = Some(sequence);
+
+    let mmr_size = DefaultStore::<_, ()>::new_with_sequence(db, sequence)
+        .get(MMR_SIZE_KEY)
+        .map(|slice| u64::from_le_bytes(slice.as_ref().try_into().expect("checked length")))
+        .unwrap_or_default();
+
+    let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+    let width = stored_sequence_width(db).map_err(map_err)?;
+    let element_integrity = stored_element_integrity(db).map_err(map_err)?;
+    let mut nodes = HashMap::new();
+    let mut elements = Vec::new();
+    for (key, value) in latest_versions(
+        db,
+        sequence,
+        width,
+        |key| is_versioned_key(key, width),
+        |key| !key.starts_with(MMR_SIZE_KEY),
+        &mut report,
+    ) {
+        if key.starts_with(POS_KEY) {
+            let pos = parse_position_key(POS_KEY, &key).expect("checked length");
+            if pos >= mmr_size {
+                report.errors.push(format!(
+                    "node at position {} beyond the mmr size {}",
+                    pos, mmr_size
+                ));
+            } else if value.len() != 32 {
+                report
+                    .errors
+                    .push(format!("node at position {} is not a hash", pos));
+            } else {
+                nodes.insert(pos, H256::from(value));
+            }
+        } else {
+            elements.push((key, value));
+        }
+    }
+
+    let mut leaves = HashSet::new();
+    for pos in 0..mmr_size {
+        let node = match nodes.get(&pos) {
+            Some(node) => node,
+            None => {
+                report
+                    .errors
+                    .push(format!("missing node at position {}", pos));
+                continue;
+            }
+        };
+        let height = pos_height_in_tree(pos);
+        if height == 0 {
+            leaves.insert(pos);
+            continue;
+        }
+        // the right child is right before its parent, the left child is the root of a perfect tree of height - 1 before it
+        let left = pos - (1 << height);
+        let right = pos - 1;
+        if let (Some(left), Some(right)) = (nodes.get(&left), nodes.get(&right)) {
+            if &MergeH256::merge(left, right)? != node {
+                report.errors.push(format!(
+                    "node at position {} doesn't rehash from its children",
+                    pos
+                ));
+            }
+        }
+    }
+    report.leaves = leaves.len() as u64;
+
+    let mut indexed = HashSet::with_capacity(elements.len());
+    for (key, value) in elements {
+        report.elements += 1;
+        if value.len() != ELEMENT_RECORD_LEN && value.len() != CHECKED_ELEMENT_RECORD_LEN {
+            report.errors.push(format!(
+                "element has an invalid value length: {}",
+                value.len()
+            ));
+            continue;
+        }
+        let (pos, _status) = parse_element_record(&value);
+        if element_integrity {
+            match (checked_record_sequence(&value), nodes.get(&pos)) {
+                (None, _) => report
+                    .errors
+                    .push(format!("element at position {} has no checksum", pos)),
+                (Some(written), _) if written > sequence => report.errors.push(format!(
+                    "element at position {} was written at sequence {} after the last commit",
+                    pos, written
+                )),
+                (Some(_), Some(leaf)) if !verify_element_record(&key, &value, leaf) => {
+                    report.errors.push(format!(
+                        "element at position {} doesn't match the leaf hash",
+                        pos
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if !leaves.contains(&pos) {
+            report.errors.push(format!(
+                "element points to position {} which is not a leaf",
+                pos
+            ));
+        } else if !indexed.insert(pos) {
+            report
+                .errors
+                .push(format!("more than one element points to leaf {}", pos));
+        }
+    }
+    // the element index of consumed cells may have been garbage collected
+    let collected = db.get(GC_HORIZON_KEY).map_err(map_err)?.is_some();
+    if indexed.len() != leaves.len() && !collected {
+        report.errors.push(format!(
+            "{} leaves but {} indexed elements",
+            leaves.len(),
+            indexed.len()
+        ));
+    }
+    Ok(report)
+}
+
+// mirrors `pos_height_in_tree` of the MMR crate: jumps left until the position is the peak of a perfect tree,
+// the height is then given by the number of bits of the position
+fn pos_height_in_tree(pos: u64) -> u32 {
+    fn all_ones(num: u64) -> bool {
+        num != 0 && num.count_zeros() == num.leading_zeros()
+    }
+
+    fn jump_left(pos: u64) -> u64 {
+        let bit_length = 64 - pos.leading_zeros();
+        let most_significant_bits = 1 << (bit_length - 1);
+        pos - (most_significant_bits - 1)
+    }
+
+    let mut pos = pos + 1;
+    while !all_ones(pos) {
+        pos = jump_left(pos);
+    }
+    64 - pos.leading_zeros() - 1
+}
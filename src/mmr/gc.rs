@@ -0,0 +1,120 @@
+use super::{
+    store::{parse_element_record, ELEMENT_KEY},
+    ELEMENT_KEY_LEN,
+};
+use crate::{
+    sequence_width::stored_sequence_width,
+    storage::traits::{ReadStore, WriteStore},
+    AccumulatorError,
+};
+use rocksdb::{Direction, IteratorMode};
+use std::fmt;
+
+/// The oldest sequence which can still be read after a garbage collection, stored as u64 LE.
+pub const GC_HORIZON_KEY: &[u8] = &[4];
+
+/// Returns the horizon of the last garbage collection of the database, `None` if it was never collected.
+pub fn gc_horizon<DB>(db: &DB) -> Result<Option<u64>, rocksdb::Error>
+where
+    DB: ReadStore + ?Sized,
+{
+    Ok(db
+        .get(GC_HORIZON_KEY)?
+        .map(|v| u64::from_le_bytes(v.as_ref().try_into().expect("checked length"))))
+}
+
+/// What a garbage collection of the element index deleted.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    // Versions superseded before the horizon.
+    pub versions: u64,
+    // Dead cells consumed before the horizon, all their versions are deleted.
+    pub dead_cells: u64,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gc deleted {} superseded versions, {} dead cells",
+            self.versions, self.dead_cells
+        )
+    }
+}
+
+/// Cleans the element index of a MMR database whose last committed sequence is `sequence`, keeping the history of the last
+/// `retention` sequences: versions superseded at or before the horizon `sequence - retention` are deleted, and so are the
+/// cells consumed before it. Readers at the horizon or later see the same index, older ones can't be opened anymore and the
+/// consumed cells become unknown, e.g. a non-strict delete of such a cell fails. The leaves of the MMR are kept.
+pub fn gc_element_index<DB, WO>(
+    db: &DB,
+    sequence: u64,
+    retention: u64,
+) -> Result<GcReport, AccumulatorError>
+where
+    DB: WriteStore<WO>,
+{
+    let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+    let mut report = GcReport::default();
+    let horizon = match sequence.checked_sub(retention) {
+        Some(horizon) => horizon,
+        None => return Ok(report),
+    };
+    let width = stored_sequence_width(db).map_err(map_err)?;
+
+    // the committed versions of the current element, versions are big endian encoded so they are iterated in order
+    let mut versions: Vec<(u64, Box<[u8]>, Box<[u8]>)> = Vec::new();
+    let mut deleted = Vec::new();
+    let mut collect = |versions: &mut Vec<(u64, Box<[u8]>, Box<[u8]>)>| {
+        let consumed = versions.last().and_then(|(version, _key, value)| {
+            Some(*version).filter(|_| !parse_element_record(value).1.is_live())
+        });
+        match consumed {
+            Some(consumed) if consumed < horizon => {
+                report.dead_cells += 1;
+                deleted.extend(versions.drain(..).map(|(_version, key, _value)| key));
+            }
+            _ => {
+                // the latest version at or before the horizon is still read by the readers at the horizon
+                let retained = versions
+                    .iter()
+                    .rposition(|(version, _key, _value)| *version <= horizon)
+                    .unwrap_or(0);
+                report.versions += retained as u64;
+                deleted.extend(
+                    versions
+                        .drain(..retained)
+                        .map(|(_version, key, _value)| key),
+                );
+            }
+        }
+        versions.clear();
+    };
+
+    let mode = IteratorMode::From(ELEMENT_KEY, Direction::Forward);
+    for (key, value) in db
+        .iterator(mode)
+        .take_while(|(key, _value)| key.starts_with(ELEMENT_KEY))
+    {
+        if key.len() != ELEMENT_KEY_LEN + width.encoded_len() {
+            continue;
+        }
+        let (prefix, version) = width.split(&key);
+        if !matches!(versions.last(), Some((_, last_key, _)) if last_key.starts_with(prefix)) {
+            collect(&mut versions);
+        }
+        // uncommitted versions are left alone
+        if version <= sequence {
+            versions.push((version, key, value));
+        }
+    }
+    collect(&mut versions);
+
+    for key in deleted {
+        db.delete(key).map_err(map_err)?;
+    }
+    let previous = gc_horizon(db).map_err(map_err)?.unwrap_or_default();
+    db.put(GC_HORIZON_KEY, horizon.max(previous).to_le_bytes())
+        .map_err(map_err)?;
+    Ok(report)
+}
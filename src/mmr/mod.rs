@@ -1,4 +1,47 @@
+use crate::{
+    element_index::{ElementIndex, ElementKeying, RecordEncoding},
+    keycodec::hashed_element_key,
+    sequence_width::SequenceWidth,
+    OutPoint,
+};
+use store::{ELEMENT_KEY, MMR_SIZE_KEY, POS_KEY};
+
 pub mod accumulator;
+pub mod bulk;
+pub mod fsck;
+pub mod gc;
+pub mod position_index;
 mod store;
+pub mod sync;
 #[cfg(test)]
 mod tests;
+
+pub use store::set_element_integrity;
+
+// all keys are versioned: nodes are keyed by their position, elements by the out_point hash and index
+pub(crate) const POS_KEY_LEN: usize = 1 + 8;
+pub(crate) const ELEMENT_KEY_LEN: usize = 1 + 32 + 4;
+pub(crate) const MMR_SIZE_KEY_LEN: usize = 1;
+
+/// The key of the element index record of an out_point, without its version.
+pub fn element_key(out_point: &OutPoint) -> Vec<u8> {
+    hashed_element_key(ELEMENT_KEY, out_point)
+}
+
+/// The element index of this backend, keyed by out_point hash with the positions and statuses of the elements.
+pub fn element_index() -> ElementIndex {
+    ElementIndex::new(
+        ELEMENT_KEY,
+        ElementKeying::Hashed,
+        RecordEncoding::Positioned,
+    )
+}
+
+/// Whether a stored key is a versioned key of this backend, i.e. a node, element or mmr size key suffixed with a sequence
+/// of `width`.
+pub fn is_versioned_key(key: &[u8], width: SequenceWidth) -> bool {
+    let len = key.len().saturating_sub(width.encoded_len());
+    (key.starts_with(POS_KEY) && len == POS_KEY_LEN)
+        || (key.starts_with(ELEMENT_KEY) && len == ELEMENT_KEY_LEN)
+        || (key.starts_with(MMR_SIZE_KEY) && len == MMR_SIZE_KEY_LEN)
+}
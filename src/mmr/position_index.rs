@@ -0,0 +1,540 @@
+use std::ops::Deref;
+
+use super::accumulator::{
+    AccumulatorCommitment, AccumulatorProof, GenericMMRAccumulator, WalletProof,
+};
+use crate::{
+    commitment_digest, ct_eq,
+    metrics::{
+        CoalescedOps, FlushMode, FlushStats, FlushWrites, KeyTracer, LevelWrites, StageTimes,
+        StageTiming, TraceKeys,
+    },
+    new_blake2b,
+    smt::store::DefaultStore,
+    storage::traits::{ReadStore, WriteStore},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof,
+};
+use rocksdb::prelude::Put;
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, traits::Value, CompiledMerkleProof, MerkleProof, SparseMerkleTree, H256,
+};
+
+/// Tags the commitment digests of a MMR commitment folded with its position index root.
+pub const POSITIONED_COMMITMENT_TAG: &[u8] = b"mmr-positioned";
+
+/// The leaf position of an out_point, stored as position + 1 (u64 LE) so that the first leaf isn't the zero value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LeafPosition([u8; 8]);
+
+impl LeafPosition {
+    pub fn new(pos: u64) -> Self {
+        LeafPosition((pos + 1).to_le_bytes())
+    }
+
+    /// The position of the leaf, `None` for an out_point which isn't indexed.
+    pub fn pos(&self) -> Option<u64> {
+        u64::from_le_bytes(self.0).checked_sub(1)
+    }
+}
+
+impl Value for LeafPosition {
+    fn to_h256(&self) -> H256 {
+        if self.0 == [0u8; 8] {
+            return H256::zero();
+        }
+        let mut buf = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&self.0);
+        hasher.finalize(&mut buf);
+        buf.into()
+    }
+
+    fn zero() -> Self {
+        LeafPosition::default()
+    }
+}
+
+impl From<Box<[u8]>> for LeafPosition {
+    fn from(vec: Box<[u8]>) -> Self {
+        LeafPosition(vec[..].try_into().expect("checked length"))
+    }
+}
+
+impl AsRef<[u8]> for LeafPosition {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+fn leaf_key(out_point: &OutPoint) -> H256 {
+    out_point.hash().into()
+}
+
+// the leaves of the index claimed by the out_points at the positions of the same index
+fn position_leaves(
+    out_points: &[OutPoint],
+    pos_list: &[u64],
+) -> Result<Vec<(H256, H256)>, AccumulatorError> {
+    if out_points.len() != pos_list.len() {
+        return Err(AccumulatorError::InvalidProof);
+    }
+    Ok(out_points
+        .iter()
+        .zip(pos_list)
+        .map(|(out_point, pos)| (leaf_key(out_point), LeafPosition::new(*pos).to_h256()))
+        .collect())
+}
+
+/// A secondary accumulator committing to `out_point -> leaf position` of a MMR accumulator, a SMT keyed by the out_point
+/// hash. A leaf never moves once it's pushed, so only the added cells of a block are indexed. Like the script index its
+/// keys collide with the keys of the cell accumulators, so it's stored in a column family of its own, and it's committed
+/// once per block to stay at the sequence of the MMR, see `PositionedMMRAccumulator`.
+pub struct PositionIndex<D, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, LeafPosition, DefaultStore<D, WO>>,
+}
+
+impl<D, WO> PositionIndex<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    pub fn new_with_sequence(db: D, sequence: u64) -> Result<Self, AccumulatorError> {
+        let smt = SparseMerkleTree::new_with_store(DefaultStore::new_with_sequence(db, sequence))?;
+        Ok(PositionIndex { smt })
+    }
+
+    pub fn root(&self) -> &H256 {
+        self.smt.root()
+    }
+
+    /// The leaf position of an out_point, `None` if it isn't indexed.
+    pub fn position(&self, out_point: &OutPoint) -> Result<Option<u64>, AccumulatorError> {
+        Ok(self.smt.get(&leaf_key(out_point))?.pos())
+    }
+
+    /// Generates a proof of the leaf positions of the given out_points, to be checked along the `pos_list` of a MMR proof.
+    pub fn proof(&self, out_points: &[OutPoint]) -> Result<PositionProof, AccumulatorError> {
+        let keys = out_points.iter().map(leaf_key).collect();
+        Ok(PositionProof {
+            inner: self.smt.merkle_proof(keys)?,
+        })
+    }
+
+    /// Generates the proof of the leaf positions of the given out_points in its serializable form, see
+    /// `PositionedWalletProof`.
+    pub fn compiled_proof(
+        &self,
+        out_points: &[OutPoint],
+    ) -> Result<CompiledMerkleProof, AccumulatorError> {
+        let mut keys: Vec<H256> = out_points.iter().map(leaf_key).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(self.smt.merkle_proof(keys.clone())?.compile(keys)?)
+    }
+
+    /// Folds the root of the index into a MMR commitment of the same sequence.
+    pub fn positioned_commitment(&self, commitment: AccumulatorCommitment) -> PositionedCommitment {
+        PositionedCommitment {
+            commitment,
+            position_root: *self.root(),
+        }
+    }
+}
+
+impl<D, WO> PositionIndex<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    pub fn new(db: D) -> Result<Self, AccumulatorError> {
+        let smt = SparseMerkleTree::new_with_store(DefaultStore::new(db))?;
+        Ok(PositionIndex { smt })
+    }
+
+    /// Indexes the leaf positions of the cells added by a block and commits it, see `MMRAccumulator::position`.
+    /// Returns the new root.
+    pub fn apply_block(
+        &mut self,
+        positions: Vec<(OutPoint, u64)>,
+    ) -> Result<H256, AccumulatorError> {
+        let leaves = positions
+            .iter()
+            .map(|(out_point, pos)| (leaf_key(out_point), LeafPosition::new(*pos)))
+            .collect();
+        self.smt.update_all(leaves)?;
+        self.smt.store_mut().commit()?;
+        Ok(*self.smt.root())
+    }
+
+    /// See `AccumulatorWriter::skip_to`.
+    pub fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        Ok(self.smt.store_mut().skip_to(sequence)?)
+    }
+}
+
+/// A MMR accumulator maintaining its `PositionIndex`, whose commitments fold the root of the index and whose proofs
+/// prove the positions of their elements. The two handles must write to the same transaction or write batch, e.g. two
+/// `ColumnFamilyStore`s over two column families of one `OptimisticTransaction`, so that a block is stored in the MMR
+/// and in its index atomically.
+pub struct PositionedMMRAccumulator<D, C, WO> {
+    accumulator: GenericMMRAccumulator<D, WO>,
+    index: PositionIndex<C, WO>,
+    // the cells added since the last commit, indexed by the commit
+    added: Vec<OutPoint>,
+}
+
+impl<D, C, WO> PositionedMMRAccumulator<D, C, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+    C: Deref,
+    C::Target: WriteStore<WO>,
+{
+    pub fn new(db: D, index_db: C) -> Result<Self, AccumulatorError> {
+        Self::new_with_strict(db, index_db, false)
+    }
+
+    pub fn new_with_strict(db: D, index_db: C, strict: bool) -> Result<Self, AccumulatorError> {
+        let accumulator = GenericMMRAccumulator::new_with_strict(db, strict)?;
+        let index = PositionIndex::new(index_db)?;
+        Ok(PositionedMMRAccumulator {
+            accumulator,
+            index,
+            added: Vec::new(),
+        })
+    }
+}
+
+impl<D, C, WO> PositionedMMRAccumulator<D, C, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+    C: Deref,
+    C::Target: ReadStore,
+{
+    pub fn new_with_sequence(db: D, index_db: C, sequence: u64) -> Result<Self, AccumulatorError> {
+        let accumulator = GenericMMRAccumulator::new_with_sequence(db, sequence)?;
+        let index = PositionIndex::new_with_sequence(index_db, sequence)?;
+        Ok(PositionedMMRAccumulator {
+            accumulator,
+            index,
+            added: Vec::new(),
+        })
+    }
+
+    pub fn accumulator(&self) -> &GenericMMRAccumulator<D, WO> {
+        &self.accumulator
+    }
+
+    pub fn index(&self) -> &PositionIndex<C, WO> {
+        &self.index
+    }
+
+    /// Returns the commitment of the state seen by this accumulator, i.e. of the sequence it was opened with.
+    pub fn commitment(&self) -> Result<PositionedCommitment, AccumulatorError> {
+        Ok(self
+            .index
+            .positioned_commitment(self.accumulator.commitment()?))
+    }
+
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        self.accumulator.cell_status(out_point)
+    }
+
+    /// Generates a `MMRAccumulator::wallet_proof` of the out_points at the given sequence, together with the proof of
+    /// their leaf positions in the index of the same sequence.
+    pub fn wallet_proof(
+        db: D,
+        index_db: C,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<PositionedWalletProof, AccumulatorError> {
+        let proof = GenericMMRAccumulator::<D, WO>::wallet_proof(db, out_points, sequence)?;
+        let index = PositionIndex::<C, WO>::new_with_sequence(index_db, sequence)?;
+        let out_points: Vec<OutPoint> = proof
+            .elements()
+            .iter()
+            .map(|(out_point, _status)| out_point.clone())
+            .collect();
+        let positions = index.compiled_proof(&out_points)?;
+        Ok(PositionedWalletProof { proof, positions })
+    }
+}
+
+impl<D, C, WO> AccumulatorWriter for PositionedMMRAccumulator<D, C, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+    C: Deref,
+    C::Target: WriteStore<WO>,
+{
+    type Item = OutPoint;
+    type Commitment = PositionedCommitment;
+
+    fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        self.added.extend(elements.iter().cloned());
+        self.accumulator.add(elements)
+    }
+
+    fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        self.accumulator.delete(elements)
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        self.accumulator.update(elements)
+    }
+
+    fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let commitment = self.accumulator.commit()?;
+        let mut positions = Vec::with_capacity(self.added.len());
+        for (i, out_point) in self.added.drain(..).enumerate() {
+            let pos = self
+                .accumulator
+                .position(&out_point)?
+                .ok_or(AccumulatorError::ElementNotFound(i))?;
+            positions.push((out_point, pos));
+        }
+        self.index.apply_block(positions)?;
+        Ok(self.index.positioned_commitment(commitment))
+    }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        self.accumulator.skip_to(sequence)?;
+        self.index.skip_to(sequence)
+    }
+}
+
+// the metrics are the ones of the MMR, the index is a small tree updated once per block
+impl<D, C, WO> LevelWrites for PositionedMMRAccumulator<D, C, WO> {}
+
+impl<D, C, WO> FlushWrites for PositionedMMRAccumulator<D, C, WO>
+where
+    D: Deref,
+    D::Target: Put<WO>,
+{
+    fn set_flush_mode(&mut self, mode: FlushMode) -> Result<(), AccumulatorError> {
+        self.accumulator.set_flush_mode(mode)
+    }
+
+    fn flush(&mut self) -> Result<(), AccumulatorError> {
+        self.accumulator.flush()
+    }
+
+    fn flush_stats(&self) -> Option<&FlushStats> {
+        self.accumulator.flush_stats()
+    }
+}
+
+impl<D, C, WO> CoalescedOps for PositionedMMRAccumulator<D, C, WO> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        self.accumulator.coalesced_ops()
+    }
+}
+
+impl<D, C, WO> StageTiming for PositionedMMRAccumulator<D, C, WO> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        self.accumulator.stage_times()
+    }
+}
+
+impl<D, C, WO> TraceKeys for PositionedMMRAccumulator<D, C, WO> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.accumulator.trace_keys(tracer);
+    }
+}
+
+impl<D, C, WO> AccumulatorReader for PositionedMMRAccumulator<D, C, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+    C: Deref,
+    C::Target: ReadStore,
+{
+    type Item = OutPoint;
+    type Commitment = PositionedCommitment;
+    type Proof = PositionedProof;
+
+    fn proof(
+        &self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<Self::Proof, AccumulatorError> {
+        if &commitment.position_root != self.index.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let positions = self.index.proof(&elements)?;
+        let proof = self.accumulator.proof(commitment.commitment, elements)?;
+        Ok(PositionedProof { proof, positions })
+    }
+}
+
+/// A MMR commitment together with the root of its position index, see `PositionIndex`.
+#[derive(Clone, Debug)]
+pub struct PositionedCommitment {
+    commitment: AccumulatorCommitment,
+    position_root: H256,
+}
+
+impl PositionedCommitment {
+    pub fn commitment(&self) -> &AccumulatorCommitment {
+        &self.commitment
+    }
+
+    pub fn position_root(&self) -> &H256 {
+        &self.position_root
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.commitment.sequence()
+    }
+
+    /// See `crate::commitment_digest`, over the MMR root followed by the position root and tagged with
+    /// `POSITIONED_COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        let root = [
+            self.commitment.root().as_ref(),
+            self.position_root.as_slice(),
+        ]
+        .concat();
+        commitment_digest(POSITIONED_COMMITMENT_TAG, &root, self.commitment.sequence())
+    }
+
+    /// Serializes the commitment as the `AccumulatorCommitment::to_bytes` of the MMR followed by the position root.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.commitment.to_bytes().as_slice(),
+            self.position_root.as_slice(),
+        ]
+        .concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 72 {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let position_root: [u8; 32] = slice[40..72].try_into().expect("checked length");
+        Ok(PositionedCommitment {
+            commitment: AccumulatorCommitment::from_bytes(&slice[0..40])?,
+            position_root: position_root.into(),
+        })
+    }
+}
+
+/// A proof that out_points are at given leaf positions, see `PositionIndex::proof`.
+pub struct PositionProof {
+    inner: MerkleProof,
+}
+
+impl PositionProof {
+    /// Verifies that each out_point is at the position of the same index in `pos_list`, e.g. the `pos_list` of a MMR
+    /// proof of these out_points.
+    pub fn verify(
+        self,
+        position_root: &H256,
+        out_points: &[OutPoint],
+        pos_list: &[u64],
+    ) -> Result<bool, AccumulatorError> {
+        let leaves = position_leaves(out_points, pos_list)?;
+        let root = self.inner.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), position_root.as_slice()))
+    }
+}
+
+/// A MMR proof together with the proof of the leaf positions it claims, see `PositionedMMRAccumulator`.
+pub struct PositionedProof {
+    proof: AccumulatorProof,
+    positions: PositionProof,
+}
+
+impl PositionedProof {
+    pub fn pos_list(&self) -> &[u64] {
+        self.proof.pos_list()
+    }
+}
+
+impl Proof for PositionedProof {
+    type Item = (OutPoint, CellStatus);
+
+    type Commitment = PositionedCommitment;
+
+    /// Verifies the elements against the MMR root and their positions against the position root, both are checked
+    /// before the results are combined so that the time taken doesn't tell which one failed.
+    fn verify(
+        self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<bool, AccumulatorError> {
+        let out_points: Vec<_> = elements
+            .iter()
+            .map(|(out_point, _status)| out_point.clone())
+            .collect();
+        let pos_list = self.proof.pos_list().to_vec();
+        let positioned =
+            self.positions
+                .verify(&commitment.position_root, &out_points, &pos_list)?;
+        let proved = self.proof.verify(commitment.commitment, elements)?;
+        Ok(positioned & proved)
+    }
+}
+
+/// A MMR wallet proof together with the proof of the leaf positions it claims, see
+/// `PositionedMMRAccumulator::wallet_proof`.
+pub struct PositionedWalletProof {
+    proof: WalletProof,
+    positions: CompiledMerkleProof,
+}
+
+impl PositionedWalletProof {
+    pub fn sequence(&self) -> u64 {
+        self.proof.sequence()
+    }
+
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        self.proof.elements()
+    }
+
+    /// Verifies the wallet proof against the MMR commitment and its positions against the position root, both are
+    /// checked before the results are combined, like `PositionedProof::verify`.
+    pub fn verify(&self, commitment: &PositionedCommitment) -> Result<bool, AccumulatorError> {
+        let out_points: Vec<OutPoint> = self
+            .elements()
+            .iter()
+            .map(|(out_point, _status)| out_point.clone())
+            .collect();
+        // the compiled proof is checked in key order
+        let mut leaves = position_leaves(&out_points, self.proof.pos_list())?;
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        let root = self.positions.compute_root::<Blake2bHasher>(leaves)?;
+        let positioned = ct_eq(root.as_slice(), commitment.position_root.as_slice());
+        let proved = self.proof.verify(&commitment.commitment)?;
+        Ok(positioned & proved)
+    }
+
+    /// Serializes the proof as `wallet proof length (4) | WalletProof::to_bytes | compiled position proof`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let proof = self.proof.to_bytes();
+        let mut ret = Vec::with_capacity(4 + proof.len() + self.positions.0.len());
+        ret.extend_from_slice(&(proof.len() as u32).to_le_bytes());
+        ret.extend_from_slice(&proof);
+        ret.extend_from_slice(&self.positions.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() < 4 {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        let len = u32::from_le_bytes(slice[0..4].try_into().expect("checked length")) as usize;
+        let end = len
+            .checked_add(4)
+            .filter(|end| *end <= slice.len())
+            .ok_or(AccumulatorError::InvalidProof)?;
+        Ok(PositionedWalletProof {
+            proof: WalletProof::from_bytes(&slice[4..end])?,
+            positions: CompiledMerkleProof(slice[end..].to_vec()),
+        })
+    }
+}
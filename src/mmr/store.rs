@@ -1,29 +1,167 @@
-use std::marker::PhantomData;
+use std::{collections::BTreeMap, marker::PhantomData, ops::Deref};
 
+use super::accumulator::H256;
+use crate::{
+    element_index::{
+        latest_versions, ElementIndex, ElementRecord, RecordEncoding, RecordStore, RecordStoreMut,
+    },
+    keycodec::position_key,
+    metrics::{FlushMode, KeyTracer},
+    new_blake2b,
+    sequence_width::{skipped_record, stored_sequence_width, SequenceWidth},
+    storage::traits::ReadStore,
+    AccumulatorError, CellStatus,
+};
 use merkle_mountain_range::{Error, MMRStoreReadOps, MMRStoreWriteOps};
-use rocksdb::{prelude::*, Direction, IteratorMode};
+use rocksdb::{prelude::Put, Direction, IteratorMode};
 
-const POS_KEY: &[u8] = &[0];
+pub const POS_KEY: &[u8] = &[0];
 const SEQUENCE_KEY: &[u8] = &[1];
 
 pub const ELEMENT_KEY: &[u8] = &[2];
 pub const MMR_SIZE_KEY: &[u8] = &[3];
+/// Whether the element records are checked, stored as one byte, it isn't versioned.
+const ELEMENT_INTEGRITY_KEY: &[u8] = &[5];
+
+/// The length of an element record, the position of the element (8 bytes LE) followed by its cell status (16 bytes).
+/// This is format version 0, which has no version byte to keep the records small, a future format must append a
+/// version byte so that the existing records are still told apart by their length.
+pub const ELEMENT_RECORD_LEN: usize = 8 + 16;
+
+/// The length of a checked element record, format version 1: the version 0 record followed by the version byte, the
+/// sequence it was written at (8 bytes LE) and a checksum binding it to the leaf hash at its position, see
+/// `set_element_integrity`.
+pub const CHECKED_ELEMENT_RECORD_LEN: usize = ELEMENT_RECORD_LEN + 1 + 8 + 32;
+const CHECKED_RECORD_VERSION: u8 = 1;
+
+pub fn element_record(pos: u64, status: &CellStatus) -> Vec<u8> {
+    RecordEncoding::Positioned.encode(&ElementRecord {
+        pos: Some(pos),
+        status: status.clone(),
+    })
+}
+
+pub fn checked_element_record(
+    key: &[u8],
+    pos: u64,
+    status: &CellStatus,
+    sequence: u64,
+    leaf: &H256,
+) -> Vec<u8> {
+    let mut record = element_record(pos, status);
+    record.push(CHECKED_RECORD_VERSION);
+    record.extend_from_slice(&sequence.to_le_bytes());
+    let checksum = element_checksum(key, &record, leaf);
+    record.extend_from_slice(&checksum);
+    record
+}
+
+// blake2b(element key || record up to the checksum || leaf hash at the position of the record)
+fn element_checksum(key: &[u8], record: &[u8], leaf: &H256) -> [u8; 32] {
+    let mut checksum = [0u8; 32];
+    let mut hasher = new_blake2b();
+    hasher.update(key);
+    hasher.update(record);
+    hasher.update(leaf.as_ref());
+    hasher.finalize(&mut checksum);
+    checksum
+}
+
+/// Returns the sequence a checked element record was written at, `None` for a version 0 record.
+pub fn checked_record_sequence(record: &[u8]) -> Option<u64> {
+    if record.len() != CHECKED_ELEMENT_RECORD_LEN {
+        return None;
+    }
+    let sequence = &record[ELEMENT_RECORD_LEN + 1..CHECKED_ELEMENT_RECORD_LEN - 32];
+    Some(u64::from_le_bytes(
+        sequence.try_into().expect("checked length"),
+    ))
+}
+
+/// Whether a checked element record matches the leaf hash at its position.
+pub fn verify_element_record(key: &[u8], record: &[u8], leaf: &H256) -> bool {
+    if record.len() != CHECKED_ELEMENT_RECORD_LEN {
+        return false;
+    }
+    let (checked, checksum) = record.split_at(CHECKED_ELEMENT_RECORD_LEN - 32);
+    element_checksum(key, checked, leaf) == checksum
+}
+
+/// Parses the position and status of an element record of either format version.
+pub fn parse_element_record(slice: &[u8]) -> (u64, CellStatus) {
+    assert!(
+        slice.len() == ELEMENT_RECORD_LEN
+            || (slice.len() == CHECKED_ELEMENT_RECORD_LEN
+                && slice[ELEMENT_RECORD_LEN] == CHECKED_RECORD_VERSION),
+        "unsupported element record format"
+    );
+    let record = RecordEncoding::Positioned
+        .decode(slice)
+        .expect("checked length");
+    (record.pos.expect("positioned record"), record.status)
+}
+
+/// Enables the checked element records of a MMR database, it must be set before the first commit and can't be changed
+/// afterwards. Each record then carries the sequence it was written at and a checksum over the element key, the record
+/// and the leaf hash at its position, which are verified on read and by `fsck`, so an element index and a tree which
+/// disagree after a partial write are caught instead of producing wrong proofs. It costs 41 bytes per record and a node
+/// read per element lookup.
+pub fn set_element_integrity<DB, WO>(db: &DB, enabled: bool) -> Result<(), AccumulatorError>
+where
+    DB: ReadStore + Put<WO>,
+{
+    let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+    if db.get(SEQUENCE_KEY).map_err(map_err)?.is_some()
+        && stored_element_integrity(db).map_err(map_err)? != enabled
+    {
+        return Err(AccumulatorError::InternalError(
+            "the element integrity mode can't be changed after the first commit".to_string(),
+        ));
+    }
+    db.put(ELEMENT_INTEGRITY_KEY, [enabled as u8])
+        .map_err(map_err)
+}
+
+pub fn stored_element_integrity<DB: ReadStore + ?Sized>(db: &DB) -> Result<bool, rocksdb::Error> {
+    Ok(matches!(
+        db.get(ELEMENT_INTEGRITY_KEY)?.as_deref(),
+        Some([1])
+    ))
+}
 
 /// A MMR `Store` implementation backed by a RocksDB database, using the default column family and supports historical queries.
-pub struct DefaultStore<'a, DB, WO> {
-    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
-    inner: &'a DB,
+pub struct DefaultStore<D, WO> {
+    // A handle to the RocksDB database which stores the data, can be a `&DB` / `&OptimisticTransaction` / `Arc<Snapshot>` etc.
+    inner: D,
     // The sequence number is used to support historical queries.
     sequence: u64,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<WO>,
+    // A generic write options, can be a `WriteOptions` / `()` etc, it's never stored so it doesn't affect `Send` / `Sync`.
+    write_options: PhantomData<fn() -> WO>,
+    // Traces the keys read and written by the store, disabled by default.
+    key_tracer: KeyTracer,
+    sequence_width: SequenceWidth,
+    // The first sequence skipped by `skip_to` since the last commit, the gap is recorded by the next commit.
+    skipped_from: Option<u64>,
+    // Whether element records are written with a checksum.
+    element_integrity: bool,
+    // The encoding and the counters of the element records, the checked records are handled by the store.
+    element_index: ElementIndex,
+    // The last committed sequence + 1 of the database, the element records written at or after it are not committed yet,
+    // their leaves may still be pending in the MMR.
+    committed: u64,
+    flush_mode: FlushMode,
+    // The writes buffered in the `Transaction` flush mode, by versioned key.
+    buffer: BTreeMap<Vec<u8>, Box<[u8]>>,
+    // The number and bytes of the MMR nodes inserted since the last `take_batch`.
+    batch: (u64, u64),
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
 {
-    pub fn new(db: &'a DB) -> Self {
+    pub fn new(db: D) -> Self {
         let sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -35,14 +173,26 @@ where
                 )
             })
             .unwrap_or(0);
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
+        let element_integrity =
+            stored_element_integrity(&*db).expect("init element integrity should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            element_integrity,
+            element_index: super::element_index(),
+            committed: sequence,
+            flush_mode: FlushMode::default(),
+            buffer: BTreeMap::new(),
+            batch: (0, 0),
         }
     }
 
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Self {
+    pub fn new_with_sequence(db: D, sequence: u64) -> Self {
         let stored_sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -57,10 +207,22 @@ where
         if sequence > stored_sequence {
             panic!("sequence number: {} should be less than or equal to the stored sequence number: {}", sequence, stored_sequence);
         }
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
+        let element_integrity =
+            stored_element_integrity(&*db).expect("init element integrity should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            element_integrity,
+            element_index: super::element_index(),
+            committed: stored_sequence,
+            flush_mode: FlushMode::default(),
+            buffer: BTreeMap::new(),
+            batch: (0, 0),
         }
     }
 
@@ -69,46 +231,291 @@ where
     }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO> {
+    /// Moves an empty store to `sequence`, so that a syncing client commits the synced state at the sequence of the
+    /// commitment it syncs to.
+    pub fn start_at(&mut self, sequence: u64) -> Result<(), Error> {
+        if self.sequence != 0 {
+            return Err(Error::StoreError("the database is not empty".to_string()));
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// Moves the store forward to `sequence` before the writes of a block, the sequences in between are never committed
+    /// and read as the state of the previous commit, see `SequenceSource::BlockNumber`.
+    pub fn skip_to(&mut self, sequence: u64) -> Result<(), Error> {
+        if sequence < self.sequence {
+            return Err(Error::StoreError(format!(
+                "sequence {} is behind the next sequence {}",
+                sequence, self.sequence
+            )));
+        }
+        if sequence > self.sequence_width.max_sequence() {
+            return Err(Error::StoreError(format!(
+                "sequence {} is beyond the sequence width of the database",
+                sequence
+            )));
+        }
+        if sequence > self.sequence {
+            self.skipped_from.get_or_insert(self.sequence);
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    pub fn element_index(&self) -> &ElementIndex {
+        &self.element_index
+    }
+
+    pub fn set_key_tracer(&mut self, key_tracer: KeyTracer) {
+        self.key_tracer = key_tracer;
+    }
+
+    /// Sets when the writes reach the database, see `FlushMode`, the buffered writes must be flushed first.
+    pub fn set_flush_mode(&mut self, flush_mode: FlushMode) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            return Err(Error::StoreError(
+                "the buffered writes must be flushed first".to_string(),
+            ));
+        }
+        self.flush_mode = flush_mode;
+        Ok(())
+    }
+
+    /// Returns the number and bytes of the MMR nodes inserted since the last call, i.e. the size of the batch flushed
+    /// by the MMR at the last commit.
+    pub fn take_batch(&mut self) -> (u64, u64) {
+        std::mem::take(&mut self.batch)
+    }
+}
+
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
     pub fn get(&self, key: &[u8]) -> Option<Box<[u8]>> {
-        let start = [key, self.sequence.to_be_bytes().as_ref()].concat();
+        self.key_tracer.read(key);
+        let start = self.sequence_width.versioned_key(key, self.sequence);
+        // the buffered writes are newer than the ones in the database
+        if let Some((_k, v)) = self
+            .buffer
+            .range(..=&start)
+            .next_back()
+            .filter(|(k, _v)| k.starts_with(key))
+        {
+            return Some(v.clone());
+        }
         let mode = IteratorMode::From(&start, Direction::Reverse);
         let iter = self.inner.iterator(mode);
         iter.take_while(|(k, _v)| k.starts_with(key))
             .next()
             .map(|(_k, v)| v)
     }
+
+    /// Reads the position and status of an element, a checked record written at a committed sequence must match the
+    /// leaf hash at its position as seen by this store.
+    pub fn get_element(&self, key: &[u8]) -> Result<Option<(u64, CellStatus)>, Error> {
+        let record = self.get(key);
+        self.decode_element(key, record.as_deref())
+    }
+
+    /// Reads the positions and statuses of several elements, in order, with a single `RecordStore::get_records`, see
+    /// `get_element`.
+    pub fn get_elements(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<(u64, CellStatus)>>, Error> {
+        keys.iter()
+            .zip(self.get_records(keys))
+            .map(|(key, record)| self.decode_element(key, record.as_deref()))
+            .collect()
+    }
+
+    // decodes an element record with the element index, an empty version is a deleted record
+    fn decode_element(
+        &self,
+        key: &[u8],
+        record: Option<&[u8]>,
+    ) -> Result<Option<(u64, CellStatus)>, Error> {
+        let element = self
+            .element_index
+            .decode(key, record)
+            .map_err(|e| Error::StoreError(e.to_string()))?;
+        let (record, element) = match (record, element) {
+            (Some(record), Some(element)) => (record, element),
+            _ => return Ok(None),
+        };
+        let supported = record.len() == ELEMENT_RECORD_LEN
+            || (record.len() == CHECKED_ELEMENT_RECORD_LEN
+                && record[ELEMENT_RECORD_LEN] == CHECKED_RECORD_VERSION);
+        if !supported {
+            return Err(Error::StoreError(format!(
+                "unsupported element record format of {:?}",
+                key
+            )));
+        }
+        let (pos, status) = (
+            element.pos.expect("a positioned record has a position"),
+            element.status,
+        );
+        if self.element_integrity {
+            let verified = match checked_record_sequence(record) {
+                // written by the pending block, its leaf may still be held in memory by the MMR
+                Some(sequence) if sequence >= self.committed => true,
+                Some(_) => {
+                    let leaf: Option<H256> = MMRStoreReadOps::get(self, pos)?;
+                    leaf.map_or(false, |leaf| verify_element_record(key, record, &leaf))
+                }
+                None => false,
+            };
+            if !verified {
+                return Err(Error::StoreError(format!(
+                    "element record at position {} doesn't match the tree",
+                    pos
+                )));
+            }
+        }
+        Ok(Some((pos, status)))
+    }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Put<WO>,
+    D: Deref,
+    D::Target: Put<WO>,
 {
     pub fn put<V: AsRef<[u8]>>(&mut self, key: &[u8], value: V) -> Result<(), Error> {
-        let k = [key, self.sequence.to_be_bytes().as_ref()].concat();
-        self.inner
-            .put(k, value)
-            .map_err(|e| Error::StoreError(e.to_string()))
+        let k = self.sequence_width.versioned_key(key, self.sequence);
+        self.key_tracer.write(key, k.len() + value.as_ref().len());
+        match self.flush_mode {
+            FlushMode::Block => self
+                .inner
+                .put(k, value)
+                .map_err(|e| Error::StoreError(e.to_string())),
+            FlushMode::Transaction => {
+                self.buffer.insert(k, value.as_ref().into());
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the buffered writes to the database, returns their number and bytes.
+    pub fn flush(&mut self) -> Result<(u64, u64), Error> {
+        let entries = self.buffer.len() as u64;
+        let mut bytes = 0;
+        for (k, v) in std::mem::take(&mut self.buffer) {
+            bytes += (k.len() + v.len()) as u64;
+            self.inner
+                .put(k, v)
+                .map_err(|e| Error::StoreError(e.to_string()))?;
+        }
+        Ok((entries, bytes))
+    }
+
+    /// Writes an element record, with a checksum over `leaf` if the element integrity mode is enabled.
+    pub fn put_element(
+        &mut self,
+        key: &[u8],
+        pos: u64,
+        status: &CellStatus,
+        leaf: &H256,
+    ) -> Result<(), Error> {
+        let record = if self.element_integrity {
+            let record = checked_element_record(key, pos, status, self.sequence, leaf);
+            self.element_index.stats().record_write(record.len());
+            record
+        } else {
+            self.element_index.encode(&ElementRecord {
+                pos: Some(pos),
+                status: status.clone(),
+            })
+        };
+        self.put(key, record)
     }
 
     pub fn commit(&mut self) -> Result<(), Error> {
+        if self.sequence == self.sequence_width.max_sequence() {
+            return Err(Error::StoreError(format!(
+                "sequence {} is the last one of the sequence width of the database",
+                self.sequence
+            )));
+        }
+        if let Some(skipped_from) = self.skipped_from.take() {
+            let (key, value) = skipped_record(skipped_from..self.sequence);
+            self.inner
+                .put(key, value)
+                .map_err(|e| Error::StoreError(e.to_string()))?;
+        }
         self.sequence += 1;
+        self.committed = self.sequence;
         self.inner
             .put(SEQUENCE_KEY, self.sequence.to_be_bytes())
             .map_err(|e| Error::StoreError(e.to_string()))
     }
 }
 
-impl<'a, Elem, DB, WO> MMRStoreReadOps<Elem> for DefaultStore<'a, DB, WO>
+// the element records are versioned, an empty version deletes a record
+impl<D, WO> RecordStore for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn get_record(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.get(key).map(|record| record.to_vec())
+    }
+
+    // the buffered writes are newer than the ones in the database, the other keys are read in a single pass
+    fn get_records(&self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        let mut records: Vec<Option<Vec<u8>>> = Vec::with_capacity(keys.len());
+        let mut unbuffered = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            self.key_tracer.read(key);
+            let start = self.sequence_width.versioned_key(key, self.sequence);
+            let buffered = self
+                .buffer
+                .range(..=&start)
+                .next_back()
+                .filter(|(k, _v)| k.starts_with(key))
+                .map(|(_k, v)| v.to_vec());
+            if buffered.is_none() {
+                unbuffered.push(i);
+            }
+            records.push(buffered);
+        }
+        let unbuffered_keys: Vec<&[u8]> = unbuffered.iter().map(|i| keys[*i].as_slice()).collect();
+        let read = latest_versions(&*self.inner, &unbuffered_keys, |key| {
+            self.sequence_width.versioned_key(key, self.sequence)
+        });
+        for (i, record) in unbuffered.into_iter().zip(read) {
+            records[i] = record;
+        }
+        records
+    }
+}
+
+impl<D, WO> RecordStoreMut for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore + Put<WO>,
+{
+    fn put_record(&mut self, key: &[u8], record: &[u8]) -> Result<(), AccumulatorError> {
+        self.put(key, record)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+
+    fn delete_record(&mut self, key: &[u8]) -> Result<(), AccumulatorError> {
+        self.put(key, [])
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+}
+
+impl<Elem, D, WO> MMRStoreReadOps<Elem> for DefaultStore<D, WO>
 where
     Elem: From<Box<[u8]>>,
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
     fn get(&self, pos: u64) -> Result<Option<Elem>, Error> {
-        let key = [POS_KEY, pos.to_le_bytes().as_ref()].concat();
+        let key = position_key(POS_KEY, pos);
         let slice = self.get(&key);
         match slice {
             Some(s) if s.is_empty() => Ok(None),
@@ -118,13 +525,16 @@ where
     }
 }
 
-impl<'a, Elem, DB, WO> MMRStoreWriteOps<Elem> for DefaultStore<'a, DB, WO>
+impl<Elem, D, WO> MMRStoreWriteOps<Elem> for DefaultStore<D, WO>
 where
     Elem: AsRef<[u8]>,
-    DB: Put<WO>,
+    D: Deref,
+    D::Target: Put<WO>,
 {
     fn insert(&mut self, pos: u64, elem: Elem) -> Result<(), Error> {
-        let key = [POS_KEY, pos.to_le_bytes().as_ref()].concat();
+        let key = position_key(POS_KEY, pos);
+        self.batch.0 += 1;
+        self.batch.1 += (key.len() + elem.as_ref().len()) as u64;
         self.put(&key, elem.as_ref())
             .map_err(|e| Error::StoreError(e.to_string()))
     }
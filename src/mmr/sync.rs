@@ -0,0 +1,190 @@
+use super::{
+    accumulator::{AccumulatorCommitment, MergeH256, H256},
+    store::{parse_element_record, DefaultStore, ELEMENT_KEY, ELEMENT_RECORD_LEN, MMR_SIZE_KEY},
+    ELEMENT_KEY_LEN,
+};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    keycodec::{hashed_element_key_from_parts, parse_hashed_element_key},
+    sequence_width::stored_sequence_width,
+    storage::traits::ReadStore,
+    sync::{SyncChunk, SyncClient},
+    AccumulatorError,
+};
+use merkle_mountain_range::{
+    leaf_index_to_mmr_size, leaf_index_to_pos, MMRStoreReadOps, MerkleProof, MMR,
+};
+use rocksdb::prelude::Put;
+use std::collections::HashMap;
+
+// an indexed leaf carries its element key, without the prefix, and its record after its hash
+const INDEXED_LEAF_LEN: usize = 32 + 32 + 4 + ELEMENT_RECORD_LEN;
+
+/// Exports all the leaves of a MMR accumulator database at `sequence` to `send`, in chunks of `chunk_size` leaves in
+/// position order, the dead ones included since they are part of the root. The entries of a chunk are the leaf positions
+/// (8 bytes LE) and their hashes, followed by the element key (out_point hash || index LE) and record when the leaf is
+/// indexed, its proof is the mmr size (8 bytes LE) followed by the proof items of the positions. Returns the number of
+/// exported leaves.
+///
+/// The element records can't be checked by the client, the leaf hash commits to the tx hash which the index doesn't keep.
+pub fn export_chunks<DB, F>(
+    db: &DB,
+    sequence: u64,
+    chunk_size: usize,
+    mut send: F,
+) -> Result<u64, AccumulatorError>
+where
+    DB: ReadStore,
+    F: FnMut(SyncChunk) -> Result<(), AccumulatorError>,
+{
+    assert!(chunk_size > 0, "chunk size must be positive");
+    let store = DefaultStore::<_, ()>::new_with_sequence(db, sequence);
+    let mmr_size = store
+        .get(MMR_SIZE_KEY)
+        .map(|slice| u64::from_le_bytes(slice.as_ref().try_into().expect("checked length")))
+        .unwrap_or_default();
+
+    // the versions written after the sequence are skipped, they are fsck's concern, and the records dropped by
+    // `gc_element_index` are missing, their leaves are exported without them
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    let mut report = FsckReport::default();
+    let mut elements: HashMap<u64, Vec<u8>> = latest_versions(
+        db,
+        sequence,
+        width,
+        |key| key.starts_with(ELEMENT_KEY) && key.len() == ELEMENT_KEY_LEN + width.encoded_len(),
+        |_key| true,
+        &mut report,
+    )
+    .into_iter()
+    .map(|(key, record)| {
+        let (pos, _status) = parse_element_record(&record);
+        let (hash, index) = parse_hashed_element_key(ELEMENT_KEY, &key).expect("checked length");
+        // checked records are sent as version 0 records, the client loads them without the element integrity mode
+        let record = &record[..ELEMENT_RECORD_LEN];
+        (
+            pos,
+            [hash.as_ref(), index.to_le_bytes().as_ref(), record].concat(),
+        )
+    })
+    .collect();
+
+    let mmr = MMR::<H256, MergeH256, _>::new(mmr_size, store);
+    let positions: Vec<u64> = (0..)
+        .take_while(|leaf_index| leaf_index_to_mmr_size(*leaf_index) <= mmr_size)
+        .map(leaf_index_to_pos)
+        .collect();
+    for chunk in positions.chunks(chunk_size) {
+        let proof = mmr.gen_proof(chunk.to_vec())?;
+        let mut entries = Vec::with_capacity(chunk.len());
+        for pos in chunk {
+            let hash: H256 = MMRStoreReadOps::get(mmr.store(), *pos)?.ok_or_else(|| {
+                AccumulatorError::InternalError(format!("missing leaf at position {}", pos))
+            })?;
+            let element = elements.remove(pos).unwrap_or_default();
+            entries.push((
+                pos.to_le_bytes().to_vec(),
+                [hash.as_ref(), element.as_slice()].concat(),
+            ));
+        }
+        let mut proof_bytes = proof.mmr_size().to_le_bytes().to_vec();
+        for item in proof.proof_items() {
+            proof_bytes.extend_from_slice(item.as_ref());
+        }
+        send(SyncChunk {
+            entries,
+            proof: proof_bytes,
+        })?;
+    }
+    Ok(positions.len() as u64)
+}
+
+/// Loads the chunks of `export_chunks` into an empty MMR accumulator database, the leaves are pushed in position order
+/// so they get the positions they are proved at.
+pub struct MMRSyncClient<'a, DB, WO> {
+    mmr: MMR<H256, MergeH256, DefaultStore<&'a DB, WO>>,
+    commitment: AccumulatorCommitment,
+}
+
+impl<'a, DB, WO> MMRSyncClient<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// The commitment must come from a trusted source, e.g. a block header.
+    pub fn new(db: &'a DB, commitment: AccumulatorCommitment) -> Result<Self, AccumulatorError> {
+        let mut store = DefaultStore::new(db);
+        store.start_at(commitment.sequence())?;
+        Ok(MMRSyncClient {
+            mmr: MMR::new(0, store),
+            commitment,
+        })
+    }
+}
+
+impl<'a, DB, WO> SyncClient for MMRSyncClient<'a, DB, WO>
+where
+    DB: ReadStore + Put<WO>,
+{
+    fn ingest(&mut self, chunk: SyncChunk) -> Result<(), AccumulatorError> {
+        if chunk.proof.len() < 8 || (chunk.proof.len() - 8) % 32 != 0 {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        let mmr_size = u64::from_le_bytes(chunk.proof[0..8].try_into().expect("checked length"));
+        let proof_items = chunk.proof[8..]
+            .chunks(32)
+            .map(|item| H256::from(Box::<[u8]>::from(item)))
+            .collect();
+
+        let mut leaves = Vec::with_capacity(chunk.entries.len());
+        let mut elements = Vec::new();
+        for (key, value) in chunk.entries {
+            let pos = u64::from_le_bytes(
+                key.as_slice()
+                    .try_into()
+                    .map_err(|_| AccumulatorError::InvalidProof)?,
+            );
+            match value.len() {
+                32 => {}
+                INDEXED_LEAF_LEN if parse_element_record(&value[68..]).0 == pos => {
+                    let hash = value[32..64].try_into().expect("checked length");
+                    let index =
+                        u32::from_le_bytes(value[64..68].try_into().expect("checked length"));
+                    let key = hashed_element_key_from_parts(ELEMENT_KEY, &hash, index);
+                    elements.push((key, value[68..].to_vec()));
+                }
+                _ => return Err(AccumulatorError::InvalidProof),
+            }
+            leaves.push((pos, H256::from(Box::<[u8]>::from(&value[0..32]))));
+        }
+        let verified = MerkleProof::<H256, MergeH256>::new(mmr_size, proof_items)
+            .verify(self.commitment.root().clone(), leaves.clone());
+        if !matches!(verified, Ok(true)) {
+            return Err(AccumulatorError::InvalidProof);
+        }
+
+        for (pos, hash) in leaves {
+            if self.mmr.push(hash)? != pos {
+                return Err(AccumulatorError::InvalidProof);
+            }
+        }
+        for (key, record) in elements {
+            self.mmr.store_mut().put(&key, record)?;
+        }
+        // the pushed nodes are kept in memory until they are written to the store
+        self.mmr.commit()?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), AccumulatorError> {
+        if &self.mmr.get_root()? != self.commitment.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let mmr_size = self.mmr.mmr_size();
+        self.mmr
+            .store_mut()
+            .put(MMR_SIZE_KEY, mmr_size.to_le_bytes())?;
+        self.mmr.store_mut().commit()?;
+        Ok(())
+    }
+}
@@ -1,15 +1,34 @@
-use merkle_mountain_range::{leaf_index_to_mmr_size, Error, Merge, MMR};
-use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use merkle_mountain_range::{leaf_index_to_mmr_size, leaf_index_to_pos, Error, Merge, MMR};
+use rocksdb::{
+    prelude::{Delete, Iterate, Open, OpenCF, Put},
+    IteratorMode, OptimisticTransactionDB, Options,
+};
 use tempfile::{Builder, TempDir};
 
 use crate::{
-    mmr::accumulator::MMRAccumulator, new_blake2b, AccumulatorReader, AccumulatorWriter,
-    CellStatus, OutPoint, Proof,
+    expiry::{VersionExpiry, VersionedKeys},
+    metrics::{FlushMode, FlushWrites},
+    mmr::accumulator::MMRAccumulator,
+    new_blake2b,
+    sequence_width::SequenceWidth,
+    storage::column_family::ColumnFamilyStore,
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof,
 };
 
-use super::store::DefaultStore;
+use super::{
+    accumulator::{MergeH256, WalletProof, H256},
+    bulk::build_tree,
+    fsck::fsck,
+    gc::gc_element_index,
+    position_index::{PositionedCommitment, PositionedMMRAccumulator, PositionedWalletProof},
+    set_element_integrity,
+    store::{
+        element_record, parse_element_record, DefaultStore, CHECKED_ELEMENT_RECORD_LEN,
+        ELEMENT_KEY, ELEMENT_RECORD_LEN, POS_KEY,
+    },
+};
 
-type DefaultStoreMMR<'a, DB, WO> = MMR<WordHash, MergeWordHash, DefaultStore<'a, DB, WO>>;
+type DefaultStoreMMR<'a, DB, WO> = MMR<WordHash, MergeWordHash, DefaultStore<&'a DB, WO>>;
 
 pub struct Word(String);
 
@@ -204,3 +223,508 @@ fn test_accumulator() {
         .verify(commitment3, vec![(out_point_2, CellStatus::new_dead(0, 2))])
         .unwrap());
 }
+
+#[test]
+fn test_position_index() {
+    let out_points: Vec<_> = (0..4u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: 0,
+        })
+        .collect();
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db =
+        OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), ["mmr", "positions"]).unwrap();
+    let (mmr_cf, index_cf) = (
+        db.cf_handle("mmr").unwrap(),
+        db.cf_handle("positions").unwrap(),
+    );
+    let tx = db.transaction_default();
+    let (store, index_store) = (
+        ColumnFamilyStore::new(&tx, mmr_cf).unwrap(),
+        ColumnFamilyStore::new(&tx, index_cf).unwrap(),
+    );
+    let mut accumulator = PositionedMMRAccumulator::<_, _, ()>::new(&store, &index_store).unwrap();
+    let mut commitments = Vec::new();
+    for block in [&out_points[0..3], &out_points[3..]] {
+        accumulator.add(block.to_vec()).unwrap();
+        commitments.push(accumulator.commit().unwrap());
+    }
+    drop(accumulator);
+    drop((store, index_store));
+    // a single transaction stores the MMR and its index
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let (store, index_store) = (
+        ColumnFamilyStore::new(&snapshot, mmr_cf).unwrap(),
+        ColumnFamilyStore::new(&snapshot, index_cf).unwrap(),
+    );
+    let accumulator =
+        PositionedMMRAccumulator::<_, _, ()>::new_with_sequence(&store, &index_store, 1).unwrap();
+    let positioned = commitments[1].clone();
+    assert_eq!(positioned.position_root(), accumulator.index().root());
+    let elements = vec![out_points[1].clone(), out_points[3].clone()];
+    let live: Vec<_> = elements
+        .iter()
+        .map(|out_point| {
+            let status = accumulator
+                .accumulator()
+                .cell_status(out_point)
+                .unwrap()
+                .unwrap();
+            (out_point.clone(), status)
+        })
+        .collect();
+    let proof = accumulator
+        .proof(positioned.clone(), elements.clone())
+        .unwrap();
+    let pos_list = proof.pos_list().to_vec();
+    assert!(proof.verify(positioned.clone(), live.clone()).unwrap());
+    // the proof doesn't verify against the position root of another commitment
+    let proof = accumulator
+        .proof(positioned.clone(), elements.clone())
+        .unwrap();
+    assert!(!proof.verify(commitments[0].clone(), live.clone()).unwrap());
+    // the positions of other leaves don't belong to these out_points
+    let swapped = vec![pos_list[1], pos_list[0]];
+    assert!(!accumulator
+        .index()
+        .proof(&elements)
+        .unwrap()
+        .verify(positioned.position_root(), &elements, &swapped)
+        .unwrap());
+    // the positioned wallet proof roundtrips and only verifies against its own commitment
+    let wallet_proof = PositionedMMRAccumulator::<_, _, ()>::wallet_proof(
+        &store,
+        &index_store,
+        elements.clone(),
+        1,
+    )
+    .unwrap();
+    let wallet_proof = PositionedWalletProof::from_bytes(&wallet_proof.to_bytes()).unwrap();
+    assert_eq!(1, wallet_proof.sequence());
+    assert_eq!(live.as_slice(), wallet_proof.elements());
+    assert!(wallet_proof.verify(&positioned).unwrap());
+    assert!(!wallet_proof.verify(&commitments[0]).unwrap());
+    let commitment = PositionedCommitment::from_bytes(&positioned.to_bytes()).unwrap();
+    assert!(wallet_proof.verify(&commitment).unwrap());
+    // the last cell wasn't indexed yet at the first commitment
+    let accumulator =
+        PositionedMMRAccumulator::<_, _, ()>::new_with_sequence(&store, &index_store, 0).unwrap();
+    assert_eq!(commitments[0].position_root(), accumulator.index().root());
+    assert_eq!(None, accumulator.index().position(&out_points[3]).unwrap());
+    assert!(accumulator
+        .proof(commitments[1].clone(), elements.clone())
+        .is_err());
+    assert_ne!(
+        commitments[0].commitment_digest(),
+        commitments[0].commitment().commitment_digest()
+    );
+}
+
+#[test]
+fn test_wallet_proof() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let wallet = vec![
+        out_points[7].clone(),
+        out_points[0].clone(),
+        out_points[3].clone(),
+    ];
+    let proof = MMRAccumulator::<_, ()>::wallet_proof(&snapshot, wallet, 1).unwrap();
+    assert_eq!(3, proof.elements().len());
+    assert!(proof.verify(&commitment).unwrap());
+
+    let proof = WalletProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    assert!(matches!(
+        MMRAccumulator::<_, ()>::wallet_proof(
+            &snapshot,
+            vec![out_points[0].clone(), out_points[1].clone()],
+            1
+        ),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+}
+
+#[test]
+fn test_fsck() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(Some(1), report.sequence);
+    assert_eq!(10, report.leaves);
+    assert_eq!(0, report.orphaned_versions);
+
+    // a block written without committing the accumulator leaves orphaned versions behind
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+    accumulator
+        .add(vec![OutPoint {
+            tx_hash: [10u8; 32],
+            index: 10,
+        }])
+        .unwrap();
+    tx.commit().unwrap();
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert!(report.orphaned_versions > 0);
+
+    // a node overwritten behind the accumulator's back doesn't rehash into its parent anymore
+    let tx = db.transaction_default();
+    let key = [
+        POS_KEY,
+        0u64.to_le_bytes().as_ref(),
+        1u64.to_be_bytes().as_ref(),
+    ]
+    .concat();
+    tx.put(key, [9u8; 32]).unwrap();
+    tx.commit().unwrap();
+    assert!(!fsck(&db.snapshot()).unwrap().is_ok());
+}
+
+#[test]
+fn test_element_record_encoding() {
+    let status = CellStatus::new_dead(1, 2);
+    let record = element_record(3, &status);
+    assert_eq!(ELEMENT_RECORD_LEN, record.len());
+    assert_eq!(
+        [
+            3u64.to_le_bytes().as_ref(),
+            1u64.to_le_bytes().as_ref(),
+            2u64.to_le_bytes().as_ref()
+        ]
+        .concat(),
+        record
+    );
+    assert_eq!((3, status), parse_element_record(&record));
+}
+
+#[test]
+fn test_gc_element_index() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+    let out_points: Vec<_> = (0..5u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..4].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[2].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(vec![out_points[4].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[3].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    // the horizon is sequence 2: the cell consumed at 1 is dropped, the created version of the cell consumed at 2 is superseded
+    let tx = db.transaction_default();
+    let report = gc_element_index::<_, ()>(&tx, 4, 2).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(1, report.versions);
+    assert_eq!(1, report.dead_cells);
+
+    let snapshot = db.snapshot();
+    // the index of the states before the horizon is gone
+    assert!(MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, 1).is_err());
+    assert!(
+        MMRAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_points[0].clone()], 1).is_err()
+    );
+    let accumulator = MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, 2).unwrap();
+    assert_eq!(None, accumulator.cell_status(&out_points[1]).unwrap());
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 2)),
+        accumulator.cell_status(&out_points[2]).unwrap()
+    );
+    assert_eq!(
+        Some(CellStatus::new_live(0)),
+        accumulator.cell_status(&out_points[3]).unwrap()
+    );
+    let report = fsck(&snapshot).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(4, report.elements);
+}
+
+#[test]
+fn test_version_expiry() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new_with_strict(&tx, true).unwrap();
+    let out_points: Vec<_> = (0..5u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..4].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[2].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(vec![out_points[4].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[3].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    // the keys of the whole database in order, a compaction drops the ones which expire
+    let mut entries: Vec<(Box<[u8]>, Box<[u8]>)> = db.iterator(IteratorMode::Start).collect();
+    let mut compact = |expiry: &VersionExpiry| {
+        let mut expired = Vec::new();
+        entries.retain(|(key, value)| {
+            let expires = expiry.expires(key, value);
+            if expires {
+                expired.push(key.clone());
+            }
+            !expires
+        });
+        expired
+    };
+    let element_versions = |keys: &[Box<[u8]>]| -> Vec<u64> {
+        keys.iter()
+            .filter(|key| key.starts_with(ELEMENT_KEY))
+            .map(|key| SequenceWidth::U64.split(key).1)
+            .collect()
+    };
+
+    // nothing expires before the first horizon
+    let expiry = VersionExpiry::new(VersionedKeys::Mmr, 2);
+    assert!(compact(&expiry).is_empty());
+    assert_eq!("version expiry not started", expiry.to_string());
+
+    // the horizon is sequence 2: the created versions of the cells consumed at 1 and 2 are superseded, but a compaction
+    // sees them before their dead versions
+    expiry.advance(4);
+    assert!(compact(&expiry).is_empty());
+    assert!(expiry.pending() >= 2);
+    let expired = compact(&expiry);
+    assert_eq!(vec![0, 0], element_versions(&expired));
+    assert_eq!(expired.len() as u64, expiry.expired());
+    assert_eq!(0, expiry.pending());
+    assert!(compact(&expiry).is_empty());
+    let report = expiry.to_string();
+    assert!(
+        report.starts_with(&format!(
+            "version expiry horizon 2, {} expired versions of ",
+            expired.len()
+        )),
+        "{}",
+        report
+    );
+    // the horizon doesn't move back
+    expiry.advance(3);
+    assert_eq!(report, expiry.to_string());
+
+    // the readers at the horizon or later don't see the expired versions
+    let snapshot = db.snapshot();
+    let before: Vec<_> = (2..5)
+        .map(|sequence| {
+            let accumulator =
+                MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence).unwrap();
+            out_points
+                .iter()
+                .map(|out_point| accumulator.cell_status(out_point).unwrap())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let tx = db.transaction_default();
+    for key in expired {
+        tx.delete(key).unwrap();
+    }
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    for (sequence, before) in (2..5).zip(before) {
+        let accumulator = MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence).unwrap();
+        for (out_point, status) in out_points.iter().zip(before) {
+            assert_eq!(status, accumulator.cell_status(out_point).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_element_integrity() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    set_element_integrity::<_, ()>(&tx, true).unwrap();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+    let out_points: Vec<_> = (0..5u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..4].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    // a cell consumed in the block which creates it is read before its leaf is written to the store
+    accumulator.add(vec![out_points[4].clone()]).unwrap();
+    accumulator
+        .delete(vec![out_points[1].clone(), out_points[4].clone()])
+        .unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    let key = [
+        ELEMENT_KEY,
+        out_points[2].hash().as_ref(),
+        2u32.to_le_bytes().as_ref(),
+    ]
+    .concat();
+    let record = DefaultStore::<_, ()>::new(&db).get(&key).unwrap();
+    assert_eq!(CHECKED_ELEMENT_RECORD_LEN, record.len());
+    let accumulator = MMRAccumulator::<_, ()>::new_with_sequence(&db, 0).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_live(0)),
+        accumulator.cell_status(&out_points[1]).unwrap()
+    );
+
+    // the mode of a database can't change after the first commit
+    let tx = db.transaction_default();
+    assert!(set_element_integrity::<_, ()>(&tx, false).is_err());
+
+    // a leaf rewritten without its element record, e.g. by a partial write, is caught on read and by fsck
+    let leaf_key = [
+        POS_KEY,
+        leaf_index_to_pos(2).to_le_bytes().as_ref(),
+        1u64.to_be_bytes().as_ref(),
+    ]
+    .concat();
+    tx.put(leaf_key, [9u8; 32]).unwrap();
+    tx.commit().unwrap();
+    let accumulator = MMRAccumulator::<_, ()>::new_with_sequence(&db, 1).unwrap();
+    assert!(matches!(
+        accumulator.cell_status(&out_points[2]),
+        Err(AccumulatorError::InternalError(_))
+    ));
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report
+        .errors
+        .iter()
+        .any(|error| error.contains("doesn't match the leaf hash")));
+}
+
+#[test]
+fn test_transaction_flush_mode() {
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    let mut commitments = Vec::new();
+    for mode in [FlushMode::Block, FlushMode::Transaction] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut accumulator = MMRAccumulator::new_with_strict(&tx, true).unwrap();
+        accumulator.set_flush_mode(mode).unwrap();
+        accumulator.add(out_points[0..5].to_vec()).unwrap();
+        let first = accumulator.commit().unwrap();
+        accumulator.add(out_points[5..].to_vec()).unwrap();
+        accumulator.delete(vec![out_points[1].clone()]).unwrap();
+        let second = accumulator.commit().unwrap();
+        let stats = accumulator.flush_stats().unwrap();
+        assert_eq!(2, stats.batches);
+        assert!(stats.entries > 0);
+        // another reader of the transaction only sees the buffered writes once they are flushed
+        let proved = || {
+            MMRAccumulator::<_, ()>::wallet_proof(&tx, vec![out_points[7].clone()], 1)
+                .map_or(false, |proof| proof.verify(&second).unwrap_or(false))
+        };
+        assert_eq!(mode == FlushMode::Block, proved());
+        accumulator.flush().unwrap();
+        assert!(proved());
+        assert_eq!(
+            mode == FlushMode::Transaction,
+            accumulator.flush_stats().unwrap().flushes == 1
+        );
+        tx.commit().unwrap();
+
+        // the buffered writes keep the sequences they were committed at
+        let snapshot = db.snapshot();
+        let proof =
+            MMRAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_points[1].clone()], 0)
+                .unwrap();
+        assert!(proof.verify(&first).unwrap());
+        let proof =
+            MMRAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_points[7].clone()], 1)
+                .unwrap();
+        assert!(proof.verify(&second).unwrap());
+        commitments.push(second.to_bytes());
+    }
+    assert_eq!(commitments[0], commitments[1]);
+}
+
+#[test]
+fn test_build_tree() {
+    let leaves: Vec<([u8; 32], [u8; 32])> = (0..10u8).map(|i| ([i; 32], [i + 1; 32])).collect();
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    // no leaves is an error, an empty mmr has no root
+    assert!(build_tree::<_, ()>(&tx, vec![]).is_err());
+    let root = build_tree::<_, ()>(&tx, leaves.clone()).unwrap();
+    // only an empty database can be built
+    assert!(build_tree::<_, ()>(&tx, leaves.clone()).is_err());
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let reader = MMRAccumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    assert_eq!(&H256::from(root), reader.commitment().unwrap().root());
+    // every leaf is the whole value hash at the position of its index
+    let mmr = MMR::<H256, MergeH256, _>::new(
+        leaf_index_to_mmr_size(leaves.len() as u64 - 1),
+        DefaultStore::<_, ()>::new_with_sequence(&snapshot, 0),
+    );
+    for (i, (_key, hash)) in leaves.into_iter().enumerate() {
+        let pos = leaf_index_to_pos(i as u64);
+        assert!(mmr
+            .gen_proof(vec![pos])
+            .unwrap()
+            .verify(H256::from(root), vec![(pos, H256::from(hash))])
+            .unwrap());
+    }
+}
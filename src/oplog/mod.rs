@@ -0,0 +1,86 @@
+use crate::{
+    keycodec::sequence_key, storage::traits::ReadStore, AccumulatorError, BlockOps, OutPoint,
+};
+use rocksdb::prelude::Put;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+pub const OPLOG_KEY: &[u8] = b"OPLOG";
+
+/// A per-sequence log of the added and deleted out_points (undo data), stored next to the accumulator in the same RocksDB.
+/// It is keyed by sequence directly and not versioned, a record is written once when the sequence is committed.
+pub struct OpLog<'a, DB, WO> {
+    inner: &'a DB,
+    write_options: PhantomData<WO>,
+}
+
+impl<'a, DB, WO> OpLog<'a, DB, WO> {
+    pub fn new(db: &'a DB) -> Self {
+        OpLog {
+            inner: db,
+            write_options: PhantomData,
+        }
+    }
+}
+
+impl<'a, DB, WO> OpLog<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the operations committed at the given sequence, or `None` if no record was written.
+    pub fn ops_at(&self, sequence: u64) -> Result<Option<BlockOps>, AccumulatorError> {
+        self.inner
+            .get(oplog_key(sequence))
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?
+            .map(|v| decode_ops(v.as_ref()))
+            .transpose()
+    }
+}
+
+impl<'a, DB, WO> OpLog<'a, DB, WO>
+where
+    DB: Put<WO>,
+{
+    pub fn put(&mut self, sequence: u64, ops: &BlockOps) -> Result<(), AccumulatorError> {
+        self.inner
+            .put(oplog_key(sequence), encode_ops(ops))
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+}
+
+fn oplog_key(sequence: u64) -> Vec<u8> {
+    sequence_key(OPLOG_KEY, sequence)
+}
+
+/// Serialize `BlockOps` as `added count (4) | deleted count (4) | (tx_hash (32) | index (4)) * (added count + deleted count)`.
+pub fn encode_ops(ops: &BlockOps) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(8 + (ops.added.len() + ops.deleted.len()) * OutPoint::LEN);
+    ret.extend_from_slice(&(ops.added.len() as u32).to_le_bytes());
+    ret.extend_from_slice(&(ops.deleted.len() as u32).to_le_bytes());
+    for out_point in ops.added.iter().chain(ops.deleted.iter()) {
+        ret.extend_from_slice(&out_point.to_bytes());
+    }
+    ret
+}
+
+/// Deserialize `BlockOps` from a slice that was previously serialized with `encode_ops`.
+pub fn decode_ops(slice: &[u8]) -> Result<BlockOps, AccumulatorError> {
+    let invalid = || AccumulatorError::InternalError("invalid oplog record".to_string());
+    if slice.len() < 8 {
+        return Err(invalid());
+    }
+    let added_count = u32::from_le_bytes(slice[0..4].try_into().expect("checked length")) as usize;
+    let deleted_count =
+        u32::from_le_bytes(slice[4..8].try_into().expect("checked length")) as usize;
+    if slice.len() != 8 + (added_count + deleted_count) * OutPoint::LEN {
+        return Err(invalid());
+    }
+    let mut out_points = slice[8..]
+        .chunks_exact(OutPoint::LEN)
+        .map(|chunk| OutPoint::from_bytes(chunk).expect("checked length"));
+    let added = out_points.by_ref().take(added_count).collect();
+    let deleted = out_points.collect();
+    Ok(BlockOps { added, deleted })
+}
@@ -0,0 +1,56 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use crate::{BlockOps, OutPoint};
+
+use super::{decode_ops, encode_ops, OpLog};
+
+#[test]
+fn test_encode_decode_ops() {
+    let ops = BlockOps {
+        added: vec![
+            OutPoint {
+                tx_hash: [1u8; 32],
+                index: 0,
+            },
+            OutPoint {
+                tx_hash: [2u8; 32],
+                index: 1,
+            },
+        ],
+        deleted: vec![OutPoint {
+            tx_hash: [3u8; 32],
+            index: 2,
+        }],
+    };
+    let slice = encode_ops(&ops);
+    assert_eq!(8 + 3 * 36, slice.len());
+    assert_eq!(slice, encode_ops(&decode_ops(&slice).unwrap()));
+    assert!(decode_ops(&slice[1..]).is_err());
+}
+
+#[test]
+fn test_ops_at() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let mut oplog = OpLog::new(&tx);
+
+    let ops = BlockOps {
+        added: vec![OutPoint {
+            tx_hash: [1u8; 32],
+            index: 0,
+        }],
+        deleted: vec![],
+    };
+    oplog.put(3, &ops).unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let oplog = OpLog::<_, ()>::new(&snapshot);
+    assert_eq!(
+        encode_ops(&ops),
+        encode_ops(&oplog.ops_at(3).unwrap().unwrap())
+    );
+    assert!(oplog.ops_at(2).unwrap().is_none());
+}
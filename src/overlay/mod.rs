@@ -0,0 +1,108 @@
+use crate::{
+    retry::{is_conflict, CommitConflicts, RetryPolicy},
+    AccumulatorError, AccumulatorWriter, BlockOps, OutPoint,
+};
+use rocksdb::{OptimisticTransaction, OptimisticTransactionDB};
+use std::{marker::PhantomData, sync::Arc};
+
+mod store;
+#[cfg(test)]
+mod tests;
+
+pub use store::{MMROverlayStore, SMTOverlayStore};
+
+/// Adds and deletes pending on top of the committed accumulator, like the transactions of a block template.
+/// They are kept in memory and replayed in a RocksDB transaction of their own when a commitment is needed,
+/// which is rolled back for a speculative commitment, or committed when the overlay is promoted.
+pub struct PendingOverlay<'a, A, F> {
+    db: &'a OptimisticTransactionDB,
+    // Opens the accumulator on a fresh transaction, it's an owned accumulator so it can't outlive the transaction.
+    open: F,
+    block: BlockOps,
+    accumulator: PhantomData<fn() -> A>,
+}
+
+impl<'a, A, F> PendingOverlay<'a, A, F>
+where
+    A: AccumulatorWriter<Item = OutPoint>,
+    F: Fn(Arc<OptimisticTransaction>) -> Result<A, AccumulatorError>,
+{
+    pub fn new(db: &'a OptimisticTransactionDB, open: F) -> Self {
+        PendingOverlay {
+            db,
+            open,
+            block: BlockOps::default(),
+            accumulator: PhantomData,
+        }
+    }
+
+    pub fn add(&mut self, elements: Vec<OutPoint>) {
+        self.block.added.extend(elements);
+    }
+
+    pub fn delete(&mut self, elements: Vec<OutPoint>) {
+        self.block.deleted.extend(elements);
+    }
+
+    /// Returns the commitment the pending operations would produce as the next block, without persisting them.
+    /// Strict mode errors of the pending operations are reported here.
+    pub fn speculative_commitment(&self) -> Result<A::Commitment, AccumulatorError> {
+        let (tx, commitment) = self.apply()?;
+        tx.rollback()
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        Ok(commitment)
+    }
+
+    /// Commits the pending operations into the accumulator as the next block.
+    pub fn promote(self) -> Result<A::Commitment, AccumulatorError> {
+        let (tx, commitment) = self.apply()?;
+        tx.commit()
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        Ok(commitment)
+    }
+
+    /// Commits the pending operations like `promote`, and applies them again on a new transaction when the commit
+    /// conflicts with a concurrent writer, as often as `policy` allows. The conflicts and retries are counted in
+    /// `conflicts`.
+    pub fn promote_with_retry(
+        self,
+        policy: &RetryPolicy,
+        conflicts: &mut CommitConflicts,
+    ) -> Result<A::Commitment, AccumulatorError> {
+        let mut attempt = 0;
+        loop {
+            let (tx, commitment) = self.apply()?;
+            match tx.commit() {
+                Ok(()) => return Ok(commitment),
+                Err(e) if is_conflict(&e) && attempt < policy.max_retries => {
+                    conflicts.record_retry(1);
+                    std::thread::sleep(policy.backoff(attempt));
+                    attempt += 1;
+                }
+                Err(e) => {
+                    if is_conflict(&e) {
+                        conflicts.record_failure();
+                    }
+                    return Err(AccumulatorError::InternalError(e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Drops the pending operations, nothing has been written.
+    pub fn discard(self) {}
+
+    fn apply(&self) -> Result<(OptimisticTransaction, A::Commitment), AccumulatorError> {
+        let tx = Arc::new(self.db.transaction_default());
+        let mut accumulator = (self.open)(Arc::clone(&tx))?;
+        let commitment = accumulator
+            .apply_blocks(vec![self.block.clone()])?
+            .pop()
+            .expect("one commitment per block");
+        drop(accumulator);
+        let tx = Arc::try_unwrap(tx)
+            .ok()
+            .expect("the accumulator releases the transaction when dropped");
+        Ok((tx, commitment))
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use merkle_mountain_range::{MMRStoreReadOps, MMRStoreWriteOps};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{StoreReadOps, StoreWriteOps},
+    BranchKey, BranchNode, H256,
+};
+
+/// A SMT store which reads through to the store of a writer and keeps its own writes in memory, so that a tree updated
+/// on it computes a root without persisting anything. It's dropped with the writes.
+pub struct SMTOverlayStore<'a, S, V> {
+    base: &'a S,
+    // `None` marks a node removed by the overlay
+    branches: HashMap<BranchKey, Option<BranchNode>>,
+    leaves: HashMap<H256, Option<V>>,
+}
+
+impl<'a, S, V> SMTOverlayStore<'a, S, V> {
+    pub fn new(base: &'a S) -> Self {
+        SMTOverlayStore {
+            base,
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+        }
+    }
+}
+
+impl<S, V> StoreReadOps<V> for SMTOverlayStore<'_, S, V>
+where
+    S: StoreReadOps<V>,
+    V: Clone,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        match self.branches.get(branch_key) {
+            Some(branch) => Ok(branch.clone()),
+            None => self.base.get_branch(branch_key),
+        }
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        match self.leaves.get(leaf_key) {
+            Some(leaf) => Ok(leaf.clone()),
+            None => self.base.get_leaf(leaf_key),
+        }
+    }
+}
+
+impl<S, V> StoreWriteOps<V> for SMTOverlayStore<'_, S, V> {
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.branches.insert(node_key, Some(branch));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.leaves.insert(leaf_key, Some(leaf));
+        Ok(())
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.branches.insert(*node_key, None);
+        Ok(())
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.leaves.insert(*leaf_key, None);
+        Ok(())
+    }
+}
+
+/// The MMR counterpart of `SMTOverlayStore`, the positions written by the overlay shadow the ones of the writer.
+pub struct MMROverlayStore<'a, S, Elem> {
+    base: &'a S,
+    nodes: HashMap<u64, Elem>,
+}
+
+impl<'a, S, Elem> MMROverlayStore<'a, S, Elem> {
+    pub fn new(base: &'a S) -> Self {
+        MMROverlayStore {
+            base,
+            nodes: HashMap::new(),
+        }
+    }
+}
+
+impl<S, Elem> MMRStoreReadOps<Elem> for MMROverlayStore<'_, S, Elem>
+where
+    S: MMRStoreReadOps<Elem>,
+    Elem: Clone,
+{
+    fn get(&self, pos: u64) -> Result<Option<Elem>, merkle_mountain_range::Error> {
+        match self.nodes.get(&pos) {
+            Some(elem) => Ok(Some(elem.clone())),
+            None => self.base.get(pos),
+        }
+    }
+}
+
+impl<S, Elem> MMRStoreWriteOps<Elem> for MMROverlayStore<'_, S, Elem> {
+    fn insert(&mut self, pos: u64, elem: Elem) -> Result<(), merkle_mountain_range::Error> {
+        self.nodes.insert(pos, elem);
+        Ok(())
+    }
+}
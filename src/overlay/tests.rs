@@ -0,0 +1,96 @@
+use rocksdb::{prelude::Open, OptimisticTransaction, OptimisticTransactionDB};
+use std::sync::Arc;
+use tempfile::Builder;
+
+use crate::{
+    builder, smt::accumulator::SMTAccumulatorOwned, AccumulatorError, AccumulatorWriter, BlockOps,
+    OutPoint,
+};
+
+use super::PendingOverlay;
+
+fn open_smt(
+    tx: Arc<OptimisticTransaction>,
+) -> Result<SMTAccumulatorOwned<OptimisticTransaction, ()>, AccumulatorError> {
+    Ok(SMTAccumulatorOwned::new_with_strict(tx, true)?)
+}
+
+fn out_point(i: u8) -> OutPoint {
+    OutPoint {
+        tx_hash: [i; 32],
+        index: i as u32,
+    }
+}
+
+#[test]
+fn test_speculative_commitment() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+
+    let mut overlay = PendingOverlay::new(&db, open_smt);
+    overlay.add(vec![out_point(1), out_point(2)]);
+    let first = overlay.speculative_commitment().unwrap();
+    overlay.add(vec![out_point(3)]);
+    overlay.delete(vec![out_point(1)]);
+    let speculative = overlay.speculative_commitment().unwrap();
+    assert_ne!(first.to_bytes(), speculative.to_bytes());
+    let promoted = overlay.promote().unwrap();
+    // the speculative commitments were not persisted, the promoted block is still the first one
+    assert_eq!(0, promoted.sequence());
+    assert_eq!(speculative.to_bytes(), promoted.to_bytes());
+
+    // the next block starts from the promoted one, a strict error only fails its own overlay
+    let mut overlay = PendingOverlay::new(&db, open_smt);
+    overlay.delete(vec![out_point(1)]);
+    assert!(matches!(
+        overlay.speculative_commitment(),
+        Err(AccumulatorError::ElementNotFound(0))
+    ));
+    overlay.discard();
+    let mut overlay = PendingOverlay::new(&db, open_smt);
+    overlay.delete(vec![out_point(2)]);
+    assert_eq!(
+        promoted.sequence() + 1,
+        overlay.promote().unwrap().sequence()
+    );
+}
+
+// The preview of a block is the commitment of its commit, and nothing is written before it.
+#[test]
+fn test_preview_root() {
+    for backend in ["smt", "smt_live", "mmr"] {
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+        let tx = db.transaction_default();
+        let mut accumulator = builder::Builder::from_name(backend)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        accumulator.add(vec![out_point(1), out_point(2)]).unwrap();
+        accumulator.commit().unwrap();
+
+        // the cell 4 is consumed in the block which creates it
+        let block = BlockOps {
+            added: vec![out_point(3), out_point(4)],
+            deleted: vec![out_point(1), out_point(4)],
+        };
+        let preview = accumulator.preview_root(block.clone()).unwrap();
+        assert_eq!(
+            preview.to_bytes(),
+            accumulator.preview_root(block.clone()).unwrap().to_bytes()
+        );
+        assert_eq!(None, accumulator.cell_status(&out_point(3)).unwrap());
+        let committed = accumulator.apply_blocks(vec![block]).unwrap();
+        assert_eq!(vec![preview.commitment_digest()], committed, "{}", backend);
+
+        let existing = BlockOps {
+            added: vec![out_point(2)],
+            deleted: vec![],
+        };
+        assert!(matches!(
+            accumulator.preview_root(existing),
+            Err(AccumulatorError::AlreadyExists(0))
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+use pprof::{ProfilerGuard, Report};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+// sampling frequency in Hz, the phases of a block last a few milliseconds so the default 99 Hz would miss most of them
+const FREQUENCY: i32 = 997;
+
+/// A phase of a benchmark block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    // the expiry sweep of a block
+    Sweep,
+    // the update and the commit of a block by `apply_blocks`
+    Apply,
+}
+
+impl Phase {
+    const ALL: [Phase; 2] = [Phase::Sweep, Phase::Apply];
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Sweep => write!(f, "sweep"),
+            Phase::Apply => write!(f, "apply"),
+        }
+    }
+}
+
+/// Samples the CPU stacks of the phases of the benchmark blocks with pprof, one profile per phase. The profiler only
+/// runs while a phase runs, the samples of every block are merged into the profile of the phase.
+#[derive(Default)]
+pub struct PhaseProfiler {
+    reports: [Option<Report>; 2],
+}
+
+impl PhaseProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` as the given phase under the profiler.
+    pub fn profile<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> Result<T, pprof::Error> {
+        let guard = ProfilerGuard::new(FREQUENCY)?;
+        let result = f();
+        let report = guard.report().build()?;
+        match &mut self.reports[phase as usize] {
+            Some(merged) => {
+                for (frames, count) in report.data {
+                    *merged.data.entry(frames).or_insert(0) += count;
+                }
+                merged.timing.duration += report.timing.duration;
+            }
+            none => *none = Some(report),
+        }
+        Ok(result)
+    }
+
+    /// Writes the flamegraph of every profiled phase to `<dir>/<backend>-<phase>.svg`, returns the written paths.
+    pub fn write_flamegraphs(
+        &self,
+        dir: impl AsRef<Path>,
+        backend: &str,
+    ) -> Result<Vec<PathBuf>, pprof::Error> {
+        fs::create_dir_all(dir.as_ref())?;
+        let mut paths = Vec::new();
+        for (phase, report) in Phase::ALL.iter().zip(&self.reports) {
+            if let Some(report) = report {
+                let path = dir.as_ref().join(format!("{}-{}.svg", backend, phase));
+                report.flamegraph(fs::File::create(&path)?)?;
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::metrics::{Stage, StageTimes};
+use std::{fmt::Write as _, fs, io, path::Path, time::Duration};
+
+#[cfg(test)]
+mod tests;
+
+/// The upper bounds of the histogram buckets in seconds, from half a millisecond to 10 seconds.
+pub const BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A cumulative histogram of durations over `BUCKETS`, in the Prometheus histogram model.
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    // the number of samples of every bucket, not cumulative, the samples above the last bound are only in `count`
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    // appends the bucket, sum and count samples of the histogram, `labels` are the labels of the series without braces
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            )
+            .expect("write to string");
+        }
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        )
+        .expect("write to string");
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum).expect("write to string");
+        writeln!(out, "{}_count{{{}}} {}", name, labels, self.count).expect("write to string");
+    }
+}
+
+/// The processing time of the benchmark blocks and of their stages, exported in the text format of the node_exporter
+/// textfile collector, so that long runs can be monitored next to the machine metrics. The stage histograms only
+/// observe the blocks which spent time in the stage.
+pub struct BlockMetrics {
+    backend: String,
+    block: Histogram,
+    stages: [Histogram; 6],
+    last_block: Option<u64>,
+}
+
+impl BlockMetrics {
+    pub fn new(backend: &str) -> Self {
+        BlockMetrics {
+            backend: backend.to_string(),
+            block: Histogram::default(),
+            stages: Default::default(),
+            last_block: None,
+        }
+    }
+
+    /// Records the processing time of a block and the time it spent in every stage.
+    pub fn observe_block(&mut self, block_number: u64, elapsed: Duration, stages: &StageTimes) {
+        self.block.observe(elapsed);
+        for stage in Stage::ALL {
+            let elapsed = stages.get(stage);
+            if !elapsed.is_zero() {
+                self.stages[stage as usize].observe(elapsed);
+            }
+        }
+        self.last_block = Some(block_number);
+    }
+
+    /// Records the time spent in a stage outside of the blocks, e.g. the transaction commit of every 100 blocks.
+    pub fn observe_stage(&mut self, stage: Stage, elapsed: Duration) {
+        self.stages[stage as usize].observe(elapsed);
+    }
+
+    pub fn block(&self) -> &Histogram {
+        &self.block
+    }
+
+    pub fn stage(&self, stage: Stage) -> &Histogram {
+        &self.stages[stage as usize]
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let backend = format!("backend=\"{}\"", self.backend);
+        out.push_str(
+            "# HELP dca_bench_block_duration_seconds Processing time of a benchmark block.\n",
+        );
+        out.push_str("# TYPE dca_bench_block_duration_seconds histogram\n");
+        self.block
+            .render(&mut out, "dca_bench_block_duration_seconds", &backend);
+        out.push_str(
+            "# HELP dca_bench_stage_duration_seconds Time a benchmark block spent in a stage.\n",
+        );
+        out.push_str("# TYPE dca_bench_stage_duration_seconds histogram\n");
+        for stage in Stage::ALL {
+            let labels = format!("{},stage=\"{}\"", backend, stage.name());
+            self.stages[stage as usize].render(
+                &mut out,
+                "dca_bench_stage_duration_seconds",
+                &labels,
+            );
+        }
+        if let Some(block_number) = self.last_block {
+            out.push_str("# HELP dca_bench_last_block Number of the last processed block.\n");
+            out.push_str("# TYPE dca_bench_last_block gauge\n");
+            writeln!(out, "dca_bench_last_block{{{}}} {}", backend, block_number)
+                .expect("write to string");
+        }
+        out
+    }
+
+    /// Writes the metrics to `path`, through a temporary file renamed over it, so the collector never reads a partial
+    /// file. The path should end in `.prom` in the directory of the textfile collector.
+    pub fn write_textfile<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("prom.tmp");
+        fs::write(&tmp_path, self.render())?;
+        fs::rename(tmp_path, path)
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+use tempfile::Builder;
+
+use crate::metrics::{Stage, StageTimes};
+
+use super::{BlockMetrics, Histogram};
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::default();
+    histogram.observe(Duration::from_micros(300));
+    histogram.observe(Duration::from_millis(1));
+    histogram.observe(Duration::from_millis(30));
+    histogram.observe(Duration::from_secs(60));
+    assert_eq!(4, histogram.count());
+
+    let mut out = String::new();
+    histogram.render(&mut out, "h", "backend=\"smt\"");
+    // the buckets are cumulative, a sample on a bound is in its bucket, the last one is only in +Inf
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"0.0005\"} 1\n"));
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"0.001\"} 2\n"));
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"0.025\"} 2\n"));
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"0.05\"} 3\n"));
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"10\"} 3\n"));
+    assert!(out.contains("h_bucket{backend=\"smt\",le=\"+Inf\"} 4\n"));
+    assert!(out.contains("h_count{backend=\"smt\"} 4\n"));
+}
+
+#[test]
+fn test_block_metrics() {
+    let mut metrics = BlockMetrics::new("smt");
+    let mut stages = StageTimes::default();
+    stages.record(Stage::TreeUpdate, Duration::from_millis(2));
+    metrics.observe_block(0, Duration::from_millis(3), &stages);
+    metrics.observe_block(1, Duration::from_millis(5), &stages);
+    metrics.observe_stage(Stage::TxCommit, Duration::from_millis(20));
+    assert_eq!(2, metrics.block().count());
+    assert_eq!(2, metrics.stage(Stage::TreeUpdate).count());
+    // only the blocks which spent time in a stage are observed
+    assert_eq!(1, metrics.stage(Stage::TxCommit).count());
+    assert_eq!(0, metrics.stage(Stage::Hashing).count());
+
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let path = tmp_dir.path().join("dca_bench.prom");
+    metrics.write_textfile(&path).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("# TYPE dca_bench_block_duration_seconds histogram\n"));
+    assert!(content.contains(
+        "dca_bench_stage_duration_seconds_count{backend=\"smt\",stage=\"tx_commit\"} 1\n"
+    ));
+    assert!(content.contains("dca_bench_last_block{backend=\"smt\"} 1\n"));
+    assert!(!tmp_dir.path().join("dca_bench.prom.tmp").exists());
+}
@@ -0,0 +1,93 @@
+use crate::{
+    keycodec::{element_key, sequence_key},
+    storage::traits::ReadStore,
+    AccumulatorError, OutPoint,
+};
+use rocksdb::prelude::Put;
+use std::marker::PhantomData;
+
+#[cfg(test)]
+mod tests;
+
+pub const PROOF_KEY: &[u8] = b"PROOF";
+
+/// Serialized single cell proofs (`WalletProof::to_bytes` of any backend) precomputed at a sequence, to serve light
+/// clients without walking the tree. The cache is meant to be stored in a RocksDB of its own, so that its writes and size
+/// don't add to the accumulator's. The proofs are keyed by sequence and out_point and not versioned, a proof is written
+/// once for a sequence.
+pub struct ProofCache<'a, DB, WO> {
+    inner: &'a DB,
+    write_options: PhantomData<WO>,
+}
+
+impl<'a, DB, WO> ProofCache<'a, DB, WO> {
+    pub fn new(db: &'a DB) -> Self {
+        ProofCache {
+            inner: db,
+            write_options: PhantomData,
+        }
+    }
+}
+
+impl<'a, DB, WO> ProofCache<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// Returns the cached proof of an out_point at the given sequence, or `None` if it wasn't precomputed.
+    pub fn get(
+        &self,
+        sequence: u64,
+        out_point: &OutPoint,
+    ) -> Result<Option<Vec<u8>>, AccumulatorError> {
+        self.inner
+            .get(proof_key(sequence, out_point))
+            .map(|v| v.map(|v| v.to_vec()))
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+}
+
+impl<'a, DB, WO> ProofCache<'a, DB, WO>
+where
+    DB: Put<WO>,
+{
+    pub fn put(
+        &mut self,
+        sequence: u64,
+        out_point: &OutPoint,
+        proof: &[u8],
+    ) -> Result<(), AccumulatorError> {
+        self.inner
+            .put(proof_key(sequence, out_point), proof)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+
+    /// Generates the proofs of the given out_points at `sequence` with `prove` and stores them, the out_points `prove`
+    /// rejects as not found (i.e. not live at the sequence) are skipped. Returns the number of stored proofs.
+    pub fn precompute_proofs<I, F>(
+        &mut self,
+        sequence: u64,
+        out_points: I,
+        mut prove: F,
+    ) -> Result<u64, AccumulatorError>
+    where
+        I: IntoIterator<Item = OutPoint>,
+        F: FnMut(&OutPoint) -> Result<Vec<u8>, AccumulatorError>,
+    {
+        let mut stored = 0;
+        for out_point in out_points {
+            match prove(&out_point) {
+                Ok(proof) => {
+                    self.put(sequence, &out_point, &proof)?;
+                    stored += 1;
+                }
+                Err(AccumulatorError::ElementNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(stored)
+    }
+}
+
+fn proof_key(sequence: u64, out_point: &OutPoint) -> Vec<u8> {
+    element_key(&sequence_key(PROOF_KEY, sequence), out_point)
+}
@@ -0,0 +1,51 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use crate::{
+    smt::accumulator::{AccumulatorCommitment, SMTAccumulator, WalletProof},
+    AccumulatorWriter, OutPoint,
+};
+
+use super::ProofCache;
+
+#[test]
+fn test_precompute_proofs() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let out_points: Vec<_> = (0..4u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator.add(out_points[0..3].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let tx = db.transaction_default();
+    let mut cache = ProofCache::<_, ()>::new(&tx);
+    // the consumed and the unknown out_points are skipped
+    let stored = cache
+        .precompute_proofs(0, out_points.clone(), |out_point| {
+            Ok(
+                SMTAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_point.clone()], 0)?
+                    .to_bytes(),
+            )
+        })
+        .unwrap();
+    assert_eq!(2, stored);
+
+    let proof = cache.get(0, &out_points[2]).unwrap().unwrap();
+    let proof = WalletProof::from_bytes(&proof).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+    assert!(!proof
+        .verify(&AccumulatorCommitment::from_bytes(&[0; 40]).unwrap())
+        .unwrap());
+    assert!(cache.get(0, &out_points[1]).unwrap().is_none());
+    assert!(cache.get(1, &out_points[2]).unwrap().is_none());
+}
@@ -0,0 +1,265 @@
+use crate::{
+    metrics::{CellCosts, Stage, StageTimes},
+    AccumulatorError,
+};
+use std::{fmt, path::Path};
+
+#[cfg(test)]
+mod tests;
+
+/// The summary of a benchmark run, written by `--metrics <path>` as one `key=value` line per field and read back by
+/// the `report` subcommand.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunMetrics {
+    pub backend: String,
+    pub blocks: u64,
+    pub elapsed_millis: u64,
+    // Total size of the database files at the end of the run.
+    pub db_size: u64,
+    // Size of the wallet proof of one cell of the last block, at the last sequence.
+    pub proof_size: u64,
+    // 99th percentile of the per block latency of `apply_blocks`, which updates the tree and commits the block.
+    pub p99_commit_micros: u64,
+    // Time spent in every stage of the block pipeline, written as `stage_<name>_micros` lines.
+    pub stages: StageTimes,
+    // Logical keys written by the stores of the accumulator and their bytes, see `WriteCounts`.
+    pub keys_written: u64,
+    pub bytes_written: u64,
+    // The bytes written attributed to the created and consumed cells, written as their field names.
+    pub costs: CellCosts,
+    // The transaction commits which failed on a conflict, their retries and the blocks replayed by the retries, see
+    // `CommitConflicts`.
+    pub commit_conflicts: u64,
+    pub commit_retries: u64,
+    pub replayed_blocks: u64,
+    // The cells added and consumed in the same block, written once, see `CoalescedOps`.
+    pub coalesced_ops: u64,
+    // The cumulative write stall time of the database and the windows of consecutive stalled intervals, see
+    // `WriteStalls`.
+    pub stall_millis: u64,
+    pub stall_windows: u64,
+    // The memory configuration of the database, see `DbOptions`, written only when it's not the RocksDB default.
+    pub block_cache_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub max_write_buffer_number: Option<i32>,
+}
+
+impl RunMetrics {
+    pub fn parse(content: &str) -> Result<Self, AccumulatorError> {
+        let mut metrics = RunMetrics::default();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                AccumulatorError::InternalError(format!("invalid metrics line: {}", line))
+            })?;
+            let number = || {
+                value.trim().parse::<u64>().map_err(|_| {
+                    AccumulatorError::InternalError(format!("invalid metrics value: {}", line))
+                })
+            };
+            match key.trim() {
+                "backend" => metrics.backend = value.trim().to_string(),
+                "blocks" => metrics.blocks = number()?,
+                "elapsed_millis" => metrics.elapsed_millis = number()?,
+                "db_size" => metrics.db_size = number()?,
+                "proof_size" => metrics.proof_size = number()?,
+                "p99_commit_micros" => metrics.p99_commit_micros = number()?,
+                "keys_written" => metrics.keys_written = number()?,
+                "bytes_written" => metrics.bytes_written = number()?,
+                "cells_created" => metrics.costs.cells_created = number()?,
+                "cells_spent" => metrics.costs.cells_spent = number()?,
+                "transactions" => metrics.costs.transactions = number()?,
+                "created_bytes" => metrics.costs.created_bytes = number()?,
+                "spent_bytes" => metrics.costs.spent_bytes = number()?,
+                "commit_conflicts" => metrics.commit_conflicts = number()?,
+                "commit_retries" => metrics.commit_retries = number()?,
+                "replayed_blocks" => metrics.replayed_blocks = number()?,
+                "coalesced_ops" => metrics.coalesced_ops = number()?,
+                "stall_millis" => metrics.stall_millis = number()?,
+                "stall_windows" => metrics.stall_windows = number()?,
+                "block_cache_size" => metrics.block_cache_size = Some(number()? as usize),
+                "write_buffer_size" => metrics.write_buffer_size = Some(number()? as usize),
+                "max_write_buffer_number" => {
+                    metrics.max_write_buffer_number = Some(number()? as i32)
+                }
+                key if key.starts_with("stage_") => {
+                    let name = key
+                        .strip_prefix("stage_")
+                        .and_then(|key| key.strip_suffix("_micros"));
+                    if let Some(stage) = Stage::ALL
+                        .into_iter()
+                        .find(|stage| Some(stage.name()) == name)
+                    {
+                        metrics.stages.0[stage as usize] = number()? * 1000;
+                    }
+                }
+                // newer fields are skipped so that old binaries can still report
+                _ => {}
+            }
+        }
+        Ok(metrics)
+    }
+
+    /// Blocks per second.
+    pub fn throughput(&self) -> f64 {
+        self.blocks as f64 * 1000.0 / self.elapsed_millis.max(1) as f64
+    }
+
+    /// Logical keys written per second.
+    pub fn keys_per_sec(&self) -> f64 {
+        self.keys_written as f64 * 1000.0 / self.elapsed_millis.max(1) as f64
+    }
+
+    /// Bytes written per second, versioned keys and values.
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_written as f64 * 1000.0 / self.elapsed_millis.max(1) as f64
+    }
+}
+
+impl fmt::Display for RunMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "backend={}", self.backend)?;
+        writeln!(f, "blocks={}", self.blocks)?;
+        writeln!(f, "elapsed_millis={}", self.elapsed_millis)?;
+        writeln!(f, "db_size={}", self.db_size)?;
+        writeln!(f, "proof_size={}", self.proof_size)?;
+        writeln!(f, "p99_commit_micros={}", self.p99_commit_micros)?;
+        writeln!(f, "keys_written={}", self.keys_written)?;
+        writeln!(f, "bytes_written={}", self.bytes_written)?;
+        writeln!(f, "cells_created={}", self.costs.cells_created)?;
+        writeln!(f, "cells_spent={}", self.costs.cells_spent)?;
+        writeln!(f, "transactions={}", self.costs.transactions)?;
+        writeln!(f, "created_bytes={}", self.costs.created_bytes)?;
+        writeln!(f, "spent_bytes={}", self.costs.spent_bytes)?;
+        writeln!(f, "commit_conflicts={}", self.commit_conflicts)?;
+        writeln!(f, "commit_retries={}", self.commit_retries)?;
+        writeln!(f, "replayed_blocks={}", self.replayed_blocks)?;
+        writeln!(f, "coalesced_ops={}", self.coalesced_ops)?;
+        writeln!(f, "stall_millis={}", self.stall_millis)?;
+        writeln!(f, "stall_windows={}", self.stall_windows)?;
+        for stage in Stage::ALL {
+            writeln!(
+                f,
+                "stage_{}_micros={}",
+                stage.name(),
+                self.stages.get(stage).as_micros()
+            )?;
+        }
+        if let Some(size) = self.block_cache_size {
+            writeln!(f, "block_cache_size={}", size)?;
+        }
+        if let Some(size) = self.write_buffer_size {
+            writeln!(f, "write_buffer_size={}", size)?;
+        }
+        if let Some(number) = self.max_write_buffer_number {
+            writeln!(f, "max_write_buffer_number={}", number)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the cost model of the runs as a markdown table, the bytes written per created cell, per spent cell and per
+/// transaction, and the growth they project in a year at every transaction rate of `tx_rates`, see `CellCosts`.
+pub fn cost_table(runs: &[(String, RunMetrics)], tx_rates: &[f64]) -> String {
+    let mut table = String::from(
+        "| run | backend | per created cell (bytes) | per spent cell (bytes) | per tx (bytes) |",
+    );
+    for rate in tx_rates {
+        table.push_str(&format!(" growth at {} tx/s (GiB/year) |", rate));
+    }
+    table.push_str("\n|---|---|---:|---:|---:|");
+    table.push_str(&"---:|".repeat(tx_rates.len()));
+    table.push('\n');
+    for (label, metrics) in runs {
+        let costs = &metrics.costs;
+        table.push_str(&format!(
+            "| {} | {} | {:.0} | {:.0} | {:.0} |",
+            label,
+            metrics.backend,
+            costs.per_created_cell(),
+            costs.per_spent_cell(),
+            costs.per_transaction()
+        ));
+        for rate in tx_rates {
+            table.push_str(&format!(
+                " {:.1} |",
+                costs.annual_growth(*rate) / (1024.0 * 1024.0 * 1024.0)
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+/// Returns the `percentile` (0 - 100) of the samples with the nearest rank method, 0 if there are none.
+pub fn percentile(samples: &mut [u64], percentile: u64) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    samples.sort_unstable();
+    let rank = ((samples.len() as u64 * percentile + 99) / 100).max(1);
+    samples[rank as usize - 1]
+}
+
+/// Total size of the files in a directory and its subdirectories, i.e. of a RocksDB database. It only uses the standard
+/// library, so it's measured the same way on every platform, and the symbolic links are not followed.
+pub fn dir_size<P: AsRef<Path>>(path: P) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(entry.path())?;
+        } else if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Renders the runs as a markdown table, one row per run named by its label, e.g. the metrics file name.
+pub fn markdown_table(runs: &[(String, RunMetrics)]) -> String {
+    let mut table = String::from(
+        "| run | backend | blocks | throughput (blocks/s) | written (keys/s) | written (KiB/s) | final size (MiB) | proof size (bytes) | p99 commit (ms) |\n\
+         |---|---|---:|---:|---:|---:|---:|---:|---:|\n",
+    );
+    for (label, metrics) in runs {
+        table.push_str(&format!(
+            "| {} | {} | {} | {:.1} | {:.0} | {:.1} | {:.1} | {} | {:.3} |\n",
+            label,
+            metrics.backend,
+            metrics.blocks,
+            metrics.throughput(),
+            metrics.keys_per_sec(),
+            metrics.bytes_per_sec() / 1024.0,
+            metrics.db_size as f64 / (1024.0 * 1024.0),
+            metrics.proof_size,
+            metrics.p99_commit_micros as f64 / 1000.0
+        ));
+    }
+    table
+}
+
+/// Renders the stage breakdown of the runs as a markdown table, one row per run with the share of every stage of the
+/// block pipeline in percent, so the dominant stage of every backend stands out.
+pub fn stage_table(runs: &[(String, RunMetrics)]) -> String {
+    let mut table = String::from("| run | backend |");
+    for stage in Stage::ALL {
+        table.push_str(&format!(" {} (%) |", stage.name()));
+    }
+    table.push_str("\n|---|---|");
+    table.push_str(&"---:|".repeat(Stage::ALL.len()));
+    table.push('\n');
+    for (label, metrics) in runs {
+        let total = metrics.stages.total().as_secs_f64().max(f64::MIN_POSITIVE);
+        table.push_str(&format!("| {} | {} |", label, metrics.backend));
+        for stage in Stage::ALL {
+            table.push_str(&format!(
+                " {:.1} |",
+                metrics.stages.get(stage).as_secs_f64() * 100.0 / total
+            ));
+        }
+        table.push('\n');
+    }
+    table
+}
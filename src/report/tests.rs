@@ -0,0 +1,75 @@
+use super::{cost_table, dir_size, markdown_table, percentile, stage_table, RunMetrics};
+use crate::metrics::{CellCosts, StageTimes};
+use tempfile::Builder;
+
+#[test]
+fn test_metrics_roundtrip() {
+    let metrics = RunMetrics {
+        backend: "smt".to_string(),
+        blocks: 1000,
+        elapsed_millis: 4000,
+        db_size: 3 * 1024 * 1024,
+        proof_size: 1234,
+        p99_commit_micros: 2500,
+        stages: StageTimes([1000, 2000, 3000, 0, 4_000_000, 0]),
+        keys_written: 12_000,
+        bytes_written: 1024 * 1024,
+        costs: CellCosts {
+            cells_created: 10_000,
+            cells_spent: 5_000,
+            transactions: 10_000,
+            created_bytes: 2_000_000,
+            spent_bytes: 500_000,
+        },
+        commit_conflicts: 3,
+        commit_retries: 2,
+        replayed_blocks: 150,
+        coalesced_ops: 42,
+        stall_millis: 1250,
+        stall_windows: 2,
+        block_cache_size: Some(64 << 20),
+        write_buffer_size: None,
+        max_write_buffer_number: Some(4),
+    };
+    assert_eq!(metrics, RunMetrics::parse(&metrics.to_string()).unwrap());
+    assert!(RunMetrics::parse("blocks=many").is_err());
+
+    let runs = [("smt-run".to_string(), metrics)];
+    let table = markdown_table(&runs);
+    assert_eq!(
+        Some("| smt-run | smt | 1000 | 250.0 | 3000 | 256.0 | 3.0 | 1234 | 2.500 |"),
+        table.lines().nth(2)
+    );
+    assert_eq!(
+        Some("| smt-run | smt | 0.0 | 0.0 | 0.1 | 0.0 | 99.9 | 0.0 |"),
+        stage_table(&runs).lines().nth(2)
+    );
+    let table = cost_table(&runs, &[10.0]);
+    assert_eq!(
+        Some("| run | backend | per created cell (bytes) | per spent cell (bytes) | per tx (bytes) | growth at 10 tx/s (GiB/year) |"),
+        table.lines().next()
+    );
+    assert_eq!(
+        Some("| smt-run | smt | 200 | 100 | 250 | 73.4 |"),
+        table.lines().nth(2)
+    );
+}
+
+#[test]
+fn test_percentile() {
+    let mut samples: Vec<u64> = (1..=200).rev().collect();
+    assert_eq!(198, percentile(&mut samples, 99));
+    assert_eq!(1, percentile(&mut samples, 0));
+    assert_eq!(0, percentile(&mut [], 99));
+}
+
+#[test]
+fn test_dir_size() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let dir = tmp_dir.path();
+    std::fs::write(dir.join("000001.sst"), [0u8; 100]).unwrap();
+    std::fs::create_dir(dir.join("blobs")).unwrap();
+    std::fs::write(dir.join("blobs").join("000002.blob"), [0u8; 20]).unwrap();
+    assert_eq!(120, dir_size(dir).unwrap());
+    assert!(dir_size(dir.join("missing")).is_err());
+}
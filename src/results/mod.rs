@@ -0,0 +1,227 @@
+use crate::{new_blake2b, report::RunMetrics, AccumulatorError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// A run appended to the results ledger: its metrics, the crate commit it was built from and the hash of its
+/// configuration, runs of the same backend and configuration are compared by `trends`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunRecord {
+    pub commit: String,
+    pub config_hash: String,
+    pub metrics: RunMetrics,
+}
+
+impl RunRecord {
+    /// Serializes the record as one flat JSON object.
+    pub fn to_json_line(&self) -> String {
+        let line = LedgerLine {
+            backend: self.metrics.backend.clone(),
+            commit: self.commit.clone(),
+            config_hash: self.config_hash.clone(),
+            blocks: self.metrics.blocks,
+            elapsed_millis: self.metrics.elapsed_millis,
+            db_size: self.metrics.db_size,
+            proof_size: self.metrics.proof_size,
+            p99_commit_micros: self.metrics.p99_commit_micros,
+        };
+        serde_json::to_string(&line).expect("a ledger line is serializable")
+    }
+
+    pub fn parse_json_line(line: &str) -> Result<Self, AccumulatorError> {
+        let line: LedgerLine = serde_json::from_str(line).map_err(|e| {
+            AccumulatorError::InternalError(format!("invalid results line: {}: {}", line, e))
+        })?;
+        Ok(RunRecord {
+            commit: line.commit,
+            config_hash: line.config_hash,
+            metrics: RunMetrics {
+                backend: line.backend,
+                blocks: line.blocks,
+                elapsed_millis: line.elapsed_millis,
+                db_size: line.db_size,
+                proof_size: line.proof_size,
+                p99_commit_micros: line.p99_commit_micros,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+// a record as a line of the ledger, the fields missing from a line are zero and the newer fields are skipped like in the
+// metrics files
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LedgerLine {
+    backend: String,
+    commit: String,
+    config_hash: String,
+    blocks: u64,
+    elapsed_millis: u64,
+    db_size: u64,
+    proof_size: u64,
+    p99_commit_micros: u64,
+}
+
+/// The hash of a run configuration, i.e. its backend and flags without the paths, as 16 hex chars. The arguments are
+/// sorted first, so the hash doesn't depend on the order of the flags.
+pub fn config_hash<S: AsRef<str>>(config: &[S]) -> String {
+    let mut config: Vec<&str> = config.iter().map(|arg| arg.as_ref()).collect();
+    config.sort_unstable();
+    let mut hash = [0u8; 32];
+    let mut hasher = new_blake2b();
+    for arg in config {
+        hasher.update(arg.as_bytes());
+        // separates the arguments so that `a b` and `ab` differ
+        hasher.update(&[0]);
+    }
+    hasher.finalize(&mut hash);
+    hash[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// An append-only JSONL file of run records, one per line.
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Ledger {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, record: &RunRecord) -> Result<(), AccumulatorError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        writeln!(file, "{}", record.to_json_line())
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+
+    /// Returns the records in the order they were appended.
+    pub fn records(&self) -> Result<Vec<RunRecord>, AccumulatorError> {
+        std::fs::read_to_string(&self.path)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(RunRecord::parse_json_line)
+            .collect()
+    }
+}
+
+/// The latest run of a backend and configuration compared with the previous one.
+pub struct Trend {
+    pub previous: RunRecord,
+    pub latest: RunRecord,
+    // the metrics that got worse by more than the threshold, with their change in percent
+    pub regressions: Vec<(&'static str, f64)>,
+}
+
+impl Trend {
+    /// The change in percent of every metric, throughput is better higher and the others lower.
+    pub fn changes(&self) -> [(&'static str, f64, bool); 4] {
+        let (previous, latest) = (&self.previous.metrics, &self.latest.metrics);
+        [
+            (
+                "throughput",
+                change(previous.throughput(), latest.throughput()),
+                true,
+            ),
+            (
+                "final size",
+                change(previous.db_size as f64, latest.db_size as f64),
+                false,
+            ),
+            (
+                "proof size",
+                change(previous.proof_size as f64, latest.proof_size as f64),
+                false,
+            ),
+            (
+                "p99 commit",
+                change(
+                    previous.p99_commit_micros as f64,
+                    latest.p99_commit_micros as f64,
+                ),
+                false,
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [config {}] {} -> {}:",
+            self.latest.metrics.backend,
+            self.latest.config_hash,
+            self.previous.commit,
+            self.latest.commit
+        )?;
+        for (i, (name, change, _)) in self.changes().iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} {} {:+.1}%", separator, name, change)?;
+        }
+        for (name, change) in &self.regressions {
+            write!(f, "\n  regression: {} {:+.1}%", name, change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the latest run of every backend and configuration with the previous one, in the order the configurations
+/// first appear in the records, a metric regresses when it got worse by more than `threshold_percent`.
+pub fn trends(records: &[RunRecord], threshold_percent: f64) -> Vec<Trend> {
+    let mut groups: Vec<Vec<&RunRecord>> = Vec::new();
+    for record in records {
+        match groups.iter_mut().find(|group| {
+            group[0].metrics.backend == record.metrics.backend
+                && group[0].config_hash == record.config_hash
+        }) {
+            Some(group) => group.push(record),
+            None => groups.push(vec![record]),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|group| {
+            let mut trend = Trend {
+                previous: group[group.len() - 2].clone(),
+                latest: group[group.len() - 1].clone(),
+                regressions: Vec::new(),
+            };
+            trend.regressions = trend
+                .changes()
+                .iter()
+                .filter(|(_, change, higher_is_better)| {
+                    if *higher_is_better {
+                        -change > threshold_percent
+                    } else {
+                        *change > threshold_percent
+                    }
+                })
+                .map(|(name, change, _)| (*name, *change))
+                .collect();
+            trend
+        })
+        .collect()
+}
+
+fn change(previous: f64, latest: f64) -> f64 {
+    if previous == 0.0 {
+        return 0.0;
+    }
+    (latest - previous) * 100.0 / previous
+}
@@ -0,0 +1,74 @@
+use tempfile::Builder;
+
+use crate::report::RunMetrics;
+
+use super::{config_hash, trends, Ledger, RunRecord};
+
+fn record(commit: &str, config: &[&str], elapsed_millis: u64, db_size: u64) -> RunRecord {
+    RunRecord {
+        commit: commit.to_string(),
+        config_hash: config_hash(config),
+        metrics: RunMetrics {
+            backend: config[0].to_string(),
+            blocks: 1000,
+            elapsed_millis,
+            db_size,
+            proof_size: 1234,
+            p99_commit_micros: 2500,
+            ..Default::default()
+        },
+    }
+}
+
+#[test]
+fn test_ledger_roundtrip() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let ledger = Ledger::new(tmp_dir.path().join("results.jsonl"));
+    let mut quoted = record("abc", &["smt", "--strict"], 4000, 100);
+    quoted.commit = "a \"b\" \\c".to_string();
+    ledger.append(&quoted).unwrap();
+    ledger.append(&record("def", &["mmr"], 4000, 100)).unwrap();
+    assert_eq!(
+        vec![quoted, record("def", &["mmr"], 4000, 100)],
+        ledger.records().unwrap()
+    );
+    assert!(RunRecord::parse_json_line("{\"blocks\":\"many\"}").is_err());
+    assert!(RunRecord::parse_json_line("{\"blocks\":1").is_err());
+    assert_ne!(
+        config_hash(&["smt", "--strict"]),
+        config_hash(&["smt--strict"])
+    );
+    // the flags of a configuration are hashed in a canonical order
+    assert_eq!(
+        config_hash(&["backend=smt", "strict=true", "db_kind=plain"]),
+        config_hash(&["backend=smt", "db_kind=plain", "strict=true"])
+    );
+}
+
+// a line written by an older version misses the newer fields, which are zero
+#[test]
+fn test_ledger_line_fields() {
+    let record =
+        RunRecord::parse_json_line("{\"backend\":\"smt\",\"blocks\":10,\"future\":[1]}").unwrap();
+    assert_eq!("smt", record.metrics.backend);
+    assert_eq!(10, record.metrics.blocks);
+    assert_eq!(0, record.metrics.proof_size);
+}
+
+#[test]
+fn test_trends() {
+    let records = vec![
+        record("a", &["smt"], 4000, 100),
+        record("a", &["smt", "--strict"], 4000, 100),
+        record("b", &["smt"], 4000, 100),
+        // slower by a third and larger by 5%
+        record("c", &["smt"], 6000, 105),
+    ];
+    let trends = trends(&records, 10.0);
+    // a single run of a configuration has no trend
+    assert_eq!(1, trends.len());
+    assert_eq!("b", trends[0].previous.commit);
+    assert_eq!("c", trends[0].latest.commit);
+    assert_eq!(1, trends[0].regressions.len());
+    assert_eq!("throughput", trends[0].regressions[0].0);
+}
@@ -0,0 +1,157 @@
+use crate::{
+    sweep::SweepCells, AccumulatorError, AccumulatorWriter, BlockOps, CellStatus, OutPoint,
+};
+use std::{fmt, time::Duration};
+
+/// How a transaction commit failing on a conflict is retried: at most `max_retries` times, waiting `initial_backoff`
+/// before the first retry and twice as long before every next one, up to `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before the retry `attempt`, counted from 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32 << attempt.min(31))
+            .min(self.max_backoff)
+    }
+}
+
+/// The code of the RocksDB status a failed operation returned. The errors of the rocksdb crate only carry the status
+/// rendered by `Status::ToString`, whose fixed prefix names the code, so the code is recovered from it once here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusCode {
+    NotFound,
+    Corruption,
+    NotSupported,
+    InvalidArgument,
+    IOError,
+    MergeInProgress,
+    Incomplete,
+    ShutdownInProgress,
+    TimedOut,
+    Aborted,
+    /// An optimistic commit whose keys were written since they were read, or a lock held by another transaction.
+    Busy,
+    Expired,
+    /// A commit whose conflict check ran out of memtable history.
+    TryAgain,
+    CompactionTooLarge,
+    ColumnFamilyDropped,
+    Unknown,
+}
+
+impl StatusCode {
+    // the prefixes of `Status::ToString`, by code
+    const PREFIXES: [(&'static str, StatusCode); 15] = [
+        ("NotFound", StatusCode::NotFound),
+        ("Corruption", StatusCode::Corruption),
+        ("Not implemented", StatusCode::NotSupported),
+        ("Invalid argument", StatusCode::InvalidArgument),
+        ("IO error", StatusCode::IOError),
+        ("Merge in progress", StatusCode::MergeInProgress),
+        ("Result incomplete", StatusCode::Incomplete),
+        ("Shutdown in progress", StatusCode::ShutdownInProgress),
+        ("Operation timed out", StatusCode::TimedOut),
+        ("Operation aborted", StatusCode::Aborted),
+        ("Resource busy", StatusCode::Busy),
+        ("Operation expired", StatusCode::Expired),
+        ("Operation failed. Try again.", StatusCode::TryAgain),
+        ("Compaction too large", StatusCode::CompactionTooLarge),
+        ("Column family dropped", StatusCode::ColumnFamilyDropped),
+    ];
+
+    pub fn of(error: &rocksdb::Error) -> Self {
+        let message = error.to_string();
+        Self::PREFIXES
+            .iter()
+            .find(|(prefix, _)| message.starts_with(prefix))
+            .map_or(StatusCode::Unknown, |(_, code)| *code)
+    }
+}
+
+/// Whether a RocksDB error is a write conflict, which the transaction can be retried after: a `Busy` or `TryAgain`
+/// status, see `StatusCode`.
+pub fn is_conflict(error: &rocksdb::Error) -> bool {
+    matches!(
+        StatusCode::of(error),
+        StatusCode::Busy | StatusCode::TryAgain
+    )
+}
+
+/// A block written since the last transaction commit, kept in memory to be replayed on a new transaction if the commit
+/// conflicts: its sequence, its operations and the `created_before` sequence of the expiry sweep run in it, if any.
+#[derive(Clone, Debug)]
+pub struct UncommittedBlock {
+    pub sequence: u64,
+    pub ops: BlockOps,
+    pub sweep: Option<u64>,
+}
+
+/// Replays the blocks lost with a conflicting transaction on an accumulator opened on a new one, the way they were first
+/// applied: the expiry sweep of the cells matching `is_locked`, then `apply_blocks`. Returns the commitment of the last
+/// block, which matches the lost one unless a concurrent writer changed the state the blocks were built on.
+pub fn replay_blocks<A, F>(
+    accumulator: &mut A,
+    blocks: &[UncommittedBlock],
+    mut is_locked: F,
+) -> Result<Option<A::Commitment>, AccumulatorError>
+where
+    A: AccumulatorWriter<Item = OutPoint> + SweepCells,
+    F: FnMut(&[u8; 32], &CellStatus) -> bool,
+{
+    let mut commitment = None;
+    for block in blocks {
+        if let Some(created_before) = block.sweep {
+            accumulator.sweep(created_before, &mut is_locked)?;
+        }
+        commitment = accumulator.apply_blocks(vec![block.ops.clone()])?.pop();
+    }
+    Ok(commitment)
+}
+
+/// The transaction commits of a run which failed on a conflict, the retries and the blocks they replayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitConflicts {
+    pub conflicts: u64,
+    pub retries: u64,
+    pub replayed_blocks: u64,
+}
+
+impl CommitConflicts {
+    /// Records a retry of a conflicting commit, which replayed `blocks` uncommitted blocks.
+    pub fn record_retry(&mut self, blocks: u64) {
+        self.conflicts += 1;
+        self.retries += 1;
+        self.replayed_blocks += blocks;
+    }
+
+    /// Records a conflicting commit which isn't retried.
+    pub fn record_failure(&mut self) {
+        self.conflicts += 1;
+    }
+}
+
+impl fmt::Display for CommitConflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "commit conflicts: {} conflicts, {} retries, {} replayed blocks",
+            self.conflicts, self.retries, self.replayed_blocks
+        )
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    keycodec::{parse_sequence_key, sequence_key},
+    storage::traits::ReadStore,
+    AccumulatorError,
+};
+use rocksdb::{prelude::Put, Direction, IteratorMode};
+use std::ops::Range;
+
+/// The key of the sequence width of a database, it isn't versioned and doesn't collide with the keys of any backend.
+pub const SEQUENCE_WIDTH_KEY: &[u8] = b"SEQWIDTH";
+
+/// Prefixes the records of the sequences skipped by `AccumulatorWriter::skip_to`, `SKIPPED_KEY || first skipped
+/// sequence (8 BE)` to the sequence committed after them (8 BE). They aren't versioned and don't collide with the keys of
+/// any backend either.
+pub const SKIPPED_KEY: &[u8] = b"SKIPPED";
+
+/// The width of the sequence suffix of the versioned keys, chosen once per database by `set_sequence_width`. Both widths
+/// are big endian encoded, so the versions of a key are sorted by sequence, and fixed, so the key families of a backend
+/// are still told apart by their length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SequenceWidth {
+    #[default]
+    U64,
+    // Saves 4 bytes per versioned key, a store panics when it's asked to write a sequence beyond `u32::MAX`.
+    U32,
+}
+
+impl SequenceWidth {
+    pub fn encoded_len(self) -> usize {
+        match self {
+            SequenceWidth::U64 => 8,
+            SequenceWidth::U32 => 4,
+        }
+    }
+
+    /// The largest sequence of the width, a reverse seek from it finds the latest version of a key.
+    pub fn max_sequence(self) -> u64 {
+        match self {
+            SequenceWidth::U64 => u64::MAX,
+            SequenceWidth::U32 => u32::MAX as u64,
+        }
+    }
+
+    /// Appends the encoded sequence to a key.
+    pub fn versioned_key(self, key: &[u8], sequence: u64) -> Vec<u8> {
+        match self {
+            SequenceWidth::U64 => [key, sequence.to_be_bytes().as_ref()].concat(),
+            SequenceWidth::U32 => {
+                let sequence =
+                    u32::try_from(sequence).expect("sequence should fit in the 32 bits width");
+                [key, sequence.to_be_bytes().as_ref()].concat()
+            }
+        }
+    }
+
+    /// Splits a versioned key into the key and its sequence.
+    pub fn split(self, versioned_key: &[u8]) -> (&[u8], u64) {
+        let (key, version) = versioned_key.split_at(versioned_key.len() - self.encoded_len());
+        let sequence = match self {
+            SequenceWidth::U64 => u64::from_be_bytes(version.try_into().expect("checked length")),
+            SequenceWidth::U32 => {
+                u32::from_be_bytes(version.try_into().expect("checked length")) as u64
+            }
+        };
+        (key, sequence)
+    }
+}
+
+/// Returns every stored version of a key as `(sequence, value)` by increasing sequence, an empty value is a removal.
+pub fn key_versions<DB: ReadStore + ?Sized>(
+    db: &DB,
+    key: &[u8],
+    width: SequenceWidth,
+) -> Vec<(u64, Box<[u8]>)> {
+    db.iterator(IteratorMode::From(key, Direction::Forward))
+        .take_while(|(k, _v)| k.starts_with(key))
+        // a longer key of another family may start with the key
+        .filter(|(k, _v)| k.len() == key.len() + width.encoded_len())
+        .map(|(k, v)| (width.split(&k).1, v))
+        .collect()
+}
+
+/// The record of a gap of skipped sequences, written by the commit which ends it, see `SKIPPED_KEY`.
+pub(crate) fn skipped_record(gap: Range<u64>) -> (Vec<u8>, [u8; 8]) {
+    (sequence_key(SKIPPED_KEY, gap.start), gap.end.to_be_bytes())
+}
+
+/// Returns the gaps of sequences skipped by `skip_to` by increasing sequence, the sequences of a gap were never committed
+/// and read as the commit before it.
+pub fn skipped_sequences<DB: ReadStore + ?Sized>(db: &DB) -> Vec<Range<u64>> {
+    db.iterator(IteratorMode::From(SKIPPED_KEY, Direction::Forward))
+        .take_while(|(key, _value)| key.starts_with(SKIPPED_KEY))
+        .filter_map(|(key, value)| {
+            let start = parse_sequence_key(SKIPPED_KEY, &key)?;
+            let end = u64::from_be_bytes(value.as_ref().try_into().ok()?);
+            Some(start..end)
+        })
+        .collect()
+}
+
+pub fn stored_sequence_width<DB: ReadStore + ?Sized>(
+    db: &DB,
+) -> Result<SequenceWidth, rocksdb::Error> {
+    Ok(match db.get(SEQUENCE_WIDTH_KEY)?.as_deref() {
+        Some([4]) => SequenceWidth::U32,
+        _ => SequenceWidth::U64,
+    })
+}
+
+/// Sets the sequence width of a database, it must be set before anything else is written to the database and can only
+/// be changed afterwards by copying the database with `migrate_sequence_width`.
+pub fn set_sequence_width<DB, WO>(db: &DB, width: SequenceWidth) -> Result<(), AccumulatorError>
+where
+    DB: ReadStore + Put<WO>,
+{
+    let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+    if stored_sequence_width(db).map_err(map_err)? != width
+        && db
+            .iterator(IteratorMode::Start)
+            .any(|(key, _value)| key.as_ref() != SEQUENCE_WIDTH_KEY)
+    {
+        return Err(AccumulatorError::InternalError(
+            "the sequence width of a non-empty database can't be changed".to_string(),
+        ));
+    }
+    db.put(SEQUENCE_WIDTH_KEY, [width.encoded_len() as u8])
+        .map_err(map_err)
+}
+
+/// Copies a database into an empty `target` with its versioned keys re-encoded in `width`, the versioned keys of the
+/// backend are told apart by `is_versioned`, e.g. `smt::is_versioned_key`. Returns the number of re-encoded keys.
+pub fn migrate_sequence_width<S, T, WO, F>(
+    source: &S,
+    target: &T,
+    width: SequenceWidth,
+    is_versioned: F,
+) -> Result<u64, AccumulatorError>
+where
+    S: ReadStore,
+    T: ReadStore + Put<WO>,
+    F: Fn(&[u8], SequenceWidth) -> bool,
+{
+    let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+    let from = stored_sequence_width(source).map_err(map_err)?;
+    set_sequence_width::<_, WO>(target, width)?;
+    let mut migrated = 0;
+    for (key, value) in source.iterator(IteratorMode::Start) {
+        if key.as_ref() == SEQUENCE_WIDTH_KEY {
+            continue;
+        }
+        if is_versioned(&key, from) {
+            let (key, sequence) = from.split(&key);
+            target
+                .put(width.versioned_key(key, sequence), value)
+                .map_err(map_err)?;
+            migrated += 1;
+        } else {
+            target.put(key, value).map_err(map_err)?;
+        }
+    }
+    Ok(migrated)
+}
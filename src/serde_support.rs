@@ -0,0 +1,34 @@
+use crate::{mmr, smt, smt_live};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+// the commitments and serialized proofs are (de)serialized as their `to_bytes` encoding, so every serde format carries
+// the same bytes as the C ABI and the sync protocol
+macro_rules! impl_serde_bytes {
+    ($($ty: ty),*) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_bytes(&self.to_bytes())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let bytes = Vec::<u8>::deserialize(deserializer)?;
+                    <$ty>::from_bytes(&bytes).map_err(|e| D::Error::custom(format!("{:?}", e)))
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_bytes!(
+    smt::accumulator::AccumulatorCommitment,
+    smt::accumulator::WalletProof,
+    smt_live::accumulator::AccumulatorCommitment,
+    smt_live::accumulator::WalletProof,
+    mmr::accumulator::AccumulatorCommitment,
+    mmr::accumulator::WalletProof,
+    mmr::position_index::PositionedCommitment,
+    mmr::position_index::PositionedWalletProof
+);
@@ -0,0 +1,283 @@
+use crate::AccumulatorError;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{LineWriter, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Sender, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The commitment of a block as published to a `CommitmentSink`, written as a `<block number> <sequence> <digest hex>`
+/// line so that another process can consume the stream of roots line by line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublishedCommitment {
+    pub block_number: u64,
+    pub sequence: u64,
+    /// The commitment digest of the header of the block, see `crate::commitment_digest`.
+    pub digest: [u8; 32],
+}
+
+impl fmt::Display for PublishedCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.block_number, self.sequence)?;
+        for byte in self.digest {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PublishedCommitment {
+    type Err = AccumulatorError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || AccumulatorError::InternalError(format!("invalid commitment line: {}", line));
+        let mut fields = line.split_whitespace();
+        let block_number = fields.next().and_then(|field| field.parse().ok());
+        let sequence = fields.next().and_then(|field| field.parse().ok());
+        let digest = fields.next().filter(|field| field.len() == 64);
+        match (block_number, sequence, digest, fields.next()) {
+            (Some(block_number), Some(sequence), Some(hex), None) => {
+                let mut digest = [0u8; 32];
+                for (i, byte) in digest.iter_mut().enumerate() {
+                    *byte = hex
+                        .get(i * 2..i * 2 + 2)
+                        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                        .ok_or_else(invalid)?;
+                }
+                Ok(PublishedCommitment {
+                    block_number,
+                    sequence,
+                    digest,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Receives the commitment of every block of a benchmark run right after it's committed, so that external systems, e.g.
+/// a second verification process, consume the roots while the run goes on.
+pub trait CommitmentSink {
+    fn publish(&mut self, commitment: &PublishedCommitment) -> Result<(), AccumulatorError>;
+
+    /// Waits until every published commitment is delivered, called once at the end of the run.
+    fn finish(&mut self) -> Result<(), AccumulatorError> {
+        Ok(())
+    }
+}
+
+fn io_error(e: std::io::Error) -> AccumulatorError {
+    AccumulatorError::InternalError(e.to_string())
+}
+
+/// Appends the commitments to a file, one line each, written as soon as it's published. The file can be a named pipe
+/// read by another process.
+pub struct FileSink(LineWriter<File>);
+
+impl FileSink {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, AccumulatorError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(io_error)?;
+        Ok(FileSink(LineWriter::new(file)))
+    }
+}
+
+impl CommitmentSink for FileSink {
+    fn publish(&mut self, commitment: &PublishedCommitment) -> Result<(), AccumulatorError> {
+        writeln!(self.0, "{}", commitment).map_err(io_error)
+    }
+}
+
+/// POSTs every commitment line to a plain `http://host[:port]/path` endpoint, on a connection of its own, and fails
+/// unless the response status is 2xx. It only speaks HTTP/1.1 without TLS, to keep the benchmark free of an HTTP client.
+/// The requests are sent by a background thread, so a slow endpoint doesn't hold the timed run, over a channel of
+/// `HTTP_SINK_QUEUE` commitments: `publish` only waits once the queue is full. A request failing or timing out stops the
+/// thread, and its error is returned by the next `publish` or by `finish`.
+pub struct HttpSink {
+    sender: Option<SyncSender<PublishedCommitment>>,
+    worker: Option<JoinHandle<Result<(), AccumulatorError>>>,
+}
+
+/// The commitments an `HttpSink` queues before `publish` waits for the endpoint.
+pub const HTTP_SINK_QUEUE: usize = 1024;
+
+/// The connect, read and write timeout of every request of an `HttpSink`.
+pub const HTTP_SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// splits an `http://host[:port]/path` url into `host:port` and the path, `None` if it isn't one
+fn parse_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return None;
+    }
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    Some((address, path.to_string()))
+}
+
+impl HttpSink {
+    pub fn new(url: &str) -> Result<Self, AccumulatorError> {
+        Self::with_timeout(url, HTTP_SINK_TIMEOUT)
+    }
+
+    pub fn with_timeout(url: &str, timeout: Duration) -> Result<Self, AccumulatorError> {
+        let (address, path) = parse_url(url)
+            .ok_or_else(|| AccumulatorError::InternalError(format!("invalid http url: {}", url)))?;
+        let (sender, receiver) = mpsc::sync_channel::<PublishedCommitment>(HTTP_SINK_QUEUE);
+        let worker = thread::spawn(move || {
+            for commitment in receiver {
+                post(&address, &path, timeout, &commitment)?;
+            }
+            Ok(())
+        });
+        Ok(HttpSink {
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    // the error which stopped the worker, once it's stopped
+    fn worker_error(&mut self) -> AccumulatorError {
+        self.sender = None;
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => e,
+            Some(Err(_)) => AccumulatorError::InternalError("the http sink panicked".to_string()),
+            _ => AccumulatorError::InternalError("the http sink is finished".to_string()),
+        }
+    }
+}
+
+// POSTs a commitment line and checks the response status, every step bounded by `timeout`
+fn post(
+    address: &str,
+    path: &str,
+    timeout: Duration,
+    commitment: &PublishedCommitment,
+) -> Result<(), AccumulatorError> {
+    let body = format!("{}\n", commitment);
+    let socket_addr = address
+        .to_socket_addrs()
+        .map_err(io_error)?
+        .next()
+        .ok_or_else(|| {
+            AccumulatorError::InternalError(format!("unresolved address: {}", address))
+        })?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, timeout).map_err(io_error)?;
+    stream.set_read_timeout(Some(timeout)).map_err(io_error)?;
+    stream.set_write_timeout(Some(timeout)).map_err(io_error)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        address,
+        body.len(),
+        body
+    )
+    .map_err(io_error)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(io_error)?;
+    let status = response.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(AccumulatorError::InternalError(format!(
+            "commitment rejected by {}: {}",
+            address, status
+        ))),
+    }
+}
+
+impl CommitmentSink for HttpSink {
+    fn publish(&mut self, commitment: &PublishedCommitment) -> Result<(), AccumulatorError> {
+        // a full queue waits for the endpoint, a disconnected one means the worker failed
+        let sent = match self
+            .sender
+            .as_ref()
+            .map(|sender| (sender, sender.try_send(*commitment)))
+        {
+            Some((_, Ok(()))) => Ok(()),
+            Some((sender, Err(TrySendError::Full(commitment)))) => {
+                sender.send(commitment).map_err(|_| ())
+            }
+            Some((_, Err(TrySendError::Disconnected(_)))) | None => Err(()),
+        };
+        sent.map_err(|()| self.worker_error())
+    }
+
+    fn finish(&mut self) -> Result<(), AccumulatorError> {
+        self.sender = None;
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(AccumulatorError::InternalError(
+                "the http sink panicked".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for HttpSink {
+    // delivers the queued commitments of a sink dropped without `finish`, its error is lost
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Sends the commitments to a channel, e.g. to a verifier thread of the same process.
+pub struct ChannelSink(pub Sender<PublishedCommitment>);
+
+impl CommitmentSink for ChannelSink {
+    fn publish(&mut self, commitment: &PublishedCommitment) -> Result<(), AccumulatorError> {
+        self.0.send(*commitment).map_err(|_| {
+            AccumulatorError::InternalError("the commitment receiver is gone".to_string())
+        })
+    }
+}
+
+/// Where a benchmark run publishes its commitments, an `http://` url or otherwise the path of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SinkTarget {
+    File(PathBuf),
+    Http(String),
+}
+
+impl FromStr for SinkTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.starts_with("http://") {
+            // checked here so that a malformed url fails before the run
+            parse_url(value).ok_or("must be http://<host>[:<port>]/<path>")?;
+            Ok(SinkTarget::Http(value.to_string()))
+        } else {
+            Ok(SinkTarget::File(PathBuf::from(value)))
+        }
+    }
+}
+
+impl SinkTarget {
+    pub fn open(&self) -> Result<Box<dyn CommitmentSink>, AccumulatorError> {
+        Ok(match self {
+            SinkTarget::File(path) => Box::new(FileSink::new(path)?),
+            SinkTarget::Http(url) => Box::new(HttpSink::new(url)?),
+        })
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::mpsc::channel,
+    thread,
+    time::Duration,
+};
+use tempfile::Builder;
+
+use super::{ChannelSink, CommitmentSink, FileSink, HttpSink, PublishedCommitment, SinkTarget};
+
+fn commitment(block_number: u64) -> PublishedCommitment {
+    PublishedCommitment {
+        block_number,
+        sequence: block_number - 100,
+        digest: [block_number as u8; 32],
+    }
+}
+
+#[test]
+fn test_commitment_line() {
+    let line = commitment(171).to_string();
+    assert_eq!(format!("171 71 {}", "ab".repeat(32)), line);
+    assert_eq!(
+        commitment(171),
+        line.parse::<PublishedCommitment>().unwrap()
+    );
+    assert!("171 71".parse::<PublishedCommitment>().is_err());
+    assert!(format!("171 71 {}", "zz".repeat(32))
+        .parse::<PublishedCommitment>()
+        .is_err());
+}
+
+#[test]
+fn test_file_and_channel_sinks() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let path = tmp_dir.path().join("commitments");
+    // a second sink appends to the lines of the first one
+    for block_number in [101, 102] {
+        let mut sink = FileSink::new(&path).unwrap();
+        sink.publish(&commitment(block_number)).unwrap();
+    }
+    let lines: Vec<PublishedCommitment> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| line.parse().unwrap())
+        .collect();
+    assert_eq!(vec![commitment(101), commitment(102)], lines);
+
+    let (sender, receiver) = channel();
+    let mut sink = ChannelSink(sender);
+    sink.publish(&commitment(103)).unwrap();
+    assert_eq!(commitment(103), receiver.recv().unwrap());
+    drop(receiver);
+    assert!(sink.publish(&commitment(104)).is_err());
+}
+
+// whether a request holds its headers and the body of its content length
+fn is_complete(request: &[u8]) -> bool {
+    let request = String::from_utf8_lossy(request);
+    match request.split_once("\r\n\r\n") {
+        Some((headers, body)) => headers
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(false, |len| body.len() >= len.parse().unwrap()),
+        None => false,
+    }
+}
+
+#[test]
+fn test_http_sink() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/roots", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for status in ["200 OK", "500 Internal Server Error"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !is_complete(&request) {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            requests.push(String::from_utf8(request).unwrap());
+        }
+        requests
+    });
+
+    // the requests are sent in the background, the rejected one fails the sink once it's answered
+    let mut sink = HttpSink::new(&url).unwrap();
+    sink.publish(&commitment(101)).unwrap();
+    sink.publish(&commitment(102)).unwrap();
+    assert!(sink.finish().is_err());
+    assert!(sink.publish(&commitment(103)).is_err());
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("POST /roots HTTP/1.1\r\n"));
+    assert!(requests[0].ends_with(&format!("\r\n\r\n{}\n", commitment(101))));
+
+    assert_eq!(Ok(SinkTarget::Http(url.clone())), url.parse::<SinkTarget>());
+    assert!("http:///roots".parse::<SinkTarget>().is_err());
+
+    // an endpoint which never answers times out instead of hanging the run
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/roots", listener.local_addr().unwrap());
+    let mut sink = HttpSink::with_timeout(&url, Duration::from_millis(100)).unwrap();
+    sink.publish(&commitment(104)).unwrap();
+    let (_stream, _) = listener.accept().unwrap();
+    assert!(sink.finish().is_err());
+    assert_eq!(
+        Ok(SinkTarget::File(PathBuf::from("roots.txt"))),
+        "roots.txt".parse::<SinkTarget>()
+    );
+}
@@ -1,95 +1,497 @@
-use super::{store::DefaultStore, ZERO_CELL_STATUS};
-use crate::{AccumulatorError, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof};
-use rocksdb::{
-    prelude::{Delete, Get, Iterate, Put},
-    ReadOptions,
+use super::{
+    store::{DefaultStore, KeyDerivation, TreeStore, TreeStoreMut},
+    ZERO_CELL_STATUS,
+};
+use crate::{
+    commitment_digest, ct_eq, debug_commitment,
+    element_index::IndexedElements,
+    live_cache::{CacheLiveCells, LiveCache},
+    metrics::{
+        CoalescedOps, FlushWrites, KeyTracer, LevelHistogram, LevelWrites, Stage, StageTimes,
+        StageTiming, TraceKeys,
+    },
+    overlay::SMTOverlayStore,
+    storage::traits::{ReadStore, WriteStore},
+    sweep::{SweepCells, Swept},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
 };
 use sparse_merkle_tree::{
-    blake2b::Blake2bHasher, error::Error, traits::Value, MerkleProof, SparseMerkleTree, H256,
+    blake2b::Blake2bHasher, error::Error, traits::Value, CompiledMerkleProof, MerkleProof,
+    SparseMerkleTree, H256,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+    sync::Arc,
+    time::Instant,
 };
 
-pub struct SMTAccumulator<'a, DB, WO> {
-    smt: SparseMerkleTree<Blake2bHasher, CellStatus, DefaultStore<'a, DB, WO>>,
+/// Tags the commitment digests of this backend.
+pub const COMMITMENT_TAG: &[u8] = b"smt";
+
+/// The accumulator over a handle which dereferences to the RocksDB database, see `SMTAccumulator` and `SMTAccumulatorOwned`.
+/// The tree is stored in the `DefaultStore` of the database by default, or in another `TreeStore` over such handles, see
+/// `ShardedSMTAccumulator`.
+pub struct GenericSMTAccumulator<D, WO, S = DefaultStore<D, WO>> {
+    smt: SparseMerkleTree<Blake2bHasher, CellStatus, S>,
+    // When enabled, `add` rejects existing elements and `delete` rejects missing or dead elements.
+    strict: bool,
+    // The maximum number of leaves of a single tree update, larger updates are split, see `set_update_chunk_size`.
+    update_chunk_size: Option<usize>,
+    stage_times: StageTimes,
+    // See `CoalescedOps`.
+    coalesced_ops: u64,
+    // See `CacheLiveCells`, disabled by default.
+    live_cache: Option<LiveCache>,
+    // The handles are owned by the store.
+    handles: PhantomData<fn() -> (D, WO)>,
 }
 
-impl<'a, DB, WO> SMTAccumulator<'a, DB, WO>
+/// The accumulator borrowing a RocksDB database, transaction or snapshot.
+pub type SMTAccumulator<'a, DB, WO> = GenericSMTAccumulator<&'a DB, WO>;
+
+/// The accumulator sharing ownership of the RocksDB database, so that it can be held by long-lived services or moved across threads.
+pub type SMTAccumulatorOwned<DB, WO> = GenericSMTAccumulator<Arc<DB>, WO>;
+
+impl<D, WO> GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions> + Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
-    pub fn new(db: &'a DB) -> Result<Self, Error> {
-        let store = DefaultStore::new(db);
+    pub fn new(db: D) -> Result<Self, Error> {
+        Self::new_with_strict(db, false)
+    }
+
+    pub fn new_with_strict(db: D, strict: bool) -> Result<Self, Error> {
+        Self::with_store(DefaultStore::new(db), strict)
+    }
+}
+
+impl<D, WO, S: TreeStore> GenericSMTAccumulator<D, WO, S> {
+    pub(super) fn with_store(store: S, strict: bool) -> Result<Self, Error> {
         let smt = SparseMerkleTree::new_with_store(store)?;
-        Ok(SMTAccumulator { smt })
+        Ok(GenericSMTAccumulator {
+            smt,
+            strict,
+            update_chunk_size: None,
+            stage_times: StageTimes::default(),
+            coalesced_ops: 0,
+            live_cache: None,
+            handles: PhantomData,
+        })
+    }
+
+    /// Returns the commitment of the state seen by this accumulator, i.e. of the sequence it was opened with.
+    pub fn commitment(&self) -> AccumulatorCommitment {
+        AccumulatorCommitment {
+            root: *self.smt.root(),
+            sequence: self.smt.store().sequence(),
+        }
+    }
+
+    /// Splits the tree updates of `add`, `delete` and `apply_blocks` into chunks of at most `chunk_size` leaves, each
+    /// one applied on the root left by the previous one. It bounds the memory and the latency spikes of the updates of
+    /// large blocks, the resulting root is the same. `None` (the default) applies every update at once.
+    pub fn set_update_chunk_size(&mut self, chunk_size: Option<usize>) {
+        assert!(chunk_size != Some(0), "update chunk size must be positive");
+        self.update_chunk_size = chunk_size;
     }
 }
 
-impl<'a, DB, WO> AccumulatorWriter for SMTAccumulator<'a, DB, WO>
-where
-    DB: Iterate + Get<ReadOptions> + Delete<WO> + Put<WO>,
-{
+impl<D, WO, S: TreeStoreMut> AccumulatorWriter for GenericSMTAccumulator<D, WO, S> {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
 
     fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element exists already, caller should make sure the element is unique
-        let sequence = self.smt.store().sequence();
-        self.smt.update_all(
-            elements
-                .into_iter()
-                .map(|out_point| {
-                    let key = out_point.hash();
-                    (key.into(), CellStatus::new_live(sequence))
-                })
-                .collect(),
-        )?;
+        let start = Instant::now();
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.added_leaves(elements, &HashSet::new(), &mut leaves)?;
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+        self.update_leaves(leaves)?;
         Ok(())
     }
 
     fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element has been deleted already, caller should make sure the element is deleted only once
-        let sequence = self.smt.store().sequence();
-        let mut kvs = Vec::with_capacity(elements.len());
-        for (i, out_point) in elements.iter().enumerate() {
-            let key = out_point.hash();
-            let mut status = self.smt.get(&key.into())?;
-            if status == ZERO_CELL_STATUS {
+        let start = Instant::now();
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.deleted_leaves(elements, &mut leaves)?;
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+        self.update_leaves(leaves)?;
+        Ok(())
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        for (i, (out_point, status)) in elements.into_iter().enumerate() {
+            let key = self.leaf_key(&out_point);
+            if self.smt.get(&key)? == ZERO_CELL_STATUS {
                 return Err(AccumulatorError::ElementNotFound(i));
             }
-            status.mark_as_dead(sequence);
-            kvs.push((key.into(), status));
+            leaves.push((key, status));
         }
-
-        self.smt.update_all(kvs)?;
+        self.update_leaves(leaves)?;
         Ok(())
     }
 
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError> {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // the adds and deletes of a block are written in a single tree update, a cell consumed in the block it's
+            // added in is written once with its dead status
+            let transient = block.take_transient();
+            self.coalesced_ops += transient.len() as u64;
+            let start = Instant::now();
+            let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+            self.added_leaves(block.added, &transient, &mut leaves)?;
+            self.deleted_leaves(block.deleted, &mut leaves)?;
+            self.stage_times.record(Stage::Hashing, start.elapsed());
+            self.update_leaves(leaves)?;
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
     fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let start = Instant::now();
         let root = *self.smt.root();
         let sequence = self.smt.store().sequence();
         self.smt.store_mut().commit()?;
+        self.stage_times.record(Stage::TreeCommit, start.elapsed());
         Ok(AccumulatorCommitment { root, sequence })
     }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        Ok(self.smt.store_mut().skip_to(sequence)?)
+    }
 }
 
-impl<'a, DB, WO> SMTAccumulator<'a, DB, WO>
-where
-    DB: Iterate + Get<ReadOptions>,
-{
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Result<Self, Error> {
-        let store = DefaultStore::new_with_sequence(db, sequence);
-        let smt = SparseMerkleTree::new_with_store(store)?;
-        Ok(SMTAccumulator { smt })
+impl<D, WO, S: TreeStoreMut> GenericSMTAccumulator<D, WO, S> {
+    // writes the leaves to the tree, in chunks of `update_chunk_size` if set, the chunks keep the order of the leaves so
+    // the last value of a key still wins
+    fn update_leaves(&mut self, leaves: Vec<(H256, CellStatus)>) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        // the cache only records the leaves once they are written, a failed update leaves it as it was
+        let cached = self.live_cache.is_some().then(|| leaves.clone());
+        match self.update_chunk_size {
+            Some(chunk_size) if leaves.len() > chunk_size => {
+                let mut leaves = leaves.into_iter().peekable();
+                while leaves.peek().is_some() {
+                    self.smt
+                        .update_all(leaves.by_ref().take(chunk_size).collect())?;
+                }
+            }
+            _ => {
+                self.smt.update_all(leaves)?;
+            }
+        }
+        if let (Some(cache), Some(leaves)) = (self.live_cache.as_mut(), cached) {
+            for (key, status) in &leaves {
+                cache.write(key, status);
+            }
+        }
+        self.stage_times.record(Stage::TreeUpdate, start.elapsed());
+        Ok(())
+    }
+
+    // appends the leaves of the deleted elements to `leaves`, an element may be one of the pending leaves,
+    // its dead status is appended after it since `update_all` keeps the last value of a key
+    fn deleted_leaves(
+        &mut self,
+        elements: Vec<OutPoint>,
+        leaves: &mut Vec<(H256, CellStatus)>,
+    ) -> Result<(), AccumulatorError> {
+        let mut live_cache = self.live_cache.take();
+        let result = self.dead_leaves(elements, leaves, |key| {
+            live_cache.as_mut().and_then(|cache| cache.get(key))
+        });
+        self.live_cache = live_cache;
+        result
     }
 }
 
-impl<'a, DB, WO> AccumulatorReader for SMTAccumulator<'a, DB, WO>
+impl<D, WO, S: TreeStore> GenericSMTAccumulator<D, WO, S> {
+    // the leaf key of a cell under the key derivation of the database
+    fn leaf_key(&self, out_point: &OutPoint) -> H256 {
+        self.smt.store().key_derivation().leaf_key(out_point)
+    }
+
+    // appends the leaves of the added elements to `leaves`, which are pending to be written to the tree, the `transient`
+    // ones are consumed in the same sequence
+    fn added_leaves(
+        &self,
+        elements: Vec<OutPoint>,
+        transient: &HashSet<OutPoint>,
+        leaves: &mut Vec<(H256, CellStatus)>,
+    ) -> Result<(), AccumulatorError> {
+        // in non-strict mode we don't check if the element exists already, caller should make sure the element is unique
+        if self.strict {
+            let mut keys = HashSet::with_capacity(elements.len());
+            for (i, out_point) in elements.iter().enumerate() {
+                let key = self.leaf_key(out_point);
+                if self.smt.get(&key)? != ZERO_CELL_STATUS || !keys.insert(key) {
+                    return Err(AccumulatorError::AlreadyExists(i));
+                }
+            }
+        }
+
+        let sequence = self.smt.store().sequence();
+        leaves.extend(elements.iter().map(|out_point| {
+            let status = if transient.contains(out_point) {
+                CellStatus::new_dead(sequence, sequence)
+            } else {
+                CellStatus::new_live(sequence)
+            };
+            (self.leaf_key(out_point), status)
+        }));
+        Ok(())
+    }
+
+    // see `deleted_leaves`, `cached` returns the status of a live cell without reading the tree
+    fn dead_leaves<F>(
+        &self,
+        elements: Vec<OutPoint>,
+        leaves: &mut Vec<(H256, CellStatus)>,
+        mut cached: F,
+    ) -> Result<(), AccumulatorError>
+    where
+        F: FnMut(&H256) -> Option<CellStatus>,
+    {
+        // in non-strict mode we don't check if the element has been deleted already, caller should make sure the element is deleted only once
+        let sequence = self.smt.store().sequence();
+        let pending: HashMap<H256, CellStatus> = leaves.iter().cloned().collect();
+        let mut keys = HashSet::new();
+        for (i, out_point) in elements.iter().enumerate() {
+            let key = self.leaf_key(out_point);
+            let cached = match pending.get(&key) {
+                Some(status) => Some(status.clone()),
+                None => cached(&key),
+            };
+            let mut status = match cached {
+                Some(status) => status,
+                None => self.smt.get(&key)?,
+            };
+            if status == ZERO_CELL_STATUS
+                || (self.strict && (!status.is_live() || !keys.insert(key)))
+            {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            status.mark_as_dead(sequence);
+            leaves.push((key, status));
+        }
+        Ok(())
+    }
+}
+
+impl<D, WO> GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
 {
+    pub fn new_with_sequence(db: D, sequence: u64) -> Result<Self, Error> {
+        Self::with_store(DefaultStore::new_with_sequence(db, sequence), false)
+    }
+
+    /// Returns the commitment that `commit` would return after applying `block` on top of the writes of this
+    /// accumulator, without persisting anything: the tree is updated on a `SMTOverlayStore` which is dropped. It's the
+    /// post-state root a block producer puts in the header of the block it's still building. Strict mode errors of
+    /// the block are reported here.
+    pub fn preview_root(
+        &self,
+        mut block: BlockOps,
+    ) -> Result<AccumulatorCommitment, AccumulatorError> {
+        let transient = block.take_transient();
+        let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+        self.added_leaves(block.added, &transient, &mut leaves)?;
+        self.dead_leaves(block.deleted, &mut leaves, |_| None)?;
+        let mut smt = SparseMerkleTree::<Blake2bHasher, CellStatus, _>::new(
+            *self.smt.root(),
+            SMTOverlayStore::new(self.smt.store()),
+        );
+        smt.update_all(leaves)?;
+        Ok(AccumulatorCommitment {
+            root: *smt.root(),
+            sequence: self.smt.store().sequence(),
+        })
+    }
+
+    /// Generates a single multi-proof that all the given out_points are live at the given sequence,
+    /// which is the typical query of a wallet proving its whole set of cells to a light client.
+    pub fn wallet_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<WalletProof, AccumulatorError> {
+        Self::new_with_sequence(db, sequence)?.prove_live(out_points)
+    }
+
+    /// Generates a single multi-proof that none of the given out_points was added up to the given sequence. A consumed
+    /// cell keeps its dead status in the tree, so it's proved by the `proof` of its status instead.
+    pub fn absence_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<AbsenceProof, AccumulatorError> {
+        Self::new_with_sequence(db, sequence)?.prove_absence(out_points)
+    }
+}
+
+impl<D, WO, S: TreeStore> GenericSMTAccumulator<D, WO, S> {
+    /// Returns the status of an element as seen at the sequence of this accumulator, `None` if the element doesn't exist yet.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        let status = self.smt.get(&self.leaf_key(out_point))?;
+        Ok(Some(status).filter(|status| *status != ZERO_CELL_STATUS))
+    }
+
+    /// Generates the `wallet_proof` of the given out_points at the sequence of this accumulator.
+    pub fn prove_live(&self, out_points: Vec<OutPoint>) -> Result<WalletProof, AccumulatorError> {
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key = self.leaf_key(&out_point);
+            let status = self.smt.get(&key)?;
+            if !status.is_live() {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            leaves.push((key, out_point, status));
+        }
+
+        // unrelated keys are proved in one pass over the tree when they are sorted
+        leaves.sort_unstable_by_key(|(key, _, _)| *key);
+        leaves.dedup_by_key(|(key, _, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _, _)| *key).collect();
+        let proof = self.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(WalletProof {
+            sequence: self.smt.store().sequence(),
+            elements: leaves
+                .into_iter()
+                .map(|(_, out_point, status)| (out_point, status))
+                .collect(),
+            proof,
+            key_derivation: self.smt.store().key_derivation(),
+        })
+    }
+
+    /// Generates the `absence_proof` of the given out_points at the sequence of this accumulator.
+    pub fn prove_absence(
+        &self,
+        out_points: Vec<OutPoint>,
+    ) -> Result<AbsenceProof, AccumulatorError> {
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key = self.leaf_key(&out_point);
+            if self.smt.get(&key)? != ZERO_CELL_STATUS {
+                return Err(AccumulatorError::AlreadyExists(i));
+            }
+            leaves.push((key, out_point));
+        }
+
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        leaves.dedup_by_key(|(key, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _)| *key).collect();
+        let proof = self.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(AbsenceProof {
+            sequence: self.smt.store().sequence(),
+            out_points: leaves.into_iter().map(|(_, out_point)| out_point).collect(),
+            proof,
+            key_derivation: self.smt.store().key_derivation(),
+        })
+    }
+}
+
+impl<D, WO> LevelWrites for GenericSMTAccumulator<D, WO> {
+    fn level_writes(&self) -> Option<&LevelHistogram> {
+        Some(self.smt.store().branch_writes())
+    }
+}
+
+// the SMT writes through to the store, it has no batch to flush
+impl<D, WO, S> FlushWrites for GenericSMTAccumulator<D, WO, S> {}
+
+impl<D, WO, S> CoalescedOps for GenericSMTAccumulator<D, WO, S> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        Some(self.coalesced_ops)
+    }
+}
+
+// the leaves hold the statuses, there is no element index
+impl<D, WO, S> IndexedElements for GenericSMTAccumulator<D, WO, S> {}
+
+impl<D, WO, S> CacheLiveCells for GenericSMTAccumulator<D, WO, S> {
+    fn set_live_cache(&mut self, cache: LiveCache) {
+        self.live_cache = Some(cache);
+    }
+
+    fn live_cache(&self) -> Option<&LiveCache> {
+        self.live_cache.as_ref()
+    }
+
+    fn take_live_cache(&mut self) -> Option<LiveCache> {
+        self.live_cache.take()
+    }
+}
+
+impl<D, WO, S: TreeStoreMut> SweepCells for GenericSMTAccumulator<D, WO, S> {
+    // the leaves are scanned with their values, the predicate is given their key, which is the out_point hash with the
+    // hashed derivation
+    fn sweep<F>(&mut self, created_before: u64, mut predicate: F) -> Result<Swept, AccumulatorError>
+    where
+        F: FnMut(&[u8; 32], &CellStatus) -> bool,
+    {
+        if self.smt.store().key_derivation() != KeyDerivation::Hashed {
+            return Err(AccumulatorError::InternalError(
+                "sweeping needs the hashed key derivation".to_string(),
+            ));
+        }
+        let start = Instant::now();
+        let sequence = self.smt.store().sequence();
+        let mut leaves = Vec::new();
+        for (key, mut status) in self.smt.store().leaves() {
+            let hash: [u8; 32] = key.as_slice().try_into().expect("checked length");
+            if status.is_live() && status.created_by() < created_before && predicate(&hash, &status)
+            {
+                status.mark_as_dead(sequence);
+                leaves.push((key, status));
+            }
+        }
+        let scan = start.elapsed();
+        let start = Instant::now();
+        let cells = leaves.len() as u64;
+        self.update_leaves(leaves)?;
+        Ok(Swept {
+            cells,
+            scan,
+            update: start.elapsed(),
+        })
+    }
+}
+
+impl<D, WO, S> StageTiming for GenericSMTAccumulator<D, WO, S> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        Some(&self.stage_times)
+    }
+}
+
+impl<D, WO, S: TreeStoreMut> TraceKeys for GenericSMTAccumulator<D, WO, S> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.smt.store_mut().set_key_tracer(tracer);
+    }
+}
+
+impl<D, WO, S: TreeStore> AccumulatorReader for GenericSMTAccumulator<D, WO, S> {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
     type Proof = AccumulatorProof;
 
+    /// Proves the status of cells which exist at the sequence of this accumulator, live or consumed, since a dead cell's
+    /// status is still a leaf. Only a cell which was never added fails with `ElementNotFound`, see
+    /// `AccumulatorProof::verify_live` to check liveness.
     fn proof(
         &self,
         commitment: Self::Commitment,
@@ -102,23 +504,37 @@ where
 
         let mut keys = Vec::with_capacity(elements.len());
         for (i, out_point) in elements.iter().enumerate() {
-            let key = out_point.hash();
-            let status = self.smt.get(&key.into())?;
+            let key = self.leaf_key(out_point);
+            let status = self.smt.get(&key)?;
             if status == ZERO_CELL_STATUS {
                 return Err(AccumulatorError::ElementNotFound(i));
             }
-            keys.push(key.into());
+            keys.push(key);
         }
 
         let proof = self.smt.merkle_proof(keys)?;
-        Ok(AccumulatorProof { inner: proof })
+        Ok(AccumulatorProof {
+            inner: proof,
+            key_derivation: self.smt.store().key_derivation(),
+        })
     }
 }
 
 #[derive(Clone)]
 pub struct AccumulatorCommitment {
-    root: H256,
-    sequence: u64,
+    pub(super) root: H256,
+    pub(super) sequence: u64,
+}
+
+impl fmt::Debug for AccumulatorCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        debug_commitment(
+            f,
+            self.sequence,
+            self.root.as_slice(),
+            &self.commitment_digest(),
+        )
+    }
 }
 
 impl AccumulatorCommitment {
@@ -129,10 +545,60 @@ impl AccumulatorCommitment {
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    /// See `crate::commitment_digest`, tagged with `COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        commitment_digest(COMMITMENT_TAG, self.root.as_slice(), self.sequence)
+    }
+
+    /// Serializes the commitment as root || sequence as u64 LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.root.as_slice(), self.sequence.to_le_bytes().as_ref()].concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 40 {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let root: [u8; 32] = slice[0..32].try_into().expect("checked length");
+        let sequence = u64::from_le_bytes(slice[32..40].try_into().expect("checked length"));
+        Ok(AccumulatorCommitment {
+            root: H256::from(root),
+            sequence,
+        })
+    }
 }
 
 pub struct AccumulatorProof {
     inner: MerkleProof,
+    key_derivation: KeyDerivation,
+}
+
+impl AccumulatorProof {
+    /// Verifies the proof and that all the proved cells are live, i.e. not consumed at the commitment.
+    pub fn verify_live(
+        self,
+        commitment: AccumulatorCommitment,
+        elements: Vec<(OutPoint, CellStatus)>,
+    ) -> Result<bool, AccumulatorError> {
+        if !elements.iter().all(|(_, status)| status.is_live()) {
+            return Ok(false);
+        }
+        self.verify(commitment, elements)
+    }
+
+    // the leaves of the proved cells, keyed by the key derivation of the tree
+    fn leaves(&self, elements: &[(OutPoint, CellStatus)]) -> Vec<(H256, H256)> {
+        elements
+            .iter()
+            .map(|(out_point, cell_status)| {
+                (
+                    self.key_derivation.leaf_key(out_point),
+                    cell_status.to_h256(),
+                )
+            })
+            .collect()
+    }
 }
 
 impl Proof for AccumulatorProof {
@@ -145,14 +611,208 @@ impl Proof for AccumulatorProof {
         commitment: Self::Commitment,
         elements: Vec<Self::Item>,
     ) -> Result<bool, AccumulatorError> {
-        let leaves = elements
-            .into_iter()
-            .map(|(out_point, cell_status)| (out_point.hash().into(), cell_status.to_h256()))
+        let leaves = self.leaves(&elements);
+        let root = self.inner.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Computes the root of every proof from its borrowed elements and compares it to the root of the commitment, which
+    /// isn't copied per proof. The siblings shared by the proofs are still hashed once per proof, a single proof of all
+    /// the cells hashes them once.
+    fn verify_batch(
+        commitment: Self::Commitment,
+        proofs: Vec<(Vec<Self::Item>, Self)>,
+    ) -> Result<bool, AccumulatorError> {
+        let root = commitment.root.as_slice();
+        for (elements, proof) in proofs {
+            let leaves = proof.leaves(&elements);
+            if !ct_eq(
+                proof
+                    .inner
+                    .compute_root::<Blake2bHasher>(leaves)?
+                    .as_slice(),
+                root,
+            ) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A multi-proof of many live cells at one commitment, see `SMTAccumulator::wallet_proof`.
+pub struct WalletProof {
+    pub(super) sequence: u64,
+    pub(super) elements: Vec<(OutPoint, CellStatus)>,
+    pub(super) proof: CompiledMerkleProof,
+    pub(super) key_derivation: KeyDerivation,
+}
+
+impl WalletProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        &self.elements
+    }
+
+    pub fn key_derivation(&self) -> KeyDerivation {
+        self.key_derivation
+    }
+
+    pub fn verify(&self, commitment: &AccumulatorCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence
+            || !self.elements.iter().all(|(_, status)| status.is_live())
+        {
+            return Ok(false);
+        }
+        let leaves = self
+            .elements
+            .iter()
+            .map(|(out_point, status)| (self.key_derivation.leaf_key(out_point), status.to_h256()))
+            .collect();
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Serializes the proof as `version (1) | key derivation (1) | sequence (8) | count (4) | count * (tx_hash (32) |
+    /// index (4) | status (16)) | compiled proof`, the version is `PROOF_FORMAT_VERSION`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(14 + self.elements.len() * 52 + self.proof.0.len());
+        ret.push(PROOF_FORMAT_VERSION);
+        ret.push(self.key_derivation.to_byte());
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.elements.len() as u32).to_le_bytes());
+        for (out_point, status) in &self.elements {
+            ret.extend_from_slice(&out_point.to_bytes());
+            ret.extend_from_slice(&status.block_numbers);
+        }
+        ret.extend_from_slice(&self.proof.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        let (key_derivation, slice) = proof_header_from_bytes(slice)?;
+        let (sequence, elements, proof) = proof_from_bytes(slice, 52, |chunk| {
+            let out_point = OutPoint::from_bytes(&chunk[0..36]).expect("checked length");
+            let block_numbers = chunk[36..52].try_into().expect("checked length");
+            (out_point, CellStatus { block_numbers })
+        })?;
+        Ok(WalletProof {
+            sequence,
+            elements,
+            proof,
+            key_derivation,
+        })
+    }
+}
+
+/// The version of the serialized wallet and absence proofs of the smt backend, their first byte. The proofs of version 0 didn't
+/// start with a version and didn't carry the key derivation of the tree.
+pub const PROOF_FORMAT_VERSION: u8 = 1;
+
+// decodes `version (1) | key derivation (1)` and returns the rest of the serialized proof
+fn proof_header_from_bytes(slice: &[u8]) -> Result<(KeyDerivation, &[u8]), AccumulatorError> {
+    match slice {
+        [PROOF_FORMAT_VERSION, derivation, rest @ ..] => Ok((
+            KeyDerivation::from_byte(*derivation).ok_or(AccumulatorError::InvalidProof)?,
+            rest,
+        )),
+        _ => Err(AccumulatorError::InvalidProof),
+    }
+}
+
+/// A multi-proof that cells were never added at one commitment, see `SMTAccumulator::absence_proof`.
+pub struct AbsenceProof {
+    sequence: u64,
+    out_points: Vec<OutPoint>,
+    proof: CompiledMerkleProof,
+    key_derivation: KeyDerivation,
+}
+
+impl AbsenceProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn out_points(&self) -> &[OutPoint] {
+        &self.out_points
+    }
+
+    pub fn key_derivation(&self) -> KeyDerivation {
+        self.key_derivation
+    }
+
+    pub fn verify(&self, commitment: &AccumulatorCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence {
+            return Ok(false);
+        }
+        let leaves = self
+            .out_points
+            .iter()
+            .map(|out_point| (self.key_derivation.leaf_key(out_point), H256::zero()))
             .collect();
-        self.inner
-            .verify::<Blake2bHasher>(&commitment.root, leaves)
-            .map_err(Into::into)
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Serializes the proof as `version (1) | key derivation (1) | sequence (8) | count (4) | count * (tx_hash (32) |
+    /// index (4)) | compiled proof`, as a `WalletProof`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(14 + self.out_points.len() * 36 + self.proof.0.len());
+        ret.push(PROOF_FORMAT_VERSION);
+        ret.push(self.key_derivation.to_byte());
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.out_points.len() as u32).to_le_bytes());
+        for out_point in &self.out_points {
+            ret.extend_from_slice(&out_point.to_bytes());
+        }
+        ret.extend_from_slice(&self.proof.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        let (key_derivation, slice) = proof_header_from_bytes(slice)?;
+        let (sequence, out_points, proof) = proof_from_bytes(slice, OutPoint::LEN, |chunk| {
+            OutPoint::from_bytes(chunk).expect("checked length")
+        })?;
+        Ok(AbsenceProof {
+            sequence,
+            out_points,
+            proof,
+            key_derivation,
+        })
+    }
+}
+
+// decodes `sequence (8) | count (4) | count * entry | compiled proof`, the serialized proofs of the smt backends after
+// their header. `decode_entry` gets every entry as a slice of `entry_len` bytes. The count comes from the input, the
+// length of the entries is checked against overflow before slicing.
+pub(crate) fn proof_from_bytes<T>(
+    slice: &[u8],
+    entry_len: usize,
+    decode_entry: impl Fn(&[u8]) -> T,
+) -> Result<(u64, Vec<T>, CompiledMerkleProof), AccumulatorError> {
+    if slice.len() < 12 {
+        return Err(AccumulatorError::InvalidProof);
     }
+    let sequence = u64::from_le_bytes(slice[0..8].try_into().expect("checked length"));
+    let count = u32::from_le_bytes(slice[8..12].try_into().expect("checked length")) as usize;
+    let proof_offset = count
+        .checked_mul(entry_len)
+        .and_then(|len| len.checked_add(12))
+        .filter(|offset| *offset <= slice.len())
+        .ok_or(AccumulatorError::InvalidProof)?;
+    let entries = slice[12..proof_offset]
+        .chunks_exact(entry_len)
+        .map(decode_entry)
+        .collect();
+    Ok((
+        sequence,
+        entries,
+        CompiledMerkleProof(slice[proof_offset..].to_vec()),
+    ))
 }
 
 impl From<Error> for AccumulatorError {
@@ -0,0 +1,69 @@
+use super::store::{DefaultStore, LeafEncoding};
+use crate::{storage::traits::WriteStore, AccumulatorError};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+/// A leaf value given by its hash, the leaves of a tree built from pre-hashed leaves by `build_tree`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafHash(pub [u8; 32]);
+
+impl Value for LeafHash {
+    fn to_h256(&self) -> H256 {
+        self.0.into()
+    }
+
+    fn zero() -> Self {
+        LeafHash([0u8; 32])
+    }
+}
+
+impl From<Box<[u8]>> for LeafHash {
+    fn from(slice: Box<[u8]>) -> Self {
+        LeafHash(slice[..].try_into().expect("checked length"))
+    }
+}
+
+impl AsRef<[u8]> for LeafHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Builds the tree of an empty database from pre-hashed `(key, value hash)` leaves and commits it as sequence 0,
+/// returns the root. The leaves are written by a single `update_all`, which sorts them and computes every branch once
+/// from the leaves up, the bulk path of the tree. A zero value hash is an absent leaf. The value hashes are stored
+/// whole, so the database must have the full leaf encoding.
+pub fn build_tree<DB, WO>(
+    db: &DB,
+    leaves: Vec<([u8; 32], [u8; 32])>,
+) -> Result<[u8; 32], AccumulatorError>
+where
+    DB: WriteStore<WO>,
+{
+    if leaves.is_empty() {
+        return Err(AccumulatorError::InternalError(
+            "no leaves to build the tree from".to_string(),
+        ));
+    }
+    let store = DefaultStore::<_, WO>::new(db);
+    if store.sequence() != 0 {
+        return Err(AccumulatorError::InternalError(
+            "the database is not empty".to_string(),
+        ));
+    }
+    // the delta encoding stores cell statuses, not the 32 bytes value hashes
+    if store.leaf_encoding() != LeafEncoding::Full {
+        return Err(AccumulatorError::InternalError(
+            "the leaves of a built tree need the full leaf encoding".to_string(),
+        ));
+    }
+    let mut smt = SparseMerkleTree::<Blake2bHasher, LeafHash, _>::new_with_store(store)?;
+    smt.update_all(
+        leaves
+            .into_iter()
+            .map(|(key, hash)| (key.into(), LeafHash(hash)))
+            .collect(),
+    )?;
+    let root = smt.root().as_slice().try_into().expect("checked length");
+    smt.store_mut().commit()?;
+    Ok(root)
+}
@@ -0,0 +1,280 @@
+use super::{
+    accumulator::{AccumulatorCommitment, WalletProof},
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+    store::{is_node_key, stored_key_derivation, DefaultStore},
+    KeyDerivation, BRANCH_KEY_LEN, LEAF_KEY_LEN,
+};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    sequence_width::stored_sequence_width,
+    storage::traits::ReadStore,
+    AccumulatorError, CellStatus, OutPoint,
+};
+use memmap2::Mmap;
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, error::Error, traits::StoreReadOps, BranchKey, BranchNode,
+    SparseMerkleTree, H256,
+};
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The file names of an exported tree in its directory.
+pub const HEADER_FILE: &str = "header";
+pub const LEAVES_FILE: &str = "leaves";
+pub const NODES_FILE: &str = "nodes";
+
+const MAGIC: &[u8; 8] = b"DCAFLAT1";
+// magic, sequence, root, key derivation, leaf count and node count
+const HEADER_LEN: usize = 8 + 8 + 32 + 1 + 8 + 8;
+// the leaf key and the 16 bytes status
+const LEAF_RECORD_LEN: usize = LEAF_KEY_LEN + 16;
+// the longest encoding of a branch node, the shorter ones are zero padded so that the records have a fixed width
+const MAX_NODE_LEN: usize = 131;
+// the branch key, the length of the node encoding and the padded node
+const NODE_RECORD_LEN: usize = BRANCH_KEY_LEN + 1 + MAX_NODE_LEN;
+
+/// The counts and bytes written by `export_flat`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlatExport {
+    pub leaves: u64,
+    pub nodes: u64,
+    pub bytes: u64,
+}
+
+/// Exports the tree of a SMT accumulator database at `sequence` to flat files in `dir`: a header with the commitment,
+/// a table of the leaves and a table of the branch nodes, both of fixed width records sorted by key. The files are
+/// read by `FlatReader` without RocksDB, e.g. as a static snapshot served by a CDN.
+pub fn export_flat<DB, P>(db: &DB, sequence: u64, dir: P) -> Result<FlatExport, AccumulatorError>
+where
+    DB: ReadStore,
+    P: AsRef<Path>,
+{
+    let map_err = |e: std::io::Error| AccumulatorError::InternalError(e.to_string());
+    let store = DefaultStore::<_, ()>::new_with_sequence(db, sequence);
+    let smt = SparseMerkleTree::<Blake2bHasher, CellStatus, _>::new_with_store(store)?;
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    let key_derivation =
+        stored_key_derivation(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    fs::create_dir_all(dir.as_ref()).map_err(map_err)?;
+
+    // the versions written after the sequence are skipped, they are fsck's concern. The stored keys are iterated in
+    // byte order and the keys of a table have the same length, so both tables are written sorted
+    let mut report = FsckReport::default();
+    let mut export = FlatExport::default();
+    let mut leaves = BufWriter::new(File::create(dir.as_ref().join(LEAVES_FILE)).map_err(map_err)?);
+    for (key, value) in latest_versions(
+        db,
+        sequence,
+        width,
+        |key| key.len() == LEAF_KEY_LEN + width.encoded_len(),
+        |_key| true,
+        &mut report,
+    ) {
+        // a removed leaf is stored as an empty value
+        if value.is_empty() {
+            continue;
+        }
+        let key: [u8; 32] = key.try_into().expect("checked length");
+        // a delta encoded leaf is rebuilt by the store from its previous versions
+        let status = if value.len() == 16 {
+            CellStatus::from(value)
+        } else {
+            smt.get(&H256::from(key))?
+        };
+        leaves.write_all(&key).map_err(map_err)?;
+        leaves.write_all(status.as_ref()).map_err(map_err)?;
+        export.leaves += 1;
+    }
+    leaves.flush().map_err(map_err)?;
+
+    let mut nodes = BufWriter::new(File::create(dir.as_ref().join(NODES_FILE)).map_err(map_err)?);
+    for (key, value) in latest_versions(
+        db,
+        sequence,
+        width,
+        |key| key.len() == BRANCH_KEY_LEN + width.encoded_len() && !is_node_key(key),
+        |_key| true,
+        &mut report,
+    ) {
+        if value.is_empty() {
+            continue;
+        }
+        // a shared branch node is resolved by the store from its hash
+        let node_key: [u8; 32] = key[1..].try_into().expect("checked length");
+        let branch_key = BranchKey {
+            height: key[0],
+            node_key: node_key.into(),
+        };
+        let node = match StoreReadOps::<CellStatus>::get_branch(smt.store(), &branch_key)? {
+            Some(node) => branch_node_to_vec(&node),
+            None => continue,
+        };
+        let mut padded = [0u8; MAX_NODE_LEN];
+        padded[..node.len()].copy_from_slice(&node);
+        nodes.write_all(&key).map_err(map_err)?;
+        nodes.write_all(&[node.len() as u8]).map_err(map_err)?;
+        nodes.write_all(&padded).map_err(map_err)?;
+        export.nodes += 1;
+    }
+    nodes.flush().map_err(map_err)?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&sequence.to_le_bytes());
+    header.extend_from_slice(smt.root().as_slice());
+    header.push(key_derivation.to_byte());
+    header.extend_from_slice(&export.leaves.to_le_bytes());
+    header.extend_from_slice(&export.nodes.to_le_bytes());
+    fs::write(dir.as_ref().join(HEADER_FILE), header).map_err(map_err)?;
+    export.bytes = HEADER_LEN as u64
+        + export.leaves * LEAF_RECORD_LEN as u64
+        + export.nodes * NODE_RECORD_LEN as u64;
+    Ok(export)
+}
+
+/// The memory mapped tables of an exported tree, a SMT store which only reads.
+pub struct FlatStore {
+    leaves: Mmap,
+    nodes: Mmap,
+}
+
+// binary searches a table of fixed width records for the record starting with `key`
+fn search<'a>(table: &'a [u8], record_len: usize, key: &[u8]) -> Option<&'a [u8]> {
+    let (mut low, mut high) = (0, table.len() / record_len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let record = &table[mid * record_len..(mid + 1) * record_len];
+        match record[..key.len()].cmp(key) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Some(record),
+        }
+    }
+    None
+}
+
+impl FlatStore {
+    fn leaf(&self, key: &H256) -> Option<CellStatus> {
+        search(&self.leaves, LEAF_RECORD_LEN, key.as_slice())
+            .map(|record| CellStatus::from(Box::from(&record[LEAF_KEY_LEN..])))
+    }
+}
+
+impl StoreReadOps<CellStatus> for FlatStore {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        let key = branch_key_to_vec(branch_key);
+        let record = match search(&self.nodes, NODE_RECORD_LEN, &key) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let len = (record[BRANCH_KEY_LEN] as usize).min(MAX_NODE_LEN);
+        slice_to_branch_node(&record[BRANCH_KEY_LEN + 1..BRANCH_KEY_LEN + 1 + len])
+            .map(Some)
+            .map_err(|e| Error::Store(format!("branch node {:?}: {}", branch_key, e)))
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<CellStatus>, Error> {
+        Ok(self.leaf(leaf_key))
+    }
+}
+
+/// Serves the statuses and proofs of an exported tree from its memory mapped files, without RocksDB.
+pub struct FlatReader {
+    sequence: u64,
+    key_derivation: KeyDerivation,
+    smt: SparseMerkleTree<Blake2bHasher, CellStatus, FlatStore>,
+}
+
+impl FlatReader {
+    /// Maps the files of `export_flat` in `dir`, their lengths are checked against the counts of the header.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, AccumulatorError> {
+        let map_err = |e: std::io::Error| AccumulatorError::InternalError(e.to_string());
+        let header = fs::read(dir.as_ref().join(HEADER_FILE)).map_err(map_err)?;
+        if header.len() != HEADER_LEN || &header[..8] != MAGIC {
+            return Err(AccumulatorError::InternalError(
+                "invalid flat header".to_string(),
+            ));
+        }
+        let u64_at =
+            |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().expect("checked length"));
+        let sequence = u64_at(8);
+        let root: [u8; 32] = header[16..48].try_into().expect("checked length");
+        let key_derivation = KeyDerivation::from_byte(header[48]).ok_or_else(|| {
+            AccumulatorError::InternalError(format!("unknown key derivation {}", header[48]))
+        })?;
+        let map = |name: &str, records: u64, record_len: usize| -> Result<Mmap, AccumulatorError> {
+            let file = File::open(dir.as_ref().join(name)).map_err(map_err)?;
+            // SAFETY: the files are only read, a snapshot must not be modified while it's mapped
+            let mmap = unsafe { Mmap::map(&file) }.map_err(map_err)?;
+            if mmap.len() as u64 != records.saturating_mul(record_len as u64) {
+                return Err(AccumulatorError::InternalError(format!(
+                    "flat {} file of {} bytes, expected {} records",
+                    name,
+                    mmap.len(),
+                    records
+                )));
+            }
+            Ok(mmap)
+        };
+        let store = FlatStore {
+            leaves: map(LEAVES_FILE, u64_at(49), LEAF_RECORD_LEN)?,
+            nodes: map(NODES_FILE, u64_at(57), NODE_RECORD_LEN)?,
+        };
+        Ok(FlatReader {
+            sequence,
+            key_derivation,
+            smt: SparseMerkleTree::new(root.into(), store),
+        })
+    }
+
+    /// The commitment of the exported tree.
+    pub fn commitment(&self) -> AccumulatorCommitment {
+        AccumulatorCommitment {
+            root: *self.smt.root(),
+            sequence: self.sequence,
+        }
+    }
+
+    /// The status of a cell, `None` if it isn't a leaf of the exported tree.
+    pub fn cell_status(&self, out_point: &OutPoint) -> Option<CellStatus> {
+        self.smt
+            .store()
+            .leaf(&self.key_derivation.leaf_key(out_point))
+    }
+
+    /// Generates the multi-proof that the given out_points are live, like `SMTAccumulator::wallet_proof` at the
+    /// sequence of the export.
+    pub fn wallet_proof(&self, out_points: Vec<OutPoint>) -> Result<WalletProof, AccumulatorError> {
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key = self.key_derivation.leaf_key(&out_point);
+            match self.smt.store().leaf(&key) {
+                Some(status) if status.is_live() => leaves.push((key, out_point, status)),
+                _ => return Err(AccumulatorError::ElementNotFound(i)),
+            }
+        }
+
+        // unrelated keys are proved in one pass over the tree when they are sorted
+        leaves.sort_unstable_by_key(|(key, _, _)| *key);
+        leaves.dedup_by_key(|(key, _, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _, _)| *key).collect();
+        let proof = self.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(WalletProof {
+            sequence: self.sequence,
+            elements: leaves
+                .into_iter()
+                .map(|(_, out_point, status)| (out_point, status))
+                .collect(),
+            proof,
+            key_derivation: self.key_derivation,
+        })
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::OpenOptions;
+
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::Builder;
+
+use super::{export_flat, FlatReader, HEADER_FILE, NODES_FILE};
+use crate::{
+    smt::{
+        accumulator::SMTAccumulator, set_branch_storage, set_key_derivation, set_leaf_encoding,
+        BranchStorage, KeyDerivation, LeafEncoding,
+    },
+    storage::traits::ReadStore,
+    testing::FixtureBuilder,
+    AccumulatorError, AccumulatorWriter, OutPoint,
+};
+
+// checks the statuses and proofs of the flat reader against a RocksDB reader at the sequence of the export
+fn check_export<DB>(db: &DB, sequence: u64, out_points: &[OutPoint])
+where
+    DB: ReadStore,
+{
+    let dir = Builder::new().tempdir().unwrap();
+    let export = export_flat(db, sequence, dir.path()).unwrap();
+    assert!(export.leaves > 0 && export.nodes > 0);
+    let flat = FlatReader::open(dir.path()).unwrap();
+    let reader = SMTAccumulator::<_, ()>::new_with_sequence(db, sequence).unwrap();
+    let commitment = reader.commitment();
+    assert_eq!(commitment.to_bytes(), flat.commitment().to_bytes());
+
+    let mut live = Vec::new();
+    for out_point in out_points {
+        let status = reader.cell_status(out_point).unwrap();
+        assert_eq!(status, flat.cell_status(out_point));
+        match status {
+            Some(status) if status.is_live() => {
+                let proof = flat.wallet_proof(vec![out_point.clone()]).unwrap();
+                assert!(proof.verify(&commitment).unwrap());
+                live.push(out_point.clone());
+            }
+            _ => assert!(matches!(
+                flat.wallet_proof(vec![out_point.clone()]),
+                Err(AccumulatorError::ElementNotFound(0))
+            )),
+        }
+    }
+    assert!(!live.is_empty());
+    // the same proof as from RocksDB
+    let expected = SMTAccumulator::<_, ()>::wallet_proof(db, live.clone(), sequence).unwrap();
+    assert_eq!(
+        expected.to_bytes(),
+        flat.wallet_proof(live).unwrap().to_bytes()
+    );
+}
+
+#[test]
+fn test_export_flat() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let fixture = FixtureBuilder::new("smt")
+        .blocks(30)
+        .build(tmp_dir.path())
+        .unwrap();
+    let out_points: Vec<OutPoint> = fixture
+        .blocks()
+        .iter()
+        .flat_map(|block| block.added.clone())
+        .collect();
+    let snapshot = fixture.db().snapshot();
+    // the head and a historical sequence
+    check_export(&snapshot, 29, &out_points);
+    check_export(&snapshot, 10, &out_points);
+}
+
+#[test]
+fn test_export_flat_encodings() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    set_branch_storage::<_, ()>(&tx, BranchStorage::Shared).unwrap();
+    set_leaf_encoding::<_, ()>(&tx, LeafEncoding::Delta).unwrap();
+    set_key_derivation::<_, ()>(&tx, KeyDerivation::Prefixed).unwrap();
+    let out_points: Vec<_> = (0..20u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32 % 3,
+        })
+        .collect();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator.add(out_points[..10].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(out_points[..4].to_vec()).unwrap();
+    accumulator.add(out_points[10..].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    drop(accumulator);
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    check_export(&snapshot, 1, &out_points);
+}
+
+#[test]
+fn test_open_invalid_files() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let fixture = FixtureBuilder::new("smt")
+        .blocks(5)
+        .build(tmp_dir.path())
+        .unwrap();
+    let dir = Builder::new().tempdir().unwrap();
+    export_flat(&fixture.db().snapshot(), 4, dir.path()).unwrap();
+    assert!(FlatReader::open(dir.path()).is_ok());
+
+    // a truncated table doesn't match the counts of the header
+    let nodes = OpenOptions::new()
+        .write(true)
+        .open(dir.path().join(NODES_FILE))
+        .unwrap();
+    let len = nodes.metadata().unwrap().len();
+    nodes.set_len(len - 1).unwrap();
+    assert!(FlatReader::open(dir.path()).is_err());
+    nodes.set_len(len).unwrap();
+    assert!(FlatReader::open(dir.path()).is_ok());
+
+    let mut header = std::fs::read(dir.path().join(HEADER_FILE)).unwrap();
+    header[0] ^= 1;
+    std::fs::write(dir.path().join(HEADER_FILE), header).unwrap();
+    assert!(FlatReader::open(dir.path()).is_err());
+}
@@ -0,0 +1,119 @@
+use super::{
+    is_versioned_key,
+    store::{is_node_key, DefaultStore},
+    BRANCH_KEY_LEN, LEAF_KEY_LEN,
+};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    sequence_width::stored_sequence_width,
+    storage::traits::ReadStore,
+    AccumulatorError, CellStatus,
+};
+use rocksdb::IteratorMode;
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    default_store::DefaultStore as MemoryStore,
+    traits::{StoreReadOps, Value},
+    SparseMerkleTree, H256,
+};
+use std::collections::HashMap;
+
+/// Checks the internal consistency of a SMT accumulator database at its last committed sequence:
+/// the root rebuilt from the live leaves must match the stored root, and every leaf must be provable
+/// through the stored branches, which fails if a branch on its path is missing or doesn't rehash to its parent.
+/// With the shared branch storage, the refcount of every node must also match the versions referring to it.
+pub fn fsck<DB>(db: &DB) -> Result<FsckReport, AccumulatorError>
+where
+    DB: ReadStore,
+{
+    let mut report = FsckReport::default();
+    let sequence = match DefaultStore::<_, ()>::new(db).sequence().checked_sub(1) {
+        Some(sequence) => sequence,
+        None => return Ok(report),
+    };
+    report.sequence = Some(sequence);
+
+    let store = DefaultStore::<_, ()>::new_with_sequence(db, sequence);
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    let mut leaves: Vec<(H256, CellStatus)> = Vec::new();
+    for (key, value) in latest_versions(
+        db,
+        sequence,
+        width,
+        |key| is_versioned_key(key, width),
+        |key| key.len() == LEAF_KEY_LEN + width.encoded_len(),
+        &mut report,
+    ) {
+        // a removed leaf is stored as an empty value
+        if value.is_empty() {
+            continue;
+        }
+        let key: [u8; 32] = key.try_into().expect("checked length");
+        let key: H256 = key.into();
+        // a delta encoded leaf is rebuilt by the store from its previous versions
+        let status = if value.len() == 16 {
+            CellStatus::from(value)
+        } else {
+            StoreReadOps::<CellStatus>::get_leaf(&store, &key)?.expect("non-empty leaf")
+        };
+        leaves.push((key, status));
+    }
+    report.leaves = leaves.len() as u64;
+    check_refcounts(db, width.encoded_len(), &mut report);
+
+    let smt = SparseMerkleTree::<Blake2bHasher, CellStatus, _>::new_with_store(store)?;
+    let mut rebuilt =
+        SparseMerkleTree::<Blake2bHasher, CellStatus, MemoryStore<CellStatus>>::default();
+    rebuilt.update_all(leaves.clone())?;
+    if rebuilt.root() != smt.root() {
+        report.errors.push(format!(
+            "root mismatch, stored: {:?}, rebuilt from leaves: {:?}",
+            smt.root(),
+            rebuilt.root()
+        ));
+    }
+
+    for (key, status) in leaves {
+        let verified = smt.merkle_proof(vec![key]).and_then(|proof| {
+            proof.verify::<Blake2bHasher>(smt.root(), vec![(key, status.to_h256())])
+        });
+        match verified {
+            Ok(true) => {}
+            Ok(false) => report
+                .errors
+                .push(format!("leaf {:?} doesn't hash to the stored root", key)),
+            Err(e) => report.errors.push(format!("leaf {:?}: {:?}", key, e)),
+        }
+    }
+    Ok(report)
+}
+
+// counts the branch versions referring to every shared node, a reference is a 32 bytes branch value, an inline node
+// is at least 65 bytes
+fn check_refcounts<DB: ReadStore>(db: &DB, width_len: usize, report: &mut FsckReport) {
+    let mut references: HashMap<Box<[u8]>, u32> = HashMap::new();
+    let mut refcounts = Vec::new();
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        if is_node_key(&key) {
+            let refcount = u32::from_le_bytes(value[0..4].try_into().expect("checked length"));
+            refcounts.push((key[key.len() - 32..].into(), refcount));
+        } else if key.len() == BRANCH_KEY_LEN + width_len && value.len() == 32 {
+            *references.entry(value).or_default() += 1;
+        }
+    }
+    for (hash, refcount) in refcounts {
+        let referenced = references.remove(&hash).unwrap_or(0);
+        if referenced != refcount {
+            report.errors.push(format!(
+                "shared branch node {:?} has refcount {} but {} references",
+                hash, refcount, referenced
+            ));
+        }
+    }
+    for hash in references.keys() {
+        report
+            .errors
+            .push(format!("missing shared branch node {:?}", hash));
+    }
+}
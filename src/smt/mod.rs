@@ -1,11 +1,46 @@
-use crate::{new_blake2b, CellStatus};
+use crate::{
+    new_blake2b, sequence_width::SequenceWidth, storage::traits::ReadStore, AccumulatorError,
+    CellStatus, OutPoint,
+};
 use sparse_merkle_tree::{traits::Value, H256};
 
 pub mod accumulator;
-mod serde;
-mod store;
+pub mod bulk;
+pub mod flat;
+pub mod fsck;
+pub mod script_index;
+pub(crate) mod serde;
+pub mod sharded;
+pub(crate) mod store;
+pub mod sync;
 #[cfg(test)]
 mod tests;
+pub mod tiered;
+
+pub use store::{
+    set_branch_storage, set_key_derivation, set_leaf_encoding, BranchStorage, KeyDerivation,
+    LeafEncoding,
+};
+
+// leaf keys are the out_point hash, branch keys are the 1 byte height followed by the node key, both are versioned
+pub(crate) const LEAF_KEY_LEN: usize = 32;
+pub(crate) const BRANCH_KEY_LEN: usize = 33;
+
+/// Whether a stored key is a versioned key of this backend, i.e. a leaf or branch key suffixed with a sequence of `width`.
+pub fn is_versioned_key(key: &[u8], width: SequenceWidth) -> bool {
+    key.len() == LEAF_KEY_LEN + width.encoded_len()
+        || key.len() == BRANCH_KEY_LEN + width.encoded_len()
+}
+
+/// The leaf key of an out_point in a database, by the key derivation of the database.
+pub fn leaf_key<DB: ReadStore + ?Sized>(
+    db: &DB,
+    out_point: &OutPoint,
+) -> Result<H256, AccumulatorError> {
+    let derivation = store::stored_key_derivation(db)
+        .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    Ok(derivation.leaf_key(out_point))
+}
 
 pub const ZERO_CELL_STATUS: CellStatus = CellStatus {
     block_numbers: [0u8; 16],
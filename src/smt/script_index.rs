@@ -0,0 +1,106 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ops::Deref,
+};
+
+use super::store::DefaultStore;
+use crate::{new_blake2b, storage::traits::WriteStore, AccumulatorError, BlockOps, OutPoint};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+/// The live out_points locked by a script, as the XOR of their hashes, so adding and consuming a cell are the same
+/// update and the value doesn't depend on the order of the cells. It's a cheap rolling hash to measure the cost of the
+/// index, not a collision resistant set commitment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScriptCells(pub [u8; 32]);
+
+impl ScriptCells {
+    fn toggle(&mut self, out_point: &OutPoint) {
+        for (byte, hash_byte) in self.0.iter_mut().zip(out_point.hash()) {
+            *byte ^= hash_byte;
+        }
+    }
+}
+
+impl Value for ScriptCells {
+    fn to_h256(&self) -> H256 {
+        if self.0 == [0u8; 32] {
+            return H256::zero();
+        }
+        let mut buf = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(&self.0);
+        hasher.finalize(&mut buf);
+        buf.into()
+    }
+
+    fn zero() -> Self {
+        ScriptCells::default()
+    }
+}
+
+impl From<Box<[u8]>> for ScriptCells {
+    fn from(vec: Box<[u8]>) -> Self {
+        ScriptCells(vec[..].try_into().expect("checked length"))
+    }
+}
+
+impl AsRef<[u8]> for ScriptCells {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A secondary accumulator committing to `lock_script_hash -> live out_points`, a SMT keyed by the lock script hash
+/// whose leaves are the `ScriptCells` of the script. Its keys collide with the keys of the cell accumulators, it's stored
+/// in a database of its own.
+pub struct ScriptIndex<D, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, ScriptCells, DefaultStore<D, WO>>,
+}
+
+impl<D, WO> ScriptIndex<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    pub fn new(db: D) -> Result<Self, AccumulatorError> {
+        let smt = SparseMerkleTree::new_with_store(DefaultStore::new(db))?;
+        Ok(ScriptIndex { smt })
+    }
+
+    /// Applies the cells added and consumed by a block and commits it, `lock_script_hash` returns the lock script hash
+    /// of a cell. Returns the new root. The block must be valid, the out_points are toggled without checking their status,
+    /// so consuming a dead cell toggles it back into the index.
+    pub fn apply_block<F>(
+        &mut self,
+        block: &BlockOps,
+        lock_script_hash: F,
+    ) -> Result<H256, AccumulatorError>
+    where
+        F: Fn(&OutPoint) -> [u8; 32],
+    {
+        let mut scripts: HashMap<H256, ScriptCells> = HashMap::new();
+        for out_point in block.added.iter().chain(&block.deleted) {
+            let key = H256::from(lock_script_hash(out_point));
+            let cells = match scripts.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.smt.get(&key)?),
+            };
+            cells.toggle(out_point);
+        }
+        self.smt.update_all(scripts.into_iter().collect())?;
+        self.smt.store_mut().commit()?;
+        Ok(*self.smt.root())
+    }
+
+    pub fn root(&self) -> &H256 {
+        self.smt.root()
+    }
+
+    /// The live out_points of a script as of the last committed block.
+    pub fn script_cells(
+        &self,
+        lock_script_hash: [u8; 32],
+    ) -> Result<ScriptCells, AccumulatorError> {
+        Ok(self.smt.get(&lock_script_hash.into())?)
+    }
+}
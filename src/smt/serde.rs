@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, fmt};
 
 use sparse_merkle_tree::{merge::MergeValue, BranchKey, BranchNode};
 
@@ -10,12 +10,78 @@ pub fn branch_key_to_vec(key: &BranchKey) -> Vec<u8> {
     ret
 }
 
+/// The format version of the serialized branch nodes, stored in the high 4 bits of the variant tag byte,
+/// so that a future format can be told apart from the existing data.
+pub const FORMAT_VERSION: u8 = 0;
+
+/// The errors of decoding a stored branch node, e.g. a corrupted database entry.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SerdeError {
+    /// The format version of the tag byte isn't `FORMAT_VERSION`.
+    UnsupportedVersion(u8),
+    /// The variant of the tag byte isn't one of a branch node.
+    UnknownVariant(u8),
+    /// The slice is empty, or its length doesn't match the encoding of its variant.
+    InvalidLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerdeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported branch node format version: {}", version)
+            }
+            SerdeError::UnknownVariant(variant) => {
+                write!(f, "unknown branch node variant: {}", variant)
+            }
+            SerdeError::InvalidLength { expected, actual } => write!(
+                f,
+                "invalid branch node length: {}, expected {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+/// Checks the tag byte at `offset` and the length of an encoded branch node, the encoding of its variant follows the
+/// tag byte.
+pub(crate) fn check_encoding(slice: &[u8], offset: usize) -> Result<u8, SerdeError> {
+    let tag = *slice.get(offset).ok_or(SerdeError::InvalidLength {
+        expected: offset + 1,
+        actual: slice.len(),
+    })?;
+    let version = tag >> 4;
+    if version != FORMAT_VERSION {
+        return Err(SerdeError::UnsupportedVersion(version));
+    }
+    let variant = tag & 0x0f;
+    let len = match variant {
+        0 => 65,
+        1 | 2 | 4 | 5 => 98,
+        3 | 6 | 7 | 8 => 131,
+        _ => return Err(SerdeError::UnknownVariant(variant)),
+    };
+    if slice.len() != offset + len {
+        return Err(SerdeError::InvalidLength {
+            expected: offset + len,
+            actual: slice.len(),
+        });
+    }
+    Ok(variant)
+}
+
+fn tag(variant: u8) -> u8 {
+    FORMAT_VERSION << 4 | variant
+}
+
 /// Serialize a `BranchNode` into a `Vec<u8>` for use as a key in the key-value store.
 pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
     match (&node.left, &node.right) {
         (MergeValue::Value(left), MergeValue::Value(right)) => {
-            let mut ret = Vec::with_capacity(33);
-            ret.extend_from_slice(&[0]);
+            let mut ret = Vec::with_capacity(65);
+            ret.push(tag(0));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(right.as_slice());
             ret
@@ -29,7 +95,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
         ) => {
             let mut ret = Vec::with_capacity(98);
-            ret.extend_from_slice(&[1]);
+            ret.push(tag(1));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
@@ -45,7 +111,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             MergeValue::Value(right),
         ) => {
             let mut ret = Vec::with_capacity(98);
-            ret.extend_from_slice(&[2]);
+            ret.push(tag(2));
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
@@ -65,7 +131,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
         ) => {
             let mut ret = Vec::with_capacity(131);
-            ret.extend_from_slice(&[3]);
+            ret.push(tag(3));
             ret.extend_from_slice(l_base_node.as_slice());
             ret.extend_from_slice(l_zero_bits.as_slice());
             ret.extend_from_slice(&[*l_zero_count]);
@@ -76,7 +142,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         }
         (MergeValue::Value(left), MergeValue::ShortCut { key, value, height }) => {
             let mut ret = Vec::with_capacity(98);
-            ret.extend_from_slice(&[4]);
+            ret.push(tag(4));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
@@ -85,7 +151,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         }
         (MergeValue::ShortCut { key, value, height }, MergeValue::Value(right)) => {
             let mut ret = Vec::with_capacity(98);
-            ret.extend_from_slice(&[5]);
+            ret.push(tag(5));
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
@@ -105,7 +171,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
         ) => {
             let mut ret = Vec::with_capacity(131);
-            ret.extend_from_slice(&[6]);
+            ret.push(tag(6));
             ret.extend_from_slice(l_key.as_slice());
             ret.extend_from_slice(l_value.as_slice());
             ret.extend_from_slice(&[*l_height]);
@@ -123,7 +189,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             MergeValue::ShortCut { key, value, height },
         ) => {
             let mut ret = Vec::with_capacity(131);
-            ret.extend_from_slice(&[7]);
+            ret.push(tag(7));
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
@@ -141,7 +207,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
         ) => {
             let mut ret = Vec::with_capacity(131);
-            ret.extend_from_slice(&[8]);
+            ret.push(tag(8));
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
@@ -153,9 +219,10 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
     }
 }
 
-/// Deserialize a `BranchNode` from a slice that was previously serialized with `branch_node_to_vec`.
-pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
-    match slice[0] {
+/// Deserialize a `BranchNode` from a slice that was previously serialized with `branch_node_to_vec`, an error if it
+/// isn't a valid encoding.
+pub fn slice_to_branch_node(slice: &[u8]) -> Result<BranchNode, SerdeError> {
+    let node = match check_encoding(slice, 0)? {
         0 => {
             let left: [u8; 32] = slice[1..33].try_into().expect("checked slice");
             let right: [u8; 32] = slice[33..65].try_into().expect("checked slice");
@@ -300,8 +367,7 @@ pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
                 },
             }
         }
-        _ => {
-            unreachable!()
-        }
-    }
+        _ => unreachable!("checked variant"),
+    };
+    Ok(node)
 }
@@ -0,0 +1,212 @@
+use std::ops::Deref;
+
+use super::{
+    accumulator::{GenericSMTAccumulator, WalletProof},
+    store::{DefaultStore, KeyDerivation, TreeStore, TreeStoreMut},
+};
+use crate::{
+    metrics::{KeyTracer, LevelWrites},
+    new_blake2b,
+    storage::traits::{ReadStore, WriteStore},
+    AccumulatorError, CellStatus, OutPoint,
+};
+use rocksdb::prelude::{Delete, Put};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, H256,
+};
+
+/// A SMT store sharded across several `DefaultStore`s, e.g. over RocksDB databases on separate devices, to spread the
+/// I/O of the tree. A node is stored in the shard picked by a hash of its whole store key, the height and node key of a
+/// branch node or the key of a leaf: the node keys of the branch nodes are mostly zeros, a shard picked from their bytes
+/// would hold nearly every branch write. Every shard keeps its own sequence and all of them are committed together, the
+/// writes across shards are not atomic.
+pub struct ShardedStore<D, WO> {
+    shards: Vec<DefaultStore<D, WO>>,
+}
+
+impl<D, WO> ShardedStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    pub fn new(dbs: Vec<D>) -> Self {
+        assert!(!dbs.is_empty(), "a sharded store needs at least one shard");
+        let shards: Vec<DefaultStore<D, WO>> = dbs.into_iter().map(DefaultStore::new).collect();
+        assert!(
+            shards
+                .iter()
+                .all(|shard| shard.sequence() == shards[0].sequence()),
+            "the shards must be at the same sequence"
+        );
+        ShardedStore { shards }
+    }
+
+    /// Opens the shards at a committed sequence for historical reads, see `DefaultStore::new_with_sequence`.
+    pub fn new_with_sequence(dbs: Vec<D>, sequence: u64) -> Self {
+        assert!(!dbs.is_empty(), "a sharded store needs at least one shard");
+        let shards = dbs
+            .into_iter()
+            .map(|db| DefaultStore::new_with_sequence(db, sequence))
+            .collect();
+        ShardedStore { shards }
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.shards[0].sequence()
+    }
+}
+
+impl<D, WO> ShardedStore<D, WO> {
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // the shard of a leaf, or of a branch node with its height
+    fn shard(&self, height: Option<u8>, key: &H256) -> usize {
+        let mut hasher = new_blake2b();
+        if let Some(height) = height {
+            hasher.update(&[height]);
+        }
+        hasher.update(key.as_slice());
+        let mut hash = [0u8; 32];
+        hasher.finalize(&mut hash);
+        let hash = u64::from_le_bytes(hash[..8].try_into().expect("checked length"));
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    fn branch_shard(&self, branch_key: &BranchKey) -> usize {
+        self.shard(Some(branch_key.height), &branch_key.node_key)
+    }
+}
+
+impl<D, WO> ShardedStore<D, WO>
+where
+    D: Deref,
+    D::Target: Delete<WO> + Put<WO>,
+{
+    pub fn commit(&mut self) -> Result<(), Error> {
+        for shard in self.shards.iter_mut() {
+            shard.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl<V, D, WO> StoreReadOps<V> for ShardedStore<D, WO>
+where
+    V: Value + From<Box<[u8]>>,
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        StoreReadOps::<V>::get_branch(&self.shards[self.branch_shard(branch_key)], branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.shards[self.shard(None, leaf_key)].get_leaf(leaf_key)
+    }
+}
+
+impl<V, D, WO> StoreWriteOps<V> for ShardedStore<D, WO>
+where
+    V: Value + AsRef<[u8]> + From<Box<[u8]>>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let shard = self.branch_shard(&node_key);
+        StoreWriteOps::<V>::insert_branch(&mut self.shards[shard], node_key, branch)
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        let shard = self.shard(None, &leaf_key);
+        self.shards[shard].insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        let shard = self.branch_shard(node_key);
+        StoreWriteOps::<V>::remove_branch(&mut self.shards[shard], node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        let shard = self.shard(None, leaf_key);
+        StoreWriteOps::<V>::remove_leaf(&mut self.shards[shard], leaf_key)
+    }
+}
+
+impl<D, WO> TreeStore for ShardedStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn sequence(&self) -> u64 {
+        self.shards[0].sequence()
+    }
+
+    // every shard stores the derivation of the first one
+    fn key_derivation(&self) -> KeyDerivation {
+        self.shards[0].key_derivation()
+    }
+
+    fn leaves(&self) -> Vec<(H256, CellStatus)> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.leaves())
+            .collect()
+    }
+}
+
+impl<D, WO> TreeStoreMut for ShardedStore<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    fn commit(&mut self) -> Result<(), Error> {
+        ShardedStore::commit(self)
+    }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), Error> {
+        for shard in self.shards.iter_mut() {
+            shard.skip_to(sequence)?;
+        }
+        Ok(())
+    }
+
+    fn set_key_tracer(&mut self, key_tracer: KeyTracer) {
+        for shard in self.shards.iter_mut() {
+            shard.set_key_tracer(key_tracer.clone());
+        }
+    }
+}
+
+/// The SMT accumulator over a `ShardedStore`, it has the same tree and commitments as `SMTAccumulator` over a single
+/// database.
+pub type ShardedSMTAccumulator<D, WO> = GenericSMTAccumulator<D, WO, ShardedStore<D, WO>>;
+
+impl<D, WO> GenericSMTAccumulator<D, WO, ShardedStore<D, WO>>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    pub fn new_with_strict(dbs: Vec<D>, strict: bool) -> Result<Self, Error> {
+        Self::with_store(ShardedStore::new(dbs), strict)
+    }
+
+    pub fn new_with_sequence(dbs: Vec<D>, sequence: u64) -> Result<Self, Error> {
+        Self::with_store(ShardedStore::new_with_sequence(dbs, sequence), false)
+    }
+
+    /// See `SMTAccumulator::wallet_proof`.
+    pub fn wallet_proof(
+        dbs: Vec<D>,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<WalletProof, AccumulatorError> {
+        Self::new_with_sequence(dbs, sequence)?.prove_live(out_points)
+    }
+}
+
+// the branch writes are counted by height in each shard, they aren't merged
+impl<D, WO> LevelWrites for ShardedSMTAccumulator<D, WO> {}
@@ -1,31 +1,224 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Deref};
 
-use rocksdb::{prelude::*, Direction, IteratorMode};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    keycodec::{hash_key, parse_hash_key},
+    metrics::{KeyTracer, LevelHistogram},
+    new_blake2b,
+    sequence_width::{skipped_record, stored_sequence_width, SequenceWidth},
+    storage::traits::{ReadStore, WriteStore},
+    CellStatus, OutPoint,
+};
+use rocksdb::{
+    prelude::{Delete, Put},
+    Direction, IteratorMode,
+};
 use sparse_merkle_tree::{
     error::Error,
     traits::{StoreReadOps, StoreWriteOps, Value},
     BranchKey, BranchNode, H256,
 };
 
-use super::serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node};
+use super::{
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+    LEAF_KEY_LEN,
+};
 
 const SEQUENCE_KEY: &[u8] = b"SEQUENCE";
+const LEAF_ENCODING_KEY: &[u8] = b"ENCODING";
+const KEY_DERIVATION_KEY: &[u8] = b"KEYDERIV";
+const BRANCH_STORAGE_KEY: &[u8] = b"BRANCHES";
+
+// a shared branch node is stored once under this prefix and its hash, as a refcount (4 bytes LE) followed by the node,
+// the 42 bytes keys are told apart from the versioned leaf and branch keys by their length
+const NODE_PREFIX: &[u8] = b"BRANCHNODE";
+const REFCOUNT_LEN: usize = 4;
+
+// the stored records of a leaf in the delta encoding, besides the full 16 bytes `CellStatus`
+const CREATED_RECORD_LEN: usize = 8;
+const SPEND_RECORD_LEN: usize = 4;
+
+/// How the `CellStatus` leaf values are stored, chosen once per database by `set_leaf_encoding`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeafEncoding {
+    // The 16 bytes `CellStatus`.
+    Full,
+    // The created-by block number (8 bytes) when a cell is added, then a spend record of the consumed-by block number
+    // relative to it (4 bytes) when it's consumed, the status is rebuilt from both versions on read.
+    // A cell consumed in the block which creates it, or too long after it, is stored in full.
+    Delta,
+}
+
+/// Sets the leaf encoding of a SMT database, it must be set before the first commit and can't be changed afterwards.
+pub fn set_leaf_encoding<DB, WO>(db: &DB, encoding: LeafEncoding) -> Result<(), Error>
+where
+    DB: ReadStore + Put<WO>,
+{
+    let map_err = |e: rocksdb::Error| Error::Store(e.to_string());
+    if db.get(SEQUENCE_KEY).map_err(map_err)?.is_some()
+        && stored_leaf_encoding(db).map_err(map_err)? != encoding
+    {
+        return Err(Error::Store(
+            "the leaf encoding can't be changed after the first commit".to_string(),
+        ));
+    }
+    let value = match encoding {
+        LeafEncoding::Full => [0],
+        LeafEncoding::Delta => [1],
+    };
+    db.put(LEAF_ENCODING_KEY, value).map_err(map_err)
+}
+
+/// How the leaf key of a cell is derived from its out_point, chosen once per database by `set_key_derivation`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyDerivation {
+    // blake2b(out_point), the keys are uniformly spread so the paths of unrelated cells split near the root and the
+    // tree stays balanced whatever the transactions are.
+    #[default]
+    Hashed,
+    // index LE (4) || tx_hash[0..28]. The tree branches on the highest bits first, so the cells of a transaction share
+    // their path down to height 32 and their branch nodes are written and read together. The trade-off is that the
+    // keys are chosen by the transaction creator: tx hashes can be ground to share long prefixes with other cells,
+    // which deepens their paths and grows their proofs, and the truncated tx hash leaves a 112 bits collision margin
+    // instead of 128 bits.
+    Prefixed,
+}
+
+impl KeyDerivation {
+    pub fn leaf_key(&self, out_point: &OutPoint) -> H256 {
+        match self {
+            KeyDerivation::Hashed => out_point.hash().into(),
+            KeyDerivation::Prefixed => {
+                let mut key = [0u8; 32];
+                key[0..4].copy_from_slice(&out_point.index.to_le_bytes());
+                key[4..32].copy_from_slice(&out_point.tx_hash[0..28]);
+                key.into()
+            }
+        }
+    }
+
+    /// The byte of the derivation in the database, the header of a flat export and the serialized proofs.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            KeyDerivation::Hashed => 0,
+            KeyDerivation::Prefixed => 1,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(KeyDerivation::Hashed),
+            1 => Some(KeyDerivation::Prefixed),
+            _ => None,
+        }
+    }
+}
+
+/// Sets the key derivation of a SMT database, it must be set before the first commit and can't be changed afterwards.
+pub fn set_key_derivation<DB, WO>(db: &DB, derivation: KeyDerivation) -> Result<(), Error>
+where
+    DB: ReadStore + Put<WO>,
+{
+    let map_err = |e: rocksdb::Error| Error::Store(e.to_string());
+    if db.get(SEQUENCE_KEY).map_err(map_err)?.is_some()
+        && stored_key_derivation(db).map_err(map_err)? != derivation
+    {
+        return Err(Error::Store(
+            "the key derivation can't be changed after the first commit".to_string(),
+        ));
+    }
+    db.put(KEY_DERIVATION_KEY, [derivation.to_byte()])
+        .map_err(map_err)
+}
+
+pub(super) fn stored_key_derivation<DB: ReadStore + ?Sized>(
+    db: &DB,
+) -> Result<KeyDerivation, rocksdb::Error> {
+    Ok(match db.get(KEY_DERIVATION_KEY)?.as_deref() {
+        Some([byte]) => KeyDerivation::from_byte(*byte).unwrap_or_default(),
+        _ => KeyDerivation::Hashed,
+    })
+}
+
+/// How the branch nodes are stored, chosen once per database by `set_branch_storage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BranchStorage {
+    // Every version of a branch key holds a copy of its node.
+    #[default]
+    Inline,
+    // Every version of a branch key holds the 32 bytes hash of its node, the nodes are stored once by hash with the
+    // count of the versions referring to them, a node recurring across versions and keys is written once. The count is
+    // read and written back, so the store must write to a transaction, which commits it with the versions referring to
+    // the node or not at all.
+    Shared,
+}
+
+/// Sets the branch storage of a SMT database, it must be set before the first commit and can't be changed afterwards. A
+/// database with the shared storage must be written through transactions, see `BranchStorage::Shared`.
+pub fn set_branch_storage<DB, WO>(db: &DB, storage: BranchStorage) -> Result<(), Error>
+where
+    DB: ReadStore + Put<WO>,
+{
+    let map_err = |e: rocksdb::Error| Error::Store(e.to_string());
+    if db.get(SEQUENCE_KEY).map_err(map_err)?.is_some()
+        && stored_branch_storage(db).map_err(map_err)? != storage
+    {
+        return Err(Error::Store(
+            "the branch storage can't be changed after the first commit".to_string(),
+        ));
+    }
+    let value = match storage {
+        BranchStorage::Inline => [0],
+        BranchStorage::Shared => [1],
+    };
+    db.put(BRANCH_STORAGE_KEY, value).map_err(map_err)
+}
+
+fn stored_branch_storage<DB: ReadStore + ?Sized>(db: &DB) -> Result<BranchStorage, rocksdb::Error> {
+    Ok(match db.get(BRANCH_STORAGE_KEY)?.as_deref() {
+        Some([1]) => BranchStorage::Shared,
+        _ => BranchStorage::Inline,
+    })
+}
+
+/// Whether a stored key is the key of a shared branch node.
+pub(crate) fn is_node_key(key: &[u8]) -> bool {
+    parse_hash_key(NODE_PREFIX, key).is_some()
+}
+
+fn stored_leaf_encoding<DB: ReadStore + ?Sized>(db: &DB) -> Result<LeafEncoding, rocksdb::Error> {
+    Ok(match db.get(LEAF_ENCODING_KEY)?.as_deref() {
+        Some([1]) => LeafEncoding::Delta,
+        _ => LeafEncoding::Full,
+    })
+}
 
 /// A SMT `Store` implementation backed by a RocksDB database, using the default column family and supports historical queries.
-pub struct DefaultStore<'a, DB, WO> {
-    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
-    inner: &'a DB,
+pub struct DefaultStore<D, WO> {
+    // A handle to the RocksDB database which stores the data, can be a `&DB` / `&OptimisticTransaction` / `Arc<Snapshot>` etc.
+    inner: D,
     // The sequence number is used to support historical queries.
     sequence: u64,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<WO>,
+    // A generic write options, can be a `WriteOptions` / `()` etc, it's never stored so it doesn't affect `Send` / `Sync`.
+    write_options: PhantomData<fn() -> WO>,
+    // Traces the keys read and written by the store, disabled by default.
+    key_tracer: KeyTracer,
+    sequence_width: SequenceWidth,
+    // The first sequence skipped by `skip_to` since the last commit, the gap is recorded by the next commit.
+    skipped_from: Option<u64>,
+    // Number of branch inserts and removals per height.
+    branch_writes: LevelHistogram,
+    leaf_encoding: LeafEncoding,
+    key_derivation: KeyDerivation,
+    branch_storage: BranchStorage,
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
 {
-    pub fn new(db: &'a DB) -> Self {
+    pub fn new(db: D) -> Self {
         let sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -37,14 +230,25 @@ where
                 )
             })
             .unwrap_or(0);
+        let leaf_encoding = stored_leaf_encoding(&*db).expect("init leaf encoding should be ok");
+        let key_derivation = stored_key_derivation(&*db).expect("init key derivation should be ok");
+        let branch_storage = stored_branch_storage(&*db).expect("init branch storage should be ok");
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            branch_writes: LevelHistogram::default(),
+            leaf_encoding,
+            key_derivation,
+            branch_storage,
         }
     }
 
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Self {
+    pub fn new_with_sequence(db: D, sequence: u64) -> Self {
         let stored_sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -59,67 +263,340 @@ where
         if sequence > stored_sequence {
             panic!("sequence number: {} should be less than or equal to the stored sequence number: {}", sequence, stored_sequence);
         }
+        let leaf_encoding = stored_leaf_encoding(&*db).expect("init leaf encoding should be ok");
+        let key_derivation = stored_key_derivation(&*db).expect("init key derivation should be ok");
+        let branch_storage = stored_branch_storage(&*db).expect("init branch storage should be ok");
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            branch_writes: LevelHistogram::default(),
+            leaf_encoding,
+            key_derivation,
+            branch_storage,
         }
     }
+}
 
+impl<D, WO> DefaultStore<D, WO> {
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    pub fn key_derivation(&self) -> KeyDerivation {
+        self.key_derivation
+    }
+
+    pub fn leaf_encoding(&self) -> LeafEncoding {
+        self.leaf_encoding
+    }
+
+    /// Moves an empty store to `sequence`, so that a syncing client commits the synced state at the sequence of the
+    /// commitment it syncs to.
+    pub fn start_at(&mut self, sequence: u64) -> Result<(), Error> {
+        if self.sequence != 0 {
+            return Err(Error::Store("the database is not empty".to_string()));
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// Moves the store forward to `sequence` before the writes of a block, the sequences in between are never committed
+    /// and read as the state of the previous commit, see `SequenceSource::BlockNumber`.
+    pub fn skip_to(&mut self, sequence: u64) -> Result<(), Error> {
+        if sequence < self.sequence {
+            return Err(Error::Store(format!(
+                "sequence {} is behind the next sequence {}",
+                sequence, self.sequence
+            )));
+        }
+        if sequence > self.sequence_width.max_sequence() {
+            return Err(Error::Store(format!(
+                "sequence {} is beyond the sequence width of the database",
+                sequence
+            )));
+        }
+        if sequence > self.sequence {
+            self.skipped_from.get_or_insert(self.sequence);
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    pub fn branch_writes(&self) -> &LevelHistogram {
+        &self.branch_writes
+    }
+
+    pub fn set_key_tracer(&mut self, key_tracer: KeyTracer) {
+        self.key_tracer = key_tracer;
+    }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
+    /// The keys of the leaves stored at the sequence of the store, without the removed ones, in stored key order. It
+    /// scans the whole database.
+    pub(crate) fn leaves(&self) -> Vec<(H256, CellStatus)> {
+        let width = self.sequence_width;
+        latest_versions(
+            &*self.inner,
+            self.sequence,
+            width,
+            |key| key.len() == LEAF_KEY_LEN + width.encoded_len(),
+            |_key| true,
+            &mut FsckReport::default(),
+        )
+        .into_iter()
+        // a removed leaf is stored as an empty value
+        .filter(|(_key, value)| !value.is_empty())
+        .map(|(key, value)| {
+            let value = match self.leaf_encoding {
+                LeafEncoding::Delta if value.len() == SPEND_RECORD_LEN => self
+                    .delta_leaf(&key, self.sequence)
+                    .expect("a spend record follows a created record"),
+                LeafEncoding::Delta => decode_created_record(value),
+                LeafEncoding::Full => value,
+            };
+            let key: [u8; 32] = key.try_into().expect("checked length");
+            (key.into(), CellStatus::from(value))
+        })
+        .collect()
+    }
+
     fn get(&self, key: &[u8]) -> Option<Box<[u8]>> {
-        let start = [key, self.sequence.to_be_bytes().as_ref()].concat();
+        self.key_tracer.read(key);
+        let start = self.sequence_width.versioned_key(key, self.sequence);
         let mode = IteratorMode::From(&start, Direction::Reverse);
         let iter = self.inner.iterator(mode);
         iter.take_while(|(k, _v)| k.starts_with(key))
             .next()
             .map(|(_k, v)| v)
     }
+
+    // reads a key which isn't versioned, i.e. a shared branch node
+    fn get_unversioned(&self, key: &[u8]) -> Option<Box<[u8]>> {
+        self.inner
+            .iterator(IteratorMode::From(key, Direction::Forward))
+            .next()
+            .filter(|(k, _v)| k.as_ref() == key)
+            .map(|(_k, v)| v)
+    }
+
+    fn get_leaf_slice(&self, key: &[u8]) -> Option<Box<[u8]>> {
+        match self.leaf_encoding {
+            LeafEncoding::Full => self.get(key),
+            LeafEncoding::Delta => {
+                self.key_tracer.read(key);
+                self.delta_leaf(key, self.sequence)
+            }
+        }
+    }
+
+    // rebuilds the 16 bytes status of a delta encoded leaf at `sequence`, a spend record refers to the created-by block number
+    // of the version before it, a removed leaf is an empty value in both encodings
+    fn delta_leaf(&self, key: &[u8], sequence: u64) -> Option<Box<[u8]>> {
+        let start = self.sequence_width.versioned_key(key, sequence);
+        let mode = IteratorMode::From(&start, Direction::Reverse);
+        let mut versions = self
+            .inner
+            .iterator(mode)
+            .take_while(|(k, _v)| k.starts_with(key))
+            .map(|(_k, v)| v);
+        let latest = versions.next()?;
+        if latest.len() != SPEND_RECORD_LEN {
+            return Some(decode_created_record(latest));
+        }
+        let created = versions
+            .find(|v| v.len() != SPEND_RECORD_LEN)
+            .map(decode_created_record)
+            .expect("a spend record follows a created record");
+        let created_by = u64::from_le_bytes(created[0..8].try_into().expect("checked length"));
+        let delta = u32::from_le_bytes(latest[..].try_into().expect("checked length"));
+        let mut status = created.to_vec();
+        status[8..16].copy_from_slice(&(created_by + delta as u64).to_le_bytes());
+        Some(status.into())
+    }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+// converts a created record to the 16 bytes status of a live cell, full records and empty values are returned as is
+fn decode_created_record(value: Box<[u8]>) -> Box<[u8]> {
+    if value.len() == CREATED_RECORD_LEN {
+        [value.as_ref(), [u8::MAX; 8].as_ref()].concat().into()
+    } else {
+        value
+    }
+}
+
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: Delete<WO> + Put<WO>,
 {
     fn put<V: AsRef<[u8]>>(&mut self, key: &[u8], value: V) -> Result<(), Error> {
-        let k = [key, self.sequence.to_be_bytes().as_ref()].concat();
+        let k = self.sequence_width.versioned_key(key, self.sequence);
+        self.key_tracer.write(key, k.len() + value.as_ref().len());
         self.inner
             .put(k, value)
             .map_err(|e| Error::Store(e.to_string()))
     }
 
+    fn put_unversioned(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.key_tracer.write(key, key.len() + value.len());
+        self.inner
+            .put(key, value)
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
     pub fn commit(&mut self) -> Result<(), Error> {
+        if self.sequence == self.sequence_width.max_sequence() {
+            return Err(Error::Store(format!(
+                "sequence {} is the last one of the sequence width of the database",
+                self.sequence
+            )));
+        }
+        if let Some(skipped_from) = self.skipped_from.take() {
+            let (key, value) = skipped_record(skipped_from..self.sequence);
+            self.put_unversioned(&key, &value)?;
+        }
         self.sequence += 1;
+        let sequence = self.sequence.to_be_bytes();
+        self.key_tracer
+            .write(SEQUENCE_KEY, SEQUENCE_KEY.len() + sequence.len());
         self.inner
-            .put(SEQUENCE_KEY, self.sequence.to_be_bytes())
+            .put(SEQUENCE_KEY, sequence)
             .map_err(|e| Error::Store(e.to_string()))
     }
 }
 
-impl<'a, V, DB, WO> StoreReadOps<V> for DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    // the delta encoded record of a 16 bytes status, a spend record is only written if the status was created before
+    // the current sequence, the record of the current sequence is overwritten by the next write of the same sequence
+    fn delta_record(&self, key: &[u8], status: &[u8]) -> Vec<u8> {
+        assert_eq!(
+            16,
+            status.len(),
+            "delta encoded leaves must be cell statuses"
+        );
+        let (created, consumed) = status.split_at(CREATED_RECORD_LEN);
+        if consumed == [u8::MAX; 8] {
+            return created.to_vec();
+        }
+        let created_by = u64::from_le_bytes(created.try_into().expect("checked length"));
+        let consumed_by = u64::from_le_bytes(consumed.try_into().expect("checked length"));
+        let delta = consumed_by
+            .checked_sub(created_by)
+            .and_then(|delta| u32::try_from(delta).ok());
+        let previous = self
+            .sequence
+            .checked_sub(1)
+            .and_then(|sequence| self.delta_leaf(key, sequence));
+        match (previous, delta) {
+            (Some(previous), Some(delta)) if previous.len() == 16 && &previous[0..8] == created => {
+                delta.to_le_bytes().to_vec()
+            }
+            _ => status.to_vec(),
+        }
+    }
+}
+
+impl<D, WO> DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    // stores a shared node, or counts one more reference to it, returns the hash referring to it
+    fn retain_node(&mut self, node: &[u8]) -> Result<[u8; 32], Error> {
+        let mut hash = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(node);
+        hasher.finalize(&mut hash);
+        let key = hash_key(NODE_PREFIX, &hash);
+        let record = match self.get_unversioned(&key) {
+            Some(record) => {
+                let refcount = refcount(&record) + 1;
+                [refcount.to_le_bytes().as_ref(), &record[REFCOUNT_LEN..]].concat()
+            }
+            None => [1u32.to_le_bytes().as_ref(), node].concat(),
+        };
+        self.put_unversioned(&key, &record)?;
+        Ok(hash)
+    }
+
+    // a branch key written more than once in a sequence overwrites its version of the sequence, the reference of the
+    // overwritten version is released, and the node is deleted with its last reference
+    fn release_current_node(&mut self, branch_key: &[u8]) -> Result<(), Error> {
+        let versioned_key = self.sequence_width.versioned_key(branch_key, self.sequence);
+        let hash = match self.get_unversioned(&versioned_key) {
+            Some(hash) if !hash.is_empty() => hash,
+            _ => return Ok(()),
+        };
+        let key = hash_key(NODE_PREFIX, &hash);
+        let record = self
+            .get_unversioned(&key)
+            .ok_or_else(|| Error::Store(format!("missing shared branch node {:?}", hash)))?;
+        match refcount(&record) {
+            1 => {
+                self.key_tracer.write(&key, key.len());
+                self.inner
+                    .delete(&key)
+                    .map_err(|e| Error::Store(e.to_string()))
+            }
+            refcount => self.put_unversioned(
+                &key,
+                &[
+                    (refcount - 1).to_le_bytes().as_ref(),
+                    &record[REFCOUNT_LEN..],
+                ]
+                .concat(),
+            ),
+        }
+    }
+}
+
+fn refcount(record: &[u8]) -> u32 {
+    u32::from_le_bytes(record[..REFCOUNT_LEN].try_into().expect("checked length"))
+}
+
+impl<V, D, WO> StoreReadOps<V> for DefaultStore<D, WO>
 where
     V: Value + From<Box<[u8]>>,
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         let slice = self.get(&branch_key_to_vec(branch_key));
-        match slice {
-            Some(s) if s.is_empty() => Ok(None),
-            Some(s) => Ok(Some(slice_to_branch_node(&s))),
-            None => Ok(None),
+        match (slice, self.branch_storage) {
+            (Some(s), _) if s.is_empty() => Ok(None),
+            (Some(s), BranchStorage::Inline) => slice_to_branch_node(&s)
+                .map(Some)
+                .map_err(|e| Error::Store(format!("branch node {:?}: {}", branch_key, e))),
+            (Some(hash), BranchStorage::Shared) => {
+                let record = self
+                    .get_unversioned(&hash_key(NODE_PREFIX, &hash))
+                    .ok_or_else(|| {
+                        Error::Store(format!("missing shared branch node {:?}", hash))
+                    })?;
+                slice_to_branch_node(record.get(REFCOUNT_LEN..).unwrap_or_default())
+                    .map(Some)
+                    .map_err(|e| Error::Store(format!("shared branch node {:?}: {}", hash, e)))
+            }
+            (None, _) => Ok(None),
         }
     }
 
     fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
-        let slice = self.get(leaf_key.as_slice());
+        let slice = self.get_leaf_slice(leaf_key.as_slice());
         match slice {
             Some(s) if s.is_empty() => Ok(None),
             Some(s) => Ok(Some(V::from(s))),
@@ -128,34 +605,112 @@ where
     }
 }
 
-impl<'a, V, DB, WO> StoreWriteOps<V> for DefaultStore<'a, DB, WO>
+impl<V, D, WO> StoreWriteOps<V> for DefaultStore<D, WO>
 where
     V: Value + AsRef<[u8]> + From<Box<[u8]>>,
-    DB: Iterate + Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
-        self.put(&branch_key_to_vec(&node_key), &branch_node_to_vec(&branch))
+        self.branch_writes.record(node_key.height);
+        let key = branch_key_to_vec(&node_key);
+        match self.branch_storage {
+            BranchStorage::Inline => self.put(&key, branch_node_to_vec(&branch)),
+            BranchStorage::Shared => {
+                self.release_current_node(&key)?;
+                let hash = self.retain_node(&branch_node_to_vec(&branch))?;
+                self.put(&key, hash)
+            }
+        }
     }
 
     fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
-        self.put(leaf_key.as_slice(), leaf)
+        match self.leaf_encoding {
+            LeafEncoding::Full => self.put(leaf_key.as_slice(), leaf),
+            LeafEncoding::Delta => {
+                let record = self.delta_record(leaf_key.as_slice(), leaf.as_ref());
+                self.put(leaf_key.as_slice(), record)
+            }
+        }
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
-        let k = [
-            &branch_key_to_vec(node_key),
-            self.sequence.to_be_bytes().as_ref(),
-        ]
-        .concat();
+        self.branch_writes.record(node_key.height);
+        let key = branch_key_to_vec(node_key);
+        if self.branch_storage == BranchStorage::Shared {
+            self.release_current_node(&key)?;
+        }
+        let k = self.sequence_width.versioned_key(&key, self.sequence);
+        self.key_tracer.write(&key, k.len());
         self.inner
             .put(k, [])
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
-        let k = [leaf_key.as_slice(), self.sequence.to_be_bytes().as_ref()].concat();
+        let k = self
+            .sequence_width
+            .versioned_key(leaf_key.as_slice(), self.sequence);
+        self.key_tracer.write(leaf_key.as_slice(), k.len());
         self.inner
             .put(k, [])
             .map_err(|e| Error::Store(e.to_string()))
     }
 }
+
+/// The store of the tree of a `GenericSMTAccumulator`: the `DefaultStore` of a database, or a `ShardedStore` spreading
+/// the tree over several of them.
+pub trait TreeStore: StoreReadOps<CellStatus> {
+    /// The sequence the tree is read at, and written at until the next `commit`.
+    fn sequence(&self) -> u64;
+
+    fn key_derivation(&self) -> KeyDerivation;
+
+    /// The leaves of the tree and their values, scanned from their latest versions.
+    fn leaves(&self) -> Vec<(H256, CellStatus)>;
+}
+
+/// A `TreeStore` the accumulator writes its blocks to.
+pub trait TreeStoreMut: TreeStore + StoreWriteOps<CellStatus> {
+    fn commit(&mut self) -> Result<(), Error>;
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), Error>;
+
+    fn set_key_tracer(&mut self, key_tracer: KeyTracer);
+}
+
+impl<D, WO> TreeStore for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn sequence(&self) -> u64 {
+        DefaultStore::sequence(self)
+    }
+
+    fn key_derivation(&self) -> KeyDerivation {
+        DefaultStore::key_derivation(self)
+    }
+
+    fn leaves(&self) -> Vec<(H256, CellStatus)> {
+        DefaultStore::leaves(self)
+    }
+}
+
+impl<D, WO> TreeStoreMut for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    fn commit(&mut self) -> Result<(), Error> {
+        DefaultStore::commit(self)
+    }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), Error> {
+        DefaultStore::skip_to(self, sequence)
+    }
+
+    fn set_key_tracer(&mut self, key_tracer: KeyTracer) {
+        DefaultStore::set_key_tracer(self, key_tracer)
+    }
+}
@@ -0,0 +1,144 @@
+use super::{accumulator::AccumulatorCommitment, store::DefaultStore, LEAF_KEY_LEN};
+use crate::{
+    fsck::{latest_versions, FsckReport},
+    sequence_width::stored_sequence_width,
+    storage::traits::{ReadStore, WriteStore},
+    sync::{SyncChunk, SyncClient},
+    AccumulatorError, CellStatus,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    traits::{StoreReadOps, Value},
+    CompiledMerkleProof, SparseMerkleTree, H256,
+};
+
+/// Exports the live leaves of a SMT accumulator database at `sequence` to `send`, in chunks of `chunk_size` leaves in stored
+/// key order. The entries of a chunk are the leaf keys and their 16 bytes statuses, its proof is a compiled multi-proof of
+/// all of them against the root at the sequence. Returns the number of exported leaves.
+pub fn export_chunks<DB, F>(
+    db: &DB,
+    sequence: u64,
+    chunk_size: usize,
+    mut send: F,
+) -> Result<u64, AccumulatorError>
+where
+    DB: ReadStore,
+    F: FnMut(SyncChunk) -> Result<(), AccumulatorError>,
+{
+    assert!(chunk_size > 0, "chunk size must be positive");
+    let store = DefaultStore::<_, ()>::new_with_sequence(db, sequence);
+    let smt = SparseMerkleTree::<Blake2bHasher, CellStatus, _>::new_with_store(store)?;
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    // the versions written after the sequence are skipped, they are fsck's concern
+    let mut report = FsckReport::default();
+    let mut exported = 0;
+    let mut leaves = Vec::with_capacity(chunk_size);
+    for (key, value) in latest_versions(
+        db,
+        sequence,
+        width,
+        |key| key.len() == LEAF_KEY_LEN + width.encoded_len(),
+        |_key| true,
+        &mut report,
+    ) {
+        // a removed leaf is stored as an empty value
+        if value.is_empty() {
+            continue;
+        }
+        let key: [u8; 32] = key.try_into().expect("checked length");
+        let key: H256 = key.into();
+        // a delta encoded leaf is rebuilt by the store from its previous versions
+        let status = if value.len() == 16 {
+            CellStatus::from(value)
+        } else {
+            smt.get(&key)?
+        };
+        leaves.push((key, status));
+        if leaves.len() == chunk_size {
+            exported += leaves.len() as u64;
+            send(export_chunk(&smt, std::mem::take(&mut leaves))?)?;
+        }
+    }
+    if !leaves.is_empty() {
+        exported += leaves.len() as u64;
+        send(export_chunk(&smt, leaves)?)?;
+    }
+    Ok(exported)
+}
+
+fn export_chunk<S: StoreReadOps<CellStatus>>(
+    smt: &SparseMerkleTree<Blake2bHasher, CellStatus, S>,
+    mut leaves: Vec<(H256, CellStatus)>,
+) -> Result<SyncChunk, AccumulatorError> {
+    // the tree orders the keys from their highest bit, not as the stored bytes
+    leaves.sort_unstable_by_key(|(key, _)| *key);
+    let keys: Vec<H256> = leaves.iter().map(|(key, _)| *key).collect();
+    let proof = smt.merkle_proof(keys.clone())?.compile(keys)?;
+    Ok(SyncChunk {
+        entries: leaves
+            .into_iter()
+            .map(|(key, status)| (key.as_slice().to_vec(), status.block_numbers.to_vec()))
+            .collect(),
+        proof: proof.0,
+    })
+}
+
+/// Loads the chunks of `export_chunks` into an empty SMT accumulator database. A leaf sent twice is harmless since both
+/// copies are proved, and a missing one fails `finish`.
+pub struct SMTSyncClient<'a, DB, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, CellStatus, DefaultStore<&'a DB, WO>>,
+    commitment: AccumulatorCommitment,
+}
+
+impl<'a, DB, WO> SMTSyncClient<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// The commitment must come from a trusted source, e.g. a block header.
+    pub fn new(db: &'a DB, commitment: AccumulatorCommitment) -> Result<Self, AccumulatorError> {
+        let mut store = DefaultStore::new(db);
+        store.start_at(commitment.sequence())?;
+        let smt = SparseMerkleTree::new_with_store(store)?;
+        Ok(SMTSyncClient { smt, commitment })
+    }
+}
+
+impl<'a, DB, WO> SyncClient for SMTSyncClient<'a, DB, WO>
+where
+    DB: WriteStore<WO>,
+{
+    fn ingest(&mut self, chunk: SyncChunk) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(chunk.entries.len());
+        for (key, value) in chunk.entries {
+            let key: [u8; 32] = key
+                .as_slice()
+                .try_into()
+                .map_err(|_| AccumulatorError::InvalidProof)?;
+            let block_numbers = value
+                .as_slice()
+                .try_into()
+                .map_err(|_| AccumulatorError::InvalidProof)?;
+            leaves.push((H256::from(key), CellStatus { block_numbers }));
+        }
+        let hashes = leaves
+            .iter()
+            .map(|(key, status)| (*key, status.to_h256()))
+            .collect();
+        let verified = CompiledMerkleProof(chunk.proof)
+            .verify::<Blake2bHasher>(self.commitment.root(), hashes);
+        if !matches!(verified, Ok(true)) {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        self.smt.update_all(leaves)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), AccumulatorError> {
+        if self.smt.root() != self.commitment.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        self.smt.store_mut().commit()?;
+        Ok(())
+    }
+}
@@ -1,12 +1,46 @@
-use rocksdb::{prelude::Open, OptimisticTransactionDB};
-use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+use rocksdb::{
+    prelude::{Iterate, Open, Put},
+    Direction, IteratorMode, OptimisticTransactionDB, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, merge::MergeValue, traits::Value, BranchNode, SparseMerkleTree, H256,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
 use tempfile::{Builder, TempDir};
 
-use crate::{new_blake2b, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof};
+use crate::{
+    commitment_digest,
+    live_cache::{CacheLiveCells, LiveCache},
+    metrics::{KeyStats, KeyTracer, LevelWrites, TraceKeys, WriteCounts},
+    new_blake2b,
+    smt_live::accumulator::SMTAccumulator as SMTLiveAccumulator,
+    storage::traits::WriteStore,
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
+};
 
-use super::{accumulator::SMTAccumulator, store::DefaultStore};
+use super::{
+    accumulator::{
+        AbsenceProof, AccumulatorCommitment, SMTAccumulator, SMTAccumulatorOwned, WalletProof,
+        COMMITMENT_TAG, PROOF_FORMAT_VERSION,
+    },
+    bulk::{build_tree, LeafHash},
+    fsck::fsck,
+    script_index::{ScriptCells, ScriptIndex},
+    serde::{branch_node_to_vec, slice_to_branch_node, SerdeError, FORMAT_VERSION},
+    set_branch_storage, set_key_derivation, set_leaf_encoding,
+    sharded::ShardedSMTAccumulator,
+    store::DefaultStore,
+    tiered::{TieredDBs, TieredSMTAccumulator},
+    BranchStorage, KeyDerivation, LeafEncoding, ZERO_CELL_STATUS,
+};
 
-type DefaultStoreSMT<'a, DB, WO> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, DB, WO>>;
+// smt branch nodes are stored without a prefix
+const EMPTY_PREFIX: &[u8] = &[];
+
+type DefaultStoreSMT<'a, DB, WO> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<&'a DB, WO>>;
 
 #[derive(Default, Clone)]
 pub struct Word(String);
@@ -199,3 +233,1123 @@ fn test_accumulator() {
         .verify(commitment3, vec![(out_point_2, CellStatus::new_dead(0, 2))])
         .unwrap());
 }
+
+#[test]
+fn test_spent_cell_proofs() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    // several outputs of several txs
+    let out_points: Vec<_> = [(1u8, 0), (1, 1), (1, 2), (2, 0), (2, 5)]
+        .iter()
+        .map(|(tx_hash, index)| OutPoint {
+            tx_hash: [*tx_hash; 32],
+            index: *index,
+        })
+        .collect();
+    accumulator.add(out_points.clone()).unwrap();
+    let created = accumulator.commit().unwrap();
+    accumulator
+        .delete(vec![out_points[1].clone(), out_points[3].clone()])
+        .unwrap();
+    let spent = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    let statuses: Vec<_> = out_points
+        .iter()
+        .map(|out_point| (out_point.clone(), CellStatus::new_live(0)))
+        .collect();
+    let proof = accumulator
+        .proof(created.clone(), out_points.clone())
+        .unwrap();
+    assert!(proof.verify_live(created, statuses.clone()).unwrap());
+
+    // after the spend the same set is still provable, with the consumed statuses, but not as live
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 1).unwrap();
+    let mut statuses = statuses;
+    statuses[1].1 = CellStatus::new_dead(0, 1);
+    statuses[3].1 = CellStatus::new_dead(0, 1);
+    let proof = accumulator
+        .proof(spent.clone(), out_points.clone())
+        .unwrap();
+    assert!(proof.verify(spent.clone(), statuses.clone()).unwrap());
+    let proof = accumulator
+        .proof(spent.clone(), out_points.clone())
+        .unwrap();
+    assert!(!proof.verify_live(spent.clone(), statuses).unwrap());
+
+    // a cell which was never added can't be proved
+    let missing = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 3,
+    };
+    assert!(matches!(
+        accumulator.proof(spent, vec![missing]),
+        Err(AccumulatorError::ElementNotFound(0))
+    ));
+}
+
+#[test]
+fn test_wallet_proof() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let wallet = vec![
+        out_points[7].clone(),
+        out_points[0].clone(),
+        out_points[3].clone(),
+    ];
+    let proof = SMTAccumulator::<_, ()>::wallet_proof(&snapshot, wallet, 1).unwrap();
+    assert_eq!(3, proof.elements().len());
+    assert!(proof.verify(&commitment).unwrap());
+
+    let proof = WalletProof::from_bytes(&proof.to_bytes()).unwrap();
+    let commitment = AccumulatorCommitment::from_bytes(&commitment.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    assert!(matches!(
+        SMTAccumulator::<_, ()>::wallet_proof(
+            &snapshot,
+            vec![out_points[0].clone(), out_points[1].clone()],
+            1
+        ),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+}
+
+#[test]
+fn test_absence_proof() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    let first = accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let unknown = vec![out_points[8].clone(), out_points[6].clone()];
+    let proof = SMTAccumulator::<_, ()>::absence_proof(&snapshot, unknown, 1).unwrap();
+    assert_eq!(2, proof.out_points().len());
+    assert!(proof.verify(&commitment).unwrap());
+    assert!(!proof.verify(&first).unwrap());
+
+    let proof = AbsenceProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    // a count whose entries would overflow the length of the input is rejected, as is a truncated input
+    let mut bytes = proof.to_bytes();
+    bytes[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        AbsenceProof::from_bytes(&bytes),
+        Err(AccumulatorError::InvalidProof)
+    ));
+    assert!(matches!(
+        WalletProof::from_bytes(&bytes),
+        Err(AccumulatorError::InvalidProof)
+    ));
+    let bytes = proof.to_bytes();
+    assert!(matches!(
+        AbsenceProof::from_bytes(&bytes[..13]),
+        Err(AccumulatorError::InvalidProof)
+    ));
+
+    // a consumed cell keeps its status in the tree
+    assert!(matches!(
+        SMTAccumulator::<_, ()>::absence_proof(
+            &snapshot,
+            vec![out_points[9].clone(), out_points[1].clone()],
+            1
+        ),
+        Err(AccumulatorError::AlreadyExists(1))
+    ));
+}
+
+#[test]
+fn test_owned_accumulator() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+
+    let out_point = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    };
+
+    let mut accumulator = SMTAccumulatorOwned::<_, WriteOptions>::new(Arc::clone(&db)).unwrap();
+    let elements = vec![out_point.clone()];
+    let commitment = thread::spawn(move || {
+        accumulator.add(elements).unwrap();
+        accumulator.commit().unwrap()
+    })
+    .join()
+    .unwrap();
+
+    let accumulator = SMTAccumulatorOwned::<_, WriteOptions>::new_with_sequence(db, 0).unwrap();
+    let proof = accumulator
+        .proof(commitment.clone(), vec![out_point.clone()])
+        .unwrap();
+    assert!(proof
+        .verify(commitment, vec![(out_point, CellStatus::new_live(0))])
+        .unwrap());
+}
+
+#[test]
+fn test_fsck() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(Some(1), report.sequence);
+    // the 9 live leaves and the consumed one: the smt commits to the consumed cells, so a delete writes the dead status
+    // of the leaf instead of removing it
+    assert_eq!(10, report.leaves);
+    assert_eq!(0, report.orphaned_versions);
+    let snapshot = db.snapshot();
+    let reader = SMTAccumulator::new_with_sequence(&snapshot, 1).unwrap();
+    assert!(!reader
+        .cell_status(&out_points[1])
+        .unwrap()
+        .unwrap()
+        .is_live());
+
+    // a block written without committing the accumulator leaves orphaned versions behind
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator
+        .add(vec![OutPoint {
+            tx_hash: [10u8; 32],
+            index: 10,
+        }])
+        .unwrap();
+    tx.commit().unwrap();
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert!(report.orphaned_versions > 0);
+
+    // a leaf overwritten behind the accumulator's back doesn't match the stored branches anymore
+    let tx = db.transaction_default();
+    let key = [out_points[0].hash().as_ref(), 1u64.to_be_bytes().as_ref()].concat();
+    tx.put(key, CellStatus::new_live(1)).unwrap();
+    tx.commit().unwrap();
+    assert!(!fsck(&db.snapshot()).unwrap().is_ok());
+}
+
+#[test]
+fn test_commitment_digest() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    let commitment1 = accumulator.commit().unwrap();
+    let commitment2 = accumulator.commit().unwrap();
+    assert_eq!(commitment1.root(), commitment2.root());
+    assert_ne!(
+        commitment1.commitment_digest(),
+        commitment2.commitment_digest()
+    );
+    assert_eq!(
+        commitment_digest(COMMITMENT_TAG, commitment1.root().as_slice(), 0),
+        commitment1.commitment_digest()
+    );
+
+    // the empty trees of both smt backends have the same root, the tag tells them apart
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTLiveAccumulator::new(&tx).unwrap();
+    let live_commitment = accumulator.commit().unwrap();
+    assert_eq!(commitment1.root(), live_commitment.root());
+    assert_ne!(
+        commitment1.commitment_digest(),
+        live_commitment.commitment_digest()
+    );
+}
+
+#[test]
+fn test_apply_blocks() {
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    // the last block consumes a cell created in the same block
+    let blocks = vec![
+        BlockOps {
+            added: out_points[0..5].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: out_points[5..].to_vec(),
+            deleted: vec![out_points[1].clone(), out_points[9].clone()],
+        },
+    ];
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    let commitments = accumulator.apply_blocks(blocks.clone()).unwrap();
+    assert_eq!(2, commitments.len());
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    for (block, commitment) in blocks.into_iter().zip(commitments) {
+        accumulator.add(block.added).unwrap();
+        accumulator.delete(block.deleted).unwrap();
+        let expected = accumulator.commit().unwrap();
+        assert_eq!(expected.root(), commitment.root());
+        assert_eq!(expected.sequence(), commitment.sequence());
+    }
+}
+
+#[test]
+fn test_update_chunk_size() {
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    // the second block has 7 leaves, the live and dead leaves of its last cell fall into different chunks
+    let blocks = vec![
+        BlockOps {
+            added: out_points[0..5].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: out_points[5..].to_vec(),
+            deleted: vec![out_points[1].clone(), out_points[9].clone()],
+        },
+    ];
+
+    let mut roots = Vec::new();
+    for chunk_size in [None, Some(1), Some(3)] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+        accumulator.set_update_chunk_size(chunk_size);
+        let commitments = accumulator.apply_blocks(blocks.clone()).unwrap();
+        roots.push(*commitments.last().unwrap().root());
+    }
+    assert!(roots.windows(2).all(|pair| pair[0] == pair[1]));
+}
+
+#[test]
+fn test_sharded_store() {
+    let out_points: Vec<_> = (0..64u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    accumulator.add(out_points.clone()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(out_points[..10].to_vec()).unwrap();
+    let expected = accumulator.commit().unwrap();
+
+    let shards: Vec<_> = (0..3).map(|_| open_db()).collect();
+    let txs: Vec<_> = shards
+        .iter()
+        .map(|(db, _)| db.transaction_default())
+        .collect();
+    let mut accumulator =
+        ShardedSMTAccumulator::<_, ()>::new_with_strict(txs.iter().collect(), true).unwrap();
+    accumulator.add(out_points.clone()).unwrap();
+    accumulator.commit().unwrap();
+    assert!(matches!(
+        accumulator.delete(vec![out_points[0].clone(), out_points[0].clone()]),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+    accumulator.delete(out_points[..10].to_vec()).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    assert_eq!(expected.root(), commitment.root());
+    assert_eq!(expected.sequence(), commitment.sequence());
+    let proof = ShardedSMTAccumulator::<_, ()>::wallet_proof(
+        txs.iter().collect(),
+        out_points[10..12].to_vec(),
+        commitment.sequence(),
+    )
+    .unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+    // the nodes are spread by a hash of their whole keys, no shard holds most of the tree
+    let counts: Vec<usize> = txs
+        .iter()
+        .map(|tx| tx.iterator(IteratorMode::Start).count())
+        .collect();
+    let total: usize = counts.iter().sum();
+    assert!(
+        counts.iter().all(|count| *count < total / 2),
+        "{:?}",
+        counts
+    );
+}
+
+#[test]
+fn test_tiered_fold() {
+    let out_points: Vec<_> = (0..64u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    let blocks = vec![
+        BlockOps {
+            added: out_points[..48].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: vec![],
+            deleted: out_points[..10].to_vec(),
+        },
+        BlockOps {
+            added: out_points[48..].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: vec![],
+            deleted: out_points[40..50].to_vec(),
+        },
+    ];
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    let expected = accumulator.apply_blocks(blocks.clone()).unwrap();
+
+    let (hot_db, _hot_tmp_dir) = open_db();
+    let (cold_db, _cold_tmp_dir) = open_db();
+    let hot_tx = hot_db.transaction_default();
+    let cold_tx = cold_db.transaction_default();
+    let dbs = TieredDBs {
+        hot: &hot_tx,
+        cold: &cold_tx,
+        fold_interval: 4,
+    };
+    let mut accumulator = TieredSMTAccumulator::<_, ()>::new_with_strict(dbs, true).unwrap();
+    let commitments = accumulator.apply_blocks(blocks).unwrap();
+    // the leaves stay in the hot tree until the fold at sequence 3
+    assert_eq!(&H256::zero(), commitments[2].cold_root());
+    assert_ne!(&H256::zero(), commitments[2].hot_root());
+    assert_eq!(&H256::zero(), commitments[3].hot_root());
+    assert_eq!(expected[3].root(), commitments[3].cold_root());
+    assert_eq!(64, accumulator.folded_leaves());
+    assert!(!accumulator
+        .cell_status(&out_points[45])
+        .unwrap()
+        .unwrap()
+        .is_live());
+    assert!(accumulator
+        .cell_status(&out_points[60])
+        .unwrap()
+        .unwrap()
+        .is_live());
+
+    // a live cell is proved by its hot leaf before the fold and by its cold leaf after it
+    for sequence in [2, 3] {
+        let proof = TieredSMTAccumulator::<_, ()>::wallet_proof(
+            dbs,
+            vec![out_points[60].clone()],
+            sequence,
+        )
+        .unwrap();
+        assert!(proof.verify(&commitments[sequence as usize]).unwrap());
+        assert!(!proof.verify(&commitments[1]).unwrap());
+    }
+    assert!(
+        TieredSMTAccumulator::<_, ()>::wallet_proof(dbs, vec![out_points[45].clone()], 3).is_err()
+    );
+
+    // the cells consumed by the next block are in the hot tree, the others in the cold one, a proof covers both
+    accumulator.delete(out_points[50..52].to_vec()).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    assert_ne!(&H256::zero(), commitment.hot_root());
+    let cells = vec![out_points[50].clone(), out_points[55].clone()];
+    let statuses: Vec<_> = cells
+        .iter()
+        .map(|cell| {
+            (
+                cell.clone(),
+                accumulator.cell_status(cell).unwrap().unwrap(),
+            )
+        })
+        .collect();
+    assert!(!statuses[0].1.is_live());
+    assert!(statuses[1].1.is_live());
+    let proof = accumulator
+        .proof(commitment.clone(), cells.clone())
+        .unwrap();
+    assert!(proof.verify(commitment.clone(), statuses.clone()).unwrap());
+    // the consumed cell isn't proved live, nor by the cold leaf its hot leaf overrides
+    let mut live = statuses.clone();
+    live[0].1 = statuses[1].1.clone();
+    let proof = accumulator.proof(commitment.clone(), cells).unwrap();
+    assert!(!proof.verify(commitment, live).unwrap());
+}
+
+#[test]
+fn test_tiered_fold_recovery() {
+    let out_points: Vec<_> = (0..16u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    let blocks = vec![
+        BlockOps {
+            added: out_points[..8].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: out_points[8..].to_vec(),
+            deleted: out_points[..2].to_vec(),
+        },
+    ];
+    // folded every other sequence
+    fn open<'a, T: WriteStore<()>>(
+        hot: &'a T,
+        cold: &'a T,
+    ) -> Result<TieredSMTAccumulator<&'a T, ()>, AccumulatorError> {
+        TieredSMTAccumulator::new_with_strict(
+            TieredDBs {
+                hot,
+                cold,
+                fold_interval: 2,
+            },
+            true,
+        )
+    }
+
+    // the same blocks in a single pair of transactions
+    let (hot_db, _hot_tmp_dir) = open_db();
+    let (cold_db, _cold_tmp_dir) = open_db();
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    let mut accumulator = open(&hot_tx, &cold_tx).unwrap();
+    let expected = accumulator
+        .apply_blocks(blocks.clone())
+        .unwrap()
+        .pop()
+        .unwrap();
+    drop(accumulator);
+
+    let (hot_db, _hot_tmp_dir) = open_db();
+    let (cold_db, _cold_tmp_dir) = open_db();
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    let mut accumulator = open(&hot_tx, &cold_tx).unwrap();
+    accumulator.apply_blocks(blocks[..1].to_vec()).unwrap();
+    drop(accumulator);
+    hot_tx.commit().unwrap();
+    cold_tx.commit().unwrap();
+
+    // the hot transaction of the fold is committed and the cold one is lost
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    let mut accumulator = open(&hot_tx, &cold_tx).unwrap();
+    accumulator.apply_blocks(blocks[1..].to_vec()).unwrap();
+    drop(accumulator);
+    hot_tx.commit().unwrap();
+    cold_tx.rollback().unwrap();
+
+    // opening the accumulator redoes the fold in the cold tree from the journal
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    let accumulator = open(&hot_tx, &cold_tx).unwrap();
+    let commitment = accumulator.commitment();
+    assert_eq!(2, commitment.sequence());
+    assert_eq!(expected.hot_root(), commitment.hot_root());
+    assert_eq!(expected.cold_root(), commitment.cold_root());
+    assert!(!accumulator
+        .cell_status(&out_points[0])
+        .unwrap()
+        .unwrap()
+        .is_live());
+    drop(accumulator);
+    hot_tx.commit().unwrap();
+    cold_tx.commit().unwrap();
+
+    // a cold tree ahead of the hot one can't be recovered
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    let mut accumulator = open(&hot_tx, &cold_tx).unwrap();
+    accumulator
+        .add(vec![OutPoint {
+            tx_hash: [u8::MAX; 32],
+            index: 0,
+        }])
+        .unwrap();
+    accumulator.commit().unwrap();
+    drop(accumulator);
+    hot_tx.rollback().unwrap();
+    cold_tx.commit().unwrap();
+    let (hot_tx, cold_tx) = (hot_db.transaction_default(), cold_db.transaction_default());
+    assert!(open(&hot_tx, &cold_tx).is_err());
+}
+
+#[test]
+fn test_live_cache() {
+    let out_points: Vec<_> = (0..32u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    let blocks = vec![
+        BlockOps {
+            added: out_points[..16].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: out_points[16..].to_vec(),
+            deleted: out_points[..4].to_vec(),
+        },
+        BlockOps {
+            added: vec![],
+            deleted: out_points[12..20].to_vec(),
+        },
+    ];
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    let expected = accumulator.apply_blocks(blocks.clone()).unwrap();
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    // the first block is written before the cache is enabled, its cells are read from the tree
+    accumulator.apply_blocks(blocks[..1].to_vec()).unwrap();
+    accumulator.set_live_cache(LiveCache::default());
+    let mut commitments = accumulator.apply_blocks(blocks[1..2].to_vec()).unwrap();
+    assert_eq!(0, accumulator.live_cache().unwrap().hits());
+    assert_eq!(4, accumulator.live_cache().unwrap().misses());
+    assert_eq!(16, accumulator.live_cache().unwrap().len());
+
+    // the next writer takes the cache over, the cells of the second block are hits
+    let cache = accumulator.take_live_cache().unwrap();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+    accumulator.set_live_cache(cache);
+    commitments.extend(accumulator.apply_blocks(blocks[2..].to_vec()).unwrap());
+    let cache = accumulator.live_cache().unwrap();
+    assert_eq!(4, cache.hits());
+    assert_eq!(8, cache.misses());
+    assert_eq!(12, cache.len());
+    for (expected, commitment) in expected[1..].iter().zip(commitments.iter()) {
+        assert_eq!(expected.root(), commitment.root());
+    }
+
+    // a consumed cell is evicted, deleting it again reads its dead status from the tree
+    assert!(matches!(
+        accumulator.delete(vec![out_points[18].clone()]),
+        Err(AccumulatorError::ElementNotFound(0))
+    ));
+}
+
+#[test]
+fn test_level_writes() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    assert_eq!(0, accumulator.level_writes().unwrap().total());
+
+    accumulator
+        .add(vec![OutPoint {
+            tx_hash: [1; 32],
+            index: 0,
+        }])
+        .unwrap();
+    accumulator.commit().unwrap();
+    // every update path ends at the root branch
+    let histogram = accumulator.level_writes().unwrap();
+    assert!(histogram.0[255] > 0);
+    assert!(histogram.total() >= histogram.0[255]);
+}
+
+#[test]
+fn test_trace_keys() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let key_stats = Arc::new(Mutex::new(KeyStats::default()));
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator.trace_keys(KeyTracer::new(Arc::clone(&key_stats)));
+    accumulator
+        .add(vec![
+            OutPoint {
+                tx_hash: [1; 32],
+                index: 0,
+            },
+            OutPoint {
+                tx_hash: [2; 32],
+                index: 0,
+            },
+        ])
+        .unwrap();
+    accumulator.commit().unwrap();
+
+    let hot_keys = key_stats.lock().unwrap().hottest(3);
+    assert_eq!(3, hot_keys.0.len());
+    // the branches shared by both paths are the hottest, a branch key is the height followed by the node key
+    let (key, counts) = &hot_keys.0[0];
+    assert_eq!(33, key.len());
+    assert!(counts.writes > 0);
+}
+
+#[test]
+fn test_delta_leaf_encoding() {
+    let out_points: Vec<_> = (0..6u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    // every block also consumes its first added cell, and non-strict mode allows to consume a cell twice
+    let blocks = vec![
+        (out_points[0..4].to_vec(), vec![]),
+        (out_points[4..].to_vec(), vec![out_points[0].clone()]),
+        (vec![], vec![out_points[1].clone(), out_points[4].clone()]),
+        (vec![], vec![out_points[1].clone()]),
+    ];
+    let run = |encoding| {
+        let (db, tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        set_leaf_encoding::<_, ()>(&tx, encoding).unwrap();
+        let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+        let mut commitments = Vec::new();
+        for (added, deleted) in blocks.clone() {
+            accumulator.add(added.clone()).unwrap();
+            accumulator.delete(deleted).unwrap();
+            accumulator
+                .delete(added[..added.len().min(1)].to_vec())
+                .unwrap();
+            commitments.push(accumulator.commit().unwrap().to_bytes());
+        }
+        tx.commit().unwrap();
+        let report = fsck(&db.snapshot()).unwrap();
+        assert!(report.is_ok(), "{}", report);
+        (commitments, db, tmp_dir)
+    };
+
+    let (full, full_db, _tmp_dir) = run(LeafEncoding::Full);
+    let (delta, db, _tmp_dir) = run(LeafEncoding::Delta);
+    assert_eq!(full, delta);
+    // the leaves scanned by a sweep rebuild the same statuses from the spend records
+    let leaves = |db: &OptimisticTransactionDB| DefaultStore::<_, ()>::new(db).leaves();
+    assert_eq!(leaves(&full_db), leaves(&db));
+
+    // a cell consumed in a later block is stored as spend records after its created record,
+    // a cell consumed in the block which creates it is stored in full
+    let values = |out_point: &OutPoint| -> Vec<usize> {
+        let leaf_key = out_point.hash();
+        db.iterator(IteratorMode::From(&leaf_key, Direction::Forward))
+            .take_while(|(k, _v)| k.starts_with(&leaf_key))
+            .map(|(_k, v)| v.len())
+            .collect()
+    };
+    assert_eq!(vec![8, 4, 4], values(&out_points[1]));
+    assert_eq!(vec![16, 4], values(&out_points[4]));
+
+    // the encoding of a database can't change after the first commit
+    let tx = db.transaction_default();
+    assert!(set_leaf_encoding::<_, ()>(&tx, LeafEncoding::Full).is_err());
+}
+
+#[test]
+fn test_prefixed_key_derivation() {
+    // the outputs of one transaction
+    let out_points: Vec<_> = (0..4u32)
+        .map(|index| OutPoint {
+            tx_hash: [7u8; 32],
+            index,
+        })
+        .collect();
+    let run = |derivation| {
+        let (db, tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        set_key_derivation::<_, ()>(&tx, derivation).unwrap();
+        let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+        accumulator.add(out_points.clone()).unwrap();
+        accumulator.delete(vec![out_points[3].clone()]).unwrap();
+        let commitment = accumulator.commit().unwrap();
+        tx.commit().unwrap();
+        (commitment, db, tmp_dir)
+    };
+
+    let (hashed, _db, _tmp_dir) = run(KeyDerivation::Hashed);
+    let (prefixed, db, _tmp_dir) = run(KeyDerivation::Prefixed);
+    assert_ne!(hashed.root(), prefixed.root());
+    let leaf_key = KeyDerivation::Prefixed.leaf_key(&out_points[1]);
+    assert_eq!([1, 0, 0, 0], leaf_key.as_slice()[0..4]);
+    assert_eq!([7u8; 28], leaf_key.as_slice()[4..]);
+    assert!(db
+        .iterator(IteratorMode::From(leaf_key.as_slice(), Direction::Forward))
+        .next()
+        .map_or(false, |(k, _v)| k.starts_with(leaf_key.as_slice())));
+
+    let snapshot = db.snapshot();
+    let proof =
+        SMTAccumulator::<_, ()>::wallet_proof(&snapshot, out_points[0..3].to_vec(), 0).unwrap();
+    assert!(proof.verify(&prefixed).unwrap());
+    // the serialized proof carries the derivation
+    let bytes = proof.to_bytes();
+    assert_eq!([PROOF_FORMAT_VERSION, 1], bytes[0..2]);
+    let decoded = WalletProof::from_bytes(&bytes).unwrap();
+    assert_eq!(KeyDerivation::Prefixed, decoded.key_derivation());
+    assert!(decoded.verify(&prefixed).unwrap());
+    // nor a proof of another version, nor an unknown derivation decode
+    let mut bytes = proof.to_bytes();
+    bytes[0] = 0;
+    assert!(WalletProof::from_bytes(&bytes).is_err());
+    bytes[0] = PROOF_FORMAT_VERSION;
+    bytes[1] = 2;
+    assert!(WalletProof::from_bytes(&bytes).is_err());
+
+    // as does a serialized absence proof
+    let unknown = OutPoint {
+        tx_hash: [7u8; 32],
+        index: 9,
+    };
+    let proof = SMTAccumulator::<_, ()>::absence_proof(&snapshot, vec![unknown], 0).unwrap();
+    let decoded = AbsenceProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert_eq!(KeyDerivation::Prefixed, decoded.key_derivation());
+    assert!(decoded.verify(&prefixed).unwrap());
+
+    // the derivation of a database can't change after the first commit
+    let tx = db.transaction_default();
+    assert!(set_key_derivation::<_, ()>(&tx, KeyDerivation::Hashed).is_err());
+}
+
+#[test]
+fn test_shared_branch_storage() {
+    let out_points: Vec<_> = (0..6u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    let run = |storage| {
+        let (db, tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        set_branch_storage::<_, ()>(&tx, storage).unwrap();
+        let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+        let write_counts = Arc::new(WriteCounts::default());
+        accumulator.trace_keys(KeyTracer::default().with_write_counts(Arc::clone(&write_counts)));
+        let mut commitments = Vec::new();
+        // one call per cell, so that the branches of a block are written more than once
+        for block in [&out_points[0..4], &out_points[4..]] {
+            for out_point in block {
+                accumulator.add(vec![out_point.clone()]).unwrap();
+            }
+            commitments.push(accumulator.commit().unwrap().to_bytes());
+        }
+        // reverting the second block restores the nodes of the first one
+        let reverted = out_points[4..]
+            .iter()
+            .map(|out_point| (out_point.clone(), ZERO_CELL_STATUS))
+            .collect();
+        accumulator.update(reverted).unwrap();
+        commitments.push(accumulator.commit().unwrap().to_bytes());
+        tx.commit().unwrap();
+        let report = fsck(&db.snapshot()).unwrap();
+        assert!(report.is_ok(), "{}", report);
+        (commitments, write_counts.keys(), db, tmp_dir)
+    };
+
+    let (inline, inline_writes, _db, _tmp_dir) = run(BranchStorage::Inline);
+    let (shared, shared_writes, db, _tmp_dir) = run(BranchStorage::Shared);
+    assert_eq!(inline, shared);
+
+    let (mut nodes, mut references) = (0, 0);
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        if key.starts_with(b"BRANCHNODE") {
+            nodes += 1;
+        } else if value.len() == 32 {
+            references += 1;
+        }
+    }
+    assert!(
+        nodes < references,
+        "{} nodes, {} references",
+        nodes,
+        references
+    );
+    // the writes of the shared nodes and of their references are both counted
+    assert!(
+        shared_writes >= inline_writes + nodes,
+        "{} shared writes, {} inline writes, {} nodes",
+        shared_writes,
+        inline_writes,
+        nodes
+    );
+
+    // the storage of a database can't change after the first commit
+    let tx = db.transaction_default();
+    assert!(set_branch_storage::<_, ()>(&tx, BranchStorage::Inline).is_err());
+}
+
+#[test]
+fn test_build_tree() {
+    let leaves: Vec<([u8; 32], [u8; 32])> = (0..100u8)
+        .map(|i| {
+            let mut key = [0u8; 32];
+            let mut hasher = new_blake2b();
+            hasher.update(&[i]);
+            hasher.finalize(&mut key);
+            (key, [i + 1; 32])
+        })
+        .collect();
+    let mut expected = SparseMerkleTree::<
+        Blake2bHasher,
+        LeafHash,
+        sparse_merkle_tree::default_store::DefaultStore<LeafHash>,
+    >::default();
+    for (key, hash) in &leaves {
+        expected.update((*key).into(), LeafHash(*hash)).unwrap();
+    }
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    // no leaves is an error, not an empty tree
+    assert!(build_tree::<_, ()>(&tx, vec![]).is_err());
+    let root = build_tree::<_, ()>(&tx, leaves.clone()).unwrap();
+    assert_eq!(expected.root().as_slice(), root);
+    assert_eq!(1, DefaultStore::<_, ()>::new(&tx).sequence());
+    // only an empty database can be built
+    assert!(build_tree::<_, ()>(&tx, leaves.clone()).is_err());
+    // the leaves are stored with their whole value hash
+    let smt = SparseMerkleTree::<Blake2bHasher, LeafHash, _>::new_with_store(
+        DefaultStore::<_, ()>::new_with_sequence(&tx, 0),
+    )
+    .unwrap();
+    for (key, hash) in &leaves {
+        assert_eq!(LeafHash(*hash), smt.get(&(*key).into()).unwrap());
+    }
+
+    // the delta encoding can't store a value hash
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    set_leaf_encoding::<_, ()>(&tx, LeafEncoding::Delta).unwrap();
+    assert!(build_tree::<_, ()>(&tx, leaves).is_err());
+}
+
+#[test]
+fn test_branch_node_encoding() {
+    let value = |b: u8| MergeValue::Value([b; 32].into());
+    let merge_with_zero = |b: u8| MergeValue::MergeWithZero {
+        base_node: [b; 32].into(),
+        zero_bits: [b + 1; 32].into(),
+        zero_count: b + 2,
+    };
+    let shortcut = |b: u8| MergeValue::ShortCut {
+        key: [b; 32].into(),
+        value: [b + 1; 32].into(),
+        height: b + 2,
+    };
+    // the exact layout of each variant: the literal tag byte of format version 0, then the fields
+    // of the left and right values
+    let value_bytes = |b: u8| vec![b; 32];
+    let merge_with_zero_bytes = |b: u8| [vec![b; 32], vec![b + 1; 32], vec![b + 2]].concat();
+    let shortcut_bytes = |b: u8| [vec![b; 32], vec![b + 1; 32], vec![b + 2]].concat();
+    let cases = vec![
+        (
+            0x00,
+            value(1),
+            value(5),
+            65,
+            [value_bytes(1), value_bytes(5)].concat(),
+        ),
+        (
+            0x01,
+            value(1),
+            merge_with_zero(5),
+            98,
+            [value_bytes(1), merge_with_zero_bytes(5)].concat(),
+        ),
+        (
+            0x02,
+            merge_with_zero(1),
+            value(5),
+            98,
+            [merge_with_zero_bytes(1), value_bytes(5)].concat(),
+        ),
+        (
+            0x03,
+            merge_with_zero(1),
+            merge_with_zero(5),
+            131,
+            [merge_with_zero_bytes(1), merge_with_zero_bytes(5)].concat(),
+        ),
+        (
+            0x04,
+            value(1),
+            shortcut(5),
+            98,
+            [value_bytes(1), shortcut_bytes(5)].concat(),
+        ),
+        (
+            0x05,
+            shortcut(1),
+            value(5),
+            98,
+            [shortcut_bytes(1), value_bytes(5)].concat(),
+        ),
+        (
+            0x06,
+            shortcut(1),
+            shortcut(5),
+            131,
+            [shortcut_bytes(1), shortcut_bytes(5)].concat(),
+        ),
+        (
+            0x07,
+            merge_with_zero(1),
+            shortcut(5),
+            131,
+            [merge_with_zero_bytes(1), shortcut_bytes(5)].concat(),
+        ),
+        (
+            0x08,
+            shortcut(1),
+            merge_with_zero(5),
+            131,
+            [shortcut_bytes(1), merge_with_zero_bytes(5)].concat(),
+        ),
+    ];
+    for (tag, left, right, len, fields) in cases {
+        let node = BranchNode { left, right };
+        let encoded = branch_node_to_vec(&node);
+        let expected = [EMPTY_PREFIX, [tag].as_ref(), fields.as_slice()].concat();
+        assert_eq!(EMPTY_PREFIX.len() + len, encoded.len());
+        assert_eq!(expected, encoded);
+        assert_eq!(node, slice_to_branch_node(&encoded).unwrap());
+    }
+}
+
+#[test]
+fn test_branch_node_decoding_errors() {
+    let node = BranchNode {
+        left: MergeValue::Value([1u8; 32].into()),
+        right: MergeValue::Value([2u8; 32].into()),
+    };
+    let encoded = branch_node_to_vec(&node);
+    let tag_offset = EMPTY_PREFIX.len();
+
+    // truncated, down to an empty slice
+    for len in [0, tag_offset + 1, encoded.len() - 1] {
+        assert_eq!(
+            Err(SerdeError::InvalidLength {
+                expected: if len <= tag_offset {
+                    tag_offset + 1
+                } else {
+                    encoded.len()
+                },
+                actual: len,
+            }),
+            slice_to_branch_node(&encoded[..len])
+        );
+    }
+    let mut longer = encoded.clone();
+    longer.push(0);
+    assert!(matches!(
+        slice_to_branch_node(&longer),
+        Err(SerdeError::InvalidLength { .. })
+    ));
+
+    // garbage tags
+    let mut garbage = encoded.clone();
+    garbage[tag_offset] = (FORMAT_VERSION + 1) << 4;
+    assert_eq!(
+        Err(SerdeError::UnsupportedVersion(FORMAT_VERSION + 1)),
+        slice_to_branch_node(&garbage)
+    );
+    garbage[tag_offset] = FORMAT_VERSION << 4 | 9;
+    assert_eq!(
+        Err(SerdeError::UnknownVariant(9)),
+        slice_to_branch_node(&garbage)
+    );
+    // a valid tag with the length of another variant
+    garbage[tag_offset] = FORMAT_VERSION << 4 | 3;
+    assert!(matches!(
+        slice_to_branch_node(&garbage),
+        Err(SerdeError::InvalidLength { .. })
+    ));
+}
+
+#[test]
+fn test_script_index() {
+    let out_points: Vec<_> = (0..4u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    // two scripts, each locks the cells of an index parity
+    let lock_script_hash = |out_point: &OutPoint| [(out_point.index % 2) as u8; 32];
+    let blocks = vec![
+        BlockOps {
+            added: out_points[0..3].to_vec(),
+            deleted: vec![],
+        },
+        BlockOps {
+            added: out_points[3..].to_vec(),
+            deleted: vec![out_points[0].clone(), out_points[2].clone()],
+        },
+    ];
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut index = ScriptIndex::<_, ()>::new(&tx).unwrap();
+    for block in &blocks {
+        index.apply_block(block, lock_script_hash).unwrap();
+    }
+    // the script of the consumed cells has no live cells left
+    assert_eq!(ScriptCells::default(), index.script_cells([0; 32]).unwrap());
+    let root = *index.root();
+    tx.commit().unwrap();
+
+    // the index only depends on the live cells, not on the blocks which lead to them
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut index = ScriptIndex::<_, ()>::new(&tx).unwrap();
+    let live = BlockOps {
+        added: vec![out_points[3].clone(), out_points[1].clone()],
+        deleted: vec![],
+    };
+    assert_eq!(root, index.apply_block(&live, lock_script_hash).unwrap());
+}
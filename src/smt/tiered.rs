@@ -0,0 +1,700 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    time::Instant,
+};
+
+use super::{store::DefaultStore, ZERO_CELL_STATUS};
+use crate::{
+    commitment_digest, ct_eq,
+    element_index::IndexedElements,
+    keycodec::{hash_key, parse_hash_key},
+    live_cache::CacheLiveCells,
+    metrics::{CoalescedOps, FlushWrites, KeyTracer, LevelWrites, StageTiming, TraceKeys},
+    new_blake2b,
+    storage::traits::{ReadStore, WriteStore},
+    sweep::{SweepCells, Swept},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
+};
+use rocksdb::{Direction, IteratorMode};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, error::Error, traits::Value, CompiledMerkleProof, SparseMerkleTree,
+    H256,
+};
+
+/// Tags the commitment digests of this backend.
+pub const COMMITMENT_TAG: &[u8] = b"smt_tiered";
+
+/// Prefixes the index of the hot leaves in the hot database, `HOT_KEY || leaf key`, the leaves to move on the next fold.
+pub const HOT_KEY: &[u8] = b"HOT";
+
+/// The journal of the last fold in the hot database, `sequence (8) | count * (leaf key (32) | status (16))`, the
+/// sequence of the fold and the leaves it moved into the cold tree.
+pub const FOLD_KEY: &[u8] = b"FOLD";
+
+/// The database handles of a `TieredSMTAccumulator`, the hot tree and the index of its leaves are in `hot`, the cold
+/// tree in `cold`.
+#[derive(Clone, Copy)]
+pub struct TieredDBs<D> {
+    pub hot: D,
+    pub cold: D,
+    /// The hot tree is folded into the cold tree every `fold_interval` sequences.
+    pub fold_interval: u64,
+}
+
+/// A two-tier SMT accumulator: the cells written since the last fold are in a small hot tree, which is folded into the
+/// large cold tree every `fold_interval` sequences in a single batch update. The hot leaf of a cell overrides its cold
+/// leaf, and the commitment is `blake2b(hot_root || cold_root)`. The updates of the cold tree are amortized over the
+/// blocks of a fold, while a block only updates the shallow paths of the hot tree.
+///
+/// The trees are in separate databases, and the hot one is the commit point: `commit` commits the hot tree before the
+/// cold one, and the handles of transactions must be committed in the same order. A fold records the leaves it moves in
+/// a journal written with the hot tree, so that when the cold tree misses the last commit, e.g. after a crash between
+/// the two, opening the accumulator redoes that commit of the cold tree from the journal.
+pub struct TieredSMTAccumulator<D, WO> {
+    hot: SparseMerkleTree<Blake2bHasher, CellStatus, DefaultStore<D, WO>>,
+    cold: SparseMerkleTree<Blake2bHasher, CellStatus, DefaultStore<D, WO>>,
+    // the hot database, which also stores the index of the hot leaves
+    hot_db: D,
+    fold_interval: u64,
+    // When enabled, `add` rejects existing elements and `delete` rejects missing or dead elements.
+    strict: bool,
+    // Number of leaves moved into the cold tree by the folds since the accumulator was created.
+    folded_leaves: u64,
+    // See `CoalescedOps`.
+    coalesced_ops: u64,
+}
+
+impl<D, WO> TieredSMTAccumulator<D, WO>
+where
+    D: Deref + Clone,
+    D::Target: WriteStore<WO>,
+{
+    /// Opens the latest state, a cold tree a sequence behind the hot one is brought up to it first, see `recover`.
+    pub fn new_with_strict(dbs: TieredDBs<D>, strict: bool) -> Result<Self, AccumulatorError> {
+        assert!(dbs.fold_interval > 0, "the fold interval must be positive");
+        let hot_store = DefaultStore::new(dbs.hot.clone());
+        let cold_store = DefaultStore::new(dbs.cold);
+        let mut accumulator = TieredSMTAccumulator {
+            hot: SparseMerkleTree::new_with_store(hot_store)?,
+            cold: SparseMerkleTree::new_with_store(cold_store)?,
+            hot_db: dbs.hot,
+            fold_interval: dbs.fold_interval,
+            strict,
+            folded_leaves: 0,
+            coalesced_ops: 0,
+        };
+        accumulator.recover()?;
+        Ok(accumulator)
+    }
+
+    // the hot tree is committed first, a cold tree one sequence behind missed the last commit, which is redone with the
+    // leaves of the journal if it was the commit of a fold. The writes of the cold tree at its sequence are those of the
+    // same fold, the update rewrites them.
+    fn recover(&mut self) -> Result<(), AccumulatorError> {
+        let (hot, cold) = (self.hot.store().sequence(), self.cold.store().sequence());
+        if hot == cold {
+            return Ok(());
+        }
+        if hot != cold + 1 {
+            return Err(AccumulatorError::InternalError(format!(
+                "the hot tree is at sequence {} and the cold tree at sequence {}",
+                hot, cold
+            )));
+        }
+        if let Some((sequence, leaves)) = self.fold_journal()? {
+            if sequence == cold {
+                self.cold.update_all(leaves)?;
+            }
+        }
+        self.cold.store_mut().commit()?;
+        Ok(())
+    }
+}
+
+impl<D, WO> TieredSMTAccumulator<D, WO>
+where
+    D: Deref + Clone,
+    D::Target: ReadStore,
+{
+    /// Opens both trees at a committed sequence for historical reads, see `DefaultStore::new_with_sequence`.
+    pub fn new_with_sequence(dbs: TieredDBs<D>, sequence: u64) -> Result<Self, Error> {
+        let hot_store = DefaultStore::new_with_sequence(dbs.hot.clone(), sequence);
+        let cold_store = DefaultStore::new_with_sequence(dbs.cold, sequence);
+        Ok(TieredSMTAccumulator {
+            hot: SparseMerkleTree::new_with_store(hot_store)?,
+            cold: SparseMerkleTree::new_with_store(cold_store)?,
+            hot_db: dbs.hot,
+            fold_interval: dbs.fold_interval,
+            strict: false,
+            folded_leaves: 0,
+            coalesced_ops: 0,
+        })
+    }
+
+    /// Generates a single proof that all the given out_points are live at the given sequence, see
+    /// `SMTAccumulator::wallet_proof`.
+    pub fn wallet_proof(
+        dbs: TieredDBs<D>,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<TieredWalletProof, AccumulatorError> {
+        Self::new_with_sequence(dbs, sequence)?.prove_live(out_points)
+    }
+}
+
+impl<D, WO> TieredSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    /// Returns the commitment of the state seen by this accumulator.
+    pub fn commitment(&self) -> TieredCommitment {
+        TieredCommitment {
+            hot_root: *self.hot.root(),
+            cold_root: *self.cold.root(),
+            sequence: self.hot.store().sequence(),
+        }
+    }
+
+    /// Returns the status of an element, `None` if the element doesn't exist.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        let status = self.status(&out_point.hash().into())?;
+        Ok(Some(status).filter(|status| *status != ZERO_CELL_STATUS))
+    }
+
+    pub fn folded_leaves(&self) -> u64 {
+        self.folded_leaves
+    }
+
+    /// Generates the `wallet_proof` of the given out_points at the sequence of this accumulator.
+    pub fn prove_live(
+        &self,
+        out_points: Vec<OutPoint>,
+    ) -> Result<TieredWalletProof, AccumulatorError> {
+        let mut elements = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let status = self.status(&out_point.hash().into())?;
+            if !status.is_live() {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            elements.push((out_point, status));
+        }
+        elements.sort_unstable_by_key(|(out_point, _)| out_point.hash());
+        elements.dedup_by_key(|(out_point, _)| out_point.hash());
+        let keys = elements
+            .iter()
+            .map(|(out_point, _)| out_point.hash().into())
+            .collect();
+        Ok(TieredWalletProof {
+            sequence: self.hot.store().sequence(),
+            proof: self.prove_leaves(keys)?,
+            elements,
+        })
+    }
+
+    // proves the leaves of the keys, which all have a leaf in one of the trees: the hot leaf of every key, and the cold
+    // leaf of the keys without a hot leaf
+    fn prove_leaves(&self, mut keys: Vec<H256>) -> Result<TieredProof, AccumulatorError> {
+        keys.sort_unstable();
+        keys.dedup();
+        let mut cold_keys = Vec::new();
+        for key in &keys {
+            if self.hot.get(key)? == ZERO_CELL_STATUS {
+                cold_keys.push(*key);
+            }
+        }
+        let hot = self.hot.merkle_proof(keys.clone())?.compile(keys)?;
+        let cold = if cold_keys.is_empty() {
+            None
+        } else {
+            Some(
+                self.cold
+                    .merkle_proof(cold_keys.clone())?
+                    .compile(cold_keys.clone())?,
+            )
+        };
+        Ok(TieredProof {
+            hot,
+            cold,
+            cold_keys,
+        })
+    }
+
+    // the sequence and the leaves of the last fold
+    fn fold_journal(&self) -> Result<Option<(u64, Vec<(H256, CellStatus)>)>, AccumulatorError> {
+        let journal = match self
+            .hot_db
+            .get(FOLD_KEY)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?
+        {
+            Some(journal) => journal,
+            None => return Ok(None),
+        };
+        let journal: &[u8] = journal.as_ref();
+        if journal.len() < 8 || (journal.len() - 8) % 48 != 0 {
+            return Err(AccumulatorError::InternalError(
+                "malformed fold journal".to_string(),
+            ));
+        }
+        let sequence = u64::from_le_bytes(journal[..8].try_into().expect("checked length"));
+        let leaves = journal[8..]
+            .chunks_exact(48)
+            .map(|chunk| {
+                let key: [u8; 32] = chunk[..32].try_into().expect("checked length");
+                let block_numbers = chunk[32..].try_into().expect("checked length");
+                (key.into(), CellStatus { block_numbers })
+            })
+            .collect();
+        Ok(Some((sequence, leaves)))
+    }
+
+    // the hot leaf of a key if it's set, its cold leaf otherwise
+    fn status(&self, key: &H256) -> Result<CellStatus, AccumulatorError> {
+        let status = self.hot.get(key)?;
+        if status != ZERO_CELL_STATUS {
+            return Ok(status);
+        }
+        Ok(self.cold.get(key)?)
+    }
+
+    // appends the leaves of the added elements to `leaves`, the `transient` ones are consumed in the same sequence
+    fn added_leaves(
+        &self,
+        elements: Vec<OutPoint>,
+        transient: &HashSet<OutPoint>,
+        leaves: &mut Vec<(H256, CellStatus)>,
+    ) -> Result<(), AccumulatorError> {
+        let mut keys = HashSet::with_capacity(elements.len());
+        let sequence = self.hot.store().sequence();
+        for (i, out_point) in elements.iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            if self.strict && (self.status(&key)? != ZERO_CELL_STATUS || !keys.insert(key)) {
+                return Err(AccumulatorError::AlreadyExists(i));
+            }
+            let status = if transient.contains(out_point) {
+                CellStatus::new_dead(sequence, sequence)
+            } else {
+                CellStatus::new_live(sequence)
+            };
+            leaves.push((key, status));
+        }
+        Ok(())
+    }
+
+    // appends the leaves of the deleted elements to `leaves`, an element may be one of the pending leaves, its dead
+    // status is appended after it since `update_all` keeps the last value of a key
+    fn deleted_leaves(
+        &self,
+        elements: Vec<OutPoint>,
+        leaves: &mut Vec<(H256, CellStatus)>,
+    ) -> Result<(), AccumulatorError> {
+        let mut keys = HashSet::new();
+        let sequence = self.hot.store().sequence();
+        let pending: HashMap<H256, CellStatus> = leaves.iter().cloned().collect();
+        for (i, out_point) in elements.iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            let mut status = match pending.get(&key) {
+                Some(status) => status.clone(),
+                None => self.status(&key)?,
+            };
+            if status == ZERO_CELL_STATUS
+                || (self.strict && (!status.is_live() || !keys.insert(key)))
+            {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            status.mark_as_dead(sequence);
+            leaves.push((key, status));
+        }
+        Ok(())
+    }
+}
+
+impl<D, WO> TieredSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    /// Moves the hot leaves into the cold tree in a single update and empties the hot tree, returns the number of moved
+    /// leaves. The moved leaves are recorded in the journal of the fold, see `FOLD_KEY`. `commit` folds every
+    /// `fold_interval` sequences.
+    pub fn fold(&mut self) -> Result<usize, AccumulatorError> {
+        let keys: Vec<H256> = self
+            .hot_db
+            .iterator(IteratorMode::From(HOT_KEY, Direction::Forward))
+            .take_while(|(key, _)| key.starts_with(HOT_KEY))
+            // a versioned leaf key of the hot tree may also start with the prefix
+            .filter_map(|(key, _)| parse_hash_key(HOT_KEY, &key))
+            .map(H256::from)
+            .collect();
+        let mut leaves = Vec::with_capacity(keys.len());
+        let mut journal = Vec::with_capacity(8 + keys.len() * 48);
+        journal.extend_from_slice(&self.hot.store().sequence().to_le_bytes());
+        for key in &keys {
+            let status = self.hot.get(key)?;
+            journal.extend_from_slice(key.as_slice());
+            journal.extend_from_slice(&status.block_numbers);
+            leaves.push((*key, status));
+        }
+        self.hot_db
+            .put(FOLD_KEY, journal)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        self.cold.update_all(leaves)?;
+        self.hot
+            .update_all(keys.iter().map(|key| (*key, ZERO_CELL_STATUS)).collect())?;
+        for key in &keys {
+            self.hot_db
+                .delete(hot_key(key))
+                .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        }
+        self.folded_leaves += keys.len() as u64;
+        Ok(keys.len())
+    }
+
+    // writes the leaves to the hot tree and records them in the index of the hot leaves
+    fn update_hot(&mut self, leaves: Vec<(H256, CellStatus)>) -> Result<(), AccumulatorError> {
+        for (key, _) in &leaves {
+            self.hot_db
+                .put(hot_key(key), [])
+                .map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+        }
+        self.hot.update_all(leaves)?;
+        Ok(())
+    }
+}
+
+impl<D, WO> AccumulatorWriter for TieredSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    type Item = OutPoint;
+    type Commitment = TieredCommitment;
+
+    fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.added_leaves(elements, &HashSet::new(), &mut leaves)?;
+        self.update_hot(leaves)
+    }
+
+    fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.deleted_leaves(elements, &mut leaves)?;
+        self.update_hot(leaves)
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        for (i, (out_point, status)) in elements.into_iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            if self.status(&key)? == ZERO_CELL_STATUS {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            leaves.push((key, status));
+        }
+        self.update_hot(leaves)
+    }
+
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError> {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // the adds and deletes of a block are written in a single update of the hot tree, a cell consumed in the
+            // block it's added in is written once with its dead status
+            let transient = block.take_transient();
+            self.coalesced_ops += transient.len() as u64;
+            let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+            self.added_leaves(block.added, &transient, &mut leaves)?;
+            self.deleted_leaves(block.deleted, &mut leaves)?;
+            self.update_hot(leaves)?;
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
+    // the hot tree is committed first, see `recover`
+    fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let sequence = self.hot.store().sequence();
+        if sequence % self.fold_interval == self.fold_interval - 1 {
+            self.fold()?;
+        }
+        let commitment = self.commitment();
+        self.hot.store_mut().commit()?;
+        self.cold.store_mut().commit()?;
+        Ok(commitment)
+    }
+}
+
+impl<D, WO> AccumulatorReader for TieredSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    type Item = OutPoint;
+    type Commitment = TieredCommitment;
+    type Proof = TieredProof;
+
+    /// Proves the status of cells which exist at the sequence of this accumulator, live or consumed, from the tree
+    /// holding their leaf. Only a cell which was never added fails with `ElementNotFound`.
+    fn proof(
+        &self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<Self::Proof, AccumulatorError> {
+        if commitment.hot_root != *self.hot.root() || commitment.cold_root != *self.cold.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+
+        let mut keys = Vec::with_capacity(elements.len());
+        for (i, out_point) in elements.iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            if self.status(&key)? == ZERO_CELL_STATUS {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            keys.push(key);
+        }
+        self.prove_leaves(keys)
+    }
+}
+
+// the swept cells are consumed in the hot tree, like the cells of a block
+impl<D, WO> SweepCells for TieredSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    // the hot leaves are scanned, then the cold leaves of the cells without a hot leaf, the predicate is given their
+    // key, which is the out_point hash
+    fn sweep<F>(&mut self, created_before: u64, mut predicate: F) -> Result<Swept, AccumulatorError>
+    where
+        F: FnMut(&[u8; 32], &CellStatus) -> bool,
+    {
+        let start = Instant::now();
+        let sequence = self.hot.store().sequence();
+        let hot: Vec<(H256, CellStatus)> = self
+            .hot
+            .store()
+            .leaves()
+            .into_iter()
+            .filter(|(_, status)| *status != ZERO_CELL_STATUS)
+            .collect();
+        let hot_keys: HashSet<H256> = hot.iter().map(|(key, _)| *key).collect();
+        let cold = self
+            .cold
+            .store()
+            .leaves()
+            .into_iter()
+            .filter(|(key, _)| !hot_keys.contains(key));
+        let mut leaves = Vec::new();
+        for (key, mut status) in hot.into_iter().chain(cold) {
+            let hash: [u8; 32] = key.as_slice().try_into().expect("checked length");
+            if status.is_live() && status.created_by() < created_before && predicate(&hash, &status)
+            {
+                status.mark_as_dead(sequence);
+                leaves.push((key, status));
+            }
+        }
+        let scan = start.elapsed();
+        let start = Instant::now();
+        let cells = leaves.len() as u64;
+        self.update_hot(leaves)?;
+        Ok(Swept {
+            cells,
+            scan,
+            update: start.elapsed(),
+        })
+    }
+}
+
+impl<D, WO> CoalescedOps for TieredSMTAccumulator<D, WO> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        Some(self.coalesced_ops)
+    }
+}
+
+impl<D, WO> LevelWrites for TieredSMTAccumulator<D, WO> {}
+
+impl<D, WO> FlushWrites for TieredSMTAccumulator<D, WO> {}
+
+impl<D, WO> StageTiming for TieredSMTAccumulator<D, WO> {}
+
+// the leaves hold the statuses, there is no element index
+impl<D, WO> IndexedElements for TieredSMTAccumulator<D, WO> {}
+
+impl<D, WO> CacheLiveCells for TieredSMTAccumulator<D, WO> {}
+
+// the writes of both trees are traced, the index of the hot leaves isn't
+impl<D, WO> TraceKeys for TieredSMTAccumulator<D, WO> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.hot.store_mut().set_key_tracer(tracer.clone());
+        self.cold.store_mut().set_key_tracer(tracer);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TieredCommitment {
+    hot_root: H256,
+    cold_root: H256,
+    sequence: u64,
+}
+
+impl TieredCommitment {
+    pub fn hot_root(&self) -> &H256 {
+        &self.hot_root
+    }
+
+    pub fn cold_root(&self) -> &H256 {
+        &self.cold_root
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The combined root, blake2b(hot_root || cold_root).
+    pub fn root(&self) -> [u8; 32] {
+        let mut root = [0u8; 32];
+        let mut hasher = new_blake2b();
+        hasher.update(self.hot_root.as_slice());
+        hasher.update(self.cold_root.as_slice());
+        hasher.finalize(&mut root);
+        root
+    }
+
+    /// See `crate::commitment_digest`, tagged with `COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        commitment_digest(COMMITMENT_TAG, &self.root(), self.sequence)
+    }
+
+    /// Serializes the commitment as hot_root || cold_root || sequence as u64 LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.hot_root.as_slice(),
+            self.cold_root.as_slice(),
+            self.sequence.to_le_bytes().as_ref(),
+        ]
+        .concat()
+    }
+}
+
+/// A proof of the statuses of cells at a `TieredCommitment`: the hot leaf of every cell, which is empty for the cells
+/// proved by their cold leaf, and the cold leaf of those, see `TieredSMTAccumulator::proof`.
+pub struct TieredProof {
+    hot: CompiledMerkleProof,
+    // `None` when every cell is proved by its hot leaf
+    cold: Option<CompiledMerkleProof>,
+    // the sorted keys of the cells proved by their cold leaf
+    cold_keys: Vec<H256>,
+}
+
+impl TieredProof {
+    fn verify_leaves(
+        &self,
+        commitment: &TieredCommitment,
+        elements: &[(OutPoint, CellStatus)],
+    ) -> Result<bool, AccumulatorError> {
+        let mut elements: Vec<(H256, H256)> = elements
+            .iter()
+            .map(|(out_point, status)| (out_point.hash().into(), status.to_h256()))
+            .collect();
+        elements.sort_unstable_by_key(|(key, _)| *key);
+        elements.dedup_by_key(|(key, _)| *key);
+        let mut hot_leaves = Vec::with_capacity(elements.len());
+        let mut cold_leaves = Vec::new();
+        for (key, value) in elements {
+            if self.cold_keys.binary_search(&key).is_ok() {
+                hot_leaves.push((key, H256::zero()));
+                cold_leaves.push((key, value));
+            } else {
+                hot_leaves.push((key, value));
+            }
+        }
+        let hot_root = self.hot.compute_root::<Blake2bHasher>(hot_leaves)?;
+        let cold_proved = match &self.cold {
+            Some(cold) => {
+                let cold_root = cold.compute_root::<Blake2bHasher>(cold_leaves)?;
+                ct_eq(cold_root.as_slice(), commitment.cold_root.as_slice())
+            }
+            None => cold_leaves.is_empty(),
+        };
+        Ok(ct_eq(hot_root.as_slice(), commitment.hot_root.as_slice()) & cold_proved)
+    }
+
+    // `cold proof length (4) | cold key count (4) | cold keys | hot proof | cold proof`
+    fn to_bytes(&self) -> Vec<u8> {
+        let cold = self.cold.as_ref().map_or(&[][..], |cold| &cold.0[..]);
+        let mut ret =
+            Vec::with_capacity(8 + self.cold_keys.len() * 32 + self.hot.0.len() + cold.len());
+        ret.extend_from_slice(&(cold.len() as u32).to_le_bytes());
+        ret.extend_from_slice(&(self.cold_keys.len() as u32).to_le_bytes());
+        for key in &self.cold_keys {
+            ret.extend_from_slice(key.as_slice());
+        }
+        ret.extend_from_slice(&self.hot.0);
+        ret.extend_from_slice(cold);
+        ret
+    }
+}
+
+impl Proof for TieredProof {
+    type Item = (OutPoint, CellStatus);
+
+    type Commitment = TieredCommitment;
+
+    fn verify(
+        self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<bool, AccumulatorError> {
+        self.verify_leaves(&commitment, &elements)
+    }
+}
+
+/// A proof of many live cells at one commitment, see `TieredSMTAccumulator::wallet_proof`.
+pub struct TieredWalletProof {
+    sequence: u64,
+    elements: Vec<(OutPoint, CellStatus)>,
+    proof: TieredProof,
+}
+
+impl TieredWalletProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        &self.elements
+    }
+
+    pub fn verify(&self, commitment: &TieredCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence
+            || !self.elements.iter().all(|(_, status)| status.is_live())
+        {
+            return Ok(false);
+        }
+        self.proof.verify_leaves(commitment, &self.elements)
+    }
+
+    /// Serializes the proof as `sequence (8) | count (4) | count * (tx_hash (32) | index (4) | status (16)) | cold proof
+    /// length (4) | cold key count (4) | cold keys | hot proof | cold proof`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(12 + self.elements.len() * 52);
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.elements.len() as u32).to_le_bytes());
+        for (out_point, status) in &self.elements {
+            ret.extend_from_slice(&out_point.to_bytes());
+            ret.extend_from_slice(&status.block_numbers);
+        }
+        ret.extend_from_slice(&self.proof.to_bytes());
+        ret
+    }
+}
+
+fn hot_key(key: &H256) -> Vec<u8> {
+    hash_key(HOT_KEY, key.as_slice())
+}
@@ -1,105 +1,541 @@
-use super::store::{DefaultStore, ELEMENT_KEY};
-use crate::{
-    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockNumber, CellStatus, OutPoint,
-    Proof,
+use super::{
+    element_index,
+    store::{DefaultStore, ELEMENT_KEY},
 };
-use rocksdb::{
-    prelude::{Delete, Get, Iterate, Put},
-    ReadOptions,
+use crate::{
+    commitment_digest, ct_eq, debug_commitment,
+    element_index::{ElementIndex, ElementIndexStats, ElementRecord, IndexedElements},
+    keycodec::parse_element_key,
+    live_cache::CacheLiveCells,
+    metrics::{
+        CoalescedOps, FlushWrites, KeyTracer, LevelHistogram, LevelWrites, Stage, StageTimes,
+        StageTiming, TraceKeys,
+    },
+    overlay::SMTOverlayStore,
+    smt::accumulator::proof_from_bytes,
+    storage::traits::{ReadStore, WriteStore},
+    sweep::{SweepCells, Swept},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockNumber, BlockOps, CellStatus,
+    OutPoint, Proof,
 };
 use sparse_merkle_tree::{
-    blake2b::Blake2bHasher, error::Error, traits::Value, MerkleProof, SparseMerkleTree, H256,
+    blake2b::Blake2bHasher, error::Error, traits::Value, CompiledMerkleProof, MerkleProof,
+    SparseMerkleTree, H256,
 };
+use std::{collections::HashSet, fmt, ops::Deref, sync::Arc, time::Instant};
 
-pub struct SMTAccumulator<'a, DB, WO> {
-    smt: SparseMerkleTree<Blake2bHasher, BlockNumber, DefaultStore<'a, DB, WO>>,
+/// Tags the commitment digests of this backend.
+pub const COMMITMENT_TAG: &[u8] = b"smt_live";
+
+/// The accumulator over a handle which dereferences to the RocksDB database, see `SMTAccumulator` and `SMTAccumulatorOwned`.
+pub struct GenericSMTAccumulator<D, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, BlockNumber, DefaultStore<D, WO>>,
+    // When enabled, `add` rejects existing elements and `delete` rejects missing or dead elements.
+    strict: bool,
+    // The maximum number of leaves of a single tree update, larger updates are split, see `set_update_chunk_size`.
+    update_chunk_size: Option<usize>,
+    stage_times: StageTimes,
+    // See `CoalescedOps`.
+    coalesced_ops: u64,
+    // The created-by and consumed-by sequences of the elements, the leaves only keep the created-by sequence of the
+    // live ones.
+    element_index: ElementIndex,
 }
 
-impl<'a, DB, WO> SMTAccumulator<'a, DB, WO>
+/// The accumulator borrowing a RocksDB database, transaction or snapshot.
+pub type SMTAccumulator<'a, DB, WO> = GenericSMTAccumulator<&'a DB, WO>;
+
+/// The accumulator sharing ownership of the RocksDB database, so that it can be held by long-lived services or moved across threads.
+pub type SMTAccumulatorOwned<DB, WO> = GenericSMTAccumulator<Arc<DB>, WO>;
+
+impl<D, WO> GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions> + Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
-    pub fn new(db: &'a DB) -> Result<Self, Error> {
+    pub fn new(db: D) -> Result<Self, Error> {
+        Self::new_with_strict(db, false)
+    }
+
+    pub fn new_with_strict(db: D, strict: bool) -> Result<Self, Error> {
         let store = DefaultStore::new(db);
         let smt = SparseMerkleTree::new_with_store(store)?;
-        Ok(SMTAccumulator { smt })
+        Ok(GenericSMTAccumulator {
+            smt,
+            strict,
+            update_chunk_size: None,
+            stage_times: StageTimes::default(),
+            coalesced_ops: 0,
+            element_index: element_index(),
+        })
+    }
+
+    /// Splits the tree updates of `add`, `delete` and `apply_blocks` into chunks of at most `chunk_size` leaves, see
+    /// `smt::accumulator::GenericSMTAccumulator::set_update_chunk_size`.
+    pub fn set_update_chunk_size(&mut self, chunk_size: Option<usize>) {
+        assert!(chunk_size != Some(0), "update chunk size must be positive");
+        self.update_chunk_size = chunk_size;
     }
 }
 
-impl<'a, DB, WO> AccumulatorWriter for SMTAccumulator<'a, DB, WO>
+impl<D, WO> AccumulatorWriter for GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions> + Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
 
     fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element exists already, caller should make sure the element is unique
-        let store = self.smt.store_mut();
-        let sequence = store.sequence();
         let mut leaves = Vec::with_capacity(elements.len());
-        for out_point in elements {
-            let key = [
-                ELEMENT_KEY,
-                out_point.tx_hash.as_ref(),
-                out_point.index.to_le_bytes().as_ref(),
-            ]
-            .concat();
-            store.put_raw(key.as_ref(), sequence.to_le_bytes().as_ref())?;
-            leaves.push((out_point.hash().into(), BlockNumber(sequence.to_le_bytes())));
-        }
-
-        self.smt.update_all(leaves)?;
+        self.added_leaves(elements, &HashSet::new(), &mut leaves)?;
+        self.update_leaves(leaves)?;
         Ok(())
     }
 
     fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
-        // we don't check if the element has been deleted already, caller should make sure the element is deleted only once
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.deleted_leaves(elements, &mut leaves)?;
+        self.update_leaves(leaves)?;
+        Ok(())
+    }
+
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        let element_index = &self.element_index;
         let store = self.smt.store_mut();
-        let sequence = store.sequence();
-        let mut kvs = Vec::with_capacity(elements.len());
-        for (i, out_point) in elements.iter().enumerate() {
-            let key = [
-                ELEMENT_KEY,
-                out_point.tx_hash.as_ref(),
-                out_point.index.to_le_bytes().as_ref(),
-            ]
-            .concat();
-            if let Some(mut stored_sequences) = store.get_raw(&key.as_ref()) {
-                stored_sequences.extend_from_slice(sequence.to_le_bytes().as_slice());
-                store.put_raw(key.as_ref(), stored_sequences.as_slice())?;
-                kvs.push((out_point.hash().into(), BlockNumber::zero()));
-            } else {
+        let mut leaves = Vec::with_capacity(elements.len());
+        for (i, (out_point, status)) in elements.into_iter().enumerate() {
+            let key = element_index.key(&out_point);
+            if element_index.get(&*store, &key)?.is_none() {
                 return Err(AccumulatorError::ElementNotFound(i));
             }
+            // the element record keeps the consumed-by sequence of dead elements only, which have a zero leaf
+            let record = ElementRecord { pos: None, status };
+            element_index.put(&mut *store, &key, &record)?;
+            if record.status.is_live() {
+                leaves.push((
+                    out_point.hash().into(),
+                    BlockNumber(record.created_by().to_le_bytes()),
+                ));
+            } else {
+                leaves.push((out_point.hash().into(), BlockNumber::zero()));
+            }
         }
-
-        self.smt.update_all(kvs)?;
+        self.update_leaves(leaves)?;
         Ok(())
     }
 
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError> {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // the adds and deletes of a block are written in a single tree update, a cell consumed in the block it's
+            // added in only gets its element record, its leaf stays zero
+            let transient = block.take_transient();
+            self.coalesced_ops += transient.len() as u64;
+            let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+            self.added_leaves(block.added, &transient, &mut leaves)?;
+            self.deleted_leaves(block.deleted, &mut leaves)?;
+            self.update_leaves(leaves)?;
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
     fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let start = Instant::now();
         let root = *self.smt.root();
         let sequence = self.smt.store().sequence();
         self.smt.store_mut().commit()?;
+        self.stage_times.record(Stage::TreeCommit, start.elapsed());
         Ok(AccumulatorCommitment { root, sequence })
     }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        Ok(self.smt.store_mut().skip_to(sequence)?)
+    }
+}
+
+impl<D, WO> GenericSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    // writes the leaves to the tree, in chunks of `update_chunk_size` if set, the chunks keep the order of the leaves so
+    // the last value of a key still wins
+    fn update_leaves(&mut self, leaves: Vec<(H256, BlockNumber)>) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        match self.update_chunk_size {
+            Some(chunk_size) if leaves.len() > chunk_size => {
+                let mut leaves = leaves.into_iter().peekable();
+                while leaves.peek().is_some() {
+                    self.smt
+                        .update_all(leaves.by_ref().take(chunk_size).collect())?;
+                }
+            }
+            _ => {
+                self.smt.update_all(leaves)?;
+            }
+        }
+        self.stage_times.record(Stage::TreeUpdate, start.elapsed());
+        Ok(())
+    }
+
+    // writes the element records of the added elements and appends their leaves to `leaves`, which are pending to be written to the tree,
+    // the `transient` elements are consumed in the same sequence, they are recorded as dead and have no leaf
+    fn added_leaves(
+        &mut self,
+        elements: Vec<OutPoint>,
+        transient: &HashSet<OutPoint>,
+        leaves: &mut Vec<(H256, BlockNumber)>,
+    ) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        let hashes: Vec<H256> = elements
+            .iter()
+            .map(|out_point| out_point.hash().into())
+            .collect();
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+
+        // in non-strict mode we don't check if the element exists already, caller should make sure the element is unique
+        let start = Instant::now();
+        let element_index = &self.element_index;
+        let store = self.smt.store_mut();
+        if self.strict {
+            let mut out_points = HashSet::with_capacity(elements.len());
+            for (i, out_point) in elements.iter().enumerate() {
+                let key = element_index.key(out_point);
+                if element_index.get(&*store, &key)?.is_some() || !out_points.insert(out_point) {
+                    return Err(AccumulatorError::AlreadyExists(i));
+                }
+            }
+        }
+
+        let sequence = store.sequence();
+        for (out_point, hash) in elements.into_iter().zip(hashes) {
+            let key = element_index.key(&out_point);
+            let status = if transient.contains(&out_point) {
+                CellStatus::new_dead(sequence, sequence)
+            } else {
+                leaves.push((hash, BlockNumber(sequence.to_le_bytes())));
+                CellStatus::new_live(sequence)
+            };
+            element_index.put(&mut *store, &key, &ElementRecord { pos: None, status })?;
+        }
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+        Ok(())
+    }
+
+    // updates the element records of the deleted elements and appends their leaves to `leaves`, an element may be one of the
+    // pending leaves, its element record is already written and its zero leaf is appended after it since `update_all` keeps the last value of a key.
+    // Every element is checked before any record is written, so a rejected delete doesn't tombstone the elements before it
+    fn deleted_leaves(
+        &mut self,
+        elements: Vec<OutPoint>,
+        leaves: &mut Vec<(H256, BlockNumber)>,
+    ) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        let hashes: Vec<H256> = elements
+            .iter()
+            .map(|out_point| out_point.hash().into())
+            .collect();
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+
+        // in non-strict mode we don't check if the element has been deleted already, caller should make sure the element is deleted only once
+        let start = Instant::now();
+        let strict = self.strict;
+        let element_index = &self.element_index;
+        let store = self.smt.store_mut();
+        let sequence = store.sequence();
+        let mut consumed = HashSet::with_capacity(elements.len());
+        let mut records = Vec::with_capacity(elements.len());
+        let keys: Vec<_> = elements
+            .iter()
+            .map(|out_point| element_index.key(out_point))
+            .collect();
+        let stored = element_index.multi_get(&*store, &keys)?;
+        for (i, ((out_point, key), record)) in elements.iter().zip(keys).zip(stored).enumerate() {
+            let mut record = match record {
+                Some(record) => record,
+                None => return Err(AccumulatorError::ElementNotFound(i)),
+            };
+            // an element deleted twice in the batch is consumed by its first delete
+            if strict && !consumed.insert(out_point) {
+                record.status.mark_as_dead(sequence);
+            }
+            if strict && !record.status.is_live() {
+                return Err(AccumulatorError::AlreadyConsumed(i, record.status));
+            }
+            record.status.mark_as_dead(sequence);
+            records.push((key, record));
+        }
+        for ((key, record), hash) in records.into_iter().zip(hashes) {
+            element_index.put(&mut *store, &key, &record)?;
+            leaves.push((hash, BlockNumber::zero()));
+        }
+        self.stage_times
+            .record(Stage::ElementIndex, start.elapsed());
+        Ok(())
+    }
 }
 
-impl<'a, DB, WO> SMTAccumulator<'a, DB, WO>
+impl<D, WO> GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
 {
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Result<Self, Error> {
+    pub fn new_with_sequence(db: D, sequence: u64) -> Result<Self, Error> {
         let store = DefaultStore::new_with_sequence(db, sequence);
         let smt = SparseMerkleTree::new_with_store(store)?;
-        Ok(SMTAccumulator { smt })
+        Ok(GenericSMTAccumulator {
+            smt,
+            strict: false,
+            update_chunk_size: None,
+            stage_times: StageTimes::default(),
+            coalesced_ops: 0,
+            element_index: element_index(),
+        })
+    }
+
+    /// Returns the commitment of the state seen by this accumulator, i.e. of the sequence it was opened with.
+    pub fn commitment(&self) -> AccumulatorCommitment {
+        AccumulatorCommitment {
+            root: *self.smt.root(),
+            sequence: self.smt.store().sequence(),
+        }
+    }
+
+    /// Returns the commitment that `commit` would return after applying `block` on top of the writes of this
+    /// accumulator, without persisting anything: the element records are only read, and the tree is updated on a
+    /// `SMTOverlayStore` which is dropped. Strict mode errors of the block are reported here.
+    pub fn preview_root(
+        &self,
+        mut block: BlockOps,
+    ) -> Result<AccumulatorCommitment, AccumulatorError> {
+        // a cell consumed in the block it's added in keeps a zero leaf, like in `apply_blocks`
+        let transient = block.take_transient();
+        let store = self.smt.store();
+        let sequence = store.sequence();
+        let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+        let mut out_points = HashSet::with_capacity(block.added.len());
+        for (i, out_point) in block.added.iter().enumerate() {
+            let key = self.element_index.key(out_point);
+            if self.strict
+                && (self.element_index.get(store, &key)?.is_some() || !out_points.insert(out_point))
+            {
+                return Err(AccumulatorError::AlreadyExists(i));
+            }
+            if !transient.contains(out_point) {
+                leaves.push((out_point.hash().into(), BlockNumber(sequence.to_le_bytes())));
+            }
+        }
+        let mut consumed = HashSet::with_capacity(block.deleted.len());
+        for (i, out_point) in block.deleted.iter().enumerate() {
+            let key = self.element_index.key(out_point);
+            match self.element_index.get(store, &key)? {
+                None => return Err(AccumulatorError::ElementNotFound(i)),
+                Some(mut record)
+                    if self.strict && (!record.status.is_live() || !consumed.insert(out_point)) =>
+                {
+                    if record.status.is_live() {
+                        record.status.mark_as_dead(sequence);
+                    }
+                    return Err(AccumulatorError::AlreadyConsumed(i, record.status));
+                }
+                Some(_) => leaves.push((out_point.hash().into(), BlockNumber::zero())),
+            }
+        }
+        let mut smt = SparseMerkleTree::<Blake2bHasher, BlockNumber, _>::new(
+            *self.smt.root(),
+            SMTOverlayStore::new(store),
+        );
+        smt.update_all(leaves)?;
+        Ok(AccumulatorCommitment {
+            root: *smt.root(),
+            sequence,
+        })
+    }
+
+    /// Recovers the status of an element from its element record, as seen at the sequence of this accumulator,
+    /// returns `None` if the element doesn't exist yet.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        let key = self.element_index.key(out_point);
+        let sequence = self.smt.store().sequence();
+        // the unversioned records of an older database may have been written after the sequence
+        Ok(self
+            .element_index
+            .get(self.smt.store(), &key)?
+            .and_then(|record| record.status_at(sequence)))
+    }
+
+    /// Generates a single multi-proof that all the given out_points are live at the given sequence,
+    /// which is the typical query of a wallet proving its whole set of cells to a light client.
+    pub fn wallet_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<WalletProof, AccumulatorError> {
+        let accumulator = Self::new_with_sequence(db, sequence)?;
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            // only live cells are kept in the tree, a dead or unknown cell reads as zero
+            let created_by = accumulator.smt.get(&key)?;
+            if created_by == BlockNumber::zero() {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            leaves.push((key, out_point, created_by));
+        }
+
+        // unrelated keys are proved in one pass over the tree when they are sorted
+        leaves.sort_unstable_by_key(|(key, _, _)| *key);
+        leaves.dedup_by_key(|(key, _, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _, _)| *key).collect();
+        let proof = accumulator.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(WalletProof {
+            sequence,
+            elements: leaves
+                .into_iter()
+                .map(|(_, out_point, created_by)| {
+                    (
+                        out_point,
+                        CellStatus::new_live(u64::from_le_bytes(created_by.0)),
+                    )
+                })
+                .collect(),
+            proof,
+        })
+    }
+
+    /// Generates a single multi-proof that none of the given out_points is live at the given sequence, i.e. that each
+    /// was either never added or consumed already, which is how a wallet checks that its cells were spent.
+    pub fn absence_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<AbsenceProof, AccumulatorError> {
+        Self::new_with_sequence(db, sequence)?.prove_absence(out_points)
+    }
+
+    /// Generates the `absence_proof` of the given out_points at the sequence of this accumulator.
+    pub fn prove_absence(
+        &self,
+        out_points: Vec<OutPoint>,
+    ) -> Result<AbsenceProof, AccumulatorError> {
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            if self.smt.get(&key)? != BlockNumber::zero() {
+                return Err(AccumulatorError::AlreadyExists(i));
+            }
+            leaves.push((key, out_point));
+        }
+
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        leaves.dedup_by_key(|(key, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _)| *key).collect();
+        let proof = self.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(AbsenceProof {
+            sequence: self.smt.store().sequence(),
+            out_points: leaves.into_iter().map(|(_, out_point)| out_point).collect(),
+            proof,
+        })
+    }
+}
+
+impl<D, WO> LevelWrites for GenericSMTAccumulator<D, WO> {
+    fn level_writes(&self) -> Option<&LevelHistogram> {
+        Some(self.smt.store().branch_writes())
+    }
+}
+
+// the SMT writes through to the store, it has no batch to flush
+impl<D, WO> FlushWrites for GenericSMTAccumulator<D, WO> {}
+
+impl<D, WO> CoalescedOps for GenericSMTAccumulator<D, WO> {
+    fn coalesced_ops(&self) -> Option<u64> {
+        Some(self.coalesced_ops)
+    }
+}
+
+impl<D, WO> CacheLiveCells for GenericSMTAccumulator<D, WO> {}
+
+impl<D, WO> IndexedElements for GenericSMTAccumulator<D, WO> {
+    fn element_index_stats(&self) -> Option<&ElementIndexStats> {
+        Some(self.element_index.stats())
     }
 }
 
-impl<'a, DB, WO> AccumulatorReader for SMTAccumulator<'a, DB, WO>
+impl<D, WO> SweepCells for GenericSMTAccumulator<D, WO>
 where
-    DB: Iterate + Get<ReadOptions>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    // the element records are scanned, their plain keys give the out_points, which are consumed with the scanned records
+    // like in `delete` without looking them up again
+    fn sweep<F>(&mut self, created_before: u64, mut predicate: F) -> Result<Swept, AccumulatorError>
+    where
+        F: FnMut(&[u8; 32], &CellStatus) -> bool,
+    {
+        let start = Instant::now();
+        let mut swept = Vec::new();
+        for (key, value) in self.smt.store().element_records() {
+            let record = match self.element_index.decode(&key, Some(&value[..]))? {
+                Some(record) => record,
+                None => continue,
+            };
+            let out_point = parse_element_key(ELEMENT_KEY, &key).expect("checked length");
+            if record.status.is_live()
+                && record.created_by() < created_before
+                && predicate(&out_point.hash(), &record.status)
+            {
+                swept.push((out_point, record));
+            }
+        }
+        let scan = start.elapsed();
+        self.stage_times.record(Stage::ElementIndex, scan);
+
+        let start = Instant::now();
+        let cells = swept.len() as u64;
+        let element_index = &self.element_index;
+        let store = self.smt.store_mut();
+        let sequence = store.sequence();
+        let mut leaves = Vec::with_capacity(swept.len());
+        for (out_point, mut record) in swept {
+            record.status.mark_as_dead(sequence);
+            element_index.put(&mut *store, &element_index.key(&out_point), &record)?;
+            leaves.push((out_point.hash().into(), BlockNumber::zero()));
+        }
+        self.update_leaves(leaves)?;
+        Ok(Swept {
+            cells,
+            scan,
+            update: start.elapsed(),
+        })
+    }
+}
+
+impl<D, WO> StageTiming for GenericSMTAccumulator<D, WO> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        Some(&self.stage_times)
+    }
+}
+
+impl<D, WO> TraceKeys for GenericSMTAccumulator<D, WO> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.smt.store_mut().set_key_tracer(tracer);
+    }
+}
+
+impl<D, WO> AccumulatorReader for GenericSMTAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
 {
     type Item = OutPoint;
     type Commitment = AccumulatorCommitment;
@@ -116,13 +552,21 @@ where
         }
 
         let mut proofs = Vec::with_capacity(elements.len());
-        for out_point in elements.iter() {
+        let mut statuses = Vec::with_capacity(elements.len());
+        for (i, out_point) in elements.into_iter().enumerate() {
+            let status = self
+                .cell_status(&out_point)?
+                .ok_or(AccumulatorError::ElementNotFound(i))?;
             let key = out_point.hash();
             let proof = self.smt.merkle_proof(vec![key.into()])?;
             proofs.push((proof, None));
+            statuses.push((out_point, status));
         }
 
-        Ok(AccumulatorProof { inner: proofs })
+        Ok(AccumulatorProof {
+            inner: proofs,
+            elements: statuses,
+        })
     }
 }
 
@@ -132,6 +576,17 @@ pub struct AccumulatorCommitment {
     sequence: u64,
 }
 
+impl fmt::Debug for AccumulatorCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        debug_commitment(
+            f,
+            self.sequence,
+            self.root.as_slice(),
+            &self.commitment_digest(),
+        )
+    }
+}
+
 impl AccumulatorCommitment {
     pub fn root(&self) -> &H256 {
         &self.root
@@ -140,10 +595,78 @@ impl AccumulatorCommitment {
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    /// See `crate::commitment_digest`, tagged with `COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        commitment_digest(COMMITMENT_TAG, self.root.as_slice(), self.sequence)
+    }
+
+    /// Serializes the commitment as root || sequence as u64 LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.root.as_slice(), self.sequence.to_le_bytes().as_ref()].concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 40 {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let root: [u8; 32] = slice[0..32].try_into().expect("checked length");
+        let sequence = u64::from_le_bytes(slice[32..40].try_into().expect("checked length"));
+        Ok(AccumulatorCommitment {
+            root: H256::from(root),
+            sequence,
+        })
+    }
 }
 
 pub struct AccumulatorProof {
     inner: Vec<(MerkleProof, Option<MerkleProof>)>,
+    // The proved elements with their status recovered from the store, so a verifier doesn't need to know them beforehand.
+    elements: Vec<(OutPoint, CellStatus)>,
+}
+
+impl AccumulatorProof {
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        &self.elements
+    }
+
+    // verifies the proofs of the cells against the commitments creating and consuming them, one pair per cell
+    fn verify_cells(
+        self,
+        commitment: &[(AccumulatorCommitment, Option<AccumulatorCommitment>)],
+        elements: &[(OutPoint, CellStatus)],
+    ) -> Result<bool, AccumulatorError> {
+        if commitment.len() != self.inner.len() {
+            return Ok(false);
+        }
+
+        // every proof is checked, whichever fails, so the time taken doesn't tell which one it is
+        let mut valid = true;
+        for (
+            ((out_point, cell_status), (create_commitment, consume_commitment)),
+            (proof, consume_proof),
+        ) in elements.iter().zip(commitment.iter()).zip(self.inner)
+        {
+            let root = proof.compute_root::<Blake2bHasher>(vec![(
+                out_point.hash().into(),
+                BlockNumber(cell_status.block_numbers[0..8].try_into().unwrap()).to_h256(),
+            )])?;
+            valid &= ct_eq(root.as_slice(), create_commitment.root.as_slice());
+            if !cell_status.is_live() {
+                match (consume_commitment, consume_proof) {
+                    (Some(consume_commitment), Some(proof)) => {
+                        let root = proof.compute_root::<Blake2bHasher>(vec![(
+                            out_point.hash().into(),
+                            BlockNumber::zero().to_h256(),
+                        )])?;
+                        valid &= ct_eq(root.as_slice(), consume_commitment.root.as_slice());
+                    }
+                    _ => valid = false,
+                }
+            }
+        }
+        Ok(valid)
+    }
 }
 
 impl Proof for AccumulatorProof {
@@ -156,38 +679,136 @@ impl Proof for AccumulatorProof {
         commitment: Self::Commitment,
         elements: Vec<Self::Item>,
     ) -> Result<bool, AccumulatorError> {
-        if commitment.len() != self.inner.len() {
-            return Ok(false);
-        }
+        self.verify_cells(&commitment, &elements)
+    }
 
-        for (i, ((out_point, cell_status), (create_commitment, consume_commitment))) in
-            elements.iter().zip(commitment.iter()).enumerate()
-        {
-            let proof = self.inner[i].0.clone();
-            if !proof.verify::<Blake2bHasher>(
-                &create_commitment.root,
-                vec![(
-                    out_point.hash().into(),
-                    BlockNumber(cell_status.block_numbers[0..8].try_into().unwrap()).to_h256(),
-                )],
-            )? {
+    /// Verifies every proof against the borrowed commitments, which aren't copied per proof. Every cell has proofs of
+    /// its own, so a batch shares no hashing.
+    fn verify_batch(
+        commitment: Self::Commitment,
+        proofs: Vec<(Vec<Self::Item>, Self)>,
+    ) -> Result<bool, AccumulatorError> {
+        for (elements, proof) in proofs {
+            if !proof.verify_cells(&commitment, &elements)? {
                 return Ok(false);
             }
-            if !cell_status.is_live() {
-                if let (Some(consume_commitment), Some(proof)) =
-                    (consume_commitment, &self.inner[i].1)
-                {
-                    if !proof.clone().verify::<Blake2bHasher>(
-                        &consume_commitment.root,
-                        vec![(out_point.hash().into(), BlockNumber::zero().to_h256())],
-                    )? {
-                        return Ok(false);
-                    }
-                } else {
-                    return Ok(false);
-                }
-            }
         }
         Ok(true)
     }
 }
+
+/// A multi-proof of many live cells at one commitment, see `SMTAccumulator::wallet_proof`.
+pub struct WalletProof {
+    sequence: u64,
+    elements: Vec<(OutPoint, CellStatus)>,
+    proof: CompiledMerkleProof,
+}
+
+impl WalletProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn elements(&self) -> &[(OutPoint, CellStatus)] {
+        &self.elements
+    }
+
+    pub fn verify(&self, commitment: &AccumulatorCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence
+            || !self.elements.iter().all(|(_, status)| status.is_live())
+        {
+            return Ok(false);
+        }
+        let leaves = self
+            .elements
+            .iter()
+            .map(|(out_point, status)| {
+                let created_by = status.block_numbers[0..8]
+                    .try_into()
+                    .expect("checked length");
+                (out_point.hash().into(), BlockNumber(created_by).to_h256())
+            })
+            .collect();
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Serializes the proof as `sequence (8) | count (4) | count * (tx_hash (32) | index (4) | created by (8)) | compiled proof`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(12 + self.elements.len() * 44 + self.proof.0.len());
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.elements.len() as u32).to_le_bytes());
+        for (out_point, status) in &self.elements {
+            ret.extend_from_slice(&out_point.to_bytes());
+            ret.extend_from_slice(&status.block_numbers[0..8]);
+        }
+        ret.extend_from_slice(&self.proof.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        let (sequence, elements, proof) = proof_from_bytes(slice, 44, |chunk| {
+            let out_point = OutPoint::from_bytes(&chunk[0..36]).expect("checked length");
+            let created_by = u64::from_le_bytes(chunk[36..44].try_into().expect("checked length"));
+            (out_point, CellStatus::new_live(created_by))
+        })?;
+        Ok(WalletProof {
+            sequence,
+            elements,
+            proof,
+        })
+    }
+}
+
+/// A multi-proof that cells are not live at one commitment, see `SMTAccumulator::absence_proof`.
+pub struct AbsenceProof {
+    sequence: u64,
+    out_points: Vec<OutPoint>,
+    proof: CompiledMerkleProof,
+}
+
+impl AbsenceProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn out_points(&self) -> &[OutPoint] {
+        &self.out_points
+    }
+
+    pub fn verify(&self, commitment: &AccumulatorCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence {
+            return Ok(false);
+        }
+        let leaves = self
+            .out_points
+            .iter()
+            .map(|out_point| (out_point.hash().into(), H256::zero()))
+            .collect();
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Serializes the proof as `sequence (8) | count (4) | count * (tx_hash (32) | index (4)) | compiled proof`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(12 + self.out_points.len() * 36 + self.proof.0.len());
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.out_points.len() as u32).to_le_bytes());
+        for out_point in &self.out_points {
+            ret.extend_from_slice(&out_point.to_bytes());
+        }
+        ret.extend_from_slice(&self.proof.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        let (sequence, out_points, proof) = proof_from_bytes(slice, OutPoint::LEN, |chunk| {
+            OutPoint::from_bytes(chunk).expect("checked length")
+        })?;
+        Ok(AbsenceProof {
+            sequence,
+            out_points,
+            proof,
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use super::store::DefaultStore;
+use crate::{smt::bulk::LeafHash, storage::traits::WriteStore, AccumulatorError};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree};
+
+/// Builds the tree of an empty database from pre-hashed `(key, value hash)` leaves and commits it as sequence 0,
+/// returns the root, like `smt::bulk::build_tree` with the layout of this backend. No element records are written,
+/// so the tree can't be proved or checked by out_point.
+pub fn build_tree<DB, WO>(
+    db: &DB,
+    leaves: Vec<([u8; 32], [u8; 32])>,
+) -> Result<[u8; 32], AccumulatorError>
+where
+    DB: WriteStore<WO>,
+{
+    if leaves.is_empty() {
+        return Err(AccumulatorError::InternalError(
+            "no leaves to build the tree from".to_string(),
+        ));
+    }
+    let store = DefaultStore::<_, WO>::new(db);
+    if store.sequence() != 0 {
+        return Err(AccumulatorError::InternalError(
+            "the database is not empty".to_string(),
+        ));
+    }
+    let mut smt = SparseMerkleTree::<Blake2bHasher, LeafHash, _>::new_with_store(store)?;
+    smt.update_all(
+        leaves
+            .into_iter()
+            .map(|(key, hash)| (key.into(), LeafHash(hash)))
+            .collect(),
+    )?;
+    let root = smt.root().as_slice().try_into().expect("checked length");
+    smt.store_mut().commit()?;
+    Ok(root)
+}
@@ -0,0 +1,126 @@
+use super::{
+    is_versioned_key,
+    store::{element_records, DefaultStore, ELEMENT_KEY},
+    LEAF_KEY_LEN, MAX_BLOCK_NUMBER,
+};
+use crate::{
+    element_index::RecordEncoding,
+    fsck::{latest_versions, FsckReport},
+    keycodec::parse_element_key,
+    sequence_width::stored_sequence_width,
+    storage::traits::ReadStore,
+    AccumulatorError, BlockNumber,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, default_store::DefaultStore as MemoryStore, traits::Value,
+    SparseMerkleTree, H256,
+};
+use std::collections::HashMap;
+
+/// Checks the internal consistency of a SMT live-cells accumulator database at its last committed sequence:
+/// the root rebuilt from the leaves must match the stored root, every leaf must be provable through the stored branches,
+/// and the element index must agree with the leaves, i.e. every live element has a leaf with its created-by sequence and dead elements have none.
+pub fn fsck<DB>(db: &DB) -> Result<FsckReport, AccumulatorError>
+where
+    DB: ReadStore,
+{
+    let mut report = FsckReport::default();
+    let sequence = match DefaultStore::<_, ()>::new(db).sequence().checked_sub(1) {
+        Some(sequence) => sequence,
+        None => return Ok(report),
+    };
+    report.sequence = Some(sequence);
+
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    let leaves: Vec<(H256, BlockNumber)> = latest_versions(
+        db,
+        sequence,
+        width,
+        |key| is_versioned_key(key, width),
+        |key| key.len() == LEAF_KEY_LEN + width.encoded_len(),
+        &mut report,
+    )
+    .into_iter()
+    // a removed leaf is stored as an empty value
+    .filter(|(_key, value)| !value.is_empty())
+    .map(|(key, value)| {
+        let key: [u8; 32] = key.try_into().expect("checked length");
+        (key.into(), BlockNumber::from(value))
+    })
+    .collect();
+    report.leaves = leaves.len() as u64;
+
+    let smt =
+        SparseMerkleTree::<Blake2bHasher, BlockNumber, _>::new_with_store(
+            DefaultStore::<_, ()>::new_with_sequence(db, sequence),
+        )?;
+    let mut rebuilt =
+        SparseMerkleTree::<Blake2bHasher, BlockNumber, MemoryStore<BlockNumber>>::default();
+    rebuilt.update_all(leaves.clone())?;
+    if rebuilt.root() != smt.root() {
+        report.errors.push(format!(
+            "root mismatch, stored: {:?}, rebuilt from leaves: {:?}",
+            smt.root(),
+            rebuilt.root()
+        ));
+    }
+
+    for (key, created_by) in leaves.iter() {
+        let verified = smt.merkle_proof(vec![*key]).and_then(|proof| {
+            proof.verify::<Blake2bHasher>(smt.root(), vec![(*key, created_by.to_h256())])
+        });
+        match verified {
+            Ok(true) => {}
+            Ok(false) => report
+                .errors
+                .push(format!("leaf {:?} doesn't hash to the stored root", key)),
+            Err(e) => report.errors.push(format!("leaf {:?}: {:?}", key, e)),
+        }
+    }
+
+    let leaves: HashMap<H256, BlockNumber> = leaves.into_iter().collect();
+    let mut live_elements = 0;
+    for (key, value) in element_records(db, sequence, width) {
+        report.elements += 1;
+        let out_point = parse_element_key(ELEMENT_KEY, &key).expect("checked length");
+        let leaf_key: H256 = out_point.hash().into();
+        let leaf = leaves.get(&leaf_key).unwrap_or(&MAX_BLOCK_NUMBER);
+        match RecordEncoding::Sequences.decode(&value) {
+            Some(record) if record.status.is_live() => {
+                live_elements += 1;
+                if u64::from_le_bytes(leaf.0) != record.created_by() {
+                    report.errors.push(format!(
+                        "live element {:?}:{} created by {} but its leaf is {:?}",
+                        out_point.tx_hash,
+                        out_point.index,
+                        record.created_by(),
+                        leaf
+                    ));
+                }
+            }
+            Some(_) => {
+                if leaf != &MAX_BLOCK_NUMBER {
+                    report.errors.push(format!(
+                        "dead element {:?}:{} still has a leaf",
+                        out_point.tx_hash, out_point.index
+                    ));
+                }
+            }
+            None => report.errors.push(format!(
+                "element {:?}:{} has an invalid value length: {}",
+                out_point.tx_hash,
+                out_point.index,
+                value.len()
+            )),
+        }
+    }
+    if live_elements != leaves.len() {
+        report.errors.push(format!(
+            "{} leaves but {} live elements",
+            leaves.len(),
+            live_elements
+        ));
+    }
+    Ok(report)
+}
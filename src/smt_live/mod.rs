@@ -1,14 +1,47 @@
-use crate::{new_blake2b, BlockNumber};
+use crate::{
+    element_index::{ElementIndex, ElementKeying, RecordEncoding},
+    new_blake2b,
+    sequence_width::SequenceWidth,
+    BlockNumber,
+};
 use sparse_merkle_tree::{traits::Value, H256};
 
 pub mod accumulator;
+pub mod bulk;
+pub mod fsck;
+pub mod presence;
 mod serde;
 mod store;
+pub mod sync;
 #[cfg(test)]
 mod tests;
 
 pub const MAX_BLOCK_NUMBER: BlockNumber = BlockNumber([u8::MAX; 8]);
 
+// leaf keys are the out_point hash, branch keys are the `SMT_KEY` prefix, the 1 byte height and the node key, both are versioned
+pub(crate) const LEAF_KEY_LEN: usize = 32;
+pub(crate) const BRANCH_KEY_LEN: usize = 1 + 33;
+// element keys are the `ELEMENT_KEY` prefix, the tx hash and the index, they are versioned, the databases written
+// before keep their unversioned records
+pub(crate) const ELEMENT_KEY_LEN: usize = 1 + 32 + 4;
+
+/// The element index of this backend, keyed by out_point with the created-by and consumed-by sequences of the elements.
+pub fn element_index() -> ElementIndex {
+    ElementIndex::new(
+        store::ELEMENT_KEY,
+        ElementKeying::Plain,
+        RecordEncoding::Sequences,
+    )
+}
+
+/// Whether a stored key is a versioned key of this backend, i.e. a leaf, branch or element key suffixed with a sequence
+/// of `width`.
+pub fn is_versioned_key(key: &[u8], width: SequenceWidth) -> bool {
+    key.len() == LEAF_KEY_LEN + width.encoded_len()
+        || key.len() == BRANCH_KEY_LEN + width.encoded_len()
+        || key.len() == ELEMENT_KEY_LEN + width.encoded_len()
+}
+
 impl Value for BlockNumber {
     fn to_h256(&self) -> H256 {
         if self == &MAX_BLOCK_NUMBER {
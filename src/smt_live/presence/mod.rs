@@ -0,0 +1,552 @@
+use super::store::DefaultStore;
+use crate::{
+    commitment_digest, ct_eq, debug_commitment,
+    element_index::IndexedElements,
+    live_cache::CacheLiveCells,
+    metrics::{
+        CoalescedOps, FlushWrites, KeyTracer, LevelHistogram, LevelWrites, Stage, StageTimes,
+        StageTiming, TraceKeys,
+    },
+    overlay::SMTOverlayStore,
+    smt::accumulator::proof_from_bytes,
+    storage::traits::{ReadStore, WriteStore},
+    sweep::SweepCells,
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, error::Error, traits::Value, CompiledMerkleProof, SparseMerkleTree,
+    H256,
+};
+use std::{collections::HashSet, fmt, ops::Deref, sync::Arc, time::Instant};
+
+#[cfg(test)]
+mod tests;
+
+/// Tags the commitment digests of this backend.
+pub const COMMITMENT_TAG: &[u8] = b"smt_presence";
+
+// the stored value of a present leaf, an absent leaf is removed
+const MARKER: &[u8] = &[1];
+
+/// The leaf value of a tree committing to the presence of the live out_points only: a constant marker, so the leaf
+/// hash only depends on the key, and a spent out_point has no leaf.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Presence(bool);
+
+impl Presence {
+    pub const PRESENT: Presence = Presence(true);
+
+    pub fn is_present(&self) -> bool {
+        self.0
+    }
+}
+
+impl Value for Presence {
+    fn to_h256(&self) -> H256 {
+        if !self.0 {
+            return H256::zero();
+        }
+        let mut buf = [0u8; 32];
+        buf[0] = 1;
+        buf.into()
+    }
+
+    fn zero() -> Self {
+        Presence(false)
+    }
+}
+
+impl From<Box<[u8]>> for Presence {
+    fn from(vec: Box<[u8]>) -> Self {
+        Presence(!vec.is_empty())
+    }
+}
+
+impl AsRef<[u8]> for Presence {
+    fn as_ref(&self) -> &[u8] {
+        if self.0 {
+            MARKER
+        } else {
+            &[]
+        }
+    }
+}
+
+/// A minimal `smt_live` committing to set membership only, without the creation sequences of the cells and without
+/// an element index, to measure how much of the cost of the other backends comes from carrying the cell statuses.
+/// It can't tell an unknown cell from a spent one, so only strict mode checks the cells of `add` and `delete`.
+pub struct GenericPresenceAccumulator<D, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, Presence, DefaultStore<D, WO>>,
+    // When enabled, `add` rejects present elements and `delete` rejects absent ones.
+    strict: bool,
+    stage_times: StageTimes,
+}
+
+/// The accumulator borrowing a RocksDB database, transaction or snapshot.
+pub type PresenceAccumulator<'a, DB, WO> = GenericPresenceAccumulator<&'a DB, WO>;
+
+/// The accumulator sharing ownership of the RocksDB database.
+pub type PresenceAccumulatorOwned<DB, WO> = GenericPresenceAccumulator<Arc<DB>, WO>;
+
+impl<D, WO> GenericPresenceAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    pub fn new(db: D) -> Result<Self, Error> {
+        Self::new_with_strict(db, false)
+    }
+
+    pub fn new_with_strict(db: D, strict: bool) -> Result<Self, Error> {
+        let store = DefaultStore::new(db);
+        let smt = SparseMerkleTree::new_with_store(store)?;
+        Ok(GenericPresenceAccumulator {
+            smt,
+            strict,
+            stage_times: StageTimes::default(),
+        })
+    }
+
+    // checks the elements against the tree in strict mode, then appends their leaves to `leaves`, a present leaf for
+    // the added elements and a removed one for the deleted elements
+    fn push_leaves(
+        &mut self,
+        elements: Vec<OutPoint>,
+        value: Presence,
+        leaves: &mut Vec<(H256, Presence)>,
+    ) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        let hashes: Vec<H256> = elements
+            .iter()
+            .map(|out_point| out_point.hash().into())
+            .collect();
+        self.stage_times.record(Stage::Hashing, start.elapsed());
+
+        if self.strict {
+            let mut keys = HashSet::with_capacity(hashes.len());
+            for (i, key) in hashes.iter().enumerate() {
+                // an element added or deleted twice in the batch is present or absent after its first write
+                let present = if keys.insert(*key) {
+                    self.smt.get(key)?.is_present()
+                } else {
+                    value.is_present()
+                };
+                match (value.is_present(), present) {
+                    (true, true) => return Err(AccumulatorError::AlreadyExists(i)),
+                    (false, false) => return Err(AccumulatorError::ElementNotFound(i)),
+                    _ => {}
+                }
+            }
+        }
+        leaves.extend(hashes.into_iter().map(|key| (key, value)));
+        Ok(())
+    }
+
+    fn update_leaves(&mut self, leaves: Vec<(H256, Presence)>) -> Result<(), AccumulatorError> {
+        let start = Instant::now();
+        self.smt.update_all(leaves)?;
+        self.stage_times.record(Stage::TreeUpdate, start.elapsed());
+        Ok(())
+    }
+}
+
+impl<D, WO> AccumulatorWriter for GenericPresenceAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    type Item = OutPoint;
+    type Commitment = PresenceCommitment;
+
+    fn add(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.push_leaves(elements, Presence::PRESENT, &mut leaves)?;
+        self.update_leaves(leaves)
+    }
+
+    fn delete(&mut self, elements: Vec<Self::Item>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        self.push_leaves(elements, Presence::zero(), &mut leaves)?;
+        self.update_leaves(leaves)
+    }
+
+    // only the liveness of a status is committed to
+    fn update(&mut self, elements: Vec<(Self::Item, CellStatus)>) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(elements.len());
+        for (i, (out_point, status)) in elements.into_iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            if self.strict && !self.smt.get(&key)?.is_present() {
+                return Err(AccumulatorError::ElementNotFound(i));
+            }
+            leaves.push((key, Presence(status.is_live())));
+        }
+        self.update_leaves(leaves)
+    }
+
+    fn apply_blocks(
+        &mut self,
+        blocks: Vec<BlockOps>,
+    ) -> Result<Vec<Self::Commitment>, AccumulatorError> {
+        let mut commitments = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // a cell consumed in the block it's added in never has a leaf
+            let transient = block.take_transient();
+            block
+                .added
+                .retain(|out_point| !transient.contains(out_point));
+            let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+            self.push_leaves(block.added, Presence::PRESENT, &mut leaves)?;
+            self.push_leaves(block.deleted, Presence::zero(), &mut leaves)?;
+            self.update_leaves(leaves)?;
+            commitments.push(self.commit()?);
+        }
+        Ok(commitments)
+    }
+
+    fn commit(&mut self) -> Result<Self::Commitment, AccumulatorError> {
+        let start = Instant::now();
+        let root = *self.smt.root();
+        let sequence = self.smt.store().sequence();
+        self.smt.store_mut().commit()?;
+        self.stage_times.record(Stage::TreeCommit, start.elapsed());
+        Ok(PresenceCommitment { root, sequence })
+    }
+
+    fn skip_to(&mut self, sequence: u64) -> Result<(), AccumulatorError> {
+        Ok(self.smt.store_mut().skip_to(sequence)?)
+    }
+}
+
+impl<D, WO> GenericPresenceAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    pub fn new_with_sequence(db: D, sequence: u64) -> Result<Self, Error> {
+        let store = DefaultStore::new_with_sequence(db, sequence);
+        let smt = SparseMerkleTree::new_with_store(store)?;
+        Ok(GenericPresenceAccumulator {
+            smt,
+            strict: false,
+            stage_times: StageTimes::default(),
+        })
+    }
+
+    /// Returns the commitment of the state seen by this accumulator, i.e. of the sequence it was opened with.
+    pub fn commitment(&self) -> PresenceCommitment {
+        PresenceCommitment {
+            root: *self.smt.root(),
+            sequence: self.smt.store().sequence(),
+        }
+    }
+
+    /// Whether the out_point is live at the sequence of this accumulator.
+    pub fn contains(&self, out_point: &OutPoint) -> Result<bool, AccumulatorError> {
+        Ok(self.smt.get(&out_point.hash().into())?.is_present())
+    }
+
+    /// The status of a live out_point, `None` for a consumed or unknown one, which the tree can't tell apart. The
+    /// tree doesn't keep the creation sequences either, a live cell is reported as created at 0.
+    pub fn cell_status(
+        &self,
+        out_point: &OutPoint,
+    ) -> Result<Option<CellStatus>, AccumulatorError> {
+        Ok(self.contains(out_point)?.then(|| CellStatus::new_live(0)))
+    }
+
+    /// Returns the commitment `apply_blocks` would commit for `block`, without persisting anything, see
+    /// `SMTAccumulator::preview_root`.
+    pub fn preview_root(
+        &self,
+        mut block: BlockOps,
+    ) -> Result<PresenceCommitment, AccumulatorError> {
+        let transient = block.take_transient();
+        block
+            .added
+            .retain(|out_point| !transient.contains(out_point));
+        let mut leaves = Vec::with_capacity(block.added.len() + block.deleted.len());
+        for (elements, value) in [
+            (block.added, Presence::PRESENT),
+            (block.deleted, Presence::zero()),
+        ] {
+            let mut keys = HashSet::with_capacity(elements.len());
+            for (i, out_point) in elements.into_iter().enumerate() {
+                let key: H256 = out_point.hash().into();
+                if self.strict
+                    && (!keys.insert(key) || self.smt.get(&key)?.is_present() == value.is_present())
+                {
+                    return Err(if value.is_present() {
+                        AccumulatorError::AlreadyExists(i)
+                    } else {
+                        AccumulatorError::ElementNotFound(i)
+                    });
+                }
+                leaves.push((key, value));
+            }
+        }
+        let store = self.smt.store();
+        let mut smt = SparseMerkleTree::<Blake2bHasher, Presence, _>::new(
+            *self.smt.root(),
+            SMTOverlayStore::new(store),
+        );
+        smt.update_all(leaves)?;
+        Ok(PresenceCommitment {
+            root: *smt.root(),
+            sequence: store.sequence(),
+        })
+    }
+
+    /// Generates a single multi-proof that all the given out_points are live at the given sequence, like
+    /// `smt_live::accumulator::SMTAccumulator::wallet_proof` but without their statuses.
+    pub fn wallet_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<PresenceProof, AccumulatorError> {
+        Self::new_with_sequence(db, sequence)?.prove(out_points, Presence::PRESENT)
+    }
+
+    /// Generates a single multi-proof that none of the given out_points is live at the given sequence.
+    pub fn absence_proof(
+        db: D,
+        out_points: Vec<OutPoint>,
+        sequence: u64,
+    ) -> Result<PresenceProof, AccumulatorError> {
+        Self::new_with_sequence(db, sequence)?.prove_absence(out_points)
+    }
+
+    /// Generates a proof that none of the given out_points is live at the sequence of this accumulator.
+    pub fn prove_absence(
+        &self,
+        out_points: Vec<OutPoint>,
+    ) -> Result<PresenceProof, AccumulatorError> {
+        self.prove(out_points, Presence::zero())
+    }
+
+    fn prove(
+        &self,
+        out_points: Vec<OutPoint>,
+        value: Presence,
+    ) -> Result<PresenceProof, AccumulatorError> {
+        let mut leaves = Vec::with_capacity(out_points.len());
+        for (i, out_point) in out_points.into_iter().enumerate() {
+            let key: H256 = out_point.hash().into();
+            match (value.is_present(), self.smt.get(&key)?.is_present()) {
+                (true, false) => return Err(AccumulatorError::ElementNotFound(i)),
+                (false, true) => return Err(AccumulatorError::AlreadyExists(i)),
+                _ => leaves.push((key, out_point)),
+            }
+        }
+
+        // unrelated keys are proved in one pass over the tree when they are sorted
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        leaves.dedup_by_key(|(key, _)| *key);
+        let keys: Vec<H256> = leaves.iter().map(|(key, _)| *key).collect();
+        let proof = self.smt.merkle_proof(keys.clone())?.compile(keys)?;
+        Ok(PresenceProof {
+            sequence: self.smt.store().sequence(),
+            present: value.is_present(),
+            out_points: leaves.into_iter().map(|(_, out_point)| out_point).collect(),
+            proof,
+        })
+    }
+}
+
+impl<D, WO> AccumulatorReader for GenericPresenceAccumulator<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    type Item = OutPoint;
+    type Commitment = PresenceCommitment;
+    type Proof = PresenceProof;
+
+    /// Proves that cells are live at the sequence of this accumulator, a consumed or unknown cell fails with
+    /// `ElementNotFound`.
+    fn proof(
+        &self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<Self::Proof, AccumulatorError> {
+        if commitment.root != *self.smt.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        self.prove(elements, Presence::PRESENT)
+    }
+}
+
+impl<D, WO> LevelWrites for GenericPresenceAccumulator<D, WO> {
+    fn level_writes(&self) -> Option<&LevelHistogram> {
+        Some(self.smt.store().branch_writes())
+    }
+}
+
+// the SMT writes through to the store, it has no batch to flush
+impl<D, WO> FlushWrites for GenericPresenceAccumulator<D, WO> {}
+
+impl<D, WO> CoalescedOps for GenericPresenceAccumulator<D, WO> {}
+
+impl<D, WO> CacheLiveCells for GenericPresenceAccumulator<D, WO> {}
+
+impl<D, WO> IndexedElements for GenericPresenceAccumulator<D, WO> {}
+
+// the leaves don't keep the creation sequences a sweep selects the cells by
+impl<D, WO> SweepCells for GenericPresenceAccumulator<D, WO> {}
+
+impl<D, WO> StageTiming for GenericPresenceAccumulator<D, WO> {
+    fn stage_times(&self) -> Option<&StageTimes> {
+        Some(&self.stage_times)
+    }
+}
+
+impl<D, WO> TraceKeys for GenericPresenceAccumulator<D, WO> {
+    fn trace_keys(&mut self, tracer: KeyTracer) {
+        self.smt.store_mut().set_key_tracer(tracer);
+    }
+}
+
+#[derive(Clone)]
+pub struct PresenceCommitment {
+    root: H256,
+    sequence: u64,
+}
+
+impl fmt::Debug for PresenceCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        debug_commitment(
+            f,
+            self.sequence,
+            self.root.as_slice(),
+            &self.commitment_digest(),
+        )
+    }
+}
+
+impl PresenceCommitment {
+    pub fn root(&self) -> &H256 {
+        &self.root
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// See `crate::commitment_digest`, tagged with `COMMITMENT_TAG`.
+    pub fn commitment_digest(&self) -> [u8; 32] {
+        commitment_digest(COMMITMENT_TAG, self.root.as_slice(), self.sequence)
+    }
+
+    /// Serializes the commitment as root || sequence as u64 LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.root.as_slice(), self.sequence.to_le_bytes().as_ref()].concat()
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 40 {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        let root: [u8; 32] = slice[0..32].try_into().expect("checked length");
+        let sequence = u64::from_le_bytes(slice[32..40].try_into().expect("checked length"));
+        Ok(PresenceCommitment {
+            root: H256::from(root),
+            sequence,
+        })
+    }
+}
+
+/// A multi-proof that cells are all live, or all absent, at one commitment, see
+/// `PresenceAccumulator::wallet_proof` and `PresenceAccumulator::absence_proof`.
+pub struct PresenceProof {
+    sequence: u64,
+    present: bool,
+    out_points: Vec<OutPoint>,
+    proof: CompiledMerkleProof,
+}
+
+impl PresenceProof {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Whether the proof proves the out_points live or absent.
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    pub fn out_points(&self) -> &[OutPoint] {
+        &self.out_points
+    }
+
+    pub fn verify(&self, commitment: &PresenceCommitment) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence {
+            return Ok(false);
+        }
+        let value = Presence(self.present).to_h256();
+        let leaves = self
+            .out_points
+            .iter()
+            .map(|out_point| (out_point.hash().into(), value))
+            .collect();
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+
+    /// Serializes the proof as `present (1) | sequence (8) | count (4) | count * (tx_hash (32) | index (4)) | compiled
+    /// proof`, a wallet proof of `smt_live` without the 8 bytes created-by sequence of every cell.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(13 + self.out_points.len() * 36 + self.proof.0.len());
+        ret.push(self.present as u8);
+        ret.extend_from_slice(&self.sequence.to_le_bytes());
+        ret.extend_from_slice(&(self.out_points.len() as u32).to_le_bytes());
+        for out_point in &self.out_points {
+            ret.extend_from_slice(&out_point.to_bytes());
+        }
+        ret.extend_from_slice(&self.proof.0);
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        let present = match slice.first() {
+            Some(0) => false,
+            Some(1) => true,
+            _ => return Err(AccumulatorError::InvalidProof),
+        };
+        let (sequence, out_points, proof) =
+            proof_from_bytes(&slice[1..], OutPoint::LEN, |chunk| {
+                OutPoint::from_bytes(chunk).expect("checked length")
+            })?;
+        Ok(PresenceProof {
+            sequence,
+            present,
+            out_points,
+            proof,
+        })
+    }
+}
+
+impl Proof for PresenceProof {
+    type Item = (OutPoint, CellStatus);
+    type Commitment = PresenceCommitment;
+
+    /// Verifies the liveness of the given cells only, a cell proved live verifies with any live status.
+    fn verify(
+        self,
+        commitment: Self::Commitment,
+        elements: Vec<Self::Item>,
+    ) -> Result<bool, AccumulatorError> {
+        if commitment.sequence != self.sequence {
+            return Ok(false);
+        }
+        let leaves = elements
+            .into_iter()
+            .map(|(out_point, status)| {
+                (
+                    out_point.hash().into(),
+                    Presence(status.is_live()).to_h256(),
+                )
+            })
+            .collect();
+        let root = self.proof.compute_root::<Blake2bHasher>(leaves)?;
+        Ok(ct_eq(root.as_slice(), commitment.root.as_slice()))
+    }
+}
@@ -0,0 +1,240 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::{Builder, TempDir};
+
+use super::{PresenceAccumulator, PresenceCommitment, PresenceProof};
+use crate::{
+    smt_live::accumulator::SMTAccumulator, AccumulatorError, AccumulatorWriter, BlockOps, OutPoint,
+};
+
+// return temp dir also to make sure it's not dropped automatically
+fn open_db() -> (OptimisticTransactionDB, TempDir) {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    (
+        OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap(),
+        tmp_dir,
+    )
+}
+
+fn out_points(n: u8) -> Vec<OutPoint> {
+    (0..n)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect()
+}
+
+#[test]
+fn test_accumulator() {
+    let out_points = out_points(10);
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = PresenceAccumulator::new(&tx).unwrap();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator
+        .delete(vec![out_points[1].clone(), out_points[6].clone()])
+        .unwrap();
+    let commitment = accumulator.commit().unwrap();
+    assert_eq!(1, commitment.sequence());
+    for (i, out_point) in out_points.iter().enumerate() {
+        assert_eq!(i != 1 && i != 6, accumulator.contains(out_point).unwrap());
+    }
+    drop(accumulator);
+    tx.commit().unwrap();
+
+    // the root only depends on the live cells, a cell consumed in the block it's added in never has a leaf
+    let (other_db, _other_dir) = open_db();
+    let tx = other_db.transaction_default();
+    let mut accumulator = PresenceAccumulator::new(&tx).unwrap();
+    let live: Vec<_> = out_points
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 1 && *i != 6)
+        .map(|(_, out_point)| out_point.clone())
+        .rev()
+        .collect();
+    let commitments = accumulator
+        .apply_blocks(vec![
+            BlockOps::default(),
+            BlockOps {
+                added: [live, vec![out_points[1].clone()]].concat(),
+                deleted: vec![out_points[1].clone()],
+            },
+        ])
+        .unwrap();
+    assert_eq!(commitment.to_bytes(), commitments[1].to_bytes());
+    assert!(!accumulator.contains(&out_points[1]).unwrap());
+
+    let commitment = PresenceCommitment::from_bytes(&commitment.to_bytes()).unwrap();
+    assert_eq!(
+        commitments[1].commitment_digest(),
+        commitment.commitment_digest()
+    );
+
+    // the historical reader sees the first block only
+    let snapshot = db.snapshot();
+    let reader = PresenceAccumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    assert!(reader.contains(&out_points[1]).unwrap());
+    assert!(!reader.contains(&out_points[5]).unwrap());
+}
+
+#[test]
+fn test_strict_mode() {
+    let out_points = out_points(4);
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = PresenceAccumulator::new_with_strict(&tx, true).unwrap();
+    accumulator.add(out_points[0..3].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[0].clone()]).unwrap();
+    accumulator.commit().unwrap();
+
+    assert!(matches!(
+        accumulator.add(vec![out_points[3].clone(), out_points[1].clone()]),
+        Err(AccumulatorError::AlreadyExists(1))
+    ));
+    assert!(matches!(
+        accumulator.add(vec![out_points[3].clone(), out_points[3].clone()]),
+        Err(AccumulatorError::AlreadyExists(1))
+    ));
+    // a consumed cell is absent like an unknown one
+    assert!(matches!(
+        accumulator.delete(vec![out_points[1].clone(), out_points[0].clone()]),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+    assert!(matches!(
+        accumulator.delete(vec![out_points[1].clone(), out_points[1].clone()]),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+    // the rejected calls wrote nothing
+    assert!(accumulator.contains(&out_points[1]).unwrap());
+    assert!(!accumulator.contains(&out_points[3]).unwrap());
+}
+
+#[test]
+fn test_proofs() {
+    let out_points = out_points(10);
+    let (db, _tmp_dir) = open_db();
+    let (live_db, _live_dir) = open_db();
+    let tx = db.transaction_default();
+    let live_tx = live_db.transaction_default();
+    let mut accumulator = PresenceAccumulator::new(&tx).unwrap();
+    let mut live_accumulator = SMTAccumulator::new(&live_tx).unwrap();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    live_accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    live_accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    live_accumulator
+        .delete(vec![out_points[1].clone()])
+        .unwrap();
+    let commitment = accumulator.commit().unwrap();
+    live_accumulator.commit().unwrap();
+    drop(accumulator);
+    drop(live_accumulator);
+    tx.commit().unwrap();
+    live_tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let wallet = vec![
+        out_points[4].clone(),
+        out_points[0].clone(),
+        out_points[3].clone(),
+    ];
+    let proof = PresenceAccumulator::<_, ()>::wallet_proof(&snapshot, wallet.clone(), 1).unwrap();
+    assert!(proof.is_present());
+    assert_eq!(3, proof.out_points().len());
+    assert!(proof.verify(&commitment).unwrap());
+    let proof = PresenceProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    // the trees of both backends have the same keys, the proof only saves the statuses of the cells
+    let live_proof = SMTAccumulator::<_, ()>::wallet_proof(&live_db.snapshot(), wallet, 1).unwrap();
+    assert_eq!(
+        live_proof.to_bytes().len(),
+        proof.to_bytes().len() + 3 * 8 - 1
+    );
+
+    assert!(matches!(
+        PresenceAccumulator::<_, ()>::wallet_proof(
+            &snapshot,
+            vec![out_points[0].clone(), out_points[1].clone()],
+            1
+        ),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+
+    let spent = vec![out_points[8].clone(), out_points[1].clone()];
+    let proof = PresenceAccumulator::<_, ()>::absence_proof(&snapshot, spent, 1).unwrap();
+    assert!(!proof.is_present());
+    assert!(proof.verify(&commitment).unwrap());
+    let proof = PresenceProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+    assert!(matches!(
+        PresenceAccumulator::<_, ()>::absence_proof(&snapshot, vec![out_points[1].clone()], 0),
+        Err(AccumulatorError::AlreadyExists(0))
+    ));
+
+    // a membership proof doesn't verify as an absence proof
+    let mut bytes =
+        PresenceAccumulator::<_, ()>::wallet_proof(&snapshot, vec![out_points[0].clone()], 1)
+            .unwrap()
+            .to_bytes();
+    bytes[0] = 0;
+    let proof = PresenceProof::from_bytes(&bytes).unwrap();
+    assert!(!proof.verify(&commitment).unwrap());
+
+    // a count whose out_points would overflow the length of the input is rejected
+    bytes[9..13].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        PresenceProof::from_bytes(&bytes),
+        Err(AccumulatorError::InvalidProof)
+    ));
+    assert!(matches!(
+        PresenceProof::from_bytes(&[]),
+        Err(AccumulatorError::InvalidProof)
+    ));
+}
+
+#[test]
+fn test_preview_root() {
+    let out_points = out_points(6);
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = PresenceAccumulator::new_with_strict(&tx, true).unwrap();
+    accumulator.add(out_points[0..3].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    let block = BlockOps {
+        added: out_points[3..].to_vec(),
+        deleted: vec![out_points[0].clone()],
+    };
+    let preview = accumulator.preview_root(block.clone()).unwrap();
+    // nothing was written
+    assert!(!accumulator.contains(&out_points[3]).unwrap());
+    assert!(accumulator
+        .cell_status(&out_points[0])
+        .unwrap()
+        .unwrap()
+        .is_live());
+    let committed = accumulator.apply_blocks(vec![block]).unwrap();
+    assert_eq!(preview.to_bytes(), committed[0].to_bytes());
+    // a consumed cell has no status, like an unknown one
+    assert_eq!(None, accumulator.cell_status(&out_points[0]).unwrap());
+
+    assert!(matches!(
+        accumulator.preview_root(BlockOps {
+            added: vec![out_points[1].clone()],
+            deleted: Vec::new(),
+        }),
+        Err(AccumulatorError::AlreadyExists(0))
+    ));
+    assert!(matches!(
+        accumulator.preview_root(BlockOps {
+            added: Vec::new(),
+            deleted: vec![out_points[0].clone()],
+        }),
+        Err(AccumulatorError::ElementNotFound(0))
+    ));
+}
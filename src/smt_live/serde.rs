@@ -1,4 +1,5 @@
 use super::store::SMT_KEY;
+use crate::smt::serde::{check_encoding, SerdeError};
 use sparse_merkle_tree::{merge::MergeValue, BranchKey, BranchNode};
 use std::convert::TryInto;
 
@@ -11,13 +12,21 @@ pub fn branch_key_to_vec(key: &BranchKey) -> Vec<u8> {
     ret
 }
 
+/// The format version of the serialized branch nodes, stored in the high 4 bits of the variant tag byte,
+/// so that a future format can be told apart from the existing data.
+pub const FORMAT_VERSION: u8 = 0;
+
+fn tag(variant: u8) -> u8 {
+    FORMAT_VERSION << 4 | variant
+}
+
 /// Serialize a `BranchNode` into a `Vec<u8>` for use as a key in the key-value store.
 pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
     match (&node.left, &node.right) {
         (MergeValue::Value(left), MergeValue::Value(right)) => {
-            let mut ret = Vec::with_capacity(34);
+            let mut ret = Vec::with_capacity(66);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[0]);
+            ret.push(tag(0));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(right.as_slice());
             ret
@@ -32,7 +41,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(99);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[1]);
+            ret.push(tag(1));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
@@ -49,7 +58,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(99);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[2]);
+            ret.push(tag(2));
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
@@ -70,7 +79,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(132);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[3]);
+            ret.push(tag(3));
             ret.extend_from_slice(l_base_node.as_slice());
             ret.extend_from_slice(l_zero_bits.as_slice());
             ret.extend_from_slice(&[*l_zero_count]);
@@ -82,7 +91,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         (MergeValue::Value(left), MergeValue::ShortCut { key, value, height }) => {
             let mut ret = Vec::with_capacity(99);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[4]);
+            ret.push(tag(4));
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
@@ -92,7 +101,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         (MergeValue::ShortCut { key, value, height }, MergeValue::Value(right)) => {
             let mut ret = Vec::with_capacity(99);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[5]);
+            ret.push(tag(5));
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
@@ -113,7 +122,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(132);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[6]);
+            ret.push(tag(6));
             ret.extend_from_slice(l_key.as_slice());
             ret.extend_from_slice(l_value.as_slice());
             ret.extend_from_slice(&[*l_height]);
@@ -132,7 +141,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(132);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[7]);
+            ret.push(tag(7));
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
@@ -151,7 +160,7 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
         ) => {
             let mut ret = Vec::with_capacity(132);
             ret.extend_from_slice(SMT_KEY);
-            ret.extend_from_slice(&[8]);
+            ret.push(tag(8));
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
@@ -163,9 +172,10 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
     }
 }
 
-/// Deserialize a `BranchNode` from a slice that was previously serialized with `branch_node_to_vec`.
-pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
-    match slice[1] {
+/// Deserialize a `BranchNode` from a slice that was previously serialized with `branch_node_to_vec`, an error if it
+/// isn't a valid encoding.
+pub fn slice_to_branch_node(slice: &[u8]) -> Result<BranchNode, SerdeError> {
+    let node = match check_encoding(slice, SMT_KEY.len())? {
         0 => {
             let left: [u8; 32] = slice[2..34].try_into().expect("checked slice");
             let right: [u8; 32] = slice[34..66].try_into().expect("checked slice");
@@ -310,8 +320,7 @@ pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
                 },
             }
         }
-        _ => {
-            unreachable!()
-        }
-    }
+        _ => unreachable!("checked variant"),
+    };
+    Ok(node)
 }
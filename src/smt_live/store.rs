@@ -1,33 +1,54 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Deref};
 
-use rocksdb::{prelude::*, Direction, IteratorMode};
+use crate::{
+    element_index::{latest_versions, RecordStore, RecordStoreMut},
+    metrics::{KeyTracer, LevelHistogram},
+    sequence_width::{skipped_record, stored_sequence_width, SequenceWidth},
+    storage::traits::{ReadStore, WriteStore},
+    AccumulatorError,
+};
+use rocksdb::{
+    prelude::{Delete, Put},
+    Direction, IteratorMode,
+};
 use sparse_merkle_tree::{
     error::Error,
     traits::{StoreReadOps, StoreWriteOps, Value},
     BranchKey, BranchNode, H256,
 };
 
-use super::serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node};
+use super::{
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+    ELEMENT_KEY_LEN,
+};
 
 pub const ELEMENT_KEY: &[u8] = &[0];
 pub const SMT_KEY: &[u8] = &[1];
 pub const SEQUENCE_KEY: &[u8] = &[2];
 
 /// A SMT `Store` implementation backed by a RocksDB database, using the default column family and supports historical queries.
-pub struct DefaultStore<'a, DB, WO> {
-    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
-    inner: &'a DB,
+pub struct DefaultStore<D, WO> {
+    // A handle to the RocksDB database which stores the data, can be a `&DB` / `&OptimisticTransaction` / `Arc<Snapshot>` etc.
+    inner: D,
     // The sequence number is used to support historical queries.
     sequence: u64,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<WO>,
+    // A generic write options, can be a `WriteOptions` / `()` etc, it's never stored so it doesn't affect `Send` / `Sync`.
+    write_options: PhantomData<fn() -> WO>,
+    // Traces the keys read and written by the store, disabled by default.
+    key_tracer: KeyTracer,
+    sequence_width: SequenceWidth,
+    // The first sequence skipped by `skip_to` since the last commit, the gap is recorded by the next commit.
+    skipped_from: Option<u64>,
+    // Number of branch inserts and removals per height.
+    branch_writes: LevelHistogram,
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Get<ReadOptions>,
+    D: Deref,
+    D::Target: ReadStore,
 {
-    pub fn new(db: &'a DB) -> Self {
+    pub fn new(db: D) -> Self {
         let sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -39,14 +60,19 @@ where
                 )
             })
             .unwrap_or(0);
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            branch_writes: LevelHistogram::default(),
         }
     }
 
-    pub fn new_with_sequence(db: &'a DB, sequence: u64) -> Self {
+    pub fn new_with_sequence(db: D, sequence: u64) -> Self {
         let stored_sequence = db
             .get(SEQUENCE_KEY)
             .expect("init sequence number should be ok")
@@ -61,31 +87,78 @@ where
         if sequence > stored_sequence {
             panic!("sequence number: {} should be less than or equal to the stored sequence number: {}", sequence, stored_sequence);
         }
+        let sequence_width = stored_sequence_width(&*db).expect("init sequence width should be ok");
         DefaultStore {
             inner: db,
             sequence,
             write_options: PhantomData,
+            key_tracer: KeyTracer::default(),
+            sequence_width,
+            skipped_from: None,
+            branch_writes: LevelHistogram::default(),
         }
     }
 
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+}
 
-    pub fn get_raw(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.inner
-            .get(key)
-            .expect("get raw should be ok")
-            .map(|slice| slice.to_vec())
+impl<D, WO> DefaultStore<D, WO> {
+    /// Moves an empty store to `sequence`, so that a syncing client commits the synced state at the sequence of the
+    /// commitment it syncs to.
+    pub fn start_at(&mut self, sequence: u64) -> Result<(), Error> {
+        if self.sequence != 0 {
+            return Err(Error::Store("the database is not empty".to_string()));
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// Moves the store forward to `sequence` before the writes of a block, the sequences in between are never committed
+    /// and read as the state of the previous commit, see `SequenceSource::BlockNumber`.
+    pub fn skip_to(&mut self, sequence: u64) -> Result<(), Error> {
+        if sequence < self.sequence {
+            return Err(Error::Store(format!(
+                "sequence {} is behind the next sequence {}",
+                sequence, self.sequence
+            )));
+        }
+        if sequence > self.sequence_width.max_sequence() {
+            return Err(Error::Store(format!(
+                "sequence {} is beyond the sequence width of the database",
+                sequence
+            )));
+        }
+        if sequence > self.sequence {
+            self.skipped_from.get_or_insert(self.sequence);
+        }
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    pub fn branch_writes(&self) -> &LevelHistogram {
+        &self.branch_writes
+    }
+
+    pub fn set_key_tracer(&mut self, key_tracer: KeyTracer) {
+        self.key_tracer = key_tracer;
     }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
+    /// The element records seen by the store, see `element_records`.
+    pub(crate) fn element_records(&self) -> Vec<(Vec<u8>, Box<[u8]>)> {
+        element_records(&*self.inner, self.sequence, self.sequence_width)
+    }
+
     fn get(&self, key: &[u8]) -> Option<Box<[u8]>> {
-        let start = [key, self.sequence.to_be_bytes().as_ref()].concat();
+        self.key_tracer.read(key);
+        let start = self.sequence_width.versioned_key(key, self.sequence);
         let mode = IteratorMode::From(&start, Direction::Reverse);
         let iter = self.inner.iterator(mode);
         iter.take_while(|(k, _v)| k.starts_with(key))
@@ -94,41 +167,125 @@ where
     }
 }
 
-impl<'a, DB, WO> DefaultStore<'a, DB, WO>
+impl<D, WO> DefaultStore<D, WO>
 where
-    DB: Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: Delete<WO> + Put<WO>,
 {
     fn put<V: AsRef<[u8]>>(&mut self, key: &[u8], value: V) -> Result<(), Error> {
-        let k = [key, self.sequence.to_be_bytes().as_ref()].concat();
+        let k = self.sequence_width.versioned_key(key, self.sequence);
+        self.key_tracer.write(key, k.len() + value.as_ref().len());
         self.inner
             .put(k, value)
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     pub fn commit(&mut self) -> Result<(), Error> {
+        if self.sequence == self.sequence_width.max_sequence() {
+            return Err(Error::Store(format!(
+                "sequence {} is the last one of the sequence width of the database",
+                self.sequence
+            )));
+        }
+        if let Some(skipped_from) = self.skipped_from.take() {
+            let (key, value) = skipped_record(skipped_from..self.sequence);
+            self.inner
+                .put(key, value)
+                .map_err(|e| Error::Store(e.to_string()))?;
+        }
         self.sequence += 1;
         self.inner
             .put(SEQUENCE_KEY, self.sequence.to_be_bytes())
             .map_err(|e| Error::Store(e.to_string()))
     }
+}
 
-    pub fn put_raw<V: AsRef<[u8]>>(&mut self, key: &[u8], value: V) -> Result<(), Error> {
-        self.inner
-            .put(key, value)
-            .map_err(|e| Error::Store(e.to_string()))
+// the element records are versioned like the tree nodes, so a historical reader sees the records of its sequence, the
+// reverse seek falls back to the unversioned record of an element key written before, which sorts before its versions
+impl<D, WO> RecordStore for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    fn get_record(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.get(key).map(|value| value.to_vec())
+    }
+
+    fn get_records(&self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        for key in &keys {
+            self.key_tracer.read(key);
+        }
+        latest_versions(&*self.inner, &keys, |key| {
+            self.sequence_width.versioned_key(key, self.sequence)
+        })
+    }
+}
+
+impl<D, WO> RecordStoreMut for DefaultStore<D, WO>
+where
+    D: Deref,
+    D::Target: WriteStore<WO>,
+{
+    fn put_record(&mut self, key: &[u8], record: &[u8]) -> Result<(), AccumulatorError> {
+        self.put(key, record)
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+
+    fn delete_record(&mut self, key: &[u8]) -> Result<(), AccumulatorError> {
+        self.put(key, [])
+            .map_err(|e| AccumulatorError::InternalError(e.to_string()))
+    }
+}
+
+/// The element records of a database as seen at `sequence`, i.e. the latest version at or before it of every element
+/// key, or the unversioned record of an element key written before the records were versioned, in key order and
+/// without the removed records.
+pub fn element_records<DB: ReadStore + ?Sized>(
+    db: &DB,
+    sequence: u64,
+    width: SequenceWidth,
+) -> Vec<(Vec<u8>, Box<[u8]>)> {
+    let mut records: Vec<(Vec<u8>, Box<[u8]>)> = Vec::new();
+    let mode = IteratorMode::From(ELEMENT_KEY, Direction::Forward);
+    for (key, value) in db
+        .iterator(mode)
+        .take_while(|(key, _value)| key.starts_with(ELEMENT_KEY))
+    {
+        // an unversioned record comes before the versions of its key, which replace it
+        let key = if key.len() == ELEMENT_KEY_LEN {
+            &key[..]
+        } else if key.len() == ELEMENT_KEY_LEN + width.encoded_len() {
+            match width.split(&key) {
+                (key, version) if version <= sequence => key,
+                _ => continue,
+            }
+        } else {
+            // a leaf key starting with the element prefix
+            continue;
+        };
+        match records.last_mut() {
+            Some((last_key, last_value)) if last_key.as_slice() == key => *last_value = value,
+            _ => records.push((key.to_vec(), value)),
+        }
     }
+    records.retain(|(_key, value)| !value.is_empty());
+    records
 }
 
-impl<'a, V, DB, WO> StoreReadOps<V> for DefaultStore<'a, DB, WO>
+impl<V, D, WO> StoreReadOps<V> for DefaultStore<D, WO>
 where
     V: Value + From<Box<[u8]>>,
-    DB: Iterate,
+    D: Deref,
+    D::Target: ReadStore,
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         let slice = self.get(&branch_key_to_vec(branch_key));
         match slice {
             Some(s) if s.is_empty() => Ok(None),
-            Some(s) => Ok(Some(slice_to_branch_node(&s))),
+            Some(s) => slice_to_branch_node(&s)
+                .map(Some)
+                .map_err(|e| Error::Store(format!("branch node {:?}: {}", branch_key, e))),
             None => Ok(None),
         }
     }
@@ -143,12 +300,14 @@ where
     }
 }
 
-impl<'a, V, DB, WO> StoreWriteOps<V> for DefaultStore<'a, DB, WO>
+impl<V, D, WO> StoreWriteOps<V> for DefaultStore<D, WO>
 where
     V: Value + AsRef<[u8]> + From<Box<[u8]>>,
-    DB: Iterate + Delete<WO> + Put<WO>,
+    D: Deref,
+    D::Target: WriteStore<WO>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.branch_writes.record(node_key.height);
         self.put(&branch_key_to_vec(&node_key), &branch_node_to_vec(&branch))
     }
 
@@ -157,18 +316,20 @@ where
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
-        let k = [
-            &branch_key_to_vec(node_key),
-            self.sequence.to_be_bytes().as_ref(),
-        ]
-        .concat();
+        self.branch_writes.record(node_key.height);
+        let key = branch_key_to_vec(node_key);
+        let k = self.sequence_width.versioned_key(&key, self.sequence);
+        self.key_tracer.write(&key, k.len());
         self.inner
             .put(k, [])
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
-        let k = [leaf_key.as_slice(), self.sequence.to_be_bytes().as_ref()].concat();
+        let k = self
+            .sequence_width
+            .versioned_key(leaf_key.as_slice(), self.sequence);
+        self.key_tracer.write(leaf_key.as_slice(), k.len());
         self.inner
             .put(k, [])
             .map_err(|e| Error::Store(e.to_string()))
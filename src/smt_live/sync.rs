@@ -0,0 +1,140 @@
+use super::{
+    accumulator::AccumulatorCommitment,
+    store::{element_records, DefaultStore, ELEMENT_KEY},
+};
+use crate::{
+    element_index::{RecordEncoding, RecordStoreMut},
+    keycodec::{element_key, parse_element_key},
+    sequence_width::stored_sequence_width,
+    storage::traits::{ReadStore, WriteStore},
+    sync::{SyncChunk, SyncClient},
+    AccumulatorError, BlockNumber, OutPoint,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, traits::Value, CompiledMerkleProof, SparseMerkleTree, H256,
+};
+
+/// Exports the cells live at `sequence` of a SMT live-cells accumulator database to `send`, in chunks of `chunk_size`
+/// cells in leaf key order. The entries of a chunk are the out_points (tx hash || index LE) and their created-by
+/// sequences (8 bytes LE), its proof is a compiled multi-proof of their leaves against the root at the sequence, which
+/// also proves the out_points since they are hashed into the leaf keys. Returns the number of exported cells.
+pub fn export_chunks<DB, F>(
+    db: &DB,
+    sequence: u64,
+    chunk_size: usize,
+    mut send: F,
+) -> Result<u64, AccumulatorError>
+where
+    DB: ReadStore,
+    F: FnMut(SyncChunk) -> Result<(), AccumulatorError>,
+{
+    assert!(chunk_size > 0, "chunk size must be positive");
+    let smt =
+        SparseMerkleTree::<Blake2bHasher, BlockNumber, _>::new_with_store(
+            DefaultStore::<_, ()>::new_with_sequence(db, sequence),
+        )?;
+
+    // the unversioned element records of an older database are read as of the last sequence, the created-by and
+    // consumed-by sequences tell which cells are live at the sequence
+    let width =
+        stored_sequence_width(db).map_err(|e| AccumulatorError::InternalError(e.to_string()))?;
+    let mut cells = Vec::new();
+    for (key, value) in element_records(db, sequence, width) {
+        let record = RecordEncoding::Sequences.decode(&value).ok_or_else(|| {
+            AccumulatorError::InternalError(format!("invalid element record {:?}", value))
+        })?;
+        if !record
+            .status_at(sequence)
+            .map_or(false, |status| status.is_live())
+        {
+            continue;
+        }
+        let created_by = record.created_by();
+        let out_point = parse_element_key(ELEMENT_KEY, &key).expect("checked length");
+        cells.push((H256::from(out_point.hash()), out_point, created_by));
+    }
+    cells.sort_unstable_by_key(|(key, _, _)| *key);
+
+    let mut exported = 0;
+    for chunk in cells.chunks(chunk_size) {
+        let keys: Vec<H256> = chunk.iter().map(|(key, _, _)| *key).collect();
+        let proof = smt.merkle_proof(keys.clone())?.compile(keys)?;
+        exported += chunk.len() as u64;
+        send(SyncChunk {
+            entries: chunk
+                .iter()
+                .map(|(_, out_point, created_by)| {
+                    (
+                        out_point.to_bytes().to_vec(),
+                        created_by.to_le_bytes().to_vec(),
+                    )
+                })
+                .collect(),
+            proof: proof.0,
+        })?;
+    }
+    Ok(exported)
+}
+
+/// Loads the chunks of `export_chunks` into an empty SMT live-cells accumulator database, with the element records of
+/// the live cells only, so the cells consumed before the synced sequence are unknown to the client.
+pub struct SMTLiveSyncClient<'a, DB, WO> {
+    smt: SparseMerkleTree<Blake2bHasher, BlockNumber, DefaultStore<&'a DB, WO>>,
+    commitment: AccumulatorCommitment,
+}
+
+impl<'a, DB, WO> SMTLiveSyncClient<'a, DB, WO>
+where
+    DB: ReadStore,
+{
+    /// The commitment must come from a trusted source, e.g. a block header.
+    pub fn new(db: &'a DB, commitment: AccumulatorCommitment) -> Result<Self, AccumulatorError> {
+        let mut store = DefaultStore::new(db);
+        store.start_at(commitment.sequence())?;
+        let smt = SparseMerkleTree::new_with_store(store)?;
+        Ok(SMTLiveSyncClient { smt, commitment })
+    }
+}
+
+impl<'a, DB, WO> SyncClient for SMTLiveSyncClient<'a, DB, WO>
+where
+    DB: WriteStore<WO>,
+{
+    fn ingest(&mut self, chunk: SyncChunk) -> Result<(), AccumulatorError> {
+        let mut leaves = Vec::with_capacity(chunk.entries.len());
+        let mut element_keys = Vec::with_capacity(chunk.entries.len());
+        for (key, value) in &chunk.entries {
+            let out_point = OutPoint::from_bytes(key).ok_or(AccumulatorError::InvalidProof)?;
+            element_keys.push(element_key(ELEMENT_KEY, &out_point));
+            let created_by: [u8; 8] = value
+                .as_slice()
+                .try_into()
+                .map_err(|_| AccumulatorError::InvalidProof)?;
+            leaves.push((H256::from(out_point.hash()), BlockNumber(created_by)));
+        }
+        let hashes = leaves
+            .iter()
+            .map(|(key, created_by)| (*key, created_by.to_h256()))
+            .collect();
+        let verified = CompiledMerkleProof(chunk.proof)
+            .verify::<Blake2bHasher>(self.commitment.root(), hashes);
+        if !matches!(verified, Ok(true)) {
+            return Err(AccumulatorError::InvalidProof);
+        }
+
+        let store = self.smt.store_mut();
+        for (key, (_, value)) in element_keys.iter().zip(chunk.entries) {
+            store.put_record(key, &value)?;
+        }
+        self.smt.update_all(leaves)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), AccumulatorError> {
+        if self.smt.root() != self.commitment.root() {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        self.smt.store_mut().commit()?;
+        Ok(())
+    }
+}
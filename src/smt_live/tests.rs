@@ -1,12 +1,29 @@
-use rocksdb::{prelude::Open, OptimisticTransactionDB};
-use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+use rocksdb::{
+    prelude::{Delete, Get, Open, Put},
+    OptimisticTransactionDB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, merge::MergeValue, traits::Value, BranchNode, SparseMerkleTree, H256,
+};
 use tempfile::{Builder, TempDir};
 
-use crate::{new_blake2b, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof};
+use crate::{
+    element_index::{ElementRecord, RecordEncoding},
+    new_blake2b,
+    smt::{self, bulk::LeafHash, serde::SerdeError},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, CellStatus, OutPoint, Proof,
+};
 
-use super::{accumulator::SMTAccumulator, store::DefaultStore};
+use super::{
+    accumulator::{AbsenceProof, SMTAccumulator, WalletProof},
+    bulk::build_tree,
+    element_index,
+    fsck::fsck,
+    serde::{branch_node_to_vec, slice_to_branch_node},
+    store::{DefaultStore, ELEMENT_KEY, SMT_KEY},
+};
 
-type DefaultStoreSMT<'a, DB, WO> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, DB, WO>>;
+type DefaultStoreSMT<'a, DB, WO> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<&'a DB, WO>>;
 
 #[derive(Default, Clone)]
 pub struct Word(String);
@@ -208,3 +225,473 @@ fn test_accumulator() {
     //     )
     //     .unwrap());
 }
+
+// the statuses of the proved elements are recovered from the element records, so the proof verifies without them
+#[test]
+fn test_recovered_status() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    let out_points: Vec<_> = (0..3u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: 0,
+        })
+        .collect();
+    accumulator.add(out_points[0..2].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(vec![out_points[2].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 1).unwrap();
+    let proof = accumulator
+        .proof(commitment.clone(), vec![out_points[2].clone()])
+        .unwrap();
+    let elements = proof.elements().to_vec();
+    assert_eq!(
+        vec![(out_points[2].clone(), CellStatus::new_live(1))],
+        elements
+    );
+    assert!(proof.verify(vec![(commitment, None)], elements).unwrap());
+
+    // out_points[1] is consumed at sequence 2, it's still live at sequence 1, and unknown before it's created
+    assert_eq!(
+        Some(CellStatus::new_live(0)),
+        accumulator.cell_status(&out_points[1]).unwrap()
+    );
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 2).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 2)),
+        accumulator.cell_status(&out_points[1]).unwrap()
+    );
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    assert_eq!(None, accumulator.cell_status(&out_points[2]).unwrap());
+}
+
+// a double spend tells when the element was created and consumed, the existence checks shared by the backends are
+// tested in `crate::tests::test_existence_checks`
+#[test]
+fn test_already_consumed() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new_with_strict(&tx, true).unwrap();
+
+    let out_point_1 = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    };
+
+    let out_point_2 = OutPoint {
+        tx_hash: [2u8; 32],
+        index: 0,
+    };
+
+    accumulator.add(vec![out_point_1.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_point_1.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    assert!(matches!(
+        accumulator.delete(vec![out_point_1]),
+        Err(AccumulatorError::AlreadyConsumed(0, status)) if status == CellStatus::new_dead(0, 1)
+    ));
+
+    // a rejected delete doesn't tombstone the elements before the rejected one, and a cell deleted twice is consumed by
+    // its first delete
+    accumulator.add(vec![out_point_2.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    assert!(matches!(
+        accumulator.delete(vec![out_point_2.clone(), out_point_2.clone()]),
+        Err(AccumulatorError::AlreadyConsumed(1, status)) if status == CellStatus::new_dead(2, 3)
+    ));
+    assert_eq!(
+        Some(CellStatus::new_live(2)),
+        accumulator.cell_status(&out_point_2).unwrap()
+    );
+}
+
+#[test]
+fn test_wallet_proof() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let wallet = vec![
+        out_points[7].clone(),
+        out_points[0].clone(),
+        out_points[3].clone(),
+    ];
+    let proof = SMTAccumulator::<_, ()>::wallet_proof(&snapshot, wallet, 1).unwrap();
+    assert_eq!(3, proof.elements().len());
+    assert!(proof.verify(&commitment).unwrap());
+
+    let proof = WalletProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    assert!(matches!(
+        SMTAccumulator::<_, ()>::wallet_proof(
+            &snapshot,
+            vec![out_points[0].clone(), out_points[1].clone()],
+            1
+        ),
+        Err(AccumulatorError::ElementNotFound(1))
+    ));
+}
+
+#[test]
+fn test_absence_proof() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    // a consumed cell is absent like an unknown one
+    let snapshot = db.snapshot();
+    let spent = vec![out_points[8].clone(), out_points[1].clone()];
+    let proof = SMTAccumulator::<_, ()>::absence_proof(&snapshot, spent, 1).unwrap();
+    assert_eq!(2, proof.out_points().len());
+    assert!(proof.verify(&commitment).unwrap());
+
+    let proof = AbsenceProof::from_bytes(&proof.to_bytes()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+
+    assert!(matches!(
+        SMTAccumulator::<_, ()>::absence_proof(&snapshot, vec![out_points[1].clone()], 0),
+        Err(AccumulatorError::AlreadyExists(0))
+    ));
+}
+
+#[test]
+fn test_fsck() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_points: Vec<_> = (0..10u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: i as u32,
+        })
+        .collect();
+    accumulator.add(out_points[0..5].to_vec()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.add(out_points[5..].to_vec()).unwrap();
+    accumulator.delete(vec![out_points[1].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(Some(1), report.sequence);
+    assert_eq!(9, report.leaves);
+    assert_eq!(0, report.orphaned_versions);
+
+    // a block written without committing the accumulator leaves orphaned versions behind
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator
+        .add(vec![OutPoint {
+            tx_hash: [10u8; 32],
+            index: 10,
+        }])
+        .unwrap();
+    tx.commit().unwrap();
+    let report = fsck(&db.snapshot()).unwrap();
+    assert!(report.orphaned_versions > 0);
+    // the element record of the uncommitted element is an orphaned version too
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(9, report.elements);
+}
+
+#[test]
+fn test_element_record_versions() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_point_1 = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    };
+    let out_point_2 = OutPoint {
+        tx_hash: [2u8; 32],
+        index: 0,
+    };
+
+    accumulator
+        .add(vec![out_point_1.clone(), out_point_2.clone()])
+        .unwrap();
+    let commitment0 = accumulator.commit().unwrap();
+    accumulator
+        .delete(vec![out_point_1.clone(), out_point_2.clone()])
+        .unwrap();
+    accumulator.commit().unwrap();
+    accumulator.commit().unwrap();
+    // a non-strict delete of a consumed element and re-add of a consumed element rewrite their records
+    accumulator.delete(vec![out_point_1.clone()]).unwrap();
+    accumulator.add(vec![out_point_2.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 2).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 1)),
+        accumulator.cell_status(&out_point_1).unwrap()
+    );
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 1)),
+        accumulator.cell_status(&out_point_2).unwrap()
+    );
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 3).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 3)),
+        accumulator.cell_status(&out_point_1).unwrap()
+    );
+    assert_eq!(
+        Some(CellStatus::new_live(3)),
+        accumulator.cell_status(&out_point_2).unwrap()
+    );
+
+    // a historical proof recovers the status of its sequence
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    let proof = accumulator
+        .proof(commitment0.clone(), vec![out_point_2.clone()])
+        .unwrap();
+    let elements = proof.elements().to_vec();
+    assert_eq!(CellStatus::new_live(0), elements[0].1);
+    assert!(proof.verify(vec![(commitment0, None)], elements).unwrap());
+
+    let report = fsck(&snapshot).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(2, report.elements);
+    assert_eq!(1, report.leaves);
+}
+
+// A non-strict delete of a consumed cell rewrites its consumed-by sequence, its record stays a 16 bytes record instead of
+// growing into an invalid one, and the records of several cells are read in one pass at every sequence.
+#[test]
+fn test_delete_consumed_element() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    let out_points: Vec<_> = (0..3u8)
+        .map(|i| OutPoint {
+            tx_hash: [i; 32],
+            index: 0,
+        })
+        .collect();
+    accumulator.add(out_points.clone()).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[0].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.delete(vec![out_points[0].clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    let index = element_index();
+    let keys: Vec<_> = out_points
+        .iter()
+        .map(|out_point| index.key(out_point))
+        .collect();
+    let versioned_key = [keys[0].as_slice(), &2u64.to_be_bytes()].concat();
+    let record = db.get(&versioned_key).unwrap().unwrap();
+    assert_eq!(
+        Some(ElementRecord {
+            pos: None,
+            status: CellStatus::new_dead(0, 2),
+        }),
+        RecordEncoding::Sequences.decode(&record)
+    );
+    assert_eq!(16, record.len());
+
+    let snapshot = db.snapshot();
+    for (sequence, status) in [
+        (0, CellStatus::new_live(0)),
+        (1, CellStatus::new_dead(0, 1)),
+        (2, CellStatus::new_dead(0, 2)),
+    ] {
+        let store = DefaultStore::<_, ()>::new_with_sequence(&snapshot, sequence);
+        let records = index.multi_get(&store, &keys).unwrap();
+        let statuses: Vec<_> = records
+            .into_iter()
+            .map(|record| record.map(|record| record.status))
+            .collect();
+        assert_eq!(
+            vec![
+                Some(status),
+                Some(CellStatus::new_live(0)),
+                Some(CellStatus::new_live(0))
+            ],
+            statuses,
+            "sequence {}",
+            sequence
+        );
+    }
+    // a missing cell is a miss in the same pass
+    let missing = index.key(&OutPoint {
+        tx_hash: [9u8; 32],
+        index: 0,
+    });
+    let store = DefaultStore::<_, ()>::new_with_sequence(&snapshot, 2);
+    let records = index
+        .multi_get(&store, &[missing, keys[1].clone()])
+        .unwrap();
+    assert_eq!(None, records[0]);
+    assert!(records[1].is_some());
+    assert!(fsck(&snapshot).unwrap().is_ok());
+}
+
+#[test]
+fn test_unversioned_element_records() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+
+    let out_point = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    };
+    accumulator.add(vec![out_point.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    // rewrites the record the way the databases written before the element records were versioned store it
+    let key = [ELEMENT_KEY, &out_point.to_bytes()[..]].concat();
+    let versioned_key = [key.as_slice(), &0u64.to_be_bytes()].concat();
+    let record = db.get(&versioned_key).unwrap().unwrap();
+    db.delete(&versioned_key).unwrap();
+    db.put(&key, &record).unwrap();
+
+    let snapshot = db.snapshot();
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 1).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_live(0)),
+        accumulator.cell_status(&out_point).unwrap()
+    );
+    assert!(fsck(&snapshot).unwrap().is_ok());
+
+    // a later version replaces it
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::new(&tx).unwrap();
+    accumulator.delete(vec![out_point.clone()]).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 1).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_live(0)),
+        accumulator.cell_status(&out_point).unwrap()
+    );
+    let accumulator = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, 2).unwrap();
+    assert_eq!(
+        Some(CellStatus::new_dead(0, 2)),
+        accumulator.cell_status(&out_point).unwrap()
+    );
+    let report = fsck(&snapshot).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(1, report.elements);
+    assert_eq!(0, report.leaves);
+}
+
+// the branch nodes are the smt ones behind the `SMT_KEY` prefix, `smt::tests` pins every layout
+#[test]
+fn test_branch_node_encoding() {
+    let node = BranchNode {
+        left: MergeValue::Value([1u8; 32].into()),
+        right: MergeValue::ShortCut {
+            key: [5u8; 32].into(),
+            value: [6u8; 32].into(),
+            height: 7,
+        },
+    };
+    let encoded = branch_node_to_vec(&node);
+    // the prefix, the tag of a value and a shortcut, then their fields
+    let expected = [
+        [0x01, 0x04].as_ref(),
+        &[1u8; 32],
+        &[5u8; 32],
+        &[6u8; 32],
+        &[7u8],
+    ]
+    .concat();
+    assert_eq!(expected, encoded);
+    assert_eq!(
+        smt::serde::branch_node_to_vec(&node),
+        encoded[SMT_KEY.len()..]
+    );
+    assert_eq!(node, slice_to_branch_node(&encoded).unwrap());
+    // the tag is read behind the prefix
+    assert!(matches!(
+        slice_to_branch_node(&encoded[SMT_KEY.len()..]),
+        Err(SerdeError::InvalidLength { .. })
+    ));
+}
+
+#[test]
+fn test_build_tree() {
+    let leaves: Vec<([u8; 32], [u8; 32])> = (0..100u8)
+        .map(|i| {
+            let mut key = [0u8; 32];
+            let mut hasher = new_blake2b();
+            hasher.update(&[i]);
+            hasher.finalize(&mut key);
+            (key, [i + 1; 32])
+        })
+        .collect();
+    let mut expected = SparseMerkleTree::<
+        Blake2bHasher,
+        LeafHash,
+        sparse_merkle_tree::default_store::DefaultStore<LeafHash>,
+    >::default();
+    for (key, hash) in &leaves {
+        expected.update((*key).into(), LeafHash(*hash)).unwrap();
+    }
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    // no leaves is an error, not an empty tree
+    assert!(build_tree::<_, ()>(&tx, vec![]).is_err());
+    let root = build_tree::<_, ()>(&tx, leaves.clone()).unwrap();
+    assert_eq!(expected.root().as_slice(), root);
+    assert_eq!(1, DefaultStore::<_, ()>::new(&tx).sequence());
+    // only an empty database can be built
+    assert!(build_tree::<_, ()>(&tx, leaves.clone()).is_err());
+    // the leaves are stored with their whole value hash
+    let smt = SparseMerkleTree::<Blake2bHasher, LeafHash, _>::new_with_store(
+        DefaultStore::<_, ()>::new_with_sequence(&tx, 0),
+    )
+    .unwrap();
+    for (key, hash) in &leaves {
+        assert_eq!(LeafHash(*hash), smt.get(&(*key).into()).unwrap());
+    }
+}
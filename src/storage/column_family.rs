@@ -0,0 +1,86 @@
+use rocksdb::{
+    ops::{Delete, DeleteCF, Get, GetCF, Iterate, IterateCF, Put, PutCF},
+    ColumnFamily, DBRawIterator, DBVector, Error, ReadOptions,
+};
+
+/// A RocksDB handle restricted to one column family, so that an accumulator can be embedded in an existing database
+/// next to foreign data. Every read, write and iteration goes to the column family, the accumulator built over it
+/// never sees the other column families and can't touch them. It's a `ReadStore` and a `WriteStore<WO>` when the
+/// handle has the column family variants of the underlying traits, e.g. a `DB` with `WriteOptions`:
+///
+/// ```ignore
+/// let store = ColumnFamilyStore::new(&db, db.cf_handle("accumulator").unwrap())?;
+/// let mut accumulator = SMTAccumulator::<_, WriteOptions>::new(&store)?;
+/// ```
+pub struct ColumnFamilyStore<'a, D: ?Sized> {
+    db: &'a D,
+    cf: &'a ColumnFamily,
+}
+
+impl<'a, D: IterateCF + ?Sized> ColumnFamilyStore<'a, D> {
+    /// Wraps a pre-opened handle and the handle of one of its column families, which must be dedicated to the
+    /// accumulator: a single accumulator per column family, whose keys would otherwise be mixed with other data.
+    /// Fails when the handle can't iterate the column family, which `Iterate` has no way to report later on.
+    pub fn new(db: &'a D, cf: &'a ColumnFamily) -> Result<Self, Error> {
+        db.get_raw_iter_cf(cf, &ReadOptions::default())?;
+        Ok(ColumnFamilyStore { db, cf })
+    }
+
+    /// An iterator over the column family, failing with the error of the underlying handle.
+    pub fn try_raw_iter<'b>(&'b self, readopts: &ReadOptions) -> Result<DBRawIterator<'b>, Error>
+    where
+        'a: 'b,
+    {
+        self.db.get_raw_iter_cf(self.cf, readopts)
+    }
+}
+
+impl<'a, D: ?Sized> ColumnFamilyStore<'a, D> {
+    pub fn db(&self) -> &'a D {
+        self.db
+    }
+
+    pub fn column_family(&self) -> &'a ColumnFamily {
+        self.cf
+    }
+}
+
+impl<D: GetCF<ReadOptions> + ?Sized> Get<ReadOptions> for ColumnFamilyStore<'_, D> {
+    fn get_full<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        readopts: Option<&ReadOptions>,
+    ) -> Result<Option<DBVector>, Error> {
+        self.db.get_cf_full(Some(self.cf), key, readopts)
+    }
+}
+
+impl<W, D: PutCF<W> + ?Sized> Put<W> for ColumnFamilyStore<'_, D> {
+    fn put_full<K, V>(&self, key: K, value: V, writeopts: Option<&W>) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.db.put_cf_full(Some(self.cf), key, value, writeopts)
+    }
+}
+
+impl<W, D: DeleteCF<W> + ?Sized> Delete<W> for ColumnFamilyStore<'_, D> {
+    fn delete_full<K: AsRef<[u8]>>(&self, key: K, writeopts: Option<&W>) -> Result<(), Error> {
+        self.db.delete_cf_full(Some(self.cf), key, writeopts)
+    }
+}
+
+impl<D: IterateCF + ?Sized> Iterate for ColumnFamilyStore<'_, D> {
+    fn get_raw_iter<'a: 'b, 'b>(&'a self, readopts: &ReadOptions) -> DBRawIterator<'b> {
+        // `new` already created an iterator over the column family with this handle, the errors of the handle don't
+        // depend on the read options
+        match self.db.get_raw_iter_cf(self.cf, readopts) {
+            Ok(iter) => iter,
+            Err(e) => unreachable!(
+                "column family iterator checked by ColumnFamilyStore::new: {}",
+                e
+            ),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod column_family;
+pub mod overlap;
+pub mod read_only;
+pub mod traits;
+pub mod write_batch;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,132 @@
+use rocksdb::{
+    ops::{Delete, Get, Iterate, Put},
+    DBRawIterator, DBVector, Error, ReadOptions,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    thread::{Scope, ScopedJoinHandle},
+};
+
+// the commit of the previous transaction running on the worker, with the keys it wrote
+struct PendingCommit<'scope> {
+    written: HashSet<Box<[u8]>>,
+    handle: ScopedJoinHandle<'scope, Result<(), Error>>,
+}
+
+/// A transaction double-buffered with the one before it: `commit_overlapped` hands the filled transaction to a worker
+/// thread of `scope`, which commits it while the writes go on into the next one. The next transaction doesn't see the
+/// writes of the committing one before they're committed, so its reads and writes of a key written by the committing
+/// transaction wait for the commit, and so does every iteration, whose keys aren't known in advance. The commits are
+/// sequential: a commit starts once the previous one is done.
+pub struct OverlapTransaction<'scope, T> {
+    tx: T,
+    // the keys written to `tx`
+    written: RefCell<HashSet<Box<[u8]>>>,
+    previous: RefCell<Option<PendingCommit<'scope>>>,
+    // the error of the previous commit once it's waited for, returned by the next `commit_overlapped` or `finish`
+    failed: RefCell<Option<Error>>,
+}
+
+impl<'scope, T> OverlapTransaction<'scope, T> {
+    pub fn new(tx: T) -> Self {
+        OverlapTransaction {
+            tx,
+            written: RefCell::new(HashSet::new()),
+            previous: RefCell::new(None),
+            failed: RefCell::new(None),
+        }
+    }
+
+    // waits for the commit of the previous transaction, if it's still running
+    fn wait(&self) {
+        if let Some(pending) = self.previous.borrow_mut().take() {
+            if let Err(e) = pending.handle.join().expect("commit worker panicked") {
+                *self.failed.borrow_mut() = Some(e);
+            }
+        }
+    }
+
+    // waits for the commit of the previous transaction if it wrote `key`
+    fn wait_for_key(&self, key: &[u8]) {
+        let written = self
+            .previous
+            .borrow()
+            .as_ref()
+            .map_or(false, |pending| pending.written.contains(key));
+        if written {
+            self.wait();
+        }
+    }
+
+    /// Waits for the commit of the previous transaction, then commits this one with `commit` on a worker thread and
+    /// returns `next`, which the writes go on into. Fails with the error of the previous commit, this one is reported
+    /// by the next `commit_overlapped` or by `finish`.
+    pub fn commit_overlapped<C>(
+        self,
+        scope: &'scope Scope<'scope, '_>,
+        next: T,
+        commit: C,
+    ) -> (Self, Result<(), Error>)
+    where
+        T: Send + 'scope,
+        C: FnOnce(T) -> Result<(), Error> + Send + 'scope,
+    {
+        self.wait();
+        let result = self.failed.take().map_or(Ok(()), Err);
+        let OverlapTransaction { tx, written, .. } = self;
+        let pending = PendingCommit {
+            written: written.into_inner(),
+            handle: scope.spawn(move || commit(tx)),
+        };
+        let next = OverlapTransaction::new(next);
+        *next.previous.borrow_mut() = Some(pending);
+        (next, result)
+    }
+
+    /// Waits for the commit of the previous transaction and returns its result. The writes of this transaction are
+    /// not committed.
+    pub fn finish(&self) -> Result<(), Error> {
+        self.wait();
+        self.failed.take().map_or(Ok(()), Err)
+    }
+}
+
+impl<T: Get<ReadOptions>> Get<ReadOptions> for OverlapTransaction<'_, T> {
+    fn get_full<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        readopts: Option<&ReadOptions>,
+    ) -> Result<Option<DBVector>, Error> {
+        self.wait_for_key(key.as_ref());
+        self.tx.get_full(key, readopts)
+    }
+}
+
+impl<W, T: Put<W>> Put<W> for OverlapTransaction<'_, T> {
+    fn put_full<K, V>(&self, key: K, value: V, writeopts: Option<&W>) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        // a write of a key of the committing transaction would conflict with it
+        self.wait_for_key(key.as_ref());
+        self.written.borrow_mut().insert(key.as_ref().into());
+        self.tx.put_full(key, value, writeopts)
+    }
+}
+
+impl<W, T: Delete<W>> Delete<W> for OverlapTransaction<'_, T> {
+    fn delete_full<K: AsRef<[u8]>>(&self, key: K, writeopts: Option<&W>) -> Result<(), Error> {
+        self.wait_for_key(key.as_ref());
+        self.written.borrow_mut().insert(key.as_ref().into());
+        self.tx.delete_full(key, writeopts)
+    }
+}
+
+impl<T: Iterate> Iterate for OverlapTransaction<'_, T> {
+    fn get_raw_iter<'a: 'b, 'b>(&'a self, readopts: &ReadOptions) -> DBRawIterator<'b> {
+        self.wait();
+        self.tx.get_raw_iter(readopts)
+    }
+}
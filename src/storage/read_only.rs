@@ -0,0 +1,52 @@
+use rocksdb::{
+    ops::{Delete, Get, Iterate, Put},
+    DBRawIterator, DBVector, Error, ReadOptions, WriteOptions,
+};
+
+/// A database handle which only reads, e.g. a `ReadOnlyDB` opened next to a running benchmark, where an accumulator
+/// wants a write store: the accumulators opened at a committed sequence never write, so a write is a bug and panics.
+pub struct ReadOnly<D>(pub D);
+
+impl<D: Get<ReadOptions>> Get<ReadOptions> for ReadOnly<D> {
+    fn get_full<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        readopts: Option<&ReadOptions>,
+    ) -> Result<Option<DBVector>, Error> {
+        self.0.get_full(key, readopts)
+    }
+}
+
+impl<D> Put<WriteOptions> for ReadOnly<D> {
+    fn put_full<K, V>(
+        &self,
+        key: K,
+        _value: V,
+        _writeopts: Option<&WriteOptions>,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        panic!("write of key {:02x?} to a read-only database", key.as_ref())
+    }
+}
+
+impl<D> Delete<WriteOptions> for ReadOnly<D> {
+    fn delete_full<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        _writeopts: Option<&WriteOptions>,
+    ) -> Result<(), Error> {
+        panic!(
+            "delete of key {:02x?} from a read-only database",
+            key.as_ref()
+        )
+    }
+}
+
+impl<D: Iterate> Iterate for ReadOnly<D> {
+    fn get_raw_iter<'a: 'b, 'b>(&'a self, readopts: &ReadOptions) -> DBRawIterator<'b> {
+        self.0.get_raw_iter(readopts)
+    }
+}
@@ -0,0 +1,393 @@
+use rocksdb::{
+    prelude::{Delete, Get, Iterate, Open, OpenCF, Put},
+    Direction, IteratorMode, OptimisticTransactionDB, Options, ReadOnlyDB, WriteOptions, DB,
+};
+use std::{sync::Arc, thread};
+use tempfile::Builder;
+
+use crate::{
+    builder,
+    mmr::accumulator::{MMRAccumulator, MMRAccumulatorOwned},
+    smt::accumulator::{SMTAccumulator, SMTAccumulatorOwned},
+    smt_live::accumulator::{
+        SMTAccumulator as SMTLiveAccumulator, SMTAccumulatorOwned as SMTLiveAccumulatorOwned,
+    },
+    workload::Workload,
+    AccumulatorWriter, BlockOps, OutPoint,
+};
+
+use super::{
+    column_family::ColumnFamilyStore,
+    overlap::OverlapTransaction,
+    read_only::ReadOnly,
+    traits::{self, WriteStore},
+    write_batch::BatchedDB,
+};
+
+// fails to compile unless the handle is a store
+fn assert_read_store<T: traits::ReadStore + ?Sized>(_store: &T) {}
+
+fn assert_write_store<T: WriteStore<WO> + ?Sized, WO>(_store: &T) {}
+
+fn out_points() -> Vec<OutPoint> {
+    vec![OutPoint {
+        tx_hash: [1u8; 32],
+        index: 0,
+    }]
+}
+
+// Opens an accumulator as a writer over a `DB` and a transaction, and as a reader over their snapshots, so that the
+// test stops compiling when the bounds of the accumulator and the rocksdb handles drift apart.
+macro_rules! test_handles {
+    ($accumulator: ident) => {{
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db = DB::open_default(tmp_dir.path()).unwrap();
+        assert_write_store::<_, WriteOptions>(&db);
+        let mut accumulator = $accumulator::<_, WriteOptions>::new(&db).unwrap();
+        accumulator.add(out_points()).unwrap();
+        accumulator.commit().unwrap();
+        let snapshot = db.snapshot();
+        assert_read_store(&snapshot);
+        $accumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+        let tx = db.transaction_default();
+        assert_write_store::<_, ()>(&tx);
+        let mut accumulator = $accumulator::<_, ()>::new(&tx).unwrap();
+        accumulator.add(out_points()).unwrap();
+        accumulator.commit().unwrap();
+        tx.commit().unwrap();
+        let snapshot = db.snapshot();
+        assert_read_store(&snapshot);
+        $accumulator::<_, ()>::new_with_sequence(&snapshot, 0).unwrap();
+    }};
+}
+
+#[test]
+fn test_store_handles() {
+    test_handles!(SMTAccumulator);
+    test_handles!(SMTLiveAccumulator);
+    test_handles!(MMRAccumulator);
+}
+
+#[test]
+fn test_owned_store_handles() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+    assert_write_store::<_, WriteOptions>(&*db);
+    SMTAccumulatorOwned::<_, WriteOptions>::new(Arc::clone(&db)).unwrap();
+    SMTLiveAccumulatorOwned::<_, WriteOptions>::new(Arc::clone(&db)).unwrap();
+    MMRAccumulatorOwned::<_, WriteOptions>::new(db).unwrap();
+}
+
+// The accumulators embedded in the column families of a database holding foreign data commit to the same roots as in
+// databases of their own, and leave the default column family as it was.
+#[test]
+fn test_column_family_stores() {
+    let names = ["smt", "smt_live", "mmr"];
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), names).unwrap();
+    // foreign keys shaped like the versioned keys of the accumulators
+    let foreign: Vec<(Vec<u8>, Vec<u8>)> = (0..10u8).map(|i| (vec![i; 40], vec![i; 16])).collect();
+    for (key, value) in &foreign {
+        db.put(key, value).unwrap();
+    }
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..20).map(|i| workload.next_block(i)).collect();
+
+    for name in names {
+        let builder = builder::Builder::from_name(name).unwrap().strict(true);
+        let store = ColumnFamilyStore::new(&db, db.cf_handle(name).unwrap()).unwrap();
+        assert_write_store::<_, WriteOptions>(&store);
+        let embedded = builder
+            .clone()
+            .build::<_, WriteOptions>(&store)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+
+        let dedicated_dir = Builder::new().tempdir().unwrap();
+        let dedicated = DB::open_default(dedicated_dir.path()).unwrap();
+        let expected = builder
+            .clone()
+            .build::<_, WriteOptions>(&dedicated)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+        assert_eq!(expected, embedded, "{}", name);
+
+        // a reader opened at a sequence of the column family
+        let reader = builder
+            .strict(false)
+            .sequence(10)
+            .build::<_, WriteOptions>(&store)
+            .unwrap();
+        assert_eq!((10, embedded[10]), reader.commitment().unwrap(), "{}", name);
+        let snapshot = db.snapshot();
+        assert_read_store(&ColumnFamilyStore::new(&snapshot, db.cf_handle(name).unwrap()).unwrap());
+    }
+
+    let stored: Vec<(Vec<u8>, Vec<u8>)> = db
+        .iterator(IteratorMode::Start)
+        .map(|(key, value)| (key.to_vec(), value.to_vec()))
+        .collect();
+    assert_eq!(foreign, stored);
+}
+
+// The same embedding in a column family of an optimistic transaction database, written through a transaction.
+#[test]
+fn test_column_family_transaction_stores() {
+    let names = ["smt", "smt_live", "mmr"];
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), names).unwrap();
+    db.put([1u8; 40], [1u8; 16]).unwrap();
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..20).map(|i| workload.next_block(i)).collect();
+
+    for name in names {
+        let builder = builder::Builder::from_name(name).unwrap().strict(true);
+        let tx = db.transaction_default();
+        let store = ColumnFamilyStore::new(&tx, db.cf_handle(name).unwrap()).unwrap();
+        assert_write_store::<_, ()>(&store);
+        let embedded = builder
+            .clone()
+            .build::<_, ()>(&store)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+        // the iterator of the column family sees the writes of the transaction
+        let mut iter = store.try_raw_iter(&Default::default()).unwrap();
+        iter.seek_to_first();
+        assert!(iter.valid(), "{}", name);
+        drop(iter);
+        drop(store);
+        tx.commit().unwrap();
+
+        let dedicated_dir = Builder::new().tempdir().unwrap();
+        let dedicated = DB::open_default(dedicated_dir.path()).unwrap();
+        let expected = builder
+            .clone()
+            .build::<_, WriteOptions>(&dedicated)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+        assert_eq!(expected, embedded, "{}", name);
+
+        let snapshot = db.snapshot();
+        let store = ColumnFamilyStore::new(&snapshot, db.cf_handle(name).unwrap()).unwrap();
+        assert_read_store(&store);
+        let reader = builder
+            .strict(false)
+            .sequence(10)
+            .build::<_, ()>(&store)
+            .unwrap();
+        assert_eq!((10, embedded[10]), reader.commitment().unwrap(), "{}", name);
+    }
+
+    let stored: Vec<(Vec<u8>, Vec<u8>)> = db
+        .iterator(IteratorMode::Start)
+        .map(|(key, value)| (key.to_vec(), value.to_vec()))
+        .collect();
+    assert_eq!(vec![(vec![1u8; 40], vec![1u8; 16])], stored);
+}
+
+#[test]
+fn test_overlap_transaction() {
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..20).map(|i| workload.next_block(i)).collect();
+    for name in ["smt", "smt_live", "mmr"] {
+        let builder = builder::Builder::from_name(name).unwrap().strict(true);
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+        let mut overlapped = Vec::new();
+        thread::scope(|scope| {
+            let mut tx = OverlapTransaction::new(db.transaction_default());
+            assert_write_store::<_, ()>(&tx);
+            // every interval of 5 blocks is committed while the next one is written, the accumulator of the next
+            // interval reads the writes of the committing one
+            for interval in blocks.chunks(5) {
+                let commitments = builder
+                    .clone()
+                    .build::<_, ()>(&tx)
+                    .unwrap()
+                    .apply_blocks(interval.to_vec())
+                    .unwrap();
+                overlapped.extend(commitments);
+                let (next, result) =
+                    tx.commit_overlapped(scope, db.transaction_default(), |tx| tx.commit());
+                result.unwrap();
+                tx = next;
+            }
+            tx.finish().unwrap();
+        });
+
+        let dedicated_dir = Builder::new().tempdir().unwrap();
+        let dedicated = DB::open_default(dedicated_dir.path()).unwrap();
+        let expected = builder
+            .clone()
+            .build::<_, WriteOptions>(&dedicated)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+        assert_eq!(expected, overlapped, "{}", name);
+        // every interval was committed
+        let reader = builder
+            .strict(false)
+            .sequence(19)
+            .build::<_, ()>(&db)
+            .unwrap();
+        assert_eq!((19, expected[19]), reader.commitment().unwrap(), "{}", name);
+    }
+}
+
+#[test]
+fn test_batched_db() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let batched = BatchedDB::new(&db);
+    assert_write_store::<_, WriteOptions>(&batched);
+    // a write stays in the batch until it's committed, the batch reads it before
+    batched.put([1u8; 8], [1u8; 8]).unwrap();
+    assert!(db.get([1u8; 8]).unwrap().is_none());
+    assert!(traits::ReadStore::get(&batched, [1u8; 8])
+        .unwrap()
+        .is_some());
+    batched.commit().unwrap();
+    assert_eq!(1, batched.writes());
+    assert!(db.get([1u8; 8]).unwrap().is_some());
+    // a pending write shadows the database value and a pending delete hides it, in point reads and iterations
+    batched.put([2u8; 8], [2u8; 8]).unwrap();
+    batched.put([1u8; 8], [3u8; 8]).unwrap();
+    batched.put([0u8; 8], [0u8; 8]).unwrap();
+    assert_eq!(
+        Some(Box::from(&[3u8; 8][..])),
+        traits::ReadStore::get(&batched, [1u8; 8]).unwrap()
+    );
+    let keys = |mode| {
+        traits::ReadStore::iterator(&batched, mode)
+            .map(|(key, value)| (key[0], value[0]))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![(0, 0), (1, 3), (2, 2)], keys(IteratorMode::Start));
+    assert_eq!(vec![(2, 2), (1, 3), (0, 0)], keys(IteratorMode::End));
+    batched.delete([1u8; 8]).unwrap();
+    assert!(traits::ReadStore::get(&batched, [1u8; 8])
+        .unwrap()
+        .is_none());
+    assert_eq!(vec![(0, 0), (2, 2)], keys(IteratorMode::Start));
+    assert_eq!(
+        vec![(2, 2), (0, 0)],
+        keys(IteratorMode::From(&[2u8; 8], Direction::Reverse))
+    );
+    assert!(db.get([1u8; 8]).unwrap().is_some());
+    batched.commit().unwrap();
+    assert_eq!(2, batched.writes());
+    assert!(db.get([1u8; 8]).unwrap().is_none());
+    assert!(db.get([2u8; 8]).unwrap().is_some());
+
+    // the accumulators read their writes through the batch
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..20).map(|i| workload.next_block(i)).collect();
+    for name in ["smt", "smt_live", "mmr"] {
+        let builder = builder::Builder::from_name(name).unwrap().strict(true);
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db = DB::open_default(tmp_dir.path()).unwrap();
+        let batched = BatchedDB::new(&db);
+        let mut commitments = Vec::new();
+        for interval in blocks.chunks(5) {
+            commitments.extend(
+                builder
+                    .clone()
+                    .build::<_, WriteOptions>(&batched)
+                    .unwrap()
+                    .apply_blocks(interval.to_vec())
+                    .unwrap(),
+            );
+            batched.commit().unwrap();
+        }
+        // one batch per commit interval, however often the accumulator read its writes
+        assert_eq!(4, batched.writes(), "{}", name);
+
+        let dedicated_dir = Builder::new().tempdir().unwrap();
+        let dedicated = DB::open_default(dedicated_dir.path()).unwrap();
+        let expected = builder
+            .build::<_, WriteOptions>(&dedicated)
+            .unwrap()
+            .apply_blocks(blocks.clone())
+            .unwrap();
+        assert_eq!(expected, commitments, "{}", name);
+    }
+}
+
+// The handle of `inspect`: a read-only instance opened next to the writer sees its commits, and a missing path is an
+// error instead of a new empty database.
+#[test]
+fn test_read_only() {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..5).map(|i| workload.next_block(i)).collect();
+    let builder = builder::Builder::from_name("smt").unwrap();
+    builder
+        .clone()
+        .strict(true)
+        .build::<_, WriteOptions>(&db)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+
+    let read_only = ReadOnly(ReadOnlyDB::open(&Options::default(), tmp_dir.path()).unwrap());
+    assert_write_store::<_, WriteOptions>(&read_only);
+    let committed = builder
+        .committed_sequences::<_, WriteOptions>(&read_only)
+        .unwrap();
+    assert_eq!(5, committed.end);
+    let missing = tmp_dir.path().join("missing");
+    assert!(ReadOnlyDB::open(&Options::default(), &missing).is_err());
+    assert!(!missing.exists());
+}
+
+// The read paths beyond the accumulators take a `ReadStore` too, which a bare `ReadOnlyDB` is: fsck checks and sync
+// exports a database opened read-only next to its writer.
+#[test]
+fn test_read_only_store() {
+    macro_rules! check_read_only {
+        ($backend: ident) => {{
+            let tmp_dir = Builder::new().tempdir().unwrap();
+            let db = DB::open_default(tmp_dir.path()).unwrap();
+            let mut workload = Workload::new(0, true);
+            let blocks: Vec<BlockOps> = (0..5).map(|i| workload.next_block(i)).collect();
+            builder::Builder::from_name(stringify!($backend))
+                .unwrap()
+                .strict(true)
+                .build::<_, WriteOptions>(&db)
+                .unwrap()
+                .apply_blocks(blocks)
+                .unwrap();
+
+            let read_only = ReadOnlyDB::open(&Options::default(), tmp_dir.path()).unwrap();
+            assert_read_store(&read_only);
+            let report = crate::$backend::fsck::fsck(&read_only).unwrap();
+            assert!(report.is_ok(), "{}", stringify!($backend));
+            assert_eq!(Some(4), report.sequence, "{}", stringify!($backend));
+            let mut chunks = 0;
+            crate::$backend::sync::export_chunks(&read_only, 4, 16, |_| {
+                chunks += 1;
+                Ok(())
+            })
+            .unwrap();
+            assert!(chunks > 0, "{}", stringify!($backend));
+        }};
+    }
+
+    check_read_only!(smt);
+    check_read_only!(smt_live);
+    check_read_only!(mmr);
+}
@@ -0,0 +1,157 @@
+use rocksdb::{
+    prelude::{Delete, Get, Iterate, Put},
+    DBRawIterator, Direction, Error, IteratorMode, ReadOptions,
+};
+
+/// The rocksdb handles an accumulator reads from: the latest versions of its keys are found with a reverse seek and its
+/// metadata with point reads. `DB`, `OptimisticTransaction` and their snapshots are all read stores, and so is a
+/// `BatchedDB`, which reads the writes it hasn't written to its database yet.
+pub trait ReadStore {
+    type Iter<'a>: RawIterator
+    where
+        Self: 'a;
+
+    /// Returns an unpositioned iterator over the keys of the store.
+    fn raw_iterator(&self) -> Self::Iter<'_>;
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Box<[u8]>>, Error>;
+
+    /// Iterates over the keys of the store from `mode`, like `Iterate::iterator`.
+    fn iterator(&self, mode: IteratorMode) -> Entries<Self::Iter<'_>> {
+        Entries::new(self.raw_iterator(), mode)
+    }
+}
+
+impl<T> ReadStore for T
+where
+    T: Iterate + Get<ReadOptions> + ?Sized,
+{
+    type Iter<'a> = DBRawIterator<'a> where Self: 'a;
+
+    fn raw_iterator(&self) -> DBRawIterator<'_> {
+        self.get_raw_iter(&ReadOptions::default())
+    }
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Box<[u8]>>, Error> {
+        Ok(Get::get(self, key)?.map(|value| Box::from(&*value)))
+    }
+}
+
+/// The rocksdb handles an accumulator writes to, with the write options `WO` of the handle: `WriteOptions` for a `DB`,
+/// `()` for a transaction.
+pub trait WriteStore<WO>: ReadStore + Delete<WO> + Put<WO> {}
+
+impl<T, WO> WriteStore<WO> for T where T: ReadStore + Delete<WO> + Put<WO> + ?Sized {}
+
+/// The raw iterator of a `ReadStore`, positioned by its seeks, like rocksdb's `DBRawIterator`.
+pub trait RawIterator {
+    fn valid(&self) -> bool;
+
+    fn seek_to_first(&mut self);
+
+    fn seek_to_last(&mut self);
+
+    /// Moves to the first key at or after `key`.
+    fn seek(&mut self, key: &[u8]);
+
+    /// Moves to the last key at or before `key`.
+    fn seek_for_prev(&mut self, key: &[u8]);
+
+    fn next(&mut self);
+
+    fn prev(&mut self);
+
+    fn key(&self) -> Option<&[u8]>;
+
+    fn value(&self) -> Option<&[u8]>;
+}
+
+impl RawIterator for DBRawIterator<'_> {
+    fn valid(&self) -> bool {
+        DBRawIterator::valid(self)
+    }
+
+    fn seek_to_first(&mut self) {
+        DBRawIterator::seek_to_first(self)
+    }
+
+    fn seek_to_last(&mut self) {
+        DBRawIterator::seek_to_last(self)
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        DBRawIterator::seek(self, key)
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        DBRawIterator::seek_for_prev(self, key)
+    }
+
+    fn next(&mut self) {
+        DBRawIterator::next(self)
+    }
+
+    fn prev(&mut self) {
+        DBRawIterator::prev(self)
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        DBRawIterator::key(self)
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        DBRawIterator::value(self)
+    }
+}
+
+/// The keys and values of a `ReadStore` from an `IteratorMode`, like rocksdb's `DBIterator`.
+pub struct Entries<I> {
+    raw: I,
+    direction: Direction,
+    // the first entry is the one sought
+    just_seeked: bool,
+}
+
+impl<I: RawIterator> Entries<I> {
+    pub fn new(mut raw: I, mode: IteratorMode) -> Self {
+        let direction = match mode {
+            IteratorMode::Start => {
+                raw.seek_to_first();
+                Direction::Forward
+            }
+            IteratorMode::End => {
+                raw.seek_to_last();
+                Direction::Reverse
+            }
+            IteratorMode::From(key, Direction::Forward) => {
+                raw.seek(key);
+                Direction::Forward
+            }
+            IteratorMode::From(key, Direction::Reverse) => {
+                raw.seek_for_prev(key);
+                Direction::Reverse
+            }
+        };
+        Entries {
+            raw,
+            direction,
+            just_seeked: true,
+        }
+    }
+}
+
+impl<I: RawIterator> Iterator for Entries<I> {
+    type Item = (Box<[u8]>, Box<[u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.just_seeked {
+            self.just_seeked = false;
+        } else {
+            match self.direction {
+                Direction::Forward => self.raw.next(),
+                Direction::Reverse => self.raw.prev(),
+            }
+        }
+        Some((Box::from(self.raw.key()?), Box::from(self.raw.value()?)))
+    }
+}
@@ -0,0 +1,253 @@
+use rocksdb::{
+    ops::{Delete, Put, WriteOps},
+    DBRawIterator, Error, WriteBatch, WriteOptions, DB,
+};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::BTreeMap,
+    ops::Bound,
+};
+
+use super::traits::{RawIterator, ReadStore};
+
+// the writes of a `BatchedDB` since its last commit by key, `None` deletes the key
+type Pending = BTreeMap<Box<[u8]>, Option<Box<[u8]>>>;
+
+/// A plain `DB` whose writes are buffered until `commit`, which writes them in a single `WriteBatch`, the way most
+/// production uses of RocksDB write without transactions. The pending writes are kept in an ordered map, which the reads
+/// see on top of the database, so a commit interval is written atomically whatever it reads.
+pub struct BatchedDB<'a> {
+    db: &'a DB,
+    pending: RefCell<Pending>,
+    // the batches written to the database
+    writes: Cell<u64>,
+}
+
+impl<'a> BatchedDB<'a> {
+    pub fn new(db: &'a DB) -> Self {
+        BatchedDB {
+            db,
+            pending: RefCell::new(BTreeMap::new()),
+            writes: Cell::new(0),
+        }
+    }
+
+    /// Writes the pending writes to the database in a single batch.
+    pub fn commit(&self) -> Result<(), Error> {
+        let pending = self.pending.take();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let mut batch = WriteBatch::default();
+        for (key, value) in pending {
+            match value {
+                Some(value) => batch.put(key, value)?,
+                None => batch.delete(key)?,
+            }
+        }
+        self.writes.set(self.writes.get() + 1);
+        self.db.write(&batch)
+    }
+
+    /// The number of batches written to the database, one per commit with pending writes.
+    pub fn writes(&self) -> u64 {
+        self.writes.get()
+    }
+}
+
+impl ReadStore for BatchedDB<'_> {
+    type Iter<'b> = BatchedIterator<'b> where Self: 'b;
+
+    fn raw_iterator(&self) -> BatchedIterator<'_> {
+        BatchedIterator {
+            pending: &self.pending,
+            db: ReadStore::raw_iterator(self.db),
+            batch: None,
+            forward: true,
+        }
+    }
+
+    fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Box<[u8]>>, Error> {
+        match self.pending.borrow().get(key.as_ref()) {
+            Some(value) => Ok(value.clone()),
+            None => ReadStore::get(self.db, key),
+        }
+    }
+}
+
+impl Put<WriteOptions> for BatchedDB<'_> {
+    fn put_full<K, V>(
+        &self,
+        key: K,
+        value: V,
+        _writeopts: Option<&WriteOptions>,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.pending
+            .borrow_mut()
+            .insert(key.as_ref().into(), Some(value.as_ref().into()));
+        Ok(())
+    }
+}
+
+impl Delete<WriteOptions> for BatchedDB<'_> {
+    fn delete_full<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        _writeopts: Option<&WriteOptions>,
+    ) -> Result<(), Error> {
+        self.pending.borrow_mut().insert(key.as_ref().into(), None);
+        Ok(())
+    }
+}
+
+/// The raw iterator of a `BatchedDB`, it merges the pending writes into an iterator of the database. A pending write
+/// shadows the database value of its key, and a pending delete hides it.
+pub struct BatchedIterator<'a> {
+    pending: &'a RefCell<Pending>,
+    db: DBRawIterator<'a>,
+    // the pending write at or beyond the position of `db` in the direction of the iteration
+    batch: Option<(Box<[u8]>, Option<Box<[u8]>>)>,
+    forward: bool,
+}
+
+impl BatchedIterator<'_> {
+    // the first pending write within `range`, or the last one when iterating backwards
+    fn pending_write(
+        &self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+    ) -> Option<(Box<[u8]>, Option<Box<[u8]>>)> {
+        let pending = self.pending.borrow();
+        let mut writes = pending.range::<[u8], _>(range);
+        let write = if self.forward {
+            writes.next()
+        } else {
+            writes.next_back()
+        };
+        write.map(|(key, value)| (key.clone(), value.clone()))
+    }
+
+    // whether the current entry is the pending write rather than the database one, they have the same key when the
+    // write shadows the database value
+    fn at_batch(&self) -> Option<Ordering> {
+        let (key, _) = self.batch.as_ref()?;
+        let ordering = match self.db.key() {
+            Some(db_key) if self.forward => (**key).cmp(db_key),
+            Some(db_key) => db_key.cmp(&**key),
+            None => Ordering::Less,
+        };
+        (ordering != Ordering::Greater).then_some(ordering)
+    }
+
+    // moves past the current entry, the pending write and its database value when they have the same key
+    fn advance(&mut self) {
+        match self.at_batch() {
+            Some(ordering) => {
+                if ordering == Ordering::Equal {
+                    self.step_db();
+                }
+                let (key, _) = self.batch.take().expect("at a pending write");
+                self.batch = self.pending_write(self.beyond(&key));
+            }
+            None => self.step_db(),
+        }
+    }
+
+    fn step_db(&mut self) {
+        if self.forward {
+            self.db.next()
+        } else {
+            self.db.prev()
+        }
+    }
+
+    // the keys beyond `key` in the direction of the iteration
+    fn beyond<'k>(&self, key: &'k [u8]) -> (Bound<&'k [u8]>, Bound<&'k [u8]>) {
+        if self.forward {
+            (Bound::Excluded(key), Bound::Unbounded)
+        } else {
+            (Bound::Unbounded, Bound::Excluded(key))
+        }
+    }
+
+    // skips the pending deletes and the database values they hide
+    fn skip_deletes(&mut self) {
+        while matches!((self.at_batch(), &self.batch), (Some(_), Some((_, None)))) {
+            self.advance();
+        }
+    }
+
+    // positions the pending writes after a seek of `db`
+    fn seek_batch(&mut self, forward: bool, range: (Bound<&[u8]>, Bound<&[u8]>)) {
+        self.forward = forward;
+        self.batch = self.pending_write(range);
+        self.skip_deletes();
+    }
+}
+
+impl RawIterator for BatchedIterator<'_> {
+    fn valid(&self) -> bool {
+        self.key().is_some()
+    }
+
+    fn seek_to_first(&mut self) {
+        self.db.seek_to_first();
+        self.seek_batch(true, (Bound::Unbounded, Bound::Unbounded));
+    }
+
+    fn seek_to_last(&mut self) {
+        self.db.seek_to_last();
+        self.seek_batch(false, (Bound::Unbounded, Bound::Unbounded));
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.db.seek(key);
+        self.seek_batch(true, (Bound::Included(key), Bound::Unbounded));
+    }
+
+    fn seek_for_prev(&mut self, key: &[u8]) {
+        self.db.seek_for_prev(key);
+        self.seek_batch(false, (Bound::Unbounded, Bound::Included(key)));
+    }
+
+    fn next(&mut self) {
+        if !self.forward {
+            // positions both iterators at the current key going forwards
+            let Some(key) = self.key().map(<[u8]>::to_vec) else {
+                return;
+            };
+            self.seek(&key);
+        }
+        self.advance();
+        self.skip_deletes();
+    }
+
+    fn prev(&mut self) {
+        if self.forward {
+            let Some(key) = self.key().map(<[u8]>::to_vec) else {
+                return;
+            };
+            self.seek_for_prev(&key);
+        }
+        self.advance();
+        self.skip_deletes();
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        match (self.at_batch(), &self.batch) {
+            (Some(_), Some((key, _))) => Some(&**key),
+            _ => self.db.key(),
+        }
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        match (self.at_batch(), &self.batch) {
+            (Some(_), Some((_, value))) => value.as_deref(),
+            _ => self.db.value(),
+        }
+    }
+}
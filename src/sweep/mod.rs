@@ -0,0 +1,93 @@
+use crate::{workload::Timelocks, AccumulatorError, CellStatus};
+use std::{fmt, time::Duration};
+
+#[cfg(test)]
+mod tests;
+
+/// Accumulators which can consume the cells matching a predicate by scanning their stored cells, simulating a protocol
+/// level expiry, e.g. the sweep of the cells whose timelock expired, which deletes large correlated batches of cells.
+pub trait SweepCells {
+    /// Consumes at the current sequence every live cell created before the sequence `created_before` for which
+    /// `predicate` holds, given the out_point hash and the status of the cell, in a single tree update. Returns the
+    /// swept cells and the times of the scan and of the update.
+    fn sweep<F>(&mut self, _created_before: u64, _predicate: F) -> Result<Swept, AccumulatorError>
+    where
+        F: FnMut(&[u8; 32], &CellStatus) -> bool,
+    {
+        Err(AccumulatorError::InternalError(
+            "the accumulator can't sweep its cells".to_string(),
+        ))
+    }
+}
+
+/// The result of a `SweepCells::sweep`. The scan reads every stored cell and grows with the state, while the update of
+/// the tree is the cost of the correlated deletes, which grows with the swept cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Swept {
+    pub cells: u64,
+    pub scan: Duration,
+    pub update: Duration,
+}
+
+/// The expiry sweeps of a benchmark run: every `interval` blocks, the timelocked cells of the workload created more
+/// than `age` sequences before are swept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpirySweep {
+    pub interval: u64,
+    pub age: u64,
+    pub timelocks: Timelocks,
+}
+
+impl ExpirySweep {
+    pub fn is_due(&self, block_number: u64) -> bool {
+        block_number % self.interval == self.interval - 1
+    }
+}
+
+/// The number of cells swept by the sweeps of a run and the durations of their scans and updates.
+#[derive(Clone, Debug, Default)]
+pub struct SweepStats {
+    sweeps: u64,
+    cells: u64,
+    scan: Duration,
+    update: Duration,
+    max_update: Duration,
+}
+
+impl SweepStats {
+    pub fn record(&mut self, swept: &Swept) {
+        self.sweeps += 1;
+        self.cells += swept.cells;
+        self.scan += swept.scan;
+        self.update += swept.update;
+        self.max_update = self.max_update.max(swept.update);
+    }
+
+    pub fn sweeps(&self) -> u64 {
+        self.sweeps
+    }
+
+    pub fn cells(&self) -> u64 {
+        self.cells
+    }
+
+    /// Swept cells per second of tree updates, the scans excluded.
+    pub fn throughput(&self) -> f64 {
+        self.cells as f64 / self.update.as_secs_f64().max(0.000_001)
+    }
+}
+
+impl fmt::Display for SweepStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expiry sweeps {} sweeps, {} cells, scans {} millis, updates {} millis, max update {} millis, {:.0} cells/s updated",
+            self.sweeps,
+            self.cells,
+            self.scan.as_millis(),
+            self.update.as_millis(),
+            self.max_update.as_millis(),
+            self.throughput()
+        )
+    }
+}
@@ -0,0 +1,126 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use std::time::Duration;
+use tempfile::Builder;
+
+use crate::{
+    mmr::accumulator::MMRAccumulator,
+    smt::accumulator::SMTAccumulator,
+    smt_live::accumulator::SMTAccumulator as SMTLiveAccumulator,
+    workload::{Timelocks, Workload},
+    AccumulatorError, AccumulatorWriter, OutPoint,
+};
+
+use super::{SweepCells, SweepStats, Swept};
+
+// the cells created at a sequence
+fn cells(sequence: u8) -> Vec<OutPoint> {
+    (0..8)
+        .map(|index| OutPoint {
+            tx_hash: [sequence; 32],
+            index,
+        })
+        .collect()
+}
+
+fn is_even(hash: &[u8; 32]) -> bool {
+    hash[0] % 2 == 0
+}
+
+// Sweeps the even cells created before sequence 2 of a database, and explicitly deletes the same cells from another
+// one: the commitments must match and the swept cells must be dead, a consumed cell isn't swept again.
+macro_rules! test_sweep {
+    ($accumulator: ident) => {{
+        let mut commitments = Vec::new();
+        for sweep in [true, false] {
+            let tmp_dir = Builder::new().tempdir().unwrap();
+            let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+            let tx = db.transaction_default();
+            let mut accumulator = $accumulator::new_with_strict(&tx, true).unwrap();
+            for sequence in 0..3 {
+                accumulator.add(cells(sequence)).unwrap();
+                if sequence == 2 {
+                    accumulator.delete(vec![cells(0)[0].clone()]).unwrap();
+                }
+                accumulator.commit().unwrap();
+            }
+
+            let expected: Vec<OutPoint> = cells(0)
+                .into_iter()
+                .skip(1)
+                .chain(cells(1))
+                .filter(|out_point| is_even(&out_point.hash()))
+                .collect();
+            assert!(!expected.is_empty());
+            if sweep {
+                let swept = accumulator
+                    .sweep(2, |hash, status| {
+                        assert!(status.is_live() && status.created_by() < 2);
+                        is_even(hash)
+                    })
+                    .unwrap();
+                assert_eq!(expected.len() as u64, swept.cells);
+            } else {
+                accumulator.delete(expected.clone()).unwrap();
+            }
+            commitments.push(accumulator.commit().unwrap().to_bytes());
+
+            for out_point in cells(0).into_iter().chain(cells(1)).chain(cells(2)) {
+                let status = accumulator.cell_status(&out_point).unwrap().unwrap();
+                let consumed = expected.contains(&out_point) || out_point == cells(0)[0];
+                assert_eq!(!consumed, status.is_live(), "{:?}", out_point);
+            }
+        }
+        assert_eq!(commitments[0], commitments[1]);
+    }};
+}
+
+#[test]
+fn test_sweep() {
+    test_sweep!(SMTAccumulator);
+    test_sweep!(SMTLiveAccumulator);
+
+    // the mmr can't recover the out_points of its leaves
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+    accumulator.add(cells(0)).unwrap();
+    assert!(matches!(
+        accumulator.sweep(1, |_hash, _status| true),
+        Err(AccumulatorError::InternalError(_))
+    ));
+}
+
+#[test]
+fn test_timelocked_workload() {
+    let timelocks = Timelocks { percent: 30 };
+    let mut workload = Workload::new(0, true).with_timelocks(timelocks);
+    let mut locked = 0;
+    for i in 0..300 {
+        let block = workload.next_block(i);
+        locked += block
+            .added
+            .iter()
+            .filter(|out_point| timelocks.is_locked(&out_point.hash()))
+            .count();
+        assert!(block
+            .deleted
+            .iter()
+            .all(|out_point| !timelocks.is_locked(&out_point.hash())));
+    }
+    // about 30% of the 3000 cells
+    assert!((700..1100).contains(&locked), "{}", locked);
+
+    // the throughput is of the updates, the scans aren't counted
+    let mut stats = SweepStats::default();
+    for (cells, scan, update) in [(10, 50, 2), (30, 70, 6)] {
+        stats.record(&Swept {
+            cells,
+            scan: Duration::from_millis(scan),
+            update: Duration::from_millis(update),
+        });
+    }
+    assert_eq!(2, stats.sweeps());
+    assert_eq!(40, stats.cells());
+    assert_eq!(5000.0, stats.throughput());
+}
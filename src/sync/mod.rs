@@ -0,0 +1,34 @@
+use crate::AccumulatorError;
+
+#[cfg(test)]
+mod tests;
+
+/// A chunk of the state of an accumulator at a commitment, streamed by a server to a syncing client. The entries are in
+/// the order of the backend (leaf key order for the SMTs, leaf position order for the MMR), the proof proves all of them
+/// against the commitment, see the `sync` module of each backend for their format.
+#[derive(Clone, Debug, Default)]
+pub struct SyncChunk {
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    pub proof: Vec<u8>,
+}
+
+impl SyncChunk {
+    /// The number of bytes of the entries and the proof, i.e. the bandwidth used by the chunk.
+    pub fn size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum::<usize>()
+            + self.proof.len()
+    }
+}
+
+/// A client building its accumulator from the chunks exported by a server at a trusted commitment, into an empty database.
+pub trait SyncClient {
+    /// Verifies a chunk against the commitment and loads its entries, the chunks must be ingested in the exported order.
+    fn ingest(&mut self, chunk: SyncChunk) -> Result<(), AccumulatorError>;
+
+    /// Commits the loaded state at the sequence of the commitment, the rebuilt root must match the commitment,
+    /// otherwise some chunks are missing.
+    fn finish(self) -> Result<(), AccumulatorError>;
+}
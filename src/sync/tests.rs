@@ -0,0 +1,126 @@
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+use tempfile::{Builder, TempDir};
+
+use crate::{
+    mmr::{self, accumulator::MMRAccumulator, sync::MMRSyncClient},
+    smt::{self, accumulator::SMTAccumulator, sync::SMTSyncClient},
+    smt_live::{self, accumulator::SMTAccumulator as SMTLiveAccumulator, sync::SMTLiveSyncClient},
+    workload::Workload,
+    AccumulatorError, AccumulatorWriter, BlockOps,
+};
+
+use super::{SyncChunk, SyncClient};
+
+// consumed cells are drawn after block 100
+const BLOCKS: u64 = 130;
+const SYNC_SEQUENCE: u64 = 120;
+
+fn open_db() -> (OptimisticTransactionDB, TempDir) {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    (
+        OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap(),
+        tmp_dir,
+    )
+}
+
+// Syncs a client to a historical sequence of a server in small chunks, then the client applies the following blocks
+// and must reproduce the commitments of the server.
+macro_rules! test_sync {
+    ($name:ident, $accumulator:ident, $client:ident, $export:path) => {
+        #[test]
+        fn $name() {
+            let mut workload = Workload::new(0, true);
+            let blocks: Vec<BlockOps> = (0..BLOCKS).map(|i| workload.next_block(i)).collect();
+            let (server, _server_dir) = open_db();
+            let tx = server.transaction_default();
+            let commitments = $accumulator::<_, ()>::new(&tx)
+                .unwrap()
+                .apply_blocks(blocks.clone())
+                .unwrap();
+            tx.commit().unwrap();
+
+            let (client, _client_dir) = open_db();
+            let tx = client.transaction_default();
+            let mut sync_client =
+                $client::<_, ()>::new(&tx, commitments[SYNC_SEQUENCE as usize].clone()).unwrap();
+            let snapshot = server.snapshot();
+            let mut chunks = 0;
+            $export(&snapshot, SYNC_SEQUENCE, 7, |chunk| {
+                chunks += 1;
+                sync_client.ingest(chunk)
+            })
+            .unwrap();
+            assert!(chunks > 1);
+            sync_client.finish().unwrap();
+
+            let synced = $accumulator::<_, ()>::new(&tx)
+                .unwrap()
+                .apply_blocks(blocks[SYNC_SEQUENCE as usize + 1..].to_vec())
+                .unwrap();
+            for (synced, expected) in synced
+                .iter()
+                .zip(&commitments[SYNC_SEQUENCE as usize + 1..])
+            {
+                assert_eq!(expected.commitment_digest(), synced.commitment_digest());
+            }
+            tx.commit().unwrap();
+        }
+    };
+}
+
+test_sync!(
+    test_sync_smt,
+    SMTAccumulator,
+    SMTSyncClient,
+    smt::sync::export_chunks
+);
+test_sync!(
+    test_sync_smt_live,
+    SMTLiveAccumulator,
+    SMTLiveSyncClient,
+    smt_live::sync::export_chunks
+);
+test_sync!(
+    test_sync_mmr,
+    MMRAccumulator,
+    MMRSyncClient,
+    mmr::sync::export_chunks
+);
+
+#[test]
+fn test_sync_rejects_tampered_chunks() {
+    let mut workload = Workload::new(0, true);
+    let (server, _server_dir) = open_db();
+    let tx = server.transaction_default();
+    let commitment = SMTAccumulator::<_, ()>::new(&tx)
+        .unwrap()
+        .apply_blocks((0..10).map(|i| workload.next_block(i)).collect())
+        .unwrap()
+        .remove(9);
+    tx.commit().unwrap();
+    let mut chunks: Vec<SyncChunk> = Vec::new();
+    smt::sync::export_chunks(&server, 9, 30, |chunk| {
+        chunks.push(chunk);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(4, chunks.len());
+
+    let (client, _client_dir) = open_db();
+    let tx = client.transaction_default();
+    let mut sync_client = SMTSyncClient::<_, ()>::new(&tx, commitment).unwrap();
+    let mut tampered = chunks[0].clone();
+    tampered.entries[0].1[0] ^= 1;
+    assert!(matches!(
+        sync_client.ingest(tampered),
+        Err(AccumulatorError::InvalidProof)
+    ));
+    // a skipped chunk is only noticed by the root of the loaded state
+    for chunk in chunks.into_iter().skip(1) {
+        sync_client.ingest(chunk).unwrap();
+    }
+    assert!(matches!(
+        sync_client.finish(),
+        Err(AccumulatorError::InvalidCommitment)
+    ));
+}
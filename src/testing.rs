@@ -0,0 +1,114 @@
+use std::{collections::BTreeSet, path::Path};
+
+use rocksdb::{prelude::Open, OptimisticTransactionDB};
+
+use crate::{
+    builder::Builder, workload::Workload, AccumulatorError, AccumulatorWriter, BlockOps,
+    CommitmentDigest, OutPoint,
+};
+
+/// Builds a database populated with blocks of the deterministic benchmark workload for a backend, so that the tests of
+/// a feature start from a realistic state instead of each writing its own population loop.
+#[derive(Clone, Debug)]
+pub struct FixtureBuilder {
+    backend: String,
+    blocks: u64,
+    strict: bool,
+    outputs_per_tx: u64,
+}
+
+impl FixtureBuilder {
+    /// A fixture of the first 100 blocks for a backend by its name, smt | smt_live | mmr, written in strict mode.
+    pub fn new(backend: &str) -> Self {
+        FixtureBuilder {
+            backend: backend.to_string(),
+            blocks: 100,
+            strict: true,
+            outputs_per_tx: 1,
+        }
+    }
+
+    pub fn blocks(mut self, blocks: u64) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn outputs_per_tx(mut self, outputs_per_tx: u64) -> Self {
+        self.outputs_per_tx = outputs_per_tx;
+        self
+    }
+
+    /// Opens a new optimistic transaction database at `path` and commits the blocks into it, one sequence per block,
+    /// in a single RocksDB transaction.
+    pub fn build<P: AsRef<Path>>(self, path: P) -> Result<Fixture, AccumulatorError> {
+        let builder = Builder::from_name(&self.backend).ok_or_else(|| {
+            AccumulatorError::InternalError(format!("unknown backend {}", self.backend))
+        })?;
+        // the workload consumes cells of the previous blocks, so a fixture always starts from block 0
+        let mut workload = Workload::new(0, self.strict).with_outputs_per_tx(self.outputs_per_tx);
+        let blocks: Vec<BlockOps> = (0..self.blocks).map(|i| workload.next_block(i)).collect();
+
+        let map_err = |e: rocksdb::Error| AccumulatorError::InternalError(e.to_string());
+        let db = OptimisticTransactionDB::open_default(path).map_err(map_err)?;
+        let tx = db.transaction_default();
+        let commitments = builder
+            .clone()
+            .strict(self.strict)
+            .build::<_, ()>(&tx)?
+            .apply_blocks(blocks.clone())?;
+        tx.commit().map_err(map_err)?;
+        Ok(Fixture {
+            db,
+            builder: builder.strict(self.strict),
+            blocks,
+            commitments,
+        })
+    }
+}
+
+/// A database populated by a `FixtureBuilder`, with the blocks written and the commitment digest of each.
+pub struct Fixture {
+    db: OptimisticTransactionDB,
+    builder: Builder,
+    blocks: Vec<BlockOps>,
+    commitments: Vec<CommitmentDigest>,
+}
+
+impl Fixture {
+    pub fn db(&self) -> &OptimisticTransactionDB {
+        &self.db
+    }
+
+    /// The builder of the backend of the fixture, with its strict mode, to open accumulators on its database, e.g. at
+    /// a sequence with `Builder::sequence`.
+    pub fn builder(&self) -> Builder {
+        self.builder.clone()
+    }
+
+    /// The blocks written, the block of index `i` is committed at sequence `i`.
+    pub fn blocks(&self) -> &[BlockOps] {
+        &self.blocks
+    }
+
+    /// The commitment digest of every block, in order.
+    pub fn commitments(&self) -> &[CommitmentDigest] {
+        &self.commitments
+    }
+
+    /// The cells live after the last block, in out_point order.
+    pub fn live_cells(&self) -> Vec<OutPoint> {
+        let mut live = BTreeSet::new();
+        for block in &self.blocks {
+            live.extend(block.added.iter().cloned());
+            for out_point in &block.deleted {
+                live.remove(out_point);
+            }
+        }
+        live.into_iter().collect()
+    }
+}
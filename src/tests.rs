@@ -0,0 +1,2259 @@
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaChaRng,
+};
+use rocksdb::{
+    prelude::{Delete, Get, Iterate, Open, Put},
+    IteratorMode, OptimisticTransactionDB,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tempfile::{Builder, NamedTempFile, TempDir};
+
+use crate::{
+    builder::{self, AccumulatorBuilder},
+    chain::{Header, HeaderChain},
+    ct_eq,
+    db_options::DbOptions,
+    expiry::{VersionExpiry, VersionedKeys},
+    inspect::{parse_command, parse_hex, parse_out_point, Command},
+    layout::dump_layout,
+    metrics::{
+        cumulative_stall, CellCosts, CoalescedOps, CommitSweep, KeyTracer, ReportClock,
+        ReportInterval, TraceKeys, WriteCounts, WriteStalls,
+    },
+    mmr::{self, accumulator::MMRAccumulator},
+    oplog::OpLog,
+    retry::{
+        is_conflict, replay_blocks, CommitConflicts, RetryPolicy, StatusCode, UncommittedBlock,
+    },
+    sequence_width::{
+        key_versions, migrate_sequence_width, set_sequence_width, skipped_sequences, SequenceWidth,
+    },
+    smt::{self, accumulator::SMTAccumulator, sharded::ShardedSMTAccumulator},
+    smt_live::{self, accumulator::SMTAccumulator as SMTLiveAccumulator},
+    testing::FixtureBuilder,
+    workload::{reorder, sample_live_cells, Reordering, Spikes, Workload},
+    AccumulatorError, AccumulatorReader, AccumulatorWriter, BlockOps, CellStatus, OutPoint, Proof,
+    SequenceSource, ShortHex,
+};
+
+// consumed cells are drawn after block 100
+const BLOCKS: u64 = 150;
+
+fn open_db() -> (OptimisticTransactionDB, TempDir) {
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    (
+        OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap(),
+        tmp_dir,
+    )
+}
+
+// Runs the same workload through every backend, and at random checkpoints opens readers at several historical sequences
+// of each backend, which must agree on the status of sampled out_points and verify their proofs.
+#[test]
+fn test_historical_equivalence() {
+    let (smt_db, _smt_dir) = open_db();
+    let (smt_live_db, _smt_live_dir) = open_db();
+    let (mmr_db, _mmr_dir) = open_db();
+    let mut rng = ChaChaRng::from_seed([2u8; 32]);
+    let mut workload = Workload::new(0, true);
+    let mut created = Vec::new();
+    let mut commitments = Vec::new();
+
+    let mut block_number = 0;
+    while block_number < BLOCKS {
+        // a checkpoint every 1 to 40 blocks, the pending blocks are written in one transaction per backend
+        let checkpoint = (block_number + 1 + rng.next_u64() % 40).min(BLOCKS);
+        let (smt_tx, smt_live_tx, mmr_tx) = (
+            smt_db.transaction_default(),
+            smt_live_db.transaction_default(),
+            mmr_db.transaction_default(),
+        );
+        let mut smt = SMTAccumulator::<_, ()>::new_with_strict(&smt_tx, true).unwrap();
+        let mut smt_live =
+            SMTLiveAccumulator::<_, ()>::new_with_strict(&smt_live_tx, true).unwrap();
+        let mut mmr = MMRAccumulator::<_, ()>::new_with_strict(&mmr_tx, true).unwrap();
+        for i in block_number..checkpoint {
+            let ops = workload.next_block(i);
+            created.extend(ops.added.iter().cloned());
+            commitments.push((
+                smt.apply_blocks(vec![ops.clone()]).unwrap().remove(0),
+                smt_live.apply_blocks(vec![ops.clone()]).unwrap().remove(0),
+                mmr.apply_blocks(vec![ops]).unwrap().remove(0),
+            ));
+        }
+        smt_tx.commit().unwrap();
+        smt_live_tx.commit().unwrap();
+        mmr_tx.commit().unwrap();
+        block_number = checkpoint;
+
+        let (smt_snapshot, smt_live_snapshot, mmr_snapshot) =
+            (smt_db.snapshot(), smt_live_db.snapshot(), mmr_db.snapshot());
+        for _ in 0..3 {
+            let sequence = rng.next_u64() % block_number;
+            let (smt_commitment, smt_live_commitment, mmr_commitment) =
+                &commitments[sequence as usize];
+            let smt = SMTAccumulator::<_, ()>::new_with_sequence(&smt_snapshot, sequence).unwrap();
+            let smt_live =
+                SMTLiveAccumulator::<_, ()>::new_with_sequence(&smt_live_snapshot, sequence)
+                    .unwrap();
+            let mmr = MMRAccumulator::<_, ()>::new_with_sequence(&mmr_snapshot, sequence).unwrap();
+
+            // cells created after the sequence and an unknown cell must be missing everywhere
+            let mut out_points: Vec<_> = (0..8)
+                .map(|_| created[(rng.next_u64() % created.len() as u64) as usize].clone())
+                .collect();
+            out_points.push(OutPoint {
+                tx_hash: [u8::MAX; 32],
+                index: 1,
+            });
+            for out_point in out_points {
+                let status = smt.cell_status(&out_point).unwrap();
+                assert_eq!(
+                    status,
+                    smt_live.cell_status(&out_point).unwrap(),
+                    "smt_live {:?} of tx {} at {:?}",
+                    out_point.index,
+                    ShortHex(&out_point.tx_hash),
+                    smt_live_commitment
+                );
+                assert_eq!(
+                    status,
+                    mmr.cell_status(&out_point).unwrap(),
+                    "mmr {:?} of tx {} at {:?}",
+                    out_point.index,
+                    ShortHex(&out_point.tx_hash),
+                    mmr_commitment
+                );
+
+                let elements = vec![out_point.clone()];
+                let (smt_proof, smt_live_proof, mmr_proof) = (
+                    smt.proof(smt_commitment.clone(), elements.clone()),
+                    smt_live.proof(smt_live_commitment.clone(), elements.clone()),
+                    mmr.proof(mmr_commitment.clone(), elements),
+                );
+                let status = match status {
+                    Some(status) => status,
+                    None => {
+                        assert!(matches!(
+                            smt_proof,
+                            Err(AccumulatorError::ElementNotFound(0))
+                        ));
+                        assert!(matches!(
+                            smt_live_proof,
+                            Err(AccumulatorError::ElementNotFound(0))
+                        ));
+                        assert!(matches!(
+                            mmr_proof,
+                            Err(AccumulatorError::ElementNotFound(0))
+                        ));
+                        continue;
+                    }
+                };
+                let leaves = vec![(out_point, status.clone())];
+                assert!(smt_proof
+                    .unwrap()
+                    .verify(smt_commitment.clone(), leaves.clone())
+                    .unwrap());
+                assert!(mmr_proof
+                    .unwrap()
+                    .verify(mmr_commitment.clone(), leaves.clone())
+                    .unwrap());
+                // a consumed cell of smt_live is proved against its consuming commitment too, which the reader doesn't provide
+                if status.is_live() {
+                    assert!(smt_live_proof
+                        .unwrap()
+                        .verify(vec![(smt_live_commitment.clone(), None)], leaves)
+                        .unwrap());
+                }
+            }
+        }
+    }
+}
+
+// Runs two compactions of the whole database through `expiry`, deleting the versions they drop, and returns the number
+// of dropped versions. A superseded version is only dropped by the compaction after the one which sees it superseded.
+fn compact_versions(db: &OptimisticTransactionDB, expiry: &VersionExpiry) -> usize {
+    let mut dropped = 0;
+    for _ in 0..2 {
+        let expired: Vec<Box<[u8]>> = db
+            .iterator(IteratorMode::Start)
+            .filter(|(key, value)| expiry.expires(key, value))
+            .map(|(key, _)| key)
+            .collect();
+        dropped += expired.len();
+        for key in expired {
+            db.delete(key).unwrap();
+        }
+    }
+    dropped
+}
+
+// Expires the superseded versions of the smt and smt_live keys, the readers at the horizon or later must still see the
+// same statuses and prove them against the same commitments.
+#[test]
+fn test_version_expiry_keeps_history() {
+    const EXPIRY_BLOCKS: u64 = 40;
+    const RETENTION: u64 = 10;
+    let (smt_db, _smt_dir) = open_db();
+    let (smt_live_db, _smt_live_dir) = open_db();
+    let mut workload = Workload::new(0, true);
+    let mut created = Vec::new();
+    let mut commitments = Vec::new();
+    let (smt_tx, smt_live_tx) = (
+        smt_db.transaction_default(),
+        smt_live_db.transaction_default(),
+    );
+    let mut smt = SMTAccumulator::<_, ()>::new_with_strict(&smt_tx, true).unwrap();
+    let mut smt_live = SMTLiveAccumulator::<_, ()>::new_with_strict(&smt_live_tx, true).unwrap();
+    for i in 0..EXPIRY_BLOCKS {
+        let ops = workload.next_block(i);
+        created.extend(ops.added.iter().cloned());
+        commitments.push((
+            smt.apply_blocks(vec![ops.clone()]).unwrap().remove(0),
+            smt_live.apply_blocks(vec![ops]).unwrap().remove(0),
+        ));
+    }
+    smt_tx.commit().unwrap();
+    smt_live_tx.commit().unwrap();
+
+    let horizon = EXPIRY_BLOCKS - 1 - RETENTION;
+    let statuses = |sequence: u64| -> Vec<_> {
+        let smt = SMTAccumulator::<_, ()>::new_with_sequence(&smt_db, sequence).unwrap();
+        let smt_live =
+            SMTLiveAccumulator::<_, ()>::new_with_sequence(&smt_live_db, sequence).unwrap();
+        created
+            .iter()
+            .map(|out_point| {
+                (
+                    smt.cell_status(out_point).unwrap(),
+                    smt_live.cell_status(out_point).unwrap(),
+                )
+            })
+            .collect()
+    };
+    let before: Vec<_> = (horizon..EXPIRY_BLOCKS).map(&statuses).collect();
+
+    let smt_expiry = VersionExpiry::new(VersionedKeys::Smt, RETENTION);
+    let smt_live_expiry = VersionExpiry::new(VersionedKeys::SmtLive, RETENTION);
+    smt_expiry.advance(EXPIRY_BLOCKS - 1);
+    smt_live_expiry.advance(EXPIRY_BLOCKS - 1);
+    assert!(compact_versions(&smt_db, &smt_expiry) > 0);
+    assert!(compact_versions(&smt_live_db, &smt_live_expiry) > 0);
+    assert_eq!(0, smt_expiry.pending());
+    assert!(smt_expiry.expired_bytes() > 0);
+    // every superseded version is gone, another compaction drops nothing
+    assert_eq!(0, compact_versions(&smt_db, &smt_expiry));
+    assert_eq!(0, compact_versions(&smt_live_db, &smt_live_expiry));
+
+    for (sequence, before) in (horizon..EXPIRY_BLOCKS).zip(before) {
+        assert_eq!(before, statuses(sequence), "sequence {}", sequence);
+        let (smt_commitment, smt_live_commitment) = &commitments[sequence as usize];
+        let smt = SMTAccumulator::<_, ()>::new_with_sequence(&smt_db, sequence).unwrap();
+        let smt_live =
+            SMTLiveAccumulator::<_, ()>::new_with_sequence(&smt_live_db, sequence).unwrap();
+        for (out_point, (status, _)) in created.iter().zip(before) {
+            let status = match status {
+                Some(status) if status.is_live() => status,
+                _ => continue,
+            };
+            let leaves = vec![(out_point.clone(), status)];
+            assert!(smt
+                .proof(smt_commitment.clone(), vec![out_point.clone()])
+                .unwrap()
+                .verify(smt_commitment.clone(), leaves.clone())
+                .unwrap());
+            assert!(smt_live
+                .proof(smt_live_commitment.clone(), vec![out_point.clone()])
+                .unwrap()
+                .verify(vec![(smt_live_commitment.clone(), None)], leaves)
+                .unwrap());
+        }
+    }
+}
+
+// The commands of the inspect prompt, with the sequences defaulting to the latest one and the malformed arguments
+// reported to the user.
+#[test]
+fn test_parse_inspect_command() {
+    let tx_hash = "ab".repeat(32);
+    let out_point = OutPoint {
+        tx_hash: [0xab; 32],
+        index: 3,
+    };
+    assert_eq!(Ok(Command::Empty), parse_command("  \n"));
+    assert_eq!(Ok(Command::Quit), parse_command("quit\n"));
+    assert_eq!(Ok(Command::Quit), parse_command("exit"));
+    assert_eq!(Ok(Command::Help), parse_command("help"));
+    assert_eq!(Ok(Command::Sequences), parse_command("sequences"));
+    assert_eq!(Ok(Command::Root(None)), parse_command("root"));
+    assert_eq!(Ok(Command::Root(Some(12))), parse_command("root 12"));
+    assert_eq!(
+        Ok(Command::Status(out_point.clone(), None)),
+        parse_command(&format!("status {}:3", tx_hash))
+    );
+    assert_eq!(
+        Ok(Command::Proof(out_point, Some(7))),
+        parse_command(&format!("proof 0x{}:3 7", tx_hash))
+    );
+    assert_eq!(
+        Ok(Command::Key(vec![0x01, 0xff])),
+        parse_command("key 01ff")
+    );
+    assert_eq!(Ok(Command::Key(vec![0x02])), parse_command("branch 0x02"));
+
+    assert_eq!(
+        Err("sequence must be a number".to_string()),
+        parse_command("root latest")
+    );
+    assert_eq!(
+        Err("sequence must be a number".to_string()),
+        parse_command(&format!("status {}:3 -1", tx_hash))
+    );
+    let malformed_out_point = Err("out_point must be <tx-hash-hex>:<index>".to_string());
+    assert_eq!(malformed_out_point, parse_command("status"));
+    assert_eq!(malformed_out_point, parse_command("proof abab:3"));
+    assert_eq!(
+        malformed_out_point,
+        parse_command(&format!("proof {}", tx_hash))
+    );
+    assert_eq!(Err("key must be hex".to_string()), parse_command("key 123"));
+    assert_eq!(Err("key must be hex".to_string()), parse_command("key"));
+    assert_eq!(
+        Err("unknown command dump, see help".to_string()),
+        parse_command("dump 1")
+    );
+
+    assert_eq!(None, parse_hex("0g"));
+    assert_eq!(None, parse_out_point(&format!("{}:x", tx_hash)));
+}
+
+// The roots are compared in constant time, a proof checked against the root of another block is rejected, not an error.
+#[test]
+fn test_ct_eq() {
+    let root = [7u8; 32];
+    let mut first = root;
+    first[0] ^= 1;
+    let mut last = root;
+    last[31] ^= 0x80;
+    assert!(ct_eq(&root, &root));
+    assert!(!ct_eq(&root, &first));
+    assert!(!ct_eq(&root, &last));
+    assert!(!ct_eq(&root, &root[..31]));
+    assert!(ct_eq(&[], &[]));
+
+    let (smt_db, _smt_dir) = open_db();
+    let (smt_live_db, _smt_live_dir) = open_db();
+    let (mmr_db, _mmr_dir) = open_db();
+    let out_point = OutPoint {
+        tx_hash: [1; 32],
+        index: 0,
+    };
+    let other = OutPoint {
+        tx_hash: [2; 32],
+        index: 0,
+    };
+    let leaves = vec![(out_point.clone(), CellStatus::new_live(0))];
+
+    let smt_tx = smt_db.transaction_default();
+    let mut smt = SMTAccumulator::<_, ()>::new(&smt_tx).unwrap();
+    smt.add(vec![out_point.clone()]).unwrap();
+    let stale = smt.commit().unwrap();
+    smt.add(vec![other.clone()]).unwrap();
+    let commitment = smt.commit().unwrap();
+    let proof = || {
+        smt.proof(commitment.clone(), vec![out_point.clone()])
+            .unwrap()
+    };
+    assert!(proof().verify(commitment.clone(), leaves.clone()).unwrap());
+    assert!(!proof().verify(stale, leaves.clone()).unwrap());
+
+    let smt_live_tx = smt_live_db.transaction_default();
+    let mut smt_live = SMTLiveAccumulator::<_, ()>::new(&smt_live_tx).unwrap();
+    smt_live.add(vec![out_point.clone()]).unwrap();
+    let stale = smt_live.commit().unwrap();
+    smt_live.add(vec![other.clone()]).unwrap();
+    let commitment = smt_live.commit().unwrap();
+    let proof = || {
+        smt_live
+            .proof(commitment.clone(), vec![out_point.clone()])
+            .unwrap()
+    };
+    assert!(proof()
+        .verify(vec![(commitment.clone(), None)], leaves.clone())
+        .unwrap());
+    assert!(!proof().verify(vec![(stale, None)], leaves.clone()).unwrap());
+
+    let mmr_tx = mmr_db.transaction_default();
+    let mut mmr = MMRAccumulator::<_, ()>::new(&mmr_tx).unwrap();
+    mmr.add(vec![out_point.clone()]).unwrap();
+    let stale = mmr.commit().unwrap();
+    mmr.add(vec![other]).unwrap();
+    let commitment = mmr.commit().unwrap();
+    let proof = || {
+        mmr.proof(commitment.clone(), vec![out_point.clone()])
+            .unwrap()
+    };
+    assert!(proof().verify(commitment.clone(), leaves.clone()).unwrap());
+    assert!(!proof().verify(stale, leaves).unwrap());
+}
+
+// Statuses and commitments are formatted with their block numbers as integers and their hashes as short hex, so the
+// failures of the tests comparing backends are readable.
+#[test]
+fn test_readable_formatting() {
+    assert_eq!(
+        "CellStatus { created_by: 3, consumed_by: Some(7) }",
+        format!("{:?}", CellStatus::new_dead(3, 7))
+    );
+    assert_eq!(
+        "CellStatus { created_by: 3, consumed_by: None }",
+        format!("{:?}", CellStatus::new_live(3))
+    );
+    assert_eq!(
+        "created by 3, consumed by 7",
+        CellStatus::new_dead(3, 7).to_string()
+    );
+    assert_eq!("created by 3, live", CellStatus::new_live(3).to_string());
+
+    let digest = [0xabu8; 32];
+    assert_eq!("abababababababab..", ShortHex(&digest).to_string());
+    assert_eq!("0102", ShortHex(&[1, 2]).to_string());
+    let mut other = digest;
+    other[31] = 0;
+    // the whole hashes are compared
+    assert_ne!(ShortHex(&digest), ShortHex(&other));
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let commitment = SMTAccumulator::<_, ()>::new(&tx).unwrap().commit().unwrap();
+    let debug = format!("{:?}", commitment);
+    assert!(debug.starts_with("AccumulatorCommitment { sequence: 0, root: "));
+    assert!(debug.contains(&format!(
+        "digest: {} }}",
+        ShortHex(&commitment.commitment_digest())
+    )));
+}
+
+// Writes a backend with 32 bits versions, then migrates it to 64 bits versions: both databases must pass fsck and read
+// the same history, and the keys of the migrated one are longer.
+macro_rules! test_sequence_width_migration {
+    ($name:ident, $accumulator:ident, $backend:ident) => {
+        #[test]
+        fn $name() {
+            let mut workload = Workload::new(0, true);
+            let blocks: Vec<BlockOps> = (0..BLOCKS).map(|i| workload.next_block(i)).collect();
+            let (source_db, _source_dir) = open_db();
+            let tx = source_db.transaction_default();
+            set_sequence_width::<_, ()>(&tx, SequenceWidth::U32).unwrap();
+            $accumulator::<_, ()>::new_with_strict(&tx, true)
+                .unwrap()
+                .apply_blocks(blocks.clone())
+                .unwrap();
+            tx.commit().unwrap();
+
+            let (target_db, _target_dir) = open_db();
+            let tx = target_db.transaction_default();
+            migrate_sequence_width::<_, _, (), _>(
+                &source_db.snapshot(),
+                &tx,
+                SequenceWidth::U64,
+                $backend::is_versioned_key,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+
+            let (source, target) = (source_db.snapshot(), target_db.snapshot());
+            assert!($backend::fsck::fsck(&source).unwrap().is_ok());
+            assert!($backend::fsck::fsck(&target).unwrap().is_ok());
+
+            let source_key_bytes: usize = source
+                .iterator(IteratorMode::Start)
+                .map(|(key, _value)| key.len())
+                .sum();
+            let target_key_bytes: usize = target
+                .iterator(IteratorMode::Start)
+                .map(|(key, _value)| key.len())
+                .sum();
+            assert!(source_key_bytes < target_key_bytes);
+
+            for sequence in [0, 100, BLOCKS - 1] {
+                let source = $accumulator::<_, ()>::new_with_sequence(&source, sequence).unwrap();
+                let target = $accumulator::<_, ()>::new_with_sequence(&target, sequence).unwrap();
+                for out_point in blocks.iter().flat_map(|block| &block.added).step_by(7) {
+                    assert_eq!(
+                        source.cell_status(out_point).unwrap(),
+                        target.cell_status(out_point).unwrap()
+                    );
+                }
+            }
+        }
+    };
+}
+
+test_sequence_width_migration!(test_smt_sequence_width_migration, SMTAccumulator, smt);
+test_sequence_width_migration!(
+    test_smt_live_sequence_width_migration,
+    SMTLiveAccumulator,
+    smt_live
+);
+test_sequence_width_migration!(test_mmr_sequence_width_migration, MMRAccumulator, mmr);
+
+// the runtime chosen builder builds the same accumulators as the backend builders
+#[test]
+fn test_builder() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..20).map(|i| workload.next_block(i)).collect()
+    };
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let expected: Vec<[u8; 32]> = AccumulatorBuilder::<SMTAccumulator<_, ()>>::new()
+        .strict(true)
+        .build(&tx)
+        .unwrap()
+        .apply_blocks(blocks.clone())
+        .unwrap()
+        .iter()
+        .map(|commitment| commitment.commitment_digest())
+        .collect();
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let digests = builder::Builder::from_name("smt")
+        .unwrap()
+        .strict(true)
+        .build::<_, ()>(&tx)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+    assert_eq!(
+        expected
+            .iter()
+            .map(|digest| ShortHex(digest))
+            .collect::<Vec<_>>(),
+        digests
+            .iter()
+            .map(|digest| ShortHex(digest))
+            .collect::<Vec<_>>()
+    );
+    tx.commit().unwrap();
+
+    // a historical accumulator is read only
+    let tx = db.transaction_default();
+    // the digests of the committed sequences are read back in order, the range is clamped to them
+    let commitments: Vec<[u8; 32]> = builder::Builder::from_name("smt")
+        .unwrap()
+        .commitments::<_, ()>(&tx, 0..100)
+        .unwrap()
+        .map(|commitment| commitment.unwrap().1)
+        .collect();
+    assert_eq!(expected, commitments);
+    let historical = builder::Builder::from_name("mmr")
+        .unwrap()
+        .strict(true)
+        .sequence(10)
+        .build::<_, ()>(&tx);
+    assert!(matches!(
+        historical,
+        Err(AccumulatorError::InternalError(_))
+    ));
+    assert!(builder::Builder::from_name("utxo").is_none());
+}
+
+// a fixture commits its blocks at one sequence each, an accumulator opened at a sequence sees the commitment of its block
+#[test]
+fn test_fixture_builder() {
+    for backend in ["smt", "smt_live", "mmr"] {
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let fixture = FixtureBuilder::new(backend)
+            .blocks(20)
+            .build(tmp_dir.path())
+            .unwrap();
+        assert_eq!(20, fixture.blocks().len());
+        assert_eq!(20, fixture.commitments().len());
+
+        let tx = fixture.db().transaction_default();
+        let (sequence, digest) = fixture
+            .builder()
+            .sequence(9)
+            .build::<_, ()>(&tx)
+            .unwrap()
+            .commitment()
+            .unwrap();
+        assert_eq!(9, sequence);
+        assert_eq!(fixture.commitments()[9], digest, "{}", backend);
+
+        let accumulator = fixture.builder().build::<_, ()>(&tx).unwrap();
+        let live_cells = fixture.live_cells();
+        assert!(!live_cells.is_empty());
+        for cell in live_cells {
+            assert!(accumulator.cell_status(&cell).unwrap().unwrap().is_live());
+        }
+    }
+
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    assert!(matches!(
+        FixtureBuilder::new("utxo").build(tmp_dir.path()),
+        Err(AccumulatorError::InternalError(_))
+    ));
+}
+
+// a backend chosen at runtime proves its cells through the delegating reader
+#[test]
+fn test_any_accumulator_proofs() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    // the cells of the last block are live
+    let cells = blocks[BLOCKS as usize - 1].added[2..5].to_vec();
+    let mut commitments = Vec::new();
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut accumulator = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        accumulator.apply_blocks(blocks.clone()).unwrap();
+        accumulator.commit().unwrap();
+        let commitment = accumulator.backend_commitment().unwrap();
+        assert_eq!(BLOCKS, commitment.sequence());
+
+        let leaves: Vec<_> = cells
+            .iter()
+            .map(|cell| {
+                (
+                    cell.clone(),
+                    accumulator.cell_status(cell).unwrap().unwrap(),
+                )
+            })
+            .collect();
+        let proof = accumulator
+            .proof(commitment.clone(), cells.clone())
+            .unwrap();
+        assert!(
+            proof.verify(commitment.clone(), leaves).unwrap(),
+            "{}",
+            name
+        );
+        commitments.push(commitment);
+    }
+    // a commitment of another backend is rejected
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = builder::Builder::from_name("mmr")
+        .unwrap()
+        .build::<_, ()>(&tx)
+        .unwrap();
+    accumulator.apply_blocks(blocks).unwrap();
+    assert!(matches!(
+        accumulator.proof(commitments[0].clone(), cells),
+        Err(AccumulatorError::InvalidCommitment)
+    ));
+}
+
+#[test]
+fn test_verify_batch() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    // the cells of the last block are live
+    let cells = blocks[BLOCKS as usize - 1].added[2..6].to_vec();
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut accumulator = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        accumulator.apply_blocks(blocks.clone()).unwrap();
+        accumulator.commit().unwrap();
+        let commitment = accumulator.backend_commitment().unwrap();
+        let proofs = |statuses: &[CellStatus]| {
+            cells
+                .iter()
+                .zip(statuses)
+                .map(|(cell, status)| {
+                    let proof = accumulator
+                        .proof(commitment.clone(), vec![cell.clone()])
+                        .unwrap();
+                    (vec![(cell.clone(), status.clone())], proof)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut statuses: Vec<CellStatus> = cells
+            .iter()
+            .map(|cell| accumulator.cell_status(cell).unwrap().unwrap())
+            .collect();
+
+        assert!(
+            builder::AnyProof::verify_batch(commitment.clone(), proofs(&statuses)).unwrap(),
+            "{}",
+            name
+        );
+        assert!(builder::AnyProof::verify_batch(commitment.clone(), Vec::new()).unwrap());
+        // one wrong status fails the whole batch
+        statuses[2] = CellStatus::new_dead(statuses[2].created_by(), BLOCKS);
+        assert!(
+            !builder::AnyProof::verify_batch(commitment.clone(), proofs(&statuses)).unwrap(),
+            "{}",
+            name
+        );
+    }
+}
+
+// the smt backends commit to the same roots whatever the order of the operations of a block, `smt` also commits to
+// the cells consumed in the block they were created in and to the sequence of a consumption, and `mmr` to the insertion
+// order
+#[test]
+fn test_history_independence() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    let digests = |name: &str, blocks: Vec<BlockOps>| {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap()
+            .apply_blocks(blocks)
+            .unwrap()
+    };
+    let shuffled = reorder(&blocks, Reordering::Shuffled, 1);
+    let transient = reorder(&blocks, Reordering::Transient, 1);
+    for name in ["smt", "smt_live"] {
+        assert_eq!(
+            digests(name, blocks.clone()),
+            digests(name, shuffled.clone())
+        );
+    }
+    assert_eq!(
+        digests("smt_live", blocks.clone()),
+        digests("smt_live", transient.clone())
+    );
+    assert_ne!(digests("smt", blocks.clone()), digests("smt", transient));
+    assert_ne!(digests("mmr", blocks.clone()), digests("mmr", shuffled));
+
+    // the same live cells at the last block, after other ones in between
+    let moved = reorder(&blocks, Reordering::AcrossBlocks, 1);
+    let (canonical, moved_digests) = (
+        digests("smt_live", blocks.clone()),
+        digests("smt_live", moved.clone()),
+    );
+    assert_eq!(canonical.last(), moved_digests.last());
+    assert_ne!(canonical, moved_digests);
+    assert_ne!(digests("smt", blocks).last(), digests("smt", moved).last());
+}
+
+// a cell added and consumed in the same block is written once by `apply_blocks`, with the commitments and the statuses of
+// an add followed by a delete
+#[test]
+fn test_coalesced_ops() {
+    let blocks = {
+        let mut workload = Workload::new(0, true);
+        let blocks: Vec<BlockOps> = (0..3).map(|i| workload.next_block(i)).collect();
+        reorder(&blocks, Reordering::Transient, 1)
+    };
+    let transient = blocks[2]
+        .deleted
+        .iter()
+        .find(|out_point| blocks[2].added.contains(out_point));
+    let transient = transient.unwrap().clone();
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut separate = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        let mut expected = Vec::new();
+        for block in blocks.clone() {
+            separate.add(block.added).unwrap();
+            separate.delete(block.deleted).unwrap();
+            expected.push(separate.commit().unwrap());
+        }
+        assert_eq!(Some(0), separate.coalesced_ops());
+
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut coalesced = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        assert_eq!(
+            expected,
+            coalesced.apply_blocks(blocks.clone()).unwrap(),
+            "{}",
+            name
+        );
+        // `reorder` adds two transient cells to every block
+        assert_eq!(Some(6), coalesced.coalesced_ops());
+        assert_eq!(
+            Some(CellStatus::new_dead(2, 2)),
+            coalesced.cell_status(&transient).unwrap(),
+            "{}",
+            name
+        );
+    }
+}
+
+// updating a cell to the status a delete would give it commits to the same state in every backend
+#[test]
+fn test_update_status() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..2).map(|i| workload.next_block(i)).collect()
+    };
+    let consumed = blocks[0].added[3].clone();
+    let mut smt_expected = None;
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut deleted = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        deleted.apply_blocks(blocks.clone()).unwrap();
+        deleted.delete(vec![consumed.clone()]).unwrap();
+        let expected = deleted.commit().unwrap();
+
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut updated = builder::Builder::from_name(name)
+            .unwrap()
+            .strict(true)
+            .build::<_, ()>(&tx)
+            .unwrap();
+        updated.apply_blocks(blocks.clone()).unwrap();
+        updated
+            .update(vec![(consumed.clone(), CellStatus::new_dead(0, 2))])
+            .unwrap();
+        assert_eq!(expected, updated.commit().unwrap(), "{}", name);
+        if name == "smt" {
+            smt_expected = Some(expected);
+        }
+
+        let unknown = OutPoint {
+            tx_hash: [9u8; 32],
+            index: 9,
+        };
+        assert!(matches!(
+            updated.update(vec![(unknown, CellStatus::new_live(0))]),
+            Err(AccumulatorError::ElementNotFound(0))
+        ));
+    }
+
+    // the sharded smt updates its leaves like the smt
+    let (dbs, _tmp_dirs): (Vec<_>, Vec<_>) = (0..3).map(|_| open_db()).unzip();
+    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+    let mut sharded =
+        ShardedSMTAccumulator::<_, ()>::new_with_strict(txs.iter().collect(), true).unwrap();
+    sharded.apply_blocks(blocks).unwrap();
+    sharded
+        .update(vec![(consumed, CellStatus::new_dead(0, 2))])
+        .unwrap();
+    assert_eq!(
+        smt_expected,
+        Some(sharded.commit().unwrap().commitment_digest())
+    );
+}
+
+// The existence checks of every backend reject the same elements of the same operations. A double spend is an
+// `ElementNotFound` in `smt` and `mmr` and an `AlreadyConsumed` in `smt_live`, so only the index is compared for it. A
+// rejected operation leaves no write behind, and a non-strict writer only rejects unknown cells.
+#[test]
+fn test_existence_checks() {
+    let cell = |index: u32| OutPoint {
+        tx_hash: [3u8; 32],
+        index,
+    };
+    let unknown = OutPoint {
+        tx_hash: [9u8; 32],
+        index: 9,
+    };
+    let rejected = |result: Result<(), AccumulatorError>| match result {
+        Err(AccumulatorError::AlreadyExists(i))
+        | Err(AccumulatorError::ElementNotFound(i))
+        | Err(AccumulatorError::AlreadyConsumed(i, _)) => Some(i),
+        _ => None,
+    };
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let builder = builder::Builder::from_name(name).unwrap();
+        let mut accumulator = builder.clone().strict(true).build::<_, ()>(&tx).unwrap();
+        accumulator.add(vec![cell(0), cell(1), cell(2)]).unwrap();
+        accumulator.commit().unwrap();
+        accumulator.delete(vec![cell(0)]).unwrap();
+        accumulator.commit().unwrap();
+
+        assert!(
+            matches!(
+                accumulator.add(vec![cell(3), cell(1)]),
+                Err(AccumulatorError::AlreadyExists(1))
+            ),
+            "{}",
+            name
+        );
+        assert!(
+            matches!(
+                accumulator.add(vec![cell(3), cell(3)]),
+                Err(AccumulatorError::AlreadyExists(1))
+            ),
+            "{}",
+            name
+        );
+        assert!(
+            matches!(
+                accumulator.delete(vec![cell(1), unknown.clone()]),
+                Err(AccumulatorError::ElementNotFound(1))
+            ),
+            "{}",
+            name
+        );
+        assert_eq!(
+            Some(1),
+            rejected(accumulator.delete(vec![cell(1), cell(0)])),
+            "{}",
+            name
+        );
+        if name != "smt_live" {
+            assert!(
+                matches!(
+                    accumulator.delete(vec![cell(0)]),
+                    Err(AccumulatorError::ElementNotFound(0))
+                ),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            Some(1),
+            rejected(accumulator.delete(vec![cell(1), cell(1)])),
+            "{}",
+            name
+        );
+        assert!(
+            accumulator
+                .cell_status(&cell(1))
+                .unwrap()
+                .unwrap()
+                .is_live(),
+            "{}",
+            name
+        );
+        assert_eq!(None, accumulator.cell_status(&cell(3)).unwrap(), "{}", name);
+        drop(accumulator);
+
+        let mut accumulator = builder.strict(false).build::<_, ()>(&tx).unwrap();
+        assert!(
+            matches!(
+                accumulator.delete(vec![unknown.clone()]),
+                Err(AccumulatorError::ElementNotFound(0))
+            ),
+            "{}",
+            name
+        );
+        // the caller is trusted, a dead cell is tombstoned again
+        accumulator.delete(vec![cell(0)]).unwrap();
+        assert!(!accumulator
+            .cell_status(&cell(0))
+            .unwrap()
+            .unwrap()
+            .is_live());
+    }
+}
+
+// Every committed sequence of every backend is read back with the commitment it was committed with, after consumed
+// cells, rolled back transactions and a garbage collection of the mmr element index.
+#[test]
+fn test_committed_sequence_roots() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    // a cell written by the rolled back transactions only
+    let rolled_back = |i: usize| OutPoint {
+        tx_hash: [u8::MAX; 32],
+        index: i as u32,
+    };
+    let mut smt_digests = Vec::new();
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let builder = builder::Builder::from_name(name).unwrap().strict(true);
+        let mut digests = Vec::new();
+        for (i, chunk) in blocks.chunks(40).enumerate() {
+            if i % 2 == 1 {
+                let tx = db.transaction_default();
+                let mut accumulator = builder.clone().build::<_, ()>(&tx).unwrap();
+                accumulator.apply_blocks(chunk.to_vec()).unwrap();
+                accumulator.add(vec![rolled_back(i)]).unwrap();
+                accumulator.commit().unwrap();
+                drop(accumulator);
+                tx.rollback().unwrap();
+            }
+            let tx = db.transaction_default();
+            let mut accumulator = builder.clone().build::<_, ()>(&tx).unwrap();
+            digests.extend(accumulator.apply_blocks(chunk.to_vec()).unwrap());
+            drop(accumulator);
+            tx.commit().unwrap();
+        }
+        if name == "mmr" {
+            let tx = db.transaction_default();
+            mmr::gc::gc_element_index::<_, ()>(&tx, BLOCKS - 1, 20).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let tx = db.transaction_default();
+        let sequences = builder.committed_sequences::<_, ()>(&tx).unwrap();
+        assert_eq!(0..BLOCKS, sequences);
+        for sequence in sequences {
+            let reader = builder
+                .clone()
+                .strict(false)
+                .sequence(sequence)
+                .build::<_, ()>(&tx)
+                .unwrap();
+            assert_eq!(
+                (sequence, digests[sequence as usize]),
+                reader.commitment().unwrap(),
+                "{} at sequence {}",
+                name,
+                sequence
+            );
+        }
+        if name == "smt" {
+            smt_digests = digests;
+        }
+    }
+
+    // the sharded smt commits to the same trees as the smt
+    let (dbs, _tmp_dirs): (Vec<_>, Vec<_>) = (0..3).map(|_| open_db()).unzip();
+    for (i, chunk) in blocks.chunks(40).enumerate() {
+        if i % 2 == 1 {
+            let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+            let mut accumulator =
+                ShardedSMTAccumulator::<_, ()>::new_with_strict(txs.iter().collect(), true)
+                    .unwrap();
+            accumulator.apply_blocks(chunk.to_vec()).unwrap();
+            accumulator.add(vec![rolled_back(i)]).unwrap();
+            accumulator.commit().unwrap();
+            drop(accumulator);
+            for tx in txs {
+                tx.rollback().unwrap();
+            }
+        }
+        let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+        let mut accumulator =
+            ShardedSMTAccumulator::<_, ()>::new_with_strict(txs.iter().collect(), true).unwrap();
+        accumulator.apply_blocks(chunk.to_vec()).unwrap();
+        drop(accumulator);
+        for tx in txs {
+            tx.commit().unwrap();
+        }
+    }
+    let snapshots: Vec<_> = dbs.iter().map(|db| db.snapshot()).collect();
+    for sequence in 0..BLOCKS {
+        let reader =
+            ShardedSMTAccumulator::<_, ()>::new_with_sequence(snapshots.iter().collect(), sequence)
+                .unwrap();
+        let commitment = reader.commitment();
+        assert_eq!(sequence, commitment.sequence());
+        assert_eq!(
+            ShortHex(&smt_digests[sequence as usize]),
+            ShortHex(&commitment.commitment_digest()),
+            "sharded smt at sequence {}",
+            sequence
+        );
+    }
+}
+
+// The outputs of a transaction share the tx_hash prefix of their smt_live element keys, a database with a prefix
+// extractor and bloom filters over that prefix must still read every cell and every historical root.
+#[test]
+fn test_clustered_outputs() {
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true).with_outputs_per_tx(4);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    let added = &blocks[0].added;
+    assert_eq!(added[0].tx_hash, added[3].tx_hash);
+    assert_eq!(3, added[3].index);
+    assert_ne!(added[3].tx_hash, added[4].tx_hash);
+    // the last transaction of a block has the remaining 2 outputs
+    assert_eq!((added[8].tx_hash, 1), (added[9].tx_hash, added[9].index));
+
+    // the sequence every cell was created and consumed at
+    let mut lifetimes = HashMap::new();
+    for (sequence, block) in (0..).zip(&blocks) {
+        for out_point in &block.added {
+            lifetimes.insert(out_point.clone(), (sequence, None));
+        }
+        for out_point in &block.deleted {
+            lifetimes.get_mut(out_point).unwrap().1 = Some(sequence);
+        }
+    }
+
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db_options = DbOptions {
+        prefix_len: Some(1 + 32),
+        bloom_bits_per_key: Some(10),
+        ..Default::default()
+    };
+    let db =
+        OptimisticTransactionDB::open(&db_options.to_rocksdb_options(), tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+    let commitments = SMTLiveAccumulator::<_, ()>::new_with_strict(&tx, true)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    for sequence in [0, 100, 120, BLOCKS - 1] {
+        let reader = SMTLiveAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence).unwrap();
+        assert_eq!(
+            commitments[sequence as usize].to_bytes(),
+            reader.commitment().to_bytes()
+        );
+        for (out_point, (created, consumed)) in &lifetimes {
+            let expected = match consumed {
+                _ if *created > sequence => None,
+                Some(consumed) if *consumed <= sequence => {
+                    Some(CellStatus::new_dead(*created, *consumed))
+                }
+                _ => Some(CellStatus::new_live(*created)),
+            };
+            assert_eq!(
+                expected,
+                reader.cell_status(out_point).unwrap(),
+                "{:?} at sequence {}",
+                out_point,
+                sequence
+            );
+        }
+    }
+}
+
+// the values of at least `min_blob_size` bytes go to blob files, which the flush of the memtable recovered from the log
+// writes when the database is reopened
+#[test]
+fn test_min_blob_size() {
+    for min_blob_size in [None, Some(64)] {
+        let tmp_dir = Builder::new().tempdir().unwrap();
+        let db_options = DbOptions {
+            min_blob_size,
+            ..Default::default()
+        };
+        {
+            let db =
+                OptimisticTransactionDB::open(&db_options.to_rocksdb_options(), tmp_dir.path())
+                    .unwrap();
+            db.put(b"small", [1u8; 16]).unwrap();
+            db.put(b"large", [2u8; 128]).unwrap();
+        }
+        let db = OptimisticTransactionDB::open(&db_options.to_rocksdb_options(), tmp_dir.path())
+            .unwrap();
+        assert_eq!(&[1u8; 16][..], db.get(b"small").unwrap().unwrap().as_ref());
+        assert_eq!(&[2u8; 128][..], db.get(b"large").unwrap().unwrap().as_ref());
+        let blob_files = std::fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("blob".as_ref()))
+            .count();
+        assert_eq!(
+            min_blob_size.is_some(),
+            blob_files > 0,
+            "min blob size {:?}",
+            min_blob_size
+        );
+    }
+}
+
+#[test]
+fn test_report_interval() {
+    assert_eq!(Ok(ReportInterval::Blocks(100)), "100".parse());
+    assert_eq!(
+        Ok(ReportInterval::Elapsed(Duration::from_millis(500))),
+        "500ms".parse()
+    );
+    assert_eq!(
+        Ok(ReportInterval::Elapsed(Duration::from_secs(120))),
+        "2m".parse()
+    );
+    assert!("0".parse::<ReportInterval>().is_err());
+    assert!("30d".parse::<ReportInterval>().is_err());
+    assert!("s".parse::<ReportInterval>().is_err());
+
+    let mut clock = ReportClock::new(ReportInterval::Blocks(10));
+    let due: Vec<u64> = (0..30).filter(|i| clock.due(*i)).collect();
+    assert_eq!(vec![9, 19, 29], due);
+
+    // a duration interval reports at the first block after it elapsed, then restarts
+    let mut clock = ReportClock::new(ReportInterval::Elapsed(Duration::from_millis(20)));
+    assert!(!clock.due(0));
+    std::thread::sleep(Duration::from_millis(25));
+    assert!(clock.due(1));
+    assert!(!clock.due(2));
+}
+
+#[test]
+fn test_write_stalls() {
+    let stats = "** DB Stats **\nUptime(secs): 10.0 total, 10.0 interval\nCumulative stall: 01:02:3.250 H:M:S, 0.4 percent\nInterval stall: 00:00:0.000 H:M:S, 0.0 percent\n";
+    assert_eq!(
+        Some(Duration::from_secs(3723) + Duration::from_millis(250)),
+        cumulative_stall(stats)
+    );
+    assert_eq!(None, cumulative_stall("Interval stall: 00:00:0.000 H:M:S"));
+    assert_eq!(
+        None,
+        cumulative_stall("Cumulative stall: 00:xx:0.000 H:M:S")
+    );
+
+    // consecutive stalled intervals are merged into one window, an interval without stalls ends it
+    let mut stalls = WriteStalls::default();
+    let millis = Duration::from_millis;
+    assert_eq!(Duration::ZERO, stalls.record(Duration::ZERO, 9));
+    assert_eq!(millis(100), stalls.record(millis(100), 19));
+    assert_eq!(millis(50), stalls.record(millis(150), 29));
+    assert_eq!(Duration::ZERO, stalls.record(millis(150), 39));
+    assert_eq!(millis(20), stalls.record(millis(170), 49));
+    assert_eq!(millis(170), stalls.total());
+    assert_eq!(
+        &[(10, 29, millis(150)), (40, 49, millis(20))],
+        stalls.windows()
+    );
+    assert_eq!(
+        "write stalls: 170 millis in 2 windows, blocks 10-29 150 millis, blocks 40-49 20 millis",
+        stalls.to_string()
+    );
+
+    // a database which never stalled, the property is parsed from RocksDB itself
+    let (db, _tmp_dir) = open_db();
+    let mut stalls = WriteStalls::default();
+    assert_eq!(Some(Duration::ZERO), stalls.sample(&db, 0));
+    assert!(stalls.windows().is_empty());
+}
+
+#[test]
+fn test_write_counts() {
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let write_counts = Arc::new(WriteCounts::default());
+    let mut accumulator = MMRAccumulator::new(&tx).unwrap();
+    accumulator.trace_keys(KeyTracer::default().with_write_counts(Arc::clone(&write_counts)));
+    let out_points = (0..2)
+        .map(|index| OutPoint {
+            tx_hash: [1u8; 32],
+            index,
+        })
+        .collect();
+    accumulator.add(out_points).unwrap();
+    accumulator.commit().unwrap();
+    tx.commit().unwrap();
+
+    // the counts match the versioned keys of the database and their bytes, the single byte meta keys aren't counted
+    let (mut keys, mut bytes) = (0, 0);
+    for (key, value) in db
+        .iterator(IteratorMode::Start)
+        .filter(|(key, _)| key.len() > 1)
+    {
+        keys += 1;
+        bytes += key.len() + value.len();
+    }
+    assert!(keys > 0);
+    assert_eq!(keys as u64, write_counts.keys());
+    assert_eq!(bytes as u64, write_counts.bytes());
+
+    let rates = write_counts.rates(2, Duration::from_millis(500));
+    assert_eq!(4.0, rates.blocks_per_sec);
+    assert_eq!(keys as f64 * 2.0, rates.keys_per_sec);
+    assert_eq!(bytes as f64 * 2.0, rates.bytes_per_sec);
+}
+
+#[test]
+fn test_cell_costs() {
+    let mut costs = CellCosts::default();
+    // the commit bytes are shared out by leaves, 2 created cells for 1 spent one
+    costs.record(10, 5, 10, 100, 50, 300);
+    // a block without cells attributes nothing
+    costs.record(0, 0, 0, 0, 0, 40);
+    assert_eq!(
+        CellCosts {
+            cells_created: 10,
+            cells_spent: 5,
+            transactions: 10,
+            created_bytes: 300,
+            spent_bytes: 150,
+        },
+        costs
+    );
+    assert_eq!(30.0, costs.per_created_cell());
+    assert_eq!(30.0, costs.per_spent_cell());
+    assert_eq!(45.0, costs.per_transaction());
+    assert_eq!(45.0 * 2.0 * 365.0 * 24.0 * 3600.0, costs.annual_growth(2.0));
+    // an odd share rounds down on the created cells
+    costs.record(1, 2, 1, 0, 0, 10);
+    assert_eq!((303, 157), (costs.created_bytes, costs.spent_bytes));
+}
+
+// The blocks of a transaction whose commit conflicts, expiry sweep included, are replayed on a new transaction into the
+// commitment of the lost one.
+#[test]
+fn test_replay_blocks() {
+    let (db, _tmp_dir) = open_db();
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<_> = (0..4)
+        .map(|sequence| UncommittedBlock {
+            sequence,
+            ops: workload.next_block(sequence),
+            sweep: (sequence == 2).then_some(2),
+        })
+        .collect();
+    let is_locked = |hash: &[u8; 32], _status: &CellStatus| hash[0] % 2 == 0;
+
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::<_, ()>::new(&tx).unwrap();
+    let mut swept = 0;
+    let mut lost = None;
+    for block in &blocks {
+        accumulator.add(block.ops.added.clone()).unwrap();
+        if let Some(created_before) = block.sweep {
+            swept += accumulator.sweep(created_before, is_locked).unwrap().cells;
+        }
+        lost = Some(accumulator.commit().unwrap());
+    }
+    assert!(swept > 0);
+    let lost = lost.unwrap();
+    let concurrent = db.transaction_default();
+    OpLog::<_, ()>::new(&tx).put(3, &blocks[3].ops).unwrap();
+    OpLog::<_, ()>::new(&concurrent)
+        .put(3, &BlockOps::default())
+        .unwrap();
+    concurrent.commit().unwrap();
+    assert!(is_conflict(&tx.commit().unwrap_err()));
+
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::<_, ()>::new(&tx).unwrap();
+    let replayed = replay_blocks(&mut accumulator, &blocks, is_locked)
+        .unwrap()
+        .unwrap();
+    assert_eq!(lost.commitment_digest(), replayed.commitment_digest());
+    tx.commit().unwrap();
+}
+
+// A write conflict of two optimistic transactions fails the later commit, the failed transaction still reads its own
+// writes, e.g. the oplog records of the blocks replayed on a new transaction.
+#[test]
+fn test_commit_conflicts() {
+    let (db, _tmp_dir) = open_db();
+    let ops = BlockOps {
+        added: vec![OutPoint {
+            tx_hash: [1; 32],
+            index: 0,
+        }],
+        deleted: vec![],
+    };
+    let tx = db.transaction_default();
+    let concurrent = db.transaction_default();
+    OpLog::<_, ()>::new(&tx).put(7, &ops).unwrap();
+    OpLog::<_, ()>::new(&concurrent)
+        .put(7, &BlockOps::default())
+        .unwrap();
+    concurrent.commit().unwrap();
+    let error = tx.commit().unwrap_err();
+    assert!(is_conflict(&error), "{}", error);
+    let replayed = OpLog::<_, ()>::new(&tx).ops_at(7).unwrap().unwrap();
+    assert_eq!(ops.added, replayed.added);
+    // a path taken by a file fails to open with an IO error, which isn't a conflict
+    let file = NamedTempFile::new().unwrap();
+    let error = OptimisticTransactionDB::open_default(file.path()).unwrap_err();
+    assert_eq!(StatusCode::IOError, StatusCode::of(&error), "{}", error);
+    assert!(!is_conflict(&error));
+
+    let policy = RetryPolicy {
+        max_retries: 5,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+    };
+    let backoffs: Vec<_> = (0..5).map(|attempt| policy.backoff(attempt)).collect();
+    assert_eq!(
+        [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec(),
+        backoffs
+    );
+    assert_eq!(policy.max_backoff, policy.backoff(u32::MAX));
+
+    let mut conflicts = CommitConflicts::default();
+    conflicts.record_retry(100);
+    conflicts.record_retry(40);
+    conflicts.record_failure();
+    assert_eq!(
+        CommitConflicts {
+            conflicts: 3,
+            retries: 2,
+            replayed_blocks: 140,
+        },
+        conflicts
+    );
+}
+
+// The segments of a commit sweep split the blocks of the run, commit at their interval from their first block, and at
+// their last block.
+#[test]
+fn test_commit_sweep() {
+    let mut sweep = CommitSweep::new(&[1, 10, 100], 1000, 250);
+    let bounds: Vec<_> = sweep
+        .segments()
+        .iter()
+        .map(|segment| (segment.first_block, segment.last_block))
+        .collect();
+    assert_eq!(vec![(1000, 1082), (1083, 1165), (1166, 1249)], bounds);
+
+    let commits: Vec<u64> = (1000..1250).filter(|i| sweep.commit_due(*i)).collect();
+    assert_eq!(83, commits.iter().filter(|i| **i <= 1082).count());
+    assert_eq!(
+        vec![1092, 1102, 1112, 1122, 1132, 1142, 1152, 1162, 1165],
+        commits
+            .iter()
+            .copied()
+            .filter(|i| (1083..=1165).contains(i))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![1249],
+        commits
+            .iter()
+            .copied()
+            .filter(|i| *i >= 1166)
+            .collect::<Vec<_>>()
+    );
+
+    assert!(sweep.finish_block(1081).is_none());
+    assert_eq!(1, sweep.finish_block(1082).unwrap().commit_interval);
+    assert!(sweep.segments()[0].blocks_per_sec().is_some());
+    assert!(sweep.segments()[1].blocks_per_sec().is_none());
+}
+
+// Spike blocks add cells and consume old ones on top of the regular workload, and a strict accumulator accepts them,
+// i.e. no cell is consumed twice. The only spike consuming cells is the last block, so the regular cells are unchanged.
+#[test]
+fn test_spike_blocks() {
+    let spikes = Spikes {
+        interval: 50,
+        created: 500,
+        consumed: 200,
+    };
+    let regular: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true).with_outputs_per_tx(4);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    let blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, true)
+            .with_outputs_per_tx(4)
+            .with_spikes(spikes);
+        (0..BLOCKS).map(|i| workload.next_block(i)).collect()
+    };
+    for (i, (block, regular)) in (0..).zip(blocks.iter().zip(&regular)) {
+        let (created, consumed) = match (spikes.is_spike(i), i > 100) {
+            (true, true) => (500, 200),
+            (true, false) => (500, 0),
+            (false, _) => (0, 0),
+        };
+        assert_eq!(10 + created, block.added.len(), "block {}", i);
+        assert_eq!(regular.deleted.len() + consumed, block.deleted.len());
+        assert_eq!(regular.deleted[..], block.deleted[..regular.deleted.len()]);
+    }
+    // the created cells of a spike are grouped into transactions too
+    let added = &blocks[49].added;
+    assert_eq!((added[10].tx_hash, 3), (added[13].tx_hash, added[13].index));
+    assert_ne!(added[13].tx_hash, added[14].tx_hash);
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    SMTLiveAccumulator::<_, ()>::new_with_strict(&tx, true)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+    tx.commit().unwrap();
+}
+
+// The cells of `proof-lag` are drawn once for all the lags: distinct, the same for the same seed, never consumed, and
+// live at every sequence from the oldest lag to the head as the readers of the sequences see them.
+#[test]
+fn test_sample_live_cells() {
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..BLOCKS).map(|i| workload.next_block(i)).collect();
+    let oldest = 110;
+    let cells = sample_live_cells(&blocks, oldest, 50, 1);
+    assert_eq!(50, cells.len());
+    assert_eq!(50, cells.iter().collect::<HashSet<_>>().len());
+    assert_eq!(cells, sample_live_cells(&blocks, oldest, 50, 1));
+    assert_ne!(cells, sample_live_cells(&blocks, oldest, 50, 2));
+    // the cells of the blocks up to the oldest sequence which are never consumed
+    let created: HashSet<&OutPoint> = blocks[..=oldest]
+        .iter()
+        .flat_map(|block| &block.added)
+        .collect();
+    let consumed: HashSet<&OutPoint> = blocks.iter().flat_map(|block| &block.deleted).collect();
+    let all = sample_live_cells(&blocks, oldest, usize::MAX, 1);
+    assert!(all.len() >= created.len() - consumed.len());
+    assert!(all
+        .iter()
+        .all(|cell| created.contains(cell) && !consumed.contains(cell)));
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    SMTAccumulator::<_, ()>::new_with_strict(&tx, true)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    for sequence in oldest as u64..BLOCKS {
+        let reader = SMTAccumulator::<_, ()>::new_with_sequence(&snapshot, sequence).unwrap();
+        for cell in &cells {
+            let status = reader.cell_status(cell).unwrap();
+            assert!(status.unwrap().is_live(), "sequence {}", sequence);
+        }
+    }
+}
+
+// Replays a fixed workload into every backend and compares the database with the checked-in dump of its layout in
+// `testdata`, so that a refactored store which changes the stored keys or values fails here. A missing dump fails too,
+// the dumps are only written with `UPDATE_GOLDENS=1`, to be checked in after an intended change of the layout.
+#[test]
+fn test_golden_layout() {
+    let mut blocks: Vec<BlockOps> = {
+        let mut workload = Workload::new(0, false);
+        (0..4).map(|i| workload.next_block(i)).collect()
+    };
+    // the workload consumes cells from block 100 on
+    blocks[3].deleted = blocks[0].added[..3].to_vec();
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+    let update = std::env::var_os("UPDATE_GOLDENS").is_some();
+    for backend in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        for block in &blocks {
+            let tx = db.transaction_default();
+            let mut accumulator = builder::Builder::from_name(backend)
+                .unwrap()
+                .strict(true)
+                .build::<_, ()>(&tx)
+                .unwrap();
+            accumulator.apply_blocks(vec![block.clone()]).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let dump = dump_layout(&db);
+        let path = dir.join(format!("layout-{}.txt", backend));
+        if update {
+            std::fs::write(&path, dump).unwrap();
+            continue;
+        }
+        let golden = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "{}: {}, run with UPDATE_GOLDENS=1 to write it",
+                path.display(),
+                e
+            )
+        });
+        assert!(
+            golden == dump,
+            "the layout of {} differs from {}",
+            backend,
+            path.display()
+        );
+    }
+}
+
+// a cell created at sequence 0 and consumed at sequence 2 has a version at each, as does its element index record
+#[test]
+fn test_key_versions() {
+    let cell = OutPoint {
+        tx_hash: [1u8; 32],
+        index: 1,
+    };
+    let blocks = vec![
+        BlockOps {
+            added: vec![cell.clone()],
+            deleted: vec![],
+        },
+        BlockOps::default(),
+        BlockOps {
+            added: vec![],
+            deleted: vec![cell.clone()],
+        },
+    ];
+    for name in ["smt", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        let mut accumulator = builder::Builder::from_name(name)
+            .unwrap()
+            .build::<_, ()>(&tx)
+            .unwrap();
+        accumulator.apply_blocks(blocks.clone()).unwrap();
+        tx.commit().unwrap();
+        let key = match name {
+            "smt" => smt::leaf_key(&db, &cell).unwrap().as_slice().to_vec(),
+            _ => mmr::element_key(&cell),
+        };
+        let versions = key_versions(&db, &key, SequenceWidth::U64);
+        assert_eq!(
+            vec![0, 2],
+            versions.iter().map(|(s, _v)| *s).collect::<Vec<_>>(),
+            "{}",
+            name
+        );
+    }
+}
+
+// the headers link the blocks by the commitments of the accumulator states they are built on
+#[test]
+fn test_header_chain() {
+    let mut workload = Workload::new(0, true);
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::<_, ()>::new(&tx).unwrap();
+    let mut headers = HeaderChain::new(0);
+    for i in 0..3 {
+        let block = headers.next_block(workload.next_block(i));
+        assert_eq!(i, block.number);
+        accumulator.add(block.ops.added).unwrap();
+        let commitment = accumulator.commit().unwrap().commitment_digest();
+        let header = headers
+            .append(Header {
+                number: block.number,
+                parent_commitment: block.parent_commitment,
+                commitment,
+            })
+            .unwrap();
+        assert_eq!(commitment, header.commitment);
+    }
+
+    // a block built on a stale tip doesn't extend the chain
+    let stale = headers.next_block(BlockOps::default());
+    headers
+        .append(Header {
+            number: stale.number,
+            parent_commitment: stale.parent_commitment,
+            commitment: [1; 32],
+        })
+        .unwrap();
+    assert!(matches!(
+        headers.append(Header {
+            number: stale.number + 1,
+            parent_commitment: stale.parent_commitment,
+            commitment: [2; 32],
+        }),
+        Err(AccumulatorError::InvalidCommitment)
+    ));
+}
+
+// Commits blocks whose numbers have gaps at their block numbers, the skipped sequences read as the previous commit.
+#[test]
+fn test_sequence_source() {
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<(u64, BlockOps)> = [0, 5, 9]
+        .into_iter()
+        .enumerate()
+        .map(|(i, number)| (number, workload.next_block(i as u64)))
+        .collect();
+    let created = blocks[1].1.added[0].clone();
+
+    let (smt_db, _smt_dir) = open_db();
+    let (smt_live_db, _smt_live_dir) = open_db();
+    let (mmr_db, _mmr_dir) = open_db();
+    let (smt_tx, smt_live_tx, mmr_tx) = (
+        smt_db.transaction_default(),
+        smt_live_db.transaction_default(),
+        mmr_db.transaction_default(),
+    );
+    let mut smt = SMTAccumulator::<_, ()>::new_with_strict(&smt_tx, true).unwrap();
+    let mut smt_live = SMTLiveAccumulator::<_, ()>::new_with_strict(&smt_live_tx, true).unwrap();
+    let mut mmr = MMRAccumulator::<_, ()>::new_with_strict(&mmr_tx, true).unwrap();
+    let smt_commitments = smt
+        .apply_numbered_blocks(blocks.clone(), SequenceSource::BlockNumber)
+        .unwrap();
+    let smt_live_commitments = smt_live
+        .apply_numbered_blocks(blocks.clone(), SequenceSource::BlockNumber)
+        .unwrap();
+    let mmr_commitments = mmr
+        .apply_numbered_blocks(blocks, SequenceSource::BlockNumber)
+        .unwrap();
+    for (i, number) in [0, 5, 9].into_iter().enumerate() {
+        assert_eq!(number, smt_commitments[i].sequence());
+        assert_eq!(number, smt_live_commitments[i].sequence());
+        assert_eq!(number, mmr_commitments[i].sequence());
+    }
+    // a block number behind the next sequence is rejected
+    assert!(smt.skip_to(3).is_err());
+    assert!(smt_live.skip_to(3).is_err());
+    assert!(mmr.skip_to(3).is_err());
+    drop((smt, smt_live, mmr));
+    smt_tx.commit().unwrap();
+    smt_live_tx.commit().unwrap();
+    mmr_tx.commit().unwrap();
+
+    let (smt_snapshot, smt_live_snapshot, mmr_snapshot) =
+        (smt_db.snapshot(), smt_live_db.snapshot(), mmr_db.snapshot());
+    let smt = SMTAccumulator::<_, ()>::new_with_sequence(&smt_snapshot, 7).unwrap();
+    let smt_live = SMTLiveAccumulator::<_, ()>::new_with_sequence(&smt_live_snapshot, 7).unwrap();
+    let mmr = MMRAccumulator::<_, ()>::new_with_sequence(&mmr_snapshot, 7).unwrap();
+    assert_eq!(smt_commitments[1].root(), smt.commitment().root());
+    assert_eq!(smt_live_commitments[1].root(), smt_live.commitment().root());
+    assert_eq!(mmr_commitments[1].root(), mmr.commitment().unwrap().root());
+    assert_eq!(
+        Some(CellStatus::new_live(5)),
+        smt.cell_status(&created).unwrap()
+    );
+    assert_eq!(
+        Some(CellStatus::new_live(5)),
+        smt_live.cell_status(&created).unwrap()
+    );
+    assert_eq!(
+        Some(CellStatus::new_live(5)),
+        mmr.cell_status(&created).unwrap()
+    );
+
+    // the skipped sequences are recorded and left out of the commitments
+    for (name, db) in [
+        ("smt", &smt_db),
+        ("smt_live", &smt_live_db),
+        ("mmr", &mmr_db),
+    ] {
+        assert_eq!(vec![1..5, 6..9], skipped_sequences(db), "{}", name);
+        let tx = db.transaction_default();
+        let sequences: Vec<u64> = builder::Builder::from_name(name)
+            .unwrap()
+            .commitments::<_, ()>(&tx, 0..100)
+            .unwrap()
+            .map(|commitment| commitment.unwrap().0)
+            .collect();
+        assert_eq!(vec![0, 5, 9], sequences, "{}", name);
+    }
+
+    // one sequence per operation, a block with deletes takes two
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut smt = SMTAccumulator::<_, ()>::new_with_strict(&tx, true).unwrap();
+    let added = workload.next_block(3).added;
+    let block = BlockOps {
+        added: added[1..].to_vec(),
+        deleted: added[..1].to_vec(),
+    };
+    let commitments = smt
+        .apply_numbered_blocks(
+            vec![
+                (
+                    0,
+                    BlockOps {
+                        added: added[..1].to_vec(),
+                        deleted: Vec::new(),
+                    },
+                ),
+                (1, block),
+            ],
+            SequenceSource::Operation,
+        )
+        .unwrap();
+    assert_eq!(
+        vec![0, 1, 2],
+        commitments
+            .iter()
+            .map(|commitment| commitment.sequence())
+            .collect::<Vec<_>>()
+    );
+}
+
+// The last sequence of the width can be skipped to, but not committed: the sequence key would wrap around.
+#[test]
+fn test_last_sequence() {
+    let mut workload = Workload::new(0, true);
+    let block = workload.next_block(0);
+    for name in ["smt", "smt_live", "mmr"] {
+        let (db, _tmp_dir) = open_db();
+        let tx = db.transaction_default();
+        set_sequence_width::<_, ()>(&tx, SequenceWidth::U32).unwrap();
+        let mut accumulator = builder::Builder::from_name(name)
+            .unwrap()
+            .build::<_, ()>(&tx)
+            .unwrap();
+        let last = SequenceWidth::U32.max_sequence();
+        assert!(accumulator.skip_to(last + 1).is_err(), "{}", name);
+        assert!(
+            accumulator
+                .apply_numbered_blocks(vec![(last, block.clone())], SequenceSource::BlockNumber)
+                .is_err(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_roundtrip() {
+    let mut workload = Workload::new(0, true);
+    let ops = workload.next_block(0);
+    let json = serde_json::to_string(&ops).unwrap();
+    let decoded: BlockOps = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        ops.added.iter().map(OutPoint::hash).collect::<Vec<_>>(),
+        decoded.added.iter().map(OutPoint::hash).collect::<Vec<_>>()
+    );
+
+    let (db, _tmp_dir) = open_db();
+    let tx = db.transaction_default();
+    let mut accumulator = SMTAccumulator::<_, ()>::new(&tx).unwrap();
+    accumulator.add(ops.added.clone()).unwrap();
+    let commitment = accumulator.commit().unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    let proof = SMTAccumulator::<_, ()>::wallet_proof(
+        &snapshot,
+        vec![ops.added[0].clone()],
+        commitment.sequence(),
+    )
+    .unwrap();
+
+    let commitment: smt::accumulator::AccumulatorCommitment =
+        serde_json::from_str(&serde_json::to_string(&commitment).unwrap()).unwrap();
+    let proof: smt::accumulator::WalletProof =
+        serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+    assert!(proof.verify(&commitment).unwrap());
+    assert!(serde_json::from_str::<smt::accumulator::WalletProof>("[1, 2, 3]").is_err());
+}
+
+// The C verifiers of every backend round-trip the serialized commitment and wallet proof: a proof of the commitment
+// gives 1, a proof of another commitment doesn't, and a malformed commitment or a null pointer gives -1.
+#[cfg(feature = "cdylib")]
+#[test]
+fn test_ffi_verify() {
+    use crate::{
+        ffi::{
+            dca_verify_mmr_proof, dca_verify_positioned_mmr_proof, dca_verify_smt_live_proof,
+            dca_verify_smt_proof,
+        },
+        mmr::position_index::PositionedMMRAccumulator,
+    };
+
+    macro_rules! ffi_roundtrip {
+        ($accumulator: ty, $verify: ident) => {{
+            let mut workload = Workload::new(0, true);
+            let blocks: Vec<BlockOps> = (0..2).map(|i| workload.next_block(i)).collect();
+            let cell = blocks[1].added[0].clone();
+            let (db, _tmp_dir) = open_db();
+            let tx = db.transaction_default();
+            let mut accumulator = <$accumulator>::new_with_strict(&tx, true).unwrap();
+            let commitments = accumulator.apply_blocks(blocks).unwrap();
+            drop(accumulator);
+            tx.commit().unwrap();
+            let snapshot = db.snapshot();
+            let proof = <$accumulator>::wallet_proof(&snapshot, vec![cell], 1)
+                .unwrap()
+                .to_bytes();
+            let verify = |commitment: &[u8], proof: &[u8]| unsafe {
+                $verify(
+                    commitment.as_ptr(),
+                    commitment.len(),
+                    proof.as_ptr(),
+                    proof.len(),
+                )
+            };
+            let commitment = commitments[1].to_bytes();
+            assert_eq!(1, verify(&commitment, &proof), stringify!($verify));
+            assert_ne!(
+                1,
+                verify(&commitments[0].to_bytes(), &proof),
+                stringify!($verify)
+            );
+            assert_eq!(
+                -1,
+                verify(&commitment[..commitment.len() - 1], &proof),
+                stringify!($verify)
+            );
+            assert_eq!(
+                -1,
+                unsafe { $verify(std::ptr::null(), 0, proof.as_ptr(), proof.len()) },
+                stringify!($verify)
+            );
+        }};
+    }
+
+    ffi_roundtrip!(SMTAccumulator<_, ()>, dca_verify_smt_proof);
+    ffi_roundtrip!(SMTLiveAccumulator<_, ()>, dca_verify_smt_live_proof);
+    ffi_roundtrip!(MMRAccumulator<_, ()>, dca_verify_mmr_proof);
+
+    // the positioned mmr keeps its position index in a second database
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..2).map(|i| workload.next_block(i)).collect();
+    let cell = blocks[1].added[0].clone();
+    let ((db, _tmp_dir), (index_db, _index_tmp_dir)) = (open_db(), open_db());
+    let (tx, index_tx) = (db.transaction_default(), index_db.transaction_default());
+    let mut accumulator =
+        PositionedMMRAccumulator::<_, _, ()>::new_with_strict(&tx, &index_tx, true).unwrap();
+    let commitments = accumulator.apply_blocks(blocks).unwrap();
+    drop(accumulator);
+    tx.commit().unwrap();
+    index_tx.commit().unwrap();
+    let (snapshot, index_snapshot) = (db.snapshot(), index_db.snapshot());
+    let proof = PositionedMMRAccumulator::<_, _, ()>::wallet_proof(
+        &snapshot,
+        &index_snapshot,
+        vec![cell],
+        1,
+    )
+    .unwrap()
+    .to_bytes();
+    let verify = |commitment: &[u8], proof: &[u8]| unsafe {
+        dca_verify_positioned_mmr_proof(
+            commitment.as_ptr(),
+            commitment.len(),
+            proof.as_ptr(),
+            proof.len(),
+        )
+    };
+    let commitment = commitments[1].to_bytes();
+    assert_eq!(1, verify(&commitment, &proof));
+    assert_ne!(1, verify(&commitments[0].to_bytes(), &proof));
+    // the commitment of the mmr alone isn't a positioned commitment
+    assert_eq!(-1, verify(&commitments[1].commitment().to_bytes(), &proof));
+    assert_eq!(-1, verify(&commitment, &proof[..3]));
+}
+
+// The gRPC handlers answer from the committed sequences: the latest one by default, an older one on request, and an
+// uncommitted one is out of range. The served proof verifies against the served commitment.
+#[cfg(feature = "grpc")]
+#[test]
+fn test_grpc_service() {
+    use crate::grpc::{
+        proto::{
+            self, accumulator_server::Accumulator, CommitmentRequest, ProofRequest, StatusRequest,
+        },
+        AccumulatorService,
+    };
+    use rocksdb::{WriteOptions, DB};
+    use tonic::{Code, Request};
+
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<BlockOps> = (0..2).map(|i| workload.next_block(i)).collect();
+    let cell = blocks[1].added[0].clone();
+    let tmp_dir = Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+    let commitments = SMTAccumulator::<_, WriteOptions>::new_with_strict(&*db, true)
+        .unwrap()
+        .apply_blocks(blocks)
+        .unwrap();
+    let service = AccumulatorService::new(builder::Builder::from_name("smt").unwrap(), db);
+    let out_point = proto::OutPoint {
+        tx_hash: cell.tx_hash.to_vec(),
+        index: cell.index,
+    };
+    let status_request = |sequence| {
+        Request::new(StatusRequest {
+            out_point: Some(out_point.clone()),
+            sequence,
+        })
+    };
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let status = service.get_status(status_request(None)).await.unwrap();
+        assert_eq!(1, status.get_ref().sequence);
+        assert!(status.get_ref().live);
+        // the cell is created by the second block
+        let status = service.get_status(status_request(Some(0))).await.unwrap();
+        assert_eq!(0, status.get_ref().sequence);
+        assert!(!status.get_ref().live);
+        let error = service
+            .get_status(status_request(Some(2)))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::OutOfRange, error.code());
+        let error = service
+            .get_status(Request::new(StatusRequest {
+                out_point: None,
+                sequence: None,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(Code::InvalidArgument, error.code());
+
+        let commitment = service
+            .get_commitment(Request::new(CommitmentRequest { sequence: Some(0) }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(commitments[0].to_bytes(), commitment.commitment);
+        assert_eq!(
+            commitments[0].commitment_digest().to_vec(),
+            commitment.digest
+        );
+
+        let proof = service
+            .get_proof(Request::new(ProofRequest {
+                out_points: vec![out_point.clone()],
+                sequence: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(1, proof.sequence);
+        let proof = smt::accumulator::WalletProof::from_bytes(&proof.proof).unwrap();
+        assert!(proof.verify(&commitments[1]).unwrap());
+        assert!(!proof.verify(&commitments[0]).unwrap());
+    });
+}
+
+mod suite {
+    use super::*;
+
+    // The parity of the backends through `AnyAccumulator`: add, delete and commit work on every backend, and every
+    // other feature either works or is reported unsupported by `Builder::supports`, in which case it must fail or not
+    // verify instead of giving a wrong result. The unsupported features of a backend are listed so that the matrix
+    // changes explicitly.
+    macro_rules! accumulator_suite {
+        ($backend: ident, [$($unsupported: ident),*]) => {
+            accumulator_suite!(
+                $backend,
+                builder::Builder::from_name(stringify!($backend)).unwrap(),
+                [$($unsupported),*]
+            );
+        };
+        ($backend: ident, $builder: expr, [$($unsupported: ident),*]) => {
+            mod $backend {
+                use super::*;
+                use crate::builder::Feature;
+
+                fn builder() -> builder::Builder {
+                    $builder.strict(true)
+                }
+
+                // the databases the backend is built over, see `Builder::databases`
+                fn open_dbs() -> (Vec<OptimisticTransactionDB>, Vec<TempDir>) {
+                    (0..builder().databases()).map(|_| open_db()).unzip()
+                }
+
+                fn cells() -> Vec<OutPoint> {
+                    (0..4)
+                        .map(|index| OutPoint {
+                            tx_hash: [7u8; 32],
+                            index,
+                        })
+                        .collect()
+                }
+
+                fn unknown() -> OutPoint {
+                    OutPoint {
+                        tx_hash: [u8::MAX; 32],
+                        index: 0,
+                    }
+                }
+
+                // commits the cells at sequence 0 and consumes the first one at sequence 1, returns the digests
+                fn committed(dbs: &[OptimisticTransactionDB]) -> ([u8; 32], [u8; 32]) {
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    let mut accumulator =
+                        builder().build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    accumulator.add(cells()).unwrap();
+                    let first = accumulator.commit().unwrap();
+                    accumulator.delete(vec![cells()[0].clone()]).unwrap();
+                    let second = accumulator.commit().unwrap();
+                    drop(accumulator);
+                    for tx in txs {
+                        tx.commit().unwrap();
+                    }
+                    (first, second)
+                }
+
+                // checks that a supported feature gives `true` and an unsupported one doesn't
+                fn check(feature: Feature, verified: Result<bool, AccumulatorError>) {
+                    if builder().supports(feature) {
+                        assert!(verified.unwrap(), "{:?}", feature);
+                    } else {
+                        assert!(!matches!(verified, Ok(true)), "{:?}", feature);
+                    }
+                }
+
+                #[test]
+                fn test_features() {
+                    let unsupported: Vec<Feature> = Feature::ALL
+                        .into_iter()
+                        .filter(|feature| !builder().supports(*feature))
+                        .collect();
+                    assert_eq!(vec![$(Feature::$unsupported),*], unsupported);
+                }
+
+                #[test]
+                fn test_core_operations() {
+                    let (dbs, _tmp_dirs) = open_dbs();
+                    let (first, second) = committed(&dbs);
+                    assert_ne!(first, second);
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    let mut accumulator =
+                        builder().build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    let cells = cells();
+                    let consumed = accumulator.cell_status(&cells[0]);
+                    check(
+                        Feature::ConsumedStatus,
+                        consumed.map(|status| status.map_or(false, |status| !status.is_live())),
+                    );
+                    assert!(accumulator.cell_status(&cells[1]).unwrap().unwrap().is_live());
+                    assert_eq!(None, accumulator.cell_status(&unknown()).unwrap());
+                    // a strict writer rejects a double spend and an unknown cell
+                    assert!(accumulator.delete(vec![cells[0].clone()]).is_err());
+                    assert!(accumulator.delete(vec![unknown()]).is_err());
+                }
+
+                #[test]
+                fn test_historical_reader() {
+                    let (dbs, _tmp_dirs) = open_dbs();
+                    let (first, second) = committed(&dbs);
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    // the commitment and whether the first cell is live at a sequence
+                    let read = |sequence: u64| -> Result<_, AccumulatorError> {
+                        let reader = builder()
+                            .strict(false)
+                            .sequence(sequence)
+                            .build_over::<_, ()>(txs.iter().collect())?;
+                        let status = reader.cell_status(&cells()[0])?;
+                        Ok((reader.commitment()?, status.map(|status| status.is_live())))
+                    };
+                    check(
+                        Feature::HistoricalReader,
+                        read(0).map(|read| read == ((0, first), Some(true))),
+                    );
+                    // a backend committing to the live cells only can't tell the consumed cell from an unknown one
+                    let consumed = builder().supports(Feature::ConsumedStatus).then_some(false);
+                    check(
+                        Feature::HistoricalReader,
+                        read(1).map(|read| read == ((1, second), consumed)),
+                    );
+                }
+
+                #[test]
+                fn test_proofs() {
+                    let (dbs, _tmp_dirs) = open_dbs();
+                    committed(&dbs);
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    // a backend without historical readers is proved at its latest state, which is the same
+                    let reader = if builder().supports(Feature::HistoricalReader) {
+                        builder().strict(false).sequence(1)
+                    } else {
+                        builder().strict(false)
+                    };
+                    let reader = reader.build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    let commitment = reader.backend_commitment().unwrap();
+                    let cells = cells();
+                    let proved = [(Feature::LiveProof, &cells[1]), (Feature::DeadProof, &cells[0])];
+                    for (feature, cell) in proved {
+                        let verified = reader
+                            .cell_status(cell)
+                            .and_then(|status| status.ok_or(AccumulatorError::ElementNotFound(0)))
+                            .and_then(|status| {
+                                reader
+                                    .proof(commitment.clone(), vec![cell.clone()])
+                                    .and_then(|proof| {
+                                        proof.verify(commitment.clone(), vec![(cell.clone(), status)])
+                                    })
+                            });
+                        check(feature, verified);
+                    }
+
+                    let absent = reader
+                        .absence_proof(vec![unknown()])
+                        .and_then(|proof| proof.verify(&commitment));
+                    check(Feature::AbsenceProof, absent);
+                    // a live cell isn't absent
+                    assert!(reader.absence_proof(vec![cells[1].clone()]).is_err());
+                }
+
+                #[test]
+                fn test_rollback() {
+                    let (dbs, _tmp_dirs) = open_dbs();
+                    committed(&dbs);
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    let mut accumulator =
+                        builder().build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    accumulator.add(vec![unknown()]).unwrap();
+                    let rolled_back = accumulator.commit().unwrap();
+                    drop(accumulator);
+                    for tx in txs {
+                        tx.rollback().unwrap();
+                    }
+
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    assert_eq!(
+                        0..2,
+                        builder()
+                            .committed_sequences_over::<_, ()>(txs.iter().collect())
+                            .unwrap()
+                    );
+                    let mut accumulator =
+                        builder().build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    assert_eq!(None, accumulator.cell_status(&unknown()).unwrap());
+                    // the same block is committed again at the same sequence
+                    accumulator.add(vec![unknown()]).unwrap();
+                    let recommitted = accumulator.commit().unwrap();
+                    check(Feature::Rollback, Ok(rolled_back == recommitted));
+                }
+
+                #[test]
+                fn test_skip_sequences() {
+                    let (dbs, _tmp_dirs) = open_dbs();
+                    committed(&dbs);
+                    let txs: Vec<_> = dbs.iter().map(|db| db.transaction_default()).collect();
+                    let mut accumulator =
+                        builder().build_over::<_, ()>(txs.iter().collect()).unwrap();
+                    let mut skip = || -> Result<bool, AccumulatorError> {
+                        accumulator.skip_to(5)?;
+                        accumulator.add(vec![unknown()])?;
+                        let digest = accumulator.commit()?;
+                        let reader = builder()
+                            .strict(false)
+                            .sequence(5)
+                            .build_over::<_, ()>(txs.iter().collect())?;
+                        // every database records the gap, so it's left out of the commitments
+                        let recorded = txs.iter().all(|tx| {
+                            skipped_sequences(tx).last().map(|gap| gap.end) == Some(5)
+                        });
+                        Ok(reader.commitment()? == (5, digest) && recorded)
+                    };
+                    check(Feature::SkipSequences, skip());
+                }
+            }
+        };
+    }
+
+    accumulator_suite!(smt, []);
+    accumulator_suite!(smt_live, [DeadProof]);
+    accumulator_suite!(mmr, [AbsenceProof]);
+    accumulator_suite!(
+        mmr_positioned,
+        builder::Builder::positioned_mmr(),
+        [AbsenceProof]
+    );
+    accumulator_suite!(smt_presence, [DeadProof, ConsumedStatus]);
+    accumulator_suite!(smt_sharded, builder::Builder::sharded(3), []);
+    // folded every other sequence, so the cells are read and proved from the cold tree
+    accumulator_suite!(
+        smt_tiered,
+        builder::Builder::tiered(2),
+        [AbsenceProof, SkipSequences]
+    );
+}
@@ -0,0 +1,339 @@
+use crate::{
+    builder::{AnyCommitment, Builder},
+    mmr::{
+        accumulator::{GenericMMRAccumulator, WalletProof as MMRWalletProof},
+        position_index::PositionedWalletProof,
+    },
+    smt::accumulator::{GenericSMTAccumulator, WalletProof as SMTWalletProof},
+    smt_live::{
+        accumulator::{
+            GenericSMTAccumulator as GenericSMTLiveAccumulator, WalletProof as SMTLiveWalletProof,
+        },
+        presence::{GenericPresenceAccumulator, PresenceProof},
+    },
+    storage::traits::ReadStore,
+    AccumulatorError, CellStatus, OutPoint,
+};
+use std::{
+    io::{Read, Write},
+    marker::PhantomData,
+    ops::Deref,
+    vec::IntoIter,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The serialized wallet proof of the backend that the given out_points are live at `sequence`, see
+/// `SMTAccumulator::wallet_proof`.
+pub fn wallet_proof<D, WO>(
+    backend: &Builder,
+    db: D,
+    out_points: Vec<OutPoint>,
+    sequence: u64,
+) -> Result<Vec<u8>, AccumulatorError>
+where
+    D: Deref,
+    D::Target: ReadStore,
+{
+    Ok(match backend {
+        Builder::Smt(_) => {
+            GenericSMTAccumulator::<_, WO>::wallet_proof(db, out_points, sequence)?.to_bytes()
+        }
+        Builder::SmtLive(_) => {
+            GenericSMTLiveAccumulator::<_, WO>::wallet_proof(db, out_points, sequence)?.to_bytes()
+        }
+        Builder::Mmr(_) => {
+            GenericMMRAccumulator::<_, WO>::wallet_proof(db, out_points, sequence)?.to_bytes()
+        }
+        Builder::SmtPresence(_) => {
+            GenericPresenceAccumulator::<_, WO>::wallet_proof(db, out_points, sequence)?.to_bytes()
+        }
+        Builder::SmtSharded(..) | Builder::SmtTiered(..) => {
+            return Err(AccumulatorError::InternalError(
+                "the sharded and tiered smt have no wallet proofs over a single database"
+                    .to_string(),
+            ))
+        }
+        // its proofs read the position index too, see `PositionedMMRAccumulator::wallet_proof`
+        Builder::MmrPositioned(_) => {
+            return Err(AccumulatorError::InternalError(
+                "the positioned mmr has no wallet proofs over a single database".to_string(),
+            ))
+        }
+    })
+}
+
+/// The header of a chunked witness, sent before its chunks: the witness proves `cells` cells live at `sequence` in
+/// `chunks` chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WitnessHeader {
+    pub sequence: u64,
+    pub chunks: u32,
+    pub cells: u32,
+}
+
+impl WitnessHeader {
+    /// Serializes the header as `sequence (8) | chunks (4) | cells (4)`.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut ret = [0u8; 16];
+        ret[0..8].copy_from_slice(&self.sequence.to_le_bytes());
+        ret[8..12].copy_from_slice(&self.chunks.to_le_bytes());
+        ret[12..16].copy_from_slice(&self.cells.to_le_bytes());
+        ret
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<Self, AccumulatorError> {
+        if slice.len() != 16 {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        Ok(WitnessHeader {
+            sequence: u64::from_le_bytes(slice[0..8].try_into().expect("checked length")),
+            chunks: u32::from_le_bytes(slice[8..12].try_into().expect("checked length")),
+            cells: u32::from_le_bytes(slice[12..16].try_into().expect("checked length")),
+        })
+    }
+}
+
+/// A chunk of a chunked witness, the serialized wallet proof of some of its cells, verified on its own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofChunk {
+    pub index: u32,
+    pub proof: Vec<u8>,
+}
+
+/// Proves cells live in chunks of at most `chunk_size` cells, so that a block witness of megabytes is sent and verified
+/// a chunk at a time. The cells are sorted and deduplicated, then the chunks are proved lazily in that order, which
+/// `IncrementalVerifier` requires. A chunk is a wallet proof of its own, the
+/// siblings shared by the cells of different chunks are repeated, which makes the witness larger than a single proof.
+pub struct ChunkedProver<D, WO> {
+    backend: Builder,
+    db: D,
+    header: WitnessHeader,
+    chunks: IntoIter<Vec<OutPoint>>,
+    index: u32,
+    _write_options: PhantomData<fn() -> WO>,
+}
+
+impl<D, WO> ChunkedProver<D, WO> {
+    pub fn new(
+        backend: Builder,
+        db: D,
+        mut out_points: Vec<OutPoint>,
+        sequence: u64,
+        chunk_size: usize,
+    ) -> Self {
+        assert!(chunk_size > 0, "a chunk has at least one cell");
+        out_points.sort();
+        out_points.dedup();
+        let chunks: Vec<Vec<OutPoint>> = out_points
+            .chunks(chunk_size)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        ChunkedProver {
+            backend,
+            db,
+            header: WitnessHeader {
+                sequence,
+                chunks: chunks.len() as u32,
+                cells: out_points.len() as u32,
+            },
+            chunks: chunks.into_iter(),
+            index: 0,
+            _write_options: PhantomData,
+        }
+    }
+
+    pub fn header(&self) -> WitnessHeader {
+        self.header
+    }
+}
+
+impl<D, WO> Iterator for ChunkedProver<D, WO>
+where
+    D: Deref + Clone,
+    D::Target: ReadStore,
+{
+    type Item = Result<ProofChunk, AccumulatorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let out_points = self.chunks.next()?;
+        let index = self.index;
+        self.index += 1;
+        Some(
+            wallet_proof::<D, WO>(
+                &self.backend,
+                self.db.clone(),
+                out_points,
+                self.header.sequence,
+            )
+            .map(|proof| ProofChunk { index, proof }),
+        )
+    }
+}
+
+/// Verifies a chunked witness against a trusted commitment as its chunks arrive: `feed` verifies a chunk and drops it,
+/// and `finalize` checks that every chunk was fed. Only one chunk is held at a time, so the memory of the verifier is
+/// bounded by the largest chunk rather than by the witness. The cells must be strictly increasing across the chunks,
+/// as `ChunkedProver` sends them, so that a cell repeated in several chunks can't make up the count of the header.
+pub struct IncrementalVerifier {
+    commitment: AnyCommitment,
+    header: WitnessHeader,
+    next_index: u32,
+    cells: u32,
+    // the greatest cell verified so far
+    last: Option<OutPoint>,
+    peak_bytes: usize,
+}
+
+impl IncrementalVerifier {
+    pub fn new(commitment: AnyCommitment, header: WitnessHeader) -> Result<Self, AccumulatorError> {
+        if commitment.sequence() != header.sequence {
+            return Err(AccumulatorError::InvalidCommitment);
+        }
+        Ok(IncrementalVerifier {
+            commitment,
+            header,
+            next_index: 0,
+            cells: 0,
+            last: None,
+            peak_bytes: 0,
+        })
+    }
+
+    /// Verifies the next chunk, returns its cells and their statuses. Fails with `InvalidProof` if the chunk is out of
+    /// order, its proof doesn't verify or its cells don't all follow the cells of the previous chunks, the verifier
+    /// must then be dropped.
+    pub fn feed(
+        &mut self,
+        chunk: &ProofChunk,
+    ) -> Result<Vec<(OutPoint, CellStatus)>, AccumulatorError> {
+        if chunk.index != self.next_index || chunk.index >= self.header.chunks {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        let (verified, elements) = match &self.commitment {
+            AnyCommitment::Smt(commitment) => {
+                let proof = SMTWalletProof::from_bytes(&chunk.proof)?;
+                (proof.verify(commitment)?, proof.elements().to_vec())
+            }
+            AnyCommitment::SmtLive(commitment) => {
+                let proof = SMTLiveWalletProof::from_bytes(&chunk.proof)?;
+                (proof.verify(commitment)?, proof.elements().to_vec())
+            }
+            AnyCommitment::Mmr(commitment) => {
+                let proof = MMRWalletProof::from_bytes(&chunk.proof)?;
+                (proof.verify(commitment)?, proof.elements().to_vec())
+            }
+            // the tree doesn't keep the creation sequences, the cells are reported as created at 0
+            AnyCommitment::SmtPresence(commitment) => {
+                let proof = PresenceProof::from_bytes(&chunk.proof)?;
+                let elements = proof
+                    .out_points()
+                    .iter()
+                    .map(|out_point| (out_point.clone(), CellStatus::new_live(0)))
+                    .collect();
+                (proof.is_present() && proof.verify(commitment)?, elements)
+            }
+            // the tiered smt has no wallet proofs
+            AnyCommitment::SmtTiered(_) => return Err(AccumulatorError::InvalidProof),
+            AnyCommitment::MmrPositioned(commitment) => {
+                let proof = PositionedWalletProof::from_bytes(&chunk.proof)?;
+                (proof.verify(commitment)?, proof.elements().to_vec())
+            }
+        };
+        if !verified || elements.is_empty() {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        // the proofs may list their cells in key order, the order of the out_points is checked on a sorted copy
+        let mut out_points: Vec<&OutPoint> =
+            elements.iter().map(|(out_point, _)| out_point).collect();
+        out_points.sort();
+        let increasing = out_points.windows(2).all(|pair| pair[0] < pair[1])
+            && self.last.as_ref().map_or(true, |last| last < out_points[0]);
+        if !increasing {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        self.last = Some(out_points[out_points.len() - 1].clone());
+        // the frame, the decoded proof, estimated at the size of the frame, and the returned cells are held at once
+        self.peak_bytes = self.peak_bytes.max(
+            2 * chunk.proof.len() + elements.len() * std::mem::size_of::<(OutPoint, CellStatus)>(),
+        );
+        self.next_index += 1;
+        self.cells += elements.len() as u32;
+        Ok(elements)
+    }
+
+    /// Checks that all the chunks and cells of the header were verified.
+    pub fn finalize(self) -> Result<(), AccumulatorError> {
+        if self.next_index != self.header.chunks || self.cells != self.header.cells {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    /// The most bytes the verifier held at once for a chunk fed so far: the chunk, its decoded proof and its cells.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
+}
+
+fn io_error(e: std::io::Error) -> AccumulatorError {
+    AccumulatorError::InternalError(e.to_string())
+}
+
+/// Writes a chunked witness to a stream, e.g. a socket, as its header followed by the `index (4) | length (4) | proof`
+/// frame of every chunk, the chunks are proved as they are written.
+pub fn write_chunked<W, D, WO>(
+    writer: &mut W,
+    prover: ChunkedProver<D, WO>,
+) -> Result<(), AccumulatorError>
+where
+    W: Write,
+    D: Deref + Clone,
+    D::Target: ReadStore,
+{
+    writer
+        .write_all(&prover.header().to_bytes())
+        .map_err(io_error)?;
+    for chunk in prover {
+        let chunk = chunk?;
+        writer
+            .write_all(&chunk.index.to_le_bytes())
+            .map_err(io_error)?;
+        writer
+            .write_all(&(chunk.proof.len() as u32).to_le_bytes())
+            .map_err(io_error)?;
+        writer.write_all(&chunk.proof).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)
+}
+
+/// Reads a chunked witness written by `write_chunked` and verifies it a frame at a time against `commitment`, returns
+/// the verified cells and the peak bytes held by the verifier. A frame is at most `max_chunk_bytes` long.
+pub fn verify_chunked<R: Read>(
+    reader: &mut R,
+    commitment: AnyCommitment,
+    max_chunk_bytes: usize,
+) -> Result<(Vec<OutPoint>, usize), AccumulatorError> {
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header).map_err(io_error)?;
+    let header = WitnessHeader::from_bytes(&header)?;
+    let mut verifier = IncrementalVerifier::new(commitment, header)?;
+    let mut cells = Vec::new();
+    for _ in 0..header.chunks {
+        let mut frame = [0u8; 8];
+        reader.read_exact(&mut frame).map_err(io_error)?;
+        let index = u32::from_le_bytes(frame[0..4].try_into().expect("checked length"));
+        let len = u32::from_le_bytes(frame[4..8].try_into().expect("checked length")) as usize;
+        if len > max_chunk_bytes {
+            return Err(AccumulatorError::InvalidProof);
+        }
+        let mut proof = vec![0u8; len];
+        reader.read_exact(&mut proof).map_err(io_error)?;
+        let elements = verifier.feed(&ProofChunk { index, proof })?;
+        cells.extend(elements.into_iter().map(|(out_point, _status)| out_point));
+    }
+    let peak_bytes = verifier.peak_bytes();
+    verifier.finalize()?;
+    Ok((cells, peak_bytes))
+}
@@ -0,0 +1,149 @@
+use rocksdb::{prelude::Open, WriteOptions, DB};
+use std::{net::TcpListener, thread};
+use tempfile::Builder as TempBuilder;
+
+use crate::{builder::Builder, AccumulatorWriter, OutPoint};
+
+use super::{
+    verify_chunked, wallet_proof, write_chunked, ChunkedProver, IncrementalVerifier, ProofChunk,
+    WitnessHeader,
+};
+
+fn cells() -> Vec<OutPoint> {
+    (0..50)
+        .map(|i| OutPoint {
+            tx_hash: [i as u8; 32],
+            index: i,
+        })
+        .collect()
+}
+
+#[test]
+fn test_incremental_verifier() {
+    for name in ["smt", "smt_live", "mmr"] {
+        let tmp_dir = TempBuilder::new().tempdir().unwrap();
+        let db = DB::open_default(tmp_dir.path()).unwrap();
+        let builder = Builder::from_name(name).unwrap().strict(true);
+        let mut accumulator = builder.clone().build::<_, WriteOptions>(&db).unwrap();
+        accumulator.add(cells()).unwrap();
+        accumulator.commit().unwrap();
+        let commitment = builder
+            .clone()
+            .sequence(0)
+            .build::<_, WriteOptions>(&db)
+            .unwrap()
+            .backend_commitment()
+            .unwrap();
+
+        let prover = ChunkedProver::<_, WriteOptions>::new(builder.clone(), &db, cells(), 0, 8);
+        let header = prover.header();
+        assert_eq!(
+            WitnessHeader {
+                sequence: 0,
+                chunks: 7,
+                cells: 50
+            },
+            header
+        );
+        let chunks: Vec<ProofChunk> = prover.map(|chunk| chunk.unwrap()).collect();
+        let mut verifier = IncrementalVerifier::new(commitment.clone(), header).unwrap();
+        let mut verified = Vec::new();
+        for chunk in chunks.iter() {
+            let elements = verifier.feed(chunk).unwrap();
+            assert!(elements.iter().all(|(_, status)| status.is_live()));
+            verified.extend(elements.into_iter().map(|(out_point, _)| out_point));
+        }
+        // the verifier holds one chunk at a time, less than the single proof of all the cells
+        let single = wallet_proof::<_, WriteOptions>(&builder, &db, cells(), 0).unwrap();
+        assert!(verifier.peak_bytes() < single.len(), "{}", name);
+        verifier.finalize().unwrap();
+        verified.sort();
+        assert_eq!(cells(), verified);
+
+        // a chunk out of order, a missing chunk and a tampered chunk are rejected
+        let mut verifier = IncrementalVerifier::new(commitment.clone(), header).unwrap();
+        assert!(verifier.feed(&chunks[1]).is_err());
+        let mut verifier = IncrementalVerifier::new(commitment.clone(), header).unwrap();
+        verifier.feed(&chunks[0]).unwrap();
+        assert!(verifier.finalize().is_err());
+        let mut tampered = chunks[0].clone();
+        tampered.proof[12] ^= 1;
+        let mut verifier = IncrementalVerifier::new(commitment.clone(), header).unwrap();
+        assert!(verifier.feed(&tampered).is_err(), "{}", name);
+        // the witness must be proved at the sequence of the commitment
+        let other = WitnessHeader {
+            sequence: 1,
+            ..header
+        };
+        assert!(IncrementalVerifier::new(commitment.clone(), other).is_err());
+
+        // a cell repeated in a later chunk doesn't make up the count of the header
+        let repeated = WitnessHeader {
+            sequence: 0,
+            chunks: 2,
+            cells: 16,
+        };
+        let chunks: Vec<ProofChunk> = [&cells()[0..8], &cells()[4..12]]
+            .iter()
+            .enumerate()
+            .map(|(index, out_points)| ProofChunk {
+                index: index as u32,
+                proof: wallet_proof::<_, WriteOptions>(&builder, &db, out_points.to_vec(), 0)
+                    .unwrap(),
+            })
+            .collect();
+        let mut verifier = IncrementalVerifier::new(commitment, repeated).unwrap();
+        verifier.feed(&chunks[0]).unwrap();
+        assert!(verifier.feed(&chunks[1]).is_err(), "{}", name);
+
+        // the prover sorts and deduplicates the cells
+        let mut unsorted = cells();
+        unsorted.reverse();
+        unsorted.extend(cells()[0..10].iter().cloned());
+        let prover = ChunkedProver::<_, WriteOptions>::new(builder.clone(), &db, unsorted, 0, 8);
+        assert_eq!(header, prover.header());
+    }
+}
+
+#[test]
+fn test_chunked_stream() {
+    let tmp_dir = TempBuilder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let builder = Builder::from_name("smt_live").unwrap().strict(true);
+    let mut accumulator = builder.clone().build::<_, WriteOptions>(&db).unwrap();
+    accumulator.add(cells()).unwrap();
+    accumulator.commit().unwrap();
+    let commitment = builder
+        .clone()
+        .sequence(0)
+        .build::<_, WriteOptions>(&db)
+        .unwrap()
+        .backend_commitment()
+        .unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let verifier = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        verify_chunked(&mut stream, commitment, 1 << 20).unwrap()
+    });
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    let prover = ChunkedProver::<_, WriteOptions>::new(builder.clone(), &db, cells(), 0, 16);
+    write_chunked(&mut stream, prover).unwrap();
+    let (mut verified, peak_bytes) = verifier.join().unwrap();
+    verified.sort();
+    assert_eq!(cells(), verified);
+    assert!(peak_bytes > 0);
+
+    // a frame above the limit is rejected before it's read
+    let mut buf = Vec::new();
+    let prover = ChunkedProver::<_, WriteOptions>::new(builder.clone(), &db, cells(), 0, 16);
+    write_chunked(&mut buf, prover).unwrap();
+    let commitment = builder
+        .sequence(0)
+        .build::<_, WriteOptions>(&db)
+        .unwrap()
+        .backend_commitment()
+        .unwrap();
+    assert!(verify_chunked(&mut buf.as_slice(), commitment, 64).is_err());
+}
@@ -0,0 +1,346 @@
+use crate::{BlockOps, OutPoint};
+use model::{ChainStats, ModelWorkload};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaChaRng,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+pub mod model;
+#[cfg(test)]
+mod tests;
+
+/// The new cells of every block of the default workload.
+pub const CREATED_PER_BLOCK: u64 = 10;
+/// The old cells consumed by every block of the default workload after block 100.
+pub const CONSUMED_PER_BLOCK: u64 = 6;
+
+/// The deterministic benchmark workload, each block produces 10 new cells and consumes 6 old cells after block 100.
+/// By default every cell is the single output of its transaction, see `with_outputs_per_tx`, and there are no spike
+/// blocks, see `with_spikes`, or timelocked cells, see `with_timelocks`. `with_model` replaces the blocks with the ones
+/// of a model fitted to the statistics of a chain.
+pub struct Workload {
+    // rng1 is used to generate tx_hash for new cells
+    rng1: ChaChaRng,
+    // rng2 is used to generate tx_hash for old cells
+    rng2: ChaChaRng,
+    // rng3 is used to generate index for old cells, to make sure the index is exsiting in the accumulator
+    rng3: ChaChaRng,
+    // in strict mode a cell can't be consumed twice, so we keep track of the consumed cells and draw again on collision,
+    // this set is not persisted, strict runs should start from block 0
+    strict: bool,
+    consumed: HashSet<u64>,
+    // the cells of a block are grouped into transactions of this many outputs, with the indices 0..n under one tx_hash
+    outputs_per_tx: u64,
+    spikes: Option<Spikes>,
+    timelocks: Option<Timelocks>,
+    model: Option<ModelWorkload>,
+}
+
+/// Pathological blocks injected into the workload, e.g. an airdrop creating 100k cells or a consolidation consuming
+/// 50k, since the worst case block time is what bounds the block parameters of a chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spikes {
+    /// Every `interval`-th block is a spike block.
+    pub interval: u64,
+    /// The cells created by a spike block on top of the regular ones.
+    pub created: u64,
+    /// The old cells consumed by a spike block on top of the regular ones, after block 100.
+    pub consumed: u64,
+}
+
+impl Spikes {
+    pub fn is_spike(&self, block_number: u64) -> bool {
+        block_number % self.interval == self.interval - 1
+    }
+}
+
+impl fmt::Display for Spikes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.interval, self.created, self.consumed)
+    }
+}
+
+/// Cells locked until they expire: the blocks of the workload never consume them, they are only consumed by an expiry
+/// sweep, see `sweep::SweepCells`. A cell is locked by its out_point hash, so a sweep tells them apart without the
+/// workload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timelocks {
+    /// The share of the cells which are locked, in percent.
+    pub percent: u8,
+}
+
+impl Timelocks {
+    /// The locked share, in percent, the default workload stays below so that every block finds `CONSUMED_PER_BLOCK`
+    /// unlocked cells to consume on average.
+    pub const MAX_PERCENT: u8 =
+        ((CREATED_PER_BLOCK - CONSUMED_PER_BLOCK) * 100 / CREATED_PER_BLOCK) as u8;
+
+    pub fn is_locked(&self, out_point_hash: &[u8; 32]) -> bool {
+        u32::from(out_point_hash[0]) * 100 < u32::from(self.percent) * 256
+    }
+}
+
+impl Workload {
+    pub fn new(start_block_number: u64, strict: bool) -> Self {
+        let seed = [0u8; 32];
+        let rng1 = ChaChaRng::from_seed(seed);
+        let rng2 = ChaChaRng::from_seed(seed);
+        let mut rng3 = ChaChaRng::from_seed([1u8; 32]);
+        rng3.set_word_pos(start_block_number as u128);
+        Workload {
+            rng1,
+            rng2,
+            rng3,
+            strict,
+            consumed: HashSet::new(),
+            outputs_per_tx: 1,
+            spikes: None,
+            timelocks: None,
+            model: None,
+        }
+    }
+
+    /// Groups the 10 cells of every block into transactions of `outputs_per_tx` outputs, the last one of a block may
+    /// have fewer, so the element keys made of the out_point share the tx_hash prefix of their transaction. A transaction
+    /// takes the tx_hash its first cell has in the default workload, so with 1 output per tx the workload is unchanged.
+    pub fn with_outputs_per_tx(mut self, outputs_per_tx: u64) -> Self {
+        assert!(outputs_per_tx > 0, "a transaction has at least one output");
+        self.outputs_per_tx = outputs_per_tx;
+        self
+    }
+
+    /// Injects spike blocks on top of the regular cells of the workload, a strict workload doesn't consume the cells
+    /// consumed by a spike again but is otherwise unchanged. The cells created by the spikes are drawn from a generator
+    /// seeded by the block number and are never consumed, the cells consumed by the spikes are regular cells, distinct
+    /// from the other cells consumed in the block.
+    pub fn with_spikes(mut self, spikes: Spikes) -> Self {
+        assert!(
+            spikes.interval > 0,
+            "the spike interval is at least one block"
+        );
+        self.spikes = Some(spikes);
+        self
+    }
+
+    /// Locks a share of the cells, the regular cells consumed by the blocks are drawn again until they are unlocked, so
+    /// a workload with timelocks consumes other cells than the default one. The share must be below
+    /// `Timelocks::MAX_PERCENT`, and it can't be combined with spikes.
+    pub fn with_timelocks(mut self, timelocks: Timelocks) -> Self {
+        assert!(
+            timelocks.percent < Timelocks::MAX_PERCENT && self.spikes.is_none(),
+            "the timelocks leave the consumed cells of every block unlocked and aren't combined with spikes"
+        );
+        self.timelocks = Some(timelocks);
+        self
+    }
+
+    /// Generates the blocks with a `ModelWorkload` fitted to `stats` instead of the 10 new and 6 old cells per block, see
+    /// `model`. The model draws its own transactions, so it can't be combined with the other shapes of the workload.
+    pub fn with_model(mut self, stats: &ChainStats) -> Self {
+        assert!(
+            self.outputs_per_tx == 1 && self.spikes.is_none() && self.timelocks.is_none(),
+            "the model isn't combined with outputs per tx, spikes or timelocks"
+        );
+        self.model = Some(ModelWorkload::new(stats));
+        self
+    }
+
+    // whether the `cell`-th cell of the workload is timelocked
+    fn is_locked(&mut self, cell: u64) -> bool {
+        match self.timelocks {
+            Some(timelocks) => {
+                timelocks.is_locked(&out_point(&mut self.rng2, cell, self.outputs_per_tx).hash())
+            }
+            None => false,
+        }
+    }
+
+    pub fn next_block(&mut self, block_number: u64) -> BlockOps {
+        if let Some(model) = self.model.as_mut() {
+            return model.next_block(block_number);
+        }
+        let mut added: Vec<_> = (0..CREATED_PER_BLOCK)
+            .map(|i| {
+                out_point(
+                    &mut self.rng1,
+                    block_number * CREATED_PER_BLOCK + i,
+                    self.outputs_per_tx,
+                )
+            })
+            .collect();
+
+        let total_cells = block_number * CREATED_PER_BLOCK;
+        let mut consumed_cells = if block_number > 100 {
+            (0..CONSUMED_PER_BLOCK)
+                .map(|_| {
+                    let mut i = self.rng3.next_u64() % total_cells;
+                    while self.is_locked(i) || (self.strict && !self.consumed.insert(i)) {
+                        i = self.rng3.next_u64() % total_cells;
+                    }
+                    i
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        if let Some(spikes) = self.spikes.filter(|spikes| spikes.is_spike(block_number)) {
+            let mut rng = ChaChaRng::seed_from_u64(block_number);
+            let mut tx_hash = [0u8; 32];
+            for i in 0..spikes.created {
+                let index = i % self.outputs_per_tx;
+                if index == 0 {
+                    rng.fill_bytes(&mut tx_hash);
+                }
+                added.push(OutPoint {
+                    tx_hash,
+                    index: index as u32,
+                });
+            }
+            if block_number > 100 {
+                // at most half of the cells which can still be consumed, so that the draws terminate
+                let mut drawn: HashSet<u64> = consumed_cells.iter().copied().collect();
+                let available = if self.strict {
+                    total_cells - self.consumed.len() as u64
+                } else {
+                    total_cells - drawn.len() as u64
+                };
+                for _ in 0..spikes.consumed.min(available / 2) {
+                    let mut i = rng.next_u64() % total_cells;
+                    while !drawn.insert(i) || (self.strict && !self.consumed.insert(i)) {
+                        i = rng.next_u64() % total_cells;
+                    }
+                    consumed_cells.push(i);
+                }
+            }
+        }
+
+        let deleted = consumed_cells
+            .into_iter()
+            .map(|i| out_point(&mut self.rng2, i, self.outputs_per_tx))
+            .collect();
+        BlockOps { added, deleted }
+    }
+}
+
+// the out_point of the `cell`-th cell of the workload, to fill a tx_hash [u8; 32] we need 8 words of random data, so the
+// tx_hash of the first cell of its transaction is drawn from `rng` at 8 words per cell
+fn out_point(rng: &mut ChaChaRng, cell: u64, outputs_per_tx: u64) -> OutPoint {
+    let index = cell % CREATED_PER_BLOCK % outputs_per_tx;
+    rng.set_word_pos(((cell - index) * 8) as u128);
+    let mut tx_hash = [0u8; 32];
+    rng.fill_bytes(&mut tx_hash);
+    OutPoint {
+        tx_hash,
+        index: index as u32,
+    }
+}
+
+/// How `reorder` rewrites the blocks of a workload, the live cells at the end of the last block stay the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reordering {
+    // shuffles the added and the consumed cells of every block, the live cells at the end of every block stay the same
+    Shuffled,
+    // shuffles, and adds cells consumed in the same block, which are never live at a commitment
+    Transient,
+    // moves the consumption of every cell to a random block after the one which created it, and shuffles, so the blocks
+    // in between hold other live cells
+    AcrossBlocks,
+}
+
+impl fmt::Display for Reordering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reordering::Shuffled => write!(f, "shuffled"),
+            Reordering::Transient => write!(f, "transient"),
+            Reordering::AcrossBlocks => write!(f, "across-blocks"),
+        }
+    }
+}
+
+/// Applies the same blocks in a different operation order, so that a history independent accumulator commits to the
+/// same roots, at every block or, when the consumptions move across blocks, at the last one.
+pub fn reorder(blocks: &[BlockOps], reordering: Reordering, seed: u64) -> Vec<BlockOps> {
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let mut blocks = blocks.to_vec();
+    if reordering == Reordering::AcrossBlocks {
+        move_consumptions(&mut blocks, &mut rng);
+    }
+    blocks
+        .into_iter()
+        .map(|mut block| {
+            if reordering == Reordering::Transient {
+                for _ in 0..2 {
+                    let mut tx_hash = [0u8; 32];
+                    rng.fill_bytes(&mut tx_hash);
+                    let out_point = OutPoint { tx_hash, index: 1 };
+                    block.added.push(out_point.clone());
+                    block.deleted.push(out_point);
+                }
+            }
+            shuffle(&mut block.added, &mut rng);
+            shuffle(&mut block.deleted, &mut rng);
+            block
+        })
+        .collect()
+}
+
+// moves every consumed cell to a random block after the one which created it, or to any block if it was created before
+// the blocks, the cells consumed by the blocks are still consumed at the end of the last one
+fn move_consumptions(blocks: &mut [BlockOps], rng: &mut ChaChaRng) {
+    let created: HashMap<OutPoint, usize> = blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(i, block)| {
+            block
+                .added
+                .iter()
+                .map(move |out_point| (out_point.clone(), i))
+        })
+        .collect();
+    let consumed: Vec<OutPoint> = blocks
+        .iter_mut()
+        .flat_map(|block| std::mem::take(&mut block.deleted))
+        .collect();
+    for out_point in consumed {
+        // a cell created by the last block is consumed in it
+        let first = created
+            .get(&out_point)
+            .map_or(0, |created| created + 1)
+            .min(blocks.len() - 1);
+        let block = first + rng.next_u32() as usize % (blocks.len() - first);
+        blocks[block].deleted.push(out_point);
+    }
+}
+
+/// Draws up to `count` distinct cells added by the blocks up to `created_by` and consumed by none of the `blocks`, so
+/// live at every sequence from `created_by` to the last block, without reading the database. The same blocks and seed
+/// give the same cells.
+pub fn sample_live_cells(
+    blocks: &[BlockOps],
+    created_by: usize,
+    count: usize,
+    seed: u64,
+) -> Vec<OutPoint> {
+    let consumed: HashSet<&OutPoint> = blocks.iter().flat_map(|block| &block.deleted).collect();
+    let mut seen = HashSet::new();
+    let mut cells: Vec<OutPoint> = blocks[..=created_by]
+        .iter()
+        .flat_map(|block| &block.added)
+        .filter(|out_point| !consumed.contains(out_point) && seen.insert(*out_point))
+        .cloned()
+        .collect();
+    shuffle(&mut cells, &mut ChaChaRng::seed_from_u64(seed));
+    cells.truncate(count);
+    cells
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut ChaChaRng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.next_u32() as usize % (i + 1));
+    }
+}
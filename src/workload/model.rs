@@ -0,0 +1,231 @@
+use crate::{BlockOps, OutPoint};
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaChaRng,
+};
+use std::{collections::BTreeMap, f64::consts::PI, fmt, str::FromStr};
+
+/// Summary statistics of a chain, e.g. measured on CKB mainnet, from which `ModelWorkload` generates a workload with
+/// the same shape, so that the results of a run map to the behavior of the chain without shipping its blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainStats {
+    /// The mean number of transactions per block, cellbase excluded.
+    pub txs_per_block: f64,
+    /// The mean number of outputs per transaction, at least 1.
+    pub outputs_per_tx: f64,
+    /// The mean number of inputs per transaction, at least 1.
+    pub inputs_per_tx: f64,
+    /// The median age in blocks of the consumed cells.
+    pub age_median: f64,
+    /// The mean age in blocks of the consumed cells, at least the median since the ages are skewed to the young cells.
+    pub age_mean: f64,
+}
+
+impl fmt::Display for ChainStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "txs={},outputs={},inputs={},age-median={},age-mean={}",
+            self.txs_per_block,
+            self.outputs_per_tx,
+            self.inputs_per_tx,
+            self.age_median,
+            self.age_mean
+        )
+    }
+}
+
+impl FromStr for ChainStats {
+    type Err = String;
+
+    /// Parses the `txs=<mean>,outputs=<mean>,inputs=<mean>,age-median=<blocks>,age-mean=<blocks>` form of `Display`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let usage =
+            "must be txs=<mean>,outputs=<mean>,inputs=<mean>,age-median=<blocks>,age-mean=<blocks>";
+        let mut fields = [None; 5];
+        for field in value.split(',') {
+            let (name, number) = field.split_once('=').ok_or(usage)?;
+            let i = ["txs", "outputs", "inputs", "age-median", "age-mean"]
+                .iter()
+                .position(|known| *known == name)
+                .ok_or_else(|| format!("unknown statistic {}", name))?;
+            fields[i] = Some(number.parse::<f64>().map_err(|_| usage)?);
+        }
+        match fields {
+            [Some(txs_per_block), Some(outputs_per_tx), Some(inputs_per_tx), Some(age_median), Some(age_mean)] =>
+            {
+                let stats = ChainStats {
+                    txs_per_block,
+                    outputs_per_tx,
+                    inputs_per_tx,
+                    age_median,
+                    age_mean,
+                };
+                stats.validate()?;
+                Ok(stats)
+            }
+            _ => Err(usage.to_string()),
+        }
+    }
+}
+
+impl ChainStats {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.txs_per_block > 0.0 && self.txs_per_block <= 500.0) {
+            return Err("the transactions per block must be in (0, 500]".to_string());
+        }
+        if !(self.outputs_per_tx >= 1.0 && self.inputs_per_tx >= 1.0) {
+            return Err("the outputs and inputs per transaction must be at least 1".to_string());
+        }
+        if !(self.age_median >= 1.0 && self.age_mean >= self.age_median) {
+            return Err(
+                "the median age must be at least 1 block and the mean age at least the median"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The parameters of the distributions fitted to `ChainStats`: the transactions of a block follow a Poisson
+/// distribution, the outputs and inputs of a transaction a geometric distribution starting at 1, and the ages of the
+/// consumed cells a log-normal distribution, which has the given median and mean with `mu = ln(median)` and
+/// `sigma = sqrt(2 * ln(mean / median))`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FittedModel {
+    pub txs_lambda: f64,
+    pub outputs_p: f64,
+    pub inputs_p: f64,
+    pub age_mu: f64,
+    pub age_sigma: f64,
+}
+
+impl FittedModel {
+    pub fn fit(stats: &ChainStats) -> Self {
+        FittedModel {
+            txs_lambda: stats.txs_per_block,
+            outputs_p: 1.0 / stats.outputs_per_tx,
+            inputs_p: 1.0 / stats.inputs_per_tx,
+            age_mu: stats.age_median.ln(),
+            age_sigma: (2.0 * (stats.age_mean / stats.age_median).ln()).sqrt(),
+        }
+    }
+}
+
+/// A deterministic workload drawn from a `FittedModel`: every block has a random number of transactions, each one
+/// creating its outputs under a fresh tx_hash and consuming its inputs among the live cells created by the previous
+/// blocks. An input is the live cell created the closest before the block at its drawn age, or the oldest live cell
+/// when the chain is younger than the age. A cell is never consumed twice, so the workload suits strict runs.
+pub struct ModelWorkload {
+    model: FittedModel,
+    rng: ChaChaRng,
+    // the live cells by the block which created them
+    live: BTreeMap<u64, Vec<OutPoint>>,
+    // the number of the next block to generate
+    next_block_number: u64,
+}
+
+impl ModelWorkload {
+    pub fn new(stats: &ChainStats) -> Self {
+        ModelWorkload {
+            model: FittedModel::fit(stats),
+            rng: ChaChaRng::from_seed([2u8; 32]),
+            live: BTreeMap::new(),
+            next_block_number: 0,
+        }
+    }
+
+    pub fn model(&self) -> &FittedModel {
+        &self.model
+    }
+
+    /// The operations of the block `block_number`. The blocks are generated in order from block 0, the blocks before
+    /// `block_number` are generated and dropped first, so a run starting at a later block sees the same cells.
+    pub fn next_block(&mut self, block_number: u64) -> BlockOps {
+        assert!(
+            block_number >= self.next_block_number,
+            "the model blocks are generated in order"
+        );
+        while self.next_block_number < block_number {
+            self.generate();
+        }
+        self.generate()
+    }
+
+    fn generate(&mut self) -> BlockOps {
+        let block_number = self.next_block_number;
+        self.next_block_number += 1;
+        let mut added = Vec::new();
+        let mut deleted = Vec::new();
+        for _ in 0..self.poisson(self.model.txs_lambda) {
+            for _ in 0..self.geometric(self.model.inputs_p) {
+                match self.consume(block_number) {
+                    Some(out_point) => deleted.push(out_point),
+                    None => break,
+                }
+            }
+            let mut tx_hash = [0u8; 32];
+            self.rng.fill_bytes(&mut tx_hash);
+            for index in 0..self.geometric(self.model.outputs_p) {
+                added.push(OutPoint {
+                    tx_hash,
+                    index: index as u32,
+                });
+            }
+        }
+        if !added.is_empty() {
+            self.live.insert(block_number, added.clone());
+        }
+        BlockOps { added, deleted }
+    }
+
+    // removes a live cell of a drawn age from the live cells, `None` if there is none left
+    fn consume(&mut self, block_number: u64) -> Option<OutPoint> {
+        let age = self
+            .log_normal(self.model.age_mu, self.model.age_sigma)
+            .ceil() as u64;
+        let created = block_number.saturating_sub(age.max(1));
+        let key = match self.live.range(..=created).next_back() {
+            Some((key, _)) => *key,
+            None => *self.live.keys().next()?,
+        };
+        let cells = self.live.get_mut(&key).expect("existing block");
+        let i = (self.rng.next_u64() % cells.len() as u64) as usize;
+        let out_point = cells.swap_remove(i);
+        if cells.is_empty() {
+            self.live.remove(&key);
+        }
+        Some(out_point)
+    }
+
+    // a uniform sample in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        1.0 - (self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn poisson(&mut self, lambda: f64) -> u64 {
+        // Knuth's multiplication method, `lambda` is at most 500 so the threshold doesn't underflow
+        let threshold = (-lambda).exp();
+        let mut k = 0;
+        let mut product = self.uniform();
+        while product > threshold {
+            k += 1;
+            product *= self.uniform();
+        }
+        k
+    }
+
+    // the number of trials up to the first success of probability `p`, at least 1
+    fn geometric(&mut self, p: f64) -> u64 {
+        if p >= 1.0 {
+            return 1;
+        }
+        1 + (self.uniform().ln() / (1.0 - p).ln()).floor() as u64
+    }
+
+    fn log_normal(&mut self, mu: f64, sigma: f64) -> f64 {
+        // Box-Muller transform
+        let normal = (-2.0 * self.uniform().ln()).sqrt() * (2.0 * PI * self.uniform()).cos();
+        (mu + sigma * normal).exp()
+    }
+}
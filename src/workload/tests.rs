@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    model::{ChainStats, FittedModel},
+    reorder, Reordering, Workload,
+};
+
+fn stats() -> ChainStats {
+    ChainStats {
+        txs_per_block: 8.0,
+        outputs_per_tx: 2.0,
+        inputs_per_tx: 1.5,
+        age_median: 20.0,
+        age_mean: 50.0,
+    }
+}
+
+#[test]
+fn test_chain_stats() {
+    let stats = stats();
+    assert_eq!(
+        "txs=8,outputs=2,inputs=1.5,age-median=20,age-mean=50",
+        stats.to_string()
+    );
+    assert_eq!(Ok(stats), stats.to_string().parse::<ChainStats>());
+    assert!("txs=8,outputs=2".parse::<ChainStats>().is_err());
+    assert!("txs=8,outputs=2,inputs=1.5,age-median=20,age=50"
+        .parse::<ChainStats>()
+        .is_err());
+    // the mean age can't be below the median of a log-normal distribution
+    assert!("txs=8,outputs=2,inputs=1.5,age-median=20,age-mean=10"
+        .parse::<ChainStats>()
+        .is_err());
+    assert!("txs=8,outputs=0.5,inputs=1.5,age-median=20,age-mean=50"
+        .parse::<ChainStats>()
+        .is_err());
+
+    let model = FittedModel::fit(&stats);
+    assert_eq!(0.5, model.outputs_p);
+    assert!((model.age_mu.exp() - 20.0).abs() < 1e-9);
+    // the mean of a log-normal distribution is exp(mu + sigma^2 / 2)
+    assert!(((model.age_mu + model.age_sigma.powi(2) / 2.0).exp() - 50.0).abs() < 1e-9);
+}
+
+// Generates 2000 blocks and measures the statistics of the last 1000 ones, once the chain is much older than the ages.
+#[test]
+fn test_model_workload() {
+    let mut workload = Workload::new(0, true).with_model(&stats());
+    let mut created = HashMap::new();
+    let mut consumed = HashSet::new();
+    let (mut txs, mut outputs, mut inputs) = (0, 0, 0);
+    let mut ages = Vec::new();
+    for block_number in 0..2000 {
+        let block = workload.next_block(block_number);
+        for out_point in block.deleted.iter() {
+            let created_by = created[out_point];
+            assert!(created_by < block_number);
+            assert!(consumed.insert(out_point.clone()));
+            if block_number >= 1000 {
+                ages.push(block_number - created_by);
+            }
+        }
+        for out_point in block.added.iter() {
+            assert!(created.insert(out_point.clone(), block_number).is_none());
+        }
+        if block_number >= 1000 {
+            let tx_hashes: HashSet<_> = block
+                .added
+                .iter()
+                .map(|out_point| out_point.tx_hash)
+                .collect();
+            txs += tx_hashes.len();
+            outputs += block.added.len();
+            inputs += block.deleted.len();
+        }
+    }
+
+    let txs_per_block = txs as f64 / 1000.0;
+    let outputs_per_tx = outputs as f64 / txs as f64;
+    let inputs_per_tx = inputs as f64 / txs as f64;
+    assert!((7.5..8.5).contains(&txs_per_block), "{}", txs_per_block);
+    assert!((1.9..2.1).contains(&outputs_per_tx), "{}", outputs_per_tx);
+    assert!((1.4..1.6).contains(&inputs_per_tx), "{}", inputs_per_tx);
+    ages.sort_unstable();
+    let age_median = ages[ages.len() / 2];
+    let age_mean = ages.iter().sum::<u64>() as f64 / ages.len() as f64;
+    // the drawn ages are rounded up to whole blocks
+    assert!((17..25).contains(&age_median), "{}", age_median);
+    assert!((42.0..58.0).contains(&age_mean), "{}", age_mean);
+
+    // a run starting at a later block sees the same blocks
+    let mut resumed = Workload::new(0, true).with_model(&stats());
+    let mut workload = Workload::new(0, true).with_model(&stats());
+    for block_number in 0..10 {
+        workload.next_block(block_number);
+    }
+    let (block, resumed) = (workload.next_block(10), resumed.next_block(10));
+    assert_eq!(block.added, resumed.added);
+    assert_eq!(block.deleted, resumed.deleted);
+}
+
+// every cell is still consumed once, after the block which created it, but not always by the same block
+#[test]
+fn test_reorder_across_blocks() {
+    let mut workload = Workload::new(0, true);
+    let blocks: Vec<_> = (0..150).map(|i| workload.next_block(i)).collect();
+    let moved = reorder(&blocks, Reordering::AcrossBlocks, 1);
+    let created: HashMap<_, _> = (0..)
+        .zip(&moved)
+        .flat_map(|(i, block)| block.added.iter().map(move |out_point| (out_point, i)))
+        .collect();
+    let mut consumed = HashSet::new();
+    for (i, block) in (0..).zip(&moved) {
+        assert_eq!(blocks[i].added.len(), block.added.len());
+        for out_point in &block.deleted {
+            assert!(created[out_point] < i);
+            assert!(consumed.insert(out_point));
+        }
+    }
+    let expected: HashSet<_> = blocks.iter().flat_map(|block| &block.deleted).collect();
+    assert_eq!(expected, consumed);
+    assert!((0..blocks.len()).any(|i| blocks[i].deleted.len() != moved[i].deleted.len()));
+}